@@ -0,0 +1,738 @@
+//! DoIP backend (ISO 13400-2)
+//!
+//! The backend has one module with one `ISO_13400_2_DIAG` resource, which runs
+//! `ISO_14229_5_on_ISO_13400_2` (UDS on DoIP).
+//!
+//! DoIP entities are found with UDP vehicle identification requests, sent to the addresses in
+//! the option string of `PDUConstruct` (comma separated IP addresses, each with an optional
+//! port), or to the limited broadcast address if there are none. `PDU_IOCTL_VEHICLE_ID_REQUEST`
+//! on the module searches again with the preselection, discovery time and destination
+//! addresses of the request. Every responding entity is remembered, the combination mode of
+//! the request is not used.
+//!
+//! Connecting a ComLogicalLink opens a TCP connection to the entity whose logical address is
+//! `CP_DoIPLogicalGatewayAddress`, searching for it first if it is not known yet, and activates
+//! routing for `CP_DoIPLogicalTesterAddress` with `CP_DoIPRoutingActivationType`. A failed
+//! routing activation is returned by `PDUConnect` as the matching `DoIPRoutingActivation*`
+//! [PduError], and a failed TCP connection as [PduError::CommPcToVciFailed].
+//!
+//! Changing `CP_DoIPLogicalGatewayAddress`, `CP_DoIPLogicalTesterAddress` or
+//! `CP_DoIPRoutingActivationType`, or a new address of the gateway found by a vehicle
+//! identification request, reconnects the link when its ComParams are applied. A generic header
+//! NACK is sent for an invalid header from the entity, and the connection is closed and reported
+//! as [PduErrorEvt::LostCommToVCI].
+//!
+//! Requests are sent as diagnostic messages to `CP_DoIPLogicalEcuAddress`, or to
+//! `CP_DoIPLogicalFunctionalAddress` if `CP_RequestAddrMode` is 2. Requests which are not
+//! acknowledged within `CP_DoIPDiagnosticAckTimeout` are sent again up to
+//! `CP_DoIPNumberOfRetries` times, `CP_DoIPRetryPeriod` apart. Results contain the UDS message,
+//! with the source and target address of the diagnostic message as the extra info header.
+//! Alive check requests of the entity are answered automatically.
+//!
+//! `PDU_IOCTL_GET_ENTITY_STATUS` and `PDU_IOCTL_GET_DIAGNOSTIC_POWER_MODE` query the entity
+//! given by [IoEntityAddressData] on the module, or the gateway of the link on a ComLogicalLink.
+//!
+//! The [sim] module has a DoIP entity simulator for testing without a vehicle.
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::doip::DoIp>);
+//! ```
+
+pub mod sim;
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream, UdpSocket},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant}
+};
+
+use crate::{
+    provider::{
+        Channel, Clock, Driver, DriverModule, DriverResource, ExtraInfoData, IoctlData, IoctlInput, LinkParams,
+        ResultEvent, VehicleIdRequestData, DEFAULT_RESPONSE_TIMEOUT
+    },
+    BusType, CombinationMode, ComParamValue, IoEntityAddressData, IoEntityStatusData, IoctlCommand, PduError,
+    PduErrorEvt, Protocol, StdComParam, VidPreselectMode
+};
+
+/// Module type ID of the DoIP module
+pub const MODULE_TYPE_ID: u32 = 13400;
+
+/// UDP and TCP port of DoIP entities (`UDP_DISCOVERY` and `TCP_DATA`)
+pub const DOIP_PORT: u16 = 13400;
+
+/// Protocol version of sent messages (ISO 13400-2:2012)
+pub const PROTOCOL_VERSION: u8 = 0x02;
+
+/// Length of the generic DoIP header
+pub const HEADER_LEN: usize = 8;
+
+/// Largest payload accepted in a received message
+pub const MAX_PAYLOAD_LEN: usize = 0x40_0000;
+
+/// Timeout of control requests (`A_DoIP_Ctrl`)
+pub const DOIP_CTRL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time to wait for vehicle identification responses when connecting to an unknown entity
+pub const DEFAULT_DISCOVERY_TIME: Duration = Duration::from_millis(500);
+
+/// Generic DoIP header negative acknowledgement
+pub const GENERIC_NACK: u16 = 0x0000;
+/// Vehicle identification request
+pub const VEHICLE_ID_REQUEST: u16 = 0x0001;
+/// Vehicle identification request with EID
+pub const VEHICLE_ID_REQUEST_EID: u16 = 0x0002;
+/// Vehicle identification request with VIN
+pub const VEHICLE_ID_REQUEST_VIN: u16 = 0x0003;
+/// Vehicle announcement message / vehicle identification response
+pub const VEHICLE_ANNOUNCEMENT: u16 = 0x0004;
+/// Routing activation request
+pub const ROUTING_ACTIVATION_REQUEST: u16 = 0x0005;
+/// Routing activation response
+pub const ROUTING_ACTIVATION_RESPONSE: u16 = 0x0006;
+/// Alive check request
+pub const ALIVE_CHECK_REQUEST: u16 = 0x0007;
+/// Alive check response
+pub const ALIVE_CHECK_RESPONSE: u16 = 0x0008;
+/// DoIP entity status request
+pub const ENTITY_STATUS_REQUEST: u16 = 0x4001;
+/// DoIP entity status response
+pub const ENTITY_STATUS_RESPONSE: u16 = 0x4002;
+/// Diagnostic power mode information request
+pub const POWER_MODE_REQUEST: u16 = 0x4003;
+/// Diagnostic power mode information response
+pub const POWER_MODE_RESPONSE: u16 = 0x4004;
+/// Diagnostic message
+pub const DIAGNOSTIC_MESSAGE: u16 = 0x8001;
+/// Diagnostic message positive acknowledgement
+pub const DIAGNOSTIC_ACK: u16 = 0x8002;
+/// Diagnostic message negative acknowledgement
+pub const DIAGNOSTIC_NACK: u16 = 0x8003;
+
+/// Generic header NACK code of a header with a wrong protocol version or inverse version
+pub const NACK_INCORRECT_PATTERN: u8 = 0x00;
+/// Generic header NACK code of a message above [MAX_PAYLOAD_LEN]
+pub const NACK_MESSAGE_TOO_LARGE: u8 = 0x02;
+
+/// Routing activation response code of a successful activation
+pub const ROUTING_SUCCESS: u8 = 0x10;
+/// Routing activation response code of an activation which waits for confirmation
+pub const ROUTING_CONFIRMATION_REQUIRED: u8 = 0x11;
+
+/// Encodes a DoIP message
+pub fn encode_message(payload_type: u16, payload: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(HEADER_LEN + payload.len());
+    msg.extend([PROTOCOL_VERSION, !PROTOCOL_VERSION]);
+    msg.extend(payload_type.to_be_bytes());
+    msg.extend((payload.len() as u32).to_be_bytes());
+    msg.extend(payload);
+    msg
+}
+
+/// Decodes the DoIP message at the start of a buffer
+///
+/// Returns the payload type and payload, or [None] if the buffer does not hold the whole
+/// message yet. The message is [HEADER_LEN] bytes longer than its payload. An invalid header
+/// is returned as the generic header NACK code it is answered with
+pub fn decode_message(buf: &[u8]) -> Result<Option<(u16, &[u8])>, u8> {
+    let Some(header) = buf.get(..HEADER_LEN) else {
+        return Ok(None);
+    };
+    if header[0] != !header[1] {
+        return Err(NACK_INCORRECT_PATTERN);
+    }
+    let payload_type = u16::from_be_bytes([header[2], header[3]]);
+    let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        return Err(NACK_MESSAGE_TOO_LARGE);
+    }
+    Ok(buf.get(HEADER_LEN..HEADER_LEN + len).map(|payload| (payload_type, payload)))
+}
+
+/// Converts a routing activation response code to the error `PDUConnect` returns for it.
+/// Returns [None] for a successful activation
+pub fn routing_activation_error(code: u8) -> Option<PduError> {
+    match code {
+        ROUTING_SUCCESS => None,
+        0x00 => Some(PduError::DoIPRoutineActivationInvalidSrcAddress),
+        0x01 => Some(PduError::DoIPRoutingActivationNoDataSocketAvailable),
+        0x02 => Some(PduError::DoIPRoutineActivationSourceAddressChanged),
+        0x03 => Some(PduError::DoIPRoutingActivationSourceAddressInUse),
+        0x04 => Some(PduError::DoIPRoutingActivationAuthFailed),
+        0x05 => Some(PduError::DoIPRoutineActivationConfirmationRejected),
+        0x06 => Some(PduError::DoIPRoutineActivationTypeUnsupported),
+        0x07..=0x0F => Some(PduError::DoIPRoutingActivationFailed),
+        _ => Some(PduError::DoIPRoutineActivationResponseCodeUnknown)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Payload of a vehicle announcement message or vehicle identification response
+pub struct VehicleIdentification {
+    /// Vehicle identification number
+    pub vin: [u8; 17],
+    /// Logical address of the entity
+    pub logical_address: u16,
+    /// Entity identification, usually the MAC address of the entity
+    pub eid: [u8; 6],
+    /// Group identification of the vehicle
+    pub gid: [u8; 6],
+    /// Further action required
+    pub further_action: u8,
+    /// VIN/GID synchronisation status, if the entity sends it
+    pub sync_status: Option<u8>
+}
+
+impl VehicleIdentification {
+    /// Decodes the payload of a vehicle identification response
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        if !matches!(payload.len(), 32 | 33) {
+            return None;
+        }
+        Some(Self {
+            vin: payload[..17].try_into().ok()?,
+            logical_address: u16::from_be_bytes([payload[17], payload[18]]),
+            eid: payload[19..25].try_into().ok()?,
+            gid: payload[25..31].try_into().ok()?,
+            further_action: payload[31],
+            sync_status: payload.get(32).copied()
+        })
+    }
+
+    /// Encodes the payload of a vehicle identification response
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = self.vin.to_vec();
+        payload.extend(self.logical_address.to_be_bytes());
+        payload.extend(self.eid);
+        payload.extend(self.gid);
+        payload.push(self.further_action);
+        payload.extend(self.sync_status);
+        payload
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A DoIP entity which answered a vehicle identification request
+pub struct DoIpEntity {
+    /// Address the entity answered from, which is also its TCP data address
+    pub address: SocketAddr,
+    /// Identification sent by the entity
+    pub identification: VehicleIdentification
+}
+
+/// Converts an I/O error of a DoIP connection
+fn pdu_error(e: io::Error) -> PduError {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => {
+            PduError::CommPcToVciFailed
+        },
+        _ => PduError::FctFailed
+    }
+}
+
+/// Opens a UDP socket of the address family of `to`
+fn udp_socket(to: &SocketAddr) -> Result<UdpSocket, PduError> {
+    let local: IpAddr = match to {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into()
+    };
+    let socket = UdpSocket::bind((local, 0)).map_err(pdu_error)?;
+    socket.set_broadcast(to.is_ipv4()).map_err(pdu_error)?;
+    Ok(socket)
+}
+
+/// Waits up to `deadline` for the next DoIP datagram on a UDP socket
+fn recv_datagram(socket: &UdpSocket, deadline: Instant) -> Result<Option<(SocketAddr, u16, Vec<u8>)>, PduError> {
+    let mut buf = [0; 1500];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(remaining)).map_err(pdu_error)?;
+        match socket.recv_from(&mut buf) {
+            Ok((n, from)) => {
+                if let Ok(Some((payload_type, payload))) = decode_message(&buf[..n]) {
+                    return Ok(Some((from, payload_type, payload.to_vec())));
+                }
+            },
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+            Err(e) => return Err(pdu_error(e))
+        }
+    }
+}
+
+/// Sends a UDP request to an entity and waits for its response
+fn udp_request(
+    to: SocketAddr,
+    payload_type: u16,
+    response_type: u16,
+    timeout: Duration
+) -> Result<Vec<u8>, PduError> {
+    let socket = udp_socket(&to)?;
+    socket.send_to(&encode_message(payload_type, &[]), to).map_err(pdu_error)?;
+    let deadline = Instant::now() + timeout;
+    while let Some((from, ty, payload)) = recv_datagram(&socket, deadline)? {
+        if from.ip() == to.ip() && ty == response_type {
+            return Ok(payload);
+        }
+    }
+    Err(PduError::DoIPResponseTimeout)
+}
+
+/// Reads the status of an entity (`PDU_IOCTL_GET_ENTITY_STATUS`)
+fn entity_status(entity: SocketAddr, timeout: Duration) -> Result<IoctlData, PduError> {
+    let payload = udp_request(entity, ENTITY_STATUS_REQUEST, ENTITY_STATUS_RESPONSE, timeout)?;
+    match payload[..] {
+        [node_type, max_sockets, open_sockets, ref rest @ ..] => Ok(IoctlData::EntityStatus(IoEntityStatusData {
+            entity_type: node_type.into(),
+            tcp_clients_max: max_sockets.into(),
+            tcp_clients: open_sockets.into(),
+            max_data_size: rest.try_into().map(u32::from_be_bytes).unwrap_or_default()
+        })),
+        _ => Err(PduError::FctFailed)
+    }
+}
+
+/// Reads the diagnostic power mode of an entity (`PDU_IOCTL_GET_DIAGNOSTIC_POWER_MODE`)
+fn power_mode(entity: SocketAddr, timeout: Duration) -> Result<IoctlData, PduError> {
+    let payload = udp_request(entity, POWER_MODE_REQUEST, POWER_MODE_RESPONSE, timeout)?;
+    payload.first().map(|mode| IoctlData::Unum32((*mode).into())).ok_or(PduError::FctFailed)
+}
+
+/// Parses an EID preselection value, 12 hex digits optionally separated by `:` or `-`
+fn parse_eid(value: &[u8]) -> Option<[u8; 6]> {
+    let digits: Vec<u8> = value.iter().copied().filter(|c| !matches!(c, b':' | b'-')).collect();
+    if digits.len() != 12 {
+        return None;
+    }
+    let mut eid = [0; 6];
+    for (b, pair) in eid.iter_mut().zip(digits.chunks(2)) {
+        *b = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(eid)
+}
+
+/// Sends a vehicle identification request to every destination and collects the responses
+/// of the discovery time
+fn discover(request: &VehicleIdRequestData, destinations: &[SocketAddr]) -> Result<Vec<DoIpEntity>, PduError> {
+    let (payload_type, payload) = match request.preselection_mode {
+        VidPreselectMode::None => (VEHICLE_ID_REQUEST, Vec::new()),
+        VidPreselectMode::VIN if request.preselection_value.len() == 17 => {
+            (VEHICLE_ID_REQUEST_VIN, request.preselection_value.clone())
+        },
+        VidPreselectMode::EID => {
+            (VEHICLE_ID_REQUEST_EID, parse_eid(&request.preselection_value).ok_or(PduError::InvalidParameters)?.to_vec())
+        },
+        VidPreselectMode::VIN => return Err(PduError::InvalidParameters)
+    };
+    // Entities answer preselected requests only if they match, but not every entity checks
+    let preselected = |id: &VehicleIdentification| match payload_type {
+        VEHICLE_ID_REQUEST_VIN => id.vin[..] == payload[..],
+        VEHICLE_ID_REQUEST_EID => id.eid[..] == payload[..],
+        _ => true
+    };
+    let message = encode_message(payload_type, &payload);
+    let sockets: Vec<UdpSocket> = destinations
+        .iter()
+        .map(|to| {
+            let socket = udp_socket(to)?;
+            socket.send_to(&message, to).map_err(pdu_error)?;
+            Ok(socket)
+        })
+        .collect::<Result<_, PduError>>()?;
+    let deadline = Instant::now() + request.discovery_time;
+    let mut found: Vec<DoIpEntity> = Vec::new();
+    while Instant::now() < deadline {
+        for socket in sockets.iter() {
+            let poll = (Instant::now() + Duration::from_millis(5)).min(deadline);
+            while let Some((address, ty, payload)) = recv_datagram(socket, poll)? {
+                let identification = VehicleIdentification::from_payload(&payload)
+                    .filter(|id| ty == VEHICLE_ANNOUNCEMENT && preselected(id));
+                if let Some(identification) = identification {
+                    let entity = DoIpEntity { address, identification };
+                    if !found.contains(&entity) {
+                        found.push(entity);
+                    }
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink to a DoIP entity
+pub struct DoIpChannel {
+    stream: TcpStream,
+    entity: SocketAddr,
+    /// Entities of the driver which opened the channel, where the gateway is looked up again
+    directory: Option<Arc<Directory>>,
+    gateway: u16,
+    clock: Clock,
+    tester_address: u16,
+    activation_type: u8,
+    buf: Vec<u8>,
+    received: VecDeque<ResultEvent>,
+    errors: VecDeque<PduErrorEvt>
+}
+
+impl DoIpChannel {
+    /// Connects to an entity and activates routing for the tester address of the ComParams. The
+    /// channel stays with the entity, changing `CP_DoIPLogicalGatewayAddress` fails with
+    /// [PduError::InvalidParameters]
+    pub fn connect(entity: SocketAddr, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let stream = TcpStream::connect_timeout(&entity, DOIP_CTRL_TIMEOUT).map_err(|_| PduError::CommPcToVciFailed)?;
+        stream.set_nodelay(true).map_err(pdu_error)?;
+        let mut channel = Self {
+            stream,
+            entity,
+            directory: None,
+            gateway: params.get_u32(StdComParam::DoIpLogicalGatewayAddress).unwrap_or_default() as u16,
+            clock,
+            tester_address: params.get_u32(StdComParam::DoIpLogicalTesterAddress).unwrap_or_default() as u16,
+            activation_type: params.get_u32(StdComParam::DoIpRoutingActivationType).unwrap_or_default() as u8,
+            buf: Vec::new(),
+            received: VecDeque::new(),
+            errors: VecDeque::new()
+        };
+        channel.activate_routing()?;
+        Ok(channel)
+    }
+
+    /// Sends a routing activation request and waits for a final response. Responses asking for
+    /// confirmation are followed by another response once the entity has confirmed
+    fn activate_routing(&mut self) -> Result<(), PduError> {
+        let mut request = self.tester_address.to_be_bytes().to_vec();
+        request.push(self.activation_type);
+        request.extend([0; 4]);
+        self.write(ROUTING_ACTIVATION_REQUEST, &request)?;
+        let deadline = Instant::now() + DOIP_CTRL_TIMEOUT;
+        loop {
+            match self.next_message(deadline)? {
+                Some((ROUTING_ACTIVATION_RESPONSE, payload)) if payload.len() >= 9 => match payload[4] {
+                    ROUTING_CONFIRMATION_REQUIRED => {},
+                    code => return routing_activation_error(code).map_or(Ok(()), Err)
+                },
+                Some((ROUTING_ACTIVATION_RESPONSE | GENERIC_NACK, _)) => return Err(PduError::DoIPRoutingActivationFailed),
+                Some(_) => {},
+                None => return Err(PduError::DoIPRoutingActivationResponseTimeout)
+            }
+        }
+    }
+
+    fn write(&mut self, payload_type: u16, payload: &[u8]) -> Result<(), PduError> {
+        self.stream.write_all(&encode_message(payload_type, payload)).map_err(pdu_error)
+    }
+
+    /// Reads the next message, waiting up to `deadline` for it to arrive
+    fn read_message(&mut self, deadline: Instant) -> Result<Option<(u16, Vec<u8>)>, PduError> {
+        loop {
+            match decode_message(&self.buf) {
+                Ok(Some((payload_type, payload))) => {
+                    let msg = (payload_type, payload.to_vec());
+                    self.buf.drain(..HEADER_LEN + msg.1.len());
+                    return Ok(Some(msg));
+                },
+                Ok(None) => {},
+                Err(code) => {
+                    // ISO 13400-2 closes the connection after an invalid header, as it can not
+                    // be resynchronised
+                    let _ = self.write(GENERIC_NACK, &[code]);
+                    let _ = self.stream.shutdown(Shutdown::Both);
+                    self.buf.clear();
+                    self.errors.push_back(PduErrorEvt::LostCommToVCI);
+                    return Err(PduError::CommPcToVciFailed);
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.stream.set_read_timeout(Some(remaining)).map_err(pdu_error)?;
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(PduError::CommPcToVciFailed),
+                Ok(n) => self.buf.extend(&chunk[..n]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(pdu_error(e))
+            }
+        }
+    }
+
+    /// Reads messages until one which is not handled by the channel itself arrives
+    fn next_message(&mut self, deadline: Instant) -> Result<Option<(u16, Vec<u8>)>, PduError> {
+        while let Some((payload_type, payload)) = self.read_message(deadline)? {
+            if let Some(msg) = self.handle_message(payload_type, payload)? {
+                return Ok(Some(msg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Handles a message the channel answers itself, returning any other message. Alive check
+    /// requests are answered, and diagnostic messages are queued as results
+    fn handle_message(&mut self, payload_type: u16, payload: Vec<u8>) -> Result<Option<(u16, Vec<u8>)>, PduError> {
+        match payload_type {
+            ALIVE_CHECK_REQUEST => self.write(ALIVE_CHECK_RESPONSE, &self.tester_address.to_be_bytes())?,
+            DIAGNOSTIC_MESSAGE if payload.len() > 4 => {
+                if u16::from_be_bytes([payload[2], payload[3]]) == self.tester_address {
+                    self.received.push_back(ResultEvent {
+                        start_msg_timestamp: self.clock.now(),
+                        extra_info: Some(ExtraInfoData { header: payload[..4].to_vec(), footer: Vec::new() }),
+                        data: payload[4..].to_vec(),
+                        ..Default::default()
+                    });
+                }
+            },
+            DIAGNOSTIC_MESSAGE => self.errors.push_back(PduErrorEvt::FrameStruct),
+            GENERIC_NACK => self.errors.push_back(PduErrorEvt::ProtErr),
+            _ => return Ok(Some((payload_type, payload)))
+        }
+        Ok(None)
+    }
+}
+
+impl Channel for DoIpChannel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let gateway = params.get_u32(StdComParam::DoIpLogicalGatewayAddress).unwrap_or_default() as u16;
+        let tester = params.get_u32(StdComParam::DoIpLogicalTesterAddress).unwrap_or_default() as u16;
+        let activation = params.get_u32(StdComParam::DoIpRoutingActivationType).unwrap_or_default() as u8;
+        let entity = match &self.directory {
+            Some(directory) => directory.find_entity(gateway)?,
+            None if gateway == self.gateway => self.entity,
+            None => return Err(PduError::InvalidParameters)
+        };
+        if (entity, tester, activation) != (self.entity, self.tester_address, self.activation_type) {
+            let mut channel = Self::connect(entity, params, self.clock)?;
+            channel.directory = self.directory.take();
+            *self = channel;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8], _tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        if data.is_empty() {
+            return Err(PduError::InvalidParameters);
+        }
+        let target = match params.get_u32(StdComParam::RequestAddrMode) {
+            Some(2) => params.get_u32(StdComParam::DoIpLogicalFunctionalAddress),
+            _ => params.get_u32(StdComParam::DoIpLogicalEcuAddress)
+        }
+        .unwrap_or_default() as u16;
+        let ack_timeout = params.get_duration(StdComParam::DoIpDiagnosticAckTimeout).unwrap_or(DOIP_CTRL_TIMEOUT);
+        let retries = params.get_u32(StdComParam::DoIpNumberOfRetries).unwrap_or_default();
+        let retry_period = params.get_duration(StdComParam::DoIpRetryPeriod).unwrap_or_default();
+
+        let mut msg = self.tester_address.to_be_bytes().to_vec();
+        msg.extend(target.to_be_bytes());
+        msg.extend(data);
+        for attempt in 0..=retries {
+            if attempt != 0 {
+                thread::sleep(retry_period);
+            }
+            self.write(DIAGNOSTIC_MESSAGE, &msg)?;
+            let deadline = Instant::now() + ack_timeout;
+            while let Some((payload_type, payload)) = self.next_message(deadline)? {
+                // Acknowledgements carry the addresses of the request swapped
+                let ours = payload.get(..4).is_some_and(|a| a[..2] == msg[2..4] && a[2..] == msg[..2]);
+                match payload_type {
+                    DIAGNOSTIC_ACK if ours => return Ok(()),
+                    DIAGNOSTIC_NACK if ours => return Err(PduError::FctFailed),
+                    _ => {}
+                }
+            }
+        }
+        Err(PduError::DoIPResponseTimeout)
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        let deadline = Instant::now() + timeout;
+        while self.received.is_empty() {
+            let Some((payload_type, payload)) = self.read_message(deadline)? else {
+                break;
+            };
+            self.handle_message(payload_type, payload)?;
+        }
+        Ok(self.received.pop_front())
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        self.errors.pop_front()
+    }
+
+    fn ioctl(&mut self, command: IoctlCommand, _input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        match command {
+            IoctlCommand::GetEntityStatus => entity_status(self.entity, DOIP_CTRL_TIMEOUT).map(Some),
+            IoctlCommand::GetDiagnosticPowerMode => power_mode(self.entity, DOIP_CTRL_TIMEOUT).map(Some),
+            _ => Err(PduError::IdNotSupported)
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Entities found by vehicle identification requests, shared by the driver and its channels
+struct Directory {
+    destinations: Vec<SocketAddr>,
+    entities: Mutex<Vec<DoIpEntity>>
+}
+
+impl Directory {
+    fn known(&self) -> MutexGuard<'_, Vec<DoIpEntity>> {
+        self.entities.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs a vehicle identification request, remembering the entities which respond
+    fn identify(&self, request: &VehicleIdRequestData) -> Result<(), PduError> {
+        let destinations = match request.destination_addresses.is_empty() {
+            true => self.destinations.clone(),
+            false => request.destination_addresses.iter().map(|ip| SocketAddr::new(*ip, DOIP_PORT)).collect()
+        };
+        let found = discover(request, &destinations)?;
+        let mut known = self.known();
+        known.retain(|e| !found.iter().any(|f| f.address == e.address));
+        known.extend(found);
+        Ok(())
+    }
+
+    /// Returns the address of the entity with a logical address
+    fn entity(&self, logical_address: u16) -> Result<Option<SocketAddr>, PduError> {
+        let known = self.known();
+        let mut matching = known.iter().filter(|e| e.identification.logical_address == logical_address);
+        match (matching.next(), matching.next()) {
+            (Some(a), Some(b)) if a.address != b.address => Err(PduError::DoIPAmbiguousLogicalAddress),
+            (found, _) => Ok(found.map(|e| e.address))
+        }
+    }
+
+    /// Returns the address of the entity with a logical address, searching for it if it
+    /// has not responded to a vehicle identification request yet
+    fn find_entity(&self, logical_address: u16) -> Result<SocketAddr, PduError> {
+        if let Some(address) = self.entity(logical_address)? {
+            return Ok(address);
+        }
+        self.identify(&VehicleIdRequestData {
+            preselection_mode: VidPreselectMode::None,
+            preselection_value: Vec::new(),
+            combination_mode: CombinationMode::None,
+            discovery_time: DEFAULT_DISCOVERY_TIME,
+            destination_addresses: Vec::new()
+        })?;
+        self.entity(logical_address)?.ok_or(PduError::DoIPResponseTimeout)
+    }
+}
+
+#[derive(Debug)]
+/// [Driver] for DoIP entities reached over TCP/IP
+pub struct DoIp {
+    directory: Arc<Directory>
+}
+
+impl DoIp {
+    /// Returns the entities found by vehicle identification requests so far
+    pub fn entities(&self) -> Vec<DoIpEntity> {
+        self.directory.known().clone()
+    }
+
+    /// Returns the entity and timeout of the input of an entity IOCTL
+    fn entity_input(&self, input: &IoctlInput) -> Result<(SocketAddr, Duration), PduError> {
+        let IoctlInput::EntityAddress(IoEntityAddressData { logical_address, doip_ctrl_timeout }) = input else {
+            return Err(PduError::InvalidParameters);
+        };
+        let address = self.directory.find_entity(*logical_address as u16)?;
+        let timeout = match doip_ctrl_timeout {
+            0 => DOIP_CTRL_TIMEOUT,
+            ms => Duration::from_millis((*ms).into())
+        };
+        Ok((address, timeout))
+    }
+}
+
+impl Driver for DoIp {
+    type Channel = DoIpChannel;
+
+    fn open(options: &str) -> Result<Self, PduError> {
+        let mut destinations = options
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<SocketAddr>()
+                    .or_else(|_| s.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DOIP_PORT)))
+                    .map_err(|_| PduError::InvalidParameters)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if destinations.is_empty() {
+            destinations.push(SocketAddr::new(Ipv4Addr::BROADCAST.into(), DOIP_PORT));
+        }
+        Ok(Self { directory: Arc::new(Directory { destinations, entities: Mutex::new(Vec::new()) }) })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        let destinations: Vec<String> = self.directory.destinations.iter().map(|d| d.to_string()).collect();
+        vec![DriverModule {
+            module_type_id: MODULE_TYPE_ID,
+            name: "DoIP".into(),
+            info: format!("DoIP (ISO 13400-2) on {}", destinations.join(", ")),
+            resources: vec![DriverResource {
+                bus_type: BusType::Iso13400_2Diag,
+                protocols: vec![Protocol::Iso14229_5OnIso13400_2],
+                pins: BusType::Iso13400_2Diag.default_obd_pins()
+            }]
+        }]
+    }
+
+    fn com_params(&self, _protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        [
+            (StdComParam::P2Max, DEFAULT_RESPONSE_TIMEOUT.as_micros() as u32),
+            (StdComParam::RequestAddrMode, 1),
+            (StdComParam::DoIpLogicalGatewayAddress, 0x1000),
+            (StdComParam::DoIpLogicalTesterAddress, 0x0E00),
+            (StdComParam::DoIpLogicalEcuAddress, 0x1000),
+            (StdComParam::DoIpLogicalFunctionalAddress, 0xE400),
+            (StdComParam::DoIpRoutingActivationType, 0),
+            (StdComParam::DoIpDiagnosticAckTimeout, DOIP_CTRL_TIMEOUT.as_micros() as u32),
+            (StdComParam::DoIpNumberOfRetries, 0),
+            (StdComParam::DoIpRetryPeriod, 500_000)
+        ]
+        .into_iter()
+        .filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v)))
+        .collect()
+    }
+
+    fn open_channel(
+        &self,
+        _module: usize,
+        _resource: &DriverResource,
+        _protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        let gateway = params.get_u32(StdComParam::DoIpLogicalGatewayAddress).unwrap_or_default() as u16;
+        let mut channel = DoIpChannel::connect(self.directory.find_entity(gateway)?, params, clock)?;
+        channel.directory = Some(self.directory.clone());
+        Ok(channel)
+    }
+
+    fn module_ioctl(&self, _module: usize, command: IoctlCommand, input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        match (command, input) {
+            (IoctlCommand::VehicleIdRequest, IoctlInput::VehicleIdRequest(request)) => {
+                self.directory.identify(request).map(|_| None)
+            },
+            (IoctlCommand::VehicleIdRequest, _) => Err(PduError::InvalidParameters),
+            (IoctlCommand::GetEntityStatus, input) => {
+                let (address, timeout) = self.entity_input(input)?;
+                entity_status(address, timeout).map(Some)
+            },
+            (IoctlCommand::GetDiagnosticPowerMode, input) => {
+                let (address, timeout) = self.entity_input(input)?;
+                power_mode(address, timeout).map(Some)
+            },
+            _ => Err(PduError::IdNotSupported)
+        }
+    }
+}
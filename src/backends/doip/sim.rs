@@ -0,0 +1,454 @@
+//! Simulated DoIP entity for testing without a vehicle
+//!
+//! [DoIpSimulator] runs a DoIP gateway on a local address. It answers vehicle identification
+//! requests (optionally with VIN or EID), entity status and diagnostic power mode requests over
+//! UDP, and routing activation, diagnostic message and alive check messages over TCP, on the
+//! same port. Diagnostic messages are acknowledged and forwarded to the [VirtualEcu] with the
+//! target address of the message, or to every virtual ECU for the functional address.
+//!
+//! Routing activation follows ISO 13400-2 using the [SimConfig] of the simulator, and any
+//! response code can be forced with [DoIpSimulator::set_routing_response]. With
+//! [SimConfig::alive_check] set, the simulator sends alive check requests and closes
+//! connections which do not answer them.
+//!
+//! ```
+//! use dpdu_rust::backends::doip::sim::{DoIpSimulator, SimConfig};
+//!
+//! let sim = DoIpSimulator::start("127.0.0.1:0".parse().unwrap(), SimConfig::default()).unwrap();
+//! // Answers every request positively
+//! sim.add_ecu(0x1000, |request: &[u8]| vec![[&[request[0].wrapping_add(0x40)], &request[1..]].concat()]);
+//! // Refuses routing activation as if authentication was missing
+//! sim.set_routing_response(Some(0x04));
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+pub use crate::backends::sim::VirtualEcu;
+
+use super::{
+    decode_message, encode_message, VehicleIdentification, ALIVE_CHECK_REQUEST, ALIVE_CHECK_RESPONSE, DIAGNOSTIC_ACK,
+    DIAGNOSTIC_MESSAGE, DIAGNOSTIC_NACK, ENTITY_STATUS_REQUEST, ENTITY_STATUS_RESPONSE, GENERIC_NACK, HEADER_LEN,
+    POWER_MODE_REQUEST, POWER_MODE_RESPONSE, ROUTING_ACTIVATION_REQUEST, ROUTING_ACTIVATION_RESPONSE, ROUTING_SUCCESS,
+    VEHICLE_ANNOUNCEMENT, VEHICLE_ID_REQUEST, VEHICLE_ID_REQUEST_EID, VEHICLE_ID_REQUEST_VIN
+};
+
+/// How often the threads of the simulator check whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Generic header NACK code of an unknown payload type
+const NACK_UNKNOWN_PAYLOAD_TYPE: u8 = 0x01;
+/// Generic header NACK code of a payload length which does not fit the payload type
+const NACK_INVALID_PAYLOAD_LENGTH: u8 = 0x04;
+
+/// Diagnostic message NACK code of a source address which has no routing activation
+const DIAG_NACK_INVALID_SOURCE: u8 = 0x02;
+/// Diagnostic message NACK code of a target address without a virtual ECU
+const DIAG_NACK_UNKNOWN_TARGET: u8 = 0x03;
+/// Diagnostic message NACK code of a message above [SimConfig::max_data_size]
+const DIAG_NACK_TOO_LARGE: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Configuration of a [DoIpSimulator]
+pub struct SimConfig {
+    /// Identification sent in vehicle identification responses
+    pub identification: VehicleIdentification,
+    /// Node type of entity status responses (0 = gateway, 1 = node)
+    pub node_type: u8,
+    /// Most TCP connections accepted at once
+    pub max_sockets: u8,
+    /// Largest diagnostic message accepted, sent in entity status responses if set
+    pub max_data_size: Option<u32>,
+    /// Diagnostic power mode (0 = not ready, 1 = ready, 2 = not supported)
+    pub power_mode: u8,
+    /// Functional address, forwarded to every virtual ECU
+    pub functional_address: u16,
+    /// Tester addresses routing can be activated for. Any address is accepted if empty
+    pub tester_addresses: Vec<u16>,
+    /// Supported routing activation types
+    pub activation_types: Vec<u8>,
+    /// Interval of alive check requests sent on every connection, if set. A connection which
+    /// has not answered a request when the next one is due is closed
+    pub alive_check: Option<Duration>
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            identification: VehicleIdentification {
+                vin: *b"DPDUSIM0000000001",
+                logical_address: 0x1000,
+                eid: [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+                gid: [0; 6],
+                further_action: 0,
+                sync_status: None
+            },
+            node_type: 0,
+            max_sockets: 4,
+            max_data_size: Some(4096),
+            power_mode: 1,
+            functional_address: 0xE400,
+            tester_addresses: Vec::new(),
+            activation_types: vec![0x00, 0x01],
+            alive_check: None
+        }
+    }
+}
+
+/// Virtual ECU, locked separately from the simulator state while it handles a request
+type SharedEcu = Arc<Mutex<dyn VirtualEcu>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Connection {
+    id: u64,
+    tester: Option<u16>
+}
+
+struct SimState {
+    config: SimConfig,
+    routing_response: Option<u8>,
+    ecus: BTreeMap<u16, SharedEcu>,
+    connections: Vec<Connection>,
+    next_id: u64
+}
+
+impl fmt::Debug for SimState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimState")
+            .field("config", &self.config)
+            .field("routing_response", &self.routing_response)
+            .field("ecus", &self.ecus.keys().collect::<Vec<_>>())
+            .field("connections", &self.connections)
+            .finish()
+    }
+}
+
+impl SimState {
+    /// Returns the response code of a routing activation request of a connection, registering
+    /// the tester address on success
+    fn activate_routing(&mut self, id: u64, tester: u16, activation_type: u8) -> u8 {
+        let code = self.routing_response.unwrap_or_else(|| {
+            let other_active = self.connections.iter().filter(|c| c.id != id && c.tester.is_some()).count();
+            let own = self.connections.iter().find(|c| c.id == id).and_then(|c| c.tester);
+            if !self.config.tester_addresses.is_empty() && !self.config.tester_addresses.contains(&tester) {
+                0x00
+            } else if own.is_some_and(|own| own != tester) {
+                0x02
+            } else if self.connections.iter().any(|c| c.id != id && c.tester == Some(tester)) {
+                0x03
+            } else if own.is_none() && other_active >= self.config.max_sockets.into() {
+                0x01
+            } else if !self.config.activation_types.contains(&activation_type) {
+                0x06
+            } else {
+                ROUTING_SUCCESS
+            }
+        });
+        if code == ROUTING_SUCCESS {
+            if let Some(c) = self.connections.iter_mut().find(|c| c.id == id) {
+                c.tester = Some(tester);
+            }
+        }
+        code
+    }
+
+    /// Returns the virtual ECUs a diagnostic message of a connection is forwarded to, or the
+    /// code of the NACK to send back
+    fn diagnostic_targets(&self, id: u64, payload: &[u8]) -> Result<Vec<(u16, SharedEcu)>, u8> {
+        let tester = u16::from_be_bytes([payload[0], payload[1]]);
+        let target = u16::from_be_bytes([payload[2], payload[3]]);
+        if !self.connections.iter().any(|c| c.id == id && c.tester == Some(tester)) {
+            return Err(DIAG_NACK_INVALID_SOURCE);
+        }
+        if self.config.max_data_size.is_some_and(|max| payload.len() - 4 > max as usize) {
+            return Err(DIAG_NACK_TOO_LARGE);
+        }
+        match target == self.config.functional_address {
+            true => Ok(self.ecus.iter().map(|(address, ecu)| (*address, ecu.clone())).collect()),
+            false => self.ecus.get(&target).map(|ecu| vec![(target, ecu.clone())]).ok_or(DIAG_NACK_UNKNOWN_TARGET)
+        }
+    }
+
+    /// Returns the response to a UDP request, if the simulator answers it
+    fn udp_response(&self, payload_type: u16, payload: &[u8]) -> Option<(u16, Vec<u8>)> {
+        let id = &self.config.identification;
+        match payload_type {
+            VEHICLE_ID_REQUEST if payload.is_empty() => Some((VEHICLE_ANNOUNCEMENT, id.to_payload())),
+            VEHICLE_ID_REQUEST_EID if payload == id.eid => Some((VEHICLE_ANNOUNCEMENT, id.to_payload())),
+            VEHICLE_ID_REQUEST_VIN if payload == id.vin => Some((VEHICLE_ANNOUNCEMENT, id.to_payload())),
+            VEHICLE_ID_REQUEST | VEHICLE_ID_REQUEST_EID | VEHICLE_ID_REQUEST_VIN => None,
+            ENTITY_STATUS_REQUEST => {
+                let mut status = vec![self.config.node_type, self.config.max_sockets, self.connections.len() as u8];
+                if let Some(max) = self.config.max_data_size {
+                    status.extend(max.to_be_bytes());
+                }
+                Some((ENTITY_STATUS_RESPONSE, status))
+            },
+            POWER_MODE_REQUEST => Some((POWER_MODE_RESPONSE, vec![self.config.power_mode])),
+            _ => Some((GENERIC_NACK, vec![NACK_UNKNOWN_PAYLOAD_TYPE]))
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Simulated DoIP entity, which runs until it is dropped
+pub struct DoIpSimulator {
+    address: SocketAddr,
+    state: Arc<Mutex<SimState>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>
+}
+
+impl DoIpSimulator {
+    /// Starts a simulator listening for UDP and TCP on an address. With port 0, a free port
+    /// is chosen for both
+    pub fn start(address: SocketAddr, config: SimConfig) -> io::Result<Self> {
+        let (listener, udp) = bind(address)?;
+        let address = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))?;
+        let state = Arc::new(Mutex::new(SimState {
+            config,
+            routing_response: None,
+            ecus: BTreeMap::new(),
+            connections: Vec::new(),
+            next_id: 0
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = vec![
+            spawn("doip-sim-udp", {
+                let (state, stop) = (state.clone(), stop.clone());
+                move || run_udp(udp, &state, &stop)
+            })?,
+            spawn("doip-sim-tcp", {
+                let (state, stop) = (state.clone(), stop.clone());
+                move || run_listener(listener, state, stop)
+            })?,
+        ];
+        Ok(Self { address, state, stop, threads })
+    }
+
+    /// Returns the address the simulator listens on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        lock(&self.state)
+    }
+
+    /// Adds a virtual ECU, replacing any ECU with the same logical address
+    pub fn add_ecu(&self, logical_address: u16, ecu: impl VirtualEcu) {
+        self.state().ecus.insert(logical_address, Arc::new(Mutex::new(ecu)));
+    }
+
+    /// Removes a virtual ECU
+    pub fn remove_ecu(&self, logical_address: u16) {
+        self.state().ecus.remove(&logical_address);
+    }
+
+    /// Forces the response code of every following routing activation request, such as one
+    /// of the failure codes. [None] returns to responding as configured
+    pub fn set_routing_response(&self, code: Option<u8>) {
+        self.state().routing_response = code;
+    }
+
+    /// Changes the configuration. Open connections are kept
+    pub fn set_config(&self, config: SimConfig) {
+        self.state().config = config;
+    }
+
+    /// Returns the number of open TCP connections
+    pub fn tcp_clients(&self) -> usize {
+        self.state().connections.len()
+    }
+
+    /// Sends a vehicle announcement message to an address
+    pub fn announce(&self, to: SocketAddr) -> io::Result<()> {
+        let payload = self.state().config.identification.to_payload();
+        let socket = UdpSocket::bind((self.address.ip(), 0))?;
+        socket.set_broadcast(to.is_ipv4())?;
+        socket.send_to(&encode_message(VEHICLE_ANNOUNCEMENT, &payload), to).map(|_| ())
+    }
+}
+
+impl Drop for DoIpSimulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn lock(state: &Mutex<SimState>) -> MutexGuard<'_, SimState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new().name(name.into()).spawn(f)
+}
+
+/// Binds the TCP listener and UDP socket to the same port
+fn bind(address: SocketAddr) -> io::Result<(TcpListener, UdpSocket)> {
+    let mut last = None;
+    // A port chosen for TCP can be taken for UDP already, so a few ports are tried
+    for _ in 0..8 {
+        let listener = TcpListener::bind(address)?;
+        match UdpSocket::bind(listener.local_addr()?) {
+            Ok(udp) => return Ok((listener, udp)),
+            Err(e) if address.port() == 0 => last = Some(e),
+            Err(e) => return Err(e)
+        }
+    }
+    Err(last.unwrap_or_else(|| io::ErrorKind::AddrInUse.into()))
+}
+
+fn run_udp(socket: UdpSocket, state: &Mutex<SimState>, stop: &AtomicBool) {
+    let mut buf = [0; 1500];
+    while !stop.load(Ordering::Relaxed) {
+        let Ok((n, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let response = match decode_message(&buf[..n]) {
+            Ok(Some((payload_type, payload))) => lock(state).udp_response(payload_type, payload),
+            Ok(None) => Some((GENERIC_NACK, vec![NACK_INVALID_PAYLOAD_LENGTH])),
+            Err(code) => Some((GENERIC_NACK, vec![code]))
+        };
+        if let Some((payload_type, payload)) = response {
+            let _ = socket.send_to(&encode_message(payload_type, &payload), from);
+        }
+    }
+}
+
+fn run_listener(listener: TcpListener, state: Arc<Mutex<SimState>>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(_) => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let id = {
+            let mut state = lock(&state);
+            // One connection above the limit is kept, so that its routing activation can be
+            // refused with code 0x01
+            if state.connections.len() > state.config.max_sockets.into() {
+                continue;
+            }
+            state.next_id += 1;
+            let id = state.next_id;
+            state.connections.push(Connection { id, tester: None });
+            id
+        };
+        let (state, stop) = (state.clone(), stop.clone());
+        let _ = spawn("doip-sim-client", move || {
+            let _ = run_client(stream, id, &state, &stop);
+            lock(&state).connections.retain(|c| c.id != id);
+        });
+    }
+}
+
+fn run_client(mut stream: TcpStream, id: u64, state: &Mutex<SimState>, stop: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_nodelay(true)?;
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    // Time of the next alive check request, and whether the last one is unanswered
+    let mut alive_check: Option<Instant> = None;
+    let mut alive_pending = false;
+    while !stop.load(Ordering::Relaxed) {
+        match lock(state).config.alive_check {
+            Some(interval) => {
+                let now = Instant::now();
+                if alive_check.is_some_and(|next| now >= next) {
+                    if alive_pending {
+                        return Ok(());
+                    }
+                    stream.write_all(&encode_message(ALIVE_CHECK_REQUEST, &[]))?;
+                    alive_pending = true;
+                }
+                if alive_check.is_none_or(|next| now >= next) {
+                    alive_check = Some(now + interval);
+                }
+            },
+            None => (alive_check, alive_pending) = (None, false)
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => buf.extend(&chunk[..n]),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e)
+        }
+        loop {
+            let (payload_type, payload) = match decode_message(&buf) {
+                Ok(Some((payload_type, payload))) => (payload_type, payload.to_vec()),
+                Ok(None) => break,
+                Err(code) => {
+                    // ISO 13400-2 closes the connection after an invalid header
+                    return stream.write_all(&encode_message(GENERIC_NACK, &[code]));
+                }
+            };
+            buf.drain(..HEADER_LEN + payload.len());
+            alive_pending &= payload_type != ALIVE_CHECK_RESPONSE;
+            for (ty, response) in tcp_response(state, id, payload_type, &payload) {
+                stream.write_all(&encode_message(ty, &response))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the messages sent back for a message received on a TCP connection
+fn tcp_response(state: &Mutex<SimState>, id: u64, payload_type: u16, payload: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    match payload_type {
+        ROUTING_ACTIVATION_REQUEST if matches!(payload.len(), 7 | 11) => {
+            let mut state = lock(state);
+            let tester = u16::from_be_bytes([payload[0], payload[1]]);
+            let code = state.activate_routing(id, tester, payload[2]);
+            let mut response = tester.to_be_bytes().to_vec();
+            response.extend(state.config.identification.logical_address.to_be_bytes());
+            response.push(code);
+            response.extend([0; 4]);
+            vec![(ROUTING_ACTIVATION_RESPONSE, response)]
+        },
+        DIAGNOSTIC_MESSAGE if payload.len() > 4 => {
+            let (source, target) = (&payload[..2], &payload[2..4]);
+            let ack = |payload_type, code| (payload_type, [target, source, &[code]].concat());
+            let targets = lock(state).diagnostic_targets(id, payload);
+            let ecus = match targets {
+                Ok(ecus) => ecus,
+                Err(code) => return vec![ack(DIAGNOSTIC_NACK, code)]
+            };
+            let mut messages = vec![ack(DIAGNOSTIC_ACK, 0x00)];
+            // The virtual ECUs are called without the state locked, so that slow ECUs do not
+            // hold up other connections
+            for (address, ecu) in ecus {
+                let responses = ecu.lock().unwrap_or_else(|e| e.into_inner()).handle(&payload[4..]);
+                for response in responses {
+                    messages.push((DIAGNOSTIC_MESSAGE, [&address.to_be_bytes()[..], source, &response].concat()));
+                }
+            }
+            messages
+        },
+        ALIVE_CHECK_REQUEST => {
+            let tester = lock(state).connections.iter().find(|c| c.id == id).and_then(|c| c.tester).unwrap_or_default();
+            vec![(ALIVE_CHECK_RESPONSE, tester.to_be_bytes().to_vec())]
+        },
+        ALIVE_CHECK_RESPONSE => Vec::new(),
+        ROUTING_ACTIVATION_REQUEST | DIAGNOSTIC_MESSAGE => vec![(GENERIC_NACK, vec![NACK_INVALID_PAYLOAD_LENGTH])],
+        _ => vec![(GENERIC_NACK, vec![NACK_UNKNOWN_PAYLOAD_TYPE])]
+    }
+}
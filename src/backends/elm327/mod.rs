@@ -0,0 +1,928 @@
+//! ELM327 backend (Linux), for ELM327 and STN compatible OBD adapters on a serial port
+//!
+//! Every serial port is a module, with a resource for each bus the adapter supports:
+//! * `ISO_11898_2_DWCAN` - `ISO_15765_3_on_ISO_15765_2`, `ISO_14230_3_on_ISO_15765_2` and
+//!   `ISO_OBD_on_ISO_15765_4`, with 11 or 29 bit IDs at 500 or 250 kbaud (`CP_Baudrate`).
+//!   Requests are sent to `CP_CanPhysReqId`, or `CP_CanFuncReqId` if `CP_RequestAddrMode` is 2.
+//!   Physical requests only receive responses from `CP_CanRespUSDTId`. Only normal addressing
+//!   is supported, and the adapter sends the flow control frames
+//! * `ISO_14230_1_UART` - `ISO_14230_3_on_ISO_14230_2` and `ISO_OBD_on_ISO_14230_4`, with the
+//!   5 baud or fast init of `CP_InitializationSettings`
+//! * `ISO_9141_2_UART` - `ISO_OBD_on_ISO_9141_2`
+//! * `SAE_J1850_VPW` and `SAE_J1850_PWM` - `ISO_OBD_on_SAE_J1850` and `SAE_J2190_on_SAE_J1850`.
+//!   The default header is the one of VPW, PWM needs `CP_PhysReqFormatPriorityType` and
+//!   `CP_FuncReqFormatPriorityType` set to 0x61
+//!
+//! The ports are taken from the option string of `PDUConstruct`, as comma separated paths each
+//! followed by `;` separated options:
+//! * `baud=N` - Baud rate of the port, 38400 by default
+//!
+//! Without options, every `/dev/ttyUSB*`, `/dev/ttyACM*` and `/dev/rfcomm*` port is used.
+//!
+//! `PDUModuleConnect` resets the adapter and reads its identification ([Elm327::identification]).
+//! It fails with [PduError::CableUnknown] if the adapter does not identify as an ELM327, and with
+//! [PduError::ModuleFwOutOfDate] below version 1.3 ([MIN_VERSION]).
+//!
+//! Requests are sent as a line of hex digits, and can be at most 7 bytes long. The header of
+//! ISO 9141-2, ISO 14230 and SAE J1850 requests is `CP_PhysReqFormatPriorityType` (or
+//! `CP_FuncReqFormatPriorityType`), `CP_PhysReqTargetAddr` (or `CP_FuncReqTargetAddr`) and
+//! `CP_TesterSourceAddress`. The adapter times out `CP_P2Max` after the last response. The
+//! receive cycles of a ComPrimitive are sent to the adapter with the request, so that it stops
+//! waiting once they are complete, unless the ComPrimitive has expected responses. ComPrimitives
+//! without receive cycles turn responses off (`ATR0`). Results contain the data of the response,
+//! with the CAN ID (4 bytes big endian, bit 31 set for 29 bit IDs) or the header bytes as the
+//! extra info header, and the checksum as the footer.
+//!
+//! Adapter replies are reported as errors:
+//! * `?` - [PduError::ValueNotSupported] for commands, [PduErrorEvt::TxError] for requests
+//! * `CAN ERROR`, `BUS ERROR`, `FB ERROR`, `BUS BUSY` - [PduError::NoCableDetected] or
+//!   [PduErrorEvt::TxError]
+//! * `UNABLE TO CONNECT`, `BUS INIT: ...ERROR` - [PduError::FctFailed] or [PduErrorEvt::InitError]
+//! * `BUFFER FULL`, `RX ERROR` - [PduErrorEvt::RxError]
+//! * `DATA ERROR`, `<DATA ERROR`, `<RX ERROR` - [PduErrorEvt::FrameStruct]
+//! * `LV RESET` - [PduErrorEvt::LostCommToVCI]. The adapter is configured again before the
+//!   next request
+//! * `STOPPED`, `ERRxx` - [PduErrorEvt::VCIHardwareFault]
+//!
+//! A `PDU_COPT_STARTCOMM` ComPrimitive is required on ISO 9141-2 and ISO 14230, which performs
+//! the initialization (`ATSI` with `CP_5BaudAddressPhys` or `CP_5BaudAddressFunc`, or `ATFI`).
+//! Its result contains the key bytes. The adapter sends the requests of the initialization itself,
+//! so a `PDU_COPT_STARTCOMM` ComPrimitive with data fails with [PduError::InvalidParameters].
+//! `PDU_COPT_STOPCOMM` closes the protocol (`ATPC`).
+//!
+//! The [sim] module emulates an adapter on a pseudo terminal, for testing without one.
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::elm327::Elm327>);
+//! ```
+
+pub mod sim;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant}
+};
+
+use super::serial::SerialPort;
+use crate::{
+    provider::{
+        Channel, Clock, CopControl, Cycles, Driver, DriverModule, DriverResource, ExtraInfoData, LinkParams,
+        ResultEvent, DEFAULT_RESPONSE_TIMEOUT, EXT_ADDR_FORMATS
+    },
+    BusType, ComParamValue, PduError, PduErrorEvt, Protocol, StdComParam
+};
+
+/// Module type ID of ELM327 adapters
+pub const MODULE_TYPE_ID: u32 = 327;
+
+/// Default baud rate of the serial port
+pub const DEFAULT_BAUDRATE: u32 = 38400;
+
+/// Oldest supported ELM327 version, the first which takes the number of responses with a request
+pub const MIN_VERSION: (u8, u8) = (1, 3);
+
+/// Longest request the adapter can send
+pub const MAX_REQUEST_LEN: usize = 7;
+
+/// Longest time between the lines of an adapter reply. Bus initialization takes a few seconds
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolution of the `ATST` timeout
+const TIMEOUT_UNIT: Duration = Duration::from_millis(4);
+
+/// Length of ISO 9141-2 and SAE J1850 headers
+const ISO_HEADER_LEN: usize = 3;
+
+/// Bit 31 of a CAN ID, set on 29 bit IDs
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+
+/// `CP_RequestAddrMode` value of functional requests
+const FUNCTIONAL_ADDR_MODE: u32 = 2;
+
+/// Protocols of the CAN resource
+const CAN_PROTOCOLS: &[Protocol] = &[
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4
+];
+
+/// Protocols of the ISO 14230 resource
+const KWP_PROTOCOLS: &[Protocol] = &[Protocol::Iso14230_3OnIso14230_2, Protocol::IsoObdOnIso14230_4];
+
+/// Protocols of the SAE J1850 resources
+const J1850_PROTOCOLS: &[Protocol] = &[Protocol::IsoObdOnSaeJ1850, Protocol::SaeJ2190OnSaeJ1850];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Protocol of the adapter, as selected by `ATSP`
+pub enum ElmProtocol {
+    /// SAE J1850 PWM, 41.6 kbaud (1)
+    J1850Pwm,
+    /// SAE J1850 VPW, 10.4 kbaud (2)
+    J1850Vpw,
+    /// ISO 9141-2, 5 baud init (3)
+    Iso9141_2,
+    /// ISO 14230-4, 5 baud init (4)
+    Iso14230FiveBaud,
+    /// ISO 14230-4, fast init (5)
+    Iso14230Fast,
+    /// ISO 15765-4, 11 bit IDs at 500 kbaud (6)
+    Can11Bit500k,
+    /// ISO 15765-4, 29 bit IDs at 500 kbaud (7)
+    Can29Bit500k,
+    /// ISO 15765-4, 11 bit IDs at 250 kbaud (8)
+    Can11Bit250k,
+    /// ISO 15765-4, 29 bit IDs at 250 kbaud (9)
+    Can29Bit250k
+}
+
+impl ElmProtocol {
+    /// Converts a protocol number. Returns [None] for automatic search (0) and unknown numbers
+    pub fn from_number(number: u8) -> Option<Self> {
+        Some(match number {
+            1 => Self::J1850Pwm,
+            2 => Self::J1850Vpw,
+            3 => Self::Iso9141_2,
+            4 => Self::Iso14230FiveBaud,
+            5 => Self::Iso14230Fast,
+            6 => Self::Can11Bit500k,
+            7 => Self::Can29Bit500k,
+            8 => Self::Can11Bit250k,
+            9 => Self::Can29Bit250k,
+            _ => return None
+        })
+    }
+
+    /// Returns the protocol number
+    pub fn number(&self) -> u8 {
+        match self {
+            Self::J1850Pwm => 1,
+            Self::J1850Vpw => 2,
+            Self::Iso9141_2 => 3,
+            Self::Iso14230FiveBaud => 4,
+            Self::Iso14230Fast => 5,
+            Self::Can11Bit500k => 6,
+            Self::Can29Bit500k => 7,
+            Self::Can11Bit250k => 8,
+            Self::Can29Bit250k => 9
+        }
+    }
+
+    /// Returns true for the CAN protocols
+    pub fn is_can(&self) -> bool {
+        self.number() >= 6
+    }
+
+    /// Returns true for the CAN protocols with 29 bit IDs
+    pub fn is_29bit(&self) -> bool {
+        matches!(self, Self::Can29Bit500k | Self::Can29Bit250k)
+    }
+
+    /// Returns true for the K-Line protocols, which need a bus initialization
+    pub fn is_kline(&self) -> bool {
+        matches!(self, Self::Iso9141_2 | Self::Iso14230FiveBaud | Self::Iso14230Fast)
+    }
+
+    /// Returns true for the ISO 14230 protocols
+    pub fn is_kwp(&self) -> bool {
+        matches!(self, Self::Iso14230FiveBaud | Self::Iso14230Fast)
+    }
+}
+
+/// Parses the version of an ELM327 identification, such as `ELM327 v1.5`
+pub fn parse_version(identification: &str) -> Option<(u8, u8)> {
+    let version = identification.trim().strip_prefix("ELM327")?.trim_start().strip_prefix(['v', 'V'])?;
+    let (major, minor) = version.split_once('.')?;
+    let minor: String = minor.chars().take_while(char::is_ascii_digit).collect();
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Identification of an adapter, read when its module is connected
+pub struct Identification {
+    /// Identification of the ELM327 firmware, such as `ELM327 v1.5`
+    pub elm327: String,
+    /// Version of the ELM327 firmware
+    pub version: (u8, u8),
+    /// Identification of STN adapters (`STI`), such as `STN1110 v4.2.0`
+    pub stn: Option<String>
+}
+
+/// Converts an I/O error of a serial port
+fn pdu_error(_e: io::Error) -> PduError {
+    // The port only fails if the adapter is gone
+    PduError::CommPcToVciFailed
+}
+
+/// Returns true for 29 bit CAN IDs
+fn is_29bit(id: u32) -> bool {
+    id & CAN_EFF_FLAG != 0 || id > 0x7FF
+}
+
+/// Parses space separated hex bytes
+fn parse_bytes<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Vec<u8>> {
+    tokens.try_fold(Vec::new(), |mut bytes, token| {
+        if token.len() != 2 {
+            return None;
+        }
+        bytes.push(u8::from_str_radix(token, 16).ok()?);
+        Some(bytes)
+    })
+}
+
+/// Parses the reply to `ATKW`, such as `1:EF 2:8F`
+fn parse_key_bytes(line: &str) -> Option<[u8; 2]> {
+    let mut tokens = line.split_whitespace();
+    let mut key_byte = |prefix| u8::from_str_radix(tokens.next()?.strip_prefix(prefix)?, 16).ok();
+    Some([key_byte("1:")?, key_byte("2:")?])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error message in an adapter reply
+enum ReplyError {
+    /// The command is not supported, or the request is malformed
+    Unknown,
+    /// No response before the timeout
+    NoData,
+    /// The adapter can not use the bus
+    Bus,
+    /// No ECU answered the bus initialization
+    Init,
+    /// Received data was lost
+    Overflow,
+    /// A response was corrupt
+    Data,
+    /// The adapter was reset by a low supply voltage
+    Reset,
+    /// Internal error of the adapter
+    Internal
+}
+
+impl ReplyError {
+    /// Parses a line of a reply. Returns [None] if it is not an error message
+    fn parse(line: &str) -> Option<Self> {
+        Some(match line {
+            "?" => Self::Unknown,
+            "NO DATA" => Self::NoData,
+            "CAN ERROR" | "BUS ERROR" | "FB ERROR" | "BUS BUSY" => Self::Bus,
+            "UNABLE TO CONNECT" => Self::Init,
+            "BUFFER FULL" | "RX ERROR" => Self::Overflow,
+            "LV RESET" => Self::Reset,
+            "STOPPED" => Self::Internal,
+            l if l.starts_with("BUS INIT") && l.ends_with("ERROR") => Self::Init,
+            l if l.contains("DATA ERROR") || l.contains("<RX ERROR") => Self::Data,
+            l if l.len() == 5 && l.starts_with("ERR") && l[3..].bytes().all(|b| b.is_ascii_digit()) => Self::Internal,
+            _ => return None
+        })
+    }
+
+    /// Returns the error of a command
+    fn pdu_error(&self) -> PduError {
+        match self {
+            Self::Unknown => PduError::ValueNotSupported,
+            Self::Bus => PduError::NoCableDetected,
+            Self::Reset => PduError::CommPcToVciFailed,
+            Self::NoData | Self::Init | Self::Overflow | Self::Data | Self::Internal => PduError::FctFailed
+        }
+    }
+
+    /// Returns the error event of a request, if any
+    fn event(&self) -> Option<PduErrorEvt> {
+        match self {
+            Self::NoData => None,
+            Self::Unknown | Self::Bus => Some(PduErrorEvt::TxError),
+            Self::Init => Some(PduErrorEvt::InitError),
+            Self::Overflow => Some(PduErrorEvt::RxError),
+            Self::Data => Some(PduErrorEvt::FrameStruct),
+            Self::Reset => Some(PduErrorEvt::LostCommToVCI),
+            Self::Internal => Some(PduErrorEvt::VCIHardwareFault)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Serial port of an [Elm327] module
+pub struct Elm327Port {
+    /// Path of the port
+    pub path: PathBuf,
+    /// Baud rate of the port
+    pub baudrate: u32
+}
+
+impl Elm327Port {
+    /// Parses a port of the option string: a path followed by `;` separated options
+    pub fn parse(s: &str) -> Result<Self, PduError> {
+        let mut parts = s.split(';').map(str::trim);
+        let path = parts.next().filter(|p| !p.is_empty()).ok_or(PduError::InvalidParameters)?;
+        let mut port = Self { path: path.into(), baudrate: DEFAULT_BAUDRATE };
+        for option in parts {
+            match option.split_once('=') {
+                Some(("baud", baud)) => port.baudrate = baud.parse().map_err(|_| PduError::InvalidParameters)?,
+                _ => return Err(PduError::InvalidParameters)
+            }
+        }
+        Ok(port)
+    }
+}
+
+/// Returns the USB and Bluetooth serial ports of the system
+fn serial_ports() -> Vec<Elm327Port> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev")
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| {
+                    e.file_name()
+                        .to_str()
+                        .is_some_and(|n| ["ttyUSB", "ttyACM", "rfcomm"].iter().any(|p| n.starts_with(p)))
+                })
+                .map(|e| e.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths.into_iter().map(|path| Elm327Port { path, baudrate: DEFAULT_BAUDRATE }).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Line of an adapter reply
+enum Line {
+    /// Text line
+    Text(String),
+    /// The `>` prompt, which ends every reply
+    Prompt
+}
+
+#[derive(Debug)]
+/// Serial connection to an adapter
+struct Adapter {
+    serial: SerialPort,
+    buf: Vec<u8>
+}
+
+impl Adapter {
+    fn open(port: &Elm327Port) -> Result<Self, PduError> {
+        let serial = SerialPort::open(&port.path).map_err(pdu_error)?;
+        serial.set_baudrate(port.baudrate).map_err(|_| PduError::ValueNotSupported)?;
+        Ok(Self { serial, buf: Vec::new() })
+    }
+
+    fn write_line(&self, line: &str) -> Result<(), PduError> {
+        self.serial.write_all(format!("{line}\r").as_bytes()).map_err(pdu_error)
+    }
+
+    /// Reads a line, waiting up to `timeout` for it to complete. Empty lines are skipped
+    fn read_line(&mut self, timeout: Duration) -> Result<Option<Line>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            while let Some(pos) = self.buf.iter().position(|b| matches!(b, b'\r' | b'\n' | b'>')) {
+                let prompt = self.buf[pos] == b'>';
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                // Adapters send NUL bytes after a reset
+                let text = String::from_utf8_lossy(&line[..pos]).trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string();
+                if !text.is_empty() {
+                    if prompt {
+                        self.buf.insert(0, b'>');
+                    }
+                    return Ok(Some(Line::Text(text)));
+                }
+                if prompt {
+                    return Ok(Some(Line::Prompt));
+                }
+            }
+            let mut chunk = [0; 256];
+            match self.serial.read(&mut chunk, deadline.saturating_duration_since(Instant::now())).map_err(pdu_error)? {
+                0 => return Ok(None),
+                n => self.buf.extend_from_slice(&chunk[..n])
+            }
+        }
+    }
+
+    /// Sends a command and returns the lines of its reply, without the echo of the command
+    fn command(&mut self, command: &str) -> Result<Vec<String>, PduError> {
+        self.buf.clear();
+        self.serial.flush_input().map_err(pdu_error)?;
+        self.write_line(command)?;
+        let mut lines = Vec::new();
+        loop {
+            match self.read_line(REPLY_TIMEOUT)? {
+                Some(Line::Prompt) => break,
+                Some(Line::Text(line)) if line == command => {},
+                Some(Line::Text(line)) => lines.push(line),
+                None => return Err(PduError::CommPcToVciFailed)
+            }
+        }
+        match lines.iter().find_map(|l| ReplyError::parse(l)) {
+            Some(e) => Err(e.pdu_error()),
+            None => Ok(lines)
+        }
+    }
+
+    /// Resets the adapter and reads its identification
+    fn reset(&mut self) -> Result<Identification, PduError> {
+        let lines = self.command("ATZ").map_err(|e| match e {
+            PduError::CommPcToVciFailed => e,
+            _ => PduError::CableUnknown
+        })?;
+        let elm327 = lines.into_iter().find(|l| l.starts_with("ELM327")).ok_or(PduError::CableUnknown)?;
+        let version = parse_version(&elm327).ok_or(PduError::CableUnknown)?;
+        if version < MIN_VERSION {
+            return Err(PduError::ModuleFwOutOfDate);
+        }
+        // Other adapters do not know the command
+        let stn = self.command("STI").ok().and_then(|lines| lines.into_iter().next());
+        Ok(Identification { elm327, version, stn })
+    }
+}
+
+/// Selects the adapter protocol of a link
+fn select_protocol(bus_type: BusType, params: &LinkParams) -> Result<ElmProtocol, PduError> {
+    let get = |p| params.get_u32(p).unwrap_or_default();
+    Ok(match bus_type {
+        BusType::SaeJ1850Pwm => ElmProtocol::J1850Pwm,
+        BusType::SaeJ1850Vpw => ElmProtocol::J1850Vpw,
+        BusType::Iso9141_2Uart => ElmProtocol::Iso9141_2,
+        BusType::Iso14230_1Uart => match get(StdComParam::InitializationSettings) {
+            1 => ElmProtocol::Iso14230FiveBaud,
+            _ => ElmProtocol::Iso14230Fast
+        },
+        _ => {
+            let formats = [StdComParam::CanPhysReqFormat, StdComParam::CanFuncReqFormat, StdComParam::CanRespUsdtFormat];
+            if formats.iter().any(|p| EXT_ADDR_FORMATS.contains(&get(*p))) {
+                return Err(PduError::ValueNotSupported);
+            }
+            let ids = [StdComParam::CanPhysReqId, StdComParam::CanFuncReqId, StdComParam::CanRespUsdtId];
+            match (get(StdComParam::Baudrate), ids.iter().any(|p| is_29bit(get(*p)))) {
+                (500_000, false) => ElmProtocol::Can11Bit500k,
+                (500_000, true) => ElmProtocol::Can29Bit500k,
+                (250_000, false) => ElmProtocol::Can11Bit250k,
+                (250_000, true) => ElmProtocol::Can29Bit250k,
+                _ => return Err(PduError::ValueNotSupported)
+            }
+        }
+    })
+}
+
+#[derive(Debug)]
+/// Multi frame CAN response being received
+struct Segmented {
+    len: usize,
+    next_sn: u8,
+    start: u32,
+    data: Vec<u8>
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink on an ELM327 adapter
+pub struct Elm327Channel {
+    adapter: Adapter,
+    bus_type: BusType,
+    protocol: ElmProtocol,
+    clock: Clock,
+    params: LinkParams,
+    settings: HashMap<&'static str, String>,
+    busy: bool,
+    reset: bool,
+    segments: HashMap<u32, Segmented>,
+    results: VecDeque<ResultEvent>,
+    errors: VecDeque<PduErrorEvt>
+}
+
+impl Elm327Channel {
+    /// Opens the serial port of an adapter, and configures it for a bus
+    pub fn open(port: &Elm327Port, bus_type: BusType, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let mut channel = Self {
+            adapter: Adapter::open(port)?,
+            bus_type,
+            protocol: select_protocol(bus_type, params)?,
+            clock,
+            params: params.clone(),
+            settings: HashMap::new(),
+            busy: false,
+            reset: false,
+            segments: HashMap::new(),
+            results: VecDeque::new(),
+            errors: VecDeque::new()
+        };
+        channel.setup()?;
+        Ok(channel)
+    }
+
+    /// Returns the protocol of the adapter
+    pub fn protocol(&self) -> ElmProtocol {
+        self.protocol
+    }
+
+    /// Restores the defaults of the adapter, and sets the reply format and protocol
+    fn setup(&mut self) -> Result<(), PduError> {
+        self.settings.clear();
+        // Echo off, no linefeeds, spaces and headers on, fixed timeout
+        for command in ["ATD", "ATE0", "ATL0", "ATS1", "ATH1", "ATAT0"] {
+            self.adapter.command(command)?;
+        }
+        self.configure()
+    }
+
+    /// Sets the protocol and timeout of the ComParams
+    fn configure(&mut self) -> Result<(), PduError> {
+        let protocol = select_protocol(self.bus_type, &self.params)?;
+        self.set("SP", format!("ATSP{}", protocol.number()))?;
+        self.protocol = protocol;
+        let p2_max = self.params.get_duration(StdComParam::P2Max).unwrap_or(DEFAULT_RESPONSE_TIMEOUT);
+        let timeout = (p2_max.as_micros().div_ceil(TIMEOUT_UNIT.as_micros())).clamp(1, 0xFF);
+        self.set("ST", format!("ATST{timeout:02X}"))
+    }
+
+    /// Sends a command which changes a setting, unless the setting already has that value
+    fn set(&mut self, setting: &'static str, command: String) -> Result<(), PduError> {
+        if self.settings.get(setting) != Some(&command) {
+            self.adapter.command(&command)?;
+            self.settings.insert(setting, command);
+        }
+        Ok(())
+    }
+
+    /// Sets the request header, and the receive filter of CAN responses
+    fn address(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let get = |p| params.get_u32(p).unwrap_or_default();
+        let functional = params.get_u32(StdComParam::RequestAddrMode) == Some(FUNCTIONAL_ADDR_MODE);
+        if !self.protocol.is_can() {
+            let (format, target) = match functional {
+                true => (StdComParam::FuncReqFormatPriorityType, StdComParam::FuncReqTargetAddr),
+                false => (StdComParam::PhysReqFormatPriorityType, StdComParam::PhysReqTargetAddr)
+            };
+            let header = [get(format), get(target), get(StdComParam::TesterSourceAddress)];
+            return self.set("SH", format!("ATSH{:02X}{:02X}{:02X}", header[0] as u8, header[1] as u8, header[2] as u8));
+        }
+        let id = get(if functional { StdComParam::CanFuncReqId } else { StdComParam::CanPhysReqId });
+        let response = get(StdComParam::CanRespUsdtId);
+        if self.protocol.is_29bit() {
+            // The priority bits are set separately from the other 24 bits of the ID
+            self.set("CP", format!("ATCP{:02X}", (id >> 24) & 0x1F))?;
+            self.set("SH", format!("ATSH{:06X}", id & 0xFF_FFFF))?;
+        } else {
+            self.set("SH", format!("ATSH{:03X}", id & 0x7FF))?;
+        }
+        // Functional requests receive responses from every ECU
+        let receive = match (functional, self.protocol.is_29bit()) {
+            (true, _) => "ATAR".to_string(),
+            (false, true) => format!("ATCRA{:08X}", response & 0x1FFF_FFFF),
+            (false, false) => format!("ATCRA{:03X}", response & 0x7FF)
+        };
+        self.set("CRA", receive)
+    }
+
+    /// Reads a line of the reply to the last request. Returns false on timeout
+    fn read_reply(&mut self, timeout: Duration) -> Result<bool, PduError> {
+        let line = match self.adapter.read_line(timeout)? {
+            Some(Line::Text(line)) => line,
+            Some(Line::Prompt) => {
+                self.busy = false;
+                return Ok(true);
+            },
+            None => return Ok(false)
+        };
+        if let Some(e) = ReplyError::parse(&line) {
+            self.reset |= e == ReplyError::Reset;
+            self.errors.extend(e.event());
+        } else if !(line == "OK" || line == "SEARCHING..." || line.starts_with("BUS INIT") || line.starts_with("ELM327")) {
+            let timestamp = self.clock.now();
+            let message = match self.protocol.is_can() {
+                true => self.can_message(&line, timestamp),
+                false => self.message(&line, timestamp)
+            };
+            match message {
+                Ok(Some(result)) => self.results.push_back(result),
+                Ok(None) => {},
+                Err(e) => self.errors.push_back(e)
+            }
+        }
+        Ok(true)
+    }
+
+    /// Waits for the prompt which ends the reply to the last request
+    fn finish_reply(&mut self) -> Result<(), PduError> {
+        while self.busy {
+            if !self.read_reply(REPLY_TIMEOUT)? {
+                self.busy = false;
+                return Err(PduError::CommPcToVciFailed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts a line with an ISO 9141-2, ISO 14230 or SAE J1850 response
+    fn message(&self, line: &str, timestamp: u32) -> Result<Option<ResultEvent>, PduErrorEvt> {
+        let mut header = parse_bytes(line.split_whitespace()).ok_or(PduErrorEvt::FrameStruct)?;
+        let format = *header.first().ok_or(PduErrorEvt::FrameStruct)?;
+        let header_len = match self.protocol.is_kwp() {
+            true => (if format & 0xC0 != 0 { 3 } else { 1 }) + usize::from(format & 0x3F == 0),
+            false => ISO_HEADER_LEN
+        };
+        if header.len() < header_len + 2 {
+            return Err(PduErrorEvt::FrameStruct);
+        }
+        let footer = header.split_off(header.len() - 1);
+        let data = header.split_off(header_len);
+        Ok(Some(ResultEvent {
+            start_msg_timestamp: timestamp,
+            extra_info: Some(ExtraInfoData { header, footer }),
+            data,
+            ..Default::default()
+        }))
+    }
+
+    /// Converts a line with a CAN frame, reassembling multi frame responses
+    fn can_message(&mut self, line: &str, timestamp: u32) -> Result<Option<ResultEvent>, PduErrorEvt> {
+        let mut tokens = line.split_whitespace();
+        let (id, frame) = if self.protocol.is_29bit() {
+            let bytes = parse_bytes(tokens).filter(|b| b.len() > 4).ok_or(PduErrorEvt::FrameStruct)?;
+            (u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) | CAN_EFF_FLAG, bytes[4..].to_vec())
+        } else {
+            let id = tokens.next().filter(|t| t.len() == 3).and_then(|t| u32::from_str_radix(t, 16).ok());
+            let frame = parse_bytes(tokens).filter(|b| !b.is_empty());
+            id.zip(frame).ok_or(PduErrorEvt::FrameStruct)?
+        };
+        let pci = frame[0];
+        let (start, data) = match pci >> 4 {
+            // Single frame
+            0 => {
+                let len = (pci & 0x0F) as usize;
+                if len == 0 || len >= frame.len() {
+                    return Err(PduErrorEvt::FrameStruct);
+                }
+                (timestamp, frame[1..=len].to_vec())
+            },
+            // First frame
+            1 => {
+                let len = ((pci & 0x0F) as usize) << 8 | *frame.get(1).ok_or(PduErrorEvt::FrameStruct)? as usize;
+                let segmented = Segmented { len, next_sn: 1, start: timestamp, data: frame[2..].to_vec() };
+                self.segments.insert(id, segmented);
+                return Ok(None);
+            },
+            // Consecutive frame
+            2 => {
+                let mut segmented = self.segments.remove(&id).ok_or(PduErrorEvt::FrameStruct)?;
+                if pci & 0x0F != segmented.next_sn {
+                    return Err(PduErrorEvt::FrameStruct);
+                }
+                segmented.data.extend_from_slice(&frame[1..]);
+                if segmented.data.len() < segmented.len {
+                    segmented.next_sn = (segmented.next_sn + 1) & 0x0F;
+                    self.segments.insert(id, segmented);
+                    return Ok(None);
+                }
+                segmented.data.truncate(segmented.len);
+                (segmented.start, segmented.data)
+            },
+            // Flow control frames are handled by the adapter
+            _ => return Ok(None)
+        };
+        Ok(Some(ResultEvent {
+            start_msg_timestamp: start,
+            extra_info: Some(ExtraInfoData { header: id.to_be_bytes().to_vec(), footer: Vec::new() }),
+            data,
+            ..Default::default()
+        }))
+    }
+}
+
+impl Channel for Elm327Channel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        self.finish_reply()?;
+        self.params = params.clone();
+        self.configure()
+    }
+
+    fn send(&mut self, data: &[u8], tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        let ctrl = CopControl { receive_cycles: Cycles::Infinite, tx_flag: tx_flag.to_vec(), ..Default::default() };
+        self.send_cop(data, &ctrl, params)
+    }
+
+    fn send_cop(&mut self, data: &[u8], ctrl: &CopControl, params: &LinkParams) -> Result<(), PduError> {
+        if data.is_empty() {
+            return Err(PduError::InvalidParameters);
+        }
+        if data.len() > MAX_REQUEST_LEN {
+            return Err(PduError::ValueNotSupported);
+        }
+        self.finish_reply()?;
+        if std::mem::take(&mut self.reset) {
+            self.setup()?;
+        }
+        self.address(params)?;
+        self.set("R", format!("ATR{}", u8::from(!ctrl.receive_cycles.is_none())))?;
+        let mut line: String = data.iter().map(|b| format!("{b:02X}")).collect();
+        // The adapter stops waiting after this number of responses. It counts every response,
+        // so the number is only sent if every response counts as a receive cycle
+        if let Cycles::Count(n @ 1..=15) = ctrl.receive_cycles {
+            if ctrl.expected_responses.is_empty() {
+                line.push_str(&format!("{n:X}"));
+            }
+        }
+        self.adapter.write_line(&line)?;
+        self.busy = true;
+        Ok(())
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(result) = self.results.pop_front() {
+                return Ok(Some(result));
+            }
+            if !self.read_reply(deadline.saturating_duration_since(Instant::now()))? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        self.errors.pop_front()
+    }
+
+    fn start_comm(&mut self, data: &[u8], params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        if !data.is_empty() {
+            return Err(PduError::InvalidParameters);
+        }
+        self.finish_reply()?;
+        let command = match self.protocol {
+            ElmProtocol::Iso14230Fast => "ATFI",
+            ElmProtocol::Iso9141_2 | ElmProtocol::Iso14230FiveBaud => {
+                let address = match params.get_u32(StdComParam::RequestAddrMode) {
+                    Some(FUNCTIONAL_ADDR_MODE) => params.get_u32(StdComParam::FiveBaudAddressFunc),
+                    _ => params.get_u32(StdComParam::FiveBaudAddressPhys)
+                };
+                self.set("IIA", format!("ATIIA{:02X}", address.unwrap_or(0x33) as u8))?;
+                "ATSI"
+            },
+            _ => return Ok(None)
+        };
+        // Fast init sends StartCommunication with the request header
+        self.address(params)?;
+        self.adapter.command(command)?;
+        let key_bytes = self.adapter.command("ATKW").ok().and_then(|lines| lines.iter().find_map(|l| parse_key_bytes(l)));
+        Ok(key_bytes.map(Vec::from))
+    }
+
+    fn stop_comm(&mut self, _data: &[u8], _params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        self.finish_reply()?;
+        self.adapter.command("ATPC")?;
+        Ok(None)
+    }
+}
+
+#[derive(Debug)]
+/// [Driver] for ELM327 compatible adapters on serial ports
+pub struct Elm327 {
+    ports: Vec<Elm327Port>,
+    identifications: Mutex<Vec<Option<Identification>>>
+}
+
+impl Elm327 {
+    /// Returns the serial ports, in module order
+    pub fn ports(&self) -> &[Elm327Port] {
+        &self.ports
+    }
+
+    /// Returns the identification of the adapter of a connected module
+    pub fn identification(&self, module: usize) -> Option<Identification> {
+        self.identifications().get(module).cloned().flatten()
+    }
+
+    fn identifications(&self) -> MutexGuard<'_, Vec<Option<Identification>>> {
+        self.identifications.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn port(&self, module: usize) -> Result<&Elm327Port, PduError> {
+        self.ports.get(module).ok_or(PduError::InvalidHandle)
+    }
+}
+
+impl Driver for Elm327 {
+    type Channel = Elm327Channel;
+
+    fn open(options: &str) -> Result<Self, PduError> {
+        let ports = options
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Elm327Port::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let ports = if ports.is_empty() { serial_ports() } else { ports };
+        Ok(Self { identifications: Mutex::new(vec![None; ports.len()]), ports })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        let resources = [
+            (BusType::Iso11898_2Dwcan, CAN_PROTOCOLS),
+            (BusType::Iso14230_1Uart, KWP_PROTOCOLS),
+            (BusType::Iso9141_2Uart, &[Protocol::IsoObdOnIso9141_2][..]),
+            (BusType::SaeJ1850Vpw, J1850_PROTOCOLS),
+            (BusType::SaeJ1850Pwm, J1850_PROTOCOLS)
+        ];
+        self.ports
+            .iter()
+            .map(|port| DriverModule {
+                module_type_id: MODULE_TYPE_ID,
+                name: port.path.display().to_string(),
+                info: format!("ELM327 adapter on {}", port.path.display()),
+                resources: resources
+                    .iter()
+                    .map(|(bus_type, protocols)| DriverResource {
+                        bus_type: *bus_type,
+                        protocols: protocols.to_vec(),
+                        pins: bus_type.default_obd_pins()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn connect_module(&self, module: usize) -> Result<(), PduError> {
+        let identification = Adapter::open(self.port(module)?).and_then(|mut adapter| adapter.reset());
+        self.identifications()[module] = identification.as_ref().ok().cloned();
+        identification.map(|_| ())
+    }
+
+    fn disconnect_module(&self, module: usize) -> Result<(), PduError> {
+        if let Some(identification) = self.identifications().get_mut(module) {
+            *identification = None;
+        }
+        Ok(())
+    }
+
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        let mut params = vec![(StdComParam::P2Max, DEFAULT_RESPONSE_TIMEOUT.as_micros() as u32)];
+        params.extend_from_slice(match protocol {
+            Protocol::Iso15765_3OnIso15765_2 | Protocol::Iso14230_3OnIso15765_2 | Protocol::IsoObdOnIso15765_4 => &[
+                (StdComParam::Baudrate, 500_000),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::CanPhysReqId, 0x7E0),
+                (StdComParam::CanFuncReqId, 0x7DF),
+                (StdComParam::CanRespUsdtId, 0x7E8),
+                (StdComParam::CanPhysReqFormat, 0x05),
+                (StdComParam::CanFuncReqFormat, 0x05),
+                (StdComParam::CanRespUsdtFormat, 0x05)
+            ][..],
+            Protocol::IsoObdOnIso9141_2 => &[
+                (StdComParam::InitializationSettings, 1),
+                (StdComParam::RequestAddrMode, FUNCTIONAL_ADDR_MODE),
+                (StdComParam::FiveBaudAddressPhys, 0x33),
+                (StdComParam::FiveBaudAddressFunc, 0x33),
+                (StdComParam::PhysReqFormatPriorityType, 0x68),
+                (StdComParam::PhysReqTargetAddr, 0x6A),
+                (StdComParam::FuncReqFormatPriorityType, 0x68),
+                (StdComParam::FuncReqTargetAddr, 0x6A),
+                (StdComParam::TesterSourceAddress, 0xF1)
+            ],
+            Protocol::IsoObdOnIso14230_4 => &[
+                (StdComParam::InitializationSettings, 2),
+                (StdComParam::RequestAddrMode, FUNCTIONAL_ADDR_MODE),
+                (StdComParam::FiveBaudAddressPhys, 0x33),
+                (StdComParam::FiveBaudAddressFunc, 0x33),
+                (StdComParam::PhysReqFormatPriorityType, 0x80),
+                (StdComParam::PhysReqTargetAddr, 0x10),
+                (StdComParam::FuncReqFormatPriorityType, 0xC0),
+                (StdComParam::FuncReqTargetAddr, 0x33),
+                (StdComParam::TesterSourceAddress, 0xF1)
+            ],
+            Protocol::Iso14230_3OnIso14230_2 => &[
+                (StdComParam::InitializationSettings, 2),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::FiveBaudAddressPhys, 0x10),
+                (StdComParam::FiveBaudAddressFunc, 0x33),
+                (StdComParam::PhysReqFormatPriorityType, 0x80),
+                (StdComParam::PhysReqTargetAddr, 0x10),
+                (StdComParam::FuncReqFormatPriorityType, 0xC0),
+                (StdComParam::FuncReqTargetAddr, 0x33),
+                (StdComParam::TesterSourceAddress, 0xF1)
+            ],
+            _ => &[
+                (StdComParam::RequestAddrMode, FUNCTIONAL_ADDR_MODE),
+                (StdComParam::PhysReqFormatPriorityType, 0x6C),
+                (StdComParam::PhysReqTargetAddr, 0x10),
+                (StdComParam::FuncReqFormatPriorityType, 0x68),
+                (StdComParam::FuncReqTargetAddr, 0x6A),
+                (StdComParam::TesterSourceAddress, 0xF1)
+            ]
+        });
+        params.into_iter().filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v))).collect()
+    }
+
+    fn start_comm_required(&self, protocol: Protocol) -> bool {
+        protocol.bus_types().iter().any(|b| matches!(b, BusType::Iso14230_1Uart | BusType::Iso9141_2Uart))
+    }
+
+    fn open_channel(
+        &self,
+        module: usize,
+        resource: &DriverResource,
+        _protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        Elm327Channel::open(self.port(module)?, resource.bus_type, params, clock)
+    }
+}
@@ -0,0 +1,530 @@
+//! Emulated ELM327 adapter on a pseudo terminal, for testing without an adapter or vehicle
+//!
+//! [Elm327Simulator] opens a pseudo terminal, whose [path](Elm327Simulator::path) is used as the
+//! port of the [Elm327](super::Elm327) backend. It answers the AT commands the backend uses,
+//! `STI` if [SimConfig::stn] is set, and requests on the vehicle protocol of the configuration.
+//! Requests are forwarded to the [VirtualEcu]s by address:
+//! * 11 bit CAN - The address is the request ID. Responses are sent from the request ID + 8, and
+//!   the functional ID 0x7DF reaches every ECU
+//! * 29 bit CAN - The address is the target byte of a `0x18DA` request ID. Responses are sent
+//!   from `0x18DA` with the tester and ECU address, and `0x18DB33` reaches every ECU
+//! * ISO 9141-2, ISO 14230 and SAE J1850 - The address is the target byte of the header, and the
+//!   targets 0x33 and 0x6A reach every ECU. Communication on the K-Line protocols starts with
+//!   `ATSI`, `ATFI` or the first request
+//!
+//! A request on another protocol than the one of the vehicle gets `CAN ERROR`,
+//! `BUS INIT: ...ERROR` or `NO DATA`, like on a real vehicle. [SimConfig::unsupported] makes
+//! the adapter answer commands and requests with `?`.
+//!
+//! ```
+//! use std::time::Duration;
+//! use dpdu_rust::{
+//!     backends::elm327::{sim::{Elm327Simulator, SimConfig}, Elm327},
+//!     provider::{Channel, Clock, Driver, LinkParams},
+//!     Protocol
+//! };
+//!
+//! let sim = Elm327Simulator::start(SimConfig::default()).unwrap();
+//! sim.add_ecu(0x7E0, |request: &[u8]| vec![[&[request[0].wrapping_add(0x40)], &request[1..]].concat()]);
+//!
+//! let driver = Elm327::open(&sim.path().display().to_string()).unwrap();
+//! driver.connect_module(0).unwrap();
+//! assert_eq!(driver.identification(0).unwrap().version, (1, 5));
+//!
+//! let resource = &driver.modules()[0].resources[0];
+//! let protocol = Protocol::IsoObdOnIso15765_4;
+//! let params = LinkParams::new(driver.com_params(protocol));
+//! let mut channel = driver.open_channel(0, resource, protocol, &params, Clock::new()).unwrap();
+//! channel.send(&[0x01, 0x00], &[], &params).unwrap();
+//! let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+//! assert_eq!(response.data, [0x41, 0x00]);
+//! assert_eq!(response.extra_info.unwrap().header, [0x00, 0x00, 0x07, 0xE8]);
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard
+    },
+    thread::{self, JoinHandle},
+    time::Duration
+};
+
+pub use crate::backends::sim::VirtualEcu;
+
+use super::{ElmProtocol, MAX_REQUEST_LEN};
+use crate::backends::serial::{Pty, SerialPort};
+
+/// How often the simulator thread checks whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Functional targets of ISO 9141-2, ISO 14230 and SAE J1850 headers
+const FUNCTIONAL_TARGETS: &[u8] = &[0x33, 0x6A];
+/// Functional request ID of 11 bit CAN
+const CAN_FUNCTIONAL_ID: u32 = 0x7DF;
+/// Filler byte of CAN frames
+const CAN_FILLER: u8 = 0x00;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Configuration of an [Elm327Simulator]
+pub struct SimConfig {
+    /// Identification sent after a reset and by `ATI`
+    pub identification: &'static str,
+    /// Identification sent by `STI`. [None] emulates an adapter without STN commands
+    pub stn: Option<&'static str>,
+    /// Protocol of the vehicle
+    pub protocol: ElmProtocol,
+    /// Key bytes of the K-Line initialization
+    pub key_bytes: [u8; 2],
+    /// Time before each response
+    pub response_delay: Duration,
+    /// Beginnings of commands and requests answered with `?`, like adapters which do not
+    /// support them, such as `"ATAT"`
+    pub unsupported: &'static [&'static str]
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            identification: "ELM327 v1.5",
+            stn: None,
+            protocol: ElmProtocol::Can11Bit500k,
+            key_bytes: [0xE9, 0x8F],
+            response_delay: Duration::from_millis(10),
+            unsupported: &[]
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Settings of the emulated adapter, which a reset restores
+struct Settings {
+    echo: bool,
+    linefeeds: bool,
+    spaces: bool,
+    headers: bool,
+    responses: bool,
+    /// Protocol number of `ATSP`, 0 for automatic search
+    protocol: u8,
+    /// Protocol found by the automatic search
+    found: Option<ElmProtocol>,
+    header: Option<u32>,
+    priority: u8,
+    receive: Option<u32>,
+    initialized: bool
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            echo: true,
+            linefeeds: false,
+            spaces: true,
+            headers: false,
+            responses: true,
+            protocol: 0,
+            found: None,
+            header: None,
+            priority: 0x18,
+            receive: None,
+            initialized: false
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the protocol in use
+    fn protocol(&self) -> Option<ElmProtocol> {
+        ElmProtocol::from_number(self.protocol).or(self.found)
+    }
+
+    /// Formats bytes as hex, with or without spaces
+    fn hex(&self, bytes: &[u8]) -> String {
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+        hex.join(if self.spaces { " " } else { "" })
+    }
+
+    /// Returns the lines of a response from the ECU with `address` to `tester`, sent on `id` if
+    /// the protocol is CAN
+    fn format(&self, protocol: ElmProtocol, address: u8, tester: u8, id: u32, response: &[u8]) -> Vec<String> {
+        if protocol.is_can() {
+            let frames = can_frames(response);
+            if self.headers {
+                let id = match protocol.is_29bit() {
+                    true => self.hex(&id.to_be_bytes()),
+                    false => format!("{id:03X}")
+                };
+                let separator = if self.spaces { " " } else { "" };
+                return frames.iter().map(|f| format!("{id}{separator}{}", self.hex(f))).collect();
+            }
+            if frames.len() == 1 {
+                return vec![self.hex(response)];
+            }
+            // Without headers, the length is followed by the numbered data of each frame
+            let chunks = [&response[..6]].into_iter().chain(response[6..].chunks(7));
+            let mut lines = vec![format!("{:03X}", response.len())];
+            lines.extend(chunks.enumerate().map(|(i, c)| format!("{:X}: {}", i & 0x0F, self.hex(c))));
+            return lines;
+        }
+        if !self.headers {
+            return vec![self.hex(response)];
+        }
+        let mut frame = match protocol {
+            ElmProtocol::Iso14230FiveBaud | ElmProtocol::Iso14230Fast if response.len() > 0x3F => {
+                vec![0x80, tester, address, response.len() as u8]
+            },
+            ElmProtocol::Iso14230FiveBaud | ElmProtocol::Iso14230Fast => vec![0x80 | response.len() as u8, tester, address],
+            ElmProtocol::J1850Pwm => vec![0x41, 0x6B, address],
+            _ => vec![0x48, 0x6B, address]
+        };
+        frame.extend(response);
+        frame.push(match protocol {
+            ElmProtocol::J1850Pwm | ElmProtocol::J1850Vpw => j1850_crc(&frame),
+            _ => frame.iter().fold(0, |sum: u8, b| sum.wrapping_add(*b))
+        });
+        vec![self.hex(&frame)]
+    }
+}
+
+struct SimState {
+    config: SimConfig,
+    ecus: BTreeMap<u32, Box<dyn VirtualEcu>>,
+    settings: Settings
+}
+
+impl fmt::Debug for SimState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimState")
+            .field("config", &self.config)
+            .field("ecus", &self.ecus.keys().collect::<Vec<_>>())
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+/// Returns the J1850 CRC of a frame
+fn j1850_crc(data: &[u8]) -> u8 {
+    !data.iter().fold(0xFF, |crc: u8, b| (0..8).fold(crc ^ b, |c, _| if c & 0x80 != 0 { (c << 1) ^ 0x1D } else { c << 1 }))
+}
+
+/// Splits a response into ISO-TP frames
+fn can_frames(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    if data.len() <= 7 {
+        frames.push([&[data.len() as u8][..], data].concat());
+    } else {
+        frames.push([&[0x10 | (data.len() >> 8) as u8 & 0x0F, data.len() as u8][..], &data[..6]].concat());
+        for (i, chunk) in data[6..].chunks(7).enumerate() {
+            frames.push([&[0x20 | ((i + 1) & 0x0F) as u8][..], chunk].concat());
+        }
+    }
+    for frame in frames.iter_mut() {
+        frame.resize(8, CAN_FILLER);
+    }
+    frames
+}
+
+impl SimState {
+    /// Handles an AT or ST command, returning the lines of the reply
+    fn command(&mut self, command: &str) -> Vec<String> {
+        let on = |arg: &str| match arg {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None
+        };
+        let hex = |arg: &str, lens: &[usize]| match lens.contains(&arg.len()) {
+            true => u32::from_str_radix(arg, 16).ok(),
+            false => None
+        };
+        let settings = &mut self.settings;
+        let ok = |done: Option<()>| vec![if done.is_some() { "OK" } else { "?" }.to_string()];
+        match command {
+            "ATZ" | "ATWS" => {
+                self.settings = Settings::default();
+                vec![String::new(), self.config.identification.to_string()]
+            },
+            "ATI" => vec![self.config.identification.to_string()],
+            "AT@1" => vec!["OBDII to RS232 Interpreter".to_string()],
+            "ATRV" => vec!["12.6V".to_string()],
+            "STI" => vec![self.config.stn.unwrap_or("?").to_string()],
+            "ATD" => {
+                *settings = Settings { echo: settings.echo, ..Settings::default() };
+                ok(Some(()))
+            },
+            "ATDPN" => vec![match (settings.protocol, settings.protocol()) {
+                (0, Some(p)) => format!("A{}", p.number()),
+                (n, _) => n.to_string()
+            }],
+            "ATAR" => {
+                settings.receive = None;
+                ok(Some(()))
+            },
+            "ATPC" => {
+                settings.initialized = false;
+                ok(Some(()))
+            },
+            "ATKW" => match settings.initialized && settings.protocol().is_some_and(|p| p.is_kline()) {
+                true => vec![format!("1:{:02X} 2:{:02X}", self.config.key_bytes[0], self.config.key_bytes[1])],
+                false => vec!["NO DATA".to_string()]
+            },
+            "ATFI" | "ATSI" => {
+                let valid = match settings.protocol() {
+                    Some(ElmProtocol::Iso14230Fast) => command == "ATFI",
+                    Some(ElmProtocol::Iso9141_2 | ElmProtocol::Iso14230FiveBaud) => command == "ATSI",
+                    _ => false
+                };
+                match valid {
+                    true => vec![self.init()],
+                    false => ok(None)
+                }
+            },
+            _ => {
+                let set = |prefix: &str| command.strip_prefix(prefix);
+                ok(if let Some(arg) = set("ATE") {
+                    on(arg).map(|v| settings.echo = v)
+                } else if let Some(arg) = set("ATL") {
+                    on(arg).map(|v| settings.linefeeds = v)
+                } else if let Some(arg) = set("ATS").filter(|a| a.len() == 1) {
+                    on(arg).map(|v| settings.spaces = v)
+                } else if let Some(arg) = set("ATH") {
+                    on(arg).map(|v| settings.headers = v)
+                } else if let Some(arg) = set("ATR") {
+                    on(arg).map(|v| settings.responses = v)
+                } else if let Some(arg) = set("ATAT") {
+                    matches!(arg, "0" | "1" | "2").then_some(())
+                } else if let Some(arg) = set("ATCAF") {
+                    on(arg).map(|_| ())
+                } else if let Some(arg) = set("ATST") {
+                    hex(arg, &[2]).map(|_| ())
+                } else if let Some(arg) = set("ATSP").or_else(|| set("ATTP")) {
+                    let arg = arg.strip_prefix('A').unwrap_or(arg);
+                    hex(arg, &[1]).filter(|n| *n <= 9).map(|n| {
+                        *settings = Settings { protocol: n as u8, found: None, initialized: false, ..settings.clone() };
+                    })
+                } else if let Some(arg) = set("ATSH") {
+                    hex(arg, &[3, 6]).map(|h| settings.header = Some(h))
+                } else if let Some(arg) = set("ATCP") {
+                    hex(arg, &[2]).map(|p| settings.priority = p as u8 & 0x1F)
+                } else if let Some(arg) = set("ATCRA") {
+                    hex(arg, &[3, 8]).map(|r| settings.receive = Some(r))
+                } else if let Some(arg) = set("ATIIA") {
+                    hex(arg, &[2]).map(|_| ())
+                } else {
+                    None
+                })
+            }
+        }
+    }
+
+    /// Initializes the K-Line protocols, returning the line of the reply
+    fn init(&mut self) -> String {
+        let success = self.settings.protocol() == Some(self.config.protocol) && !self.ecus.is_empty();
+        self.settings.initialized = success;
+        format!("BUS INIT: ...{}", if success { "OK" } else { "ERROR" })
+    }
+
+    /// Returns the request header in use, 24 bits or an 11 bit CAN ID
+    fn header(&self, protocol: ElmProtocol) -> u32 {
+        self.settings.header.unwrap_or(match protocol {
+            ElmProtocol::J1850Pwm => 0x616AF1,
+            ElmProtocol::J1850Vpw | ElmProtocol::Iso9141_2 => 0x686AF1,
+            ElmProtocol::Iso14230FiveBaud | ElmProtocol::Iso14230Fast => 0xC133F1,
+            ElmProtocol::Can11Bit500k | ElmProtocol::Can11Bit250k => CAN_FUNCTIONAL_ID,
+            ElmProtocol::Can29Bit500k | ElmProtocol::Can29Bit250k => 0xDB33F1
+        })
+    }
+
+    /// Handles a request, returning the lines of each response. `count` limits the responses
+    fn request(&mut self, data: &[u8], count: Option<usize>) -> Vec<Vec<String>> {
+        let line = |s: &str| vec![vec![s.to_string()]];
+        if data.len() > MAX_REQUEST_LEN {
+            return line("?");
+        }
+        let mut replies = Vec::new();
+        let protocol = match self.settings.protocol() {
+            Some(p) => p,
+            None => {
+                replies.push(vec!["SEARCHING...".to_string()]);
+                self.settings.found = Some(self.config.protocol);
+                self.config.protocol
+            }
+        };
+        if protocol != self.config.protocol {
+            return match protocol {
+                p if p.is_can() => line("CAN ERROR"),
+                p if p.is_kline() => line("BUS INIT: ...ERROR"),
+                _ => line("NO DATA")
+            };
+        }
+        if protocol.is_kline() && !self.settings.initialized {
+            let init = self.init();
+            replies.push(vec![init]);
+            if !self.settings.initialized {
+                return replies;
+            }
+        }
+        if !self.settings.responses {
+            return replies;
+        }
+
+        let header = self.header(protocol);
+        // Addresses of the ECUs the request reaches, with the CAN ID of their responses
+        let ecus: Vec<(u32, u32)> = if protocol.is_can() && !protocol.is_29bit() {
+            let id = header & 0x7FF;
+            self.ecus.keys().filter(|a| **a <= 0x7FF && (id == CAN_FUNCTIONAL_ID || **a == id)).map(|a| (*a, a + 8)).collect()
+        } else if protocol.is_can() {
+            let id = (self.settings.priority as u32) << 24 | header;
+            let functional = id >> 8 & 0xFFFF == 0xDB33;
+            let physical = id >> 16 & 0xFF == 0xDA;
+            self.ecus
+                .keys()
+                .filter(|a| functional || (physical && **a == id >> 8 & 0xFF))
+                .map(|a| (*a, 0x18DA_0000 | (id & 0xFF) << 8 | a))
+                .collect()
+        } else {
+            let target = header >> 8 & 0xFF;
+            let functional = FUNCTIONAL_TARGETS.contains(&(target as u8));
+            self.ecus.keys().filter(|a| **a <= 0xFF && (functional || **a == target)).map(|a| (*a, 0)).collect()
+        };
+        let mut responses = Vec::new();
+        for (address, id) in ecus {
+            if let Some(ecu) = self.ecus.get_mut(&address) {
+                responses.extend(ecu.handle(data).into_iter().filter(|r| !r.is_empty()).map(|r| (address, id, r)));
+            }
+        }
+        if let (true, Some(receive)) = (protocol.is_can(), self.settings.receive) {
+            responses.retain(|(_, id, _)| *id == receive);
+        }
+        if responses.is_empty() {
+            replies.push(vec!["NO DATA".to_string()]);
+        }
+        let tester = (header & 0xFF) as u8;
+        for (address, id, response) in responses.into_iter().take(count.unwrap_or(usize::MAX)) {
+            replies.push(self.settings.format(protocol, address as u8, tester, id, &response));
+        }
+        replies
+    }
+}
+
+#[derive(Debug)]
+/// Emulated ELM327 adapter with virtual ECUs, which runs until it is dropped
+pub struct Elm327Simulator {
+    path: PathBuf,
+    state: Arc<Mutex<SimState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Elm327Simulator {
+    /// Starts a simulator on a new pseudo terminal
+    pub fn start(config: SimConfig) -> io::Result<Self> {
+        let pty = Pty::open()?;
+        let path = pty.path().to_owned();
+        let state = Arc::new(Mutex::new(SimState { config, ecus: BTreeMap::new(), settings: Settings::default() }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new().name("elm327-sim".into()).spawn({
+            let (state, stop) = (state.clone(), stop.clone());
+            move || run(pty.master(), &state, &stop)
+        })?;
+        Ok(Self { path, state, stop, thread: Some(thread) })
+    }
+
+    /// Returns the path of the serial port the backend opens
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        lock(&self.state)
+    }
+
+    /// Adds a virtual ECU, replacing any ECU with the same address
+    pub fn add_ecu(&self, address: u32, ecu: impl VirtualEcu) {
+        self.state().ecus.insert(address, Box::new(ecu));
+    }
+
+    /// Removes a virtual ECU
+    pub fn remove_ecu(&self, address: u32) {
+        self.state().ecus.remove(&address);
+    }
+
+    /// Changes the configuration
+    pub fn set_config(&self, config: SimConfig) {
+        self.state().config = config;
+    }
+
+    /// Returns true once a K-Line protocol has been initialized, until the protocol is closed
+    pub fn is_initialized(&self) -> bool {
+        self.state().settings.initialized
+    }
+}
+
+impl Drop for Elm327Simulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn lock(state: &Mutex<SimState>) -> MutexGuard<'_, SimState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run(port: &SerialPort, state: &Mutex<SimState>, stop: &AtomicBool) {
+    let mut line = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        match port.read_byte(POLL_INTERVAL) {
+            Ok(Some(b'\r')) => {
+                let command = String::from_utf8_lossy(&line).into_owned();
+                line.clear();
+                let _ = handle_line(port, state, &command);
+            },
+            Ok(Some(b'\n' | 0)) | Ok(None) => {},
+            Ok(Some(b)) => line.push(b),
+            Err(_) => thread::sleep(POLL_INTERVAL)
+        }
+    }
+}
+
+/// Answers a command or request line, ending the reply with the prompt
+fn handle_line(port: &SerialPort, state: &Mutex<SimState>, line: &str) -> io::Result<()> {
+    let mut guard = lock(state);
+    let settings = guard.settings.clone();
+    let eol = if settings.linefeeds { "\r\n" } else { "\r" };
+    if settings.echo {
+        port.write_all(format!("{line}{eol}").as_bytes())?;
+    }
+    // Spaces and case are ignored
+    let command: String = line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+    let mut delay = Duration::ZERO;
+    let replies = if command.is_empty() {
+        Vec::new()
+    } else if guard.config.unsupported.iter().any(|u| command.starts_with(u)) {
+        vec![vec!["?".to_string()]]
+    } else if command.starts_with("AT") || command.starts_with("ST") {
+        vec![guard.command(&command)]
+    } else if command.bytes().all(|b| b.is_ascii_hexdigit()) {
+        // An odd digit at the end is the number of responses
+        let (data, count) = command.split_at(command.len() & !1);
+        let data: Vec<u8> = (0..data.len()).step_by(2).filter_map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok()).collect();
+        let count = usize::from_str_radix(count, 16).ok().filter(|n| *n > 0);
+        delay = guard.config.response_delay;
+        guard.request(&data, count)
+    } else {
+        vec![vec!["?".to_string()]]
+    };
+    let eol = if guard.settings.linefeeds { "\r\n" } else { "\r" };
+    drop(guard);
+    for lines in replies {
+        thread::sleep(delay);
+        for l in lines {
+            port.write_all(format!("{l}{eol}").as_bytes())?;
+        }
+    }
+    port.write_all(format!("{eol}>").as_bytes())
+}
@@ -0,0 +1,977 @@
+//! SAE J2534 PassThru bridge, which runs the CAN protocols of a J2534 (04.04) library
+//!
+//! Every library is a module with one `ISO_11898_2_DWCAN` resource on pins 6 and 14 of the J1962
+//! connector. The libraries are taken from the option string of `PDUConstruct`, as comma
+//! separated paths each followed by `;` separated options:
+//! * `name=NAME` - Name of the module, the file name of the library by default
+//! * `device=NAME` - Device name passed to `PassThruOpen`, which opens the default device if
+//!   it is not given
+//!
+//! Without options, the libraries registered in `~/.passthru/*.json` (`FUNCTION_LIB` and `NAME`)
+//! are used on Unix systems.
+//!
+//! `PDUModuleConnect` opens the device (`PassThruOpen`) and reads its versions
+//! ([J2534::version]), `PDUModuleDisconnect` closes it. Each ComLogicalLink is a J2534
+//! channel, connected with `CP_Baudrate`:
+//! * `ISO_11898_RAW` - J2534 `CAN`, with both 11 and 29 bit IDs. Messages are a 4 byte big
+//!   endian CAN ID followed by up to 8 data bytes. Bit 31 of the ID marks a 29 bit ID, which is
+//!   implied for IDs above 0x7FF
+//! * `ISO_15765_3_on_ISO_15765_2`, `ISO_14230_3_on_ISO_15765_2` and `ISO_OBD_on_ISO_15765_4` -
+//!   J2534 `ISO15765`. A flow control filter is started for `CP_CanRespUSDTId` with flow
+//!   control frames sent to `CP_CanPhysReqId`. Functional requests (`CP_RequestAddrMode` = 2)
+//!   are sent to `CP_CanFuncReqId`. `CP_BlockSize`, `CP_StMin`, `CP_BlockSizeOverride` and
+//!   `CP_StMinOverride` are set with `SET_CONFIG`, and `CP_CanFillerByteHandling` pads frames.
+//!   Results contain the data of the response, with the CAN ID (and address extension byte) as
+//!   the extra info header
+//!
+//! Changing `CP_Baudrate` or an ISO-TP address reconnects the channel. On `ISO_11898_RAW` links,
+//! message filters started with `PDU_IOCTL_START_MSG_FILTER` ([IoFilterData](crate::IoFilterData))
+//! are started on the channel as J2534 `PASS_FILTER`s and `BLOCK_FILTER`s, and a pass filter for
+//! every frame is started while the link has no pass filter. Once no filter is left, as after
+//! `PDU_IOCTL_CLEAR_MSG_FILTER`, the filters of the channel are cleared with `CLEAR_MSG_FILTERS`.
+//! Block filters comparing bit 31 of the CAN ID are left to the backend. J2534 only allows flow
+//! control filters on `ISO15765` channels, so the backend filters the results of ISO-TP links.
+//!
+//! `PDU_IOCTL_CLEAR_TX_QUEUE` (`CLEAR_TX_BUFFER`), `PDU_IOCTL_CLEAR_RX_QUEUE` (`CLEAR_RX_BUFFER`),
+//! `PDU_IOCTL_READ_VBATT` (`READ_VBATT`), `PDU_IOCTL_READ_PROG_VOLTAGE` (`READ_PROG_VOLTAGE`) and
+//! `PDU_IOCTL_SET_PROG_VOLTAGE` (`PassThruSetProgrammingVoltage`) are passed to the device.
+//!
+//! Results are timestamped with the timestamps of the device, converted to the [Clock] of the
+//! backend.
+//!
+//! J2534 errors are returned as a [PduError] ([pdu_error]), the description of the last one is
+//! kept by the library ([J2534::last_error]). An overflow of the receive buffer is reported as
+//! [PduErrorEvt::RxError].
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::j2534::J2534>);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_long, c_ulong, c_void, CStr, CString},
+    fs,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration
+};
+
+use libloading::Library;
+
+use crate::{
+    provider::{
+        encode_st_min, Channel, Clock, Driver, DriverModule, DriverResource, ExtraInfoData, IoctlData, IoctlInput,
+        IsoTpConfig, LinkParams, MessageFilter, MessageFilters, ResultEvent, DEFAULT_RESPONSE_TIMEOUT, OVERRIDE_DISABLED
+    },
+    BusType, CanFlags, ComParamValue, IoctlCommand, PduError, PduErrorEvt, Protocol, StdComParam, CAN_MAX_DLEN
+};
+
+/// Module type ID of J2534 devices
+pub const MODULE_TYPE_ID: u32 = 2534;
+
+/// Protocols of the CAN resource
+const PROTOCOLS: &[Protocol] = &[
+    Protocol::Iso11898Raw,
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4
+];
+
+/// Time a message may take to be transmitted by the device
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `CP_RequestAddrMode` of functional requests
+const FUNCTIONAL_ADDR_MODE: u32 = 2;
+
+/// Largest message of `PASSTHRU_MSG`
+const MAX_MSG_LEN: usize = 4128;
+
+/// Largest mask and pattern message of a pass or block filter
+const MAX_FILTER_LEN: usize = 12;
+
+// Protocol IDs
+const CAN: c_ulong = 0x05;
+const ISO15765: c_ulong = 0x06;
+
+// Connect and transmit flags
+const ISO15765_FRAME_PAD: c_ulong = 0x0040;
+const ISO15765_ADDR_TYPE: c_ulong = 0x0080;
+const CAN_29BIT_ID: c_ulong = 0x0100;
+const CAN_ID_BOTH: c_ulong = 0x0800;
+
+// Receive status bits
+const TX_MSG_TYPE: c_ulong = 0x0001;
+const START_OF_MESSAGE: c_ulong = 0x0002;
+const TX_INDICATION: c_ulong = 0x0008;
+
+// Filter types
+const PASS_FILTER: c_ulong = 0x01;
+const BLOCK_FILTER: c_ulong = 0x02;
+const FLOW_CONTROL_FILTER: c_ulong = 0x03;
+
+// IOCTL IDs
+const SET_CONFIG: c_ulong = 0x02;
+const READ_VBATT: c_ulong = 0x03;
+const CLEAR_TX_BUFFER: c_ulong = 0x07;
+const CLEAR_RX_BUFFER: c_ulong = 0x08;
+const CLEAR_MSG_FILTERS: c_ulong = 0x0A;
+const READ_PROG_VOLTAGE: c_ulong = 0x0E;
+
+// Configuration parameters
+const ISO15765_BS: c_ulong = 0x1E;
+const ISO15765_STMIN: c_ulong = 0x1F;
+const BS_TX: c_ulong = 0x22;
+const STMIN_TX: c_ulong = 0x23;
+
+/// `BS_TX` and `STMIN_TX` value which uses the values of the ECU
+const USE_ECU_VALUE: c_ulong = 0xFFFF;
+
+// Error codes
+const STATUS_NOERROR: c_long = 0x00;
+const ERR_NOT_SUPPORTED: c_long = 0x01;
+const ERR_INVALID_CHANNEL_ID: c_long = 0x02;
+const ERR_INVALID_PROTOCOL_ID: c_long = 0x03;
+const ERR_NULL_PARAMETER: c_long = 0x04;
+const ERR_INVALID_IOCTL_VALUE: c_long = 0x05;
+const ERR_INVALID_FLAGS: c_long = 0x06;
+const ERR_DEVICE_NOT_CONNECTED: c_long = 0x08;
+const ERR_TIMEOUT: c_long = 0x09;
+const ERR_INVALID_MSG: c_long = 0x0A;
+const ERR_INVALID_TIME_INTERVAL: c_long = 0x0B;
+const ERR_EXCEEDED_LIMIT: c_long = 0x0C;
+const ERR_INVALID_MSG_ID: c_long = 0x0D;
+const ERR_DEVICE_IN_USE: c_long = 0x0E;
+const ERR_INVALID_IOCTL_ID: c_long = 0x0F;
+const ERR_BUFFER_EMPTY: c_long = 0x10;
+const ERR_BUFFER_FULL: c_long = 0x11;
+const ERR_BUFFER_OVERFLOW: c_long = 0x12;
+const ERR_PIN_INVALID: c_long = 0x13;
+const ERR_CHANNEL_IN_USE: c_long = 0x14;
+const ERR_MSG_PROTOCOL_ID: c_long = 0x15;
+const ERR_INVALID_FILTER_ID: c_long = 0x16;
+const ERR_NOT_UNIQUE: c_long = 0x18;
+const ERR_INVALID_BAUDRATE: c_long = 0x19;
+const ERR_INVALID_DEVICE_ID: c_long = 0x1A;
+
+/// Converts a J2534 error code to a [PduError]
+pub fn pdu_error(code: c_long) -> PduError {
+    match code {
+        ERR_NOT_SUPPORTED | ERR_INVALID_IOCTL_ID => PduError::IdNotSupported,
+        ERR_INVALID_PROTOCOL_ID | ERR_INVALID_BAUDRATE | ERR_INVALID_FLAGS | ERR_INVALID_IOCTL_VALUE => {
+            PduError::ValueNotSupported
+        },
+        ERR_NULL_PARAMETER | ERR_INVALID_MSG | ERR_INVALID_TIME_INTERVAL | ERR_INVALID_MSG_ID | ERR_MSG_PROTOCOL_ID => {
+            PduError::InvalidParameters
+        },
+        ERR_INVALID_CHANNEL_ID | ERR_INVALID_FILTER_ID | ERR_INVALID_DEVICE_ID => PduError::InvalidHandle,
+        ERR_DEVICE_NOT_CONNECTED => PduError::CommPcToVciFailed,
+        ERR_DEVICE_IN_USE | ERR_CHANNEL_IN_USE | ERR_NOT_UNIQUE => PduError::ResourceBusy,
+        ERR_BUFFER_FULL => PduError::TxQueueFull,
+        ERR_EXCEEDED_LIMIT => PduError::ResourceError,
+        ERR_PIN_INVALID => PduError::MuxRscNotSupported,
+        _ => PduError::FctFailed
+    }
+}
+
+#[repr(C)]
+/// `PASSTHRU_MSG`
+struct PassThruMsg {
+    protocol_id: c_ulong,
+    rx_status: c_ulong,
+    tx_flags: c_ulong,
+    timestamp: c_ulong,
+    data_size: c_ulong,
+    extra_data_index: c_ulong,
+    data: [u8; MAX_MSG_LEN]
+}
+
+impl PassThruMsg {
+    fn new(protocol_id: c_ulong, tx_flags: c_ulong, data: &[u8]) -> Box<Self> {
+        let mut msg = Box::new(Self {
+            protocol_id,
+            rx_status: 0,
+            tx_flags,
+            timestamp: 0,
+            data_size: data.len() as c_ulong,
+            extra_data_index: data.len() as c_ulong,
+            data: [0; MAX_MSG_LEN]
+        });
+        msg.data[..data.len()].copy_from_slice(data);
+        msg
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data[..(self.data_size as usize).min(MAX_MSG_LEN)]
+    }
+}
+
+#[repr(C)]
+/// `SCONFIG`
+struct SConfig {
+    parameter: c_ulong,
+    value: c_ulong
+}
+
+#[repr(C)]
+/// `SCONFIG_LIST`
+struct SConfigList {
+    num_of_params: c_ulong,
+    config_ptr: *mut SConfig
+}
+
+type PassThruOpenFn = unsafe extern "system" fn(*const c_void, *mut c_ulong) -> c_long;
+type PassThruCloseFn = unsafe extern "system" fn(c_ulong) -> c_long;
+type PassThruConnectFn = unsafe extern "system" fn(c_ulong, c_ulong, c_ulong, c_ulong, *mut c_ulong) -> c_long;
+type PassThruDisconnectFn = unsafe extern "system" fn(c_ulong) -> c_long;
+type PassThruReadMsgsFn = unsafe extern "system" fn(c_ulong, *mut PassThruMsg, *mut c_ulong, c_ulong) -> c_long;
+type PassThruWriteMsgsFn = unsafe extern "system" fn(c_ulong, *const PassThruMsg, *mut c_ulong, c_ulong) -> c_long;
+type PassThruStartMsgFilterFn = unsafe extern "system" fn(
+    c_ulong,
+    c_ulong,
+    *const PassThruMsg,
+    *const PassThruMsg,
+    *const PassThruMsg,
+    *mut c_ulong
+) -> c_long;
+type PassThruStopMsgFilterFn = unsafe extern "system" fn(c_ulong, c_ulong) -> c_long;
+type PassThruSetProgrammingVoltageFn = unsafe extern "system" fn(c_ulong, c_ulong, c_ulong) -> c_long;
+type PassThruReadVersionFn = unsafe extern "system" fn(c_ulong, *mut c_char, *mut c_char, *mut c_char) -> c_long;
+type PassThruGetLastErrorFn = unsafe extern "system" fn(*mut c_char) -> c_long;
+type PassThruIoctlFn = unsafe extern "system" fn(c_ulong, c_ulong, *const c_void, *mut c_void) -> c_long;
+
+#[derive(Debug)]
+/// Functions of a loaded J2534 library
+struct PassThruApi {
+    open: PassThruOpenFn,
+    close: PassThruCloseFn,
+    connect: PassThruConnectFn,
+    disconnect: PassThruDisconnectFn,
+    read_msgs: PassThruReadMsgsFn,
+    write_msgs: PassThruWriteMsgsFn,
+    start_msg_filter: PassThruStartMsgFilterFn,
+    stop_msg_filter: PassThruStopMsgFilterFn,
+    set_programming_voltage: PassThruSetProgrammingVoltageFn,
+    read_version: PassThruReadVersionFn,
+    get_last_error: PassThruGetLastErrorFn,
+    ioctl: PassThruIoctlFn,
+    /// Keeps the functions loaded
+    _library: Library
+}
+
+/// Length of the strings of `PassThruReadVersion` and `PassThruGetLastError`
+const STRING_LEN: usize = 80;
+
+/// Converts a nul terminated string buffer of the library
+fn buffer_string(buf: &[c_char]) -> String {
+    let bytes: Vec<u8> = buf.iter().take_while(|c| **c != 0).map(|c| *c as u8).collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+impl PassThruApi {
+    /// Loads a library and its functions
+    ///
+    /// # Safety
+    /// The library must be a J2534 library, as its initialization routines are run
+    unsafe fn load(path: &Path) -> Result<Self, PduError> {
+        let library = Library::new(path).map_err(|_| PduError::FctFailed)?;
+        macro_rules! function {
+            ($name:literal) => {
+                *library.get(concat!($name, "\0").as_bytes()).map_err(|_| PduError::FctFailed)?
+            };
+        }
+        Ok(Self {
+            open: function!("PassThruOpen"),
+            close: function!("PassThruClose"),
+            connect: function!("PassThruConnect"),
+            disconnect: function!("PassThruDisconnect"),
+            read_msgs: function!("PassThruReadMsgs"),
+            write_msgs: function!("PassThruWriteMsgs"),
+            start_msg_filter: function!("PassThruStartMsgFilter"),
+            stop_msg_filter: function!("PassThruStopMsgFilter"),
+            set_programming_voltage: function!("PassThruSetProgrammingVoltage"),
+            read_version: function!("PassThruReadVersion"),
+            get_last_error: function!("PassThruGetLastError"),
+            ioctl: function!("PassThruIoctl"),
+            _library: library
+        })
+    }
+
+    fn check(code: c_long) -> Result<(), PduError> {
+        match code {
+            STATUS_NOERROR => Ok(()),
+            code => Err(pdu_error(code))
+        }
+    }
+
+    fn open(&self, device: Option<&CStr>) -> Result<c_ulong, PduError> {
+        let mut device_id = 0;
+        let name = device.map_or(ptr::null(), |d| d.as_ptr().cast());
+        // Safety: The name is null or nul terminated, and the ID is written to a valid pointer
+        Self::check(unsafe { (self.open)(name, &mut device_id) })?;
+        Ok(device_id)
+    }
+
+    fn close(&self, device_id: c_ulong) -> Result<(), PduError> {
+        // Safety: Only IDs are passed
+        Self::check(unsafe { (self.close)(device_id) })
+    }
+
+    fn connect(&self, device_id: c_ulong, protocol_id: c_ulong, flags: c_ulong, baudrate: u32) -> Result<c_ulong, PduError> {
+        let mut channel_id = 0;
+        // Safety: The ID is written to a valid pointer
+        Self::check(unsafe { (self.connect)(device_id, protocol_id, flags, baudrate as c_ulong, &mut channel_id) })?;
+        Ok(channel_id)
+    }
+
+    fn disconnect(&self, channel_id: c_ulong) -> Result<(), PduError> {
+        // Safety: Only IDs are passed
+        Self::check(unsafe { (self.disconnect)(channel_id) })
+    }
+
+    /// Reads a message, waiting up to `timeout` for it. [ERR_BUFFER_OVERFLOW] is returned as
+    /// the error code alongside the message
+    fn read_msg(&self, channel_id: c_ulong, timeout: Duration) -> Result<(Option<Box<PassThruMsg>>, c_long), PduError> {
+        let mut msg = PassThruMsg::new(0, 0, &[]);
+        let mut count = 1;
+        let timeout = timeout.as_millis().min(c_ulong::MAX as u128) as c_ulong;
+        // Safety: One message is read into a message buffer
+        let code = unsafe { (self.read_msgs)(channel_id, &mut *msg, &mut count, timeout) };
+        match code {
+            STATUS_NOERROR | ERR_BUFFER_EMPTY | ERR_TIMEOUT | ERR_BUFFER_OVERFLOW => {
+                Ok(((count == 1).then_some(msg), code))
+            },
+            code => Err(pdu_error(code))
+        }
+    }
+
+    fn write_msg(&self, channel_id: c_ulong, msg: &PassThruMsg) -> Result<(), PduError> {
+        let mut count = 1;
+        let timeout = WRITE_TIMEOUT.as_millis() as c_ulong;
+        // Safety: One message is written from a valid message
+        Self::check(unsafe { (self.write_msgs)(channel_id, msg, &mut count, timeout) })
+    }
+
+    fn start_msg_filter(
+        &self,
+        channel_id: c_ulong,
+        filter_type: c_ulong,
+        mask: &PassThruMsg,
+        pattern: &PassThruMsg,
+        flow_control: Option<&PassThruMsg>
+    ) -> Result<c_ulong, PduError> {
+        let mut filter_id = 0;
+        let flow_control = flow_control.map_or(ptr::null(), ptr::from_ref);
+        // Safety: The messages are valid or null, and the ID is written to a valid pointer
+        Self::check(unsafe { (self.start_msg_filter)(channel_id, filter_type, mask, pattern, flow_control, &mut filter_id) })?;
+        Ok(filter_id)
+    }
+
+    fn stop_msg_filter(&self, channel_id: c_ulong, filter_id: c_ulong) -> Result<(), PduError> {
+        // Safety: Only IDs are passed
+        Self::check(unsafe { (self.stop_msg_filter)(channel_id, filter_id) })
+    }
+
+    fn set_programming_voltage(&self, device_id: c_ulong, pin: u32, voltage: u32) -> Result<(), PduError> {
+        // Safety: Only values are passed
+        Self::check(unsafe { (self.set_programming_voltage)(device_id, pin as c_ulong, voltage as c_ulong) })
+    }
+
+    fn read_version(&self, device_id: c_ulong) -> Result<J2534Version, PduError> {
+        let mut firmware = [0; STRING_LEN];
+        let mut dll = [0; STRING_LEN];
+        let mut api = [0; STRING_LEN];
+        // Safety: The buffers have the length required by J2534
+        Self::check(unsafe { (self.read_version)(device_id, firmware.as_mut_ptr(), dll.as_mut_ptr(), api.as_mut_ptr()) })?;
+        Ok(J2534Version { firmware: buffer_string(&firmware), dll: buffer_string(&dll), api: buffer_string(&api) })
+    }
+
+    fn last_error(&self) -> Option<String> {
+        let mut description = [0; STRING_LEN];
+        // Safety: The buffer has the length required by J2534
+        let code = unsafe { (self.get_last_error)(description.as_mut_ptr()) };
+        Some(buffer_string(&description)).filter(|s| code == STATUS_NOERROR && !s.is_empty())
+    }
+
+    /// Performs an IOCTL which has an `unsigned long` output
+    fn ioctl_read(&self, id: c_ulong, ioctl_id: c_ulong) -> Result<u32, PduError> {
+        let mut value: c_ulong = 0;
+        // Safety: The output is a valid unsigned long
+        Self::check(unsafe { (self.ioctl)(id, ioctl_id, ptr::null(), ptr::from_mut(&mut value).cast()) })?;
+        Ok(value as u32)
+    }
+
+    /// Performs an IOCTL which has no input or output
+    fn ioctl_clear(&self, channel_id: c_ulong, ioctl_id: c_ulong) -> Result<(), PduError> {
+        // Safety: The IOCTL reads and writes nothing
+        Self::check(unsafe { (self.ioctl)(channel_id, ioctl_id, ptr::null(), ptr::null_mut()) })
+    }
+
+    fn set_config(&self, channel_id: c_ulong, config: &mut [SConfig]) -> Result<(), PduError> {
+        let list = SConfigList { num_of_params: config.len() as c_ulong, config_ptr: config.as_mut_ptr() };
+        // Safety: The list points to `config`, which outlives the call
+        Self::check(unsafe { (self.ioctl)(channel_id, SET_CONFIG, ptr::from_ref(&list).cast(), ptr::null_mut()) })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Versions reported by `PassThruReadVersion`
+pub struct J2534Version {
+    /// Firmware version of the device
+    pub firmware: String,
+    /// Version of the library
+    pub dll: String,
+    /// J2534 API version, such as `04.04`
+    pub api: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// J2534 library of a [J2534] module
+pub struct J2534Library {
+    /// Path of the library
+    pub path: PathBuf,
+    /// Name of the module
+    pub name: String,
+    /// Device name passed to `PassThruOpen`
+    pub device: Option<String>
+}
+
+impl J2534Library {
+    /// Parses a library of the option string: a path followed by `;` separated options
+    pub fn parse(s: &str) -> Result<Self, PduError> {
+        let mut parts = s.split(';').map(str::trim);
+        let path = parts.next().filter(|p| !p.is_empty()).ok_or(PduError::InvalidParameters)?;
+        let mut library = Self::new(path.into());
+        for option in parts {
+            match option.split_once('=') {
+                Some(("name", name)) => library.name = name.to_string(),
+                Some(("device", device)) => library.device = Some(device.to_string()),
+                _ => return Err(PduError::InvalidParameters)
+            }
+        }
+        Ok(library)
+    }
+
+    fn new(path: PathBuf) -> Self {
+        let name = path.file_stem().map_or_else(|| path.display().to_string(), |s| s.to_string_lossy().to_string());
+        Self { path, name, device: None }
+    }
+}
+
+/// Returns the value of a string member of a flat JSON object
+fn json_string(json: &str, key: &str) -> Option<String> {
+    let rest = &json[json.find(&format!("\"{key}\""))? + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].replace("\\\\", "\\"))
+}
+
+/// Returns the libraries registered in `~/.passthru`
+fn registered_libraries() -> Vec<J2534Library> {
+    let Some(dir) = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".passthru")) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|dir| dir.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().is_some_and(|e| e == "json")).collect())
+        .unwrap_or_default();
+    files.sort();
+    files
+        .iter()
+        .filter_map(|file| {
+            let json = fs::read_to_string(file).ok()?;
+            let mut library = J2534Library::new(json_string(&json, "FUNCTION_LIB")?.into());
+            if let Some(name) = json_string(&json, "NAME") {
+                library.name = name;
+            }
+            Some(library)
+        })
+        .collect()
+}
+
+/// Returns true for 29 bit CAN IDs. Bit 31 marks a 29 bit ID, which is implied above 0x7FF
+fn is_29bit(id: u32) -> bool {
+    id & 0x8000_0000 != 0 || id > 0x7FF
+}
+
+/// Returns the J2534 message data of a CAN ID and address extension byte, and its flags
+fn id_bytes(id: u32, ext_addr: Option<u8>) -> (Vec<u8>, c_ulong) {
+    let mut data = (id & 0x1FFF_FFFF).to_be_bytes().to_vec();
+    data.extend(ext_addr);
+    let mut flags = if is_29bit(id) { CAN_29BIT_ID } else { 0 };
+    if ext_addr.is_some() {
+        flags |= ISO15765_ADDR_TYPE;
+    }
+    (data, flags)
+}
+
+/// Converts a message filter of a raw CAN link to a J2534 filter type, mask and pattern. J2534
+/// messages carry bit 31 of the CAN ID as a flag, so pass filters ignore it. Block filters
+/// which compare it, or more bytes than a J2534 filter, are left to the backend
+fn can_filter(filter: &MessageFilter) -> Option<(c_ulong, Box<PassThruMsg>, Box<PassThruMsg>)> {
+    let (mut mask, mut pattern) = (filter.mask.clone(), filter.pattern.clone());
+    if filter.is_pass() {
+        mask.truncate(MAX_FILTER_LEN);
+        pattern.truncate(MAX_FILTER_LEN);
+    } else if mask.len() > MAX_FILTER_LEN || mask.first().is_some_and(|m| m & 0x80 != 0) {
+        return None;
+    }
+    if let (Some(m), Some(p)) = (mask.first_mut(), pattern.first_mut()) {
+        *m &= 0x7F;
+        *p &= 0x7F;
+    }
+    // J2534 filters compare at least one byte
+    if mask.is_empty() {
+        (mask, pattern) = (vec![0], vec![0]);
+    }
+    let filter_type = if filter.is_pass() { PASS_FILTER } else { BLOCK_FILTER };
+    Some((filter_type, PassThruMsg::new(CAN, 0, &mask), PassThruMsg::new(CAN, 0, &pattern)))
+}
+
+/// Starts the J2534 filters of the message filters of a raw CAN link, with a pass filter for
+/// every frame if the link has no pass filter. Returns the IDs of the started filters, which
+/// are stopped again if one fails to start
+fn start_can_filters(api: &PassThruApi, channel_id: c_ulong, filters: &MessageFilters) -> Result<Vec<c_ulong>, PduError> {
+    let mut filters: Vec<_> = filters.iter().filter_map(can_filter).collect();
+    if !filters.iter().any(|(filter_type, ..)| *filter_type == PASS_FILTER) {
+        filters.push((PASS_FILTER, PassThruMsg::new(CAN, 0, &[0; 4]), PassThruMsg::new(CAN, 0, &[0; 4])));
+    }
+    let mut ids = Vec::new();
+    for (filter_type, mask, pattern) in &filters {
+        match api.start_msg_filter(channel_id, *filter_type, mask, pattern, None) {
+            Ok(id) => ids.push(id),
+            Err(e) => {
+                for id in ids {
+                    let _ = api.stop_msg_filter(channel_id, id);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+#[derive(Debug, Default)]
+/// Converts the microsecond timestamps of the device to the [Clock] of the backend
+///
+/// The offset between the clocks is the smallest one seen between the timestamp of a message
+/// and the time it was read, so that messages are never timestamped after they were read.
+struct TimestampSync {
+    offset: Option<u32>
+}
+
+impl TimestampSync {
+    /// Returns the [Clock] timestamp of a message with the device timestamp `device`, which was
+    /// read at `read`
+    fn timestamp(&mut self, device: c_ulong, read: u32) -> u32 {
+        let device = device as u32;
+        let offset = read.wrapping_sub(device);
+        // Both clocks wrap around, so the offsets are compared by their difference
+        let offset = match self.offset {
+            Some(last) if (offset.wrapping_sub(last) as i32) > 0 => last,
+            _ => offset
+        };
+        self.offset = Some(offset);
+        device.wrapping_add(offset)
+    }
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink on a J2534 device
+pub struct J2534Channel {
+    api: Arc<PassThruApi>,
+    device_id: c_ulong,
+    /// ID of the J2534 channel, [None] once the channel could neither be reconnected nor restored
+    channel_id: Option<c_ulong>,
+    clock: Clock,
+    timestamps: TimestampSync,
+    baudrate: u32,
+    /// ISO-TP addresses and timing, [None] for raw CAN
+    isotp: Option<IsoTpConfig>,
+    /// Message filters of a raw CAN link
+    filters: MessageFilters,
+    /// IDs of the J2534 filters started for [J2534Channel::filters]
+    filter_ids: Vec<c_ulong>,
+    errors: VecDeque<PduErrorEvt>
+}
+
+impl J2534Channel {
+    fn open(api: Arc<PassThruApi>, device_id: c_ulong, protocol: Protocol, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let isotp = (protocol != Protocol::Iso11898Raw).then(|| IsoTpConfig::from_params(params));
+        let baudrate = params.get_u32(StdComParam::Baudrate).unwrap_or(500_000);
+        let filters = MessageFilters::new();
+        let (channel_id, filter_ids) = Self::connect(&api, device_id, baudrate, isotp.as_ref(), &filters)?;
+        Ok(Self {
+            api,
+            device_id,
+            channel_id: Some(channel_id),
+            clock,
+            timestamps: TimestampSync::default(),
+            baudrate,
+            isotp,
+            filters,
+            filter_ids,
+            errors: VecDeque::new()
+        })
+    }
+
+    /// Returns the ID of the J2534 channel. Fails with [PduError::CommPcToVciFailed] if it was lost
+    fn channel_id(&self) -> Result<c_ulong, PduError> {
+        self.channel_id.ok_or(PduError::CommPcToVciFailed)
+    }
+
+    /// Connects a J2534 channel, and starts its filters and configuration. Returns the channel
+    /// ID and the IDs of the filters of a raw CAN channel
+    fn connect(
+        api: &PassThruApi,
+        device_id: c_ulong,
+        baudrate: u32,
+        isotp: Option<&IsoTpConfig>,
+        filters: &MessageFilters
+    ) -> Result<(c_ulong, Vec<c_ulong>), PduError> {
+        let Some(config) = isotp else {
+            let channel_id = api.connect(device_id, CAN, CAN_ID_BOTH, baudrate)?;
+            return match start_can_filters(api, channel_id, filters) {
+                Ok(filter_ids) => Ok((channel_id, filter_ids)),
+                Err(e) => {
+                    let _ = api.disconnect(channel_id);
+                    Err(e)
+                }
+            };
+        };
+        if config.tx_dl > CAN_MAX_DLEN {
+            return Err(PduError::ValueNotSupported);
+        }
+        let (pattern, flags) = id_bytes(config.rx_id, config.rx_ext_addr);
+        let (flow_control, _) = id_bytes(config.tx_id, config.tx_ext_addr);
+        let channel_id = api.connect(device_id, ISO15765, flags & CAN_29BIT_ID, baudrate)?;
+        let mask = PassThruMsg::new(ISO15765, flags, &vec![0xFF; pattern.len()]);
+        let pattern = PassThruMsg::new(ISO15765, flags, &pattern);
+        let flow_control = PassThruMsg::new(ISO15765, flags, &flow_control);
+        let mut settings = [
+            SConfig { parameter: ISO15765_BS, value: config.block_size as c_ulong },
+            SConfig { parameter: ISO15765_STMIN, value: encode_st_min(config.st_min) as c_ulong },
+            SConfig { parameter: BS_TX, value: config.block_size_override.map_or(USE_ECU_VALUE, |b| b as c_ulong) },
+            SConfig { parameter: STMIN_TX, value: config.st_min_override.map_or(USE_ECU_VALUE, |t| encode_st_min(t) as c_ulong) }
+        ];
+        let setup = api
+            .start_msg_filter(channel_id, FLOW_CONTROL_FILTER, &mask, &pattern, Some(&flow_control))
+            .and_then(|_| api.set_config(channel_id, &mut settings));
+        match setup {
+            Ok(()) => Ok((channel_id, Vec::new())),
+            Err(e) => {
+                let _ = api.disconnect(channel_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Converts a received message with its [Clock] timestamp to a result. Transmit indications
+    /// are skipped
+    fn result(&self, msg: &PassThruMsg, timestamp: u32) -> Option<ResultEvent> {
+        if msg.rx_status & (TX_MSG_TYPE | START_OF_MESSAGE | TX_INDICATION) != 0 || msg.data().len() < 4 {
+            return None;
+        }
+        let mut id = u32::from_be_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
+        if msg.rx_status & CAN_29BIT_ID != 0 {
+            id |= 0x8000_0000;
+        }
+        let mut data = msg.data().to_vec();
+        data[..4].copy_from_slice(&id.to_be_bytes());
+        let Some(config) = &self.isotp else {
+            return Some(ResultEvent {
+                rx_flag: CanFlags::default().to_flag_bytes(),
+                start_msg_timestamp: timestamp,
+                data,
+                ..Default::default()
+            });
+        };
+        let header_len = if msg.rx_status & ISO15765_ADDR_TYPE != 0 || config.rx_ext_addr.is_some() { 5 } else { 4 };
+        if data.len() < header_len {
+            return None;
+        }
+        let payload = data.split_off(header_len);
+        Some(ResultEvent {
+            start_msg_timestamp: timestamp,
+            extra_info: Some(ExtraInfoData { header: data, footer: Vec::new() }),
+            data: payload,
+            ..Default::default()
+        })
+    }
+}
+
+impl Drop for J2534Channel {
+    fn drop(&mut self) {
+        if let Some(channel_id) = self.channel_id {
+            let _ = self.api.disconnect(channel_id);
+        }
+    }
+}
+
+impl Channel for J2534Channel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let baudrate = params.get_u32(StdComParam::Baudrate).unwrap_or(self.baudrate);
+        let isotp = self.isotp.map(|_| IsoTpConfig::from_params(params));
+        if baudrate == self.baudrate && isotp == self.isotp {
+            return Ok(());
+        }
+        self.api.disconnect(self.channel_id()?)?;
+        // The disconnected channel is forgotten, so that it is never disconnected again
+        self.channel_id = None;
+        self.filter_ids.clear();
+        let (channel_id, filter_ids) = match Self::connect(&self.api, self.device_id, baudrate, isotp.as_ref(), &self.filters) {
+            Ok(ids) => ids,
+            // Restore the previous channel, so that the link keeps working
+            Err(e) => {
+                let (channel_id, filter_ids) =
+                    Self::connect(&self.api, self.device_id, self.baudrate, self.isotp.as_ref(), &self.filters)?;
+                (self.channel_id, self.filter_ids) = (Some(channel_id), filter_ids);
+                return Err(e);
+            }
+        };
+        (self.channel_id, self.filter_ids) = (Some(channel_id), filter_ids);
+        self.baudrate = baudrate;
+        self.isotp = isotp;
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8], _tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        let msg = match &self.isotp {
+            None => {
+                if data.len() < 4 || data.len() - 4 > CAN_MAX_DLEN {
+                    return Err(PduError::InvalidParameters);
+                }
+                let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let (mut bytes, flags) = id_bytes(id, None);
+                bytes.extend(&data[4..]);
+                PassThruMsg::new(CAN, flags, &bytes)
+            },
+            Some(config) => {
+                let (id, ext_addr) = match params.get_u32(StdComParam::RequestAddrMode) {
+                    Some(FUNCTIONAL_ADDR_MODE) => (config.func_id, config.func_ext_addr),
+                    _ => (config.tx_id, config.tx_ext_addr)
+                };
+                let (mut bytes, mut flags) = id_bytes(id, ext_addr);
+                if data.is_empty() || bytes.len() + data.len() > MAX_MSG_LEN {
+                    return Err(PduError::InvalidParameters);
+                }
+                bytes.extend(data);
+                if config.padding.is_some() {
+                    flags |= ISO15765_FRAME_PAD;
+                }
+                PassThruMsg::new(ISO15765, flags, &bytes)
+            }
+        };
+        self.api.write_msg(self.channel_id()?, &msg)
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        let (msg, code) = self.api.read_msg(self.channel_id()?, timeout)?;
+        if code == ERR_BUFFER_OVERFLOW {
+            self.errors.push_back(PduErrorEvt::RxError);
+        }
+        let Some(msg) = msg else {
+            return Ok(None);
+        };
+        // Transmit indications are skipped, but their timestamps still synchronise the clocks
+        let timestamp = self.timestamps.timestamp(msg.timestamp, self.clock.now());
+        Ok(self.result(&msg, timestamp))
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        self.errors.pop_front()
+    }
+
+    fn apply_filters(&mut self, filters: &MessageFilters) -> Result<(), PduError> {
+        // ISO15765 channels only accept flow control filters
+        if self.isotp.is_some() {
+            return Ok(());
+        }
+        let channel_id = self.channel_id()?;
+        if filters.is_empty() {
+            self.api.ioctl_clear(channel_id, CLEAR_MSG_FILTERS)?;
+            self.filter_ids = start_can_filters(&self.api, channel_id, filters)?;
+            self.filters.clear();
+            return Ok(());
+        }
+        // The new filters are started before the previous ones are stopped, so that frames
+        // passed by both are never lost
+        let filter_ids = start_can_filters(&self.api, channel_id, filters)?;
+        for id in std::mem::replace(&mut self.filter_ids, filter_ids) {
+            let _ = self.api.stop_msg_filter(channel_id, id);
+        }
+        self.filters = filters.clone();
+        Ok(())
+    }
+
+    fn ioctl(&mut self, command: IoctlCommand, _input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        match command {
+            IoctlCommand::ReadVbatt => self.api.ioctl_read(self.device_id, READ_VBATT).map(|v| Some(IoctlData::Unum32(v))),
+            IoctlCommand::ClearTxQueue => self.api.ioctl_clear(self.channel_id()?, CLEAR_TX_BUFFER).map(|_| None),
+            IoctlCommand::ClearRxQueue => self.api.ioctl_clear(self.channel_id()?, CLEAR_RX_BUFFER).map(|_| None),
+            _ => Err(PduError::IdNotSupported)
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Open device of a connected module
+struct Device {
+    id: c_ulong,
+    version: J2534Version
+}
+
+#[derive(Debug)]
+/// [Driver] which bridges J2534 PassThru libraries
+///
+/// The libraries are loaded when the API is constructed
+pub struct J2534 {
+    libraries: Vec<(J2534Library, Arc<PassThruApi>)>,
+    devices: Mutex<Vec<Option<Device>>>
+}
+
+impl J2534 {
+    /// Returns the libraries, in module order
+    pub fn libraries(&self) -> impl Iterator<Item = &J2534Library> {
+        self.libraries.iter().map(|(library, _)| library)
+    }
+
+    /// Returns the versions of the device of a connected module
+    pub fn version(&self, module: usize) -> Option<J2534Version> {
+        self.devices().get(module)?.as_ref().map(|d| d.version.clone())
+    }
+
+    /// Returns the description of the last error of a module's library (`PassThruGetLastError`)
+    pub fn last_error(&self, module: usize) -> Option<String> {
+        self.libraries.get(module)?.1.last_error()
+    }
+
+    fn devices(&self) -> MutexGuard<'_, Vec<Option<Device>>> {
+        self.devices.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn api(&self, module: usize) -> Result<&Arc<PassThruApi>, PduError> {
+        self.libraries.get(module).map(|(_, api)| api).ok_or(PduError::InvalidHandle)
+    }
+
+    fn device_id(&self, module: usize) -> Result<c_ulong, PduError> {
+        self.devices().get(module).and_then(|d| d.as_ref().map(|d| d.id)).ok_or(PduError::ModuleNotConnected)
+    }
+}
+
+impl Driver for J2534 {
+    type Channel = J2534Channel;
+
+    fn open(options: &str) -> Result<Self, PduError> {
+        let libraries = options
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(J2534Library::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let libraries = if libraries.is_empty() {
+            // Registered libraries which fail to load are left out
+            registered_libraries()
+                .into_iter()
+                // Safety: The library is registered as a J2534 library
+                .filter_map(|library| unsafe { PassThruApi::load(&library.path) }.ok().map(|api| (library, Arc::new(api))))
+                .collect()
+        } else {
+            libraries
+                .into_iter()
+                // Safety: The application passes J2534 libraries
+                .map(|library| unsafe { PassThruApi::load(&library.path) }.map(|api| (library, Arc::new(api))))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(Self { devices: Mutex::new((0..libraries.len()).map(|_| None).collect()), libraries })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        self.libraries
+            .iter()
+            .map(|(library, _)| DriverModule {
+                module_type_id: MODULE_TYPE_ID,
+                name: library.name.clone(),
+                info: format!("J2534 device of {}", library.path.display()),
+                resources: vec![DriverResource {
+                    bus_type: BusType::Iso11898_2Dwcan,
+                    protocols: PROTOCOLS.to_vec(),
+                    pins: BusType::Iso11898_2Dwcan.default_obd_pins()
+                }]
+            })
+            .collect()
+    }
+
+    fn connect_module(&self, module: usize) -> Result<(), PduError> {
+        let api = self.api(module)?;
+        let name = self.libraries[module].0.device.as_deref().map(CString::new).transpose().map_err(|_| PduError::InvalidParameters)?;
+        let id = api.open(name.as_deref())?;
+        let version = match api.read_version(id) {
+            Ok(version) => version,
+            Err(e) => {
+                let _ = api.close(id);
+                return Err(e);
+            }
+        };
+        if let Some(Device { id, .. }) = self.devices()[module].replace(Device { id, version }) {
+            let _ = api.close(id);
+        }
+        Ok(())
+    }
+
+    fn disconnect_module(&self, module: usize) -> Result<(), PduError> {
+        let device = self.devices().get_mut(module).and_then(Option::take);
+        match device {
+            Some(device) => self.api(module)?.close(device.id),
+            None => Ok(())
+        }
+    }
+
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        let mut params = vec![(StdComParam::Baudrate, 500_000)];
+        if protocol != Protocol::Iso11898Raw {
+            params.extend([
+                (StdComParam::P2Max, DEFAULT_RESPONSE_TIMEOUT.as_micros() as u32),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::CanPhysReqId, 0x7E0),
+                (StdComParam::CanFuncReqId, 0x7DF),
+                (StdComParam::CanRespUsdtId, 0x7E8),
+                (StdComParam::CanPhysReqFormat, 0x05),
+                (StdComParam::CanFuncReqFormat, 0x05),
+                (StdComParam::CanRespUsdtFormat, 0x05),
+                (StdComParam::CanPhysReqExtAddr, 0),
+                (StdComParam::CanFuncReqExtAddr, 0),
+                (StdComParam::CanRespUsdtExtAddr, 0),
+                (StdComParam::CanFillerByteHandling, 1),
+                (StdComParam::BlockSize, 0),
+                (StdComParam::StMin, 0),
+                (StdComParam::BlockSizeOverride, OVERRIDE_DISABLED),
+                (StdComParam::StMinOverride, OVERRIDE_DISABLED)
+            ]);
+        }
+        params.into_iter().filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v))).collect()
+    }
+
+    fn open_channel(
+        &self,
+        module: usize,
+        _resource: &DriverResource,
+        protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        J2534Channel::open(self.api(module)?.clone(), self.device_id(module)?, protocol, params, clock)
+    }
+
+    fn module_ioctl(&self, module: usize, command: IoctlCommand, input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        let api = self.api(module)?;
+        let device_id = self.device_id(module)?;
+        match (command, input) {
+            (IoctlCommand::ReadVbatt, _) => api.ioctl_read(device_id, READ_VBATT).map(|v| Some(IoctlData::Unum32(v))),
+            (IoctlCommand::ReadProgVoltage, _) => api.ioctl_read(device_id, READ_PROG_VOLTAGE).map(|v| Some(IoctlData::Unum32(v))),
+            (IoctlCommand::SetProgVoltage, IoctlInput::ProgVoltage(v)) => {
+                api.set_programming_voltage(device_id, v.pin_on_dlc, v.prog_voltage_mv).map(|_| None)
+            },
+            (IoctlCommand::SetProgVoltage, _) => Err(PduError::InvalidParameters),
+            _ => Err(PduError::IdNotSupported)
+        }
+    }
+}
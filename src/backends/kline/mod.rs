@@ -0,0 +1,772 @@
+//! K-Line backend (Linux), for ISO 14230 (KWP2000) and ISO 9141-2 over a serial port
+//!
+//! Every serial port is a module with an `ISO_14230_1_UART` resource, which runs
+//! `ISO_14230_3_on_ISO_14230_2` and `ISO_OBD_on_ISO_14230_4`, and an `ISO_9141_2_UART`
+//! resource, which runs `ISO_OBD_on_ISO_9141_2`. The ports are taken from the option string of
+//! `PDUConstruct`, as comma separated paths each followed by `;` separated options:
+//! * `5baud=break` (default) or `5baud=uart` - Sends the 5 baud address byte by toggling the
+//!   break condition of the port, or by switching the UART to 5 baud
+//! * `echo=on` (default) or `echo=off` - Whether the interface receives the bytes it sends, like
+//!   common K-Line cables do. Echoed bytes are checked and discarded
+//!
+//! Without options, every `/dev/ttyUSB*` and `/dev/ttyACM*` port is used.
+//!
+//! A `PDU_COPT_STARTCOMM` ComPrimitive is required before sending requests. It performs the
+//! initialization of `CP_InitializationSettings`:
+//! * 5 baud init - Sends `CP_5BaudAddressPhys`, or `CP_5BaudAddressFunc` if `CP_RequestAddrMode`
+//!   is 2, and exchanges the key bytes within `CP_W1Max` to `CP_W4Max`. Key bytes of another
+//!   protocol end the initialization unconfirmed. The result contains the two key bytes
+//! * Fast init - Sends the `CP_TInil` / `CP_TWup` wake up pattern after `CP_TIdle`, followed by
+//!   the data of the ComPrimitive (StartCommunication if empty) as a request. The result contains
+//!   the StartCommunication response
+//!
+//! KWP2000 requests get the header of `CP_HeaderFormatKW` (0 = format byte, 1 = format and
+//! length byte, 2 = format, target and source byte, 3 = format, target, source and length byte,
+//! 4 and 5 = like 0 and 2, with a length byte only for more than 63 data bytes). The address
+//! mode bits of the format byte come from `CP_PhysReqFormatPriorityType` or
+//! `CP_FuncReqFormatPriorityType`. ISO 9141-2 requests get the format byte, the target address
+//! and `CP_TesterSourceAddress` as header. Requests are sent `CP_P3Min` after the last bus
+//! activity, with `CP_P4Min` between their bytes. `CP_AccessTimingOverride` replaces `CP_P2Min`,
+//! `CP_P2Max`, `CP_P3Min` and `CP_P4Min` with the entry of the `OverrideTester` timing set, or
+//! else the `Normal` or `Extended` set given by the key bytes, or else the `Default` set. P2Max
+//! of the entry is also the response timeout of the ComPrimitives of the link.
+//!
+//! Responses end after `CP_P1Max` without a byte (ISO 9141-2) or after the length of their
+//! header (ISO 14230). Results contain the service data, with the header bytes as the extra
+//! info header and the checksum as the footer. KWP2000 responses with addresses are only
+//! received if their target is `CP_TesterSourceAddress`. A StopCommunication request is sent
+//! by `PDU_COPT_STOPCOMM` on ISO 14230.
+//!
+//! `PDU_IOCTL_SEND_BREAK` holds the break condition for the [IoctlInput::Unum32] time in
+//! microseconds, or `CP_TInil` without input.
+//!
+//! The [sim] module has a simulated K-Line ECU on a pseudo terminal, for testing without a vehicle.
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::kline::KLine>);
+//! ```
+
+pub mod sim;
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant}
+};
+
+use super::serial::SerialPort;
+use crate::{
+    provider::{
+        Channel, Clock, Driver, DriverModule, DriverResource, ExtraInfoData, IoctlData, IoctlInput, LinkParams,
+        ResponseTiming, ResultEvent
+    },
+    BusType, ComParamValue, IoctlCommand, PduError, PduErrorEvt, Protocol, StdComParam, TimingSet
+};
+
+/// Module type ID of K-Line serial ports
+pub const MODULE_TYPE_ID: u32 = 14230;
+
+/// StartCommunication service ID
+pub const START_COMMUNICATION: u8 = 0x81;
+/// StopCommunication service ID
+pub const STOP_COMMUNICATION: u8 = 0x82;
+/// Negative response service ID
+pub const NEGATIVE_RESPONSE: u8 = 0x7F;
+/// Synchronization pattern sent by the ECU during 5 baud init
+pub const SYNC_BYTE: u8 = 0x55;
+
+/// Address mode bits of a format byte without addresses
+pub const ADDR_NONE: u8 = 0x00;
+/// Address mode bits of a format byte with physical addresses
+pub const ADDR_PHYSICAL: u8 = 0x80;
+/// Address mode bits of a format byte with functional addresses
+pub const ADDR_FUNCTIONAL: u8 = 0xC0;
+/// Mask of the address mode bits of a format byte
+const ADDR_MASK: u8 = 0xC0;
+/// Largest data length which fits in the format byte
+pub const MAX_FORMAT_LEN: usize = 0x3F;
+
+/// Length of ISO 9141-2 headers
+const ISO9141_HEADER_LEN: usize = 3;
+
+/// `CP_RequestAddrMode` value of functional requests
+const FUNCTIONAL_ADDR_MODE: u32 = 2;
+
+/// Bit time of 5 baud init
+const FIVE_BAUD_BIT: Duration = Duration::from_millis(200);
+/// Minimum time between key byte 2 and its inversion (W4Min)
+const W4_MIN: Duration = Duration::from_millis(25);
+/// Time allowed for an echo on top of the transmission time of the byte
+const ECHO_TIMEOUT: Duration = Duration::from_millis(50);
+/// P2Max without a `CP_P2Max` ComParam
+const DEFAULT_P2_MAX: Duration = Duration::from_millis(50);
+/// P3Max of ISO 14230-2, which has no ComParam of its own
+const DEFAULT_P3_MAX: Duration = Duration::from_secs(5);
+/// Resolution of the P3Max value of [ParamStructAccessTiming](crate::ParamStructAccessTiming)
+const P3_MAX_RESOLUTION: Duration = Duration::from_millis(250);
+
+/// Protocols of the ISO 14230 resource
+const KWP_PROTOCOLS: &[Protocol] = &[Protocol::Iso14230_3OnIso14230_2, Protocol::IsoObdOnIso14230_4];
+
+/// Returns the checksum of a frame: the sum of its bytes, modulo 256
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Returns true if the key bytes of 5 baud init belong to the protocol: `08 08` or `94 94` for
+/// ISO 9141-2, and key byte 2 `8F` with an odd parity key byte 1 for ISO 14230
+fn valid_key_bytes(iso9141: bool, [kb1, kb2]: [u8; 2]) -> bool {
+    match iso9141 {
+        true => matches!([kb1, kb2], [0x08, 0x08] | [0x94, 0x94]),
+        false => kb2 == 0x8F && kb1.count_ones() % 2 == 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Header of a KWP2000 frame (ISO 14230-2)
+pub struct KwpHeader {
+    /// Address mode bits of the format byte ([ADDR_NONE], [ADDR_PHYSICAL] or [ADDR_FUNCTIONAL]).
+    /// Target and source bytes are only present if not [ADDR_NONE]
+    pub address_mode: u8,
+    /// Target address
+    pub target: u8,
+    /// Source address
+    pub source: u8,
+    /// The data length is sent in a length byte, rather than in the format byte
+    pub length_byte: bool
+}
+
+impl KwpHeader {
+    /// Returns true if the header contains the target and source address
+    pub fn has_addresses(&self) -> bool {
+        self.address_mode != ADDR_NONE
+    }
+
+    /// Returns the number of header bytes
+    pub fn encoded_len(&self) -> usize {
+        1 + if self.has_addresses() { 2 } else { 0 } + usize::from(self.length_byte)
+    }
+}
+
+/// Encodes a KWP2000 frame: header, data and checksum
+pub fn encode_frame(header: &KwpHeader, data: &[u8]) -> Result<Vec<u8>, PduError> {
+    if data.len() > u8::MAX as usize || (!header.length_byte && data.len() > MAX_FORMAT_LEN) {
+        return Err(PduError::InvalidParameters);
+    }
+    let format_len = if header.length_byte { 0 } else { data.len() as u8 };
+    let mut frame = vec![(header.address_mode & ADDR_MASK) | format_len];
+    if header.has_addresses() {
+        frame.extend([header.target, header.source]);
+    }
+    if header.length_byte {
+        frame.push(data.len() as u8);
+    }
+    frame.extend(data);
+    frame.push(checksum(&frame));
+    Ok(frame)
+}
+
+/// Returns the total length of the KWP2000 frame at the start of `buf`, once enough of its
+/// header has been received
+pub fn frame_len(buf: &[u8]) -> Option<usize> {
+    let format = *buf.first()?;
+    let header_len = if format & ADDR_MASK != ADDR_NONE { 3 } else { 1 };
+    let data_len = match format as usize & MAX_FORMAT_LEN {
+        0 => *buf.get(header_len)? as usize + 1,
+        len => len
+    };
+    Some(header_len + data_len + 1)
+}
+
+/// Decodes a complete KWP2000 frame, returning its header and data. Returns [None] if the
+/// length or checksum is wrong
+pub fn decode_frame(frame: &[u8]) -> Option<(KwpHeader, &[u8])> {
+    if frame_len(frame)? != frame.len() || checksum(&frame[..frame.len() - 1]) != frame[frame.len() - 1] {
+        return None;
+    }
+    let address_mode = frame[0] & ADDR_MASK;
+    let header = KwpHeader {
+        address_mode,
+        target: if address_mode != ADDR_NONE { frame[1] } else { 0 },
+        source: if address_mode != ADDR_NONE { frame[2] } else { 0 },
+        length_byte: frame[0] as usize & MAX_FORMAT_LEN == 0
+    };
+    Some((header, &frame[header.encoded_len()..frame.len() - 1]))
+}
+
+/// Converts an I/O error of a serial port
+fn pdu_error(_e: io::Error) -> PduError {
+    // The port only fails if the interface is gone
+    PduError::CommPcToVciFailed
+}
+
+fn sleep_until(deadline: Instant) {
+    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+}
+
+/// Converts a 0.5ms resolution timing value of [ParamStructAccessTiming](crate::ParamStructAccessTiming)
+fn half_ms(value: u8) -> Duration {
+    Duration::from_micros(value as u64 * 500)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Timing of a K-Line link ([KLineChannel::timing])
+pub struct KLineTiming {
+    /// Minimum time between the end of a request and the response (P2Min)
+    pub p2_min: Duration,
+    /// Time to wait for the response to a request (P2Max)
+    pub p2_max: Duration,
+    /// Minimum time between the end of a response and the next request (P3Min)
+    pub p3_min: Duration,
+    /// Time after which the ECU ends the communication if no request arrives (P3Max)
+    pub p3_max: Duration,
+    /// Time between the bytes of a request (P4Min)
+    pub p4_min: Duration
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the address byte of 5 baud init is sent
+pub enum FiveBaudMethod {
+    /// Toggling the break condition of the port for every bit
+    #[default]
+    Break,
+    /// Switching the UART to 5 baud
+    Uart
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Serial port of a [KLine] module
+pub struct KLinePort {
+    /// Path of the port
+    pub path: PathBuf,
+    /// How the address byte of 5 baud init is sent
+    pub five_baud: FiveBaudMethod,
+    /// The interface receives the bytes it sends
+    pub echo: bool
+}
+
+impl KLinePort {
+    /// Parses a port of the option string: a path followed by `;` separated options
+    pub fn parse(s: &str) -> Result<Self, PduError> {
+        let mut parts = s.split(';').map(str::trim);
+        let path = parts.next().filter(|p| !p.is_empty()).ok_or(PduError::InvalidParameters)?;
+        let mut port = Self { path: path.into(), five_baud: FiveBaudMethod::Break, echo: true };
+        for option in parts {
+            match option {
+                "5baud=break" => port.five_baud = FiveBaudMethod::Break,
+                "5baud=uart" => port.five_baud = FiveBaudMethod::Uart,
+                "echo=on" => port.echo = true,
+                "echo=off" => port.echo = false,
+                _ => return Err(PduError::InvalidParameters)
+            }
+        }
+        Ok(port)
+    }
+}
+
+/// Returns the USB serial ports of the system
+fn serial_ports() -> Vec<KLinePort> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev")
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| e.file_name().to_str().is_some_and(|n| n.starts_with("ttyUSB") || n.starts_with("ttyACM")))
+                .map(|e| e.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths.into_iter().map(|path| KLinePort { path, five_baud: FiveBaudMethod::Break, echo: true }).collect()
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink on a K-Line serial port
+pub struct KLineChannel {
+    serial: SerialPort,
+    port: KLinePort,
+    iso9141: bool,
+    clock: Clock,
+    params: LinkParams,
+    baudrate: u32,
+    key_bytes: Option<[u8; 2]>,
+    last_activity: Instant,
+    errors: VecDeque<PduErrorEvt>
+}
+
+impl KLineChannel {
+    /// Opens a serial port for a protocol
+    pub fn open(port: &KLinePort, protocol: Protocol, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let serial = SerialPort::open(&port.path).map_err(pdu_error)?;
+        let baudrate = params.get_u32(StdComParam::Baudrate).unwrap_or(10400);
+        serial.set_baudrate(baudrate).map_err(|_| PduError::ValueNotSupported)?;
+        Ok(Self {
+            serial,
+            port: port.clone(),
+            iso9141: !KWP_PROTOCOLS.contains(&protocol),
+            clock,
+            params: params.clone(),
+            baudrate,
+            key_bytes: None,
+            last_activity: Instant::now(),
+            errors: VecDeque::new()
+        })
+    }
+
+    /// Returns the key bytes of the last initialization
+    pub fn key_bytes(&self) -> Option<[u8; 2]> {
+        self.key_bytes
+    }
+
+    /// Returns the transmission time of a byte (start bit, 8 data bits and stop bit)
+    fn byte_time(&self) -> Duration {
+        Duration::from_micros(10_000_000 / self.baudrate.max(1) as u64)
+    }
+
+    /// Returns the timing of the link: the entry of `CP_AccessTimingOverride` in use, or else the
+    /// timing ComParams
+    pub fn timing(&self, params: &LinkParams) -> KLineTiming {
+        let timing = KLineTiming {
+            p2_min: params.get_duration(StdComParam::P2Min).unwrap_or_default(),
+            p2_max: params.get_duration(StdComParam::P2Max).unwrap_or(DEFAULT_P2_MAX),
+            p3_min: params.get_duration(StdComParam::P3Min).unwrap_or_default(),
+            p3_max: DEFAULT_P3_MAX,
+            p4_min: params.get_duration(StdComParam::P4Min).unwrap_or_default()
+        };
+        let Some(ComParamValue::AccessTiming(entries)) = params.get(StdComParam::AccessTimingOverride) else {
+            return timing;
+        };
+        // Key byte 1 has TP0 set and TP1 clear for extended timing
+        let key_set = match self.key_bytes {
+            Some([kb1, _]) if kb1 & 0x30 == 0x10 => TimingSet::Extended,
+            _ => TimingSet::Normal
+        };
+        [TimingSet::OverrideTester, key_set, TimingSet::Default]
+            .iter()
+            .find_map(|set| entries.iter().find(|e| e.timing_set == *set))
+            .map_or(timing, |e| KLineTiming {
+                p2_min: half_ms(e.p2_min),
+                p2_max: half_ms(e.p2_max),
+                p3_min: half_ms(e.p3_min),
+                p3_max: P3_MAX_RESOLUTION * e.p3_max.into(),
+                p4_min: half_ms(e.p4_min)
+            })
+    }
+
+    /// Builds the frame of a request
+    fn frame(&self, data: &[u8], params: &LinkParams) -> Result<Vec<u8>, PduError> {
+        let (format_param, target_param) = match params.get_u32(StdComParam::RequestAddrMode) {
+            Some(FUNCTIONAL_ADDR_MODE) => (StdComParam::FuncReqFormatPriorityType, StdComParam::FuncReqTargetAddr),
+            _ => (StdComParam::PhysReqFormatPriorityType, StdComParam::PhysReqTargetAddr)
+        };
+        let format = params.get_u32(format_param).unwrap_or(ADDR_PHYSICAL.into()) as u8;
+        let target = params.get_u32(target_param).unwrap_or_default() as u8;
+        let source = params.get_u32(StdComParam::TesterSourceAddress).unwrap_or_default() as u8;
+        if self.iso9141 {
+            let mut frame = vec![format, target, source];
+            frame.extend(data);
+            frame.push(checksum(&frame));
+            return Ok(frame);
+        }
+        let long = data.len() > MAX_FORMAT_LEN;
+        let (addresses, length_byte) = match params.get_u32(StdComParam::HeaderFormatKw).unwrap_or(5) {
+            0 => (false, false),
+            1 => (false, true),
+            2 => (true, false),
+            3 => (true, true),
+            4 => (false, long),
+            _ => (true, long)
+        };
+        let address_mode = match (addresses, format & ADDR_MASK) {
+            (false, _) => ADDR_NONE,
+            (true, ADDR_NONE) => ADDR_PHYSICAL,
+            (true, mode) => mode
+        };
+        encode_frame(&KwpHeader { address_mode, target, source, length_byte }, data)
+    }
+
+    /// Sends bytes `p4_min` apart, checking the echo of every byte
+    fn write_bytes(&mut self, bytes: &[u8], p4_min: Duration) -> Result<(), PduError> {
+        let echo_timeout = self.byte_time() + ECHO_TIMEOUT;
+        for (i, b) in bytes.iter().enumerate() {
+            if i != 0 {
+                thread::sleep(p4_min);
+            }
+            self.serial.write_all(&[*b]).map_err(pdu_error)?;
+            if self.port.echo {
+                // A different or missing echo means another node drove the line
+                if self.serial.read_byte(echo_timeout).map_err(pdu_error)? != Some(*b) {
+                    self.last_activity = Instant::now();
+                    return Err(PduError::FctFailed);
+                }
+            } else {
+                self.serial.drain().map_err(pdu_error)?;
+            }
+        }
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Sends a request once P3Min has passed since the last bus activity
+    fn write_request(&mut self, data: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        let frame = self.frame(data, params)?;
+        let timing = self.timing(params);
+        sleep_until(self.last_activity + timing.p3_min);
+        // Bytes left over from earlier responses can not belong to this request
+        self.serial.flush_input().map_err(pdu_error)?;
+        self.write_bytes(&frame, timing.p4_min)
+    }
+
+    /// Reads a frame, waiting up to `timeout` for its first byte
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        let Some(first) = self.serial.read_byte(timeout).map_err(pdu_error)? else {
+            return Ok(None);
+        };
+        let start = self.clock.now();
+        let p1_max = self.params.get_duration(StdComParam::P1Max).unwrap_or(Duration::from_millis(20)) + self.byte_time();
+        let mut frame = vec![first];
+        loop {
+            if !self.iso9141 && frame_len(&frame).is_some_and(|len| frame.len() >= len) {
+                break;
+            }
+            match self.serial.read_byte(p1_max).map_err(pdu_error)? {
+                Some(b) => frame.push(b),
+                None if self.iso9141 => break,
+                None => {
+                    self.last_activity = Instant::now();
+                    self.errors.push_back(PduErrorEvt::FrameStruct);
+                    return Ok(None);
+                }
+            }
+        }
+        self.last_activity = Instant::now();
+
+        let header_len = if self.iso9141 {
+            let valid = frame.len() > ISO9141_HEADER_LEN + 1 && checksum(&frame[..frame.len() - 1]) == frame[frame.len() - 1];
+            valid.then_some(ISO9141_HEADER_LEN)
+        } else {
+            let tester = self.params.get_u32(StdComParam::TesterSourceAddress).unwrap_or_default() as u8;
+            match decode_frame(&frame) {
+                Some((header, _)) if header.has_addresses() && header.target != tester => return Ok(None),
+                Some((header, _)) => Some(header.encoded_len()),
+                None => None
+            }
+        };
+        let Some(header_len) = header_len else {
+            self.errors.push_back(PduErrorEvt::FrameStruct);
+            return Ok(None);
+        };
+        let footer = frame.split_off(frame.len() - 1);
+        let data = frame.split_off(header_len);
+        Ok(Some(ResultEvent {
+            start_msg_timestamp: start,
+            extra_info: Some(ExtraInfoData { header: frame, footer }),
+            data,
+            ..Default::default()
+        }))
+    }
+
+    /// Waits up to P2Max for the response to a request
+    fn read_response(&mut self, params: &LinkParams) -> Result<Option<ResultEvent>, PduError> {
+        let deadline = Instant::now() + self.timing(params).p2_max;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            if let Some(response) = self.read_frame(remaining)? {
+                return Ok(Some(response));
+            }
+        }
+    }
+
+    /// Holds the break condition of the port
+    fn send_break(&mut self, duration: Duration) -> Result<(), PduError> {
+        self.serial.set_break(true).map_err(pdu_error)?;
+        thread::sleep(duration);
+        self.serial.set_break(false).map_err(pdu_error)?;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Fast init: wake up pattern followed by a StartCommunication request
+    fn fast_init(&mut self, data: &[u8], params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        if self.iso9141 {
+            return Err(PduError::ValueNotSupported);
+        }
+        let t_idle = params.get_duration(StdComParam::TIdle).unwrap_or_default();
+        let t_inil = params.get_duration(StdComParam::TInil).unwrap_or_default();
+        let t_wup = params.get_duration(StdComParam::TWup).unwrap_or_default();
+        sleep_until(self.last_activity + t_idle);
+        self.send_break(t_inil)?;
+        sleep_until(self.last_activity + t_wup.saturating_sub(t_inil));
+
+        let request = if data.is_empty() { &[START_COMMUNICATION][..] } else { data };
+        let frame = self.frame(request, params)?;
+        self.serial.flush_input().map_err(pdu_error)?;
+        self.write_bytes(&frame, self.timing(params).p4_min)?;
+        let response = self.read_response(params)?.ok_or(PduError::FctFailed)?;
+        match response.data.as_slice() {
+            [NEGATIVE_RESPONSE, ..] => Err(PduError::FctFailed),
+            [sid, kb1, kb2, ..] if *sid == START_COMMUNICATION + 0x40 => {
+                self.key_bytes = Some([*kb1, *kb2]);
+                Ok(Some(response.data))
+            },
+            _ => Ok(Some(response.data))
+        }
+    }
+
+    /// Reads a byte of the 5 baud init sequence
+    fn init_byte(&self, timeout: Duration) -> Result<u8, PduError> {
+        self.serial.read_byte(timeout + self.byte_time()).map_err(pdu_error)?.ok_or(PduError::FctFailed)
+    }
+
+    /// 5 baud init: address byte at 5 baud, then synchronization and key byte exchange
+    fn five_baud_init(&mut self, params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        let address = match params.get_u32(StdComParam::RequestAddrMode) {
+            Some(FUNCTIONAL_ADDR_MODE) => params.get_u32(StdComParam::FiveBaudAddressFunc),
+            _ => params.get_u32(StdComParam::FiveBaudAddressPhys)
+        }
+        .unwrap_or_default() as u8;
+        let w = |p| params.get_duration(p).unwrap_or_default();
+        sleep_until(self.last_activity + w(StdComParam::W5Min));
+
+        match self.port.five_baud {
+            FiveBaudMethod::Break => {
+                // Start bit, 8 data bits (LSB first) and stop bit. The line is low during a break
+                let start = Instant::now();
+                let bits = [false].into_iter().chain((0..8).map(|i| address & (1 << i) != 0)).chain([true]);
+                for (i, bit) in bits.enumerate() {
+                    self.serial.set_break(!bit).map_err(pdu_error)?;
+                    sleep_until(start + FIVE_BAUD_BIT * (i as u32 + 1));
+                }
+            },
+            FiveBaudMethod::Uart => {
+                self.serial.set_baudrate(5).map_err(|_| PduError::ValueNotSupported)?;
+                let sent = self.serial.write_all(&[address]).and_then(|_| self.serial.drain());
+                self.serial.set_baudrate(self.baudrate).map_err(pdu_error)?;
+                sent.map_err(pdu_error)?;
+            }
+        }
+        self.serial.flush_input().map_err(pdu_error)?;
+
+        // The echo of the address byte can still arrive
+        let deadline = Instant::now() + w(StdComParam::W1Max);
+        let sync = loop {
+            match self.init_byte(deadline.saturating_duration_since(Instant::now()))? {
+                b if b == address => {},
+                b => break b
+            }
+        };
+        if sync != SYNC_BYTE {
+            return Err(PduError::FctFailed);
+        }
+        let kb1 = self.init_byte(w(StdComParam::W2Max))?;
+        let kb2 = self.init_byte(w(StdComParam::W3Max))?;
+        if !valid_key_bytes(self.iso9141, [kb1, kb2]) {
+            self.last_activity = Instant::now();
+            return Err(PduError::FctFailed);
+        }
+        thread::sleep(W4_MIN);
+        self.write_bytes(&[!kb2], Duration::ZERO)?;
+        if self.init_byte(w(StdComParam::W4Max))? != !address {
+            return Err(PduError::FctFailed);
+        }
+        self.last_activity = Instant::now();
+        self.key_bytes = Some([kb1, kb2]);
+        Ok(Some(vec![kb1, kb2]))
+    }
+}
+
+impl Channel for KLineChannel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let baudrate = params.get_u32(StdComParam::Baudrate).unwrap_or(self.baudrate);
+        if baudrate != self.baudrate {
+            self.serial.set_baudrate(baudrate).map_err(|_| PduError::ValueNotSupported)?;
+            self.baudrate = baudrate;
+        }
+        self.params = params.clone();
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8], _tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        if data.is_empty() {
+            return Err(PduError::InvalidParameters);
+        }
+        self.write_request(data, params)
+    }
+
+    fn response_timing(&self, params: &LinkParams) -> ResponseTiming {
+        ResponseTiming { p2_max: self.timing(params).p2_max, ..ResponseTiming::from_params(params) }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        self.read_frame(timeout)
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        self.errors.pop_front()
+    }
+
+    fn start_comm(&mut self, data: &[u8], params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        self.key_bytes = None;
+        match params.get_u32(StdComParam::InitializationSettings) {
+            Some(1) => self.five_baud_init(params),
+            Some(2) => self.fast_init(data, params),
+            _ => Ok(None)
+        }
+    }
+
+    fn stop_comm(&mut self, data: &[u8], params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        if self.iso9141 {
+            return Ok(None);
+        }
+        let request = if data.is_empty() { &[STOP_COMMUNICATION][..] } else { data };
+        self.write_request(request, params)?;
+        // The response still uses the timing of the key bytes
+        let response = self.read_response(params);
+        self.key_bytes = None;
+        Ok(response?.map(|r| r.data))
+    }
+
+    fn ioctl(&mut self, command: IoctlCommand, input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        match (command, input) {
+            (IoctlCommand::SendBreak, IoctlInput::None) => {
+                let t_inil = self.params.get_duration(StdComParam::TInil).unwrap_or_default();
+                self.send_break(t_inil).map(|_| None)
+            },
+            (IoctlCommand::SendBreak, input) => {
+                self.send_break(Duration::from_micros(input.unum32()?.into())).map(|_| None)
+            },
+            _ => Err(PduError::IdNotSupported)
+        }
+    }
+}
+
+#[derive(Debug)]
+/// [Driver] for K-Line interfaces on serial ports
+pub struct KLine {
+    ports: Vec<KLinePort>
+}
+
+impl KLine {
+    /// Returns the serial ports, in module order
+    pub fn ports(&self) -> &[KLinePort] {
+        &self.ports
+    }
+
+    fn port(&self, module: usize) -> Result<&KLinePort, PduError> {
+        self.ports.get(module).ok_or(PduError::InvalidHandle)
+    }
+}
+
+impl Driver for KLine {
+    type Channel = KLineChannel;
+
+    fn open(options: &str) -> Result<Self, PduError> {
+        let ports = options
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(KLinePort::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { ports: if ports.is_empty() { serial_ports() } else { ports } })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        self.ports
+            .iter()
+            .map(|port| DriverModule {
+                module_type_id: MODULE_TYPE_ID,
+                name: port.path.display().to_string(),
+                info: format!("K-Line interface on {}", port.path.display()),
+                resources: [(BusType::Iso14230_1Uart, KWP_PROTOCOLS), (BusType::Iso9141_2Uart, &[Protocol::IsoObdOnIso9141_2][..])]
+                    .into_iter()
+                    .map(|(bus_type, protocols)| DriverResource {
+                        bus_type,
+                        protocols: protocols.to_vec(),
+                        pins: bus_type.default_obd_pins()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn connect_module(&self, module: usize) -> Result<(), PduError> {
+        SerialPort::open(&self.port(module)?.path).map(|_| ()).map_err(pdu_error)
+    }
+
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        let mut params = vec![
+            (StdComParam::Baudrate, 10400),
+            (StdComParam::P1Max, 20_000),
+            (StdComParam::P2Max, 50_000),
+            (StdComParam::P3Min, 55_000),
+            (StdComParam::P4Min, 5_000),
+            (StdComParam::W1Max, 300_000),
+            (StdComParam::W2Max, 20_000),
+            (StdComParam::W3Max, 20_000),
+            (StdComParam::W4Max, 50_000),
+            (StdComParam::W5Min, 300_000),
+            (StdComParam::TIdle, 300_000),
+            (StdComParam::TInil, 25_000),
+            (StdComParam::TWup, 50_000),
+            (StdComParam::TesterSourceAddress, 0xF1),
+            (StdComParam::FiveBaudAddressFunc, 0x33)
+        ];
+        params.extend(match protocol {
+            Protocol::IsoObdOnIso9141_2 => [
+                (StdComParam::InitializationSettings, 1),
+                (StdComParam::RequestAddrMode, FUNCTIONAL_ADDR_MODE),
+                (StdComParam::FiveBaudAddressPhys, 0x33),
+                (StdComParam::PhysReqFormatPriorityType, 0x68),
+                (StdComParam::PhysReqTargetAddr, 0x6A),
+                (StdComParam::FuncReqFormatPriorityType, 0x68),
+                (StdComParam::FuncReqTargetAddr, 0x6A)
+            ],
+            Protocol::IsoObdOnIso14230_4 => [
+                (StdComParam::InitializationSettings, 2),
+                (StdComParam::RequestAddrMode, FUNCTIONAL_ADDR_MODE),
+                (StdComParam::FiveBaudAddressPhys, 0x33),
+                (StdComParam::PhysReqFormatPriorityType, ADDR_PHYSICAL.into()),
+                (StdComParam::PhysReqTargetAddr, 0x10),
+                (StdComParam::FuncReqFormatPriorityType, ADDR_FUNCTIONAL.into()),
+                (StdComParam::FuncReqTargetAddr, 0x33)
+            ],
+            _ => [
+                (StdComParam::InitializationSettings, 2),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::FiveBaudAddressPhys, 0x10),
+                (StdComParam::PhysReqFormatPriorityType, ADDR_PHYSICAL.into()),
+                (StdComParam::PhysReqTargetAddr, 0x10),
+                (StdComParam::FuncReqFormatPriorityType, ADDR_FUNCTIONAL.into()),
+                (StdComParam::FuncReqTargetAddr, 0x33)
+            ]
+        });
+        match protocol {
+            Protocol::IsoObdOnIso9141_2 => {},
+            Protocol::IsoObdOnIso14230_4 => params.push((StdComParam::HeaderFormatKw, 2)),
+            _ => params.push((StdComParam::HeaderFormatKw, 5))
+        }
+        let mut params: Vec<_> = params.into_iter().filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v))).collect();
+        if let Ok(v) = StdComParam::AccessTimingOverride.access_timing(&[]) {
+            params.push((StdComParam::AccessTimingOverride, v));
+        }
+        params
+    }
+
+    fn start_comm_required(&self, _protocol: Protocol) -> bool {
+        true
+    }
+
+    fn open_channel(
+        &self,
+        module: usize,
+        _resource: &DriverResource,
+        protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        KLineChannel::open(self.port(module)?, protocol, params, clock)
+    }
+}
@@ -0,0 +1,335 @@
+//! Simulated K-Line ECUs on a pseudo terminal, for testing without a vehicle
+//!
+//! [KLineSimulator] opens a pseudo terminal, whose [path](KLineSimulator::path) is used as the
+//! port of the [KLine](super::KLine) backend. Requests are forwarded to the [VirtualEcu] with
+//! their target address, or to every virtual ECU for [SimConfig::functional_address] and for
+//! headers without addresses. Responses use the header format of the request, with the ECU as
+//! source.
+//!
+//! Communication starts with a StartCommunication request, or with 5 baud init: a lone byte
+//! with the address of an ECU or the functional address. A pseudo terminal can not carry the
+//! break condition, so the wake up pattern of fast init is not checked and 5 baud init needs
+//! the `5baud=uart` option of the port. Key bytes `[0x08, 0x08]` and `[0x94, 0x94]` switch the
+//! simulator to ISO 9141-2 frames.
+//!
+//! ```
+//! use std::time::Duration;
+//! use dpdu_rust::{
+//!     backends::kline::{sim::{KLineSimulator, SimConfig}, KLine},
+//!     provider::{Channel, Clock, Driver, LinkParams},
+//!     Protocol
+//! };
+//!
+//! let sim = KLineSimulator::start(SimConfig::default()).unwrap();
+//! sim.add_ecu(0x10, |request: &[u8]| vec![[&[request[0].wrapping_add(0x40)], &request[1..]].concat()]);
+//!
+//! let driver = KLine::open(&format!("{};5baud=uart", sim.path().display())).unwrap();
+//! let resource = &driver.modules()[0].resources[0];
+//! let protocol = Protocol::Iso14230_3OnIso14230_2;
+//! let params = LinkParams::new(driver.com_params(protocol));
+//! let mut channel = driver.open_channel(0, resource, protocol, &params, Clock::new()).unwrap();
+//! let response = channel.start_comm(&[], &params).unwrap().unwrap();
+//! assert_eq!(response, [0xC1, 0xEF, 0x8F]);
+//!
+//! channel.send(&[0x3E, 0x01], &[], &params).unwrap();
+//! let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+//! assert_eq!(response.data, [0x7E, 0x01]);
+//! assert_eq!(response.extra_info.unwrap().header, [0x82, 0xF1, 0x10]);
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+pub use crate::backends::sim::VirtualEcu;
+
+use super::{
+    checksum, decode_frame, encode_frame, frame_len, sleep_until, KwpHeader, ADDR_NONE, ADDR_PHYSICAL,
+    MAX_FORMAT_LEN, START_COMMUNICATION, STOP_COMMUNICATION, SYNC_BYTE
+};
+use crate::backends::serial::{Pty, SerialPort};
+
+/// How often the simulator thread checks whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Time without a byte which ends a request that has no length information
+const FRAME_GAP: Duration = Duration::from_millis(20);
+/// Time between the bytes of the 5 baud init sequence sent by the ECU
+const INIT_BYTE_DELAY: Duration = Duration::from_millis(10);
+/// Time the ECU waits for the inverted key byte 2 during 5 baud init. Above W4Max for slow
+/// testers, but below W5Min, so that the address byte of a retry is not taken for it
+const INIT_TIMEOUT: Duration = Duration::from_millis(150);
+/// Time between the inverted key byte 2 and the inverted address during 5 baud init (W4)
+const W4_DELAY: Duration = Duration::from_millis(25);
+
+/// Format byte of ISO 9141-2 responses
+const ISO9141_RESPONSE_FORMAT: u8 = 0x48;
+/// Target byte of ISO 9141-2 responses
+const ISO9141_RESPONSE_TARGET: u8 = 0x6B;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Configuration of a [KLineSimulator]
+pub struct SimConfig {
+    /// Key bytes sent during 5 baud init and in StartCommunication responses
+    pub key_bytes: [u8; 2],
+    /// Functional address, forwarded to every virtual ECU
+    pub functional_address: u8,
+    /// Echo every received byte, like the single wire K-Line
+    pub echo: bool,
+    /// Time between the end of a request and each response (P2)
+    pub response_delay: Duration
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self { key_bytes: [0xEF, 0x8F], functional_address: 0x33, echo: true, response_delay: Duration::from_millis(25) }
+    }
+}
+
+impl SimConfig {
+    /// Returns true if the key bytes select ISO 9141-2
+    pub fn is_iso9141(&self) -> bool {
+        matches!(self.key_bytes, [0x08, 0x08] | [0x94, 0x94])
+    }
+}
+
+struct SimState {
+    config: SimConfig,
+    ecus: BTreeMap<u8, Box<dyn VirtualEcu>>,
+    initialized: bool,
+    outgoing: Vec<u8>
+}
+
+impl fmt::Debug for SimState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimState")
+            .field("config", &self.config)
+            .field("ecus", &self.ecus.keys().collect::<Vec<_>>())
+            .field("initialized", &self.initialized)
+            .field("outgoing", &self.outgoing)
+            .finish()
+    }
+}
+
+impl SimState {
+    /// Returns the ECUs a request is addressed to
+    fn targets(&self, target: Option<u8>) -> Vec<u8> {
+        match target {
+            Some(t) if t != self.config.functional_address => {
+                self.ecus.keys().copied().filter(|a| *a == t).collect()
+            },
+            _ => self.ecus.keys().copied().collect()
+        }
+    }
+
+    /// Handles a KWP2000 request, returning the response frames
+    fn kwp_request(&mut self, header: KwpHeader, data: &[u8]) -> Vec<Vec<u8>> {
+        let targets = self.targets(header.has_addresses().then_some(header.target));
+        let mut frames = Vec::new();
+        for address in targets {
+            let responses = match data[0] {
+                START_COMMUNICATION => {
+                    self.initialized = true;
+                    vec![[&[START_COMMUNICATION + 0x40][..], &self.config.key_bytes].concat()]
+                },
+                STOP_COMMUNICATION if self.initialized => {
+                    self.initialized = false;
+                    vec![vec![STOP_COMMUNICATION + 0x40]]
+                },
+                _ if self.initialized => self.ecus.get_mut(&address).map(|ecu| ecu.handle(data)).unwrap_or_default(),
+                _ => Vec::new()
+            };
+            for response in responses.iter().filter(|r| !r.is_empty()) {
+                let response_header = KwpHeader {
+                    address_mode: if header.has_addresses() { ADDR_PHYSICAL } else { ADDR_NONE },
+                    target: header.source,
+                    source: address,
+                    length_byte: header.length_byte || response.len() > MAX_FORMAT_LEN
+                };
+                frames.extend(encode_frame(&response_header, response));
+            }
+        }
+        frames
+    }
+
+    /// Handles an ISO 9141-2 request, returning the response frames
+    fn iso9141_request(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for (address, ecu) in self.ecus.iter_mut() {
+            for response in ecu.handle(data).iter().filter(|r| !r.is_empty()) {
+                let mut frame = vec![ISO9141_RESPONSE_FORMAT, ISO9141_RESPONSE_TARGET, *address];
+                frame.extend(response);
+                frame.push(checksum(&frame));
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+}
+
+#[derive(Debug)]
+/// Simulated K-Line bus with virtual ECUs, which runs until it is dropped
+pub struct KLineSimulator {
+    path: PathBuf,
+    state: Arc<Mutex<SimState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl KLineSimulator {
+    /// Starts a simulator on a new pseudo terminal
+    pub fn start(config: SimConfig) -> io::Result<Self> {
+        let pty = Pty::open()?;
+        let path = pty.path().to_owned();
+        let state = Arc::new(Mutex::new(SimState { config, ecus: BTreeMap::new(), initialized: false, outgoing: Vec::new() }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new().name("kline-sim".into()).spawn({
+            let (state, stop) = (state.clone(), stop.clone());
+            move || run(pty.master(), &state, &stop)
+        })?;
+        Ok(Self { path, state, stop, thread: Some(thread) })
+    }
+
+    /// Returns the path of the serial port the backend opens
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        lock(&self.state)
+    }
+
+    /// Adds a virtual ECU, replacing any ECU with the same address
+    pub fn add_ecu(&self, address: u8, ecu: impl VirtualEcu) {
+        self.state().ecus.insert(address, Box::new(ecu));
+    }
+
+    /// Removes a virtual ECU
+    pub fn remove_ecu(&self, address: u8) {
+        self.state().ecus.remove(&address);
+    }
+
+    /// Changes the configuration
+    pub fn set_config(&self, config: SimConfig) {
+        self.state().config = config;
+    }
+
+    /// Sends bytes to the backend as they are, such as frames with a wrong checksum which the
+    /// virtual ECUs can not produce
+    pub fn send_bytes(&self, bytes: &[u8]) {
+        self.state().outgoing.extend(bytes);
+    }
+
+    /// Returns true once communication has been started, until it is stopped
+    pub fn is_initialized(&self) -> bool {
+        self.state().initialized
+    }
+}
+
+impl Drop for KLineSimulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn lock(state: &Mutex<SimState>) -> MutexGuard<'_, SimState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Reads a byte, echoing it if configured
+fn read_byte(port: &SerialPort, timeout: Duration, echo: bool) -> io::Result<Option<u8>> {
+    let b = port.read_byte(timeout)?;
+    if let (Some(b), true) = (b, echo) {
+        port.write_all(&[b])?;
+    }
+    Ok(b)
+}
+
+fn run(port: &SerialPort, state: &Mutex<SimState>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        let outgoing = std::mem::take(&mut lock(state).outgoing);
+        if !outgoing.is_empty() {
+            let _ = port.write_all(&outgoing);
+        }
+        let echo = lock(state).config.echo;
+        let mut frame = match read_byte(port, POLL_INTERVAL, echo) {
+            Ok(Some(b)) => vec![b],
+            Ok(None) => continue,
+            Err(_) => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let mut end = Instant::now();
+        // KWP2000 frames end after their length, anything else after a gap
+        while frame_len(&frame).is_none_or(|len| frame.len() < len) {
+            match read_byte(port, FRAME_GAP, echo) {
+                Ok(Some(b)) => {
+                    frame.push(b);
+                    end = Instant::now();
+                },
+                _ => break
+            }
+        }
+        let _ = handle_frame(port, state, &frame, end);
+    }
+}
+
+/// Answers a request which ended at `end`, or performs 5 baud init for a lone address byte
+fn handle_frame(port: &SerialPort, state: &Mutex<SimState>, frame: &[u8], end: Instant) -> io::Result<()> {
+    let mut guard = lock(state);
+    let config = guard.config;
+    if let [address] = frame {
+        if *address == config.functional_address || guard.ecus.contains_key(address) {
+            drop(guard);
+            let initialized = five_baud_init(port, &config, *address)?;
+            lock(state).initialized = initialized;
+            return Ok(());
+        }
+    }
+    let frames = if config.is_iso9141() {
+        let valid = frame.len() > 4 && checksum(&frame[..frame.len() - 1]) == frame[frame.len() - 1];
+        match valid && guard.initialized {
+            true => guard.iso9141_request(&frame[3..frame.len() - 1]),
+            false => Vec::new()
+        }
+    } else {
+        match decode_frame(frame) {
+            Some((header, data)) if !data.is_empty() => guard.kwp_request(header, data),
+            _ => Vec::new()
+        }
+    };
+    drop(guard);
+    let mut last = end;
+    for frame in frames {
+        sleep_until(last + config.response_delay);
+        port.write_all(&frame)?;
+        last = Instant::now();
+    }
+    Ok(())
+}
+
+/// ECU side of 5 baud init, after the address byte. Returns true if the tester completed it
+fn five_baud_init(port: &SerialPort, config: &SimConfig, address: u8) -> io::Result<bool> {
+    let [kb1, kb2] = config.key_bytes;
+    for b in [SYNC_BYTE, kb1, kb2] {
+        thread::sleep(INIT_BYTE_DELAY);
+        port.write_all(&[b])?;
+    }
+    if read_byte(port, INIT_TIMEOUT, config.echo)? != Some(!kb2) {
+        return Ok(false);
+    }
+    thread::sleep(W4_DELAY);
+    port.write_all(&[!address])?;
+    Ok(true)
+}
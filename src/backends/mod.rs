@@ -0,0 +1,26 @@
+//! Ready made [Driver](crate::provider::Driver)s for [DriverBackend](crate::provider::DriverBackend),
+//! each behind a cargo feature of the same name
+
+#[cfg(feature = "doip")]
+pub mod doip;
+
+#[cfg(all(feature = "elm327", target_os = "linux"))]
+pub mod elm327;
+
+#[cfg(feature = "j2534")]
+pub mod j2534;
+
+#[cfg(all(feature = "kline", target_os = "linux"))]
+pub mod kline;
+
+#[cfg(all(any(feature = "elm327", feature = "kline", feature = "slcan"), target_os = "linux"))]
+mod serial;
+
+#[cfg(any(feature = "doip", feature = "elm327", feature = "kline", feature = "slcan"))]
+pub mod sim;
+
+#[cfg(all(feature = "slcan", target_os = "linux"))]
+pub mod slcan;
+
+#[cfg(all(feature = "socketcan", target_os = "linux"))]
+pub mod socketcan;
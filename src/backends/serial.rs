@@ -0,0 +1,207 @@
+//! Serial ports and pseudo terminals (Linux), shared by the serial backends
+
+use std::{
+    ffi::{c_int, CStr, CString},
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    ptr,
+    time::{Duration, Instant}
+};
+
+fn cvt(ret: c_int) -> io::Result<c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn cvt_size(ret: isize) -> io::Result<usize> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Waits for a descriptor to become readable or writable. Returns false on timeout
+fn wait(fd: &OwnedFd, events: libc::c_short, timeout: Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd { fd: fd.as_raw_fd(), events, revents: 0 };
+    let ms = timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int;
+    // Safety: Exactly one pollfd is passed
+    match cvt(unsafe { libc::poll(&mut pfd, 1, ms) }) {
+        Ok(n) => Ok(n > 0),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(false),
+        Err(e) => Err(e)
+    }
+}
+
+fn open_path(path: &Path) -> io::Result<OwnedFd> {
+    let path = CString::new(path.as_os_str().as_encoded_bytes()).map_err(|_| io::ErrorKind::InvalidInput)?;
+    let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC;
+    // Safety: The path is nul terminated, and the returned descriptor is owned by nothing else
+    unsafe { cvt(libc::open(path.as_ptr(), flags)).map(|fd| OwnedFd::from_raw_fd(fd)) }
+}
+
+fn get_termios(fd: &OwnedFd) -> io::Result<libc::termios2> {
+    // Safety: termios2 is plain data, filled in by the kernel
+    let mut tio: libc::termios2 = unsafe { std::mem::zeroed() };
+    // Safety: TCGETS2 writes a termios2
+    cvt(unsafe { libc::ioctl(fd.as_raw_fd(), libc::TCGETS2, ptr::from_mut(&mut tio)) })?;
+    Ok(tio)
+}
+
+fn set_termios(fd: &OwnedFd, tio: &libc::termios2) -> io::Result<()> {
+    // Safety: TCSETS2 reads a termios2
+    cvt(unsafe { libc::ioctl(fd.as_raw_fd(), libc::TCSETS2, ptr::from_ref(tio)) }).map(|_| ())
+}
+
+/// Switches a terminal to raw 8N1 mode, where reads return as soon as a byte is available
+fn make_raw(fd: &OwnedFd) -> io::Result<()> {
+    let mut tio = get_termios(fd)?;
+    tio.c_iflag &= !(libc::IGNBRK | libc::BRKINT | libc::PARMRK | libc::ISTRIP | libc::INLCR | libc::IGNCR | libc::ICRNL | libc::IXON | libc::IXOFF);
+    tio.c_oflag &= !libc::OPOST;
+    tio.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+    tio.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::CSTOPB | libc::CRTSCTS);
+    tio.c_cflag |= libc::CS8 | libc::CLOCAL | libc::CREAD;
+    tio.c_cc[libc::VMIN] = 0;
+    tio.c_cc[libc::VTIME] = 0;
+    set_termios(fd, &tio)
+}
+
+#[derive(Debug)]
+/// Serial port in raw 8N1 mode
+pub struct SerialPort {
+    fd: OwnedFd
+}
+
+impl SerialPort {
+    /// Opens a serial port
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let fd = open_path(path)?;
+        make_raw(&fd)?;
+        Ok(Self { fd })
+    }
+
+    /// Sets the baud rate. Any rate the UART can generate is accepted, not only the standard ones
+    pub fn set_baudrate(&self, baudrate: u32) -> io::Result<()> {
+        let mut tio = get_termios(&self.fd)?;
+        tio.c_cflag &= !libc::CBAUD;
+        tio.c_cflag |= libc::BOTHER;
+        tio.c_ispeed = baudrate;
+        tio.c_ospeed = baudrate;
+        set_termios(&self.fd, &tio)
+    }
+
+    /// Drives the transmit line low (break) or releases it
+    #[cfg_attr(not(feature = "kline"), allow(dead_code))]
+    pub fn set_break(&self, on: bool) -> io::Result<()> {
+        let request = if on { libc::TIOCSBRK } else { libc::TIOCCBRK };
+        // Safety: TIOCSBRK and TIOCCBRK take no argument
+        cvt(unsafe { libc::ioctl(self.fd.as_raw_fd(), request) }).map(|_| ())
+    }
+
+    /// Writes all bytes, waiting for room in the transmit buffer
+    pub fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            // Safety: The buffer is valid for its length
+            match cvt_size(unsafe { libc::write(self.fd.as_raw_fd(), data.as_ptr().cast(), data.len()) }) {
+                Ok(n) => data = &data[n..],
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {
+                    wait(&self.fd, libc::POLLOUT, Duration::from_millis(100))?;
+                },
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until every written byte has been transmitted
+    #[cfg_attr(not(feature = "kline"), allow(dead_code))]
+    pub fn drain(&self) -> io::Result<()> {
+        // Safety: tcdrain only takes the descriptor
+        cvt(unsafe { libc::tcdrain(self.fd.as_raw_fd()) }).map(|_| ())
+    }
+
+    /// Discards received bytes which have not been read yet
+    pub fn flush_input(&self) -> io::Result<()> {
+        // Safety: tcflush only takes the descriptor
+        cvt(unsafe { libc::tcflush(self.fd.as_raw_fd(), libc::TCIFLUSH) }).map(|_| ())
+    }
+
+    /// Reads the bytes which are available, waiting up to `timeout` for the first one.
+    /// Returns 0 on timeout
+    pub fn read(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // With VMIN and VTIME 0, a terminal returns 0 rather than EAGAIN when it has no input.
+            // Safety: The buffer is valid for its length
+            match cvt_size(unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) }) {
+                Ok(0) => {},
+                Ok(n) => return Ok(n),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {},
+                Err(e) => return Err(e)
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !wait(&self.fd, libc::POLLIN, remaining)? {
+                return Ok(0);
+            }
+        }
+    }
+
+    /// Reads one byte, waiting up to `timeout` for it
+    pub fn read_byte(&self, timeout: Duration) -> io::Result<Option<u8>> {
+        let mut b = [0];
+        Ok((self.read(&mut b, timeout)? == 1).then_some(b[0]))
+    }
+}
+
+#[derive(Debug)]
+/// Pseudo terminal, which simulators use as the other end of a serial port
+///
+/// The terminal side is kept open in raw mode, so that nothing written before a backend opens
+/// it is echoed or lost
+pub struct Pty {
+    master: SerialPort,
+    _terminal: OwnedFd,
+    path: PathBuf
+}
+
+impl Pty {
+    /// Opens a new pseudo terminal
+    pub fn open() -> io::Result<Self> {
+        // Safety: The returned descriptor is owned by nothing else
+        let fd = unsafe {
+            cvt(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC))
+                .map(|fd| OwnedFd::from_raw_fd(fd))?
+        };
+        let mut name = [0; 64];
+        // Safety: grantpt and unlockpt only take the descriptor, and ptsname_r writes at most
+        // the length of the buffer
+        unsafe {
+            cvt(libc::grantpt(fd.as_raw_fd()))?;
+            cvt(libc::unlockpt(fd.as_raw_fd()))?;
+            match libc::ptsname_r(fd.as_raw_fd(), name.as_mut_ptr(), name.len()) {
+                0 => {},
+                e => return Err(io::Error::from_raw_os_error(e))
+            }
+        }
+        // Safety: ptsname_r wrote a nul terminated name
+        let path = PathBuf::from(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned());
+        let terminal = open_path(&path)?;
+        make_raw(&terminal)?;
+        let master = SerialPort { fd };
+        Ok(Self { master, _terminal: terminal, path })
+    }
+
+    /// Returns the path of the terminal side, which is opened by the backend
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the master side, which carries the bytes written to and read from the terminal
+    pub fn master(&self) -> &SerialPort {
+        &self.master
+    }
+}
@@ -0,0 +1,15 @@
+//! Building blocks shared by the simulators of the backends
+
+/// Virtual ECU behind a simulator
+///
+/// Closures taking the request and returning the responses are virtual ECUs
+pub trait VirtualEcu: Send + 'static {
+    /// Handles a diagnostic request, returning the responses to send back to the tester
+    fn handle(&mut self, request: &[u8]) -> Vec<Vec<u8>>;
+}
+
+impl<F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static> VirtualEcu for F {
+    fn handle(&mut self, request: &[u8]) -> Vec<Vec<u8>> {
+        self(request)
+    }
+}
@@ -0,0 +1,641 @@
+//! SLCAN backend (Linux), for serial line CAN adapters speaking the Lawicel ASCII protocol
+//!
+//! Every serial port is a module with one `ISO_11898_2_DWCAN` resource on pins 6 and 14 of the
+//! J1962 connector. The resource runs:
+//! * `ISO_11898_RAW` - Raw CAN frames. Messages are a 4 byte big endian CAN ID followed by up to
+//!   8 data bytes. Bit 31 of the ID marks a 29 bit ID, which is implied for IDs above 0x7FF
+//! * `ISO_15765_3_on_ISO_15765_2`, `ISO_14230_3_on_ISO_15765_2` and `ISO_OBD_on_ISO_15765_4` -
+//!   ISO-TP using the userspace [IsoTp] engine. Physical requests are sent to `CP_CanPhysReqId`,
+//!   functional requests (`CP_RequestAddrMode` = 2) are sent as single frames to
+//!   `CP_CanFuncReqId`, and responses are received from `CP_CanRespUSDTId`
+//!
+//! CAN FD frames are not supported by the protocol.
+//!
+//! The ports are taken from the option string of `PDUConstruct`, as comma separated paths each
+//! followed by `;` separated options:
+//! * `baud=N` - Baud rate of the port, 115200 by default. Adapters with a USB virtual serial
+//!   port ignore it
+//!
+//! Without options, every `/dev/ttyUSB*` and `/dev/ttyACM*` port is used.
+//!
+//! `PDUModuleConnect` reads the version (`V`) and serial number (`N`) of the adapter
+//! ([Slcan::identification]), and fails with [PduError::CableUnknown] if it does not answer.
+//! Opening a channel closes the CAN channel of the adapter (`C`), selects the bit rate of
+//! `CP_Baudrate` (`S0` to `S8`, see [BITRATES]), enables timestamps (`Z1`) and opens it again
+//! (`O`). Bit rates without an `S` command fail with [PduError::ValueNotSupported]. Changing
+//! `CP_Baudrate` reopens the CAN channel.
+//!
+//! Received messages are timestamped with the millisecond timestamps of the adapter, converted
+//! to the [Clock] of the backend. Adapters without timestamps get the time the frame was read.
+//! Frames the adapter refuses to send (`BEL` reply) fail the ComPrimitive with a
+//! [PduErrorEvt::TxError].
+//!
+//! The [sim] module emulates an adapter on a pseudo terminal, for testing without one.
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::slcan::Slcan>);
+//! ```
+
+pub mod sim;
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant}
+};
+
+use super::serial::SerialPort;
+use crate::{
+    provider::{
+        send_error_event, CanFrame, Channel, Clock, Driver, DriverModule, DriverResource, IsoTp, IsoTpConfig, IsoTpEvent,
+        LinkParams, ResultEvent, DEFAULT_MAX_WAIT_FRAMES, DEFAULT_RESPONSE_TIMEOUT, OVERRIDE_DISABLED
+    },
+    BusType, CanFlags, ComParamValue, PduError, PduErrorEvt, Protocol, StdComParam, CAN_MAX_DLEN
+};
+
+/// Module type ID of SLCAN adapters (`N_SLCAN`, the Linux line discipline of the protocol)
+pub const MODULE_TYPE_ID: u32 = 17;
+
+/// Default baud rate of the serial port
+pub const DEFAULT_BAUDRATE: u32 = 115_200;
+
+/// CAN bit rates of the `S0` to `S8` commands, indexed by the command number
+pub const BITRATES: [u32; 9] = [10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000];
+
+/// Bit rate used if `CP_Baudrate` is not set
+const DEFAULT_BITRATE: u32 = 500_000;
+
+/// Longest time the adapter takes to answer a command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Period of the adapter timestamps, which count milliseconds up to 59999
+const TIMESTAMP_PERIOD: u32 = 60_000;
+
+/// Bit 31 of a CAN ID, set on 29 bit IDs
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_SFF_MASK: u32 = 0x0000_07FF;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+
+/// `CP_RequestAddrMode` value of functional requests
+const FUNCTIONAL_ADDR_MODE: u32 = 2;
+
+/// Reply of the adapter to a failed command
+const BEL: u8 = 0x07;
+
+/// Protocols of the CAN resource
+const PROTOCOLS: &[Protocol] = &[
+    Protocol::Iso11898Raw,
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Identification of an adapter, read when its module is connected
+pub struct Identification {
+    /// Hardware and software version digits of the `V` reply, such as `1013`
+    pub version: String,
+    /// Serial number of the `N` reply, if the adapter has one
+    pub serial: Option<String>
+}
+
+/// Converts an I/O error of a serial port
+fn pdu_error(_e: io::Error) -> PduError {
+    // The port only fails if the adapter is gone
+    PduError::CommPcToVciFailed
+}
+
+/// Returns true for 29 bit CAN IDs
+fn is_29bit(id: u32) -> bool {
+    id & CAN_EFF_FLAG != 0 || id & CAN_EFF_MASK > CAN_SFF_MASK
+}
+
+/// Returns the command number of a CAN bit rate
+fn bitrate_command(bitrate: u32) -> Result<usize, PduError> {
+    BITRATES.iter().position(|b| *b == bitrate).ok_or(PduError::ValueNotSupported)
+}
+
+/// Formats the transmit command of a frame (`t` or `T`)
+fn format_frame(frame: &CanFrame) -> Result<String, PduError> {
+    if frame.flags.fd {
+        return Err(PduError::ValueNotSupported);
+    }
+    if frame.data.len() > CAN_MAX_DLEN {
+        return Err(PduError::InvalidParameters);
+    }
+    let mut line = match is_29bit(frame.id) {
+        true => format!("T{:08X}{}", frame.id & CAN_EFF_MASK, frame.data.len()),
+        false => format!("t{:03X}{}", frame.id & CAN_SFF_MASK, frame.data.len())
+    };
+    for b in &frame.data {
+        let _ = write!(line, "{b:02X}");
+    }
+    Ok(line)
+}
+
+/// Parses a received frame (`t` or `T`), and its timestamp if it has one. Remote frames and
+/// malformed lines return [None]
+fn parse_frame(line: &str) -> Option<(CanFrame, Option<u16>)> {
+    let hex = |s: &str| u32::from_str_radix(s, 16).ok().filter(|_| s.bytes().all(|b| b.is_ascii_hexdigit()));
+    let (id_len, flag) = match line.bytes().next()? {
+        b't' => (3, 0),
+        b'T' => (8, CAN_EFF_FLAG),
+        _ => return None
+    };
+    let id = hex(line.get(1..1 + id_len)?)?;
+    let len = hex(line.get(1 + id_len..2 + id_len)?)? as usize;
+    if len > CAN_MAX_DLEN {
+        return None;
+    }
+    let rest = line.get(2 + id_len..)?;
+    let data = (0..len).map(|i| hex(rest.get(i * 2..i * 2 + 2)?).map(|b| b as u8)).collect::<Option<Vec<u8>>>()?;
+    let timestamp = match &rest[len * 2..] {
+        "" => None,
+        t if t.len() == 4 => Some(hex(t)? as u16),
+        _ => return None
+    };
+    Some((CanFrame { id: id | flag, flags: CanFlags::default(), data }, timestamp))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Serial port of an [Slcan] module
+pub struct SlcanPort {
+    /// Path of the port
+    pub path: PathBuf,
+    /// Baud rate of the port
+    pub baudrate: u32
+}
+
+impl SlcanPort {
+    /// Parses a port of the option string: a path followed by `;` separated options
+    pub fn parse(s: &str) -> Result<Self, PduError> {
+        let mut parts = s.split(';').map(str::trim);
+        let path = parts.next().filter(|p| !p.is_empty()).ok_or(PduError::InvalidParameters)?;
+        let mut port = Self { path: path.into(), baudrate: DEFAULT_BAUDRATE };
+        for option in parts {
+            match option.split_once('=') {
+                Some(("baud", baud)) => port.baudrate = baud.parse().map_err(|_| PduError::InvalidParameters)?,
+                _ => return Err(PduError::InvalidParameters)
+            }
+        }
+        Ok(port)
+    }
+}
+
+/// Returns the USB serial ports of the system
+fn serial_ports() -> Vec<SlcanPort> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev")
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| e.file_name().to_str().is_some_and(|n| n.starts_with("ttyUSB") || n.starts_with("ttyACM")))
+                .map(|e| e.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths.into_iter().map(|path| SlcanPort { path, baudrate: DEFAULT_BAUDRATE }).collect()
+}
+
+#[derive(Debug, Default)]
+/// Converts the timestamps of received frames, which wrap around every minute, to instants
+struct TimestampSync {
+    /// Timestamp and instant of the last frame
+    last: Option<(u16, Instant)>
+}
+
+impl TimestampSync {
+    /// Returns the instant of a frame which was read at `read`. The time elapsed since the last
+    /// frame is taken from the timestamps, without going past the time the frame was read
+    fn instant(&mut self, timestamp: u16, read: Instant) -> Instant {
+        let at = match self.last {
+            Some((last, last_at)) => {
+                let elapsed = (u32::from(timestamp) + TIMESTAMP_PERIOD - u32::from(last)) % TIMESTAMP_PERIOD;
+                let at = last_at + Duration::from_millis(elapsed.into());
+                // Whole periods may have passed between the frames
+                let period = Duration::from_millis(TIMESTAMP_PERIOD.into());
+                let periods = read.saturating_duration_since(at).as_millis() / period.as_millis();
+                (at + period * periods as u32).min(read)
+            },
+            None => read
+        };
+        self.last = Some((timestamp, at));
+        at
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Reply of the adapter
+enum Reply {
+    /// Command succeeded, with the text of the reply if any
+    Ok(String),
+    /// Command failed (`BEL`)
+    Error,
+    /// Received frame, with the instant it was received
+    Frame(CanFrame, Instant)
+}
+
+#[derive(Debug)]
+/// Serial connection to an adapter
+struct Adapter {
+    serial: SerialPort,
+    buf: Vec<u8>,
+    /// Frames received while waiting for a command reply
+    frames: VecDeque<(CanFrame, Instant)>,
+    timestamps: TimestampSync
+}
+
+impl Adapter {
+    fn open(port: &SlcanPort) -> Result<Self, PduError> {
+        let serial = SerialPort::open(&port.path).map_err(pdu_error)?;
+        serial.set_baudrate(port.baudrate).map_err(|_| PduError::ValueNotSupported)?;
+        Ok(Self { serial, buf: Vec::new(), frames: VecDeque::new(), timestamps: TimestampSync::default() })
+    }
+
+    /// Reads a reply, waiting up to `timeout` for it to complete
+    fn read(&mut self, timeout: Duration) -> Result<Option<Reply>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            while let Some(pos) = self.buf.iter().position(|b| matches!(*b, b'\r' | BEL)) {
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                if line[pos] == BEL {
+                    return Ok(Some(Reply::Error));
+                }
+                let text = String::from_utf8_lossy(&line[..pos]).trim().to_string();
+                match text.bytes().next() {
+                    Some(b't' | b'T') => {
+                        if let Some((frame, timestamp)) = parse_frame(&text) {
+                            let read = Instant::now();
+                            let at = timestamp.map_or(read, |t| self.timestamps.instant(t, read));
+                            return Ok(Some(Reply::Frame(frame, at)));
+                        }
+                    },
+                    // Remote frames can not be represented
+                    Some(b'r' | b'R') => {},
+                    // Acknowledgement of a transmitted frame
+                    Some(b'z' | b'Z') if text.len() == 1 => return Ok(Some(Reply::Ok(String::new()))),
+                    _ => return Ok(Some(Reply::Ok(text)))
+                }
+            }
+            let mut chunk = [0; 256];
+            match self.serial.read(&mut chunk, deadline.saturating_duration_since(Instant::now())).map_err(pdu_error)? {
+                0 => return Ok(None),
+                n => self.buf.extend_from_slice(&chunk[..n])
+            }
+        }
+    }
+
+    /// Sends a command and returns the text of its reply. Fails with [PduError::FctFailed] if
+    /// the adapter refuses the command
+    fn command(&mut self, command: &str) -> Result<String, PduError> {
+        self.serial.write_all(format!("{command}\r").as_bytes()).map_err(pdu_error)?;
+        loop {
+            match self.read(COMMAND_TIMEOUT)? {
+                Some(Reply::Ok(text)) => return Ok(text),
+                Some(Reply::Error) => return Err(PduError::FctFailed),
+                Some(Reply::Frame(frame, at)) => self.frames.push_back((frame, at)),
+                None => return Err(PduError::CommPcToVciFailed)
+            }
+        }
+    }
+
+    /// Closes the CAN channel. Adapters refuse to close a channel which is not open
+    fn close(&mut self) -> Result<(), PduError> {
+        match self.command("C") {
+            Err(PduError::FctFailed) => Ok(()),
+            result => result.map(|_| ())
+        }
+    }
+
+    /// Closes the CAN channel and reads the identification of the adapter
+    fn identify(&mut self) -> Result<Identification, PduError> {
+        self.buf.clear();
+        self.serial.flush_input().map_err(pdu_error)?;
+        let not_slcan = |_| PduError::CableUnknown;
+        self.close().map_err(not_slcan)?;
+        let version = self.command("V").map_err(not_slcan)?;
+        let version = version.strip_prefix('V').filter(|v| !v.is_empty()).ok_or(PduError::CableUnknown)?;
+        let serial = self.command("N").ok().and_then(|s| s.strip_prefix('N').map(str::to_string));
+        Ok(Identification { version: version.to_string(), serial })
+    }
+
+    /// Closes the CAN channel and opens it again at a bit rate
+    fn open_channel(&mut self, bitrate: u32) -> Result<(), PduError> {
+        let command = bitrate_command(bitrate)?;
+        self.close()?;
+        self.frames.clear();
+        self.command(&format!("S{command}")).map_err(|e| match e {
+            PduError::FctFailed => PduError::ValueNotSupported,
+            e => e
+        })?;
+        // Adapters without timestamps refuse the command
+        match self.command("Z1") {
+            Err(PduError::FctFailed) => {},
+            result => result.map(|_| ())?
+        }
+        self.timestamps = TimestampSync::default();
+        self.command("O").map(|_| ())
+    }
+
+    fn transmit(&mut self, frame: &CanFrame) -> Result<(), PduError> {
+        let line = format_frame(frame)?;
+        self.command(&line).map(|_| ())
+    }
+
+    /// Returns the next received frame, waiting up to `timeout` for it
+    fn recv(&mut self, timeout: Duration) -> Result<Option<(CanFrame, Instant)>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                return Ok(Some(frame));
+            }
+            match self.read(deadline.saturating_duration_since(Instant::now()))? {
+                Some(Reply::Frame(frame, at)) => return Ok(Some((frame, at))),
+                // Replies to commands which timed out
+                Some(_) => {},
+                None => return Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns the CAN bit rate of a link
+fn link_bitrate(params: &LinkParams) -> u32 {
+    params.get_u32(StdComParam::Baudrate).filter(|b| *b != 0).unwrap_or(DEFAULT_BITRATE)
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink on an SLCAN adapter
+pub struct SlcanChannel {
+    adapter: Adapter,
+    clock: Clock,
+    bitrate: u32,
+    /// ISO-TP engine, [None] on `ISO_11898_RAW`
+    isotp: Option<IsoTp>,
+    received: VecDeque<ResultEvent>,
+    errors: VecDeque<PduErrorEvt>,
+    tx_result: Option<Result<(), PduErrorEvt>>
+}
+
+impl SlcanChannel {
+    /// Opens a channel on an adapter, opening its CAN channel at `CP_Baudrate`
+    pub fn open(port: &SlcanPort, protocol: Protocol, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let isotp = match protocol {
+            Protocol::Iso11898Raw => None,
+            _ => Some(IsoTp::new(Self::isotp_config(params)?))
+        };
+        let bitrate = link_bitrate(params);
+        let mut adapter = Adapter::open(port)?;
+        adapter.serial.flush_input().map_err(pdu_error)?;
+        adapter.open_channel(bitrate)?;
+        Ok(Self {
+            adapter,
+            clock,
+            bitrate,
+            isotp,
+            received: VecDeque::new(),
+            errors: VecDeque::new(),
+            tx_result: None
+        })
+    }
+
+    fn isotp_config(params: &LinkParams) -> Result<IsoTpConfig, PduError> {
+        let config = IsoTpConfig::from_params(params);
+        match config.is_fd() {
+            true => Err(PduError::ValueNotSupported),
+            false => Ok(config)
+        }
+    }
+
+    /// Transmits the due frames of the ISO-TP engine, then waits up to `timeout` for a received
+    /// frame
+    fn pump(&mut self, timeout: Duration) -> Result<(), PduError> {
+        let Some(engine) = self.isotp.as_mut() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        while let Some(frame) = engine.poll_transmit(now) {
+            match self.adapter.transmit(&frame) {
+                Ok(()) => engine.confirm_transmit(Instant::now()),
+                Err(PduError::CommPcToVciFailed) => return Err(PduError::CommPcToVciFailed),
+                Err(_) => engine.abort_transmit(PduErrorEvt::TxError)
+            }
+        }
+        let wait = engine.next_wake().map_or(timeout, |t| t.saturating_duration_since(now).min(timeout));
+        if let Some((frame, at)) = self.adapter.recv(wait)? {
+            engine.on_frame(&frame, at);
+        }
+        while let Some(event) = engine.poll_event(Instant::now()) {
+            match event {
+                IsoTpEvent::Received { data, start, flags } => self.received.push_back(ResultEvent {
+                    rx_flag: flags.to_flag_bytes(),
+                    start_msg_timestamp: self.clock.at(start),
+                    data,
+                    ..Default::default()
+                }),
+                IsoTpEvent::Sent => self.tx_result = Some(Ok(())),
+                IsoTpEvent::TransmitFailed(code) => self.tx_result = Some(Err(code)),
+                IsoTpEvent::ReceiveFailed(code) => self.errors.push_back(code)
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Channel for SlcanChannel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let bitrate = link_bitrate(params);
+        if bitrate != self.bitrate {
+            self.adapter.open_channel(bitrate)?;
+            self.bitrate = bitrate;
+        }
+        if let Some(engine) = self.isotp.as_mut() {
+            let config = Self::isotp_config(params)?;
+            if *engine.config() != config {
+                engine.set_config(config);
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8], tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        let Some(engine) = self.isotp.as_mut() else {
+            if data.len() < 4 {
+                return Err(PduError::InvalidParameters);
+            }
+            let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let flags = CanFlags::from_flag_bytes(tx_flag);
+            return self.adapter.transmit(&CanFrame { id, flags, data: data[4..].to_vec() });
+        };
+        let functional = params.get_u32(StdComParam::RequestAddrMode) == Some(FUNCTIONAL_ADDR_MODE);
+        self.tx_result = None;
+        engine.send(data, functional, Instant::now())?;
+        let n_bs = engine.config().n_bs;
+        loop {
+            self.pump(n_bs)?;
+            // A failure stays in tx_result for Channel::send_error
+            match self.tx_result {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(_)) => return Err(PduError::FctFailed),
+                None => {}
+            }
+        }
+    }
+
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        match self.tx_result.take() {
+            Some(Err(code)) => code,
+            _ => send_error_event(error)
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        if self.isotp.is_none() {
+            let Some((frame, at)) = self.adapter.recv(timeout)? else {
+                return Ok(None);
+            };
+            let mut data = frame.id.to_be_bytes().to_vec();
+            data.extend(frame.data);
+            return Ok(Some(ResultEvent {
+                rx_flag: frame.flags.to_flag_bytes(),
+                start_msg_timestamp: self.clock.at(at),
+                data,
+                ..Default::default()
+            }));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(msg) = self.received.pop_front() {
+                return Ok(Some(msg));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.pump(deadline - now)?;
+        }
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        self.errors.pop_front()
+    }
+}
+
+impl Drop for SlcanChannel {
+    fn drop(&mut self) {
+        let _ = self.adapter.close();
+    }
+}
+
+#[derive(Debug)]
+/// [Driver] for SLCAN adapters on serial ports
+pub struct Slcan {
+    ports: Vec<SlcanPort>,
+    identifications: Mutex<Vec<Option<Identification>>>
+}
+
+impl Slcan {
+    /// Returns the serial ports, in module order
+    pub fn ports(&self) -> &[SlcanPort] {
+        &self.ports
+    }
+
+    /// Returns the identification of the adapter of a connected module
+    pub fn identification(&self, module: usize) -> Option<Identification> {
+        self.identifications().get(module).cloned().flatten()
+    }
+
+    fn identifications(&self) -> MutexGuard<'_, Vec<Option<Identification>>> {
+        self.identifications.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn port(&self, module: usize) -> Result<&SlcanPort, PduError> {
+        self.ports.get(module).ok_or(PduError::InvalidHandle)
+    }
+}
+
+impl Driver for Slcan {
+    type Channel = SlcanChannel;
+
+    fn open(options: &str) -> Result<Self, PduError> {
+        let ports = options
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(SlcanPort::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let ports = if ports.is_empty() { serial_ports() } else { ports };
+        Ok(Self { identifications: Mutex::new(vec![None; ports.len()]), ports })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        self.ports
+            .iter()
+            .map(|port| DriverModule {
+                module_type_id: MODULE_TYPE_ID,
+                name: port.path.display().to_string(),
+                info: format!("SLCAN adapter on {}", port.path.display()),
+                resources: vec![DriverResource {
+                    bus_type: BusType::Iso11898_2Dwcan,
+                    protocols: PROTOCOLS.to_vec(),
+                    pins: BusType::Iso11898_2Dwcan.default_obd_pins()
+                }]
+            })
+            .collect()
+    }
+
+    fn connect_module(&self, module: usize) -> Result<(), PduError> {
+        let identification = Adapter::open(self.port(module)?).and_then(|mut adapter| adapter.identify());
+        self.identifications()[module] = identification.as_ref().ok().cloned();
+        identification.map(|_| ())
+    }
+
+    fn disconnect_module(&self, module: usize) -> Result<(), PduError> {
+        if let Some(identification) = self.identifications().get_mut(module) {
+            *identification = None;
+        }
+        Ok(())
+    }
+
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        let mut params = vec![
+            (StdComParam::Baudrate, DEFAULT_BITRATE),
+            (StdComParam::CanFillerByte, 0x55),
+            (StdComParam::CanFillerByteHandling, 1)
+        ];
+        if protocol != Protocol::Iso11898Raw {
+            params.extend([
+                (StdComParam::P2Max, DEFAULT_RESPONSE_TIMEOUT.as_micros() as u32),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::CanPhysReqId, 0x7E0),
+                (StdComParam::CanFuncReqId, 0x7DF),
+                (StdComParam::CanRespUsdtId, 0x7E8),
+                (StdComParam::CanPhysReqFormat, 0x05),
+                (StdComParam::CanFuncReqFormat, 0x05),
+                (StdComParam::CanRespUsdtFormat, 0x05),
+                (StdComParam::CanPhysReqExtAddr, 0),
+                (StdComParam::CanFuncReqExtAddr, 0),
+                (StdComParam::CanRespUsdtExtAddr, 0),
+                (StdComParam::BlockSize, 0),
+                (StdComParam::StMin, 0),
+                (StdComParam::BlockSizeOverride, OVERRIDE_DISABLED),
+                (StdComParam::StMinOverride, OVERRIDE_DISABLED),
+                (StdComParam::CanMaxNumWaitFrames, DEFAULT_MAX_WAIT_FRAMES as u32)
+            ]);
+        }
+        params.into_iter().filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v))).collect()
+    }
+
+    fn open_channel(
+        &self,
+        module: usize,
+        _resource: &DriverResource,
+        protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        SlcanChannel::open(self.port(module)?, protocol, params, clock)
+    }
+}
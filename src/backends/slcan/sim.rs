@@ -0,0 +1,352 @@
+//! Emulated SLCAN adapter on a pseudo terminal, for testing without an adapter or CAN bus
+//!
+//! [SlcanSimulator] opens a pseudo terminal, whose [path](SlcanSimulator::path) is used as the
+//! port of the [Slcan](super::Slcan) backend. It answers the `S`, `O`, `C`, `Z`, `V`, `N` and `F`
+//! commands, and passes the frames sent with `t` and `T` to the emulated bus, unless
+//! [SimConfig::refuse_frames] is set. Every other command is refused with `BEL`.
+//!
+//! The bus has [VirtualEcu]s, which each receive ISO-TP requests on their request ID and respond
+//! from their response ID. The functional ID 0x7DF reaches every ECU with an 11 bit request ID.
+//! [SlcanSimulator::send_frame] sends frames from other nodes, and every frame the backend sends
+//! is recorded ([SlcanSimulator::take_frames]). Frames only pass between the adapter and the bus
+//! while the CAN channel is open at the bit rate of [SimConfig::bitrate].
+//!
+//! ```
+//! use std::time::Duration;
+//! use dpdu_rust::{
+//!     backends::slcan::{sim::{SimConfig, SlcanSimulator}, Slcan},
+//!     provider::{Channel, Clock, Driver, LinkParams},
+//!     Protocol
+//! };
+//!
+//! let sim = SlcanSimulator::start(SimConfig::default()).unwrap();
+//! sim.add_ecu(0x7E0, 0x7E8, |request: &[u8]| vec![[&[request[0].wrapping_add(0x40)], &request[1..]].concat()]);
+//!
+//! let driver = Slcan::open(&sim.path().display().to_string()).unwrap();
+//! driver.connect_module(0).unwrap();
+//! assert_eq!(driver.identification(0).unwrap().version, "1013");
+//!
+//! let resource = &driver.modules()[0].resources[0];
+//! let protocol = Protocol::Iso15765_3OnIso15765_2;
+//! let params = LinkParams::new(driver.com_params(protocol));
+//! let mut channel = driver.open_channel(0, resource, protocol, &params, Clock::new()).unwrap();
+//! let request: Vec<u8> = [0x22, 0xF1, 0x90].into_iter().chain(0..20).collect();
+//! channel.send(&request, &[], &params).unwrap();
+//! let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+//! assert_eq!(response.data[0], 0x62);
+//! assert_eq!(response.data[1..], request[1..]);
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+pub use crate::backends::sim::VirtualEcu;
+
+use super::{format_frame, parse_frame, BITRATES, BEL, TIMESTAMP_PERIOD};
+use crate::{
+    backends::serial::{Pty, SerialPort},
+    provider::{CanFrame, IsoTp, IsoTpConfig, IsoTpEvent}
+};
+
+/// How often the simulator thread checks whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How often the simulator thread runs the ECUs while they are busy
+const BUSY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Functional request ID of 11 bit CAN
+const CAN_FUNCTIONAL_ID: u32 = 0x7DF;
+/// Filler byte of the frames of the ECUs
+const CAN_FILLER: u8 = 0x55;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Configuration of an [SlcanSimulator]
+pub struct SimConfig {
+    /// Version digits sent by `V`
+    pub version: &'static str,
+    /// Serial number sent by `N`
+    pub serial: &'static str,
+    /// Bit rate of the bus
+    pub bitrate: u32,
+    /// The adapter supports timestamps (`Z`)
+    pub timestamps: bool,
+    /// Timestamp of the adapter when the simulator starts, in milliseconds
+    pub timestamp_start: u16,
+    /// Refuse frames sent with `t` and `T` (`BEL`), like an adapter which can not send on the bus
+    pub refuse_frames: bool
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self { version: "1013", serial: "A001", bitrate: 500_000, timestamps: true, timestamp_start: 0, refuse_frames: false }
+    }
+}
+
+/// Virtual ECU with its ISO-TP engine
+struct SimEcu {
+    request_id: u32,
+    engine: IsoTp,
+    ecu: Box<dyn VirtualEcu>,
+    /// Responses waiting for the engine to finish the previous one
+    responses: VecDeque<Vec<u8>>
+}
+
+struct SimState {
+    config: SimConfig,
+    ecus: BTreeMap<u32, SimEcu>,
+    /// Bit rate selected with `S`
+    bitrate: Option<u32>,
+    open: bool,
+    timestamps: bool,
+    start: Instant,
+    /// Frames sent by the backend
+    frames: Vec<CanFrame>,
+    /// Frames to send to the backend
+    outgoing: VecDeque<CanFrame>
+}
+
+impl fmt::Debug for SimState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimState")
+            .field("config", &self.config)
+            .field("ecus", &self.ecus.keys().collect::<Vec<_>>())
+            .field("bitrate", &self.bitrate)
+            .field("open", &self.open)
+            .finish()
+    }
+}
+
+impl SimState {
+    /// Returns true if frames pass between the adapter and the bus
+    fn on_bus(&self) -> bool {
+        self.open && self.bitrate == Some(self.config.bitrate)
+    }
+
+    /// Answers a command
+    fn command(&mut self, command: &str) -> Result<String, ()> {
+        let closed = !self.open;
+        match command.as_bytes() {
+            [b'S', n] if closed => {
+                self.bitrate = Some(*BITRATES.get(n.wrapping_sub(b'0') as usize).ok_or(())?);
+                Ok(String::new())
+            },
+            [b'Z', on @ (b'0' | b'1')] if closed && self.config.timestamps => {
+                self.timestamps = *on == b'1';
+                Ok(String::new())
+            },
+            b"O" if closed && self.bitrate.is_some() => {
+                self.open = true;
+                Ok(String::new())
+            },
+            b"C" if !closed => {
+                self.open = false;
+                Ok(String::new())
+            },
+            b"V" => Ok(format!("V{}", self.config.version)),
+            b"N" => Ok(format!("N{}", self.config.serial)),
+            b"F" => Ok("F00".to_string()),
+            [b't' | b'T', ..] if !closed && !self.config.refuse_frames => {
+                let (frame, None) = parse_frame(command).ok_or(())? else {
+                    return Err(());
+                };
+                let ack = if command.starts_with('t') { "z" } else { "Z" };
+                if self.on_bus() {
+                    self.deliver(&frame);
+                    self.frames.push(frame);
+                }
+                Ok(ack.to_string())
+            },
+            _ => Err(())
+        }
+    }
+
+    /// Passes a frame sent by the backend to the ECUs
+    fn deliver(&mut self, frame: &CanFrame) {
+        let now = Instant::now();
+        for ecu in self.ecus.values_mut() {
+            if frame.id == CAN_FUNCTIONAL_ID && ecu.request_id <= 0x7FF {
+                ecu.engine.on_frame(&CanFrame { id: ecu.request_id, ..frame.clone() }, now);
+            } else {
+                ecu.engine.on_frame(frame, now);
+            }
+        }
+    }
+
+    /// Runs the ECUs, returning the frames they send
+    fn run_ecus(&mut self) -> Vec<CanFrame> {
+        let now = Instant::now();
+        let mut frames = Vec::new();
+        for ecu in self.ecus.values_mut() {
+            while let Some(event) = ecu.engine.poll_event(now) {
+                if let IsoTpEvent::Received { data, .. } = event {
+                    ecu.responses.extend(ecu.ecu.handle(&data).into_iter().filter(|r| !r.is_empty()));
+                }
+            }
+            if !ecu.engine.is_sending() {
+                if let Some(response) = ecu.responses.pop_front() {
+                    let _ = ecu.engine.send(&response, false, now);
+                }
+            }
+            frames.extend(std::iter::from_fn(|| {
+                let frame = ecu.engine.poll_transmit(now)?;
+                ecu.engine.confirm_transmit(now);
+                Some(frame)
+            }));
+        }
+        frames
+    }
+
+    /// Returns true while an ECU is sending or receiving
+    fn busy(&self) -> bool {
+        self.ecus.values().any(|ecu| !ecu.engine.is_idle() || !ecu.responses.is_empty())
+    }
+
+    /// Formats a frame sent to the backend, with a timestamp if they are enabled
+    fn format(&self, frame: &CanFrame) -> Option<String> {
+        let mut line = format_frame(frame).ok()?;
+        if self.timestamps {
+            let ms = (u128::from(self.config.timestamp_start) + self.start.elapsed().as_millis()) % u128::from(TIMESTAMP_PERIOD);
+            line.push_str(&format!("{ms:04X}"));
+        }
+        Some(line)
+    }
+}
+
+#[derive(Debug)]
+/// Emulated SLCAN adapter with virtual ECUs, which runs until it is dropped
+pub struct SlcanSimulator {
+    path: PathBuf,
+    state: Arc<Mutex<SimState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl SlcanSimulator {
+    /// Starts a simulator on a new pseudo terminal
+    pub fn start(config: SimConfig) -> io::Result<Self> {
+        let pty = Pty::open()?;
+        let path = pty.path().to_owned();
+        let state = Arc::new(Mutex::new(SimState {
+            config,
+            ecus: BTreeMap::new(),
+            bitrate: None,
+            open: false,
+            timestamps: false,
+            start: Instant::now(),
+            frames: Vec::new(),
+            outgoing: VecDeque::new()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new().name("slcan-sim".into()).spawn({
+            let (state, stop) = (state.clone(), stop.clone());
+            move || run(pty.master(), &state, &stop)
+        })?;
+        Ok(Self { path, state, stop, thread: Some(thread) })
+    }
+
+    /// Returns the path of the serial port the backend opens
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        lock(&self.state)
+    }
+
+    /// Adds a virtual ECU which receives requests on `request_id` and responds from
+    /// `response_id`, replacing any ECU with the same request ID
+    pub fn add_ecu(&self, request_id: u32, response_id: u32, ecu: impl VirtualEcu) {
+        let config = IsoTpConfig { tx_id: response_id, rx_id: request_id, padding: Some(CAN_FILLER), ..Default::default() };
+        let ecu = SimEcu { request_id, engine: IsoTp::new(config), ecu: Box::new(ecu), responses: VecDeque::new() };
+        self.state().ecus.insert(request_id, ecu);
+    }
+
+    /// Removes a virtual ECU
+    pub fn remove_ecu(&self, request_id: u32) {
+        self.state().ecus.remove(&request_id);
+    }
+
+    /// Changes the configuration
+    pub fn set_config(&self, config: SimConfig) {
+        self.state().config = config;
+    }
+
+    /// Sends a frame to the backend, as if another node of the bus sent it. Frames sent while
+    /// the CAN channel is closed are lost
+    pub fn send_frame(&self, frame: CanFrame) {
+        let mut state = self.state();
+        if state.on_bus() {
+            state.outgoing.push_back(frame);
+        }
+    }
+
+    /// Returns the frames the backend sent on the bus since the last call
+    pub fn take_frames(&self) -> Vec<CanFrame> {
+        std::mem::take(&mut self.state().frames)
+    }
+
+    /// Returns the bit rate of the CAN channel while it is open
+    pub fn bitrate(&self) -> Option<u32> {
+        let state = self.state();
+        state.bitrate.filter(|_| state.open)
+    }
+}
+
+impl Drop for SlcanSimulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn lock(state: &Mutex<SimState>) -> MutexGuard<'_, SimState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn run(port: &SerialPort, state: &Mutex<SimState>, stop: &AtomicBool) {
+    let mut line = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        let interval = if lock(state).busy() { BUSY_INTERVAL } else { POLL_INTERVAL };
+        match port.read_byte(interval) {
+            Ok(Some(b'\r')) => {
+                let command = String::from_utf8_lossy(&line).into_owned();
+                line.clear();
+                let reply = match lock(state).command(&command) {
+                    Ok(text) => format!("{text}\r").into_bytes(),
+                    Err(()) => vec![BEL]
+                };
+                let _ = port.write_all(&reply);
+            },
+            Ok(Some(b'\n')) | Ok(None) => {},
+            Ok(Some(b)) => line.push(b),
+            Err(_) => thread::sleep(POLL_INTERVAL)
+        }
+        let _ = send_frames(port, state);
+    }
+}
+
+/// Sends the frames of the ECUs and other nodes to the backend
+fn send_frames(port: &SerialPort, state: &Mutex<SimState>) -> io::Result<()> {
+    let mut guard = lock(state);
+    let mut frames: Vec<CanFrame> = guard.outgoing.drain(..).collect();
+    frames.extend(guard.run_ecus());
+    if !guard.on_bus() {
+        return Ok(());
+    }
+    let lines: Vec<String> = frames.iter().filter_map(|f| guard.format(f)).collect();
+    drop(guard);
+    for l in lines {
+        port.write_all(format!("{l}\r").as_bytes())?;
+    }
+    Ok(())
+}
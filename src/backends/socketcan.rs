@@ -0,0 +1,1153 @@
+//! SocketCAN backend (Linux)
+//!
+//! Every CAN network interface of the system, including `vcan` interfaces, is a module with one
+//! `ISO_11898_2_DWCAN` resource on pins 6 and 14 of the J1962 connector. The resource runs:
+//! * `ISO_11898_RAW` - Raw CAN frames. Messages are a 4 byte big endian CAN ID followed by up to
+//!   8 data bytes, or up to 64 for CAN FD frames. Bit 31 of the ID marks a 29 bit ID, which is
+//!   implied for IDs above 0x7FF. The frame format is set by the [CanFlags] of the TxFlag data,
+//!   and reported in the RxFlag data of results
+//! * `ISO_15765_3_on_ISO_15765_2`, `ISO_14230_3_on_ISO_15765_2` and `ISO_OBD_on_ISO_15765_4` -
+//!   ISO-TP using the kernel's `can-isotp` sockets. Physical requests are sent to
+//!   `CP_CanPhysReqId`, functional requests (`CP_RequestAddrMode` = 2) are sent as single frames
+//!   to `CP_CanFuncReqId`, and responses are received from `CP_CanRespUSDTId`. The userspace
+//!   [IsoTp](crate::provider::IsoTp) engine is used instead if the kernel has no `can-isotp`
+//!   support, `CP_BlockSizeOverride` is set, or `CP_As`, `CP_Bs` or `CP_Cr` differ from the
+//!   fixed one second timeouts of the kernel. The kernel accepts any number of wait flow
+//!   control frames, `CP_CanMaxNumWaitFrames` only applies to the userspace engine. Messages
+//!   received by the kernel are timestamped when they are read, as the kernel does not report
+//!   when their first frame arrived. `CP_CanFDTxMaxDataLength` above 8 sends CAN FD frames,
+//!   with bit rate switching if `CP_CanFDBitRateSwitch` is 1
+//! * `SAE_J1939_73_on_SAE_J1939_21` - J1939 using the userspace [J1939](crate::provider::J1939)
+//!   engine. The link claims `CP_J1939PreferredAddress` with the NAME `CP_J1939Name` when it is
+//!   connected, and fails
+//!   with [PduError::ResourceBusy] if a node with a lower NAME holds the address and no other
+//!   address can be claimed (`CP_J1939AddrClaim`). Messages are the PGN (3 bytes big endian)
+//!   and the destination address followed by the data, which is sent with BAM or RTS/CTS above
+//!   8 bytes. Results carry the data, with the PGN, source and destination address in the
+//!   ExtraInfo header, which message filters compare ahead of the data. Losing the address
+//!   later is reported as [PduErrorEvt::InitError]
+//!
+//! `CP_Baudrate` and `CP_CanFDBaudrate` are applied to the interface over netlink when they differ
+//! from the bit rates the interface is configured with, which requires `CAP_NET_ADMIN`. Virtual
+//! interfaces accept any bit rate. Received messages are timestamped with the [Clock] of the backend,
+//! from the time the kernel received their first frame (`SO_TIMESTAMP`).
+//!
+//! CAN FD frames can only be used on interfaces with a CAN FD MTU. A virtual CAN FD interface is
+//! created with:
+//! ```text
+//! ip link add dev vcan0 type vcan
+//! ip link set vcan0 mtu 72 up
+//! ```
+//!
+//! J1939 can be tested on a `vcan` interface with the `j1939cat` and `testj1939` tools of
+//! can-utils, or with `cangen vcan0 -e` for raw 29 bit traffic.
+//!
+//! ```ignore
+//! dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<dpdu_rust::backends::socketcan::SocketCan>);
+//! ```
+
+use std::{
+    ffi::{c_int, CString},
+    collections::VecDeque,
+    fs, io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{
+    provider::{
+        encode_st_min, send_error_event, CanFrame, Channel, Clock, Driver, DriverModule, DriverResource, ExtraInfoData,
+        IsoTp, IsoTpConfig, IsoTpEvent, J1939Config, J1939Event, LinkParams, ResultEvent, DEFAULT_MAX_WAIT_FRAMES,
+        DEFAULT_RESPONSE_TIMEOUT, DEFAULT_TESTER_NAME, J1939, OVERRIDE_DISABLED
+    },
+    is_canfd_len, BusType, CanFlags, ComParamValue, PduError, PduErrorEvt, Protocol, StdComParam, CANFD_MAX_DLEN,
+    CAN_MAX_DLEN
+};
+
+/// Module type ID of SocketCAN interfaces (`ARPHRD_CAN`)
+pub const MODULE_TYPE_ID: u32 = 280;
+
+/// Protocols of the CAN resource
+const PROTOCOLS: &[Protocol] = &[
+    Protocol::Iso11898Raw,
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4,
+    Protocol::SaeJ1939_73OnSaeJ1939_21
+];
+
+// linux/can.h
+const CAN_RAW: c_int = 1;
+const CAN_ISOTP: c_int = 6;
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_SFF_MASK: u32 = 0x0000_07FF;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+const CANFD_FDF: u8 = 0x04;
+/// Size of `struct can_frame`
+const CAN_MTU: usize = 16;
+/// Size of `struct canfd_frame`, and MTU of CAN FD capable interfaces
+const CANFD_MTU: usize = 72;
+
+// linux/can/raw.h
+const SOL_CAN_RAW: c_int = 100 + CAN_RAW;
+const CAN_RAW_FILTER: c_int = 1;
+const CAN_RAW_FD_FRAMES: c_int = 5;
+/// Socket option value enabling a boolean option
+const ENABLE: c_int = 1;
+
+// linux/can/isotp.h
+const SOL_CAN_ISOTP: c_int = 100 + CAN_ISOTP;
+const CAN_ISOTP_OPTS: c_int = 1;
+const CAN_ISOTP_RECV_FC: c_int = 2;
+const CAN_ISOTP_EXTEND_ADDR: u32 = 0x002;
+const CAN_ISOTP_TX_PADDING: u32 = 0x004;
+const CAN_ISOTP_FORCE_TXSTMIN: u32 = 0x100;
+const CAN_ISOTP_RX_EXT_ADDR: u32 = 0x200;
+const CAN_ISOTP_TX_STMIN: c_int = 3;
+const CAN_ISOTP_LL_OPTS: c_int = 5;
+
+/// Largest message of kernel ISO-TP sockets
+const ISOTP_MAX_LEN: usize = 4095;
+
+/// N_As, N_Bs and N_Cr of kernel ISO-TP sockets, which can not be configured
+const KERNEL_ISOTP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `CP_RequestAddrMode` value of functional requests
+const FUNCTIONAL_ADDR_MODE: u32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SockaddrCan {
+    can_family: libc::sa_family_t,
+    can_ifindex: c_int,
+    rx_id: u32,
+    tx_id: u32,
+    _j1939: u64
+}
+
+/// `struct canfd_frame`. The first [CAN_MTU] bytes are a `struct can_frame`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LinuxCanFrame {
+    can_id: u32,
+    len: u8,
+    flags: u8,
+    _res0: u8,
+    _res1: u8,
+    data: [u8; CANFD_MAX_DLEN]
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct IsoTpOptions {
+    flags: u32,
+    frame_txtime: u32,
+    ext_address: u8,
+    txpad_content: u8,
+    rxpad_content: u8,
+    rx_ext_address: u8
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct IsoTpFcOptions {
+    bs: u8,
+    stmin: u8,
+    wftmax: u8
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct IsoTpLlOptions {
+    mtu: u8,
+    tx_dl: u8,
+    tx_flags: u8
+}
+
+fn cvt(ret: c_int) -> io::Result<c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn cvt_size(ret: isize) -> io::Result<usize> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Converts an I/O error of an interface
+fn pdu_error(e: io::Error) -> PduError {
+    match e.raw_os_error() {
+        Some(libc::ENODEV | libc::ENETDOWN | libc::ENXIO) => PduError::CommPcToVciFailed,
+        _ => PduError::FctFailed
+    }
+}
+
+fn open_socket(domain: c_int, ty: c_int, protocol: c_int) -> io::Result<OwnedFd> {
+    // Safety: The returned descriptor is owned by nothing else
+    unsafe { cvt(libc::socket(domain, ty | libc::SOCK_CLOEXEC, protocol)).map(|fd| OwnedFd::from_raw_fd(fd)) }
+}
+
+fn set_option<T>(fd: &OwnedFd, level: c_int, name: c_int, value: &T) -> io::Result<()> {
+    // Safety: The option value is valid for its size
+    cvt(unsafe {
+        libc::setsockopt(fd.as_raw_fd(), level, name, ptr::from_ref(value).cast(), size_of::<T>() as libc::socklen_t)
+    })
+    .map(|_| ())
+}
+
+/// Opens a CAN socket bound to an interface. The IDs are only used by ISO-TP sockets
+fn can_socket(ty: c_int, protocol: c_int, ifindex: c_int, rx_id: u32, tx_id: u32, setup: impl FnOnce(&OwnedFd) -> io::Result<()>) -> io::Result<OwnedFd> {
+    let fd = open_socket(libc::PF_CAN, ty, protocol)?;
+    setup(&fd)?;
+    let addr = SockaddrCan { can_family: libc::AF_CAN as libc::sa_family_t, can_ifindex: ifindex, rx_id, tx_id, _j1939: 0 };
+    // Safety: The address is a valid sockaddr_can
+    cvt(unsafe {
+        libc::bind(fd.as_raw_fd(), ptr::from_ref(&addr).cast(), size_of::<SockaddrCan>() as libc::socklen_t)
+    })?;
+    Ok(fd)
+}
+
+/// Waits for a socket to become readable. Returns false on timeout
+fn wait_readable(fd: &OwnedFd, timeout: Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let ms = timeout.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int;
+    // Safety: Exactly one pollfd is passed
+    match cvt(unsafe { libc::poll(&mut pfd, 1, ms) }) {
+        Ok(n) => Ok(n > 0),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(false),
+        Err(e) => Err(e)
+    }
+}
+
+fn read_fd(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    // Safety: The buffer is valid for its length
+    cvt_size(unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) })
+}
+
+fn write_fd(fd: &OwnedFd, buf: &[u8]) -> io::Result<()> {
+    // Safety: The buffer is valid for its length
+    let n = cvt_size(unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) })?;
+    if n != buf.len() {
+        return Err(io::ErrorKind::WriteZero.into());
+    }
+    Ok(())
+}
+
+/// Writes a frame, as a `struct canfd_frame` if it is a CAN FD frame
+fn write_frame(fd: &OwnedFd, frame: &LinuxCanFrame) -> io::Result<()> {
+    let len = if frame.flags & CANFD_FDF != 0 { CANFD_MTU } else { CAN_MTU };
+    // Safety: LinuxCanFrame is plain data, and at least `len` bytes long
+    let bytes = unsafe { std::slice::from_raw_parts(ptr::from_ref(frame).cast::<u8>(), len) };
+    write_fd(fd, bytes)
+}
+
+/// Reads a classic CAN frame, or a CAN FD frame if the socket has CAN FD frames enabled, with
+/// the time the interface received it. Frames without a `SO_TIMESTAMP` timestamp were received now
+fn read_frame(fd: &OwnedFd) -> io::Result<Option<(LinuxCanFrame, Instant)>> {
+    let mut frame = LinuxCanFrame::zeroed();
+    let mut iov = libc::iovec { iov_base: ptr::from_mut(&mut frame).cast(), iov_len: size_of::<LinuxCanFrame>() };
+    // Room for one timeval control message, aligned for cmsghdr
+    let mut control = [0u64; 8];
+    // Safety: msghdr is plain data, valid when zeroed
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    // The length is not a usize with every C library
+    #[allow(trivial_numeric_casts)]
+    {
+        msg.msg_controllen = size_of_val(&control) as _;
+    }
+    // Safety: The buffers of the message are valid for their lengths
+    let n = cvt_size(unsafe { libc::recvmsg(fd.as_raw_fd(), &mut msg, 0) })?;
+    let mut timestamp = None;
+    // Safety: The control messages were written by the kernel, within the control buffer
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SO_TIMESTAMP {
+                let tv = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::timeval>());
+                timestamp = Some(UNIX_EPOCH + Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    let received = received_at(timestamp);
+    Ok(match n {
+        CAN_MTU => {
+            frame.flags = 0;
+            Some((frame, received))
+        },
+        CANFD_MTU => {
+            frame.flags |= CANFD_FDF;
+            Some((frame, received))
+        },
+        _ => None
+    })
+}
+
+/// Converts a kernel receive timestamp, which is wall clock time, to an instant
+fn received_at(timestamp: Option<SystemTime>) -> Instant {
+    let now = Instant::now();
+    timestamp
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .and_then(|age| now.checked_sub(age))
+        .unwrap_or(now)
+}
+
+/// Converts a message CAN ID to a SocketCAN ID
+fn to_can_id(id: u32) -> u32 {
+    if id & CAN_EFF_FLAG != 0 || id & CAN_EFF_MASK > CAN_SFF_MASK {
+        (id & CAN_EFF_MASK) | CAN_EFF_FLAG
+    } else {
+        id & CAN_SFF_MASK
+    }
+}
+
+/// Converts a SocketCAN ID to a message CAN ID
+fn from_can_id(can_id: u32) -> u32 {
+    if can_id & CAN_EFF_FLAG != 0 {
+        (can_id & CAN_EFF_MASK) | CAN_EFF_FLAG
+    } else {
+        can_id & CAN_SFF_MASK
+    }
+}
+
+impl LinuxCanFrame {
+    fn zeroed() -> Self {
+        Self { can_id: 0, len: 0, flags: 0, _res0: 0, _res1: 0, data: [0; CANFD_MAX_DLEN] }
+    }
+
+    /// Converts a frame to transmit. The data length must be valid for the frame format
+    fn new(frame: &CanFrame) -> Result<Self, PduError> {
+        let len = frame.data.len();
+        let max = if frame.flags.fd { CANFD_MAX_DLEN } else { CAN_MAX_DLEN };
+        if len > max || !is_canfd_len(len) {
+            return Err(PduError::InvalidParameters);
+        }
+        let mut raw = Self { can_id: to_can_id(frame.id), len: len as u8, ..Self::zeroed() };
+        if frame.flags.fd {
+            raw.flags = CANFD_FDF | if frame.flags.brs { CANFD_BRS } else { 0 };
+        }
+        raw.data[..len].copy_from_slice(&frame.data);
+        Ok(raw)
+    }
+
+    /// Converts a received data frame. Error and remote frames are discarded
+    fn to_frame(self) -> Option<CanFrame> {
+        if self.can_id & (CAN_ERR_FLAG | CAN_RTR_FLAG) != 0 {
+            return None;
+        }
+        let flags = CanFlags {
+            fd: self.flags & CANFD_FDF != 0,
+            brs: self.flags & CANFD_BRS != 0,
+            esi: self.flags & CANFD_ESI != 0
+        };
+        let len = (self.len as usize).min(if flags.fd { CANFD_MAX_DLEN } else { CAN_MAX_DLEN });
+        Some(CanFrame { id: from_can_id(self.can_id), flags, data: self.data[..len].to_vec() })
+    }
+}
+
+/// Returns the names of all CAN network interfaces
+fn can_interfaces() -> Vec<String> {
+    let Ok(dir) = fs::read_dir("/sys/class/net") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = dir
+        .filter_map(|e| e.ok())
+        .filter(|e| fs::read_to_string(e.path().join("type")).is_ok_and(|t| t.trim() == MODULE_TYPE_ID.to_string()))
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns true if an interface is configured for CAN FD frames
+fn is_fd_interface(name: &str) -> bool {
+    fs::read_to_string(format!("/sys/class/net/{name}/mtu")).is_ok_and(|mtu| mtu.trim() == CANFD_MTU.to_string())
+}
+
+fn interface_index(name: &str) -> Result<c_int, PduError> {
+    let name = CString::new(name).map_err(|_| PduError::InvalidParameters)?;
+    // Safety: The name is nul terminated
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(PduError::CommPcToVciFailed),
+        x => Ok(x as c_int)
+    }
+}
+
+/// Reading and setting the bit rate of CAN interfaces over rtnetlink
+mod netlink {
+    use super::*;
+
+    const NLMSG_HDRLEN: usize = 16;
+    const IFINFOMSG_LEN: usize = 16;
+    const NLMSG_ERROR: u16 = 2;
+    const NLM_F_REQUEST: u16 = 0x1;
+    const NLM_F_ACK: u16 = 0x4;
+    const RTM_NEWLINK: u16 = 16;
+    const RTM_GETLINK: u16 = 18;
+    const IFLA_LINKINFO: u16 = 18;
+    const IFLA_INFO_KIND: u16 = 1;
+    const IFLA_INFO_DATA: u16 = 2;
+    const IFLA_CAN_BITTIMING: u16 = 1;
+    const IFLA_CAN_CTRLMODE: u16 = 5;
+    const IFLA_CAN_DATA_BITTIMING: u16 = 9;
+    const CAN_CTRLMODE_FD: u32 = 0x20;
+    /// Size of `struct can_bittiming`
+    const CAN_BITTIMING_LEN: usize = 32;
+
+    fn align(len: usize) -> usize {
+        (len + 3) & !3
+    }
+
+    fn attr(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(align(4 + payload.len()));
+        buf.extend(((4 + payload.len()) as u16).to_ne_bytes());
+        buf.extend(kind.to_ne_bytes());
+        buf.extend(payload);
+        buf.resize(align(buf.len()), 0);
+        buf
+    }
+
+    fn find_attr(mut buf: &[u8], kind: u16) -> Option<&[u8]> {
+        while buf.len() >= 4 {
+            let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+            let ty = u16::from_ne_bytes([buf[2], buf[3]]) & 0x3FFF;
+            if len < 4 || len > buf.len() {
+                return None;
+            }
+            if ty == kind {
+                return Some(&buf[4..len]);
+            }
+            buf = &buf[align(len).min(buf.len())..];
+        }
+        None
+    }
+
+    fn message(msg_type: u16, flags: u16, ifindex: c_int, if_flags: u32, if_change: u32, attrs: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(((NLMSG_HDRLEN + IFINFOMSG_LEN + attrs.len()) as u32).to_ne_bytes());
+        buf.extend(msg_type.to_ne_bytes());
+        buf.extend((flags | NLM_F_REQUEST).to_ne_bytes());
+        buf.extend(1u32.to_ne_bytes());
+        buf.extend(0u32.to_ne_bytes());
+        buf.extend([libc::AF_UNSPEC as u8, 0, 0, 0]);
+        buf.extend(ifindex.to_ne_bytes());
+        buf.extend(if_flags.to_ne_bytes());
+        buf.extend(if_change.to_ne_bytes());
+        buf.extend(attrs);
+        buf
+    }
+
+    /// Sends a request to the kernel, returning the first response message
+    fn request(msg: &[u8]) -> io::Result<Vec<u8>> {
+        let fd = open_socket(libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_ROUTE)?;
+        // Safety: The buffer is valid for its length
+        cvt_size(unsafe { libc::send(fd.as_raw_fd(), msg.as_ptr().cast(), msg.len(), 0) })?;
+        let mut buf = vec![0; 32768];
+        let n = read_fd(&fd, &mut buf)?;
+        buf.truncate(n);
+        if buf.len() < NLMSG_HDRLEN {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let len = (u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize).min(buf.len());
+        buf.truncate(len);
+        if u16::from_ne_bytes([buf[4], buf[5]]) == NLMSG_ERROR {
+            let code = buf
+                .get(NLMSG_HDRLEN..NLMSG_HDRLEN + 4)
+                .map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or(io::ErrorKind::InvalidData)?;
+            if code != 0 {
+                return Err(io::Error::from_raw_os_error(-code));
+            }
+        }
+        Ok(buf)
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    /// Bit rates of a CAN interface
+    pub struct Bitrates {
+        /// Bit rate, or the arbitration phase bit rate of CAN FD frames
+        pub bitrate: u32,
+        /// Data phase bit rate of CAN FD frames. 0 if CAN FD is not enabled
+        pub data_bitrate: u32
+    }
+
+    fn bittiming(bitrate: u32) -> [u8; CAN_BITTIMING_LEN] {
+        let mut timing = [0; CAN_BITTIMING_LEN];
+        timing[..4].copy_from_slice(&bitrate.to_ne_bytes());
+        timing
+    }
+
+    /// Returns the bit rates of a CAN interface, or [None] if the interface has no bit timing
+    /// (Virtual interfaces). An unconfigured interface has a bit rate of 0
+    pub fn bitrates(ifindex: c_int) -> io::Result<Option<Bitrates>> {
+        let resp = request(&message(RTM_GETLINK, 0, ifindex, 0, 0, &[]))?;
+        let attrs = resp.get(NLMSG_HDRLEN + IFINFOMSG_LEN..).unwrap_or_default();
+        let Some(info) = find_attr(attrs, IFLA_LINKINFO) else {
+            return Ok(None);
+        };
+        if find_attr(info, IFLA_INFO_KIND).is_none_or(|k| k.split(|b| *b == 0).next() != Some(b"can")) {
+            return Ok(None);
+        }
+        let data = find_attr(info, IFLA_INFO_DATA).unwrap_or_default();
+        let rate = |kind: u16| {
+            find_attr(data, kind)
+                .and_then(|t| t.get(..4))
+                .map_or(0, |b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        Ok(Some(Bitrates { bitrate: rate(IFLA_CAN_BITTIMING), data_bitrate: rate(IFLA_CAN_DATA_BITTIMING) }))
+    }
+
+    /// Sets the bit rates of a CAN interface, enabling CAN FD if a data phase bit rate is set.
+    /// The interface is taken down while the bit rates are changed, and brought up again
+    /// afterwards
+    pub fn set_bitrates(ifindex: c_int, rates: Bitrates) -> io::Result<()> {
+        let up = libc::IFF_UP as u32;
+        request(&message(RTM_NEWLINK, NLM_F_ACK, ifindex, 0, up, &[]))?;
+        let mut data = attr(IFLA_CAN_BITTIMING, &bittiming(rates.bitrate));
+        if rates.data_bitrate != 0 {
+            let ctrlmode = [CAN_CTRLMODE_FD.to_ne_bytes(), CAN_CTRLMODE_FD.to_ne_bytes()].concat();
+            data.extend(attr(IFLA_CAN_CTRLMODE, &ctrlmode));
+            data.extend(attr(IFLA_CAN_DATA_BITTIMING, &bittiming(rates.data_bitrate)));
+        }
+        let info = [attr(IFLA_INFO_KIND, b"can"), attr(IFLA_INFO_DATA, &data)].concat();
+        let result = request(&message(RTM_NEWLINK, NLM_F_ACK, ifindex, 0, 0, &attr(IFLA_LINKINFO, &info)));
+        request(&message(RTM_NEWLINK, NLM_F_ACK, ifindex, up, up, &[]))?;
+        result.map(|_| ())
+    }
+}
+
+/// Opens a kernel ISO-TP socket. CAN FD frames are received if `fd` is set, and sent if
+/// [IsoTpConfig::tx_dl] is above 8
+fn kernel_isotp_socket(config: &IsoTpConfig, ifindex: c_int, fd: bool) -> io::Result<OwnedFd> {
+    let mut opts = IsoTpOptions::default();
+    if let Some(ext) = config.tx_ext_addr {
+        opts.flags |= CAN_ISOTP_EXTEND_ADDR;
+        opts.ext_address = ext;
+    }
+    if let Some(ext) = config.rx_ext_addr {
+        opts.flags |= CAN_ISOTP_RX_EXT_ADDR;
+        opts.rx_ext_address = ext;
+    }
+    if let Some(pad) = config.padding {
+        opts.flags |= CAN_ISOTP_TX_PADDING;
+        opts.txpad_content = pad;
+    }
+    if config.st_min_override.is_some() {
+        opts.flags |= CAN_ISOTP_FORCE_TXSTMIN;
+    }
+    let fc = IsoTpFcOptions { bs: config.block_size, stmin: encode_st_min(config.st_min), wftmax: 0 };
+    let ll = IsoTpLlOptions {
+        mtu: CANFD_MTU as u8,
+        tx_dl: config.tx_dl as u8,
+        tx_flags: if config.brs { CANFD_BRS } else { 0 }
+    };
+    can_socket(libc::SOCK_DGRAM, CAN_ISOTP, ifindex, to_can_id(config.rx_id), to_can_id(config.tx_id), |s| {
+        set_option(s, SOL_CAN_ISOTP, CAN_ISOTP_OPTS, &opts)?;
+        set_option(s, SOL_CAN_ISOTP, CAN_ISOTP_RECV_FC, &fc)?;
+        if let Some(st_min) = config.st_min_override {
+            set_option(s, SOL_CAN_ISOTP, CAN_ISOTP_TX_STMIN, &(st_min.as_nanos().min(u32::MAX as u128) as u32))?;
+        }
+        if fd {
+            set_option(s, SOL_CAN_ISOTP, CAN_ISOTP_LL_OPTS, &ll)?;
+        }
+        Ok(())
+    })
+}
+
+/// Sets the options of raw sockets. Received frames are timestamped by the kernel, and CAN FD
+/// frames are received if `fd` is set
+fn raw_options(s: &OwnedFd, fd: bool) -> io::Result<()> {
+    set_option(s, libc::SOL_SOCKET, libc::SO_TIMESTAMP, &ENABLE)?;
+    match fd {
+        true => set_option(s, SOL_CAN_RAW, CAN_RAW_FD_FRAMES, &ENABLE),
+        false => Ok(())
+    }
+}
+
+/// Opens a raw socket, receiving CAN FD frames if `fd` is set
+fn raw_socket(ifindex: c_int, fd: bool) -> io::Result<OwnedFd> {
+    can_socket(libc::SOCK_RAW, CAN_RAW, ifindex, 0, 0, |s| raw_options(s, fd))
+}
+
+/// Opens a raw socket which only receives frames with one CAN ID
+fn filtered_raw_socket(ifindex: c_int, id: u32, fd: bool) -> io::Result<OwnedFd> {
+    let can_id = to_can_id(id);
+    let mask = if can_id & CAN_EFF_FLAG != 0 { CAN_EFF_MASK } else { CAN_SFF_MASK };
+    let filter = libc::can_filter { can_id, can_mask: mask | CAN_EFF_FLAG | CAN_RTR_FLAG };
+    can_socket(libc::SOCK_RAW, CAN_RAW, ifindex, 0, 0, |s| {
+        set_option(s, SOL_CAN_RAW, CAN_RAW_FILTER, &filter)?;
+        raw_options(s, fd)
+    })
+}
+
+/// Converts a receive error of a kernel ISO-TP socket to the error event it is reported as
+fn isotp_error_event(e: &io::Error) -> Option<PduErrorEvt> {
+    match e.raw_os_error() {
+        Some(libc::ETIMEDOUT) => Some(PduErrorEvt::RxTimeout),
+        Some(libc::EILSEQ | libc::EBADMSG) => Some(PduErrorEvt::FrameStruct),
+        _ => None
+    }
+}
+
+/// Returns true if a kernel ISO-TP socket behaves as configured. The kernel has no block size
+/// override, and fixed timeouts
+fn kernel_supports(config: &IsoTpConfig) -> bool {
+    config.block_size_override.is_none()
+        && [config.n_as, config.n_bs, config.n_cr].iter().all(|t| *t == KERNEL_ISOTP_TIMEOUT)
+}
+
+#[derive(Debug)]
+/// Userspace [IsoTp] engine on a raw socket, used when the kernel has no `can-isotp` support
+/// or a ComParam the kernel does not support is set ([kernel_supports])
+struct UserIsoTp {
+    engine: IsoTp,
+    socket: OwnedFd,
+    received: VecDeque<ResultEvent>,
+    errors: VecDeque<PduErrorEvt>,
+    tx_result: Option<Result<(), PduErrorEvt>>
+}
+
+impl UserIsoTp {
+    fn open(config: IsoTpConfig, ifindex: c_int, fd: bool) -> io::Result<Self> {
+        Ok(Self {
+            engine: IsoTp::new(config),
+            socket: filtered_raw_socket(ifindex, config.rx_id, fd)?,
+            received: VecDeque::new(),
+            errors: VecDeque::new(),
+            tx_result: None
+        })
+    }
+
+    /// Transmits due frames, then waits up to `timeout` for a received frame
+    fn pump(&mut self, timeout: Duration, clock: &Clock) -> Result<(), PduError> {
+        let now = Instant::now();
+        while let Some(frame) = self.engine.poll_transmit(now) {
+            let sent = LinuxCanFrame::new(&frame).is_ok_and(|raw| write_frame(&self.socket, &raw).is_ok());
+            match sent {
+                true => self.engine.confirm_transmit(Instant::now()),
+                false => self.engine.abort_transmit(PduErrorEvt::TxError)
+            }
+        }
+        let wait = self.engine.next_wake().map_or(timeout, |t| t.saturating_duration_since(now).min(timeout));
+        if wait_readable(&self.socket, wait).map_err(pdu_error)? {
+            if let Some((raw, received)) = read_frame(&self.socket).map_err(pdu_error)? {
+                if let Some(frame) = raw.to_frame() {
+                    self.engine.on_frame(&frame, received);
+                }
+            }
+        }
+        while let Some(event) = self.engine.poll_event(Instant::now()) {
+            match event {
+                IsoTpEvent::Received { data, start, flags } => self.received.push_back(ResultEvent {
+                    rx_flag: flags.to_flag_bytes(),
+                    start_msg_timestamp: clock.at(start),
+                    data,
+                    ..Default::default()
+                }),
+                IsoTpEvent::Sent => self.tx_result = Some(Ok(())),
+                IsoTpEvent::TransmitFailed(code) => self.tx_result = Some(Err(code)),
+                IsoTpEvent::ReceiveFailed(code) => self.errors.push_back(code)
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a message, returning once it has been transmitted
+    fn send(&mut self, data: &[u8], functional: bool, clock: &Clock) -> Result<(), PduError> {
+        self.tx_result = None;
+        self.engine.send(data, functional, Instant::now())?;
+        loop {
+            self.pump(self.engine.config().n_bs, clock)?;
+            // A failure stays in tx_result for Channel::send_error
+            match self.tx_result {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(_)) => return Err(PduError::FctFailed),
+                None => {}
+            }
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration, clock: &Clock) -> Result<Option<ResultEvent>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(msg) = self.received.pop_front() {
+                return Ok(Some(msg));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.pump(deadline - now, clock)?;
+        }
+    }
+}
+
+#[derive(Debug)]
+/// [J1939] node on a raw socket which receives every frame of the interface
+struct UserJ1939 {
+    engine: J1939,
+    socket: OwnedFd,
+    received: VecDeque<ResultEvent>,
+    errors: VecDeque<PduErrorEvt>,
+    tx_result: Option<Result<(), PduErrorEvt>>,
+    /// The address was lost, or no address could be claimed
+    lost: bool
+}
+
+impl UserJ1939 {
+    /// Opens the socket and claims the preferred address, returning once the address is claimed
+    fn open(config: J1939Config, ifindex: c_int, clock: &Clock) -> Result<Self, PduError> {
+        let mut user = Self {
+            engine: J1939::new(config),
+            socket: raw_socket(ifindex, false).map_err(pdu_error)?,
+            received: VecDeque::new(),
+            errors: VecDeque::new(),
+            tx_result: None,
+            lost: false
+        };
+        user.engine.claim(Instant::now());
+        user.wait_for_address(clock)?;
+        Ok(user)
+    }
+
+    /// Waits until the node has an address. Fails with [PduError::ResourceBusy] if the address
+    /// is lost
+    fn wait_for_address(&mut self, clock: &Clock) -> Result<(), PduError> {
+        loop {
+            if self.lost {
+                return Err(PduError::ResourceBusy);
+            }
+            if self.engine.address().is_some() {
+                return Ok(());
+            }
+            self.pump(self.engine.config().claim_timeout, clock)?;
+        }
+    }
+
+    /// Transmits due frames, then waits up to `timeout` for a received frame
+    fn pump(&mut self, timeout: Duration, clock: &Clock) -> Result<(), PduError> {
+        let now = Instant::now();
+        while let Some(frame) = self.engine.poll_transmit(now) {
+            let sent = LinuxCanFrame::new(&frame).is_ok_and(|raw| write_frame(&self.socket, &raw).is_ok());
+            if !sent {
+                self.engine.abort_transmit(PduErrorEvt::TxError);
+            }
+        }
+        let wait = self.engine.next_wake().map_or(timeout, |t| t.saturating_duration_since(now).min(timeout));
+        if wait_readable(&self.socket, wait).map_err(pdu_error)? {
+            if let Some((raw, received)) = read_frame(&self.socket).map_err(pdu_error)? {
+                if let Some(frame) = raw.to_frame() {
+                    self.engine.on_frame(&frame, received);
+                }
+            }
+        }
+        while let Some(event) = self.engine.poll_event(Instant::now()) {
+            match event {
+                J1939Event::Received { message, start } => self.received.push_back(ResultEvent {
+                    start_msg_timestamp: clock.at(start),
+                    extra_info: Some(ExtraInfoData { header: message.header().to_vec(), footer: Vec::new() }),
+                    data: message.data,
+                    ..Default::default()
+                }),
+                J1939Event::Sent => self.tx_result = Some(Ok(())),
+                J1939Event::TransmitFailed(code) => self.tx_result = Some(Err(code)),
+                J1939Event::ReceiveFailed(code) => self.errors.push_back(code),
+                J1939Event::AddressClaimed(_) => self.lost = false,
+                J1939Event::AddressLost => {
+                    self.lost = true;
+                    self.errors.push_back(PduErrorEvt::InitError);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a message (PGN, destination address and payload), returning once it has been
+    /// transmitted
+    fn send(&mut self, data: &[u8], clock: &Clock) -> Result<(), PduError> {
+        if data.len() < 4 {
+            return Err(PduError::InvalidParameters);
+        }
+        let pgn = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+        self.wait_for_address(clock)?;
+        self.tx_result = None;
+        self.engine.send(pgn, data[3], &data[4..], Instant::now())?;
+        loop {
+            self.pump(DEFAULT_RESPONSE_TIMEOUT, clock)?;
+            // A failure stays in tx_result for Channel::send_error
+            match self.tx_result {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(_)) => return Err(PduError::FctFailed),
+                None => {}
+            }
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration, clock: &Clock) -> Result<Option<ResultEvent>, PduError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(msg) = self.received.pop_front() {
+                return Ok(Some(msg));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.pump(deadline - now, clock)?;
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Raw(OwnedFd),
+    /// Kernel ISO-TP socket. Functional requests are sent on a raw socket which is opened when
+    /// first needed
+    KernelIsoTp {
+        config: IsoTpConfig,
+        socket: OwnedFd,
+        functional: Option<OwnedFd>,
+        errors: VecDeque<PduErrorEvt>
+    },
+    UserIsoTp(Box<UserIsoTp>),
+    J1939(Box<UserJ1939>)
+}
+
+impl Mode {
+    /// Opens an ISO-TP mode, falling back to the userspace engine if the kernel has no
+    /// `can-isotp` support or does not support the configuration
+    fn isotp(config: IsoTpConfig, ifindex: c_int, fd: bool) -> Result<Self, PduError> {
+        if config.is_fd() && !fd {
+            return Err(PduError::ValueNotSupported);
+        }
+        if kernel_supports(&config) {
+            match kernel_isotp_socket(&config, ifindex, fd) {
+                Ok(socket) => return Ok(Self::KernelIsoTp { config, socket, functional: None, errors: VecDeque::new() }),
+                Err(e) if e.raw_os_error() == Some(libc::EPROTONOSUPPORT) => {},
+                Err(e) => return Err(pdu_error(e))
+            }
+        }
+        UserIsoTp::open(config, ifindex, fd).map(|u| Self::UserIsoTp(Box::new(u))).map_err(pdu_error)
+    }
+
+    fn isotp_config(&self) -> Option<&IsoTpConfig> {
+        match self {
+            Self::Raw(_) | Self::J1939(_) => None,
+            Self::KernelIsoTp { config, .. } => Some(config),
+            Self::UserIsoTp(user) => Some(user.engine.config())
+        }
+    }
+}
+
+/// Applies `CP_Baudrate` and `CP_CanFDBaudrate` to an interface if they differ from the bit
+/// rates the interface is configured with. Returns true if the bit rates were changed
+fn apply_bitrates(ifindex: c_int, applied: &mut Option<netlink::Bitrates>, params: &LinkParams) -> Result<bool, PduError> {
+    let Some(bitrate) = params.get_u32(StdComParam::Baudrate).filter(|b| *b != 0) else {
+        return Ok(false);
+    };
+    let wanted = netlink::Bitrates {
+        bitrate,
+        data_bitrate: params.get_u32(StdComParam::CanFdBaudrate).unwrap_or_default()
+    };
+    if *applied == Some(wanted) {
+        return Ok(false);
+    }
+    let changed = match netlink::bitrates(ifindex).map_err(pdu_error)? {
+        Some(current)
+            if current.bitrate != wanted.bitrate
+                || (wanted.data_bitrate != 0 && current.data_bitrate != wanted.data_bitrate) =>
+        {
+            netlink::set_bitrates(ifindex, wanted).map_err(|e| match e.raw_os_error() {
+                Some(libc::EINVAL | libc::ERANGE | libc::EOPNOTSUPP) => PduError::ValueNotSupported,
+                _ => pdu_error(e)
+            })?;
+            true
+        },
+        _ => false
+    };
+    *applied = Some(wanted);
+    Ok(changed)
+}
+
+#[derive(Debug)]
+/// [Channel] of a ComLogicalLink on a SocketCAN interface
+pub struct SocketCanChannel {
+    interface: String,
+    ifindex: c_int,
+    clock: Clock,
+    bitrates: Option<netlink::Bitrates>,
+    /// The interface is configured for CAN FD frames
+    fd: bool,
+    mode: Mode
+}
+
+impl SocketCanChannel {
+    /// Opens a channel on an interface
+    pub fn open(interface: &str, protocol: Protocol, params: &LinkParams, clock: Clock) -> Result<Self, PduError> {
+        let ifindex = interface_index(interface)?;
+        let mut bitrates = None;
+        apply_bitrates(ifindex, &mut bitrates, params)?;
+        let fd = is_fd_interface(interface);
+        let mode = match protocol {
+            Protocol::Iso11898Raw => Mode::Raw(raw_socket(ifindex, fd).map_err(pdu_error)?),
+            Protocol::SaeJ1939_73OnSaeJ1939_21 => {
+                Mode::J1939(Box::new(UserJ1939::open(J1939Config::from_params(params), ifindex, &clock)?))
+            },
+            _ => Mode::isotp(IsoTpConfig::from_params(params), ifindex, fd)?
+        };
+        Ok(Self { interface: interface.to_string(), ifindex, clock, bitrates, fd, mode })
+    }
+}
+
+impl Channel for SocketCanChannel {
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError> {
+        let fd_changed = apply_bitrates(self.ifindex, &mut self.bitrates, params)?
+            && std::mem::replace(&mut self.fd, is_fd_interface(&self.interface)) != self.fd;
+        let new = IsoTpConfig::from_params(params);
+        match &self.mode {
+            Mode::Raw(_) if fd_changed => self.mode = Mode::Raw(raw_socket(self.ifindex, self.fd).map_err(pdu_error)?),
+            Mode::Raw(_) => {},
+            Mode::J1939(user) if *user.engine.config() != J1939Config::from_params(params) => {
+                let user = UserJ1939::open(J1939Config::from_params(params), self.ifindex, &self.clock)?;
+                self.mode = Mode::J1939(Box::new(user));
+            },
+            Mode::J1939(_) => {},
+            mode if fd_changed || mode.isotp_config() != Some(&new) => self.mode = Mode::isotp(new, self.ifindex, self.fd)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, data: &[u8], tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError> {
+        let functional = params.get_u32(StdComParam::RequestAddrMode) == Some(FUNCTIONAL_ADDR_MODE);
+        match &mut self.mode {
+            Mode::Raw(fd) => {
+                if data.len() < 4 {
+                    return Err(PduError::InvalidParameters);
+                }
+                let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let mut flags = CanFlags::from_flag_bytes(tx_flag);
+                flags.fd |= data.len() - 4 > CAN_MAX_DLEN;
+                flags.esi = false;
+                if flags.fd && !self.fd {
+                    return Err(PduError::ValueNotSupported);
+                }
+                let frame = LinuxCanFrame::new(&CanFrame { id, flags, data: data[4..].to_vec() })?;
+                write_frame(fd, &frame).map_err(pdu_error)
+            },
+            Mode::KernelIsoTp { config, socket, functional: raw, .. } => {
+                if data.is_empty() || data.len() > ISOTP_MAX_LEN {
+                    return Err(PduError::InvalidParameters);
+                }
+                if !functional {
+                    return write_fd(socket, data).map_err(pdu_error);
+                }
+                // The kernel socket only sends physical requests, so the single frame of a
+                // functional request is built by the userspace engine
+                let now = Instant::now();
+                let mut engine = IsoTp::new(*config);
+                engine.send(data, true, now)?;
+                let frame = LinuxCanFrame::new(&engine.poll_transmit(now).ok_or(PduError::FctFailed)?)?;
+                if raw.is_none() {
+                    *raw = Some(raw_socket(self.ifindex, self.fd).map_err(pdu_error)?);
+                }
+                let fd = raw.as_ref().ok_or(PduError::FctFailed)?;
+                write_frame(fd, &frame).map_err(pdu_error)
+            },
+            Mode::UserIsoTp(user) => user.send(data, functional, &self.clock),
+            Mode::J1939(user) => user.send(data, &self.clock)
+        }
+    }
+
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        let tx_result = match &mut self.mode {
+            Mode::UserIsoTp(user) => user.tx_result.take(),
+            Mode::J1939(user) => user.tx_result.take(),
+            _ => None
+        };
+        match tx_result {
+            Some(Err(code)) => code,
+            _ => send_error_event(error)
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError> {
+        match &mut self.mode {
+            Mode::Raw(fd) => {
+                if !wait_readable(fd, timeout).map_err(pdu_error)? {
+                    return Ok(None);
+                }
+                let Some((raw, received)) = read_frame(fd).map_err(pdu_error)? else {
+                    return Ok(None);
+                };
+                let Some(frame) = raw.to_frame() else {
+                    return Ok(None);
+                };
+                let mut data = frame.id.to_be_bytes().to_vec();
+                data.extend(frame.data);
+                Ok(Some(ResultEvent {
+                    rx_flag: frame.flags.to_flag_bytes(),
+                    start_msg_timestamp: self.clock.at(received),
+                    data,
+                    ..Default::default()
+                }))
+            },
+            Mode::KernelIsoTp { socket, errors, .. } => {
+                if !wait_readable(socket, timeout).map_err(pdu_error)? {
+                    return Ok(None);
+                }
+                let mut buf = vec![0; ISOTP_MAX_LEN + 1];
+                match read_fd(socket, &mut buf) {
+                    Ok(n) => {
+                        buf.truncate(n);
+                        // The kernel does not report when the first frame arrived
+                        Ok(Some(ResultEvent { data: buf, start_msg_timestamp: self.clock.now(), ..Default::default() }))
+                    },
+                    Err(e) => match isotp_error_event(&e) {
+                        Some(code) => {
+                            errors.push_back(code);
+                            Ok(None)
+                        },
+                        None => Err(pdu_error(e))
+                    }
+                }
+            },
+            Mode::UserIsoTp(user) => user.recv(timeout, &self.clock),
+            Mode::J1939(user) => user.recv(timeout, &self.clock)
+        }
+    }
+
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        match &mut self.mode {
+            Mode::Raw(_) => None,
+            Mode::KernelIsoTp { errors, .. } => errors.pop_front(),
+            Mode::UserIsoTp(user) => user.errors.pop_front(),
+            Mode::J1939(user) => user.errors.pop_front()
+        }
+    }
+}
+
+#[derive(Debug)]
+/// [Driver] for Linux SocketCAN interfaces
+///
+/// The interfaces are enumerated when the API is constructed
+pub struct SocketCan {
+    interfaces: Vec<String>
+}
+
+impl SocketCan {
+    /// Returns the names of the interfaces, in module order
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    fn interface(&self, module: usize) -> Result<&str, PduError> {
+        self.interfaces.get(module).map(|s| s.as_str()).ok_or(PduError::InvalidHandle)
+    }
+}
+
+impl Driver for SocketCan {
+    type Channel = SocketCanChannel;
+
+    fn open(_options: &str) -> Result<Self, PduError> {
+        Ok(Self { interfaces: can_interfaces() })
+    }
+
+    fn modules(&self) -> Vec<DriverModule> {
+        self.interfaces
+            .iter()
+            .map(|name| DriverModule {
+                module_type_id: MODULE_TYPE_ID,
+                name: name.clone(),
+                info: match is_fd_interface(name) {
+                    true => format!("SocketCAN interface {name} (CAN FD)"),
+                    false => format!("SocketCAN interface {name}")
+                },
+                resources: vec![DriverResource {
+                    bus_type: BusType::Iso11898_2Dwcan,
+                    protocols: PROTOCOLS.to_vec(),
+                    pins: BusType::Iso11898_2Dwcan.default_obd_pins()
+                }]
+            })
+            .collect()
+    }
+
+    fn connect_module(&self, module: usize) -> Result<(), PduError> {
+        interface_index(self.interface(module)?).map(|_| ())
+    }
+
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)> {
+        if protocol == Protocol::SaeJ1939_73OnSaeJ1939_21 {
+            let config = J1939Config::default();
+            let mut params: Vec<_> = [
+                (StdComParam::Baudrate, 250_000),
+                (StdComParam::J1939PreferredAddress, config.address as u32),
+                (StdComParam::J1939AddrClaim, 2),
+                (StdComParam::J1939AddrClaimTimeout, config.claim_timeout.as_micros() as u32)
+            ]
+            .into_iter()
+            .filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v)))
+            .collect();
+            let name = StdComParam::J1939Name.bytes(&DEFAULT_TESTER_NAME.to_le_bytes());
+            params.extend(name.ok().map(|v| (StdComParam::J1939Name, v)));
+            return params;
+        }
+        let mut params = vec![
+            (StdComParam::Baudrate, 500_000),
+            (StdComParam::CanFdBaudrate, 0),
+            (StdComParam::CanFdBitRateSwitch, 1),
+            (StdComParam::CanFillerByte, 0x55),
+            (StdComParam::CanFillerByteHandling, 1)
+        ];
+        if protocol != Protocol::Iso11898Raw {
+            params.extend([
+                (StdComParam::P2Max, DEFAULT_RESPONSE_TIMEOUT.as_micros() as u32),
+                (StdComParam::RequestAddrMode, 1),
+                (StdComParam::CanPhysReqId, 0x7E0),
+                (StdComParam::CanFuncReqId, 0x7DF),
+                (StdComParam::CanRespUsdtId, 0x7E8),
+                (StdComParam::CanPhysReqFormat, 0x05),
+                (StdComParam::CanFuncReqFormat, 0x05),
+                (StdComParam::CanRespUsdtFormat, 0x05),
+                (StdComParam::CanPhysReqExtAddr, 0),
+                (StdComParam::CanFuncReqExtAddr, 0),
+                (StdComParam::CanRespUsdtExtAddr, 0),
+                (StdComParam::BlockSize, 0),
+                (StdComParam::StMin, 0),
+                (StdComParam::BlockSizeOverride, OVERRIDE_DISABLED),
+                (StdComParam::StMinOverride, OVERRIDE_DISABLED),
+                (StdComParam::CanMaxNumWaitFrames, DEFAULT_MAX_WAIT_FRAMES as u32),
+                (StdComParam::As, KERNEL_ISOTP_TIMEOUT.as_micros() as u32),
+                (StdComParam::Bs, KERNEL_ISOTP_TIMEOUT.as_micros() as u32),
+                (StdComParam::Cr, KERNEL_ISOTP_TIMEOUT.as_micros() as u32),
+                (StdComParam::CanFdTxMaxDataLength, CAN_MAX_DLEN as u32)
+            ]);
+        }
+        params.into_iter().filter_map(|(p, v)| p.value(v).ok().map(|v| (p, v))).collect()
+    }
+
+    fn open_channel(
+        &self,
+        module: usize,
+        _resource: &DriverResource,
+        protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError> {
+        SocketCanChannel::open(self.interface(module)?, protocol, params, clock)
+    }
+}
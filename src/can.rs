@@ -0,0 +1,62 @@
+/// Largest data length of a classic CAN frame
+pub const CAN_MAX_DLEN: usize = 8;
+
+/// Largest data length of a CAN FD frame
+pub const CANFD_MAX_DLEN: usize = 64;
+
+/// Data lengths a CAN FD frame can have above [CAN_MAX_DLEN]
+const CANFD_LENGTHS: &[usize] = &[12, 16, 20, 24, 32, 48, 64];
+
+/// Rounds a data length up to the next length a CAN FD frame can carry.
+/// Returns [None] if the length exceeds [CANFD_MAX_DLEN]
+pub fn canfd_len(len: usize) -> Option<usize> {
+    match len {
+        0..=CAN_MAX_DLEN => Some(len),
+        _ => CANFD_LENGTHS.iter().copied().find(|l| *l >= len)
+    }
+}
+
+/// Returns true if a CAN FD frame can carry exactly `len` data bytes
+pub fn is_canfd_len(len: usize) -> bool {
+    canfd_len(len) == Some(len)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// Frame format of a CAN message
+///
+/// CAN ComLogicalLinks carry the flags in the first byte of the TxFlag data of a ComPrimitive
+/// ([CopCtrlData::tx_flag](crate::CopCtrlData::tx_flag)) and the RxFlag data of a result ([ResultData::rx_flag](crate::ResultData::rx_flag)).
+/// Missing flag data means a classic CAN frame
+pub struct CanFlags {
+    /// CAN FD frame (FDF)
+    pub fd: bool,
+    /// Data phase of the CAN FD frame is sent at `CP_CanFDBaudrate` (BRS)
+    pub brs: bool,
+    /// Transmitter of the CAN FD frame is error passive (ESI). Only set on received frames
+    pub esi: bool
+}
+
+impl CanFlags {
+    /// Flag bit of [CanFlags::fd]
+    pub const FD: u8 = 0x01;
+    /// Flag bit of [CanFlags::brs]
+    pub const BRS: u8 = 0x02;
+    /// Flag bit of [CanFlags::esi]
+    pub const ESI: u8 = 0x04;
+
+    /// Flags of a CAN FD frame, with or without bit rate switching
+    pub fn fd(brs: bool) -> Self {
+        Self { fd: true, brs, esi: false }
+    }
+
+    /// Reads the flags from TxFlag or RxFlag data
+    pub fn from_flag_bytes(bytes: &[u8]) -> Self {
+        let b = bytes.first().copied().unwrap_or_default();
+        Self { fd: b & Self::FD != 0, brs: b & Self::BRS != 0, esi: b & Self::ESI != 0 }
+    }
+
+    /// Returns the flags as TxFlag or RxFlag data
+    pub fn to_flag_bytes(self) -> Vec<u8> {
+        vec![(self.fd as u8 * Self::FD) | (self.brs as u8 * Self::BRS) | (self.esi as u8 * Self::ESI)]
+    }
+}
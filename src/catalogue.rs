@@ -0,0 +1,327 @@
+use std::{collections::HashMap, ffi::CString, fmt, str::FromStr};
+
+use crate::{ObjectId, PduError, PduGetObjectIdFn, PduObjt, PinData, ResourceRequest};
+
+/// A standardised ISO 22900-2 object which can be resolved to an [ObjectId]
+/// using [PduGetObjectIdFn]
+pub trait StandardObject: Copy + PartialEq + 'static {
+    /// Object type used when resolving the object ID
+    const OBJECT_TYPE: PduObjt;
+    /// Every object of this type
+    const ALL: &'static [Self];
+    /// Returns the standard short name of the object
+    fn short_name(&self) -> &'static str;
+}
+
+/// Generates a catalogue enum of standard short names
+macro_rules! short_name_enum {
+    ($(#[$meta:meta])* $name:ident, $objt:expr, { $($(#[$vmeta:meta])* $variant:ident => $short:literal),* $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$vmeta])* $variant),*
+        }
+
+        impl $name {
+            /// Every entry of the catalogue
+            pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+
+            /// Returns the standard short name
+            pub fn short_name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $short),*
+                }
+            }
+        }
+
+        impl StandardObject for $name {
+            const OBJECT_TYPE: PduObjt = $objt;
+            const ALL: &'static [Self] = $name::ALL;
+            fn short_name(&self) -> &'static str {
+                $name::short_name(self)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.short_name())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = PduError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($short => Ok(Self::$variant),)*
+                    _ => Err(PduError::InvalidParameters)
+                }
+            }
+        }
+    };
+}
+
+short_name_enum!(
+    /// Standard bus type short names
+    BusType, PduObjt::BusType, {
+    /// High speed CAN (ISO 11898-2)
+    Iso11898_2Dwcan => "ISO_11898_2_DWCAN",
+    /// Fault tolerant low speed CAN (ISO 11898-3)
+    Iso11898_3Dwftcan => "ISO_11898_3_DWFTCAN",
+    /// Truck and trailer CAN (ISO 11992-1)
+    Iso11992_1Dwcan => "ISO_11992_1_DWCAN",
+    /// K-Line (ISO 9141-2)
+    Iso9141_2Uart => "ISO_9141_2_UART",
+    /// K-Line (ISO 14230-1)
+    Iso14230_1Uart => "ISO_14230_1_UART",
+    /// Variable pulse width J1850
+    SaeJ1850Vpw => "SAE_J1850_VPW",
+    /// Pulse width modulated J1850
+    SaeJ1850Pwm => "SAE_J1850_PWM",
+    /// SAE J2610 UART (Chrysler SCI)
+    SaeJ2610Uart => "SAE_J2610_UART",
+    /// SAE J1708 UART
+    SaeJ1708Uart => "SAE_J1708_UART",
+    /// J1939 CAN (SAE J1939-11)
+    SaeJ1939_11Dwcan => "SAE_J1939_11_DWCAN",
+    /// Single wire CAN (SAE J2411)
+    SaeJ2411Swcan => "SAE_J2411_SWCAN",
+    /// Diagnostics over ethernet (ISO 13400-2)
+    Iso13400_2Diag => "ISO_13400_2_DIAG",
+});
+
+short_name_enum!(
+    /// Standard protocol short names
+    Protocol, PduObjt::Protocol, {
+    /// Raw CAN frames
+    Iso11898Raw => "ISO_11898_RAW",
+    /// UDS on CAN (ISO 15765-3 over ISO-TP)
+    Iso15765_3OnIso15765_2 => "ISO_15765_3_on_ISO_15765_2",
+    /// KWP2000 on CAN (ISO 14230-3 over ISO-TP)
+    Iso14230_3OnIso15765_2 => "ISO_14230_3_on_ISO_15765_2",
+    /// KWP2000 on K-Line
+    Iso14230_3OnIso14230_2 => "ISO_14230_3_on_ISO_14230_2",
+    /// UDS on DoIP
+    Iso14229_5OnIso13400_2 => "ISO_14229_5_on_ISO_13400_2",
+    /// OBD on CAN
+    IsoObdOnIso15765_4 => "ISO_OBD_on_ISO_15765_4",
+    /// OBD on KWP2000 K-Line
+    IsoObdOnIso14230_4 => "ISO_OBD_on_ISO_14230_4",
+    /// OBD on ISO 9141-2 K-Line
+    IsoObdOnIso9141_2 => "ISO_OBD_on_ISO_9141_2",
+    /// OBD on J1850
+    IsoObdOnSaeJ1850 => "ISO_OBD_on_SAE_J1850",
+    /// SAE J2190 diagnostics on J1850
+    SaeJ2190OnSaeJ1850 => "SAE_J2190_on_SAE_J1850",
+    /// J1939 diagnostics on the J1939 data link layer
+    SaeJ1939_73OnSaeJ1939_21 => "SAE_J1939_73_on_SAE_J1939_21",
+});
+
+short_name_enum!(
+    /// Standard pin type short names
+    PinType, PduObjt::PinType, {
+    /// CAN high line
+    Hi => "HI",
+    /// CAN low line
+    Lo => "LO",
+    /// K-Line
+    K => "K",
+    /// L-Line
+    L => "L",
+    /// UART transmit line
+    Tx => "TX",
+    /// UART receive line
+    Rx => "RX",
+    /// Positive line of a differential bus (J1850)
+    Plus => "PLUS",
+    /// Negative line of a differential bus (J1850)
+    Minus => "MINUS",
+    /// Single wire bus line
+    Single => "SINGLE",
+    /// Programming voltage pin
+    ProgV => "PROGV",
+});
+
+short_name_enum!(
+    /// Standard IOCTL short names
+    IoctlCommand, PduObjt::IoCtrl, {
+    /// Resets the module or ComLogicalLink
+    Reset => "PDU_IOCTL_RESET",
+    /// Cancels every ComPrimitive in the transmit queue of a ComLogicalLink
+    ClearTxQueue => "PDU_IOCTL_CLEAR_TX_QUEUE",
+    /// Suspends transmission from the transmit queue of a ComLogicalLink
+    SuspendTxQueue => "PDU_IOCTL_SUSPEND_TX_QUEUE",
+    /// Resumes transmission from the transmit queue of a ComLogicalLink
+    ResumeTxQueue => "PDU_IOCTL_RESUME_TX_QUEUE",
+    /// Clears the event queue of a ComLogicalLink
+    ClearRxQueue => "PDU_IOCTL_CLEAR_RX_QUEUE",
+    /// Reads the battery voltage of the module
+    ReadVbatt => "PDU_IOCTL_READ_VBATT",
+    /// Sets the programming voltage
+    SetProgVoltage => "PDU_IOCTL_SET_PROG_VOLTAGE",
+    /// Reads the programming voltage
+    ReadProgVoltage => "PDU_IOCTL_READ_PROG_VOLTAGE",
+    /// Vendor specific generic IOCTL
+    Generic => "PDU_IOCTL_GENERIC",
+    /// Sets the size of the transmit queue of a ComLogicalLink
+    SetBufferSize => "PDU_IOCTL_SET_BUFFER_SIZE",
+    /// Reads the ID of the cable attached to the module
+    GetCableId => "PDU_IOCTL_GET_CABLE_ID",
+    /// Starts a message filter
+    StartMsgFilter => "PDU_IOCTL_START_MSG_FILTER",
+    /// Stops a message filter
+    StopMsgFilter => "PDU_IOCTL_STOP_MSG_FILTER",
+    /// Clears all message filters
+    ClearMsgFilter => "PDU_IOCTL_CLEAR_MSG_FILTER",
+    /// Sets the mode and size of an event queue
+    SetEventQueueProperties => "PDU_IOCTL_SET_EVENT_QUEUE_PROPERTIES",
+    /// Sends a break signal on a UART bus
+    SendBreak => "PDU_IOCTL_SEND_BREAK",
+    /// Reads the state of the ignition sense pin
+    ReadIgnitionSenseState => "PDU_IOCTL_READ_IGNITION_SENSE_STATE",
+    /// Sends a DoIP vehicle identification request
+    VehicleIdRequest => "PDU_IOCTL_VEHICLE_ID_REQUEST",
+    /// Switches the ethernet activation pin
+    SetEthSwitchState => "PDU_IOCTL_SET_ETH_SWITCH_STATE",
+    /// Reads the status of a DoIP entity
+    GetEntityStatus => "PDU_IOCTL_GET_ENTITY_STATUS",
+    /// Reads the diagnostic power mode of a DoIP entity
+    GetDiagnosticPowerMode => "PDU_IOCTL_GET_DIAGNOSTIC_POWER_MODE",
+});
+
+impl BusType {
+    /// Returns the default pins of the bus type on an SAE J1962 (OBD-II) connector
+    pub fn default_obd_pins(&self) -> Vec<(u32, PinType)> {
+        match self {
+            Self::Iso11898_2Dwcan | Self::SaeJ1939_11Dwcan => vec![(6, PinType::Hi), (14, PinType::Lo)],
+            Self::Iso11898_3Dwftcan => vec![(3, PinType::Hi), (11, PinType::Lo)],
+            Self::Iso11992_1Dwcan => vec![(6, PinType::Hi), (14, PinType::Lo)],
+            Self::Iso9141_2Uart | Self::Iso14230_1Uart => vec![(7, PinType::K), (15, PinType::L)],
+            Self::SaeJ1850Vpw => vec![(2, PinType::Plus)],
+            Self::SaeJ1850Pwm => vec![(2, PinType::Plus), (10, PinType::Minus)],
+            Self::SaeJ2610Uart => vec![(7, PinType::Rx), (15, PinType::Tx)],
+            Self::SaeJ1708Uart => vec![(7, PinType::Plus), (15, PinType::Minus)],
+            Self::SaeJ2411Swcan => vec![(1, PinType::Single)],
+            Self::Iso13400_2Diag => vec![(3, PinType::Rx), (11, PinType::Rx), (12, PinType::Tx), (13, PinType::Tx)]
+        }
+    }
+}
+
+impl Protocol {
+    /// Returns the bus types the protocol can run on. The first entry is the most common bus type
+    pub fn bus_types(&self) -> &'static [BusType] {
+        match self {
+            Self::Iso11898Raw | Self::Iso15765_3OnIso15765_2 | Self::Iso14230_3OnIso15765_2 => {
+                &[BusType::Iso11898_2Dwcan, BusType::Iso11898_3Dwftcan, BusType::SaeJ2411Swcan]
+            },
+            Self::IsoObdOnIso15765_4 => &[BusType::Iso11898_2Dwcan],
+            Self::Iso14230_3OnIso14230_2 | Self::IsoObdOnIso14230_4 => &[BusType::Iso14230_1Uart],
+            Self::IsoObdOnIso9141_2 => &[BusType::Iso9141_2Uart],
+            Self::IsoObdOnSaeJ1850 | Self::SaeJ2190OnSaeJ1850 => &[BusType::SaeJ1850Vpw, BusType::SaeJ1850Pwm],
+            Self::Iso14229_5OnIso13400_2 => &[BusType::Iso13400_2Diag],
+            Self::SaeJ1939_73OnSaeJ1939_21 => &[BusType::SaeJ1939_11Dwcan, BusType::Iso11898_2Dwcan]
+        }
+    }
+
+    /// Returns true if the protocol runs on top of CAN
+    pub fn is_can(&self) -> bool {
+        self.bus_types().iter().any(|b| {
+            matches!(
+                b,
+                BusType::Iso11898_2Dwcan | BusType::Iso11898_3Dwftcan | BusType::SaeJ2411Swcan | BusType::SaeJ1939_11Dwcan
+            )
+        })
+    }
+
+    /// Returns true if ECUs of the protocol answer with a response pending (`7F xx 78`) while they
+    /// work on a request. Raw CAN frames and J1939 messages start with an ID, not a service
+    pub fn has_response_pending(&self) -> bool {
+        !matches!(self, Self::Iso11898Raw | Self::SaeJ1939_73OnSaeJ1939_21)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Cache of object IDs resolved through [PduGetObjectIdFn]
+///
+/// Object IDs are fixed for the lifetime of a constructed API, so each short name
+/// only has to be resolved once
+pub struct ObjectIdCache {
+    get_object_id: PduGetObjectIdFn,
+    cache: HashMap<(PduObjt, String), ObjectId>
+}
+
+impl ObjectIdCache {
+    /// Creates a new empty cache
+    ///
+    /// ## Parameters
+    /// * get_object_id - The API's `PDUGetObjectId` function
+    pub fn new(get_object_id: PduGetObjectIdFn) -> Self {
+        Self { get_object_id, cache: HashMap::new() }
+    }
+
+    /// Resolves the object ID of any short name. Returns [PduError::IdNotSupported]
+    /// if the API does not know the short name
+    pub fn resolve(&mut self, object_type: PduObjt, short_name: &str) -> Result<ObjectId, PduError> {
+        if let Some(id) = self.cache.get(&(object_type, short_name.to_string())) {
+            return Ok(*id);
+        }
+        let name = CString::new(short_name).map_err(|_| PduError::InvalidParameters)?;
+        let mut id = crate::PDU_ID_UNDEF;
+        match (self.get_object_id)(object_type, name.as_ptr() as *mut u8, &mut id) {
+            PduError::StatusNoError => {}
+            e => return Err(e)
+        }
+        let id = ObjectId::new(id).ok_or(PduError::IdNotSupported)?;
+        self.cache.insert((object_type, short_name.to_string()), id);
+        Ok(id)
+    }
+
+    /// Resolves the object ID of a standard object
+    pub fn resolve_object<T: StandardObject>(&mut self, object: T) -> Result<ObjectId, PduError> {
+        self.resolve(T::OBJECT_TYPE, object.short_name())
+    }
+
+    /// Clears the cache. This must be called if the API is destructed and constructed again
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Typed description of a resource using standard short names
+pub struct ResourceDescriptor {
+    /// Bus type
+    pub bus_type: BusType,
+    /// Protocol
+    pub protocol: Protocol,
+    /// Pins on the data link connector (Pin number, pin type)
+    pub pins: Vec<(u32, PinType)>
+}
+
+impl ResourceDescriptor {
+    /// Creates a new resource descriptor
+    pub fn new(bus_type: BusType, protocol: Protocol, pins: Vec<(u32, PinType)>) -> Self {
+        Self { bus_type, protocol, pins }
+    }
+
+    /// Creates a resource descriptor for a protocol on its most common bus type,
+    /// using the default pins of an SAE J1962 (OBD-II) connector
+    pub fn obd(protocol: Protocol) -> Self {
+        let bus_type = protocol.bus_types()[0];
+        Self { bus_type, protocol, pins: bus_type.default_obd_pins() }
+    }
+
+    /// Resolves all short names to their object IDs, creating a [ResourceRequest]
+    /// which can be passed to the API as [RscData](crate::RscData)
+    pub fn resolve(&self, cache: &mut ObjectIdCache) -> Result<ResourceRequest, PduError> {
+        let bus_type_id = cache.resolve_object(self.bus_type)?;
+        let protocol_id = cache.resolve_object(self.protocol)?;
+        let pins = self
+            .pins
+            .iter()
+            .map(|(pin, pin_type)| {
+                Ok(PinData { dlc_pin_number: *pin, dlc_pin_type_id: cache.resolve_object(*pin_type)?.raw() })
+            })
+            .collect::<Result<Vec<PinData>, PduError>>()?;
+        Ok(ResourceRequest::new(bus_type_id, protocol_id, pins))
+    }
+}
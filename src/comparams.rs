@@ -0,0 +1,763 @@
+use std::{ffi::c_void, fmt, str::FromStr, time::Duration};
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Unit of a ComParam value
+pub enum ComParamUnit {
+    /// Unitless value (Enumeration, flag, address or identifier)
+    None,
+    /// Time in microseconds
+    Microseconds,
+    /// Bits per second
+    BitsPerSecond,
+    /// Percentage (0-100)
+    Percent,
+    /// Count of items (Bytes, frames, retries)
+    Count
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Definition of a standard ComParam
+pub struct ComParamDef {
+    /// Short name of the ComParam
+    pub short_name: &'static str,
+    /// ComParam class
+    pub class: PduPc,
+    /// Data type of the ComParam
+    pub data_type: PduPt,
+    /// Structure type, if [ComParamDef::data_type] is [PduPt::StructField]
+    pub struct_type: Option<PduCpst>,
+    /// Unit of the ComParam value
+    pub unit: ComParamUnit,
+    /// Optional maximum value for numeric ComParams
+    pub max: Option<u32>,
+    /// Protocols the ComParam applies to. Empty if the ComParam applies to all protocols
+    pub protocols: &'static [Protocol]
+}
+
+impl ComParamDef {
+    /// Returns true if the ComParam is a physical (Bus type) ComParam, which is shared
+    /// by every ComLogicalLink on the same physical resource
+    pub fn is_physical(&self) -> bool {
+        self.class == PduPc::BusType
+    }
+
+    /// Returns true if the ComParam can be used with the protocol
+    pub fn supports_protocol(&self, protocol: Protocol) -> bool {
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+
+    /// Checks that a value has the correct type for this ComParam, and is within range
+    pub fn validate(&self, value: &ComParamValue) -> Result<(), PduError> {
+        if value.data_type() != self.data_type || value.struct_type() != self.struct_type {
+            return Err(PduError::InvalidParameters);
+        }
+        match (self.max, value.as_u32()) {
+            (Some(max), Some(v)) if v > max => Err(PduError::ValueNotSupported),
+            _ => Ok(())
+        }
+    }
+}
+
+const CAN_ISOTP: &[Protocol] = &[
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4
+];
+
+const CAN: &[Protocol] = &[
+    Protocol::Iso11898Raw,
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::IsoObdOnIso15765_4,
+    Protocol::SaeJ1939_73OnSaeJ1939_21
+];
+
+const KLINE: &[Protocol] = &[
+    Protocol::Iso14230_3OnIso14230_2,
+    Protocol::IsoObdOnIso14230_4,
+    Protocol::IsoObdOnIso9141_2
+];
+
+const HEADER: &[Protocol] = &[
+    Protocol::Iso14230_3OnIso14230_2,
+    Protocol::IsoObdOnIso14230_4,
+    Protocol::IsoObdOnIso9141_2,
+    Protocol::IsoObdOnSaeJ1850,
+    Protocol::SaeJ2190OnSaeJ1850
+];
+
+const DOIP: &[Protocol] = &[Protocol::Iso14229_5OnIso13400_2];
+
+const J1939: &[Protocol] = &[Protocol::SaeJ1939_73OnSaeJ1939_21];
+
+const DIAG: &[Protocol] = &[
+    Protocol::Iso15765_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso15765_2,
+    Protocol::Iso14230_3OnIso14230_2,
+    Protocol::Iso14229_5OnIso13400_2,
+    Protocol::IsoObdOnIso15765_4,
+    Protocol::IsoObdOnIso14230_4,
+    Protocol::IsoObdOnIso9141_2,
+    Protocol::IsoObdOnSaeJ1850,
+    Protocol::SaeJ2190OnSaeJ1850
+];
+
+const ALL: &[Protocol] = &[];
+
+/// Generates the standard ComParam enum and its definitions
+macro_rules! std_com_params {
+    ($($(#[$vmeta:meta])* $variant:ident => ($short:literal, $class:ident, $pt:ident, $unit:ident, $protocols:ident $(, max = $max:expr)? $(, struct = $cpst:ident)?)),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        /// Standard ComParams defined by ISO 22900-2
+        pub enum StdComParam {
+            $($(#[$vmeta])* $variant),*
+        }
+
+        impl StdComParam {
+            /// Every standard ComParam
+            pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+
+            /// Returns the definition of the ComParam
+            pub fn def(&self) -> &'static ComParamDef {
+                match self {
+                    $(Self::$variant => &ComParamDef {
+                        short_name: $short,
+                        class: PduPc::$class,
+                        data_type: PduPt::$pt,
+                        struct_type: std_com_params!(@opt $(PduCpst::$cpst)?),
+                        unit: ComParamUnit::$unit,
+                        max: std_com_params!(@opt $($max)?),
+                        protocols: $protocols
+                    }),*
+                }
+            }
+        }
+    };
+    (@opt $x:expr) => { Some($x) };
+    (@opt) => { None };
+}
+
+std_com_params! {
+    /// Maximum time between the end of a request and start of the ECU response
+    P2Max => ("CP_P2Max", Timing, Unum32, Microseconds, DIAG),
+    /// Minimum time between the end of a request and start of the ECU response
+    P2Min => ("CP_P2Min", Timing, Unum32, Microseconds, DIAG),
+    /// Maximum time for the ECU response after a response pending (0x78) response
+    P2Star => ("CP_P2Star", Timing, Unum32, Microseconds, DIAG),
+    /// Minimum time between the end of an ECU response and the next request
+    P3Min => ("CP_P3Min", Timing, Unum32, Microseconds, DIAG),
+    /// Minimum time between a physical request and the next request
+    P3Phys => ("CP_P3Phys", Timing, Unum32, Microseconds, DIAG),
+    /// Minimum time between a functional request and the next request
+    P3Func => ("CP_P3Func", Timing, Unum32, Microseconds, DIAG),
+    /// Maximum inter-byte time of an ECU response
+    P1Max => ("CP_P1Max", Timing, Unum32, Microseconds, KLINE),
+    /// Minimum inter-byte time of an ECU response
+    P1Min => ("CP_P1Min", Timing, Unum32, Microseconds, KLINE),
+    /// Minimum inter-byte time of a tester request
+    P4Min => ("CP_P4Min", Timing, Unum32, Microseconds, KLINE),
+    /// Maximum inter-byte time of a tester request
+    P4Max => ("CP_P4Max", Timing, Unum32, Microseconds, KLINE),
+    /// Timeout for transmission of a CAN frame by the sender (N_As)
+    As => ("CP_As", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Timeout for transmission of a CAN frame by the receiver (N_Ar)
+    Ar => ("CP_Ar", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Timeout waiting for a flow control frame (N_Bs)
+    Bs => ("CP_Bs", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Time until transmission of the next flow control frame (N_Br)
+    Br => ("CP_Br", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Timeout waiting for the next consecutive frame (N_Cr)
+    Cr => ("CP_Cr", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Time until transmission of the next consecutive frame (N_Cs)
+    Cs => ("CP_Cs", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Separation time reported by the tester in flow control frames
+    StMin => ("CP_StMin", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Override of the separation time reported by the ECU. `0xFFFFFFFF` disables the override
+    StMinOverride => ("CP_StMinOverride", Timing, Unum32, Microseconds, CAN_ISOTP),
+    /// Timeout waiting for the response to a cyclic request
+    CyclicRespTimeout => ("CP_CyclicRespTimeout", Timing, Unum32, Microseconds, ALL),
+    /// Maximum time from the end of the address byte to the start of the synchronization pattern (5 baud init)
+    W1Max => ("CP_W1Max", Init, Unum32, Microseconds, KLINE),
+    /// Maximum time from the synchronization pattern to key byte 1 (5 baud init)
+    W2Max => ("CP_W2Max", Init, Unum32, Microseconds, KLINE),
+    /// Maximum time between key byte 1 and key byte 2 (5 baud init)
+    W3Max => ("CP_W3Max", Init, Unum32, Microseconds, KLINE),
+    /// Maximum time between key byte 2 from the ECU and its inversion from the tester (5 baud init)
+    W4Max => ("CP_W4Max", Init, Unum32, Microseconds, KLINE),
+    /// Minimum bus idle time before the tester starts the address byte (5 baud init)
+    W5Min => ("CP_W5Min", Init, Unum32, Microseconds, KLINE),
+    /// Minimum bus idle time before a fast init wake up pattern
+    TIdle => ("CP_TIdle", Init, Unum32, Microseconds, KLINE),
+    /// Low time of the fast init wake up pattern
+    TInil => ("CP_TInil", Init, Unum32, Microseconds, KLINE),
+    /// Total time of the fast init wake up pattern
+    TWup => ("CP_TWup", Init, Unum32, Microseconds, KLINE),
+    /// Initialization method of a K-Line link (0 = none, 1 = 5 baud, 2 = fast init)
+    InitializationSettings => ("CP_InitializationSettings", Init, Unum32, None, KLINE, max = 2),
+    /// Functional address byte used for 5 baud init
+    FiveBaudAddressFunc => ("CP_5BaudAddressFunc", Init, Unum32, None, KLINE, max = 0xFF),
+    /// Physical address byte used for 5 baud init
+    FiveBaudAddressPhys => ("CP_5BaudAddressPhys", Init, Unum32, None, KLINE, max = 0xFF),
+    /// Session specific P2 timing values
+    SessionTimingOverride => ("CP_SessionTimingOverride", Timing, StructField, None, DIAG, struct = SessionTiming),
+    /// KWP2000 access timing parameter values
+    AccessTimingOverride => ("CP_AccessTimingOverride", Timing, StructField, None, KLINE, struct = AccessTiming),
+    /// Handling of timing values received from the ECU (0 = ignore, 1 = apply)
+    ModifyTiming => ("CP_ModifyTiming", Timing, Unum32, None, DIAG, max = 1),
+    /// Timeout for repeated busy repeat request (0x21) responses
+    Rc21CompletionTimeout => ("CP_RC21CompletionTimeout", ErrHdl, Unum32, Microseconds, DIAG),
+    /// Time between repetitions of a request after a busy repeat request (0x21) response
+    Rc21RequestTime => ("CP_RC21RequestTime", ErrHdl, Unum32, Microseconds, DIAG),
+    /// Timeout for repeated response pending (0x78) responses
+    Rc78CompletionTimeout => ("CP_RC78CompletionTimeout", ErrHdl, Unum32, Microseconds, DIAG),
+    /// Handling of busy repeat request (0x21) responses
+    Rc21Handling => ("CP_RC21Handling", ErrHdl, Unum32, None, DIAG, max = 2),
+    /// Handling of request correctly received - response pending (0x23) responses
+    Rc23Handling => ("CP_RC23Handling", ErrHdl, Unum32, None, DIAG, max = 2),
+    /// Handling of response pending (0x78) responses
+    Rc78Handling => ("CP_RC78Handling", ErrHdl, Unum32, None, DIAG, max = 2),
+    /// Byte offset of the response code in negative responses
+    RcByteOffset => ("CP_RCByteOffset", Com, Unum32, Count, DIAG),
+    /// Number of times the application layer repeats a request on failure
+    RepeatReqCountApp => ("CP_RepeatReqCountApp", ErrHdl, Unum32, Count, ALL),
+    /// Number of times the transport layer repeats a request on failure
+    RepeatReqCountTrans => ("CP_RepeatReqCountTrans", ErrHdl, Unum32, Count, ALL),
+    /// Suspend the ComPrimitive queue of the link on an error (0 = no, 1 = yes)
+    SuspendQueueOnError => ("CP_SuspendQueueOnError", ErrHdl, Unum32, None, ALL, max = 1),
+    /// Report start of message indications (0 = no, 1 = yes)
+    StartMsgIndEnable => ("CP_StartMsgIndEnable", Com, Unum32, None, ALL, max = 1),
+    /// Report transmitted messages as received messages (0 = no, 1 = yes)
+    Loopback => ("CP_Loopback", Com, Unum32, None, ALL, max = 1),
+    /// Enables the performance test of a link (0 = no, 1 = yes)
+    EnablePerformanceTest => ("CP_EnablePerformanceTest", Com, Unum32, None, ALL, max = 1),
+    /// Addressing mode of requests (0 = physical, 1 = functional, 2 = functional broadcast)
+    RequestAddrMode => ("CP_RequestAddrMode", Com, Unum32, None, DIAG, max = 2),
+    /// Time between tester present messages
+    TesterPresentTime => ("CP_TesterPresentTime", TesterPresent, Unum32, Microseconds, DIAG),
+    /// Tester present handling (0 = disabled, 1 = enabled)
+    TesterPresentHandling => ("CP_TesterPresentHandling", TesterPresent, Unum32, None, DIAG, max = 1),
+    /// Addressing mode of tester present messages (0 = physical, 1 = functional)
+    TesterPresentAddrMode => ("CP_TesterPresentAddrMode", TesterPresent, Unum32, None, DIAG, max = 1),
+    /// Whether a response is expected to tester present messages (0 = no, 1 = yes)
+    TesterPresentReqRsp => ("CP_TesterPresentReqRsp", TesterPresent, Unum32, None, DIAG, max = 1),
+    /// Tester present send type (0 = fixed period, 1 = when bus has been idle)
+    TesterPresentSendType => ("CP_TesterPresentSendType", TesterPresent, Unum32, None, DIAG, max = 1),
+    /// Tester present message payload
+    TesterPresentMessage => ("CP_TesterPresentMessage", TesterPresent, ByteField, None, DIAG),
+    /// Expected positive response to tester present messages
+    TesterPresentExpPosResp => ("CP_TesterPresentExpPosResp", TesterPresent, ByteField, None, DIAG),
+    /// Expected negative response to tester present messages
+    TesterPresentExpNegResp => ("CP_TesterPresentExpNegResp", TesterPresent, ByteField, None, DIAG),
+    /// CAN ID used for physical requests
+    CanPhysReqId => ("CP_CanPhysReqId", Com, Unum32, None, CAN),
+    /// CAN ID used for functional requests
+    CanFuncReqId => ("CP_CanFuncReqId", Com, Unum32, None, CAN),
+    /// CAN ID of segmented (USDT) ECU responses
+    CanRespUsdtId => ("CP_CanRespUSDTId", UniqueId, Unum32, None, CAN),
+    /// CAN ID of unsegmented (UUDT) ECU responses
+    CanRespUudtId => ("CP_CanRespUUDTId", UniqueId, Unum32, None, CAN),
+    /// Addressing format of physical requests
+    CanPhysReqFormat => ("CP_CanPhysReqFormat", Com, Unum32, None, CAN),
+    /// Addressing format of functional requests
+    CanFuncReqFormat => ("CP_CanFuncReqFormat", Com, Unum32, None, CAN),
+    /// Addressing format of segmented (USDT) ECU responses
+    CanRespUsdtFormat => ("CP_CanRespUSDTFormat", UniqueId, Unum32, None, CAN),
+    /// Addressing format of unsegmented (UUDT) ECU responses
+    CanRespUudtFormat => ("CP_CanRespUUDTFormat", UniqueId, Unum32, None, CAN),
+    /// Extended address byte of physical requests
+    CanPhysReqExtAddr => ("CP_CanPhysReqExtAddr", Com, Unum32, None, CAN_ISOTP, max = 0xFF),
+    /// Extended address byte of functional requests
+    CanFuncReqExtAddr => ("CP_CanFuncReqExtAddr", Com, Unum32, None, CAN_ISOTP, max = 0xFF),
+    /// Extended address byte of segmented (USDT) ECU responses
+    CanRespUsdtExtAddr => ("CP_CanRespUSDTExtAddr", UniqueId, Unum32, None, CAN_ISOTP, max = 0xFF),
+    /// Extended address byte of unsegmented (UUDT) ECU responses
+    CanRespUudtExtAddr => ("CP_CanRespUUDTExtAddr", UniqueId, Unum32, None, CAN_ISOTP, max = 0xFF),
+    /// Block size reported by the tester in flow control frames
+    BlockSize => ("CP_BlockSize", Com, Unum32, Count, CAN_ISOTP, max = 0xFF),
+    /// Override of the block size reported by the ECU. `0xFFFFFFFF` disables the override
+    BlockSizeOverride => ("CP_BlockSizeOverride", Com, Unum32, Count, CAN_ISOTP),
+    /// Number of consecutive wait flow control frames accepted from the ECU (N_WFTmax)
+    CanMaxNumWaitFrames => ("CP_CanMaxNumWaitFrames", Com, Unum32, Count, CAN_ISOTP, max = 0xFF),
+    /// Padding byte used for CAN frames
+    CanFillerByte => ("CP_CanFillerByte", Com, Unum32, None, CAN, max = 0xFF),
+    /// Padding of CAN frames (0 = disabled, 1 = enabled)
+    CanFillerByteHandling => ("CP_CanFillerByteHandling", Com, Unum32, None, CAN, max = 1),
+    /// Data length of transmitted CAN frames when padding (0 = use the frame length)
+    CanDataSizeOffset => ("CP_CanDataSizeOffset", Com, Unum32, Count, CAN),
+    /// Bit rate switching of transmitted CAN FD frames (0 = disabled, 1 = enabled)
+    CanFdBitRateSwitch => ("CP_CanFDBitRateSwitch", Com, Unum32, None, CAN, max = 1),
+    /// Largest data length of transmitted ISO-TP frames (TX_DL). 8 sends classic CAN frames,
+    /// larger values send CAN FD frames
+    CanFdTxMaxDataLength => ("CP_CanFDTxMaxDataLength", Com, Unum32, Count, CAN_ISOTP, max = 64),
+    /// Source address of the tester
+    TesterSourceAddress => ("CP_TesterSourceAddress", Com, Unum32, None, HEADER, max = 0xFF),
+    /// Source address of ECU responses
+    EcuRespSourceAddress => ("CP_EcuRespSourceAddress", UniqueId, Unum32, None, HEADER, max = 0xFF),
+    /// Target address of physical requests
+    PhysReqTargetAddr => ("CP_PhysReqTargetAddr", Com, Unum32, None, HEADER, max = 0xFF),
+    /// Target address of functional requests
+    FuncReqTargetAddr => ("CP_FuncReqTargetAddr", Com, Unum32, None, HEADER, max = 0xFF),
+    /// Format byte of physical requests
+    PhysReqFormatPriorityType => ("CP_PhysReqFormatPriorityType", Com, Unum32, None, HEADER, max = 0xFF),
+    /// Format byte of functional requests
+    FuncReqFormatPriorityType => ("CP_FuncReqFormatPriorityType", Com, Unum32, None, HEADER, max = 0xFF),
+    /// KWP2000 header format of requests
+    HeaderFormatKw => ("CP_HeaderFormatKW", Com, Unum32, None, KLINE, max = 5),
+    /// Speed change control (0 = disabled, 1 = enabled)
+    ChangeSpeedCtrl => ("CP_ChangeSpeedCtrl", Com, Unum32, None, ALL, max = 1),
+    /// New baud rate after a speed change
+    ChangeSpeedRate => ("CP_ChangeSpeedRate", Com, Unum32, BitsPerSecond, ALL),
+    /// Message which triggers a speed change
+    ChangeSpeedMessage => ("CP_ChangeSpeedMessage", Com, ByteField, None, ALL),
+    /// Speed change behaviour on response (0 = after request, 1 = after positive response)
+    ChangeSpeedResCtrl => ("CP_ChangeSpeedResCtrl", Com, Unum32, None, ALL, max = 1),
+    /// Delay after the speed change message before switching baud rate
+    ChangeSpeedTxDelay => ("CP_ChangeSpeedTxDelay", Com, Unum32, Microseconds, ALL),
+    /// Logical address of the DoIP gateway
+    DoIpLogicalGatewayAddress => ("CP_DoIPLogicalGatewayAddress", Com, Unum32, None, DOIP, max = 0xFFFF),
+    /// Logical address of the tester
+    DoIpLogicalTesterAddress => ("CP_DoIPLogicalTesterAddress", Com, Unum32, None, DOIP, max = 0xFFFF),
+    /// Logical address of the target ECU
+    DoIpLogicalEcuAddress => ("CP_DoIPLogicalEcuAddress", UniqueId, Unum32, None, DOIP, max = 0xFFFF),
+    /// Logical functional address used for functional requests
+    DoIpLogicalFunctionalAddress => ("CP_DoIPLogicalFunctionalAddress", Com, Unum32, None, DOIP, max = 0xFFFF),
+    /// Routing activation type
+    DoIpRoutingActivationType => ("CP_DoIPRoutingActivationType", Com, Unum32, None, DOIP, max = 0xFF),
+    /// Timeout for a DoIP diagnostic message acknowledgement
+    DoIpDiagnosticAckTimeout => ("CP_DoIPDiagnosticAckTimeout", Timing, Unum32, Microseconds, DOIP),
+    /// Number of retries of a DoIP diagnostic message
+    DoIpNumberOfRetries => ("CP_DoIPNumberOfRetries", ErrHdl, Unum32, Count, DOIP),
+    /// Time between retries of a DoIP diagnostic message
+    DoIpRetryPeriod => ("CP_DoIPRetryPeriod", Timing, Unum32, Microseconds, DOIP),
+    /// Source address the tester claims on a J1939 network
+    J1939PreferredAddress => ("CP_J1939PreferredAddress", Com, Unum32, None, J1939, max = 0xFD),
+    /// 64 bit J1939 NAME of the tester (8 bytes, least significant byte first)
+    J1939Name => ("CP_J1939Name", Com, ByteField, None, J1939),
+    /// J1939 address claiming (0 = none, 1 = preferred address only, 2 = arbitrary address on loss)
+    J1939AddrClaim => ("CP_J1939AddrClaim", Init, Unum32, None, J1939, max = 2),
+    /// Time to wait for contending J1939 address claims
+    J1939AddrClaimTimeout => ("CP_J1939AddrClaimTimeout", Timing, Unum32, Microseconds, J1939),
+    /// Baud rate of the bus
+    Baudrate => ("CP_Baudrate", BusType, Unum32, BitsPerSecond, ALL),
+    /// Sample point of a bit in percent
+    BitSamplePoint => ("CP_BitSamplePoint", BusType, Unum32, Percent, CAN, max = 100),
+    /// Synchronization jump width in percent
+    SyncJumpWidth => ("CP_SyncJumpWidth", BusType, Unum32, Percent, CAN, max = 100),
+    /// Number of samples per bit (1 or 3)
+    SamplesPerBit => ("CP_SamplesPerBit", BusType, Unum32, Count, CAN, max = 3),
+    /// Baud rate of the data phase of CAN FD frames. 0 if CAN FD is not used
+    CanFdBaudrate => ("CP_CanFDBaudrate", BusType, Unum32, BitsPerSecond, CAN),
+    /// Bus termination type
+    TerminationType => ("CP_TerminationType", BusType, Unum32, None, ALL),
+    /// Listen only mode, where nothing is transmitted on the bus (0 = disabled, 1 = enabled)
+    ListenOnly => ("CP_ListenOnly", BusType, Unum32, None, ALL, max = 1),
+    /// Initialization lines of a K-Line link (0 = K and L, 1 = K only)
+    KLLineInit => ("CP_K_L_LineInit", BusType, Unum32, None, KLINE, max = 1),
+    /// K-Line pull up voltage (0 = none, 1 = 12V, 2 = 24V)
+    KLinePullup => ("CP_K_LinePullup", BusType, Unum32, None, KLINE, max = 2),
+}
+
+impl StdComParam {
+    /// Returns the standard short name of the ComParam
+    pub fn short_name(&self) -> &'static str {
+        self.def().short_name
+    }
+
+    /// Returns the class of the ComParam
+    pub fn class(&self) -> PduPc {
+        self.def().class
+    }
+
+    /// Returns the data type of the ComParam
+    pub fn data_type(&self) -> PduPt {
+        self.def().data_type
+    }
+
+    /// Returns the unit of the ComParam value
+    pub fn unit(&self) -> ComParamUnit {
+        self.def().unit
+    }
+
+    /// Returns the protocols the ComParam applies to. Empty if the ComParam applies to all protocols
+    pub fn protocols(&self) -> &'static [Protocol] {
+        self.def().protocols
+    }
+
+    /// Creates a value for a numeric ComParam, validating the range of the value
+    pub fn value(&self, value: u32) -> Result<ComParamValue, PduError> {
+        let v = match self.data_type() {
+            PduPt::Unum8 => ComParamValue::Unum8(u8::try_from(value).map_err(|_| PduError::ValueNotSupported)?),
+            PduPt::Unum16 => ComParamValue::Unum16(u16::try_from(value).map_err(|_| PduError::ValueNotSupported)?),
+            PduPt::Unum32 => ComParamValue::Unum32(value),
+            _ => return Err(PduError::InvalidParameters)
+        };
+        self.def().validate(&v)?;
+        Ok(v)
+    }
+
+    /// Creates a value for a ComParam measured in microseconds
+    pub fn duration(&self, duration: Duration) -> Result<ComParamValue, PduError> {
+        if self.unit() != ComParamUnit::Microseconds {
+            return Err(PduError::InvalidParameters);
+        }
+        let us = u32::try_from(duration.as_micros()).map_err(|_| PduError::ValueNotSupported)?;
+        self.value(us)
+    }
+
+    /// Creates a value for a [PduPt::ByteField] ComParam
+    pub fn bytes(&self, data: &[u8]) -> Result<ComParamValue, PduError> {
+        let v = ComParamValue::ByteField(data.to_vec());
+        self.def().validate(&v)?;
+        Ok(v)
+    }
+
+    /// Creates a value for a [PduPt::LongField] ComParam
+    pub fn long_field(&self, data: &[u32]) -> Result<ComParamValue, PduError> {
+        let v = ComParamValue::LongField(data.to_vec());
+        self.def().validate(&v)?;
+        Ok(v)
+    }
+
+    /// Creates a value for a [PduCpst::SessionTiming] ComParam
+    pub fn session_timing(&self, entries: &[ParamStructSessionTiming]) -> Result<ComParamValue, PduError> {
+        let v = ComParamValue::SessionTiming(entries.to_vec());
+        self.def().validate(&v)?;
+        Ok(v)
+    }
+
+    /// Creates a value for a [PduCpst::AccessTiming] ComParam
+    pub fn access_timing(&self, entries: &[ParamStructAccessTiming]) -> Result<ComParamValue, PduError> {
+        let v = ComParamValue::AccessTiming(entries.to_vec());
+        self.def().validate(&v)?;
+        Ok(v)
+    }
+
+    /// Converts a value of this ComParam to a [Duration]. Returns [None] if the ComParam
+    /// is not measured in microseconds
+    pub fn to_duration(&self, value: &ComParamValue) -> Option<Duration> {
+        if self.unit() != ComParamUnit::Microseconds {
+            return None;
+        }
+        value.as_u32().map(|us| Duration::from_micros(us as u64))
+    }
+
+    /// Validates and sets the ComParam on a ComLogicalLink
+    ///
+    /// ## Parameters
+    /// * set_com_param - The API's `PDUSetComParam` function
+    /// * cache - Object ID cache used to resolve the ComParam ID
+    /// * h_mod - Handle of the MVCI module
+    /// * h_cll - Handle of the ComLogicalLink
+    /// * value - Value to set
+    pub fn set(
+        &self,
+        set_com_param: PduSetComParamFn,
+        cache: &mut ObjectIdCache,
+        h_mod: ModuleHandle,
+        h_cll: CllHandle,
+        value: &ComParamValue
+    ) -> Result<(), PduError> {
+        self.def().validate(value)?;
+        let id = cache.resolve_object(*self)?;
+        let mut item = value.to_param_item(id, self.class());
+        match set_com_param(h_mod.raw(), h_cll.raw(), item.as_mut_ptr()) {
+            PduError::StatusNoError => Ok(()),
+            e => Err(e)
+        }
+    }
+
+    /// Reads the ComParam from the working buffer of a ComLogicalLink
+    ///
+    /// ## Parameters
+    /// * get_com_param - The API's `PDUGetComParam` function
+    /// * destroy_item - The API's `PDUDestroyItem` function
+    /// * cache - Object ID cache used to resolve the ComParam ID
+    /// * h_mod - Handle of the MVCI module
+    /// * h_cll - Handle of the ComLogicalLink
+    pub fn get(
+        &self,
+        get_com_param: PduGetComParamFn,
+        destroy_item: PduDestroyItemFn,
+        cache: &mut ObjectIdCache,
+        h_mod: ModuleHandle,
+        h_cll: CllHandle
+    ) -> Result<ComParamValue, PduError> {
+        let id = cache.resolve_object(*self)?;
+        let mut item: *mut ParamItem = std::ptr::null_mut();
+        match get_com_param(h_mod.raw(), h_cll.raw(), id.raw(), &mut item) {
+            PduError::StatusNoError => {}
+            e => return Err(e)
+        }
+        if item.is_null() {
+            return Err(PduError::FctFailed);
+        }
+        // Safety: The API has returned a valid item, which stays valid until we destroy it
+        let res = unsafe { ComParamValue::from_param_item(&*item) };
+        destroy_item(item.cast());
+        res
+    }
+}
+
+impl StandardObject for StdComParam {
+    const OBJECT_TYPE: PduObjt = PduObjt::ComParam;
+    const ALL: &'static [Self] = StdComParam::ALL;
+    fn short_name(&self) -> &'static str {
+        self.def().short_name
+    }
+}
+
+impl fmt::Display for StdComParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.short_name())
+    }
+}
+
+impl FromStr for StdComParam {
+    type Err = PduError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|p| p.short_name() == s)
+            .copied()
+            .ok_or(PduError::ComParamNotSupported)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned value of a ComParam
+pub enum ComParamValue {
+    /// [PduPt::Unum8] value
+    Unum8(u8),
+    /// [PduPt::Snum8] value
+    Snum8(i8),
+    /// [PduPt::Unum16] value
+    Unum16(u16),
+    /// [PduPt::Snum16] value
+    Snum16(i16),
+    /// [PduPt::Unum32] value
+    Unum32(u32),
+    /// [PduPt::Snum32] value
+    Snum32(i32),
+    /// [PduPt::ByteField] value
+    ByteField(Vec<u8>),
+    /// [PduPt::StructField] value containing [ParamStructSessionTiming] entries
+    SessionTiming(Vec<ParamStructSessionTiming>),
+    /// [PduPt::StructField] value containing [ParamStructAccessTiming] entries
+    AccessTiming(Vec<ParamStructAccessTiming>),
+    /// [PduPt::LongField] value
+    LongField(Vec<u32>)
+}
+
+impl ComParamValue {
+    /// Returns the data type of the value
+    pub fn data_type(&self) -> PduPt {
+        match self {
+            Self::Unum8(_) => PduPt::Unum8,
+            Self::Snum8(_) => PduPt::Snum8,
+            Self::Unum16(_) => PduPt::Unum16,
+            Self::Snum16(_) => PduPt::Snum16,
+            Self::Unum32(_) => PduPt::Unum32,
+            Self::Snum32(_) => PduPt::Snum32,
+            Self::ByteField(_) => PduPt::ByteField,
+            Self::SessionTiming(_) | Self::AccessTiming(_) => PduPt::StructField,
+            Self::LongField(_) => PduPt::LongField
+        }
+    }
+
+    /// Returns the structure type of the value, if the value is a [PduPt::StructField]
+    pub fn struct_type(&self) -> Option<PduCpst> {
+        match self {
+            Self::SessionTiming(_) => Some(PduCpst::SessionTiming),
+            Self::AccessTiming(_) => Some(PduCpst::AccessTiming),
+            _ => None
+        }
+    }
+
+    /// Returns the value as a `u32` if the value is an unsigned number
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Self::Unum8(x) => Some(*x as u32),
+            Self::Unum16(x) => Some(*x as u32),
+            Self::Unum32(x) => Some(*x),
+            _ => None
+        }
+    }
+
+    /// Returns the value as an `i64` if the value is a number
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Snum8(x) => Some(*x as i64),
+            Self::Snum16(x) => Some(*x as i64),
+            Self::Snum32(x) => Some(*x as i64),
+            _ => self.as_u32().map(i64::from)
+        }
+    }
+
+    /// Returns the value as a byte slice if the value is a [PduPt::ByteField]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::ByteField(x) => Some(x),
+            _ => None
+        }
+    }
+
+    /// Copies the value out of a [ParamItem]
+    ///
+    /// # Safety
+    /// [ParamItem::p_com_param_data] must point to valid data of the type described by
+    /// [ParamItem::com_param_data_type]
+    pub unsafe fn from_param_item(item: &ParamItem) -> Result<Self, PduError> {
+        let p = item.p_com_param_data;
+        if p.is_null() {
+            return Err(PduError::InvalidParameters);
+        }
+        Ok(match item.com_param_data_type {
+            PduPt::Unum8 => Self::Unum8(*p.cast::<u8>()),
+            PduPt::Snum8 => Self::Snum8(*p.cast::<i8>()),
+            PduPt::Unum16 => Self::Unum16(*p.cast::<u16>()),
+            PduPt::Snum16 => Self::Snum16(*p.cast::<i16>()),
+            PduPt::Unum32 => Self::Unum32(*p.cast::<u32>()),
+            PduPt::Snum32 => Self::Snum32(*p.cast::<i32>()),
+            PduPt::ByteField => {
+                let d = &*p.cast::<ParamByteFieldData>();
+                Self::ByteField(copy_array(d.p_data_array, d.param_act_len))
+            },
+            PduPt::LongField => {
+                let d = &*p.cast::<ParamLongFieldData>();
+                Self::LongField(copy_array(d.p_data_array, d.param_act_len))
+            },
+            PduPt::StructField => {
+                let d = &*p.cast::<ParamStructFieldData>();
+                match d.com_param_struct_type {
+                    PduCpst::SessionTiming => {
+                        Self::SessionTiming(copy_array(d.p_struct_array.cast(), d.param_act_entries))
+                    },
+                    PduCpst::AccessTiming => {
+                        Self::AccessTiming(copy_array(d.p_struct_array.cast(), d.param_act_entries))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Creates an owned [ParamItem] containing this value, which can be passed to the API
+    pub fn to_param_item(&self, id: ObjectId, class: PduPc) -> OwnedParamItem {
+        let mut storage = match self {
+            Self::Unum8(x) => ParamStorage::Unum8(Box::new(*x)),
+            Self::Snum8(x) => ParamStorage::Snum8(Box::new(*x)),
+            Self::Unum16(x) => ParamStorage::Unum16(Box::new(*x)),
+            Self::Snum16(x) => ParamStorage::Snum16(Box::new(*x)),
+            Self::Unum32(x) => ParamStorage::Unum32(Box::new(*x)),
+            Self::Snum32(x) => ParamStorage::Snum32(Box::new(*x)),
+            Self::ByteField(x) => {
+                let mut data = x.clone();
+                let header = Box::new(ParamByteFieldData {
+                    param_max_len: data.len() as u32,
+                    param_act_len: data.len() as u32,
+                    p_data_array: data.as_mut_ptr()
+                });
+                ParamStorage::ByteField { header, _data: data }
+            },
+            Self::LongField(x) => {
+                let mut data = x.clone();
+                let header = Box::new(ParamLongFieldData {
+                    param_max_len: data.len() as u32,
+                    param_act_len: data.len() as u32,
+                    p_data_array: data.as_mut_ptr()
+                });
+                ParamStorage::LongField { header, _data: data }
+            },
+            Self::SessionTiming(x) => {
+                let mut data = x.clone();
+                let header = Box::new(ParamStructFieldData {
+                    com_param_struct_type: PduCpst::SessionTiming,
+                    param_max_entries: data.len() as u32,
+                    param_act_entries: data.len() as u32,
+                    p_struct_array: data.as_mut_ptr().cast()
+                });
+                ParamStorage::SessionTiming { header, _data: data }
+            },
+            Self::AccessTiming(x) => {
+                let mut data = x.clone();
+                let header = Box::new(ParamStructFieldData {
+                    com_param_struct_type: PduCpst::AccessTiming,
+                    param_max_entries: data.len() as u32,
+                    param_act_entries: data.len() as u32,
+                    p_struct_array: data.as_mut_ptr().cast()
+                });
+                ParamStorage::AccessTiming { header, _data: data }
+            }
+        };
+        let p_com_param_data = storage.as_mut_ptr();
+        OwnedParamItem {
+            item: ParamItem {
+                item_type: PduIt::Param,
+                com_param_id: id.raw(),
+                com_param_data_type: self.data_type(),
+                com_param_class: class,
+                p_com_param_data
+            },
+            _storage: storage
+        }
+    }
+}
+
+/// Copies a C array into a [Vec]
+unsafe fn copy_array<T: Copy>(ptr: *const T, len: u32) -> Vec<T> {
+    if ptr.is_null() || len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(ptr, len as usize).to_vec()
+    }
+}
+
+#[derive(Debug)]
+enum ParamStorage {
+    Unum8(Box<u8>),
+    Snum8(Box<i8>),
+    Unum16(Box<u16>),
+    Snum16(Box<i16>),
+    Unum32(Box<u32>),
+    Snum32(Box<i32>),
+    ByteField { header: Box<ParamByteFieldData>, _data: Vec<u8> },
+    LongField { header: Box<ParamLongFieldData>, _data: Vec<u32> },
+    SessionTiming { header: Box<ParamStructFieldData>, _data: Vec<ParamStructSessionTiming> },
+    AccessTiming { header: Box<ParamStructFieldData>, _data: Vec<ParamStructAccessTiming> }
+}
+
+impl ParamStorage {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        match self {
+            Self::Unum8(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::Snum8(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::Unum16(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::Snum16(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::Unum32(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::Snum32(x) => std::ptr::from_mut(&mut **x).cast(),
+            Self::ByteField { header: x, .. } => std::ptr::from_mut(&mut **x).cast(),
+            Self::LongField { header: x, .. } => std::ptr::from_mut(&mut **x).cast(),
+            Self::SessionTiming { header: x, .. } | Self::AccessTiming { header: x, .. } => std::ptr::from_mut(&mut **x).cast()
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A [ParamItem] which owns the data it points to
+pub struct OwnedParamItem {
+    item: ParamItem,
+    _storage: ParamStorage
+}
+
+impl OwnedParamItem {
+    /// Returns the [ParamItem]. The data pointer remains valid for as long as this structure
+    pub fn item(&self) -> &ParamItem {
+        &self.item
+    }
+
+    /// Returns a pointer to the [ParamItem] which can be passed to the API
+    pub fn as_mut_ptr(&mut self) -> *mut ParamItem {
+        &mut self.item
+    }
+}
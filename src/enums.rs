@@ -63,7 +63,7 @@ pub enum PduCopt {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// Object type
 pub enum PduObjt {
     /// Protocol object
@@ -323,7 +323,7 @@ pub enum PduPc {
     ErrHdl = 4,
     /// BusType specific ComParam
     BusType = 5,
-    /// 
+    /// Unique response identification ComParam
     UniqueId = 6,
     /// Tester present ComParam
     TesterPresent = 7
@@ -0,0 +1,225 @@
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Type of an expected response ([ExpRespData::response_type])
+pub enum ResponseType {
+    /// Positive response (0)
+    Positive,
+    /// Negative response (1)
+    Negative
+}
+
+impl ResponseType {
+    /// Converts the raw response type. Returns [PduError::InvalidParameters] if unknown
+    pub fn from_raw(raw: u32) -> Result<Self, PduError> {
+        match raw {
+            0 => Ok(Self::Positive),
+            1 => Ok(Self::Negative),
+            _ => Err(PduError::InvalidParameters)
+        }
+    }
+
+    /// Returns the raw response type
+    pub fn to_raw(&self) -> u32 {
+        match self {
+            Self::Positive => 0,
+            Self::Negative => 1
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Owned form of [ExpRespData]
+pub struct ExpectedResponse {
+    /// Response type
+    pub response_type: ResponseType,
+    /// ID reported in [ResultData::acceptance_id] when a response matches
+    pub acceptance_id: u32,
+    /// Mask applied to the start of the response
+    pub mask: Vec<u8>,
+    /// Pattern the masked response must be equal to. Same length as [ExpectedResponse::mask]
+    pub pattern: Vec<u8>,
+    /// Unique response IDs of the ECUs which may send the response. Empty for any ECU
+    pub unique_resp_ids: Vec<u32>
+}
+
+impl ExpectedResponse {
+    /// Creates a new expected response which matches responses from any ECU.
+    /// Returns [PduError::InvalidParameters] if mask and pattern differ in length
+    pub fn new(response_type: ResponseType, acceptance_id: u32, mask: Vec<u8>, pattern: Vec<u8>) -> Result<Self, PduError> {
+        if mask.len() != pattern.len() {
+            return Err(PduError::InvalidParameters);
+        }
+        Ok(Self { response_type, acceptance_id, mask, pattern, unique_resp_ids: Vec::new() })
+    }
+
+    /// Creates a positive expected response which matches every response from any ECU
+    pub fn any(acceptance_id: u32) -> Self {
+        Self {
+            response_type: ResponseType::Positive,
+            acceptance_id,
+            mask: Vec::new(),
+            pattern: Vec::new(),
+            unique_resp_ids: Vec::new()
+        }
+    }
+
+    /// Restricts the expected response to ECUs with the given unique response IDs
+    pub fn with_unique_resp_ids(mut self, ids: Vec<u32>) -> Self {
+        self.unique_resp_ids = ids;
+        self
+    }
+
+    /// Converts [ExpRespData] passed to the API
+    ///
+    /// # Safety
+    /// The mask, pattern and unique response ID pointers must be valid for their lengths
+    pub unsafe fn from_raw(raw: &ExpRespData) -> Result<Self, PduError> {
+        let len = raw.num_mask_pattern_bytes as usize;
+        let (mask, pattern) = if len == 0 {
+            (Vec::new(), Vec::new())
+        } else if raw.p_mask_data.is_null() || raw.p_pattern_data.is_null() {
+            return Err(PduError::InvalidParameters);
+        } else {
+            (
+                std::slice::from_raw_parts(raw.p_mask_data, len).to_vec(),
+                std::slice::from_raw_parts(raw.p_pattern_data, len).to_vec()
+            )
+        };
+        let unique_resp_ids = if raw.num_unique_resp_ids == 0 {
+            Vec::new()
+        } else if raw.p_unique_resp_ids.is_null() {
+            return Err(PduError::InvalidParameters);
+        } else {
+            std::slice::from_raw_parts(raw.p_unique_resp_ids, raw.num_unique_resp_ids as usize).to_vec()
+        };
+        Ok(Self {
+            response_type: ResponseType::from_raw(raw.response_type)?,
+            acceptance_id: raw.acceptance_id,
+            mask,
+            pattern,
+            unique_resp_ids
+        })
+    }
+
+    /// Returns an [ExpRespData] structure which can be passed to the API.
+    ///
+    /// The returned structure points into this response, so it is only valid
+    /// for as long as the response is neither modified nor dropped
+    pub fn as_exp_resp_data(&mut self) -> ExpRespData {
+        fn ptr<T>(v: &mut [T]) -> *mut T {
+            if v.is_empty() { std::ptr::null_mut() } else { v.as_mut_ptr() }
+        }
+        ExpRespData {
+            response_type: self.response_type.to_raw(),
+            acceptance_id: self.acceptance_id,
+            num_mask_pattern_bytes: self.mask.len().min(self.pattern.len()) as u32,
+            p_mask_data: ptr(&mut self.mask),
+            p_pattern_data: ptr(&mut self.pattern),
+            num_unique_resp_ids: self.unique_resp_ids.len() as u32,
+            p_unique_resp_ids: ptr(&mut self.unique_resp_ids)
+        }
+    }
+
+    /// Returns true if the payload matches the mask and pattern. Payloads shorter
+    /// than the mask never match
+    pub fn matches_data(&self, data: &[u8]) -> bool {
+        data.len() >= self.mask.len()
+            && self.mask.iter().zip(&self.pattern).zip(data).all(|((m, p), d)| d & m == p & m)
+    }
+
+    /// Returns true if the ECU with the unique response ID may send this response
+    pub fn matches_ecu(&self, unique_resp_id: u32) -> bool {
+        self.unique_resp_ids.is_empty() || self.unique_resp_ids.contains(&unique_resp_id)
+    }
+
+    /// Returns true if the response from the ECU matches
+    pub fn matches(&self, data: &[u8], unique_resp_id: u32) -> bool {
+        self.matches_ecu(unique_resp_id) && self.matches_data(data)
+    }
+}
+
+/// Negative response code of a request which was received, but not yet answered
+const NRC_RESPONSE_PENDING: u8 = 0x78;
+
+fn is_response_pending(data: &[u8], protocol: Protocol) -> bool {
+    protocol.has_response_pending() && matches!(data, [0x7F, _, NRC_RESPONSE_PENDING, ..])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Result of matching a response against the expected responses of a ComPrimitive
+pub struct ResponseMatch {
+    /// Acceptance ID to report in [ResultData::acceptance_id]
+    pub acceptance_id: u32,
+    /// Type of the expected response which matched
+    pub response_type: ResponseType,
+    /// True if the response counts toward [CopCtrlData::num_receive_cycles]. Every response
+    /// counts, except a negative response which only signals that the answer is pending
+    /// (`7F xx 78`) on a protocol which has them ([Protocol::has_response_pending])
+    pub counts_as_receive: bool
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+/// Matches incoming responses against the expected responses of a ComPrimitive
+///
+/// Expected responses are checked in order, and the first match wins. A matcher without
+/// any expected responses accepts every response as positive, with an acceptance ID of 0
+pub struct ResponseMatcher {
+    expected: Vec<ExpectedResponse>
+}
+
+impl ResponseMatcher {
+    /// Creates a new matcher
+    pub fn new(expected: Vec<ExpectedResponse>) -> Self {
+        Self { expected }
+    }
+
+    /// Converts the expected response array of [CopCtrlData]
+    ///
+    /// # Safety
+    /// The expected response array, and the pointers within it, must be valid
+    pub unsafe fn from_cop_ctrl(ctrl: &CopCtrlData) -> Result<Self, PduError> {
+        if ctrl.num_possible_expected_responses == 0 {
+            return Ok(Self::default());
+        }
+        if ctrl.expected_response_array.is_null() {
+            return Err(PduError::InvalidParameters);
+        }
+        std::slice::from_raw_parts(ctrl.expected_response_array, ctrl.num_possible_expected_responses as usize)
+            .iter()
+            .map(|r| ExpectedResponse::from_raw(r))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::new)
+    }
+
+    /// Returns the expected responses
+    pub fn expected(&self) -> &[ExpectedResponse] {
+        &self.expected
+    }
+
+    /// Returns true if there are no expected responses
+    pub fn is_empty(&self) -> bool {
+        self.expected.is_empty()
+    }
+
+    /// Returns an array of [ExpRespData] which can be used as [CopCtrlData::expected_response_array].
+    ///
+    /// The returned structures point into this matcher, so they are only valid
+    /// for as long as the matcher is neither modified nor dropped
+    pub fn as_exp_resp_data(&mut self) -> Vec<ExpRespData> {
+        self.expected.iter_mut().map(|e| e.as_exp_resp_data()).collect()
+    }
+
+    /// Decides if a response from an ECU of a link with the protocol belongs to the ComPrimitive.
+    /// Returns none if no expected response matches
+    pub fn match_response(&self, data: &[u8], unique_resp_id: u32, protocol: Protocol) -> Option<ResponseMatch> {
+        if self.expected.is_empty() {
+            return Some(ResponseMatch { acceptance_id: 0, response_type: ResponseType::Positive, counts_as_receive: true });
+        }
+        self.expected.iter().find(|e| e.matches(data, unique_resp_id)).map(|e| ResponseMatch {
+            acceptance_id: e.acceptance_id,
+            response_type: e.response_type,
+            counts_as_receive: e.response_type == ResponseType::Positive || !is_response_pending(data, protocol)
+        })
+    }
+}
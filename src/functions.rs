@@ -84,9 +84,10 @@ pub type PduGetListErrorFn = extern "C" fn(
 /// Obtains resource status information from the PDU API
 /// 
 /// ## Parameters
-/// * p_resource_status - Pointer to store the status of the requested resource IDs
+/// * p_resource_status - Pointer to the list of modules and resource IDs to query. The
+///   status of each entry is written to [RscStatusItem::resource_status]
 pub type PduGetResourceStatusFn = extern "C" fn(
-    p_resource_status: *mut RscStatusItem
+    p_resource_status: *mut RscStatusData
 ) -> PduError;
 
 /// Creates a ComLogicalLink for a given resource ID
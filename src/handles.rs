@@ -0,0 +1,109 @@
+use crate::{PDU_HANDLE_UNDEF, PDU_ID_UNDEF};
+
+/// Generates a `u32` newtype whose undefined sentinel maps to [None]
+macro_rules! pdu_newtype {
+    ($(#[$meta:meta])* $name:ident, $undef:ident) => {
+        #[repr(transparent)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        $(#[$meta])*
+        pub struct $name(u32);
+
+        impl $name {
+            /// Undefined value (Used when the parameter is not applicable to the call)
+            pub const UNDEF: Self = Self($undef);
+
+            /// Creates a new value from a raw `u32`, returning [None] if the value
+            #[doc = concat!("is the undefined sentinel [", stringify!($undef), "]")]
+            pub const fn new(raw: u32) -> Option<Self> {
+                if raw == $undef {
+                    None
+                } else {
+                    Some(Self(raw))
+                }
+            }
+
+            /// Returns the raw `u32` value as used by the C API
+            pub const fn raw(self) -> u32 {
+                self.0
+            }
+
+            /// Returns true if this is the undefined value
+            pub const fn is_undef(self) -> bool {
+                self.0 == $undef
+            }
+
+            /// Converts this value into an [Option], mapping the undefined value to [None]
+            pub const fn get(self) -> Option<Self> {
+                Self::new(self.0)
+            }
+
+            /// Converts an [Option] into a value that can be passed to the C API,
+            /// mapping [None] to the undefined value
+            pub const fn from_option(x: Option<Self>) -> Self {
+                match x {
+                    Some(v) => v,
+                    None => Self::UNDEF,
+                }
+            }
+
+            /// Converts an optional value directly into the raw `u32` used by the C API
+            pub const fn option_to_raw(x: Option<Self>) -> u32 {
+                Self::from_option(x).0
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::UNDEF
+            }
+        }
+
+        impl From<Option<$name>> for $name {
+            fn from(x: Option<$name>) -> Self {
+                Self::from_option(x)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(x: $name) -> Self {
+                x.0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                if self.is_undef() {
+                    write!(f, "UNDEF")
+                } else {
+                    write!(f, "0x{:08X}", self.0)
+                }
+            }
+        }
+    };
+}
+
+pdu_newtype!(
+    /// Handle of a MVCI module. Undefined value is [PDU_HANDLE_UNDEF]
+    ModuleHandle, PDU_HANDLE_UNDEF
+);
+
+pdu_newtype!(
+    /// Handle of a ComLogicalLink. Undefined value is [PDU_HANDLE_UNDEF]
+    CllHandle, PDU_HANDLE_UNDEF
+);
+
+pdu_newtype!(
+    /// Handle of a ComPrimitive. Undefined value is [PDU_HANDLE_UNDEF]
+    CopHandle, PDU_HANDLE_UNDEF
+);
+
+pdu_newtype!(
+    /// ID of a PDU object (Protocol, bus type, ComParam, IOCTL or pin type) as returned
+    /// by [PduGetObjectIdFn](crate::PduGetObjectIdFn). Undefined value is [PDU_ID_UNDEF]
+    ObjectId, PDU_ID_UNDEF
+);
+
+pdu_newtype!(
+    /// ID of a resource on a MVCI module. Undefined value is [PDU_ID_UNDEF]
+    ResourceId, PDU_ID_UNDEF
+);
@@ -12,20 +12,52 @@
 
 //! A core implementation of the D-PDU API (ISO 22900-2).
 //! 
-//! This crate just provides the type definitions in order to use the API with Rust code.
-//! 
+//! The crate covers both sides of the API:
+//! * Applications use the type definitions of the API, and the helpers built on them, such as
+//!   [typed], [ComParams](StdComParam), expected responses ([ResponseMatcher]) and the
+//!   [ResourcePlanner], to call a D-PDU API library from Rust code.
+//! * The [provider] module implements a D-PDU API library in Rust. A vendor implements
+//!   [PduBackend](provider::PduBackend), or a [Driver](provider::Driver) for the ready made
+//!   [DriverBackend](provider::DriverBackend), and exports the `PDU*` functions with
+//!   [export_pdu_api].
+//!
+//! The [backends] module holds ready made drivers, each behind a cargo feature of the same name:
+//! * `doip` - Diagnostics over IP (ISO 13400-2)
+//! * `elm327` - ELM327 serial adapters (Linux)
+//! * `j2534` - Bridge to a J2534 PassThru library
+//! * `kline` - K-Line serial interfaces, ISO 14230 and ISO 9141-2 (Linux)
+//! * `slcan` - SLCAN (Lawicel) serial CAN adapters (Linux)
+//! * `socketcan` - Linux SocketCAN interfaces
+//!
 //! For a crate that actually uses this API, you can check the [ecu_diagnostics crate](https://docs.rs/ecu_diagnostics/latest/ecu_diagnostics/)
 //!
 //! NOTE: To match the rust naming convention, enums and structure names have been slightly renamed.
 mod structures;
 mod enums;
 mod functions;
+mod handles;
+mod resource;
+mod planner;
+mod catalogue;
+mod comparams;
+mod exp_resp;
+mod can;
+pub mod typed;
+pub mod provider;
+pub mod backends;
 
 use std::ffi::c_void;
 
 pub use functions::*;
 pub use enums::*;
 pub use structures::*;
+pub use handles::*;
+pub use resource::*;
+pub use planner::*;
+pub use catalogue::*;
+pub use comparams::*;
+pub use exp_resp::*;
+pub use can::*;
 
 /// Undefined ID value
 pub const PDU_ID_UNDEF: u32 = 0xFFFFFFFE;
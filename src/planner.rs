@@ -0,0 +1,389 @@
+use std::collections::BTreeSet;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned description of the resource a ComLogicalLink should be created on
+/// (Bus type, protocol and the pins on the data link connector)
+pub struct ResourceRequest {
+    /// Bus type ID
+    pub bus_type_id: ObjectId,
+    /// Protocol ID
+    pub protocol_id: ObjectId,
+    /// Pins used on the data link connector
+    pub pins: Vec<PinData>
+}
+
+impl ResourceRequest {
+    /// Creates a new resource request
+    pub fn new(bus_type_id: ObjectId, protocol_id: ObjectId, pins: Vec<PinData>) -> Self {
+        Self { bus_type_id, protocol_id, pins }
+    }
+
+    /// Returns a [RscData] structure which can be passed to the API.
+    ///
+    /// The returned structure points into this request, so it is only valid
+    /// for as long as the request is neither modified nor dropped
+    pub fn as_rsc_data(&mut self) -> RscData {
+        RscData {
+            bus_type_id: self.bus_type_id.raw(),
+            protocol_id: self.protocol_id.raw(),
+            num_pin_data: self.pins.len() as u32,
+            p_dlc_pin_data: if self.pins.is_empty() { std::ptr::null_mut() } else { self.pins.as_mut_ptr() }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A possible placement of a requested ComLogicalLink
+pub struct LinkCandidate {
+    /// Index of the link in the list of requested links
+    pub link: usize,
+    /// Module the link would be created on
+    pub module: ModuleHandle,
+    /// Resource on the module the link would use
+    pub resource_id: ResourceId
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Graph of all possible link placements, and which placements cannot coexist
+pub struct ConflictGraph {
+    /// Number of links which were requested
+    pub num_links: usize,
+    /// Every possible placement of every requested link
+    pub candidates: Vec<LinkCandidate>,
+    /// Pairs of indexes into [ConflictGraph::candidates] which conflict with each other.
+    /// The lower index is always first
+    pub conflicts: BTreeSet<(usize, usize)>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Two requested links which can never be active at the same time
+pub struct LinkConflict {
+    /// Index of the first link
+    pub link_a: usize,
+    /// Index of the second link
+    pub link_b: usize,
+    /// Every pair of resources which caused the conflict
+    pub resources: Vec<(LinkCandidate, LinkCandidate)>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Reason why a requested link could not be placed
+pub enum UnplacedReason {
+    /// None of the modules have a resource matching the request
+    NoResource,
+    /// Every resource for the link conflicts with a link which has already been placed
+    Conflicts {
+        /// Placed links which conflict with the link
+        with_links: Vec<usize>,
+        /// Every conflicting resource pair. The first entry is always the resource
+        /// of the link that could not be placed
+        resources: Vec<(LinkCandidate, LinkCandidate)>
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A requested link which could not be placed
+pub struct UnplacedLink {
+    /// Index of the link
+    pub link: usize,
+    /// Reason the link could not be placed
+    pub reason: UnplacedReason
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Result of planning a set of concurrent ComLogicalLinks
+pub struct LinkPlan {
+    /// Conflict graph the plan was created from
+    pub graph: ConflictGraph,
+    /// Placement of every requested link (Indexed by link). [None] if the link could not be placed
+    pub assignment: Vec<Option<LinkCandidate>>,
+    /// Links which could not be placed, and why
+    pub unplaced: Vec<UnplacedLink>,
+    /// Pairs of links which can never coexist, regardless of placement
+    pub link_conflicts: Vec<LinkConflict>
+}
+
+impl LinkPlan {
+    /// Returns true if every requested link was placed
+    pub fn is_complete(&self) -> bool {
+        self.unplaced.is_empty()
+    }
+}
+
+impl ConflictGraph {
+    /// Returns true if the two candidates (By index) conflict with each other
+    pub fn is_conflict(&self, a: usize, b: usize) -> bool {
+        self.conflicts.contains(&(a.min(b), a.max(b)))
+    }
+
+    /// Marks two candidates (By index) as conflicting with each other
+    pub fn add_conflict(&mut self, a: usize, b: usize) {
+        if a != b {
+            self.conflicts.insert((a.min(b), a.max(b)));
+        }
+    }
+
+    /// Returns the indexes of all candidates for a link
+    pub fn candidates_for(&self, link: usize) -> Vec<usize> {
+        self.candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.link == link)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Returns all pairs of links which can never coexist, as every
+    /// placement of one link conflicts with every placement of the other
+    pub fn link_conflicts(&self) -> Vec<LinkConflict> {
+        let per_link: Vec<Vec<usize>> = (0..self.num_links).map(|l| self.candidates_for(l)).collect();
+        let mut res = Vec::new();
+        for a in 0..self.num_links {
+            for b in (a + 1)..self.num_links {
+                if per_link[a].is_empty() || per_link[b].is_empty() {
+                    continue;
+                }
+                let all_conflict = per_link[a]
+                    .iter()
+                    .all(|ca| per_link[b].iter().all(|cb| self.is_conflict(*ca, *cb)));
+                if all_conflict {
+                    let mut resources = Vec::new();
+                    for ca in &per_link[a] {
+                        for cb in &per_link[b] {
+                            resources.push((self.candidates[*ca], self.candidates[*cb]));
+                        }
+                    }
+                    res.push(LinkConflict { link_a: a, link_b: b, resources });
+                }
+            }
+        }
+        res
+    }
+
+    /// Finds an assignment of links to resources which places as many links as possible
+    /// without any conflicts. When several assignments place the same number of links,
+    /// links earlier in the request list (and their earlier candidates) are preferred
+    pub fn solve(&self) -> LinkPlan {
+        let per_link: Vec<Vec<usize>> = (0..self.num_links).map(|l| self.candidates_for(l)).collect();
+        let mut current = vec![None; self.num_links];
+        let mut best = vec![None; self.num_links];
+        let mut best_count = 0;
+        self.search(&per_link, 0, 0, &mut current, &mut best, &mut best_count);
+
+        let assignment: Vec<Option<LinkCandidate>> = best.iter().map(|c| c.map(|idx: usize| self.candidates[idx])).collect();
+        let mut unplaced = Vec::new();
+        for (link, placed) in best.iter().enumerate() {
+            if placed.is_some() {
+                continue;
+            }
+            if per_link[link].is_empty() {
+                unplaced.push(UnplacedLink { link, reason: UnplacedReason::NoResource });
+                continue;
+            }
+            let mut with_links = BTreeSet::new();
+            let mut resources = Vec::new();
+            for cand in &per_link[link] {
+                for other in best.iter().flatten() {
+                    if self.is_conflict(*cand, *other) {
+                        with_links.insert(self.candidates[*other].link);
+                        resources.push((self.candidates[*cand], self.candidates[*other]));
+                    }
+                }
+            }
+            unplaced.push(UnplacedLink {
+                link,
+                reason: UnplacedReason::Conflicts { with_links: with_links.into_iter().collect(), resources }
+            });
+        }
+        LinkPlan {
+            graph: self.clone(),
+            assignment,
+            unplaced,
+            link_conflicts: self.link_conflicts()
+        }
+    }
+
+    fn search(
+        &self,
+        per_link: &[Vec<usize>],
+        link: usize,
+        placed: usize,
+        current: &mut Vec<Option<usize>>,
+        best: &mut Vec<Option<usize>>,
+        best_count: &mut usize
+    ) {
+        if placed > *best_count {
+            *best_count = placed;
+            best.clone_from(current);
+        }
+        if link == self.num_links || placed + (self.num_links - link) <= *best_count {
+            return;
+        }
+        for cand in &per_link[link] {
+            let ok = current.iter().flatten().all(|other| !self.is_conflict(*cand, *other));
+            if ok {
+                current[link] = Some(*cand);
+                self.search(per_link, link + 1, placed + 1, current, best, best_count);
+                current[link] = None;
+                if *best_count == self.num_links {
+                    return;
+                }
+            }
+        }
+        self.search(per_link, link + 1, placed, current, best, best_count);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Planner for creating multiple concurrent ComLogicalLinks across a set of modules.
+///
+/// The planner queries the API for the resource IDs matching each requested link,
+/// and the conflicts between them, then finds a set of resources which allow as
+/// many of the requested links as possible to coexist.
+///
+/// Conflicts are only found between resources of the same module. The plan does not depend on
+/// the current resource status, so resources which are locked or in use are still planned on.
+/// Check them with [get_resource_status] before creating the links
+pub struct ResourcePlanner {
+    /// The API's `PDUGetResourceIds` function
+    pub get_resource_ids: PduGetResourceIdsFn,
+    /// The API's `PDUGetConflictingResources` function
+    pub get_conflicting_resources: PduGetConflictingResourcesFn,
+    /// The API's `PDUDestroyItem` function
+    pub destroy_item: PduDestroyItemFn
+}
+
+impl ResourcePlanner {
+    /// Creates a new resource planner
+    pub fn new(
+        get_resource_ids: PduGetResourceIdsFn,
+        get_conflicting_resources: PduGetConflictingResourcesFn,
+        destroy_item: PduDestroyItemFn
+    ) -> Self {
+        Self { get_resource_ids, get_conflicting_resources, destroy_item }
+    }
+
+    /// Returns the resource IDs on a module matching a resource request. A list without
+    /// module entries is treated as empty
+    pub fn resource_ids(&self, h_mod: ModuleHandle, request: &ResourceRequest) -> Result<Vec<ResourceId>, PduError> {
+        let mut request = request.clone();
+        let mut rsc_data = request.as_rsc_data();
+        let mut list: *mut RscIdItem = std::ptr::null_mut();
+        match (self.get_resource_ids)(h_mod.raw(), &mut rsc_data, &mut list) {
+            PduError::StatusNoError => {}
+            e => return Err(e)
+        }
+        if list.is_null() {
+            return Ok(Vec::new());
+        }
+        let mut res = Vec::new();
+        // Safety: The API has returned a valid item, which stays valid until we destroy it
+        unsafe {
+            let item = &*list;
+            if !item.p_id_item_data.is_null() {
+                for i in 0..item.num_modules as usize {
+                    let data = &*item.p_id_item_data.add(i);
+                    if data.h_mod != h_mod.raw() || data.p_resource_id_array.is_null() {
+                        continue;
+                    }
+                    for j in 0..data.num_ids as usize {
+                        if let Some(id) = ResourceId::new(*data.p_resource_id_array.add(j)) {
+                            res.push(id);
+                        }
+                    }
+                }
+            }
+        }
+        (self.destroy_item)(list.cast());
+        Ok(res)
+    }
+
+    /// Returns the resources on the given modules which conflict with `resource_id`
+    pub fn conflicting_resources(
+        &self,
+        resource_id: ResourceId,
+        modules: &[ModuleHandle]
+    ) -> Result<Vec<(ModuleHandle, ResourceId)>, PduError> {
+        let mut module_data: Vec<ModuleData> = modules
+            .iter()
+            .map(|h_mod| ModuleData {
+                module_type_id: PDU_ID_UNDEF,
+                h_mod: h_mod.raw(),
+                vendor_module_name: std::ptr::null_mut(),
+                vendor_additional_info: std::ptr::null_mut(),
+                status: PduStatus::ModstReady
+            })
+            .collect();
+        let mut module_item = ModuleItem {
+            item_type: PduIt::ModuleId,
+            num_entries: module_data.len() as u32,
+            p_module_data: module_data.as_mut_ptr()
+        };
+        let mut list: *mut RscConflictItem = std::ptr::null_mut();
+        match (self.get_conflicting_resources)(resource_id.raw(), &mut module_item, &mut list) {
+            PduError::StatusNoError => {}
+            e => return Err(e)
+        }
+        if list.is_null() {
+            return Ok(Vec::new());
+        }
+        let mut res = Vec::new();
+        // Safety: The API has returned a valid item, which stays valid until we destroy it
+        unsafe {
+            let item = &*list;
+            if !item.p_rsc_conflict_data.is_null() {
+                for i in 0..item.num_entries as usize {
+                    let data = &*item.p_rsc_conflict_data.add(i);
+                    if let (Some(h_mod), Some(id)) = (ModuleHandle::new(data.h_mod), ResourceId::new(data.resource_id)) {
+                        res.push((h_mod, id));
+                    }
+                }
+            }
+        }
+        (self.destroy_item)(list.cast());
+        Ok(res)
+    }
+
+    /// Queries the API to build a conflict graph of all possible placements of the requested links
+    /// on the given modules
+    pub fn build_graph(&self, links: &[ResourceRequest], modules: &[ModuleHandle]) -> Result<ConflictGraph, PduError> {
+        let mut graph = ConflictGraph { num_links: links.len(), ..Default::default() };
+        for (link, request) in links.iter().enumerate() {
+            for h_mod in modules {
+                for resource_id in self.resource_ids(*h_mod, request)? {
+                    graph.candidates.push(LinkCandidate { link, module: *h_mod, resource_id });
+                }
+            }
+        }
+
+        let resource_ids: BTreeSet<ResourceId> = graph.candidates.iter().map(|c| c.resource_id).collect();
+        for resource_id in resource_ids {
+            let conflicts = self.conflicting_resources(resource_id, modules)?;
+            let mut pairs = Vec::new();
+            for (a_idx, a) in graph.candidates.iter().enumerate() {
+                if a.resource_id != resource_id {
+                    continue;
+                }
+                for (b_idx, b) in graph.candidates.iter().enumerate() {
+                    if a.link == b.link {
+                        continue;
+                    }
+                    if a.module == b.module && conflicts.contains(&(b.module, b.resource_id)) {
+                        pairs.push((a_idx, b_idx));
+                    }
+                }
+            }
+            for (a, b) in pairs {
+                graph.add_conflict(a, b);
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Plans the placement of the requested links on the given modules
+    pub fn plan(&self, links: &[ResourceRequest], modules: &[ModuleHandle]) -> Result<LinkPlan, PduError> {
+        Ok(self.build_graph(links, modules)?.solve())
+    }
+}
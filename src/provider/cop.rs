@@ -0,0 +1,737 @@
+//! ComPrimitive execution engine
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant}
+};
+
+use crate::*;
+
+use super::{Event, EventData, EventQueue, LinkParams, PduTag, ResultEvent};
+
+/// Default time to wait for a response after the last send of a ComPrimitive
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Default time to wait for the final response after a response pending (`7F xx 78`)
+pub const DEFAULT_PENDING_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// Default time after the first response pending until a ComPrimitive stops waiting for the
+/// final response, if [PendingHandling::UntilTimeout] is used
+pub const DEFAULT_PENDING_COMPLETION_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Default number of ComPrimitives the transmit queue of a link can hold
+pub const DEFAULT_TX_QUEUE_SIZE: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Number of send or receive cycles of a ComPrimitive
+pub enum Cycles {
+    /// Repeat until the ComPrimitive is cancelled (-1)
+    Infinite,
+    /// Fixed number of cycles
+    Count(u32)
+}
+
+impl Cycles {
+    /// Converts the raw cycle count of [CopCtrlData]. Values below -1 are invalid
+    pub fn from_raw(raw: i32) -> Result<Self, PduError> {
+        match raw {
+            -1 => Ok(Self::Infinite),
+            x if x >= 0 => Ok(Self::Count(x as u32)),
+            _ => Err(PduError::InvalidParameters)
+        }
+    }
+
+    /// Converts to the raw cycle count of [CopCtrlData]
+    pub fn to_raw(&self) -> i32 {
+        match self {
+            Self::Infinite => -1,
+            Self::Count(x) => (*x).min(i32::MAX as u32) as i32
+        }
+    }
+
+    /// Returns true if `done` cycles completes the cycle count
+    pub fn is_done(&self, done: u32) -> bool {
+        match self {
+            Self::Infinite => false,
+            Self::Count(x) => done >= *x
+        }
+    }
+
+    /// Returns true if there are no cycles
+    pub fn is_none(&self) -> bool {
+        *self == Self::Count(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Handling of response pending (`7F xx 78`) responses (`CP_RC78Handling`)
+pub enum PendingHandling {
+    /// A response pending is a final negative response (0)
+    Disabled,
+    /// Waits for the final response until the completion timeout (`CP_RC78CompletionTimeout`)
+    /// after the first response pending (1)
+    UntilTimeout(Duration),
+    /// Waits for the final response for as long as the ECU sends response pending (2)
+    Unlimited
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Response timeouts of the ComPrimitives of a ComLogicalLink
+pub struct ResponseTiming {
+    /// Time to wait for a response after the last send cycle (`CP_P2Max`)
+    pub p2_max: Duration,
+    /// Time to wait for the final response after a response pending (`CP_P2Star`)
+    pub p2_star: Duration,
+    /// Handling of response pending
+    pub pending: PendingHandling
+}
+
+impl Default for ResponseTiming {
+    fn default() -> Self {
+        Self { p2_max: DEFAULT_RESPONSE_TIMEOUT, p2_star: DEFAULT_PENDING_TIMEOUT, pending: PendingHandling::Unlimited }
+    }
+}
+
+impl ResponseTiming {
+    /// Reads the response timing from the ComParams of a link. ComParams the link does not
+    /// support keep their defaults
+    pub fn from_params(params: &LinkParams) -> Self {
+        let default = Self::default();
+        let completion = params.get_duration(StdComParam::Rc78CompletionTimeout).unwrap_or(DEFAULT_PENDING_COMPLETION_TIMEOUT);
+        Self {
+            p2_max: params.get_duration(StdComParam::P2Max).unwrap_or(default.p2_max),
+            p2_star: params.get_duration(StdComParam::P2Star).unwrap_or(default.p2_star),
+            pending: match params.get_u32(StdComParam::Rc78Handling) {
+                Some(0) => PendingHandling::Disabled,
+                Some(1) => PendingHandling::UntilTimeout(completion),
+                _ => default.pending
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of [CopCtrlData]
+pub struct CopControl {
+    /// Cycle time of a cyclic ComPrimitive, or the delay of [PduCopt::Delay]
+    pub time: Duration,
+    /// Number of send cycles
+    pub send_cycles: Cycles,
+    /// Number of receive cycles
+    pub receive_cycles: Cycles,
+    /// ComParams are temporarily applied to this ComPrimitive only
+    pub temp_param_update: bool,
+    /// Transmit flag bytes
+    pub tx_flag: Vec<u8>,
+    /// Responses which belong to the ComPrimitive
+    pub expected_responses: ResponseMatcher
+}
+
+impl Default for CopControl {
+    fn default() -> Self {
+        Self {
+            time: Duration::ZERO,
+            send_cycles: Cycles::Count(1),
+            receive_cycles: Cycles::Count(1),
+            temp_param_update: false,
+            tx_flag: Vec::new(),
+            expected_responses: ResponseMatcher::default()
+        }
+    }
+}
+
+impl CopControl {
+    /// Converts [CopCtrlData] passed to `PDUStartComPrimitive`
+    ///
+    /// # Safety
+    /// The flag data and expected response pointers of the control data must be valid
+    pub unsafe fn from_raw(ctrl: &CopCtrlData) -> Result<Self, PduError> {
+        let tx_flag = if ctrl.tx_flag.p_flag_data.is_null() || ctrl.tx_flag.num_flag_bytes == 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(ctrl.tx_flag.p_flag_data, ctrl.tx_flag.num_flag_bytes as usize).to_vec()
+        };
+        Ok(Self {
+            time: Duration::from_millis(ctrl.time as u64),
+            send_cycles: Cycles::from_raw(ctrl.num_send_cycles)?,
+            receive_cycles: Cycles::from_raw(ctrl.num_receive_cycles)?,
+            temp_param_update: ctrl.temp_param_update != 0,
+            tx_flag,
+            expected_responses: ResponseMatcher::from_cop_ctrl(ctrl)?
+        })
+    }
+}
+
+/// Returns the error event of a ComPrimitive whose message could not be sent
+pub fn send_error_event(error: PduError) -> PduErrorEvt {
+    match error {
+        PduError::CommPcToVciFailed => PduErrorEvt::LostCommToVCI,
+        _ => PduErrorEvt::TxError
+    }
+}
+
+/// I/O of the ComLogicalLink which the [CopEngine] drives
+pub trait CopIo {
+    /// Sends one cycle of a [PduCopt::SendRecv] ComPrimitive
+    fn send(&mut self, h_cop: CopHandle, data: &[u8], ctrl: &CopControl) -> Result<(), PduError>;
+    /// Returns the error event sent to the ComPrimitive after [CopIo::send] failed. The default
+    /// is [send_error_event]
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        send_error_event(error)
+    }
+    /// Executes a [PduCopt::StartComm], [PduCopt::StopComm], [PduCopt::UpdateParam] or
+    /// [PduCopt::RestoreParam] ComPrimitive. Any returned data is sent to the application
+    /// as a result
+    fn execute(&mut self, h_cop: CopHandle, cop_type: PduCopt, data: &[u8]) -> Result<Option<Vec<u8>>, PduError>;
+    /// Returns the current timestamp in microseconds
+    fn timestamp(&self) -> u32;
+}
+
+#[derive(Debug)]
+struct CopRun {
+    h_cop: CopHandle,
+    cop_type: PduCopt,
+    data: Vec<u8>,
+    ctrl: CopControl,
+    tag: PduTag,
+    status: PduStatus,
+    sends: u32,
+    receives: u32,
+    next_send: Option<Instant>,
+    deadline: Option<Instant>,
+    pending_since: Option<Instant>
+}
+
+impl CopRun {
+    fn sends_done(&self) -> bool {
+        self.ctrl.send_cycles.is_done(self.sends)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.sends_done() && self.ctrl.receive_cycles.is_done(self.receives)
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.status, PduStatus::CopstFinished | PduStatus::CopstCancelled)
+    }
+
+    /// Returns true if a waiting ComPrimitive can leave the head of the queue, letting the next
+    /// ComPrimitive start. This is the case for cyclic sends and infinite receives
+    fn runs_in_background(&self) -> bool {
+        self.status == PduStatus::CopstWaiting && (!self.sends_done() || self.ctrl.receive_cycles == Cycles::Infinite)
+    }
+
+    fn match_response(&self, response: &ResultEvent, protocol: Protocol) -> Option<ResponseMatch> {
+        if self.status != PduStatus::CopstWaiting || self.ctrl.receive_cycles.is_done(self.receives) {
+            return None;
+        }
+        self.ctrl.expected_responses.match_response(&response.data, response.unique_resp_identifier, protocol)
+    }
+
+    fn wake_time(&self, suspended: bool) -> Option<Instant> {
+        let next_send = if suspended { None } else { self.next_send };
+        match (next_send, self.deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        }
+    }
+
+    fn event(&self, timestamp: u32, data: EventData) -> Event {
+        Event { h_cop: Some(self.h_cop), cop_tag: self.tag, timestamp, data }
+    }
+
+    fn set_status(&mut self, status: PduStatus, timestamp: u32, events: &mut Vec<Event>) {
+        if self.status != status {
+            self.status = status;
+            events.push(self.event(timestamp, EventData::Status(status)));
+        }
+    }
+
+    fn error(&mut self, code: PduErrorEvt, timestamp: u32, events: &mut Vec<Event>) {
+        events.push(self.event(timestamp, EventData::Error(ErrorData { error_code_id: code, extra_error_info_id: 0 })));
+        self.set_status(PduStatus::CopstFinished, timestamp, events);
+    }
+
+    /// Waits for a response after the last send cycle
+    fn wait_for_response(&mut self, now: Instant, timing: &ResponseTiming) {
+        if self.sends_done() && self.ctrl.receive_cycles != Cycles::Infinite && !self.ctrl.receive_cycles.is_done(self.receives) {
+            self.deadline = Some(now + timing.p2_max);
+        }
+    }
+
+    /// Performs one send cycle. Returns false if the ComPrimitive finished with an error
+    fn send(&mut self, now: Instant, io: &mut dyn CopIo, timing: &ResponseTiming, events: &mut Vec<Event>) -> bool {
+        if let Err(e) = io.send(self.h_cop, &self.data, &self.ctrl) {
+            let code = io.send_error(e);
+            self.error(code, io.timestamp(), events);
+            return false;
+        }
+        self.sends += 1;
+        self.next_send = if self.sends_done() { None } else { Some(now + self.ctrl.time) };
+        self.wait_for_response(now, timing);
+        true
+    }
+
+    /// Starts the ComPrimitive at the head of the queue
+    fn start(&mut self, now: Instant, io: &mut dyn CopIo, timing: &ResponseTiming, events: &mut Vec<Event>) {
+        self.set_status(PduStatus::CopstExecuting, io.timestamp(), events);
+        match self.cop_type {
+            PduCopt::Delay => {
+                self.deadline = Some(now + self.ctrl.time);
+            },
+            PduCopt::SendRecv => {
+                if !self.ctrl.send_cycles.is_none() && !self.send(now, io, timing, events) {
+                    return;
+                }
+                if self.is_complete() {
+                    self.set_status(PduStatus::CopstFinished, io.timestamp(), events);
+                } else {
+                    self.wait_for_response(now, timing);
+                    self.set_status(PduStatus::CopstWaiting, io.timestamp(), events);
+                }
+            },
+            cop_type => match io.execute(self.h_cop, cop_type, &self.data) {
+                Ok(Some(data)) => {
+                    let ts = io.timestamp();
+                    let result = ResultEvent { data, start_msg_timestamp: ts, ..Default::default() };
+                    events.push(self.event(ts, EventData::Result(result)));
+                    self.set_status(PduStatus::CopstFinished, ts, events);
+                },
+                Ok(None) => self.set_status(PduStatus::CopstFinished, io.timestamp(), events),
+                Err(_) => {
+                    let code = if cop_type == PduCopt::StartComm { PduErrorEvt::InitError } else { PduErrorEvt::ProtErr };
+                    self.error(code, io.timestamp(), events)
+                }
+            }
+        }
+    }
+
+    /// Handles cycle and timeout deadlines of a running ComPrimitive
+    fn service(&mut self, now: Instant, io: &mut dyn CopIo, context: &Context, events: &mut Vec<Event>) {
+        if !context.suspended && self.next_send.is_some_and(|t| t <= now) {
+            if !self.send(now, io, &context.timing, events) {
+                return;
+            }
+            if self.is_complete() {
+                self.set_status(PduStatus::CopstFinished, io.timestamp(), events);
+                return;
+            }
+        }
+        if self.deadline.is_some_and(|t| t <= now) {
+            self.deadline = None;
+            if self.cop_type == PduCopt::Delay {
+                self.set_status(PduStatus::CopstFinished, io.timestamp(), events);
+            } else {
+                self.error(PduErrorEvt::RxTimeout, io.timestamp(), events);
+            }
+        }
+    }
+
+    /// Takes a response which matched the ComPrimitive
+    fn accept(&mut self, now: Instant, m: ResponseMatch, mut response: ResultEvent, timing: &ResponseTiming, events: &mut Vec<Event>) {
+        let ts = response.start_msg_timestamp;
+        let pending = !m.counts_as_receive && timing.pending != PendingHandling::Disabled;
+        response.acceptance_id = m.acceptance_id;
+        if !pending {
+            self.receives += 1;
+            self.pending_since = None;
+        }
+        events.push(self.event(ts, EventData::Result(response)));
+        if self.is_complete() {
+            self.set_status(PduStatus::CopstFinished, ts, events);
+        } else if self.sends_done() && self.ctrl.receive_cycles != Cycles::Infinite {
+            self.deadline = Some(match timing.pending {
+                _ if !pending => now + timing.p2_max,
+                PendingHandling::UntilTimeout(limit) => {
+                    let since = *self.pending_since.get_or_insert(now);
+                    (now + timing.p2_star).min(since + limit)
+                },
+                _ => now + timing.p2_star
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Settings of the engine, copied out so ComPrimitives can run with the engine unlocked
+struct Context {
+    timing: ResponseTiming,
+    suspended: bool
+}
+
+#[derive(Debug)]
+struct EngineState {
+    protocol: Protocol,
+    queue: VecDeque<CopRun>,
+    background: Vec<CopRun>,
+    statuses: HashMap<CopHandle, PduStatus>,
+    timing: ResponseTiming,
+    tx_capacity: u32,
+    suspended: bool,
+    /// ComPrimitives taken out of the engine while they send
+    in_flight: Vec<CopHandle>,
+    /// ComPrimitives cancelled while they were in flight, with the timestamp of the cancel
+    cancelled: Vec<(CopHandle, u32)>
+}
+
+impl EngineState {
+    fn context(&self) -> Context {
+        Context { timing: self.timing, suspended: self.suspended }
+    }
+
+    fn set_status(&mut self, run: &mut CopRun, status: PduStatus, timestamp: u32, events: &mut Vec<Event>) {
+        run.set_status(status, timestamp, events);
+        self.statuses.insert(run.h_cop, run.status);
+    }
+
+    /// Takes a ComPrimitive out of the engine to run it unlocked
+    fn take(&mut self, run: &CopRun) {
+        self.in_flight.push(run.h_cop);
+    }
+
+    /// Returns a ComPrimitive which ran unlocked, cancelling it if this was requested meanwhile
+    fn settle(&mut self, run: &mut CopRun, events: &mut Vec<Event>) {
+        self.in_flight.retain(|h| *h != run.h_cop);
+        if let Some(idx) = self.cancelled.iter().position(|(h, _)| *h == run.h_cop) {
+            let (_, timestamp) = self.cancelled.remove(idx);
+            if !run.is_done() {
+                run.set_status(PduStatus::CopstCancelled, timestamp, events);
+            }
+        }
+        self.statuses.insert(run.h_cop, run.status);
+    }
+}
+
+/// Pushes events collected while the engine was locked. Event callbacks may call back into
+/// the API, so events are never pushed with the lock held
+fn flush(events: &EventQueue, pending: Vec<Event>) {
+    for event in pending {
+        events.push(event);
+    }
+}
+
+#[derive(Debug)]
+/// Scheduler of the ComPrimitives of one ComLogicalLink
+///
+/// ComPrimitives are executed in the order they were started, and each one moves through
+/// [PduStatus::CopstIdle] → [PduStatus::CopstExecuting] → [PduStatus::CopstWaiting] →
+/// [PduStatus::CopstFinished] or [PduStatus::CopstCancelled], with a status event being
+/// sent on every transition.
+///
+/// A ComPrimitive blocks the queue until it finishes, unless it has further send cycles or
+/// receives infinitely, in which case it continues in the background so the next ComPrimitive
+/// can start.
+///
+/// ComPrimitives which have not started yet form the transmit queue of the link. The queue is
+/// bounded ([PduError::TxQueueFull]), and can be suspended, resumed and cleared by IOCTLs.
+///
+/// The engine does not own a thread. The link's worker calls [CopEngine::poll] whenever the
+/// time it returns is reached, and [CopEngine::on_response] for every received message. The
+/// engine is unlocked while [CopIo] sends, so a blocking send does not hold up the API. A
+/// ComPrimitive cancelled while it sends is cancelled once the send returns.
+pub struct CopEngine {
+    state: Mutex<EngineState>
+}
+
+impl CopEngine {
+    /// Creates an empty engine for a link with the protocol
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            state: Mutex::new(EngineState {
+                protocol,
+                queue: VecDeque::new(),
+                background: Vec::new(),
+                statuses: HashMap::new(),
+                timing: ResponseTiming::default(),
+                tx_capacity: DEFAULT_TX_QUEUE_SIZE,
+                suspended: false,
+                in_flight: Vec::new(),
+                cancelled: Vec::new()
+            })
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EngineState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets the response timeouts of ComPrimitives
+    pub fn set_response_timing(&self, timing: ResponseTiming) {
+        self.lock().timing = timing;
+    }
+
+    /// Returns the response timeouts of ComPrimitives
+    pub fn response_timing(&self) -> ResponseTiming {
+        self.lock().timing
+    }
+
+    /// Sets the number of ComPrimitives the transmit queue can hold (`PDU_IOCTL_SET_BUFFER_SIZE`).
+    /// ComPrimitives already queued are kept, even if they exceed the new size
+    pub fn set_tx_capacity(&self, capacity: u32) -> Result<(), PduError> {
+        if capacity == 0 {
+            return Err(PduError::InvalidParameters);
+        }
+        self.lock().tx_capacity = capacity;
+        Ok(())
+    }
+
+    /// Returns the number of ComPrimitives the transmit queue can hold
+    pub fn tx_capacity(&self) -> u32 {
+        self.lock().tx_capacity
+    }
+
+    /// Returns the number of ComPrimitives waiting in the transmit queue
+    pub fn tx_pending(&self) -> usize {
+        self.lock().queue.iter().filter(|r| r.status == PduStatus::CopstIdle).count()
+    }
+
+    /// Stops transmission (`PDU_IOCTL_SUSPEND_TX_QUEUE`). Queued ComPrimitives are not started, and
+    /// cyclic ComPrimitives skip their send cycles until the queue is resumed
+    pub fn suspend_tx(&self) {
+        self.lock().suspended = true;
+    }
+
+    /// Restarts transmission (`PDU_IOCTL_RESUME_TX_QUEUE`)
+    pub fn resume_tx(&self) {
+        self.lock().suspended = false;
+    }
+
+    /// Returns true if transmission is suspended
+    pub fn is_tx_suspended(&self) -> bool {
+        self.lock().suspended
+    }
+
+    /// Cancels every ComPrimitive in the transmit queue which has not started yet
+    /// (`PDU_IOCTL_CLEAR_TX_QUEUE`). Returns the cancelled ComPrimitives
+    pub fn clear_tx_queue(&self, timestamp: u32, events: &EventQueue) -> Vec<CopHandle> {
+        let mut out = Vec::new();
+        let cancelled = {
+            let mut state = self.lock();
+            let (mut pending, running): (VecDeque<CopRun>, VecDeque<CopRun>) =
+                state.queue.drain(..).partition(|r| r.status == PduStatus::CopstIdle);
+            state.queue = running;
+            pending
+                .iter_mut()
+                .map(|run| {
+                    state.set_status(run, PduStatus::CopstCancelled, timestamp, &mut out);
+                    run.h_cop
+                })
+                .collect()
+        };
+        flush(events, out);
+        cancelled
+    }
+
+    /// Queues a ComPrimitive in the [PduStatus::CopstIdle] state. Returns [PduError::TxQueueFull]
+    /// if the transmit queue is full
+    pub fn enqueue(
+        &self,
+        h_cop: CopHandle,
+        cop_type: PduCopt,
+        data: Vec<u8>,
+        ctrl: CopControl,
+        tag: PduTag
+    ) -> Result<(), PduError> {
+        if cop_type == PduCopt::SendRecv {
+            if ctrl.send_cycles.is_none() && ctrl.receive_cycles.is_none() {
+                return Err(PduError::InvalidParameters);
+            }
+            if !ctrl.send_cycles.is_none() && data.is_empty() {
+                return Err(PduError::InvalidParameters);
+            }
+        }
+        let mut state = self.lock();
+        if state.statuses.contains_key(&h_cop) {
+            return Err(PduError::InvalidHandle);
+        }
+        let pending = state.queue.iter().filter(|r| r.status == PduStatus::CopstIdle).count();
+        if pending >= state.tx_capacity as usize {
+            return Err(PduError::TxQueueFull);
+        }
+        state.statuses.insert(h_cop, PduStatus::CopstIdle);
+        state.queue.push_back(CopRun {
+            h_cop,
+            cop_type,
+            data,
+            ctrl,
+            tag,
+            status: PduStatus::CopstIdle,
+            sends: 0,
+            receives: 0,
+            next_send: None,
+            deadline: None,
+            pending_since: None
+        });
+        Ok(())
+    }
+
+    /// Returns the status of a ComPrimitive
+    pub fn status(&self, h_cop: CopHandle) -> Option<PduStatus> {
+        self.lock().statuses.get(&h_cop).copied()
+    }
+
+    /// Forgets the status of a finished or cancelled ComPrimitive
+    pub fn forget(&self, h_cop: CopHandle) {
+        let mut state = self.lock();
+        if state.statuses.get(&h_cop).is_some_and(|s| matches!(s, PduStatus::CopstFinished | PduStatus::CopstCancelled)) {
+            state.statuses.remove(&h_cop);
+        }
+    }
+
+    /// Returns true if no ComPrimitives are queued or running
+    pub fn is_idle(&self) -> bool {
+        let state = self.lock();
+        state.queue.is_empty() && state.background.is_empty() && state.in_flight.is_empty()
+    }
+
+    /// Cancels a queued or running ComPrimitive. Returns [PduError::InvalidHandle] if the
+    /// ComPrimitive is unknown or already finished
+    pub fn cancel(&self, h_cop: CopHandle, timestamp: u32, events: &EventQueue) -> Result<(), PduError> {
+        let mut out = Vec::new();
+        {
+            let mut state = self.lock();
+            if state.in_flight.contains(&h_cop) {
+                if !state.cancelled.iter().any(|(h, _)| *h == h_cop) {
+                    state.cancelled.push((h_cop, timestamp));
+                }
+                return Ok(());
+            }
+            let mut run = if let Some(idx) = state.queue.iter().position(|r| r.h_cop == h_cop) {
+                state.queue.remove(idx)
+            } else {
+                state.background.iter().position(|r| r.h_cop == h_cop).map(|idx| state.background.remove(idx))
+            }
+            .ok_or(PduError::InvalidHandle)?;
+            state.set_status(&mut run, PduStatus::CopstCancelled, timestamp, &mut out);
+        }
+        flush(events, out);
+        Ok(())
+    }
+
+    /// Cancels every ComPrimitive. This is used when the ComLogicalLink is disconnected
+    pub fn cancel_all(&self, timestamp: u32, events: &EventQueue) {
+        let mut out = Vec::new();
+        {
+            let mut state = self.lock();
+            let mut runs: Vec<CopRun> = state.queue.drain(..).collect();
+            runs.append(&mut state.background);
+            for mut run in runs {
+                state.set_status(&mut run, PduStatus::CopstCancelled, timestamp, &mut out);
+            }
+            let in_flight = state.in_flight.clone();
+            state.cancelled.extend(in_flight.into_iter().map(|h| (h, timestamp)));
+        }
+        flush(events, out);
+    }
+
+    /// Runs all ComPrimitives which are due. Returns when the engine next has to be polled, if
+    /// any ComPrimitive is waiting on time
+    pub fn poll(&self, now: Instant, io: &mut dyn CopIo, events: &EventQueue) -> Option<Instant> {
+        let mut out = Vec::new();
+
+        let (mut background, context) = {
+            let mut state = self.lock();
+            let background = std::mem::take(&mut state.background);
+            for run in background.iter() {
+                state.take(run);
+            }
+            (background, state.context())
+        };
+        for run in background.iter_mut() {
+            run.service(now, io, &context, &mut out);
+        }
+        {
+            let mut state = self.lock();
+            for run in background.iter_mut() {
+                state.settle(run, &mut out);
+            }
+            background.retain(|r| !r.is_done());
+            state.background.append(&mut background);
+        }
+
+        loop {
+            let (mut run, context) = {
+                let mut state = self.lock();
+                let Some(run) = state.queue.pop_front() else {
+                    break;
+                };
+                if run.status == PduStatus::CopstIdle && state.suspended {
+                    state.queue.push_front(run);
+                    break;
+                }
+                state.take(&run);
+                (run, state.context())
+            };
+            if run.status == PduStatus::CopstIdle {
+                run.start(now, io, &context.timing, &mut out);
+            } else {
+                run.service(now, io, &context, &mut out);
+            }
+            let mut state = self.lock();
+            state.settle(&mut run, &mut out);
+            if run.is_done() {
+                continue;
+            }
+            if run.runs_in_background() {
+                state.background.push(run);
+                continue;
+            }
+            state.queue.push_front(run);
+            break;
+        }
+
+        let state = self.lock();
+        let suspended = state.suspended;
+        let wake = state.background.iter().chain(state.queue.front()).filter_map(|r| r.wake_time(suspended)).min();
+        drop(state);
+        flush(events, out);
+        wake
+    }
+
+    /// Passes a received message to the first ComPrimitive waiting for a response which the
+    /// message matches, sending it to the application as a result with the matching acceptance ID.
+    /// The head of the queue is served first, then background ComPrimitives in the order they
+    /// were started. Negative responses count toward the receive cycles as well, so an ECU which
+    /// rejects the request does not leave the ComPrimitive waiting for a timeout.
+    ///
+    /// On protocols with response pending ([Protocol::has_response_pending]), a response
+    /// pending (`7F xx 78`) does not count, and the ComPrimitive waits for the final
+    /// response for P2Star instead of P2Max, as set by [PendingHandling]. With
+    /// [PendingHandling::Disabled] it is a final negative response.
+    ///
+    /// Returns false if no ComPrimitive was waiting for a response. The engine must be polled
+    /// afterwards, as the response may have finished the head of the queue
+    pub fn on_response(&self, now: Instant, response: ResultEvent, events: &EventQueue) -> bool {
+        let mut out = Vec::new();
+        let mut state = self.lock();
+        let (timing, protocol) = (state.timing, state.protocol);
+        if let Some(mut run) = state.queue.pop_front() {
+            if let Some(m) = run.match_response(&response, protocol) {
+                run.accept(now, m, response, &timing, &mut out);
+                state.statuses.insert(run.h_cop, run.status);
+                if !run.is_done() {
+                    state.queue.push_front(run);
+                }
+                drop(state);
+                flush(events, out);
+                return true;
+            }
+            state.queue.push_front(run);
+        }
+        let mut background = std::mem::take(&mut state.background);
+        let consumed = match background.iter_mut().find_map(|r| r.match_response(&response, protocol).map(|m| (r, m))) {
+            Some((run, m)) => {
+                run.accept(now, m, response, &timing, &mut out);
+                state.statuses.insert(run.h_cop, run.status);
+                true
+            },
+            None => false
+        };
+        background.retain(|r| !r.is_done());
+        state.background = background;
+        drop(state);
+        flush(events, out);
+        consumed
+    }
+}
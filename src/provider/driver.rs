@@ -0,0 +1,1311 @@
+//! Generic [PduBackend] built on a vehicle interface driver
+//!
+//! Most of the D-PDU API is the same for every VCI: handles, items, event queues, the
+//! ComPrimitive scheduler, ComParam buffers, resources and state machines. [DriverBackend]
+//! implements all of that on top of a [Driver], which only has to enumerate its modules and
+//! open a [Channel] to move messages for a ComLogicalLink.
+//!
+//! Each connected ComLogicalLink has a worker thread which owns the channel, runs the
+//! ComPrimitives of the link and passes received messages through the link's [MessageFilters].
+
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    net::IpAddr,
+    ptr,
+    sync::{
+        mpsc::{self, TryRecvError},
+        Arc, Mutex, MutexGuard
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+use crate::*;
+
+use super::{
+    send_error_event, ComParamSnapshot, ComParamStore, CopControl, CopEngine, CopIo, Event, EventData, EventQueue,
+    EventSource, HandleRegistry, IoctlData, ItemAllocator, LinkAction, LinkStateMachine, ModuleAction, ModuleInfo,
+    ModuleState, ModuleStateMachine, PduBackend, PduTag, ResourceInfo, ResourceManager, ResponseTiming, ResultEvent,
+    StatusInfo, DEFAULT_PENDING_COMPLETION_TIMEOUT, DEFAULT_PENDING_TIMEOUT
+};
+
+/// Longest time a link worker waits for a message before checking its ComPrimitives and commands
+const MAX_RECV_WAIT: Duration = Duration::from_millis(5);
+
+/// Returns the first object ID a [DriverBackend] assigns to an object type
+const fn object_id_base(object_type: PduObjt) -> u32 {
+    match object_type {
+        PduObjt::Protocol => 0x1000,
+        PduObjt::BusType => 0x2000,
+        PduObjt::IoCtrl => 0x3000,
+        PduObjt::ComParam => 0x4000,
+        PduObjt::PinType => 0x5000,
+        PduObjt::Resource => 0x6000
+    }
+}
+
+/// Returns the object ID a [DriverBackend] assigns to a standard object
+pub fn std_object_id<T: StandardObject>(object: T) -> ObjectId {
+    let index = T::ALL.iter().position(|o| *o == object).unwrap_or_default() as u32;
+    ObjectId::new(object_id_base(T::OBJECT_TYPE) + index).unwrap_or(ObjectId::UNDEF)
+}
+
+/// Returns the standard object of an object ID assigned by a [DriverBackend]
+pub fn std_object<T: StandardObject>(id: ObjectId) -> Option<T> {
+    let index = id.raw().checked_sub(object_id_base(T::OBJECT_TYPE))?;
+    T::ALL.get(index as usize).copied()
+}
+
+fn find_std_object<T: StandardObject>(short_name: &str) -> Option<ObjectId> {
+    T::ALL.iter().find(|o| o.short_name() == short_name).map(|o| std_object_id(*o))
+}
+
+/// Resolves the short name of a standard object to the ID a [DriverBackend] assigns to it
+/// (`PDUGetObjectId`)
+pub fn std_object_id_by_name(object_type: PduObjt, short_name: &str) -> Option<ObjectId> {
+    match object_type {
+        PduObjt::Protocol => find_std_object::<Protocol>(short_name),
+        PduObjt::BusType => find_std_object::<BusType>(short_name),
+        PduObjt::IoCtrl => find_std_object::<IoctlCommand>(short_name),
+        PduObjt::ComParam => find_std_object::<StdComParam>(short_name),
+        PduObjt::PinType => find_std_object::<PinType>(short_name),
+        PduObjt::Resource => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Microsecond clock of a [DriverBackend], used for every timestamp of the API
+pub struct Clock {
+    start: Instant
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    /// Creates a clock starting at 0 now
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+
+    /// Returns the current timestamp in microseconds
+    pub fn now(&self) -> u32 {
+        self.at(Instant::now())
+    }
+
+    /// Returns the timestamp of an instant in microseconds. The timestamp wraps around
+    /// like the 32 bit timestamps of the API
+    pub fn at(&self, instant: Instant) -> u32 {
+        instant.saturating_duration_since(self.start).as_micros() as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// ComParams in force on a [Channel]
+pub struct LinkParams {
+    values: BTreeMap<StdComParam, ComParamValue>
+}
+
+impl LinkParams {
+    /// Creates a set of ComParams
+    pub fn new(values: impl IntoIterator<Item = (StdComParam, ComParamValue)>) -> Self {
+        Self { values: values.into_iter().collect() }
+    }
+
+    fn from_store(store: &ComParamStore, supported: &[StdComParam], temp_param_update: bool) -> Result<Self, PduError> {
+        Ok(Self::from_snapshot(&store.snapshot(temp_param_update)?, supported))
+    }
+
+    fn from_snapshot(snapshot: &ComParamSnapshot, supported: &[StdComParam]) -> Self {
+        Self::new(supported.iter().filter_map(|p| snapshot.get(std_object_id(*p)).map(|v| (*p, v.clone()))))
+    }
+
+    /// Returns the value of a ComParam
+    pub fn get(&self, param: StdComParam) -> Option<&ComParamValue> {
+        self.values.get(&param)
+    }
+
+    /// Returns the value of a numeric ComParam
+    pub fn get_u32(&self, param: StdComParam) -> Option<u32> {
+        self.get(param).and_then(|v| v.as_u32())
+    }
+
+    /// Returns the value of a timing ComParam
+    pub fn get_duration(&self, param: StdComParam) -> Option<Duration> {
+        self.get(param).and_then(|v| param.to_duration(v))
+    }
+
+    /// Returns the value of a byte field ComParam
+    pub fn get_bytes(&self, param: StdComParam) -> Option<&[u8]> {
+        self.get(param).and_then(|v| v.as_bytes())
+    }
+
+    /// Sets the value of a ComParam
+    pub fn set(&mut self, param: StdComParam, value: ComParamValue) {
+        self.values.insert(param, value);
+    }
+}
+
+/// Longest message a [MessageFilter] can compare: a CAN ID followed by a CAN FD frame
+pub const MAX_FILTER_LEN: usize = 4 + CANFD_MAX_DLEN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Message filter of a ComLogicalLink
+///
+/// Filters started with `PDU_IOCTL_START_MSG_FILTER` ([IoFilterData]) compare up to 12 bytes.
+/// Filters started by the backend itself can compare up to [MAX_FILTER_LEN] bytes, which covers
+/// a whole CAN FD frame
+pub struct MessageFilter {
+    /// Filter type
+    pub filter_type: PduFilter,
+    /// Mask message. Its length is the compare size of the filter
+    pub mask: Vec<u8>,
+    /// Pattern message, the same length as [MessageFilter::mask]
+    pub pattern: Vec<u8>
+}
+
+impl MessageFilter {
+    /// Returns true if the first bytes of a message, masked with the mask message, equal the
+    /// pattern message
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.mask.len()
+            && data.iter().zip(self.mask.iter().zip(self.pattern.iter())).all(|(d, (m, p))| d & m == p & m)
+    }
+
+    /// Returns true for pass filters, including UUDT ones
+    pub fn is_pass(&self) -> bool {
+        matches!(self.filter_type, PduFilter::Pass | PduFilter::PassUUDT)
+    }
+}
+
+impl TryFrom<IoFilterData> for MessageFilter {
+    type Error = PduError;
+
+    fn try_from(filter: IoFilterData) -> Result<Self, Self::Error> {
+        let size = filter.filter_compare_size as usize;
+        if size > filter.filter_mask_msg.len() {
+            return Err(PduError::InvalidParameters);
+        }
+        Ok(Self {
+            filter_type: filter.filter_type,
+            mask: filter.filter_mask_msg[..size].to_vec(),
+            pattern: filter.filter_pattern_msg[..size].to_vec()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Message filters of a ComLogicalLink (`PDU_IOCTL_START_MSG_FILTER`)
+///
+/// If any pass filter is active, only messages matching a pass filter are received. Messages
+/// matching a block filter are never received. UUDT filters are treated like their USDT
+/// counterparts. On `SAE_J1939_73_on_SAE_J1939_21` links the filters compare the
+/// [J1939_HEADER_LEN](super::J1939_HEADER_LEN) byte header of the ExtraInfo followed by the data, so that PGNs can be filtered
+pub struct MessageFilters {
+    filters: BTreeMap<u32, MessageFilter>
+}
+
+impl MessageFilters {
+    /// Creates an empty filter set, which receives every message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a filter from IOCTL data, replacing any filter with the same number
+    pub fn start(&mut self, filter: IoFilterData) -> Result<(), PduError> {
+        self.start_filter(filter.filter_number, filter.try_into()?)
+    }
+
+    /// Starts a filter, replacing any filter with the same number
+    ///
+    /// ## Parameters
+    /// * filter_number - Number the filter is stopped with
+    /// * filter - The filter. The mask and pattern messages must be the same length, at most [MAX_FILTER_LEN]
+    pub fn start_filter(&mut self, filter_number: u32, filter: MessageFilter) -> Result<(), PduError> {
+        if filter.mask.len() != filter.pattern.len() || filter.mask.len() > MAX_FILTER_LEN {
+            return Err(PduError::InvalidParameters);
+        }
+        self.filters.insert(filter_number, filter);
+        Ok(())
+    }
+
+    /// Stops a filter (`PDU_IOCTL_STOP_MSG_FILTER`)
+    pub fn stop(&mut self, filter_number: u32) -> Result<(), PduError> {
+        self.filters.remove(&filter_number).map(|_| ()).ok_or(PduError::InvalidParameters)
+    }
+
+    /// Stops every filter (`PDU_IOCTL_CLEAR_MSG_FILTER`)
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Returns true if no filters are active
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Returns the active filters
+    pub fn iter(&self) -> impl Iterator<Item = &MessageFilter> {
+        self.filters.values()
+    }
+
+    /// Returns true if a message passes the filters
+    pub fn accepts(&self, data: &[u8]) -> bool {
+        let mut pass = self.filters.values().filter(|f| f.is_pass()).peekable();
+        let passed = pass.peek().is_none() || pass.any(|f| f.matches(data));
+        passed && !self.filters.values().filter(|f| !f.is_pass()).any(|f| f.matches(data))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of IOCTL input data ([PduDataItem])
+pub enum IoctlInput {
+    /// No input data
+    None,
+    /// [PduIt::IoUnum32]
+    Unum32(u32),
+    /// [PduIt::IoProgVoltage]
+    ProgVoltage(IoProgVoltageData),
+    /// [PduIt::IoByteArray]
+    ByteArray(Vec<u8>),
+    /// [PduIt::IoFilter]
+    Filter(IoFilterData),
+    /// [PduIt::IoEventQueueProperty]
+    EventQueueProperty(IoEventQueuePropertyData),
+    /// [PduIt::IoVehicleIdRequest]
+    VehicleIdRequest(VehicleIdRequestData),
+    /// [PduIt::EntityAddress]
+    EntityAddress(IoEntityAddressData)
+}
+
+impl IoctlInput {
+    /// Converts the input data passed to `PDUIoCtl`
+    ///
+    /// # Safety
+    /// The data pointer of the item must point to the structure of its item type
+    pub unsafe fn from_item(item: Option<&PduDataItem>) -> Result<Self, PduError> {
+        let Some(item) = item else {
+            return Ok(Self::None);
+        };
+        if item.p_data.is_null() {
+            return Err(PduError::InvalidParameters);
+        }
+        Ok(match item.item_type {
+            PduIt::IoUnum32 => Self::Unum32(*item.p_data.cast::<u32>()),
+            PduIt::IoProgVoltage => Self::ProgVoltage(*item.p_data.cast::<IoProgVoltageData>()),
+            PduIt::IoByteArray => {
+                let array = &*item.p_data.cast::<IoByteArrayData>();
+                if array.data_size == 0 {
+                    Self::ByteArray(Vec::new())
+                } else if array.p_data.is_null() {
+                    return Err(PduError::InvalidParameters);
+                } else {
+                    Self::ByteArray(std::slice::from_raw_parts(array.p_data, array.data_size as usize).to_vec())
+                }
+            },
+            PduIt::IoFilter => Self::Filter(*item.p_data.cast::<IoFilterData>()),
+            PduIt::IoEventQueueProperty => Self::EventQueueProperty(*item.p_data.cast::<IoEventQueuePropertyData>()),
+            PduIt::IoVehicleIdRequest => {
+                Self::VehicleIdRequest(VehicleIdRequestData::from_raw(&*item.p_data.cast::<VehicleIdRequest>())?)
+            },
+            PduIt::EntityAddress => Self::EntityAddress(*item.p_data.cast::<IoEntityAddressData>()),
+            _ => return Err(PduError::InvalidParameters)
+        })
+    }
+
+    /// Returns the value of [IoctlInput::Unum32] input
+    pub fn unum32(&self) -> Result<u32, PduError> {
+        match self {
+            Self::Unum32(x) => Ok(*x),
+            _ => Err(PduError::InvalidParameters)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of [VehicleIdRequest]
+pub struct VehicleIdRequestData {
+    /// Preselection mode
+    pub preselection_mode: VidPreselectMode,
+    /// Preselection ASCII string, without the nul terminator
+    pub preselection_value: Vec<u8>,
+    /// Combination mode
+    pub combination_mode: CombinationMode,
+    /// Time to wait for vehicle identification responses
+    pub discovery_time: Duration,
+    /// Broadcast or multicast addresses to send the request to
+    pub destination_addresses: Vec<IpAddr>
+}
+
+impl VehicleIdRequestData {
+    /// Converts the request passed to `PDUIoCtl`
+    ///
+    /// # Safety
+    /// The pointers of the request must be null or point to data of the sizes described by the request
+    pub unsafe fn from_raw(request: &VehicleIdRequest) -> Result<Self, PduError> {
+        let preselection_value = match request.preselection_value.is_null() {
+            true => Vec::new(),
+            false => CStr::from_ptr(request.preselection_value.cast()).to_bytes().to_vec()
+        };
+        let addresses = match (request.num_destination_addresses, request.destination_addresses.is_null()) {
+            (0, _) => &[][..],
+            (_, true) => return Err(PduError::InvalidParameters),
+            (n, false) => std::slice::from_raw_parts(request.destination_addresses, n as usize)
+        };
+        let destination_addresses = addresses
+            .iter()
+            .map(|a| match (a.ip_version, a.p_address.is_null()) {
+                (_, true) => Err(PduError::InvalidParameters),
+                (4, false) => Ok(IpAddr::from(*a.p_address.cast::<[u8; 4]>())),
+                (6, false) => Ok(IpAddr::from(*a.p_address.cast::<[u8; 16]>())),
+                _ => Err(PduError::InvalidParameters)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            preselection_mode: request.preselection_mode,
+            preselection_value,
+            combination_mode: request.combination_mode,
+            discovery_time: Duration::from_millis(request.vehicle_discovery_time.into()),
+            destination_addresses
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A physical resource of a [DriverModule]
+pub struct DriverResource {
+    /// Bus type
+    pub bus_type: BusType,
+    /// Protocols which can run on the bus
+    pub protocols: Vec<Protocol>,
+    /// Pins used on the data link connector
+    pub pins: Vec<(u32, PinType)>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A module of a [Driver]
+pub struct DriverModule {
+    /// Module type ID
+    pub module_type_id: u32,
+    /// Vendor specific name of the module
+    pub name: String,
+    /// Vendor specific additional information
+    pub info: String,
+    /// Resources of the module
+    pub resources: Vec<DriverResource>
+}
+
+/// Vehicle interface driver of a [DriverBackend]
+///
+/// Modules are identified by their index in the list returned by [Driver::modules]
+pub trait Driver: Send + Sync + Sized + 'static {
+    /// Communication channel of a connected ComLogicalLink
+    type Channel: Channel;
+
+    /// Opens the driver (`PDUConstruct`)
+    fn open(options: &str) -> Result<Self, PduError>;
+
+    /// Returns the modules of the driver. This is called once when the API is constructed
+    fn modules(&self) -> Vec<DriverModule>;
+
+    /// Returns version information of a module. If [None], the version is built from the
+    /// name of the module
+    fn version(&self, _module: usize) -> Option<VersionData> {
+        None
+    }
+
+    /// Connects to a module (`PDUModuleConnect`)
+    fn connect_module(&self, _module: usize) -> Result<(), PduError> {
+        Ok(())
+    }
+
+    /// Disconnects from a module (`PDUModuleDisconnect`). Every channel of the module has
+    /// already been closed
+    fn disconnect_module(&self, _module: usize) -> Result<(), PduError> {
+        Ok(())
+    }
+
+    /// Returns the ComParams supported by a protocol, with their default values
+    fn com_params(&self, protocol: Protocol) -> Vec<(StdComParam, ComParamValue)>;
+
+    /// Returns true if a [PduCopt::StartComm] ComPrimitive must run before [PduCopt::SendRecv]
+    /// ComPrimitives can be started on the protocol
+    fn start_comm_required(&self, _protocol: Protocol) -> bool {
+        false
+    }
+
+    /// Opens the channel of a ComLogicalLink (`PDUConnect`)
+    ///
+    /// ## Parameters
+    /// * module - Index of the module
+    /// * resource - Resource the link is on
+    /// * protocol - Protocol of the link
+    /// * params - Active ComParams of the link
+    /// * clock - Clock used for the timestamps of received messages
+    fn open_channel(
+        &self,
+        module: usize,
+        resource: &DriverResource,
+        protocol: Protocol,
+        params: &LinkParams,
+        clock: Clock
+    ) -> Result<Self::Channel, PduError>;
+
+    /// Performs an IOCTL on a module. IOCTLs handled by the [DriverBackend] are never passed
+    /// to the driver
+    fn module_ioctl(&self, _module: usize, _command: IoctlCommand, _input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        Err(PduError::IdNotSupported)
+    }
+}
+
+/// Communication channel of a connected ComLogicalLink, which is owned by the worker thread
+/// of the link
+pub trait Channel: Send + 'static {
+    /// Applies the ComParams of the link after a [PduCopt::UpdateParam] ComPrimitive
+    fn apply_params(&mut self, params: &LinkParams) -> Result<(), PduError>;
+
+    /// Sends a message
+    ///
+    /// ## Parameters
+    /// * data - Message data in the format of the protocol
+    /// * tx_flag - Transmit flag bytes of the ComPrimitive
+    /// * params - ComParams in force for the ComPrimitive
+    fn send(&mut self, data: &[u8], tx_flag: &[u8], params: &LinkParams) -> Result<(), PduError>;
+
+    /// Sends the message of a [PduCopt::SendRecv] ComPrimitive. Channels which make use of its
+    /// control data, such as the number of receive cycles, override this. The default calls
+    /// [Channel::send]
+    fn send_cop(&mut self, data: &[u8], ctrl: &CopControl, params: &LinkParams) -> Result<(), PduError> {
+        self.send(data, &ctrl.tx_flag, params)
+    }
+
+    /// Returns the error event of the ComPrimitive after [Channel::send] failed. Channels whose
+    /// transport layer reports why a message was not sent override this. The default is
+    /// [send_error_event]
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        send_error_event(error)
+    }
+
+    /// Waits up to `timeout` for a received message
+    fn recv(&mut self, timeout: Duration) -> Result<Option<ResultEvent>, PduError>;
+
+    /// Returns the next protocol error detected while receiving, such as a transport layer
+    /// timeout. Errors are sent to the application as error events of the link
+    fn poll_error(&mut self) -> Option<PduErrorEvt> {
+        None
+    }
+
+    /// Returns the response timeouts of the ComPrimitives of the link. It is read again when
+    /// the link connects, after communication starts or stops and after the ComParams change.
+    /// Channels whose timing does not only come from `CP_P2Max` override this
+    fn response_timing(&self, params: &LinkParams) -> ResponseTiming {
+        ResponseTiming::from_params(params)
+    }
+
+    /// Starts communication ([PduCopt::StartComm]). Any returned data is sent to the application
+    /// as the result of the ComPrimitive
+    fn start_comm(&mut self, _data: &[u8], _params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    /// Stops communication ([PduCopt::StopComm])
+    fn stop_comm(&mut self, _data: &[u8], _params: &LinkParams) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    /// Applies the message filters of the link when the channel opens and when they change.
+    /// The [DriverBackend] filters the received messages as well, so channels which filter in
+    /// hardware only reduce the messages they receive
+    fn apply_filters(&mut self, _filters: &MessageFilters) -> Result<(), PduError> {
+        Ok(())
+    }
+
+    /// Performs an IOCTL on the link. IOCTLs handled by the [DriverBackend] are never passed
+    /// to the channel, except `PDU_IOCTL_CLEAR_TX_QUEUE` and `PDU_IOCTL_CLEAR_RX_QUEUE` after
+    /// the backend cleared its queues, so that the buffers of the device can be cleared as well
+    fn ioctl(&mut self, _command: IoctlCommand, _input: &IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        Err(PduError::IdNotSupported)
+    }
+}
+
+#[derive(Debug)]
+struct ModuleObject {
+    index: usize,
+    info: DriverModule,
+    resources: Vec<(ResourceId, DriverResource)>,
+    events: Arc<EventQueue>,
+    state: ModuleStateMachine
+}
+
+impl ModuleObject {
+    fn resource(&self, id: ResourceId) -> Result<&DriverResource, PduError> {
+        self.resources.iter().find(|(r, _)| *r == id).map(|(_, r)| r).ok_or(PduError::InvalidParameters)
+    }
+}
+
+#[derive(Debug)]
+struct LinkShared {
+    module: usize,
+    resource: DriverResource,
+    protocol: Protocol,
+    start_comm_required: bool,
+    supported: Vec<StdComParam>,
+    events: Arc<EventQueue>,
+    state: LinkStateMachine,
+    params: ComParamStore,
+    engine: CopEngine,
+    filters: Mutex<MessageFilters>,
+    clock: Clock
+}
+
+impl LinkShared {
+    fn params(&self, temp_param_update: bool) -> Result<LinkParams, PduError> {
+        LinkParams::from_store(&self.params, &self.supported, temp_param_update)
+    }
+
+    /// Sends an error event of the link to the application
+    fn push_error(&self, code: PduErrorEvt) {
+        self.events.push(Event {
+            h_cop: None,
+            cop_tag: PduTag::NULL,
+            timestamp: self.clock.now(),
+            data: EventData::Error(ErrorData { error_code_id: code, extra_error_info_id: 0 })
+        });
+    }
+
+    fn filters(&self) -> MutexGuard<'_, MessageFilters> {
+        self.filters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns true if a received message passes the filters of the link. On J1939 links the
+    /// filters compare the ExtraInfo header (PGN, source and destination address) followed by the data
+    fn accepts(&self, msg: &ResultEvent) -> bool {
+        match (self.protocol, &msg.extra_info) {
+            (Protocol::SaeJ1939_73OnSaeJ1939_21, Some(extra)) => {
+                self.filters().accepts(&[extra.header.as_slice(), &msg.data].concat())
+            },
+            _ => self.filters().accepts(&msg.data)
+        }
+    }
+}
+
+type IoctlReply = mpsc::Sender<Result<Option<IoctlData>, PduError>>;
+
+#[derive(Debug)]
+enum Command {
+    Ioctl(IoctlCommand, IoctlInput, IoctlReply),
+    Filters(MessageFilters, mpsc::Sender<Result<(), PduError>>),
+    Stop
+}
+
+#[derive(Debug)]
+struct Worker {
+    commands: mpsc::Sender<Command>,
+    thread: JoinHandle<()>
+}
+
+#[derive(Debug)]
+struct LinkObject {
+    shared: Arc<LinkShared>,
+    worker: Mutex<Option<Worker>>
+}
+
+impl LinkObject {
+    fn worker(&self) -> MutexGuard<'_, Option<Worker>> {
+        self.worker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stops the worker thread, closing the channel
+    fn stop(&self) {
+        if let Some(worker) = self.worker().take() {
+            let _ = worker.commands.send(Command::Stop);
+            let _ = worker.thread.join();
+        }
+    }
+
+    /// Runs an IOCTL on the channel, on the worker thread
+    fn channel_ioctl(&self, command: IoctlCommand, input: IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        let (tx, rx) = mpsc::channel();
+        self.worker()
+            .as_ref()
+            .ok_or(PduError::CllNotConnected)?
+            .commands
+            .send(Command::Ioctl(command, input, tx))
+            .map_err(|_| PduError::FctFailed)?;
+        rx.recv().map_err(|_| PduError::FctFailed)?
+    }
+
+    /// Passes an IOCTL which clears a queue of the link on to the channel while the link is connected,
+    /// so that the buffer of the device is cleared as well. Channels without such a buffer do not
+    /// support the IOCTL
+    fn clear_channel(&self, command: IoctlCommand, input: IoctlInput) -> Result<(), PduError> {
+        match self.channel_ioctl(command, input) {
+            Ok(_) | Err(PduError::IdNotSupported | PduError::CllNotConnected) => Ok(()),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Changes the message filters of the link, applying them to the channel while the link
+    /// is connected. The filters are left unchanged if the channel fails to apply them
+    fn update_filters(&self, update: impl FnOnce(&mut MessageFilters) -> Result<(), PduError>) -> Result<(), PduError> {
+        let worker = self.worker();
+        let mut filters = self.shared.filters().clone();
+        update(&mut filters)?;
+        if let Some(worker) = worker.as_ref() {
+            let (tx, rx) = mpsc::channel();
+            worker.commands.send(Command::Filters(filters.clone(), tx)).map_err(|_| PduError::FctFailed)?;
+            rx.recv().map_err(|_| PduError::FctFailed)??;
+        }
+        *self.shared.filters() = filters;
+        Ok(())
+    }
+}
+
+impl Drop for LinkObject {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// [CopIo] of a link worker
+struct WorkerIo<'a, C> {
+    channel: &'a mut C,
+    link: &'a LinkShared,
+    response_timing: Option<ResponseTiming>
+}
+
+impl<C: Channel> CopIo for WorkerIo<'_, C> {
+    fn send(&mut self, _h_cop: CopHandle, data: &[u8], ctrl: &CopControl) -> Result<(), PduError> {
+        let params = self.link.params(ctrl.temp_param_update)?;
+        self.channel.send_cop(data, ctrl, &params)
+    }
+
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        self.channel.send_error(error)
+    }
+
+    fn execute(&mut self, _h_cop: CopHandle, cop_type: PduCopt, data: &[u8]) -> Result<Option<Vec<u8>>, PduError> {
+        match cop_type {
+            PduCopt::StartComm => {
+                let params = self.link.params(false)?;
+                let result = self.channel.start_comm(data, &params)?;
+                self.response_timing = Some(self.channel.response_timing(&params));
+                self.link.state.apply(LinkAction::StartComm, self.timestamp())?;
+                Ok(result)
+            },
+            PduCopt::StopComm => {
+                let params = self.link.params(false)?;
+                let result = self.channel.stop_comm(data, &params)?;
+                self.response_timing = Some(self.channel.response_timing(&params));
+                self.link.state.apply(LinkAction::StopComm, self.timestamp())?;
+                Ok(result)
+            },
+            PduCopt::UpdateParam => {
+                // The channel takes the new ComParams before they become active, so a failure
+                // leaves both unchanged
+                let params = LinkParams::from_snapshot(&self.link.params.pending()?, &self.link.supported);
+                self.channel.apply_params(&params)?;
+                if let Err(e) = self.link.params.update(self.timestamp()) {
+                    let _ = self.channel.apply_params(&self.link.params(false)?);
+                    return Err(e);
+                }
+                self.response_timing = Some(self.channel.response_timing(&params));
+                Ok(None)
+            },
+            PduCopt::RestoreParam => {
+                self.link.params.restore();
+                Ok(None)
+            },
+            _ => Err(PduError::InvalidParameters)
+        }
+    }
+
+    fn timestamp(&self) -> u32 {
+        self.link.clock.now()
+    }
+}
+
+/// Adds the response pending ComParams (`CP_P2Star`, `CP_RC78Handling` and
+/// `CP_RC78CompletionTimeout`) to the ComParams of protocols with a response timeout, as the
+/// [CopEngine] handles them for every driver
+fn with_pending_params(mut params: Vec<(StdComParam, ComParamValue)>) -> Vec<(StdComParam, ComParamValue)> {
+    if !params.iter().any(|(p, _)| *p == StdComParam::P2Max) {
+        return params;
+    }
+    let pending = [
+        (StdComParam::P2Star, DEFAULT_PENDING_TIMEOUT.as_micros() as u32),
+        (StdComParam::Rc78Handling, 2),
+        (StdComParam::Rc78CompletionTimeout, DEFAULT_PENDING_COMPLETION_TIMEOUT.as_micros() as u32)
+    ];
+    for (param, value) in pending {
+        if !params.iter().any(|(p, _)| *p == param) {
+            if let Ok(value) = param.value(value) {
+                params.push((param, value));
+            }
+        }
+    }
+    params
+}
+
+/// Main loop of a link worker, which runs until the link is disconnected
+fn run_worker<C: Channel>(link: Arc<LinkShared>, mut channel: C, commands: mpsc::Receiver<Command>) {
+    let mut rx_failed = false;
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(Command::Ioctl(command, input, reply)) => {
+                    let _ = reply.send(channel.ioctl(command, &input));
+                },
+                Ok(Command::Filters(filters, reply)) => {
+                    let _ = reply.send(channel.apply_filters(&filters));
+                },
+                Ok(Command::Stop) | Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => break
+            }
+        }
+
+        let mut io = WorkerIo { channel: &mut channel, link: &link, response_timing: None };
+        let wake = link.engine.poll(Instant::now(), &mut io, &link.events);
+        if let Some(timing) = io.response_timing {
+            link.engine.set_response_timing(timing);
+        }
+
+        let now = Instant::now();
+        let wait = wake.map_or(MAX_RECV_WAIT, |t| t.saturating_duration_since(now).min(MAX_RECV_WAIT));
+        match channel.recv(wait) {
+            Ok(Some(msg)) => {
+                rx_failed = false;
+                if link.accepts(&msg) {
+                    link.engine.on_response(Instant::now(), msg, &link.events);
+                }
+            },
+            Ok(None) => rx_failed = false,
+            Err(_) => {
+                // Report the failure once, rather than on every retry
+                if !std::mem::replace(&mut rx_failed, true) {
+                    link.push_error(PduErrorEvt::RxError);
+                }
+                thread::sleep(MAX_RECV_WAIT);
+            }
+        }
+        while let Some(code) = channel.poll_error() {
+            link.push_error(code);
+        }
+    }
+}
+
+/// Copies a string into a fixed size, nul terminated name field of [VersionData]
+fn name_field(name: &str) -> [u8; 64] {
+    let mut field = [0; 64];
+    for (d, s) in field.iter_mut().zip(name.bytes().take(63)) {
+        *d = s;
+    }
+    field
+}
+
+/// Resource ID of a resource of a module
+fn resource_id(module: usize, resource: usize) -> ResourceId {
+    ResourceId::new((module as u32) * 0x100 + resource as u32 + 1).unwrap_or(ResourceId::UNDEF)
+}
+
+#[derive(Debug)]
+/// [PduBackend] which runs a [Driver]
+///
+/// Every module returned by the driver is registered when the API is constructed, with
+/// resource IDs assigned in the order of [DriverModule::resources]. Standard objects have fixed
+/// IDs ([std_object_id]). ComParams are validated and buffered by the backend, and applied to
+/// the channel when an [PduCopt::UpdateParam] ComPrimitive runs. The response timeout of
+/// ComPrimitives is taken from `CP_P2Max` where the protocol supports it, unless the channel
+/// has its own timing ([Channel::response_timing]).
+///
+/// The following IOCTLs are handled by the backend, any other IOCTL is passed to the driver
+/// or channel:
+/// * `PDU_IOCTL_SET_EVENT_QUEUE_PROPERTIES` - On the API, a module or a link
+/// * `PDU_IOCTL_CLEAR_TX_QUEUE`, `PDU_IOCTL_SUSPEND_TX_QUEUE`, `PDU_IOCTL_RESUME_TX_QUEUE`,
+///   `PDU_IOCTL_SET_BUFFER_SIZE` - The transmit queue of a link
+/// * `PDU_IOCTL_CLEAR_RX_QUEUE` - Discards the events of a link
+/// * `PDU_IOCTL_START_MSG_FILTER`, `PDU_IOCTL_STOP_MSG_FILTER`, `PDU_IOCTL_CLEAR_MSG_FILTER` - The
+///   [MessageFilters] of a link
+///
+/// Status timestamps are the time of the `PDUGetStatus` call.
+///
+/// ```ignore
+/// dpdu_rust::export_pdu_api!(dpdu_rust::provider::DriverBackend<MyDriver>);
+/// ```
+pub struct DriverBackend<D: Driver> {
+    driver: D,
+    api_tag: PduTag,
+    clock: Clock,
+    items: ItemAllocator,
+    events: Arc<EventQueue>,
+    registry: HandleRegistry<ModuleObject, LinkObject, ()>,
+    resources: ResourceManager
+}
+
+impl<D: Driver> DriverBackend<D> {
+    /// Returns the driver
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    /// Returns the clock used for timestamps
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    fn event_queue(&self, h_mod: Option<ModuleHandle>, h_cll: Option<CllHandle>) -> Result<Arc<EventQueue>, PduError> {
+        match (h_mod, h_cll) {
+            (None, None) => Ok(self.events.clone()),
+            (Some(h_mod), None) => Ok(self.registry.module(h_mod)?.events.clone()),
+            (Some(h_mod), Some(h_cll)) => Ok(self.registry.link(h_mod, h_cll)?.shared.events.clone()),
+            (None, Some(_)) => Err(PduError::InvalidHandle)
+        }
+    }
+
+    /// Stops a removed link and releases its resource
+    fn close_link(&self, h_cll: CllHandle, link: &LinkObject) {
+        let ts = self.clock.now();
+        link.stop();
+        link.shared.engine.cancel_all(ts, &link.shared.events);
+        self.resources.detach(h_cll, ts);
+    }
+
+    /// Forgets the finished ComPrimitives of a link
+    fn purge_cops(&self, h_mod: ModuleHandle, h_cll: CllHandle, link: &LinkShared) -> Result<(), PduError> {
+        for (h_cop, _) in self.registry.cops(h_mod, h_cll)? {
+            if link.engine.status(h_cop).is_none_or(|s| matches!(s, PduStatus::CopstFinished | PduStatus::CopstCancelled)) {
+                link.engine.forget(h_cop);
+                self.registry.remove_cop(h_mod, h_cll, h_cop)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn module_ioctl(&self, h_mod: ModuleHandle, command: IoctlCommand, input: IoctlInput) -> Result<Option<IoctlData>, PduError> {
+        let module = self.registry.module(h_mod)?;
+        match command {
+            IoctlCommand::SetEventQueueProperties => match input {
+                IoctlInput::EventQueueProperty(p) => module.events.set_property(p).map(|_| None),
+                _ => Err(PduError::InvalidParameters)
+            },
+            _ => {
+                module.state.require_connected()?;
+                self.driver.module_ioctl(module.index, command, &input)
+            }
+        }
+    }
+
+    fn link_ioctl(
+        &self,
+        h_mod: ModuleHandle,
+        h_cll: CllHandle,
+        command: IoctlCommand,
+        input: IoctlInput
+    ) -> Result<Option<IoctlData>, PduError> {
+        let link = self.registry.link(h_mod, h_cll)?;
+        let shared = &link.shared;
+        match (command, input) {
+            (IoctlCommand::SetEventQueueProperties, IoctlInput::EventQueueProperty(p)) => {
+                shared.events.set_property(p)?;
+            },
+            (IoctlCommand::ClearTxQueue, input) => {
+                shared.engine.clear_tx_queue(self.clock.now(), &shared.events);
+                link.clear_channel(IoctlCommand::ClearTxQueue, input)?;
+            },
+            (IoctlCommand::SuspendTxQueue, _) => shared.engine.suspend_tx(),
+            (IoctlCommand::ResumeTxQueue, _) => shared.engine.resume_tx(),
+            (IoctlCommand::ClearRxQueue, input) => {
+                shared.events.clear();
+                link.clear_channel(IoctlCommand::ClearRxQueue, input)?;
+            },
+            (IoctlCommand::SetBufferSize, input) => shared.engine.set_tx_capacity(input.unum32()?)?,
+            (IoctlCommand::StartMsgFilter, IoctlInput::Filter(f)) => link.update_filters(|filters| filters.start(f))?,
+            (IoctlCommand::StopMsgFilter, input) => {
+                let filter_number = input.unum32()?;
+                link.update_filters(|filters| filters.stop(filter_number))?;
+            },
+            (IoctlCommand::ClearMsgFilter, _) => link.update_filters(|filters| {
+                filters.clear();
+                Ok(())
+            })?,
+            (IoctlCommand::SetEventQueueProperties | IoctlCommand::StartMsgFilter, _) => {
+                return Err(PduError::InvalidParameters);
+            },
+            (command, input) => return link.channel_ioctl(command, input)
+        }
+        Ok(None)
+    }
+}
+
+impl<D: Driver> PduBackend for DriverBackend<D> {
+    fn construct(options: &str, api_tag: PduTag) -> Result<Self, PduError> {
+        let backend = Self {
+            driver: D::open(options)?,
+            api_tag,
+            clock: Clock::new(),
+            items: ItemAllocator::new(),
+            events: Arc::new(EventQueue::new(EventSource { api_tag, ..Default::default() })),
+            registry: HandleRegistry::new(),
+            resources: ResourceManager::new()
+        };
+        for (index, info) in backend.driver.modules().into_iter().enumerate() {
+            let resources: Vec<(ResourceId, DriverResource)> =
+                info.resources.iter().enumerate().map(|(i, r)| (resource_id(index, i), r.clone())).collect();
+            let infos = resources
+                .iter()
+                .map(|(id, r)| ResourceInfo {
+                    id: *id,
+                    bus_type_id: std_object_id(r.bus_type),
+                    protocol_ids: r.protocols.iter().map(|p| std_object_id(*p)).collect(),
+                    pins: r
+                        .pins
+                        .iter()
+                        .map(|(pin, t)| PinData { dlc_pin_number: *pin, dlc_pin_type_id: std_object_id(*t).raw() })
+                        .collect()
+                })
+                .collect();
+            let (h_mod, _) = backend.registry.add_module_with(|h_mod| {
+                let events = Arc::new(EventQueue::new(EventSource { h_mod: Some(h_mod), api_tag, ..Default::default() }));
+                ModuleObject {
+                    index,
+                    info,
+                    resources,
+                    state: ModuleStateMachine::new(ModuleState::Avail, events.clone()),
+                    events
+                }
+            });
+            backend.resources.add_module(h_mod, infos);
+        }
+        Ok(backend)
+    }
+
+    fn destruct(&self) -> Result<(), PduError> {
+        let removed = self.registry.clear();
+        for (_, h_cll, link) in removed.links.iter() {
+            self.close_link(*h_cll, link);
+        }
+        for (h_mod, module) in removed.modules.iter() {
+            if module.state.state().is_connected() {
+                let _ = self.driver.disconnect_module(module.index);
+            }
+            self.resources.remove_module(*h_mod);
+        }
+        Ok(())
+    }
+
+    fn get_module_ids(&self) -> Result<*mut ModuleItem, PduError> {
+        let modules: Vec<ModuleInfo> = self
+            .registry
+            .modules()
+            .into_iter()
+            .map(|(h_mod, m)| ModuleInfo {
+                module_type_id: m.info.module_type_id,
+                h_mod,
+                vendor_module_name: m.info.name.clone(),
+                vendor_additional_info: m.info.info.clone(),
+                status: m.state.state().status()
+            })
+            .collect();
+        Ok(self.items.alloc_modules(&modules))
+    }
+
+    fn module_connect(&self, h_mod: Option<ModuleHandle>) -> Result<(), PduError> {
+        let modules = match h_mod {
+            Some(h) => vec![(h, self.registry.module(h)?)],
+            None => self.registry.modules().into_iter().filter(|(_, m)| m.state.state() == ModuleState::Avail).collect()
+        };
+        for (_, module) in modules {
+            module.state.state().next(ModuleAction::Connect)?;
+            self.driver.connect_module(module.index)?;
+            module.state.apply(ModuleAction::Connect, self.clock.now())?;
+        }
+        Ok(())
+    }
+
+    fn module_disconnect(&self, h_mod: Option<ModuleHandle>) -> Result<(), PduError> {
+        let modules = match h_mod {
+            Some(h) => vec![(h, self.registry.module(h)?)],
+            None => self.registry.modules().into_iter().filter(|(_, m)| m.state.state().is_connected()).collect()
+        };
+        for (h_mod, module) in modules {
+            module.state.state().next(ModuleAction::Disconnect)?;
+            for (_, h_cll, link) in self.registry.clear_module(h_mod)?.links.iter() {
+                self.close_link(*h_cll, link);
+            }
+            self.driver.disconnect_module(module.index)?;
+            module.state.apply(ModuleAction::Disconnect, self.clock.now())?;
+        }
+        Ok(())
+    }
+
+    fn get_version(&self, h_mod: ModuleHandle) -> Result<VersionData, PduError> {
+        let module = self.registry.module(h_mod)?;
+        Ok(self.driver.version(module.index).unwrap_or_else(|| VersionData {
+            mvci_part1_standard_version: 0,
+            mvci_part2_standard_version: 0,
+            hw_serial_number: 0,
+            hw_name: name_field(&module.info.name),
+            hw_version: 0,
+            hw_data: 0,
+            hw_inferface: module.info.module_type_id,
+            fw_name: name_field(&module.info.info),
+            fw_version: 0,
+            fw_date: 0,
+            vendor_name: name_field(env!("CARGO_PKG_NAME")),
+            pdu_api_sw_name: name_field(env!("CARGO_PKG_NAME")),
+            pdu_api_sw_version: 0,
+            pdi_api_sw_date: 0
+        }))
+    }
+
+    fn get_timestamp(&self, h_mod: ModuleHandle) -> Result<u32, PduError> {
+        self.registry.module(h_mod)?;
+        Ok(self.clock.now())
+    }
+
+    fn get_status(
+        &self,
+        h_mod: ModuleHandle,
+        h_cll: Option<CllHandle>,
+        h_cop: Option<CopHandle>
+    ) -> Result<StatusInfo, PduError> {
+        let status = match (h_cll, h_cop) {
+            (Some(h_cll), Some(h_cop)) => {
+                self.registry.cop(h_mod, h_cll, h_cop)?;
+                self.registry.link(h_mod, h_cll)?.shared.engine.status(h_cop).ok_or(PduError::InvalidHandle)?
+            },
+            (Some(h_cll), None) => self.registry.link(h_mod, h_cll)?.shared.state.state().status(),
+            (None, None) => self.registry.module(h_mod)?.state.state().status(),
+            (None, Some(_)) => return Err(PduError::InvalidHandle)
+        };
+        Ok(StatusInfo { status, timestamp: self.clock.now(), extra_info: 0 })
+    }
+
+    fn ioctl(
+        &self,
+        h_mod: Option<ModuleHandle>,
+        h_cll: Option<CllHandle>,
+        ioctl_id: ObjectId,
+        input: Option<&PduDataItem>
+    ) -> Result<*mut PduDataItem, PduError> {
+        let command = std_object::<IoctlCommand>(ioctl_id).ok_or(PduError::IdNotSupported)?;
+        // Safety: The application passes input data matching its item type
+        let input = unsafe { IoctlInput::from_item(input)? };
+        let output = match (h_mod, h_cll) {
+            (None, None) => match (command, input) {
+                (IoctlCommand::SetEventQueueProperties, IoctlInput::EventQueueProperty(p)) => {
+                    self.events.set_property(p)?;
+                    None
+                },
+                (IoctlCommand::SetEventQueueProperties, _) => return Err(PduError::InvalidParameters),
+                _ => return Err(PduError::IdNotSupported)
+            },
+            (Some(h_mod), None) => self.module_ioctl(h_mod, command, input)?,
+            (Some(h_mod), Some(h_cll)) => self.link_ioctl(h_mod, h_cll, command, input)?,
+            (None, Some(_)) => return Err(PduError::InvalidHandle)
+        };
+        Ok(output.map_or(ptr::null_mut(), |data| self.items.alloc_ioctl_data(&data)))
+    }
+
+    fn get_object_id(&self, object_type: PduObjt, short_name: &str) -> Result<Option<ObjectId>, PduError> {
+        Ok(std_object_id_by_name(object_type, short_name))
+    }
+
+    fn get_resource_ids(&self, h_mod: Option<ModuleHandle>, rsc_data: &RscData) -> Result<*mut RscIdItem, PduError> {
+        // Safety: The application passes a valid pin data array
+        let ids = unsafe { self.resources.resource_ids(h_mod, rsc_data)? };
+        Ok(self.items.alloc_resource_ids(&ids))
+    }
+
+    fn get_resource_status(&self, items: &mut [RscStatusItem]) -> Result<(), PduError> {
+        self.resources.fill_status(items);
+        Ok(())
+    }
+
+    fn get_conflicting_resources(
+        &self,
+        resource_id: ResourceId,
+        modules: &[ModuleHandle]
+    ) -> Result<*mut RscConflictItem, PduError> {
+        let conflicts = self.resources.conflicts(resource_id, modules)?;
+        Ok(self.items.alloc_conflicts(&conflicts))
+    }
+
+    fn lock_resource(&self, h_mod: ModuleHandle, h_cll: CllHandle, lock_mask: u32) -> Result<(), PduError> {
+        self.registry.link(h_mod, h_cll)?;
+        self.resources.lock_resource(h_mod, h_cll, lock_mask, self.clock.now())
+    }
+
+    fn unlock_resource(&self, h_mod: ModuleHandle, h_cll: CllHandle, lock_mask: u32) -> Result<(), PduError> {
+        self.registry.link(h_mod, h_cll)?;
+        self.resources.unlock_resource(h_mod, h_cll, lock_mask, self.clock.now())
+    }
+
+    fn create_com_logical_link(
+        &self,
+        h_mod: ModuleHandle,
+        rsc_data: Option<&RscData>,
+        resource_id: Option<ResourceId>,
+        cll_tag: PduTag,
+        _create_flags: Option<&FlagData>
+    ) -> Result<CllHandle, PduError> {
+        let module = self.registry.module(h_mod)?;
+        module.state.require_connected()?;
+        let id = match (resource_id, rsc_data) {
+            (Some(id), _) => id,
+            // Safety: The application passes a valid pin data array
+            (None, Some(rsc)) => unsafe { self.resources.select_resource(h_mod, rsc)? },
+            (None, None) => return Err(PduError::InvalidParameters)
+        };
+        let resource = module.resource(id)?;
+        let protocol = match rsc_data {
+            Some(rsc) => ObjectId::new(rsc.protocol_id)
+                .and_then(std_object::<Protocol>)
+                .filter(|p| resource.protocols.contains(p))
+                .ok_or(PduError::InvalidParameters)?,
+            None => *resource.protocols.first().ok_or(PduError::InvalidParameters)?
+        };
+        let defaults = with_pending_params(self.driver.com_params(protocol));
+        let api_tag = self.api_tag;
+        let (h_cll, _) = self.registry.add_link_with(h_mod, |h_cll| {
+            let events = Arc::new(EventQueue::new(EventSource { h_mod: Some(h_mod), h_cll: Some(h_cll), cll_tag, api_tag }));
+            let physical = self.resources.attach(h_mod, id, h_cll, events.clone())?;
+            let supported = defaults.iter().map(|(p, _)| *p).collect();
+            let params = ComParamStore::new(
+                h_cll,
+                events.clone(),
+                physical,
+                defaults.into_iter().map(|(p, v)| (std_object_id(p), p.def(), v))
+            );
+            Ok(LinkObject {
+                shared: Arc::new(LinkShared {
+                    module: module.index,
+                    resource: resource.clone(),
+                    protocol,
+                    start_comm_required: self.driver.start_comm_required(protocol),
+                    supported,
+                    state: LinkStateMachine::new(events.clone()),
+                    events,
+                    params,
+                    engine: CopEngine::new(protocol),
+                    filters: Mutex::new(MessageFilters::new()),
+                    clock: self.clock
+                }),
+                worker: Mutex::new(None)
+            })
+        })?;
+        Ok(h_cll)
+    }
+
+    fn destroy_com_logical_link(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError> {
+        for (_, h_cll, link) in self.registry.remove_link(h_mod, h_cll)?.links.iter() {
+            self.close_link(*h_cll, link);
+        }
+        Ok(())
+    }
+
+    fn connect(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError> {
+        self.registry.module(h_mod)?.state.require_connected()?;
+        let link = self.registry.link(h_mod, h_cll)?;
+        let shared = &link.shared;
+        shared.state.state().next(LinkAction::Connect)?;
+        let mut worker = link.worker();
+        let params = shared.params(false)?;
+        let mut channel = self.driver.open_channel(shared.module, &shared.resource, shared.protocol, &params, self.clock)?;
+        let filters = shared.filters().clone();
+        channel.apply_filters(&filters)?;
+        shared.engine.set_response_timing(channel.response_timing(&params));
+        let (commands, rx) = mpsc::channel();
+        let thread_link = shared.clone();
+        let thread = thread::Builder::new()
+            .name(format!("pdu-cll-{h_cll}"))
+            .spawn(move || run_worker(thread_link, channel, rx))
+            .map_err(|_| PduError::FctFailed)?;
+        *worker = Some(Worker { commands, thread });
+        drop(worker);
+        shared.state.apply(LinkAction::Connect, self.clock.now())?;
+        Ok(())
+    }
+
+    fn disconnect(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError> {
+        let link = self.registry.link(h_mod, h_cll)?;
+        let shared = &link.shared;
+        shared.state.state().next(LinkAction::Disconnect)?;
+        link.stop();
+        let ts = self.clock.now();
+        shared.engine.cancel_all(ts, &shared.events);
+        shared.state.apply(LinkAction::Disconnect, ts)?;
+        Ok(())
+    }
+
+    fn get_com_param(&self, h_mod: ModuleHandle, h_cll: CllHandle, param_id: ObjectId) -> Result<*mut ParamItem, PduError> {
+        self.registry.link(h_mod, h_cll)?.shared.params.get_item(param_id, &self.items)
+    }
+
+    fn set_com_param(&self, h_mod: ModuleHandle, h_cll: CllHandle, param: &ParamItem) -> Result<(), PduError> {
+        let link = self.registry.link(h_mod, h_cll)?;
+        // Safety: The application passes ComParam data matching its data type
+        unsafe { link.shared.params.set_item(param) }
+    }
+
+    fn start_com_primitive(
+        &self,
+        h_mod: ModuleHandle,
+        h_cll: CllHandle,
+        cop_type: PduCopt,
+        data: &[u8],
+        ctrl: Option<&CopCtrlData>,
+        cop_tag: PduTag
+    ) -> Result<CopHandle, PduError> {
+        let link = self.registry.link(h_mod, h_cll)?;
+        let shared = &link.shared;
+        shared.state.check_cop(cop_type, shared.start_comm_required)?;
+        if cop_type == PduCopt::SendRecv {
+            self.resources.check_tx(h_cll)?;
+        }
+        let ctrl = match ctrl {
+            // Safety: The application passes valid flag and expected response data
+            Some(c) => unsafe { CopControl::from_raw(c)? },
+            None => CopControl::default()
+        };
+        if ctrl.temp_param_update {
+            shared.params.snapshot(true)?;
+        }
+        self.purge_cops(h_mod, h_cll, shared)?;
+        let h_cop = self.registry.add_cop(h_mod, h_cll, ())?;
+        if let Err(e) = shared.engine.enqueue(h_cop, cop_type, data.to_vec(), ctrl, cop_tag) {
+            self.registry.remove_cop(h_mod, h_cll, h_cop)?;
+            return Err(e);
+        }
+        Ok(h_cop)
+    }
+
+    fn cancel_com_primitive(&self, h_mod: ModuleHandle, h_cll: CllHandle, h_cop: CopHandle) -> Result<(), PduError> {
+        self.registry.cop(h_mod, h_cll, h_cop)?;
+        let link = self.registry.link(h_mod, h_cll)?;
+        link.shared.engine.cancel(h_cop, self.clock.now(), &link.shared.events)
+    }
+
+    fn get_event_item(&self, h_mod: Option<ModuleHandle>, h_cll: Option<CllHandle>) -> Result<*mut EventItem, PduError> {
+        self.event_queue(h_mod, h_cll)?.pop_item(&self.items)
+    }
+
+    fn register_event_callback(
+        &self,
+        h_mod: Option<ModuleHandle>,
+        h_cll: Option<CllHandle>,
+        callback: Option<EventCallbackFn>
+    ) -> Result<(), PduError> {
+        self.event_queue(h_mod, h_cll)?.set_callback(callback);
+        Ok(())
+    }
+
+    fn destroy_item(&self, item: *mut PduItem) -> Result<(), PduError> {
+        self.items.destroy(item)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Event queues for modules and ComLogicalLinks
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard}
+};
+
+use crate::*;
+
+use super::{Event, ItemAllocator, PduTag};
+
+/// Default size of the event queue when it is switched to a bounded mode without a size
+pub const DEFAULT_EVENT_QUEUE_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The owner of an event queue, which is passed to the event callback
+pub struct EventSource {
+    /// Module handle, or none for the system queue
+    pub h_mod: Option<ModuleHandle>,
+    /// ComLogicalLink handle, or none for module and system queues
+    pub h_cll: Option<CllHandle>,
+    /// Tag of the ComLogicalLink
+    pub cll_tag: PduTag,
+    /// Tag of the API
+    pub api_tag: PduTag
+}
+
+#[derive(Debug)]
+struct QueueState {
+    events: VecDeque<Event>,
+    property: IoEventQueuePropertyData,
+    lost: bool
+}
+
+impl QueueState {
+    fn is_bounded(&self) -> bool {
+        self.property.queue_mode != PduQueueMode::Unlimited
+    }
+
+    /// Trims the queue to the queue size, returning true if any events were discarded
+    fn trim(&mut self) -> bool {
+        if !self.is_bounded() {
+            return false;
+        }
+        let size = self.property.queue_size as usize;
+        if self.events.len() <= size {
+            return false;
+        }
+        match self.property.queue_mode {
+            PduQueueMode::Circular => {
+                let excess = self.events.len() - size;
+                self.events.drain(..excess);
+            },
+            _ => self.events.truncate(size)
+        }
+        true
+    }
+
+    /// Marks events as lost, returning true if this is a new overrun
+    fn mark_lost(&mut self) -> bool {
+        !std::mem::replace(&mut self.lost, true)
+    }
+}
+
+#[derive(Debug)]
+/// Event queue of a module or ComLogicalLink
+///
+/// The queue implements all three [PduQueueMode]s:
+/// * [PduQueueMode::Unlimited] - Every event is queued
+/// * [PduQueueMode::Limited] - When full, new events are discarded
+/// * [PduQueueMode::Circular] - When full, the oldest event is overwritten
+///
+/// The registered callback is notified with [PduEvtData::Available] when the queue goes from empty
+/// to non-empty, and with [PduEvtData::Lost] once per overrun. The overrun is reset once the
+/// application has drained the queue.
+pub struct EventQueue {
+    source: EventSource,
+    state: Mutex<QueueState>,
+    callback: Mutex<Option<EventCallbackFn>>
+}
+
+impl EventQueue {
+    /// Creates a new unlimited event queue
+    pub fn new(source: EventSource) -> Self {
+        Self {
+            source,
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                property: IoEventQueuePropertyData {
+                    queue_size: DEFAULT_EVENT_QUEUE_SIZE,
+                    queue_mode: PduQueueMode::Unlimited
+                },
+                lost: false
+            }),
+            callback: Mutex::new(None)
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the owner of the queue
+    pub fn source(&self) -> EventSource {
+        self.source
+    }
+
+    /// Registers or removes the event callback
+    pub fn set_callback(&self, callback: Option<EventCallbackFn>) {
+        *self.callback.lock().unwrap_or_else(|e| e.into_inner()) = callback;
+    }
+
+    /// Returns true if a callback is registered
+    pub fn has_callback(&self) -> bool {
+        self.callback.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    fn notify(&self, event: PduEvtData) {
+        let cb = *self.callback.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cb) = cb {
+            // Safety: The application guarantees the callback is valid while it is registered
+            unsafe {
+                cb(
+                    event,
+                    ModuleHandle::option_to_raw(self.source.h_mod),
+                    CllHandle::option_to_raw(self.source.h_cll),
+                    self.source.cll_tag.0,
+                    self.source.api_tag.0
+                )
+            }
+        }
+    }
+
+    /// Adds an event to the queue according to the queue mode, then notifies the callback
+    pub fn push(&self, event: Event) {
+        let (available, lost) = {
+            let mut state = self.lock();
+            let was_empty = state.events.is_empty();
+            let full = state.is_bounded() && state.events.len() >= state.property.queue_size as usize;
+            let mut lost = false;
+            if !full {
+                state.events.push_back(event);
+            } else if state.property.queue_mode == PduQueueMode::Circular {
+                state.events.pop_front();
+                state.events.push_back(event);
+                lost = state.mark_lost();
+            } else {
+                lost = state.mark_lost();
+            }
+            (was_empty && !state.events.is_empty(), lost)
+        };
+        if available {
+            self.notify(PduEvtData::Available);
+        }
+        if lost {
+            self.notify(PduEvtData::Lost);
+        }
+    }
+
+    /// Removes the oldest event from the queue. Returns [PduError::EventQueueEmpty] if
+    /// there are no events
+    pub fn pop(&self) -> Result<Event, PduError> {
+        let mut state = self.lock();
+        let event = state.events.pop_front().ok_or(PduError::EventQueueEmpty)?;
+        if state.events.is_empty() {
+            state.lost = false;
+        }
+        Ok(event)
+    }
+
+    /// Removes the oldest event from the queue, allocating it as an [EventItem] for `PDUGetEventItem`
+    pub fn pop_item(&self, items: &ItemAllocator) -> Result<*mut EventItem, PduError> {
+        self.pop().map(|e| items.alloc_event(&e))
+    }
+
+    /// Returns the number of queued events
+    pub fn len(&self) -> usize {
+        self.lock().events.len()
+    }
+
+    /// Returns true if there are no queued events
+    pub fn is_empty(&self) -> bool {
+        self.lock().events.is_empty()
+    }
+
+    /// Returns true if events were lost since the queue was last drained
+    pub fn has_lost_events(&self) -> bool {
+        self.lock().lost
+    }
+
+    /// Discards all queued events
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.events.clear();
+        state.lost = false;
+    }
+
+    /// Returns the queue size and mode
+    pub fn property(&self) -> IoEventQueuePropertyData {
+        self.lock().property
+    }
+
+    /// Changes the queue size and mode (`PDU_IOCTL_SET_EVENT_QUEUE_PROPERTIES`)
+    ///
+    /// Bounded queues must have a size of at least 1. If the queue shrinks, a limited queue
+    /// discards its newest events and a circular queue discards its oldest events
+    pub fn set_property(&self, property: IoEventQueuePropertyData) -> Result<(), PduError> {
+        if property.queue_mode != PduQueueMode::Unlimited && property.queue_size == 0 {
+            return Err(PduError::InvalidParameters);
+        }
+        let lost = {
+            let mut state = self.lock();
+            state.property = property;
+            state.trim() && state.mark_lost()
+        };
+        if lost {
+            self.notify(PduEvtData::Lost);
+        }
+        Ok(())
+    }
+}
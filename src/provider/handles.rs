@@ -0,0 +1,323 @@
+//! Handle registry for providers
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, MutexGuard}
+};
+
+use crate::{CllHandle, CopHandle, ModuleHandle, PduError, PDU_HANDLE_UNDEF, PDU_ID_UNDEF};
+
+#[derive(Debug)]
+struct ModuleEntry<M> {
+    object: Arc<M>,
+    links: BTreeSet<CllHandle>
+}
+
+#[derive(Debug)]
+struct LinkEntry<L> {
+    module: ModuleHandle,
+    object: Arc<L>,
+    cops: BTreeSet<CopHandle>
+}
+
+#[derive(Debug)]
+struct CopEntry<P> {
+    module: ModuleHandle,
+    link: CllHandle,
+    object: Arc<P>
+}
+
+#[derive(Debug)]
+struct Inner<M, L, P> {
+    next_handle: u32,
+    modules: BTreeMap<ModuleHandle, ModuleEntry<M>>,
+    links: BTreeMap<CllHandle, LinkEntry<L>>,
+    cops: BTreeMap<CopHandle, CopEntry<P>>
+}
+
+impl<M, L, P> Inner<M, L, P> {
+    /// Allocates a new handle which is not in use by any module, link or ComPrimitive
+    fn allocate(&mut self) -> u32 {
+        loop {
+            let h = self.next_handle;
+            self.next_handle = self.next_handle.wrapping_add(1);
+            if h == 0 || h == PDU_HANDLE_UNDEF || h == PDU_ID_UNDEF {
+                continue;
+            }
+            let in_use = ModuleHandle::new(h).is_some_and(|x| self.modules.contains_key(&x))
+                || CllHandle::new(h).is_some_and(|x| self.links.contains_key(&x))
+                || CopHandle::new(h).is_some_and(|x| self.cops.contains_key(&x));
+            if !in_use {
+                return h;
+            }
+        }
+    }
+
+    fn check_link(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<&LinkEntry<L>, PduError> {
+        if !self.modules.contains_key(&h_mod) {
+            return Err(PduError::InvalidHandle);
+        }
+        match self.links.get(&h_cll) {
+            Some(l) if l.module == h_mod => Ok(l),
+            _ => Err(PduError::InvalidHandle)
+        }
+    }
+
+    fn remove_link(&mut self, h_cll: CllHandle, removed: &mut Removed<M, L, P>) {
+        if let Some(link) = self.links.remove(&h_cll) {
+            for h_cop in link.cops {
+                if let Some(cop) = self.cops.remove(&h_cop) {
+                    removed.cops.push((cop.module, cop.link, h_cop, cop.object));
+                }
+            }
+            if let Some(m) = self.modules.get_mut(&link.module) {
+                m.links.remove(&h_cll);
+            }
+            removed.links.push((link.module, h_cll, link.object));
+        }
+    }
+
+    fn clear_module(&mut self, h_mod: ModuleHandle, removed: &mut Removed<M, L, P>) -> Result<(), PduError> {
+        let links: Vec<CllHandle> = self.modules.get(&h_mod).ok_or(PduError::InvalidHandle)?.links.iter().copied().collect();
+        for h_cll in links {
+            self.remove_link(h_cll, removed);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// Objects which were removed from a [HandleRegistry] as the result of a cascading removal
+///
+/// Objects should be cleaned up in the order ComPrimitives, ComLogicalLinks then modules
+pub struct Removed<M, L, P> {
+    /// Removed modules
+    pub modules: Vec<(ModuleHandle, Arc<M>)>,
+    /// Removed ComLogicalLinks
+    pub links: Vec<(ModuleHandle, CllHandle, Arc<L>)>,
+    /// Removed ComPrimitives
+    pub cops: Vec<(ModuleHandle, CllHandle, CopHandle, Arc<P>)>
+}
+
+impl<M, L, P> Default for Removed<M, L, P> {
+    fn default() -> Self {
+        Self { modules: Vec::new(), links: Vec::new(), cops: Vec::new() }
+    }
+}
+
+impl<M, L, P> Removed<M, L, P> {
+    /// Returns true if nothing was removed
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.links.is_empty() && self.cops.is_empty()
+    }
+}
+
+#[derive(Debug)]
+/// Thread safe registry of the handles of modules (`M`), ComLogicalLinks (`L`) and
+/// ComPrimitives (`P`).
+///
+/// The registry enforces the ownership tree of the API, where a ComLogicalLink belongs
+/// to a module, and a ComPrimitive belongs to a ComLogicalLink. Every lookup validates the
+/// full path of handles, returning [PduError::InvalidHandle] if any handle is unknown or
+/// belongs to a different parent.
+///
+/// Handles are unique across all three object types, and are never [PDU_HANDLE_UNDEF]
+/// or [PDU_ID_UNDEF].
+pub struct HandleRegistry<M, L, P> {
+    inner: Mutex<Inner<M, L, P>>
+}
+
+impl<M, L, P> Default for HandleRegistry<M, L, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, L, P> HandleRegistry<M, L, P> {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                next_handle: 1,
+                modules: BTreeMap::new(),
+                links: BTreeMap::new(),
+                cops: BTreeMap::new()
+            })
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner<M, L, P>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers a module, returning its new handle
+    pub fn add_module(&self, module: M) -> ModuleHandle {
+        let mut inner = self.lock();
+        let h = ModuleHandle::new(inner.allocate()).unwrap_or(ModuleHandle::UNDEF);
+        inner.modules.insert(h, ModuleEntry { object: Arc::new(module), links: BTreeSet::new() });
+        h
+    }
+
+    /// Registers a module, where the module object is created from its new handle. The
+    /// registry is not locked while the module is created
+    pub fn add_module_with<F: FnOnce(ModuleHandle) -> M>(&self, create: F) -> (ModuleHandle, Arc<M>) {
+        let h = ModuleHandle::new(self.lock().allocate()).unwrap_or(ModuleHandle::UNDEF);
+        let module = Arc::new(create(h));
+        self.lock().modules.insert(h, ModuleEntry { object: module.clone(), links: BTreeSet::new() });
+        (h, module)
+    }
+
+    /// Returns a module
+    pub fn module(&self, h_mod: ModuleHandle) -> Result<Arc<M>, PduError> {
+        self.lock().modules.get(&h_mod).map(|m| m.object.clone()).ok_or(PduError::InvalidHandle)
+    }
+
+    /// Returns all modules
+    pub fn modules(&self) -> Vec<(ModuleHandle, Arc<M>)> {
+        self.lock().modules.iter().map(|(h, m)| (*h, m.object.clone())).collect()
+    }
+
+    /// Registers a ComLogicalLink on a module, returning its new handle
+    pub fn add_link(&self, h_mod: ModuleHandle, link: L) -> Result<CllHandle, PduError> {
+        let mut inner = self.lock();
+        if !inner.modules.contains_key(&h_mod) {
+            return Err(PduError::InvalidHandle);
+        }
+        let h = CllHandle::new(inner.allocate()).unwrap_or(CllHandle::UNDEF);
+        inner.links.insert(h, LinkEntry { module: h_mod, object: Arc::new(link), cops: BTreeSet::new() });
+        if let Some(m) = inner.modules.get_mut(&h_mod) {
+            m.links.insert(h);
+        }
+        Ok(h)
+    }
+
+    /// Registers a ComLogicalLink on a module, where the link object is created from its new handle.
+    /// The registry is not locked while the link is created. Nothing is registered if creating
+    /// the link fails, or if the module was removed in the meantime
+    pub fn add_link_with<F>(&self, h_mod: ModuleHandle, create: F) -> Result<(CllHandle, Arc<L>), PduError>
+    where
+        F: FnOnce(CllHandle) -> Result<L, PduError>
+    {
+        let h = {
+            let mut inner = self.lock();
+            if !inner.modules.contains_key(&h_mod) {
+                return Err(PduError::InvalidHandle);
+            }
+            CllHandle::new(inner.allocate()).unwrap_or(CllHandle::UNDEF)
+        };
+        let link = Arc::new(create(h)?);
+        let mut inner = self.lock();
+        let m = inner.modules.get_mut(&h_mod).ok_or(PduError::InvalidHandle)?;
+        m.links.insert(h);
+        inner.links.insert(h, LinkEntry { module: h_mod, object: link.clone(), cops: BTreeSet::new() });
+        Ok((h, link))
+    }
+
+    /// Returns a ComLogicalLink, checking that it belongs to the module
+    pub fn link(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<Arc<L>, PduError> {
+        self.lock().check_link(h_mod, h_cll).map(|l| l.object.clone())
+    }
+
+    /// Returns the module which owns a ComLogicalLink
+    pub fn link_owner(&self, h_cll: CllHandle) -> Result<ModuleHandle, PduError> {
+        self.lock().links.get(&h_cll).map(|l| l.module).ok_or(PduError::InvalidHandle)
+    }
+
+    /// Returns all ComLogicalLinks of a module
+    pub fn links(&self, h_mod: ModuleHandle) -> Result<Vec<(CllHandle, Arc<L>)>, PduError> {
+        let inner = self.lock();
+        let m = inner.modules.get(&h_mod).ok_or(PduError::InvalidHandle)?;
+        Ok(m.links
+            .iter()
+            .filter_map(|h| inner.links.get(h).map(|l| (*h, l.object.clone())))
+            .collect())
+    }
+
+    /// Registers a ComPrimitive on a ComLogicalLink, returning its new handle
+    pub fn add_cop(&self, h_mod: ModuleHandle, h_cll: CllHandle, cop: P) -> Result<CopHandle, PduError> {
+        let mut inner = self.lock();
+        inner.check_link(h_mod, h_cll)?;
+        let h = CopHandle::new(inner.allocate()).unwrap_or(CopHandle::UNDEF);
+        inner.cops.insert(h, CopEntry { module: h_mod, link: h_cll, object: Arc::new(cop) });
+        if let Some(l) = inner.links.get_mut(&h_cll) {
+            l.cops.insert(h);
+        }
+        Ok(h)
+    }
+
+    /// Returns a ComPrimitive, checking that it belongs to the ComLogicalLink and module
+    pub fn cop(&self, h_mod: ModuleHandle, h_cll: CllHandle, h_cop: CopHandle) -> Result<Arc<P>, PduError> {
+        let inner = self.lock();
+        inner.check_link(h_mod, h_cll)?;
+        match inner.cops.get(&h_cop) {
+            Some(c) if c.link == h_cll => Ok(c.object.clone()),
+            _ => Err(PduError::InvalidHandle)
+        }
+    }
+
+    /// Returns all ComPrimitives of a ComLogicalLink
+    pub fn cops(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<Vec<(CopHandle, Arc<P>)>, PduError> {
+        let inner = self.lock();
+        let l = inner.check_link(h_mod, h_cll)?;
+        Ok(l.cops
+            .iter()
+            .filter_map(|h| inner.cops.get(h).map(|c| (*h, c.object.clone())))
+            .collect())
+    }
+
+    /// Removes a ComPrimitive
+    pub fn remove_cop(&self, h_mod: ModuleHandle, h_cll: CllHandle, h_cop: CopHandle) -> Result<Arc<P>, PduError> {
+        let mut inner = self.lock();
+        inner.check_link(h_mod, h_cll)?;
+        match inner.cops.get(&h_cop) {
+            Some(c) if c.link == h_cll => {},
+            _ => return Err(PduError::InvalidHandle)
+        }
+        if let Some(l) = inner.links.get_mut(&h_cll) {
+            l.cops.remove(&h_cop);
+        }
+        inner.cops.remove(&h_cop).map(|c| c.object).ok_or(PduError::InvalidHandle)
+    }
+
+    /// Removes a ComLogicalLink, and all of its ComPrimitives
+    pub fn remove_link(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<Removed<M, L, P>, PduError> {
+        let mut inner = self.lock();
+        inner.check_link(h_mod, h_cll)?;
+        let mut removed = Removed::default();
+        inner.remove_link(h_cll, &mut removed);
+        Ok(removed)
+    }
+
+    /// Removes all ComLogicalLinks and ComPrimitives of a module, but keeps the module registered.
+    /// This is used when a module is disconnected
+    pub fn clear_module(&self, h_mod: ModuleHandle) -> Result<Removed<M, L, P>, PduError> {
+        let mut removed = Removed::default();
+        self.lock().clear_module(h_mod, &mut removed)?;
+        Ok(removed)
+    }
+
+    /// Removes a module, and all of its ComLogicalLinks and ComPrimitives
+    pub fn remove_module(&self, h_mod: ModuleHandle) -> Result<Removed<M, L, P>, PduError> {
+        let mut inner = self.lock();
+        let mut removed = Removed::default();
+        inner.clear_module(h_mod, &mut removed)?;
+        let m = inner.modules.remove(&h_mod).ok_or(PduError::InvalidHandle)?;
+        removed.modules.push((h_mod, m.object));
+        Ok(removed)
+    }
+
+    /// Removes everything from the registry. This is used when the API is destructed
+    pub fn clear(&self) -> Removed<M, L, P> {
+        let mut inner = self.lock();
+        let links: Vec<CllHandle> = inner.links.keys().copied().collect();
+        let mut removed = Removed::default();
+        for h_cll in links {
+            inner.remove_link(h_cll, &mut removed);
+        }
+        removed.modules = std::mem::take(&mut inner.modules)
+            .into_iter()
+            .map(|(h, m)| (h, m.object))
+            .collect();
+        removed
+    }
+}
@@ -0,0 +1,663 @@
+//! ISO 15765-2 (ISO-TP) transport layer
+//!
+//! [IsoTp] segments messages into CAN frames and reassembles received frames into messages. It
+//! performs no I/O, so it can be used with any transport which sends and receives CAN frames:
+//! * Frames to transmit are taken from [IsoTp::poll_transmit], and confirmed with
+//!   [IsoTp::confirm_transmit] once the transport has sent them
+//! * Received frames are passed to [IsoTp::on_frame]
+//! * Completed messages and errors are taken from [IsoTp::poll_event]
+//!
+//! Every function takes the current time. Separation times and timeouts are only as accurate as
+//! the polling of the caller, [IsoTp::next_wake] returns when the engine next needs to be polled.
+//!
+//! Two engines with swapped IDs can talk to each other by passing frames between them:
+//!
+//! ```
+//! use std::time::Instant;
+//! use dpdu_rust::provider::{IsoTp, IsoTpConfig, IsoTpEvent};
+//!
+//! let config = IsoTpConfig { tx_id: 0x7E0, rx_id: 0x7E8, block_size: 2, ..Default::default() };
+//! let mut tester = IsoTp::new(config);
+//! let mut ecu = IsoTp::new(IsoTpConfig { tx_id: 0x7E8, rx_id: 0x7E0, ..config });
+//!
+//! let request: Vec<u8> = (0..100).collect();
+//! tester.send(&request, false, Instant::now()).unwrap();
+//! loop {
+//!     let now = Instant::now();
+//!     let mut idle = true;
+//!     while let Some(frame) = tester.poll_transmit(now) {
+//!         tester.confirm_transmit(now);
+//!         ecu.on_frame(&frame, now);
+//!         idle = false;
+//!     }
+//!     while let Some(frame) = ecu.poll_transmit(now) {
+//!         ecu.confirm_transmit(now);
+//!         tester.on_frame(&frame, now);
+//!         idle = false;
+//!     }
+//!     if idle && tester.is_idle() {
+//!         break;
+//!     }
+//! }
+//! assert_eq!(tester.poll_event(Instant::now()), Some(IsoTpEvent::Sent));
+//! assert!(matches!(ecu.poll_event(Instant::now()), Some(IsoTpEvent::Received { data, .. }) if data == request));
+//! ```
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant}
+};
+
+use crate::{canfd_len, is_canfd_len, CanFlags, PduError, PduErrorEvt, StdComParam, CAN_MAX_DLEN, CANFD_MAX_DLEN};
+
+use super::LinkParams;
+
+/// Padding of CAN FD frames when no padding byte is configured (ISO 15765-2)
+const DEFAULT_FD_PADDING: u8 = 0xCC;
+
+/// Largest message length of a first frame with a 12 bit length
+const MAX_SHORT_FF_LEN: usize = 0xFFF;
+
+/// Default N_As, N_Bs and N_Cr timeout (ISO 15765-2)
+pub const DEFAULT_ISOTP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Default number of consecutive wait flow control frames accepted by a sender (N_WFTmax)
+pub const DEFAULT_MAX_WAIT_FRAMES: u8 = 10;
+
+/// Default largest message which is received. Larger first frames are answered with an overflow
+/// flow control frame
+pub const DEFAULT_MAX_RX_LEN: usize = 0x10000;
+
+/// `CP_Can*Format` values which use an address extension byte (Extended and mixed addressing)
+pub const EXT_ADDR_FORMATS: &[u32] = &[0x02, 0x04, 0x06, 0x08, 0x09, 0x0A];
+
+/// `CP_StMinOverride` and `CP_BlockSizeOverride` value which disables the override
+pub const OVERRIDE_DISABLED: u32 = 0xFFFF_FFFF;
+
+// Protocol control information types
+const PCI_SF: u8 = 0x0;
+const PCI_FF: u8 = 0x1;
+const PCI_CF: u8 = 0x2;
+const PCI_FC: u8 = 0x3;
+
+// Flow status values
+const FS_CTS: u8 = 0x0;
+const FS_WAIT: u8 = 0x1;
+const FS_OVFLW: u8 = 0x2;
+
+/// Encodes a separation time as an STmin byte, rounding up to the next value which can be
+/// encoded. Times above 127ms are encoded as 127ms
+pub fn encode_st_min(time: Duration) -> u8 {
+    match time.as_micros() {
+        0 => 0,
+        us @ 1..=900 => 0xF0 + us.div_ceil(100) as u8,
+        us => us.div_ceil(1000).min(0x7F) as u8
+    }
+}
+
+/// Decodes an STmin byte. Reserved values are treated as 127ms, as required by ISO 15765-2
+pub fn decode_st_min(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F)
+    }
+}
+
+/// Largest message which fits a single frame of `frame_len` bytes
+fn max_single_frame(frame_len: usize, ext_addr: bool) -> usize {
+    match frame_len {
+        0..=CAN_MAX_DLEN => CAN_MAX_DLEN - 1 - ext_addr as usize,
+        _ => frame_len - 2 - ext_addr as usize
+    }
+}
+
+/// Converts a CAN ID to its canonical form, with bit 31 set on every 29 bit ID
+fn canonical_id(id: u32) -> u32 {
+    if id & 0x8000_0000 != 0 || id & 0x1FFF_FFFF > 0x7FF {
+        (id & 0x1FFF_FFFF) | 0x8000_0000
+    } else {
+        id & 0x7FF
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// CAN frame sent or received by [IsoTp]
+pub struct CanFrame {
+    /// CAN ID. Bit 31 marks a 29 bit ID, which is implied for IDs above 0x7FF
+    pub id: u32,
+    /// Frame format
+    pub flags: CanFlags,
+    /// Frame data. Up to 8 bytes for classic CAN frames, or a CAN FD length ([is_canfd_len])
+    pub data: Vec<u8>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Addressing and timing of an [IsoTp] engine
+pub struct IsoTpConfig {
+    /// CAN ID of physically addressed frames and flow control frames
+    pub tx_id: u32,
+    /// CAN ID of received frames
+    pub rx_id: u32,
+    /// CAN ID of functionally addressed frames
+    pub func_id: u32,
+    /// Address extension byte of physically addressed frames (Extended and mixed addressing)
+    pub tx_ext_addr: Option<u8>,
+    /// Address extension byte of received frames
+    pub rx_ext_addr: Option<u8>,
+    /// Address extension byte of functionally addressed frames
+    pub func_ext_addr: Option<u8>,
+    /// Byte transmitted frames are padded with. Without padding, frames are as short as possible,
+    /// except that CAN FD frames are padded with 0xCC to the next CAN FD length
+    pub padding: Option<u8>,
+    /// Largest data length of transmitted frames (TX_DL). 8 sends classic CAN frames, larger
+    /// CAN FD lengths send CAN FD frames. Frames of any length are received
+    pub tx_dl: usize,
+    /// Sends CAN FD frames with bit rate switching
+    pub brs: bool,
+    /// Block size reported in flow control frames. 0 lets the sender transmit every consecutive
+    /// frame without waiting for flow control
+    pub block_size: u8,
+    /// Separation time reported in flow control frames
+    pub st_min: Duration,
+    /// Block size used instead of the one reported by the receiver
+    pub block_size_override: Option<u8>,
+    /// Separation time used instead of the one reported by the receiver
+    pub st_min_override: Option<Duration>,
+    /// Timeout for the transmission of a frame (N_As), from [IsoTp::poll_transmit] returning the
+    /// frame until [IsoTp::confirm_transmit]
+    pub n_as: Duration,
+    /// Timeout waiting for a flow control frame (N_Bs)
+    pub n_bs: Duration,
+    /// Timeout waiting for a consecutive frame (N_Cr)
+    pub n_cr: Duration,
+    /// Number of consecutive wait flow control frames accepted before the message is aborted
+    /// (N_WFTmax)
+    pub max_wait_frames: u8,
+    /// Largest message which is received. First frames of larger messages are answered with an
+    /// overflow flow control frame
+    pub max_rx_len: usize
+}
+
+impl Default for IsoTpConfig {
+    fn default() -> Self {
+        Self {
+            tx_id: 0,
+            rx_id: 0,
+            func_id: 0,
+            tx_ext_addr: None,
+            rx_ext_addr: None,
+            func_ext_addr: None,
+            padding: None,
+            tx_dl: CAN_MAX_DLEN,
+            brs: false,
+            block_size: 0,
+            st_min: Duration::ZERO,
+            block_size_override: None,
+            st_min_override: None,
+            n_as: DEFAULT_ISOTP_TIMEOUT,
+            n_bs: DEFAULT_ISOTP_TIMEOUT,
+            n_cr: DEFAULT_ISOTP_TIMEOUT,
+            max_wait_frames: DEFAULT_MAX_WAIT_FRAMES,
+            max_rx_len: DEFAULT_MAX_RX_LEN
+        }
+    }
+}
+
+impl IsoTpConfig {
+    /// Creates the configuration from the ComParams of a link. Missing ComParams use the defaults
+    /// of [IsoTpConfig::default]
+    pub fn from_params(params: &LinkParams) -> Self {
+        let get = |p: StdComParam| params.get_u32(p).unwrap_or_default();
+        let ext = |format: StdComParam, addr: StdComParam| EXT_ADDR_FORMATS.contains(&get(format)).then_some(get(addr) as u8);
+        let time = |p: StdComParam| params.get_duration(p).unwrap_or(DEFAULT_ISOTP_TIMEOUT);
+        let over = |p: StdComParam| params.get_u32(p).filter(|x| *x != OVERRIDE_DISABLED);
+        Self {
+            tx_id: get(StdComParam::CanPhysReqId),
+            rx_id: get(StdComParam::CanRespUsdtId),
+            func_id: get(StdComParam::CanFuncReqId),
+            tx_ext_addr: ext(StdComParam::CanPhysReqFormat, StdComParam::CanPhysReqExtAddr),
+            rx_ext_addr: ext(StdComParam::CanRespUsdtFormat, StdComParam::CanRespUsdtExtAddr),
+            func_ext_addr: ext(StdComParam::CanFuncReqFormat, StdComParam::CanFuncReqExtAddr),
+            padding: (get(StdComParam::CanFillerByteHandling) != 0).then_some(get(StdComParam::CanFillerByte) as u8),
+            tx_dl: params
+                .get_u32(StdComParam::CanFdTxMaxDataLength)
+                .and_then(|dl| canfd_len((dl as usize).max(CAN_MAX_DLEN)))
+                .unwrap_or(CAN_MAX_DLEN),
+            brs: get(StdComParam::CanFdBitRateSwitch) != 0,
+            block_size: get(StdComParam::BlockSize).min(0xFF) as u8,
+            st_min: params.get_duration(StdComParam::StMin).unwrap_or_default(),
+            block_size_override: over(StdComParam::BlockSizeOverride).map(|x| x.min(0xFF) as u8),
+            st_min_override: over(StdComParam::StMinOverride).map(|us| Duration::from_micros(us as u64)),
+            n_as: time(StdComParam::As),
+            n_bs: time(StdComParam::Bs),
+            n_cr: time(StdComParam::Cr),
+            max_wait_frames: params
+                .get_u32(StdComParam::CanMaxNumWaitFrames)
+                .map_or(DEFAULT_MAX_WAIT_FRAMES, |x| x.min(0xFF) as u8),
+            max_rx_len: DEFAULT_MAX_RX_LEN
+        }
+    }
+
+    /// Largest message which is sent in a single frame
+    pub fn max_single_frame(&self, functional: bool) -> usize {
+        let ext = if functional { self.func_ext_addr } else { self.tx_ext_addr };
+        max_single_frame(self.tx_dl, ext.is_some())
+    }
+
+    /// Returns true if frames are sent as CAN FD frames
+    pub fn is_fd(&self) -> bool {
+        self.tx_dl > CAN_MAX_DLEN
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Event produced by [IsoTp]
+pub enum IsoTpEvent {
+    /// A message was received
+    Received {
+        /// Message data
+        data: Vec<u8>,
+        /// Time the single or first frame of the message was received
+        start: Instant,
+        /// Frame format of the single or first frame
+        flags: CanFlags
+    },
+    /// The message being sent has been fully transmitted
+    Sent,
+    /// Sending the message failed
+    TransmitFailed(PduErrorEvt),
+    /// Receiving a message failed
+    ReceiveFailed(PduErrorEvt)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TxState {
+    /// The single or first frame is ready to be sent
+    Start,
+    /// Waiting for a flow control frame
+    WaitFlowControl { deadline: Instant },
+    /// Sending consecutive frames
+    Consecutive { next: Instant, st_min: Duration, block_size: u8, sent: u8 },
+    /// Every frame was handed to the transport
+    Done
+}
+
+#[derive(Debug)]
+struct Transmit {
+    data: Vec<u8>,
+    functional: bool,
+    offset: usize,
+    seq: u8,
+    state: TxState,
+    /// Wait flow control frames received since the last clear to send
+    waits: u8,
+    /// N_As deadline of the frame handed to the transport, until it is confirmed
+    confirm: Option<Instant>
+}
+
+#[derive(Debug)]
+struct Receive {
+    data: Vec<u8>,
+    flags: CanFlags,
+    len: usize,
+    seq: u8,
+    received: u8,
+    start: Instant,
+    deadline: Instant
+}
+
+#[derive(Debug)]
+/// ISO 15765-2 transport layer engine for one pair of CAN IDs
+///
+/// One message can be sent and one message received at a time. Errors are reported as events:
+/// * [PduErrorEvt::RxTimeout] - No flow control frame within N_Bs, more than N_WFTmax wait flow
+///   control frames, or no consecutive frame within N_Cr
+/// * [PduErrorEvt::FrameStruct] - A malformed frame, a consecutive frame with the wrong sequence
+///   number, or a new message interrupting the message being received
+/// * [PduErrorEvt::TxError] - The receiver reported an overflow, or a frame was not confirmed
+///   within N_As
+/// * [PduErrorEvt::RxError] - A first frame announced a message larger than
+///   [IsoTpConfig::max_rx_len], which was answered with an overflow flow control frame
+pub struct IsoTp {
+    config: IsoTpConfig,
+    tx: Option<Transmit>,
+    rx: Option<Receive>,
+    flow_control: VecDeque<CanFrame>,
+    events: VecDeque<IsoTpEvent>
+}
+
+impl IsoTp {
+    /// Creates an idle engine
+    pub fn new(config: IsoTpConfig) -> Self {
+        Self { config, tx: None, rx: None, flow_control: VecDeque::new(), events: VecDeque::new() }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Changes the configuration, aborting any message being sent or received
+    pub fn set_config(&mut self, config: IsoTpConfig) {
+        self.config = config;
+        self.reset();
+    }
+
+    /// Aborts any message being sent or received, and discards pending events
+    pub fn reset(&mut self) {
+        self.tx = None;
+        self.rx = None;
+        self.flow_control.clear();
+        self.events.clear();
+    }
+
+    /// Returns true if no message is being sent or received
+    pub fn is_idle(&self) -> bool {
+        self.tx.is_none() && self.rx.is_none() && self.flow_control.is_empty()
+    }
+
+    /// Returns true if a message is being sent
+    pub fn is_sending(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// Starts sending a message. [IsoTpEvent::Sent] is produced once it has been transmitted
+    ///
+    /// ## Parameters
+    /// * data - Message data
+    /// * functional - Sends the message to [IsoTpConfig::func_id]. Functional messages must fit
+    ///   in a single frame
+    /// * now - Current time
+    pub fn send(&mut self, data: &[u8], functional: bool, now: Instant) -> Result<(), PduError> {
+        self.check_timeouts(now);
+        if data.is_empty() || data.len() > u32::MAX as usize {
+            return Err(PduError::InvalidParameters);
+        }
+        if functional && data.len() > self.config.max_single_frame(true) {
+            return Err(PduError::InvalidParameters);
+        }
+        if self.tx.is_some() {
+            return Err(PduError::FctFailed);
+        }
+        self.tx = Some(Transmit {
+            data: data.to_vec(),
+            functional,
+            offset: 0,
+            seq: 1,
+            state: TxState::Start,
+            waits: 0,
+            confirm: None
+        });
+        Ok(())
+    }
+
+    /// Aborts the message being sent, such as when the transport failed to transmit a frame
+    /// within N_As. [IsoTpEvent::TransmitFailed] is produced with the error
+    pub fn abort_transmit(&mut self, error: PduErrorEvt) {
+        if self.tx.take().is_some() {
+            self.events.push_back(IsoTpEvent::TransmitFailed(error));
+        }
+    }
+
+    /// Returns the next frame to transmit, if any is due. Frames of the message being sent must
+    /// be confirmed with [IsoTp::confirm_transmit] within N_As, and the next one is only returned
+    /// once the previous one was confirmed
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<CanFrame> {
+        self.check_timeouts(now);
+        if let Some(frame) = self.flow_control.pop_front() {
+            return Some(frame);
+        }
+        let config = self.config;
+        let tx = self.tx.as_mut().filter(|tx| tx.confirm.is_none())?;
+        let (id, ext) = match tx.functional {
+            true => (config.func_id, config.func_ext_addr),
+            false => (config.tx_id, config.tx_ext_addr)
+        };
+        let mut payload: Vec<u8> = ext.into_iter().collect();
+        match tx.state {
+            TxState::Start if tx.data.len() <= config.max_single_frame(tx.functional) => {
+                if tx.data.len() <= max_single_frame(CAN_MAX_DLEN, ext.is_some()) {
+                    payload.push((PCI_SF << 4) | tx.data.len() as u8);
+                } else {
+                    payload.extend([PCI_SF << 4, tx.data.len() as u8]);
+                }
+                payload.extend(&tx.data);
+                tx.offset = tx.data.len();
+            },
+            TxState::Start => {
+                if tx.data.len() <= MAX_SHORT_FF_LEN {
+                    payload.extend([(PCI_FF << 4) | (tx.data.len() >> 8) as u8, tx.data.len() as u8]);
+                } else {
+                    payload.extend([PCI_FF << 4, 0]);
+                    payload.extend((tx.data.len() as u32).to_be_bytes());
+                }
+                tx.offset = config.tx_dl - payload.len();
+                payload.extend(&tx.data[..tx.offset]);
+                tx.state = TxState::WaitFlowControl { deadline: now + config.n_bs };
+            },
+            TxState::Consecutive { next, st_min, block_size, sent } if next <= now => {
+                payload.push((PCI_CF << 4) | tx.seq);
+                let end = (tx.offset + config.tx_dl - payload.len()).min(tx.data.len());
+                payload.extend(&tx.data[tx.offset..end]);
+                tx.offset = end;
+                tx.seq = (tx.seq + 1) & 0x0F;
+                tx.state = if block_size != 0 && sent.wrapping_add(1) == block_size {
+                    TxState::WaitFlowControl { deadline: now + config.n_bs }
+                } else {
+                    TxState::Consecutive { next: now + st_min, st_min, block_size, sent: sent.wrapping_add(1) }
+                };
+            },
+            _ => return None
+        }
+        if tx.offset == tx.data.len() {
+            tx.state = TxState::Done;
+        }
+        tx.confirm = Some(now + config.n_as);
+        Some(self.frame(id, payload))
+    }
+
+    /// Confirms that the transport sent the last frame returned by [IsoTp::poll_transmit].
+    /// [IsoTpEvent::Sent] is produced once the last frame of the message is confirmed
+    pub fn confirm_transmit(&mut self, now: Instant) {
+        self.check_timeouts(now);
+        let Some(tx) = self.tx.as_mut() else {
+            return;
+        };
+        tx.confirm = None;
+        if matches!(tx.state, TxState::Done) {
+            self.tx = None;
+            self.events.push_back(IsoTpEvent::Sent);
+        }
+    }
+
+    /// Handles a received frame. Frames with other CAN IDs or address extensions are ignored
+    pub fn on_frame(&mut self, frame: &CanFrame, now: Instant) {
+        self.check_timeouts(now);
+        if canonical_id(frame.id) != canonical_id(self.config.rx_id) {
+            return;
+        }
+        let payload = match self.config.rx_ext_addr {
+            Some(ext) if frame.data.first() != Some(&ext) => return,
+            Some(_) => &frame.data[1..],
+            None => &frame.data[..]
+        };
+        let Some(pci) = payload.first() else {
+            return;
+        };
+        match pci >> 4 {
+            PCI_SF => self.on_single_frame(payload, frame, now),
+            PCI_FF => self.on_first_frame(payload, frame, now),
+            PCI_CF => self.on_consecutive_frame(payload, now),
+            PCI_FC => self.on_flow_control(payload, now),
+            _ => {}
+        }
+    }
+
+    /// Returns the next event
+    pub fn poll_event(&mut self, now: Instant) -> Option<IsoTpEvent> {
+        self.check_timeouts(now);
+        self.events.pop_front()
+    }
+
+    /// Returns when the engine next has to be polled for a frame to transmit or a timeout.
+    /// Frames which are due immediately are not included, [IsoTp::poll_transmit] should be called
+    /// until it returns [None] before waiting
+    pub fn next_wake(&self) -> Option<Instant> {
+        let tx = self.tx.as_ref().and_then(|tx| match tx.state {
+            _ if tx.confirm.is_some() => tx.confirm,
+            TxState::Start | TxState::Done => None,
+            TxState::WaitFlowControl { deadline } => Some(deadline),
+            TxState::Consecutive { next, .. } => Some(next)
+        });
+        let rx = self.rx.as_ref().map(|rx| rx.deadline);
+        tx.into_iter().chain(rx).min()
+    }
+
+    fn frame(&self, id: u32, mut data: Vec<u8>) -> CanFrame {
+        let pad = self.config.padding;
+        if data.len() > CAN_MAX_DLEN {
+            data.resize(canfd_len(data.len()).unwrap_or(CANFD_MAX_DLEN), pad.unwrap_or(DEFAULT_FD_PADDING));
+        } else if let Some(pad) = pad {
+            data.resize(CAN_MAX_DLEN, pad);
+        }
+        let flags = if self.config.is_fd() { CanFlags::fd(self.config.brs) } else { CanFlags::default() };
+        CanFrame { id, flags, data }
+    }
+
+    fn check_timeouts(&mut self, now: Instant) {
+        match self.tx {
+            Some(Transmit { confirm: Some(deadline), .. }) if deadline <= now => self.abort_transmit(PduErrorEvt::TxError),
+            Some(Transmit { confirm: None, state: TxState::WaitFlowControl { deadline }, .. }) if deadline <= now => {
+                self.abort_transmit(PduErrorEvt::RxTimeout)
+            },
+            _ => {}
+        }
+        if self.rx.as_ref().is_some_and(|rx| rx.deadline <= now) {
+            self.fail_receive(PduErrorEvt::RxTimeout);
+        }
+    }
+
+    fn fail_receive(&mut self, error: PduErrorEvt) {
+        self.rx = None;
+        self.events.push_back(IsoTpEvent::ReceiveFailed(error));
+    }
+
+    fn queue_flow_control(&mut self, status: u8) {
+        let mut payload: Vec<u8> = self.config.tx_ext_addr.into_iter().collect();
+        payload.extend([(PCI_FC << 4) | status, self.config.block_size, encode_st_min(self.config.st_min)]);
+        let frame = self.frame(self.config.tx_id, payload);
+        self.flow_control.push_back(frame);
+    }
+
+    fn on_single_frame(&mut self, payload: &[u8], frame: &CanFrame, now: Instant) {
+        let (len, header) = match payload {
+            // CAN FD single frames longer than 8 bytes carry the length in a second byte
+            [pci, len, ..] if pci & 0x0F == 0 && frame.data.len() > CAN_MAX_DLEN => (*len as usize, 2),
+            [pci, ..] => ((pci & 0x0F) as usize, 1),
+            [] => (0, 0)
+        };
+        if len == 0 || header + len > payload.len() {
+            self.events.push_back(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct));
+            return;
+        }
+        if self.rx.is_some() {
+            self.fail_receive(PduErrorEvt::FrameStruct);
+        }
+        let data = payload[header..header + len].to_vec();
+        self.events.push_back(IsoTpEvent::Received { data, start: now, flags: frame.flags });
+    }
+
+    fn on_first_frame(&mut self, payload: &[u8], frame: &CanFrame, now: Instant) {
+        let (len, header) = match payload {
+            [pci, 0, a, b, c, d, ..] if pci & 0x0F == 0 => (u32::from_be_bytes([*a, *b, *c, *d]) as usize, 6),
+            [pci, lo, ..] => ((((pci & 0x0F) as usize) << 8) | *lo as usize, 2),
+            _ => (0, 0)
+        };
+        // The first frame has the largest data length of the sender (RX_DL)
+        let frame_len = frame.data.len();
+        let min_len = match header {
+            6 => MAX_SHORT_FF_LEN + 1,
+            _ => max_single_frame(frame_len, self.config.rx_ext_addr.is_some()) + 1
+        };
+        if frame_len < CAN_MAX_DLEN || !is_canfd_len(frame_len) || len < min_len {
+            self.events.push_back(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct));
+            return;
+        }
+        if self.rx.is_some() {
+            self.fail_receive(PduErrorEvt::FrameStruct);
+        }
+        if len > self.config.max_rx_len {
+            self.queue_flow_control(FS_OVFLW);
+            self.events.push_back(IsoTpEvent::ReceiveFailed(PduErrorEvt::RxError));
+            return;
+        }
+        let mut data = Vec::with_capacity(len.min(MAX_SHORT_FF_LEN));
+        data.extend(&payload[header..]);
+        self.rx = Some(Receive {
+            data,
+            flags: frame.flags,
+            len,
+            seq: 1,
+            received: 0,
+            start: now,
+            deadline: now + self.config.n_cr
+        });
+        self.queue_flow_control(FS_CTS);
+    }
+
+    fn on_consecutive_frame(&mut self, payload: &[u8], now: Instant) {
+        let block_size = self.config.block_size;
+        let Some(rx) = self.rx.as_mut() else {
+            return;
+        };
+        if payload[0] & 0x0F != rx.seq {
+            self.fail_receive(PduErrorEvt::FrameStruct);
+            return;
+        }
+        let take = (rx.len - rx.data.len()).min(payload.len() - 1);
+        rx.data.extend(&payload[1..=take]);
+        rx.seq = (rx.seq + 1) & 0x0F;
+        rx.deadline = now + self.config.n_cr;
+        if rx.data.len() == rx.len {
+            let Some(rx) = self.rx.take() else {
+                return;
+            };
+            self.events.push_back(IsoTpEvent::Received { data: rx.data, start: rx.start, flags: rx.flags });
+            return;
+        }
+        rx.received = rx.received.wrapping_add(1);
+        if block_size != 0 && rx.received == block_size {
+            rx.received = 0;
+            self.queue_flow_control(FS_CTS);
+        }
+    }
+
+    fn on_flow_control(&mut self, payload: &[u8], now: Instant) {
+        let config = self.config;
+        let Some(tx) = self.tx.as_mut() else {
+            return;
+        };
+        if !matches!(tx.state, TxState::WaitFlowControl { .. }) {
+            return;
+        }
+        match (payload[0] & 0x0F, payload) {
+            (FS_CTS, [_, bs, st_min, ..]) => {
+                tx.waits = 0;
+                tx.state = TxState::Consecutive {
+                    next: now,
+                    st_min: config.st_min_override.unwrap_or_else(|| decode_st_min(*st_min)),
+                    block_size: config.block_size_override.unwrap_or(*bs),
+                    sent: 0
+                };
+            },
+            (FS_WAIT, _) if tx.waits >= config.max_wait_frames => self.abort_transmit(PduErrorEvt::RxTimeout),
+            (FS_WAIT, _) => {
+                tx.waits += 1;
+                tx.state = TxState::WaitFlowControl { deadline: now + config.n_bs };
+            },
+            (FS_OVFLW, _) => self.abort_transmit(PduErrorEvt::TxError),
+            _ => self.abort_transmit(PduErrorEvt::FrameStruct)
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Allocation of items returned to the application, which are later freed with `PDUDestroyItem`
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::c_void,
+    mem::{align_of, size_of},
+    sync::Mutex
+};
+
+use crate::*;
+
+use super::PduTag;
+
+/// Number of freed item addresses remembered for double free detection
+const FREED_HISTORY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of [ModuleData]
+pub struct ModuleInfo {
+    /// Module type ID
+    pub module_type_id: u32,
+    /// Module handle
+    pub h_mod: ModuleHandle,
+    /// Vendor specific name of the module
+    pub vendor_module_name: String,
+    /// Vendor specific additional information
+    pub vendor_additional_info: String,
+    /// Status of the module
+    pub status: PduStatus
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of a ComParam ([ParamItem])
+pub struct Param {
+    /// ComParam ID
+    pub id: ObjectId,
+    /// ComParam class
+    pub class: PduPc,
+    /// Value of the ComParam
+    pub value: ComParamValue
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Owned form of [ExtraInfo]
+pub struct ExtraInfoData {
+    /// Header bytes of the response
+    pub header: Vec<u8>,
+    /// Footer bytes of the response
+    pub footer: Vec<u8>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Owned form of [ResultData]
+pub struct ResultEvent {
+    /// Receive flag bytes
+    pub rx_flag: Vec<u8>,
+    /// Unique response identifier of the ECU which responded
+    pub unique_resp_identifier: u32,
+    /// Acceptance ID of the expected response which matched
+    pub acceptance_id: u32,
+    /// Timestamp flag bytes
+    pub timestamp_flags: Vec<u8>,
+    /// Timestamp when the transmit completed, in microseconds
+    pub tx_msg_done_timestamp: u32,
+    /// Timestamp when the message started, in microseconds
+    pub start_msg_timestamp: u32,
+    /// Optional header and footer bytes
+    pub extra_info: Option<ExtraInfoData>,
+    /// Payload
+    pub data: Vec<u8>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Data of an event
+pub enum EventData {
+    /// Result ([PduIt::Result])
+    Result(ResultEvent),
+    /// Status change ([PduIt::Status])
+    Status(PduStatus),
+    /// Error ([PduIt::Error])
+    Error(ErrorData),
+    /// Information ([PduIt::Info])
+    Info(InfoData)
+}
+
+impl EventData {
+    /// Returns the item type of the event
+    pub fn item_type(&self) -> PduIt {
+        match self {
+            Self::Result(_) => PduIt::Result,
+            Self::Status(_) => PduIt::Status,
+            Self::Error(_) => PduIt::Error,
+            Self::Info(_) => PduIt::Info
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of [EventItem]
+pub struct Event {
+    /// ComPrimitive which generated the event
+    pub h_cop: Option<CopHandle>,
+    /// Tag of the ComPrimitive
+    pub cop_tag: PduTag,
+    /// Timestamp of the event in microseconds
+    pub timestamp: u32,
+    /// Event data
+    pub data: EventData
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Owned form of IOCTL output data ([PduDataItem])
+pub enum IoctlData {
+    /// [PduIt::IoUnum32]
+    Unum32(u32),
+    /// [PduIt::IoProgVoltage]
+    ProgVoltage(IoProgVoltageData),
+    /// [PduIt::IoByteArray]
+    ByteArray(Vec<u8>),
+    /// [PduIt::IoEventQueueProperty]
+    EventQueueProperty(IoEventQueuePropertyData),
+    /// [PduIt::EntityStatus]
+    EntityStatus(IoEntityStatusData)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error when destroying an item
+pub enum ItemError {
+    /// Pointer was null
+    Null,
+    /// The item was already destroyed
+    DoubleFree,
+    /// The pointer was never allocated by this allocator
+    ForeignPointer
+}
+
+impl From<ItemError> for PduError {
+    fn from(_: ItemError) -> Self {
+        PduError::InvalidParameters
+    }
+}
+
+/// Bump allocator over a single block, which is run twice: Once without a block to measure
+/// the required size, then again to write the structures into the block
+struct Arena {
+    base: *mut u8,
+    offset: usize
+}
+
+impl Arena {
+    fn is_measuring(&self) -> bool {
+        self.base.is_null()
+    }
+
+    fn reserve<T>(&mut self, count: usize) -> *mut T {
+        debug_assert!(align_of::<T>() <= align_of::<u64>());
+        self.offset = self.offset.next_multiple_of(align_of::<T>());
+        let p = self.base.wrapping_add(self.offset).cast::<T>();
+        self.offset += size_of::<T>() * count;
+        p
+    }
+
+    fn write<T>(&mut self, p: *mut T, value: T) {
+        if !self.is_measuring() {
+            // Safety: p was reserved within the block
+            unsafe { p.write(value) }
+        }
+    }
+
+    fn push<T>(&mut self, value: T) -> *mut T {
+        let p = self.reserve::<T>(1);
+        self.write(p, value);
+        p
+    }
+
+    fn slice<T: Copy>(&mut self, data: &[T]) -> *mut T {
+        if data.is_empty() {
+            return std::ptr::null_mut();
+        }
+        let p = self.reserve::<T>(data.len());
+        if !self.is_measuring() {
+            // Safety: p was reserved within the block for data.len() elements
+            unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), p, data.len()) }
+        }
+        p
+    }
+
+    fn cstr(&mut self, s: &str) -> *mut u8 {
+        let mut bytes: Vec<u8> = s.bytes().filter(|b| *b != 0).collect();
+        bytes.push(0);
+        self.slice(&bytes)
+    }
+
+    fn flags(&mut self, flags: &[u8]) -> FlagData {
+        FlagData { num_flag_bytes: flags.len() as u32, p_flag_data: self.slice(flags) }
+    }
+
+    fn param(&mut self, param: &Param) -> ParamItem {
+        let p_com_param_data: *mut c_void = match &param.value {
+            ComParamValue::Unum8(x) => self.push(*x).cast(),
+            ComParamValue::Snum8(x) => self.push(*x).cast(),
+            ComParamValue::Unum16(x) => self.push(*x).cast(),
+            ComParamValue::Snum16(x) => self.push(*x).cast(),
+            ComParamValue::Unum32(x) => self.push(*x).cast(),
+            ComParamValue::Snum32(x) => self.push(*x).cast(),
+            ComParamValue::ByteField(x) => {
+                let hdr = self.reserve::<ParamByteFieldData>(1);
+                let data = self.slice(x);
+                self.write(hdr, ParamByteFieldData {
+                    param_max_len: x.len() as u32,
+                    param_act_len: x.len() as u32,
+                    p_data_array: data
+                });
+                hdr.cast()
+            },
+            ComParamValue::LongField(x) => {
+                let hdr = self.reserve::<ParamLongFieldData>(1);
+                let data = self.slice(x);
+                self.write(hdr, ParamLongFieldData {
+                    param_max_len: x.len() as u32,
+                    param_act_len: x.len() as u32,
+                    p_data_array: data
+                });
+                hdr.cast()
+            },
+            ComParamValue::SessionTiming(x) => {
+                let hdr = self.reserve::<ParamStructFieldData>(1);
+                let data = self.slice(x);
+                self.write(hdr, ParamStructFieldData {
+                    com_param_struct_type: PduCpst::SessionTiming,
+                    param_max_entries: x.len() as u32,
+                    param_act_entries: x.len() as u32,
+                    p_struct_array: data.cast()
+                });
+                hdr.cast()
+            },
+            ComParamValue::AccessTiming(x) => {
+                let hdr = self.reserve::<ParamStructFieldData>(1);
+                let data = self.slice(x);
+                self.write(hdr, ParamStructFieldData {
+                    com_param_struct_type: PduCpst::AccessTiming,
+                    param_max_entries: x.len() as u32,
+                    param_act_entries: x.len() as u32,
+                    p_struct_array: data.cast()
+                });
+                hdr.cast()
+            }
+        };
+        ParamItem {
+            item_type: PduIt::Param,
+            com_param_id: param.id.raw(),
+            com_param_data_type: param.value.data_type(),
+            com_param_class: param.class,
+            p_com_param_data
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Allocation {
+    item_type: PduIt,
+    _block: Box<[u64]>
+}
+
+#[derive(Debug, Default)]
+struct AllocatorState {
+    live: HashMap<usize, Allocation>,
+    freed: VecDeque<usize>
+}
+
+#[derive(Debug, Default)]
+/// Allocator for items which are handed to the application, and freed later by `PDUDestroyItem`
+///
+/// Each item, including all the structures it points to, is built from owned Rust values into a
+/// single tracked allocation. [ItemAllocator::destroy] only frees pointers which were handed
+/// out by the allocator, detecting double frees and foreign pointers.
+pub struct ItemAllocator {
+    state: Mutex<AllocatorState>
+}
+
+impl ItemAllocator {
+    /// Creates a new allocator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn alloc<T, F: Fn(&mut Arena) -> *mut T>(&self, item_type: PduIt, build: F) -> *mut T {
+        let mut measure = Arena { base: std::ptr::null_mut(), offset: 0 };
+        build(&mut measure);
+        let words = measure.offset.div_ceil(size_of::<u64>()).max(1);
+        let mut block = vec![0u64; words].into_boxed_slice();
+        let mut arena = Arena { base: block.as_mut_ptr().cast(), offset: 0 };
+        let root = build(&mut arena);
+        debug_assert_eq!(root.cast::<u8>(), arena.base);
+        let addr = root as usize;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.freed.retain(|a| *a != addr);
+        state.live.insert(addr, Allocation { item_type, _block: block });
+        root
+    }
+
+    /// Allocates a [ModuleItem] ([PduIt::ModuleId])
+    pub fn alloc_modules(&self, modules: &[ModuleInfo]) -> *mut ModuleItem {
+        self.alloc(PduIt::ModuleId, |a| {
+            let item = a.reserve::<ModuleItem>(1);
+            let data = if modules.is_empty() { std::ptr::null_mut() } else { a.reserve::<ModuleData>(modules.len()) };
+            for (i, m) in modules.iter().enumerate() {
+                let name = a.cstr(&m.vendor_module_name);
+                let info = a.cstr(&m.vendor_additional_info);
+                a.write(data.wrapping_add(i), ModuleData {
+                    module_type_id: m.module_type_id,
+                    h_mod: m.h_mod.raw(),
+                    vendor_module_name: name,
+                    vendor_additional_info: info,
+                    status: m.status
+                });
+            }
+            a.write(item, ModuleItem { item_type: PduIt::ModuleId, num_entries: modules.len() as u32, p_module_data: data });
+            item
+        })
+    }
+
+    /// Allocates a [RscIdItem] ([PduIt::RscId])
+    pub fn alloc_resource_ids(&self, ids: &[(ModuleHandle, Vec<ResourceId>)]) -> *mut RscIdItem {
+        self.alloc(PduIt::RscId, |a| {
+            let item = a.reserve::<RscIdItem>(1);
+            let data = if ids.is_empty() { std::ptr::null_mut() } else { a.reserve::<RscIdItemData>(ids.len()) };
+            for (i, (h_mod, rsc_ids)) in ids.iter().enumerate() {
+                let raw: Vec<u32> = rsc_ids.iter().map(|r| r.raw()).collect();
+                let arr = a.slice(&raw);
+                a.write(data.wrapping_add(i), RscIdItemData {
+                    h_mod: h_mod.raw(),
+                    num_ids: raw.len() as u32,
+                    p_resource_id_array: arr
+                });
+            }
+            a.write(item, RscIdItem { item_type: PduIt::RscId, num_modules: ids.len() as u32, p_id_item_data: data });
+            item
+        })
+    }
+
+    /// Allocates a [RscConflictItem] ([PduIt::RscConflict])
+    pub fn alloc_conflicts(&self, conflicts: &[(ModuleHandle, ResourceId)]) -> *mut RscConflictItem {
+        self.alloc(PduIt::RscConflict, |a| {
+            let item = a.reserve::<RscConflictItem>(1);
+            let data: Vec<RscConflictData> = conflicts
+                .iter()
+                .map(|(h_mod, id)| RscConflictData { h_mod: h_mod.raw(), resource_id: id.raw() })
+                .collect();
+            let p = a.slice(&data);
+            a.write(item, RscConflictItem { item_type: PduIt::RscConflict, num_entries: data.len() as u32, p_rsc_conflict_data: p });
+            item
+        })
+    }
+
+    /// Allocates a [ParamItem] ([PduIt::Param])
+    pub fn alloc_param(&self, param: &Param) -> *mut ParamItem {
+        self.alloc(PduIt::Param, |a| {
+            let item = a.reserve::<ParamItem>(1);
+            let v = a.param(param);
+            a.write(item, v);
+            item
+        })
+    }
+
+    /// Allocates a [UniqueRespIdTableItem] ([PduIt::UniqueRespIdTable])
+    ///
+    /// Each entry is a unique response identifier, and the ComParams which identify the ECU
+    pub fn alloc_unique_resp_id_table(&self, table: &[(u32, Vec<Param>)]) -> *mut UniqueRespIdTableItem {
+        self.alloc(PduIt::UniqueRespIdTable, |a| {
+            let item = a.reserve::<UniqueRespIdTableItem>(1);
+            let data = if table.is_empty() { std::ptr::null_mut() } else { a.reserve::<EcuUniqueRespData>(table.len()) };
+            for (i, (id, params)) in table.iter().enumerate() {
+                let p_params = if params.is_empty() { std::ptr::null_mut() } else { a.reserve::<ParamItem>(params.len()) };
+                for (j, param) in params.iter().enumerate() {
+                    let v = a.param(param);
+                    a.write(p_params.wrapping_add(j), v);
+                }
+                a.write(data.wrapping_add(i), EcuUniqueRespData {
+                    unique_resp_identifier: *id,
+                    num_param_items: params.len() as u32,
+                    p_params
+                });
+            }
+            a.write(item, UniqueRespIdTableItem {
+                item_type: PduIt::UniqueRespIdTable,
+                num_entries: table.len() as u32,
+                p_unique_data: data
+            });
+            item
+        })
+    }
+
+    /// Allocates an [EventItem] ([PduIt::Result], [PduIt::Status], [PduIt::Error] or [PduIt::Info])
+    pub fn alloc_event(&self, event: &Event) -> *mut EventItem {
+        let item_type = event.data.item_type();
+        self.alloc(item_type, |a| {
+            let item = a.reserve::<EventItem>(1);
+            let p_data: *mut c_void = match &event.data {
+                EventData::Result(r) => {
+                    let res = a.reserve::<ResultData>(1);
+                    let rx_flag = a.flags(&r.rx_flag);
+                    let timestamp_flags = a.flags(&r.timestamp_flags);
+                    let p_extra_info = match &r.extra_info {
+                        Some(extra) => {
+                            let p = a.reserve::<ExtraInfo>(1);
+                            let header = a.slice(&extra.header);
+                            let footer = a.slice(&extra.footer);
+                            a.write(p, ExtraInfo {
+                                num_header_bytes: extra.header.len() as u32,
+                                num_footer_bytes: extra.footer.len() as u32,
+                                p_header_bytes: header,
+                                p_footer_bytes: footer
+                            });
+                            p
+                        },
+                        None => std::ptr::null_mut()
+                    };
+                    let data = a.slice(&r.data);
+                    a.write(res, ResultData {
+                        rx_flag,
+                        unique_resp_identifier: r.unique_resp_identifier,
+                        acceptance_id: r.acceptance_id,
+                        timestamp_flags,
+                        tx_msg_done_timestamp: r.tx_msg_done_timestamp,
+                        start_msg_timestamp: r.start_msg_timestamp,
+                        p_extra_info,
+                        num_data_bytes: r.data.len() as u32,
+                        p_data_bytes: data
+                    });
+                    res.cast()
+                },
+                EventData::Status(s) => a.push(*s).cast(),
+                EventData::Error(e) => a.push(*e).cast(),
+                EventData::Info(i) => a.push(*i).cast()
+            };
+            a.write(item, EventItem {
+                item_type,
+                h_cop: CopHandle::option_to_raw(event.h_cop),
+                p_cop_tag: event.cop_tag.0,
+                timestamp: event.timestamp,
+                p_data
+            });
+            item
+        })
+    }
+
+    /// Allocates a [PduDataItem] for the output of an IOCTL
+    pub fn alloc_ioctl_data(&self, data: &IoctlData) -> *mut PduDataItem {
+        let item_type = match data {
+            IoctlData::Unum32(_) => PduIt::IoUnum32,
+            IoctlData::ProgVoltage(_) => PduIt::IoProgVoltage,
+            IoctlData::ByteArray(_) => PduIt::IoByteArray,
+            IoctlData::EventQueueProperty(_) => PduIt::IoEventQueueProperty,
+            IoctlData::EntityStatus(_) => PduIt::EntityStatus
+        };
+        self.alloc(item_type, |a| {
+            let item = a.reserve::<PduDataItem>(1);
+            let p_data: *mut c_void = match data {
+                IoctlData::Unum32(x) => a.push(*x).cast(),
+                IoctlData::ProgVoltage(x) => a.push(*x).cast(),
+                IoctlData::ByteArray(x) => {
+                    let hdr = a.reserve::<IoByteArrayData>(1);
+                    let p = a.slice(x);
+                    a.write(hdr, IoByteArrayData { data_size: x.len() as u32, p_data: p });
+                    hdr.cast()
+                },
+                IoctlData::EventQueueProperty(x) => a.push(*x).cast(),
+                IoctlData::EntityStatus(x) => a.push(*x).cast()
+            };
+            a.write(item, PduDataItem { item_type, p_data });
+            item
+        })
+    }
+
+    /// Destroys an item which was allocated by this allocator, returning its item type
+    pub fn destroy(&self, item: *mut PduItem) -> Result<PduIt, ItemError> {
+        if item.is_null() {
+            return Err(ItemError::Null);
+        }
+        let addr = item as usize;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.live.remove(&addr) {
+            Some(alloc) => {
+                if state.freed.len() == FREED_HISTORY {
+                    state.freed.pop_front();
+                }
+                state.freed.push_back(addr);
+                Ok(alloc.item_type)
+            },
+            None if state.freed.contains(&addr) => Err(ItemError::DoubleFree),
+            None => Err(ItemError::ForeignPointer)
+        }
+    }
+
+    /// Returns true if the pointer is an item which is currently allocated by this allocator
+    pub fn is_live(&self, item: *const PduItem) -> bool {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).live.contains_key(&(item as usize))
+    }
+
+    /// Returns the number of items which have not been destroyed yet
+    pub fn live_count(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).live.len()
+    }
+
+    /// Frees every item. Used when the API is destructed
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.live.clear();
+        state.freed.clear();
+    }
+}
@@ -0,0 +1,754 @@
+//! SAE J1939-21 data link layer, with J1939-81 address claiming
+//!
+//! [J1939] sends and receives the parameter groups of one node. Like [IsoTp](super::IsoTp) it
+//! performs no I/O:
+//! * Frames to transmit are taken from [J1939::poll_transmit]
+//! * Received frames are passed to [J1939::on_frame]
+//! * Received messages, transmit results and address claim results are taken from
+//!   [J1939::poll_event]
+//!
+//! Messages of up to 8 bytes are sent in a single frame. Longer messages, up to [MAX_TP_LEN]
+//! bytes, use the transport protocol: a BAM to the global address, or an RTS/CTS session to a
+//! specific address. Both are received, and only RTS/CTS sessions addressed to the node are
+//! answered.
+//!
+//! Before anything is sent the node claims an address with [J1939::claim]. A node with a lower
+//! NAME which claims the same address wins it, and [AddressClaim] selects whether the node then
+//! moves to an address of the arbitrary address range (128 to 247).
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//! use dpdu_rust::provider::{J1939, J1939Config, J1939Event, PGN_ADDRESS_CLAIMED};
+//!
+//! let mut tester = J1939::new(J1939Config { claim_timeout: Duration::ZERO, ..Default::default() });
+//! let mut ecu = J1939::new(J1939Config { address: 0x00, name: 0x10, claim_timeout: Duration::ZERO, ..Default::default() });
+//! tester.claim(Instant::now());
+//! ecu.claim(Instant::now());
+//!
+//! let dm1: Vec<u8> = (0..20).collect();
+//! let mut sent = false;
+//! let mut received = None;
+//! while received.is_none() {
+//!     let now = Instant::now();
+//!     while let Some(frame) = tester.poll_transmit(now) {
+//!         ecu.on_frame(&frame, now);
+//!     }
+//!     while let Some(frame) = ecu.poll_transmit(now) {
+//!         tester.on_frame(&frame, now);
+//!     }
+//!     while let Some(event) = ecu.poll_event(now) {
+//!         if event == J1939Event::AddressClaimed(0x00) && !sent {
+//!             ecu.send(0xFECA, 0xF9, &dm1, now).unwrap();
+//!             sent = true;
+//!         }
+//!     }
+//!     while let Some(event) = tester.poll_event(now) {
+//!         match event {
+//!             J1939Event::Received { message, .. } if message.pgn != PGN_ADDRESS_CLAIMED => received = Some(message),
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! let message = received.unwrap();
+//! assert_eq!((message.pgn, message.source, message.destination), (0xFECA, 0x00, 0xF9));
+//! assert_eq!(message.data, dm1);
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::RangeInclusive,
+    time::{Duration, Instant}
+};
+
+use crate::{CanFlags, PduError, PduErrorEvt, StdComParam, CAN_MAX_DLEN};
+
+use super::{CanFrame, LinkParams};
+
+/// PGN of the Request message
+pub const PGN_REQUEST: u32 = 0xEA00;
+/// PGN of the Address Claimed and Cannot Claim Address messages
+pub const PGN_ADDRESS_CLAIMED: u32 = 0xEE00;
+/// PGN of the transport protocol connection management messages (TP.CM)
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// PGN of the transport protocol data transfer messages (TP.DT)
+pub const PGN_TP_DT: u32 = 0xEB00;
+
+/// Destination address of messages to every node
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+/// Source address of Cannot Claim Address messages
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// Longest message of the transport protocol: 255 packets of 7 bytes
+pub const MAX_TP_LEN: usize = 255 * TP_PACKET_LEN;
+
+/// Length of the J1939 header of results: the PGN (3 bytes big endian), the source address and
+/// the destination address
+pub const J1939_HEADER_LEN: usize = 5;
+
+/// Default address of the tester, the first off-board diagnostic-service tool (J1939 appendix B)
+pub const DEFAULT_TESTER_ADDRESS: u8 = 0xF9;
+
+/// Default NAME of the tester: an arbitrary address capable off-board diagnostic-service tool
+/// (function 129)
+pub const DEFAULT_TESTER_NAME: u64 = 0x8000_8100_0000_0000;
+
+/// Default time to wait for contending address claims (J1939-81)
+pub const DEFAULT_ADDR_CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Data bytes of a TP.DT packet
+const TP_PACKET_LEN: usize = 7;
+
+/// Arbitrary address range (J1939-81)
+const ARBITRARY_ADDRESSES: RangeInclusive<u8> = 128..=247;
+
+/// Bit of the NAME which marks nodes capable of claiming an arbitrary address
+const NAME_ARBITRARY_ADDRESS_CAPABLE: u64 = 1 << 63;
+
+/// Priority of the transport protocol and network management messages
+const TP_PRIORITY: u8 = 7;
+const CLAIM_PRIORITY: u8 = 6;
+
+/// Lowest PDU format of PDU2 (broadcast) PGNs, which have no destination address
+const PDU2_FORMAT: u32 = 240;
+
+// TP.CM control bytes
+const CM_RTS: u8 = 16;
+const CM_CTS: u8 = 17;
+const CM_EOMA: u8 = 19;
+const CM_BAM: u8 = 32;
+const CM_ABORT: u8 = 255;
+
+// Connection abort reasons
+const ABORT_BUSY: u8 = 1;
+const ABORT_RESOURCES: u8 = 2;
+const ABORT_TIMEOUT: u8 = 3;
+const ABORT_SEQUENCE: u8 = 7;
+
+/// Time between the packets of a BAM (J1939-21 allows 50 to 200 ms)
+const BAM_PACKET_TIME: Duration = Duration::from_millis(50);
+/// Longest time between two received packets (T1)
+const T1: Duration = Duration::from_millis(750);
+/// Longest time from a CTS to the first packet (T2)
+const T2: Duration = Duration::from_millis(1250);
+/// Longest time from the last packet to the CTS or end of message acknowledgement (T3)
+const T3: Duration = Duration::from_millis(1250);
+/// Longest time from a CTS holding the connection open to the next CTS (T4)
+const T4: Duration = Duration::from_millis(1050);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Address claiming of a [J1939] node (`CP_J1939AddrClaim`)
+pub enum AddressClaim {
+    /// The preferred address is used without claiming it (0)
+    None,
+    /// The preferred address is claimed. The node stops sending if it loses the address (1)
+    Preferred,
+    /// The preferred address is claimed. If it is lost, and the NAME is arbitrary address
+    /// capable, a free address of the arbitrary address range is claimed (2)
+    #[default]
+    Arbitrary
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Address and NAME of a [J1939] node
+pub struct J1939Config {
+    /// Preferred source address
+    pub address: u8,
+    /// NAME of the node. Lower NAMEs win address claims
+    pub name: u64,
+    /// Address claiming
+    pub claim: AddressClaim,
+    /// Time to wait for contending claims before the address is used
+    pub claim_timeout: Duration,
+    /// Priority of sent messages (0 to 7)
+    pub priority: u8
+}
+
+impl Default for J1939Config {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_TESTER_ADDRESS,
+            name: DEFAULT_TESTER_NAME,
+            claim: AddressClaim::default(),
+            claim_timeout: DEFAULT_ADDR_CLAIM_TIMEOUT,
+            priority: 6
+        }
+    }
+}
+
+impl J1939Config {
+    /// Creates the configuration from the ComParams of a link. Missing ComParams use the defaults
+    /// of [J1939Config::default]
+    pub fn from_params(params: &LinkParams) -> Self {
+        let default = Self::default();
+        let name = params.get_bytes(StdComParam::J1939Name).map(|b| {
+            let mut name = [0; 8];
+            name.iter_mut().zip(b).for_each(|(n, b)| *n = *b);
+            u64::from_le_bytes(name)
+        });
+        Self {
+            address: params.get_u32(StdComParam::J1939PreferredAddress).map_or(default.address, |a| a as u8),
+            name: name.unwrap_or(default.name),
+            claim: match params.get_u32(StdComParam::J1939AddrClaim) {
+                Some(0) => AddressClaim::None,
+                Some(1) => AddressClaim::Preferred,
+                _ => AddressClaim::Arbitrary
+            },
+            claim_timeout: params.get_duration(StdComParam::J1939AddrClaimTimeout).unwrap_or(default.claim_timeout),
+            priority: default.priority
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Parameter group received by [J1939]
+pub struct J1939Message {
+    /// Parameter group number. The destination address is not part of PDU1 PGNs
+    pub pgn: u32,
+    /// Priority of the single frame or of the first TP.CM frame
+    pub priority: u8,
+    /// Source address
+    pub source: u8,
+    /// Destination address, [GLOBAL_ADDRESS] for broadcasts and PDU2 PGNs
+    pub destination: u8,
+    /// Message data
+    pub data: Vec<u8>
+}
+
+impl J1939Message {
+    /// Returns the header of results: the PGN (3 bytes big endian), the source address and the
+    /// destination address
+    pub fn header(&self) -> [u8; J1939_HEADER_LEN] {
+        let [_, a, b, c] = self.pgn.to_be_bytes();
+        [a, b, c, self.source, self.destination]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Event produced by [J1939]
+pub enum J1939Event {
+    /// A message was received
+    Received {
+        /// The message
+        message: J1939Message,
+        /// Time the single frame or first TP.CM frame of the message was received
+        start: Instant
+    },
+    /// The message being sent has been fully transmitted
+    Sent,
+    /// Sending the message failed
+    TransmitFailed(PduErrorEvt),
+    /// Receiving a message failed
+    ReceiveFailed(PduErrorEvt),
+    /// The node has claimed an address and can send messages
+    AddressClaimed(u8),
+    /// The node lost its address, or could not claim one
+    AddressLost
+}
+
+/// Returns the CAN ID of a frame (29 bit, with bit 31 set)
+fn can_id(priority: u8, pgn: u32, destination: u8, source: u8) -> u32 {
+    let ps = match (pgn >> 8) & 0xFF < PDU2_FORMAT {
+        true => destination as u32,
+        false => pgn & 0xFF
+    };
+    0x8000_0000 | ((priority as u32 & 0x07) << 26) | ((pgn & 0x3FF00) << 8) | (ps << 8) | source as u32
+}
+
+/// Splits a 29 bit CAN ID into priority, PGN, destination and source address
+fn split_id(id: u32) -> (u8, u32, u8, u8) {
+    let pgn = (id >> 8) & 0x3FFFF;
+    let (pgn, destination) = match (pgn >> 8) & 0xFF < PDU2_FORMAT {
+        true => (pgn & 0x3FF00, pgn as u8),
+        false => (pgn, GLOBAL_ADDRESS)
+    };
+    (((id >> 26) & 0x07) as u8, pgn, destination, id as u8)
+}
+
+/// Returns the PGN of a TP.CM message (3 bytes little endian at offset 5)
+fn cm_pgn(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[5], data[6], data[7], 0])
+}
+
+/// Builds a TP.CM message
+fn cm_data(control: u8, a: [u8; 4], pgn: u32) -> Vec<u8> {
+    let [p0, p1, p2, _] = pgn.to_le_bytes();
+    vec![control, a[0], a[1], a[2], a[3], p0, p1, p2]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddressState {
+    /// [J1939::claim] has not been called
+    Unclaimed,
+    /// Waiting for contending claims
+    Claiming { address: u8, deadline: Instant },
+    Claimed(u8),
+    /// The address was lost, or no address could be claimed
+    Lost
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TxState {
+    /// The single frame, BAM or RTS is ready to be sent
+    Start,
+    /// Sending the packets of a BAM
+    Bam { next: Instant },
+    /// Waiting for a CTS, or for the end of message acknowledgement once every packet is sent
+    WaitCts { deadline: Instant },
+    /// Sending the packets of a CTS, up to and including `last`
+    Data { last: u8 }
+}
+
+#[derive(Debug)]
+struct Transmit {
+    pgn: u32,
+    destination: u8,
+    data: Vec<u8>,
+    /// Sequence number of the next packet
+    seq: u8,
+    state: TxState
+}
+
+impl Transmit {
+    /// Returns true if TP.CM messages from `source` about `pgn` belong to this session
+    fn is_session(&self, source: u8, pgn: u32) -> bool {
+        self.destination == source && self.pgn == pgn
+    }
+
+    fn packets(&self) -> u8 {
+        self.data.len().div_ceil(TP_PACKET_LEN) as u8
+    }
+
+    fn packet(&self, seq: u8) -> Vec<u8> {
+        let start = (seq as usize - 1) * TP_PACKET_LEN;
+        let mut packet = vec![seq];
+        packet.extend(&self.data[start..(start + TP_PACKET_LEN).min(self.data.len())]);
+        packet.resize(CAN_MAX_DLEN, 0xFF);
+        packet
+    }
+}
+
+#[derive(Debug)]
+struct Receive {
+    message: J1939Message,
+    len: usize,
+    packets: u8,
+    /// Sequence number of the next packet
+    seq: u8,
+    /// Last packet of the current CTS. [None] for a BAM
+    window_end: Option<u8>,
+    /// Most packets the sender accepts per CTS
+    max_per_cts: u8,
+    start: Instant,
+    deadline: Instant
+}
+
+#[derive(Debug)]
+/// SAE J1939-21 engine of one node
+///
+/// Errors are reported as events:
+/// * [PduErrorEvt::RxTimeout] - No CTS or end of message acknowledgement within T3 (or T4 after
+///   a CTS holding the connection open), or no packet within T1 or T2
+/// * [PduErrorEvt::FrameStruct] - A malformed TP.CM message, a packet with the wrong sequence
+///   number, or a new session from the same node interrupting the session being received
+/// * [PduErrorEvt::TxError] - The receiver aborted the session
+/// * [PduErrorEvt::RxError] - The sender aborted the session
+pub struct J1939 {
+    config: J1939Config,
+    address: AddressState,
+    /// NAMEs of the other nodes, by address
+    names: BTreeMap<u8, u64>,
+    tx: Option<Transmit>,
+    /// Sessions being received, by source and destination address
+    rx: BTreeMap<(u8, u8), Receive>,
+    control: VecDeque<CanFrame>,
+    events: VecDeque<J1939Event>
+}
+
+impl J1939 {
+    /// Creates an engine which has no address yet
+    pub fn new(config: J1939Config) -> Self {
+        Self {
+            config,
+            address: AddressState::Unclaimed,
+            names: BTreeMap::new(),
+            tx: None,
+            rx: BTreeMap::new(),
+            control: VecDeque::new(),
+            events: VecDeque::new()
+        }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> &J1939Config {
+        &self.config
+    }
+
+    /// Returns the address of the node once it has been claimed
+    pub fn address(&self) -> Option<u8> {
+        match self.address {
+            AddressState::Claimed(address) => Some(address),
+            _ => None
+        }
+    }
+
+    /// Returns true if a message is being sent
+    pub fn is_sending(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// Claims the preferred address. [J1939Event::AddressClaimed] is produced once the claim
+    /// timeout has passed without a contending claim, or right away with [AddressClaim::None]
+    pub fn claim(&mut self, now: Instant) {
+        match self.config.claim {
+            AddressClaim::None => {
+                self.address = AddressState::Claimed(self.config.address);
+                self.events.push_back(J1939Event::AddressClaimed(self.config.address));
+            },
+            _ => self.start_claim(self.config.address, now)
+        }
+    }
+
+    /// Starts sending a message. [J1939Event::Sent] is produced once it has been transmitted
+    ///
+    /// ## Parameters
+    /// * pgn - Parameter group number. The low byte of PDU1 PGNs must be 0
+    /// * destination - Destination address, [GLOBAL_ADDRESS] to send to every node. Ignored by
+    ///   single frames of PDU2 PGNs
+    /// * data - Message data, at most [MAX_TP_LEN] bytes
+    /// * now - Current time
+    pub fn send(&mut self, pgn: u32, destination: u8, data: &[u8], now: Instant) -> Result<(), PduError> {
+        self.check_timeouts(now);
+        if pgn > 0x3FFFF || ((pgn >> 8) & 0xFF < PDU2_FORMAT && pgn & 0xFF != 0) || data.len() > MAX_TP_LEN {
+            return Err(PduError::InvalidParameters);
+        }
+        if self.address().is_none() || self.tx.is_some() {
+            return Err(PduError::FctFailed);
+        }
+        self.tx = Some(Transmit { pgn, destination, data: data.to_vec(), seq: 1, state: TxState::Start });
+        Ok(())
+    }
+
+    /// Aborts the message being sent, such as when the transport failed to transmit a frame.
+    /// [J1939Event::TransmitFailed] is produced with the error
+    pub fn abort_transmit(&mut self, error: PduErrorEvt) {
+        if self.tx.take().is_some() {
+            self.events.push_back(J1939Event::TransmitFailed(error));
+        }
+    }
+
+    /// Returns the next frame to transmit, if any is due
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<CanFrame> {
+        self.check_timeouts(now);
+        if let Some(frame) = self.control.pop_front() {
+            return Some(frame);
+        }
+        let source = self.address()?;
+        let priority = self.config.priority;
+        let tx = self.tx.as_mut()?;
+        let global = tx.destination == GLOBAL_ADDRESS;
+        let (id, data) = match tx.state {
+            TxState::Start if tx.data.len() <= CAN_MAX_DLEN => {
+                let frame = (can_id(priority, tx.pgn, tx.destination, source), tx.data.clone());
+                self.tx = None;
+                self.events.push_back(J1939Event::Sent);
+                return Some(CanFrame { id: frame.0, flags: CanFlags::default(), data: frame.1 });
+            },
+            TxState::Start => {
+                let [l0, l1, ..] = (tx.data.len() as u32).to_le_bytes();
+                let (control, max_per_cts, state) = match global {
+                    true => (CM_BAM, 0xFF, TxState::Bam { next: now + BAM_PACKET_TIME }),
+                    false => (CM_RTS, 0xFF, TxState::WaitCts { deadline: now + T3 })
+                };
+                tx.state = state;
+                let data = cm_data(control, [l0, l1, tx.packets(), max_per_cts], tx.pgn);
+                (can_id(TP_PRIORITY, PGN_TP_CM, tx.destination, source), data)
+            },
+            TxState::Bam { next } if next <= now => {
+                let packet = tx.packet(tx.seq);
+                if tx.seq == tx.packets() {
+                    self.tx = None;
+                    self.events.push_back(J1939Event::Sent);
+                } else {
+                    tx.seq += 1;
+                    tx.state = TxState::Bam { next: now + BAM_PACKET_TIME };
+                }
+                (can_id(TP_PRIORITY, PGN_TP_DT, GLOBAL_ADDRESS, source), packet)
+            },
+            TxState::Data { last } => {
+                let packet = tx.packet(tx.seq);
+                if tx.seq >= last {
+                    tx.state = TxState::WaitCts { deadline: now + T3 };
+                }
+                tx.seq = tx.seq.saturating_add(1);
+                (can_id(TP_PRIORITY, PGN_TP_DT, tx.destination, source), packet)
+            },
+            _ => return None
+        };
+        Some(CanFrame { id, flags: CanFlags::default(), data })
+    }
+
+    /// Handles a received frame. Frames with 11 bit IDs and PDU1 frames addressed to other nodes
+    /// are ignored
+    pub fn on_frame(&mut self, frame: &CanFrame, now: Instant) {
+        self.check_timeouts(now);
+        if frame.id & 0x8000_0000 == 0 && frame.id <= 0x7FF {
+            return;
+        }
+        let (priority, pgn, destination, source) = split_id(frame.id);
+        let own = match self.address {
+            AddressState::Claimed(address) | AddressState::Claiming { address, .. } => Some(address),
+            _ => None
+        };
+        if pgn == PGN_ADDRESS_CLAIMED {
+            self.on_address_claimed(source, &frame.data, now);
+        }
+        if destination != GLOBAL_ADDRESS && Some(destination) != own {
+            return;
+        }
+        match pgn {
+            PGN_TP_CM if frame.data.len() >= CAN_MAX_DLEN => {
+                self.on_connection_management(priority, source, destination, &frame.data, now)
+            },
+            PGN_TP_DT if !frame.data.is_empty() => self.on_data_transfer(source, destination, &frame.data, now),
+            PGN_TP_CM | PGN_TP_DT => self.events.push_back(J1939Event::ReceiveFailed(PduErrorEvt::FrameStruct)),
+            _ => {
+                if pgn == PGN_REQUEST && frame.data.get(..3) == Some(&PGN_ADDRESS_CLAIMED.to_le_bytes()[..3]) {
+                    self.answer_claim_request();
+                }
+                let message = J1939Message { pgn, priority, source, destination, data: frame.data.clone() };
+                self.events.push_back(J1939Event::Received { message, start: now });
+            }
+        }
+    }
+
+    /// Returns the next event
+    pub fn poll_event(&mut self, now: Instant) -> Option<J1939Event> {
+        self.check_timeouts(now);
+        self.events.pop_front()
+    }
+
+    /// Returns when the engine next has to be polled for a frame to transmit or a timeout.
+    /// Frames which are due immediately are not included, [J1939::poll_transmit] should be called
+    /// until it returns [None] before waiting
+    pub fn next_wake(&self) -> Option<Instant> {
+        let claim = match self.address {
+            AddressState::Claiming { deadline, .. } => Some(deadline),
+            _ => None
+        };
+        let tx = self.tx.as_ref().and_then(|tx| match tx.state {
+            TxState::Bam { next } => Some(next),
+            TxState::WaitCts { deadline } => Some(deadline),
+            TxState::Start | TxState::Data { .. } => None
+        });
+        let rx = self.rx.values().map(|rx| rx.deadline);
+        claim.into_iter().chain(tx).chain(rx).min()
+    }
+
+    fn start_claim(&mut self, address: u8, now: Instant) {
+        self.address = AddressState::Claiming { address, deadline: now + self.config.claim_timeout };
+        self.queue_claim(address);
+    }
+
+    /// Queues an Address Claimed message, or a Cannot Claim Address message from the null address
+    fn queue_claim(&mut self, address: u8) {
+        let id = can_id(CLAIM_PRIORITY, PGN_ADDRESS_CLAIMED, GLOBAL_ADDRESS, address);
+        self.control.push_back(CanFrame { id, flags: CanFlags::default(), data: self.config.name.to_le_bytes().to_vec() });
+    }
+
+    fn answer_claim_request(&mut self) {
+        match self.address {
+            AddressState::Claiming { address, .. } | AddressState::Claimed(address) => self.queue_claim(address),
+            AddressState::Lost => self.queue_claim(NULL_ADDRESS),
+            AddressState::Unclaimed => {}
+        }
+    }
+
+    fn on_address_claimed(&mut self, source: u8, data: &[u8], now: Instant) {
+        let Ok(name) = <[u8; 8]>::try_from(data.get(..8).unwrap_or_default()).map(u64::from_le_bytes) else {
+            return;
+        };
+        if source == NULL_ADDRESS || name == self.config.name {
+            return;
+        }
+        self.names.retain(|_, n| *n != name);
+        self.names.insert(source, name);
+        let own = match self.address {
+            AddressState::Claimed(address) | AddressState::Claiming { address, .. } => address,
+            _ => return
+        };
+        if source != own {
+            return;
+        }
+        if self.config.name < name {
+            // The contender has to give up the address
+            self.queue_claim(own);
+            return;
+        }
+        let arbitrary =
+            self.config.claim == AddressClaim::Arbitrary && self.config.name & NAME_ARBITRARY_ADDRESS_CAPABLE != 0;
+        let free = ARBITRARY_ADDRESSES.clone().find(|a| *a != own && !self.names.contains_key(a));
+        match free.filter(|_| arbitrary) {
+            Some(address) => self.start_claim(address, now),
+            None => {
+                self.address = AddressState::Lost;
+                self.abort_transmit(PduErrorEvt::TxError);
+                self.queue_claim(NULL_ADDRESS);
+                self.events.push_back(J1939Event::AddressLost);
+            }
+        }
+    }
+
+    /// Queues a TP.CM message
+    fn queue_cm(&mut self, destination: u8, data: Vec<u8>) {
+        if let Some(source) = self.address() {
+            let id = can_id(TP_PRIORITY, PGN_TP_CM, destination, source);
+            self.control.push_back(CanFrame { id, flags: CanFlags::default(), data });
+        }
+    }
+
+    fn queue_abort(&mut self, destination: u8, reason: u8, pgn: u32) {
+        self.queue_cm(destination, cm_data(CM_ABORT, [reason, 0xFF, 0xFF, 0xFF], pgn));
+    }
+
+    fn queue_cts(&mut self, destination: u8, count: u8, next: u8, pgn: u32) {
+        self.queue_cm(destination, cm_data(CM_CTS, [count, next, 0xFF, 0xFF], pgn));
+    }
+
+    fn check_timeouts(&mut self, now: Instant) {
+        if let AddressState::Claiming { address, deadline } = self.address {
+            if deadline <= now {
+                self.address = AddressState::Claimed(address);
+                self.events.push_back(J1939Event::AddressClaimed(address));
+            }
+        }
+        if let Some(Transmit { state: TxState::WaitCts { deadline }, destination, pgn, .. }) = self.tx {
+            if deadline <= now {
+                self.queue_abort(destination, ABORT_TIMEOUT, pgn);
+                self.abort_transmit(PduErrorEvt::RxTimeout);
+            }
+        }
+        let expired: Vec<(u8, u8)> = self.rx.iter().filter(|(_, rx)| rx.deadline <= now).map(|(k, _)| *k).collect();
+        for key in expired {
+            self.fail_receive(key, PduErrorEvt::RxTimeout, ABORT_TIMEOUT);
+        }
+    }
+
+    /// Ends a session being received, aborting it if it is an RTS/CTS session
+    fn fail_receive(&mut self, key: (u8, u8), error: PduErrorEvt, reason: u8) {
+        if let Some(rx) = self.rx.remove(&key) {
+            if rx.window_end.is_some() {
+                self.queue_abort(key.0, reason, rx.message.pgn);
+            }
+            self.events.push_back(J1939Event::ReceiveFailed(error));
+        }
+    }
+
+    fn on_connection_management(&mut self, priority: u8, source: u8, destination: u8, data: &[u8], now: Instant) {
+        let pgn = cm_pgn(data);
+        let len = u16::from_le_bytes([data[1], data[2]]) as usize;
+        match data[0] {
+            CM_BAM | CM_RTS => {
+                let rts = data[0] == CM_RTS;
+                if rts && destination == GLOBAL_ADDRESS {
+                    return;
+                }
+                let key = (source, destination);
+                if self.rx.contains_key(&key) {
+                    self.fail_receive(key, PduErrorEvt::FrameStruct, ABORT_BUSY);
+                }
+                let packets = data[3];
+                if len <= CAN_MAX_DLEN || len > MAX_TP_LEN || packets as usize != len.div_ceil(TP_PACKET_LEN) {
+                    if rts {
+                        self.queue_abort(source, ABORT_RESOURCES, pgn);
+                    }
+                    self.events.push_back(J1939Event::ReceiveFailed(PduErrorEvt::FrameStruct));
+                    return;
+                }
+                let max_per_cts = if rts { data[4].max(1) } else { 0xFF };
+                let window = packets.min(max_per_cts);
+                let message = J1939Message { pgn, priority, source, destination, data: Vec::with_capacity(len) };
+                self.rx.insert(key, Receive {
+                    message,
+                    len,
+                    packets,
+                    seq: 1,
+                    window_end: rts.then_some(window),
+                    max_per_cts,
+                    start: now,
+                    deadline: now + if rts { T2 } else { T1 }
+                });
+                if rts {
+                    self.queue_cts(source, window, 1, pgn);
+                }
+            },
+            CM_CTS => {
+                let Some(tx) = self.tx.as_mut().filter(|tx| tx.is_session(source, pgn)) else {
+                    return;
+                };
+                if !matches!(tx.state, TxState::WaitCts { .. }) {
+                    return;
+                }
+                let (count, next) = (data[1], data[2]);
+                if count == 0 {
+                    tx.state = TxState::WaitCts { deadline: now + T4 };
+                } else if next == 0 || next > tx.packets() {
+                    self.queue_abort(source, ABORT_SEQUENCE, pgn);
+                    self.abort_transmit(PduErrorEvt::FrameStruct);
+                } else {
+                    tx.seq = next;
+                    tx.state = TxState::Data { last: next.saturating_add(count - 1).min(tx.packets()) };
+                }
+            },
+            CM_EOMA if self.tx.as_ref().is_some_and(|tx| tx.is_session(source, pgn) && matches!(tx.state, TxState::WaitCts { .. })) => {
+                self.tx = None;
+                self.events.push_back(J1939Event::Sent);
+            },
+            CM_ABORT => {
+                if self.tx.as_ref().is_some_and(|tx| tx.is_session(source, pgn)) {
+                    self.abort_transmit(PduErrorEvt::TxError);
+                }
+                if self.rx.get(&(source, destination)).is_some_and(|rx| rx.message.pgn == pgn) {
+                    self.rx.remove(&(source, destination));
+                    self.events.push_back(J1939Event::ReceiveFailed(PduErrorEvt::RxError));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn on_data_transfer(&mut self, source: u8, destination: u8, data: &[u8], now: Instant) {
+        let key = (source, destination);
+        let Some(rx) = self.rx.get_mut(&key) else {
+            return;
+        };
+        if data[0] != rx.seq || rx.window_end.is_some_and(|end| data[0] > end) {
+            self.fail_receive(key, PduErrorEvt::FrameStruct, ABORT_SEQUENCE);
+            return;
+        }
+        let take = (rx.len - rx.message.data.len()).min(data.len() - 1);
+        rx.message.data.extend(&data[1..=take]);
+        rx.deadline = now + T1;
+        if rx.message.data.len() == rx.len {
+            let Some(rx) = self.rx.remove(&key) else {
+                return;
+            };
+            if rx.window_end.is_some() {
+                let [l0, l1, ..] = (rx.len as u32).to_le_bytes();
+                self.queue_cm(source, cm_data(CM_EOMA, [l0, l1, rx.packets, 0xFF], rx.message.pgn));
+            }
+            self.events.push_back(J1939Event::Received { message: rx.message, start: rx.start });
+            return;
+        }
+        if rx.window_end == Some(rx.seq) {
+            let next = rx.seq + 1;
+            let window = (rx.packets - rx.seq).min(rx.max_per_cts);
+            rx.window_end = Some(rx.seq + window);
+            rx.deadline = now + T2;
+            rx.seq = next;
+            let pgn = rx.message.pgn;
+            self.queue_cts(source, window, next, pgn);
+            return;
+        }
+        rx.seq += 1;
+    }
+}
@@ -0,0 +1,1087 @@
+//! Provider side of the D-PDU API
+//!
+//! This module allows for a D-PDU API shared library to be implemented in Rust. The vendor
+//! specific parts of the library are implemented with the [PduBackend] trait, and
+//! [export_pdu_api](crate::export_pdu_api) then exports all the `PDU*` functions of the API
+//! as `extern "C"` symbols.
+//!
+//! The exported functions validate their arguments before the backend is called, so a backend
+//! never has to deal with null pointers or calls made before `PDUConstruct`. Any panic
+//! within the backend is caught at the FFI boundary and reported as [PduError::FctFailed].
+//!
+//! ```ignore
+//! struct MyVci { /* ... */ }
+//!
+//! impl dpdu_rust::provider::PduBackend for MyVci {
+//!     /* ... */
+//! }
+//!
+//! dpdu_rust::export_pdu_api!(MyVci);
+//! ```
+
+use std::{
+    ffi::{c_void, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, RwLock}
+};
+
+use crate::*;
+
+mod cop;
+mod driver;
+mod events;
+mod handles;
+mod isotp;
+mod items;
+mod j1939;
+mod params;
+mod resources;
+mod state;
+
+pub use cop::*;
+pub use driver::*;
+pub use events::*;
+pub use handles::*;
+pub use isotp::*;
+pub use items::*;
+pub use j1939::*;
+pub use params::*;
+pub use resources::*;
+pub use state::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Application defined tag pointer (Used for `p_api_tag`, `p_cll_tag` and `p_cop_tag`)
+///
+/// The tag is never dereferenced by the API, it is only handed back to the application
+pub struct PduTag(pub *mut c_void);
+
+// Safety: The tag is an opaque value which is never dereferenced by the API
+unsafe impl Send for PduTag {}
+// Safety: The tag is an opaque value which is never dereferenced by the API
+unsafe impl Sync for PduTag {}
+
+impl PduTag {
+    /// Null tag
+    pub const NULL: Self = Self(std::ptr::null_mut());
+}
+
+impl Default for PduTag {
+    fn default() -> Self {
+        Self::NULL
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Last error which occurred on a module or ComLogicalLink
+pub struct LastError {
+    /// Error code
+    pub error_code: PduErrorEvt,
+    /// ComPrimitive the error relates to
+    pub h_cop: Option<CopHandle>,
+    /// Timestamp of the error in microseconds
+    pub timestamp: u32,
+    /// Extra error information
+    pub extra_error_info: u32
+}
+
+impl Default for LastError {
+    fn default() -> Self {
+        Self { error_code: PduErrorEvt::NoError, h_cop: None, timestamp: 0, extra_error_info: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Runtime status of a module, ComLogicalLink or ComPrimitive
+pub struct StatusInfo {
+    /// Status code
+    pub status: PduStatus,
+    /// Timestamp of the last status change in microseconds
+    pub timestamp: u32,
+    /// Extra status information
+    pub extra_info: u32
+}
+
+/// Vendor specific implementation of a D-PDU API library
+///
+/// Every function maps to one function of the API. Arguments have already been validated
+/// when a function is called:
+/// * Handles which are required by the API function are never undefined, and are passed
+///   as the handle type. Optional handles are passed as an [Option]
+/// * Pointer arguments are never null, and output pointers are handled by the caller
+///
+/// Items which are returned as raw pointers must stay valid until they are passed
+/// to [PduBackend::destroy_item]
+pub trait PduBackend: Send + Sync + 'static {
+    /// Constructs the backend (`PDUConstruct`)
+    ///
+    /// ## Parameters
+    /// * options - Option string passed by the application
+    /// * api_tag - Application defined tag value for callbacks
+    fn construct(options: &str, api_tag: PduTag) -> Result<Self, PduError>
+    where
+        Self: Sized;
+
+    /// Closes all open communication channels before the backend is dropped (`PDUDestruct`)
+    fn destruct(&self) -> Result<(), PduError> {
+        Ok(())
+    }
+
+    /// Returns a list of all modules (`PDUGetModuleIds`)
+    fn get_module_ids(&self) -> Result<*mut ModuleItem, PduError>;
+
+    /// Connects to a module, or all modules if `h_mod` is [None] (`PDUModuleConnect`)
+    fn module_connect(&self, h_mod: Option<ModuleHandle>) -> Result<(), PduError>;
+
+    /// Disconnects from a module, or all modules if `h_mod` is [None] (`PDUModuleDisconnect`)
+    fn module_disconnect(&self, h_mod: Option<ModuleHandle>) -> Result<(), PduError>;
+
+    /// Returns version information of a module (`PDUGetVersion`)
+    fn get_version(&self, h_mod: ModuleHandle) -> Result<VersionData, PduError>;
+
+    /// Returns the hardware clock of a module in microseconds (`PDUGetTimestamp`)
+    fn get_timestamp(&self, h_mod: ModuleHandle) -> Result<u32, PduError>;
+
+    /// Returns the status of a ComPrimitive, ComLogicalLink or module (`PDUGetStatus`)
+    ///
+    /// The status of the ComPrimitive is returned if `h_cop` is set, otherwise
+    /// the status of the ComLogicalLink if `h_cll` is set, otherwise the status of the module
+    fn get_status(
+        &self,
+        h_mod: ModuleHandle,
+        h_cll: Option<CllHandle>,
+        h_cop: Option<CopHandle>
+    ) -> Result<StatusInfo, PduError>;
+
+    /// Returns the last error of a module or ComLogicalLink (`PDUGetLastError`)
+    fn get_last_error(&self, _h_mod: ModuleHandle, _h_cll: Option<CllHandle>) -> Result<LastError, PduError> {
+        Ok(LastError::default())
+    }
+
+    /// Performs an IOCTL on the API, a module or a ComLogicalLink (`PDUIoCtl`)
+    ///
+    /// Returns a null pointer if the IOCTL has no output data
+    fn ioctl(
+        &self,
+        h_mod: Option<ModuleHandle>,
+        h_cll: Option<CllHandle>,
+        ioctl_id: ObjectId,
+        input: Option<&PduDataItem>
+    ) -> Result<*mut PduDataItem, PduError>;
+
+    /// Resolves the ID of an object by its short name (`PDUGetObjectId`)
+    ///
+    /// Returns [None] if the object is not known by the backend
+    fn get_object_id(&self, object_type: PduObjt, short_name: &str) -> Result<Option<ObjectId>, PduError>;
+
+    /// Returns the resource IDs matching the resource data on a module, or all modules if
+    /// `h_mod` is [None] (`PDUGetResourceIds`)
+    fn get_resource_ids(&self, h_mod: Option<ModuleHandle>, rsc_data: &RscData) -> Result<*mut RscIdItem, PduError>;
+
+    /// Fills in [RscStatusItem::resource_status] of each entry (`PDUGetResourceStatus`)
+    fn get_resource_status(&self, items: &mut [RscStatusItem]) -> Result<(), PduError>;
+
+    /// Returns the resources which conflict with a resource on the given modules.
+    /// `modules` is empty if all modules are to be checked (`PDUGetConflictingResources`)
+    fn get_conflicting_resources(
+        &self,
+        resource_id: ResourceId,
+        modules: &[ModuleHandle]
+    ) -> Result<*mut RscConflictItem, PduError>;
+
+    /// Locks physical resources for a ComLogicalLink (`PDULockResource`)
+    fn lock_resource(&self, _h_mod: ModuleHandle, _h_cll: CllHandle, _lock_mask: u32) -> Result<(), PduError> {
+        Err(PduError::FctFailed)
+    }
+
+    /// Unlocks physical resources of a ComLogicalLink (`PDUUnlockResource`)
+    fn unlock_resource(&self, _h_mod: ModuleHandle, _h_cll: CllHandle, _lock_mask: u32) -> Result<(), PduError> {
+        Err(PduError::FctFailed)
+    }
+
+    /// Creates a ComLogicalLink (`PDUCreateComLogicalLink`)
+    ///
+    /// Either `rsc_data` or `resource_id` is set
+    fn create_com_logical_link(
+        &self,
+        h_mod: ModuleHandle,
+        rsc_data: Option<&RscData>,
+        resource_id: Option<ResourceId>,
+        cll_tag: PduTag,
+        create_flags: Option<&FlagData>
+    ) -> Result<CllHandle, PduError>;
+
+    /// Destroys a ComLogicalLink (`PDUDestroyComLogicalLink`)
+    fn destroy_com_logical_link(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError>;
+
+    /// Connects a ComLogicalLink to the vehicle bus (`PDUConnect`)
+    fn connect(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError>;
+
+    /// Disconnects a ComLogicalLink from the vehicle bus (`PDUDisconnect`)
+    fn disconnect(&self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<(), PduError>;
+
+    /// Returns a ComParam from the working buffer of a ComLogicalLink (`PDUGetComParam`)
+    fn get_com_param(&self, h_mod: ModuleHandle, h_cll: CllHandle, param_id: ObjectId) -> Result<*mut ParamItem, PduError>;
+
+    /// Sets a ComParam in the working buffer of a ComLogicalLink (`PDUSetComParam`)
+    fn set_com_param(&self, h_mod: ModuleHandle, h_cll: CllHandle, param: &ParamItem) -> Result<(), PduError>;
+
+    /// Creates and queues a ComPrimitive on a ComLogicalLink (`PDUStartComPrimitive`)
+    fn start_com_primitive(
+        &self,
+        h_mod: ModuleHandle,
+        h_cll: CllHandle,
+        cop_type: PduCopt,
+        data: &[u8],
+        ctrl: Option<&CopCtrlData>,
+        cop_tag: PduTag
+    ) -> Result<CopHandle, PduError>;
+
+    /// Cancels a ComPrimitive (`PDUCancelComPrimitive`)
+    fn cancel_com_primitive(&self, h_mod: ModuleHandle, h_cll: CllHandle, h_cop: CopHandle) -> Result<(), PduError>;
+
+    /// Returns the next event item of the API, a module or a ComLogicalLink (`PDUGetEventItem`)
+    ///
+    /// Returns [PduError::EventQueueEmpty] if there are no events
+    fn get_event_item(&self, h_mod: Option<ModuleHandle>, h_cll: Option<CllHandle>) -> Result<*mut EventItem, PduError>;
+
+    /// Registers or removes the event callback of the API, a module or a ComLogicalLink
+    /// (`PDURegisterEventCallback`)
+    fn register_event_callback(
+        &self,
+        h_mod: Option<ModuleHandle>,
+        h_cll: Option<CllHandle>,
+        callback: Option<EventCallbackFn>
+    ) -> Result<(), PduError>;
+
+    /// Destroys an item which was previously returned by the backend (`PDUDestroyItem`)
+    fn destroy_item(&self, item: *mut PduItem) -> Result<(), PduError>;
+
+    /// Returns the unique response ID table of a ComLogicalLink (`PDUGetUniqueRespIdTable`)
+    fn get_unique_resp_id_table(
+        &self,
+        _h_mod: ModuleHandle,
+        _h_cll: CllHandle
+    ) -> Result<*mut UniqueRespIdTableItem, PduError> {
+        Err(PduError::FctFailed)
+    }
+
+    /// Sets the unique response ID table of a ComLogicalLink (`PDUSetUniqueRespIdTable`)
+    fn set_unique_resp_id_table(
+        &self,
+        _h_mod: ModuleHandle,
+        _h_cll: CllHandle,
+        _table: &UniqueRespIdTableItem
+    ) -> Result<(), PduError> {
+        Err(PduError::FctFailed)
+    }
+}
+
+/// Runs a function, converting its result and any panic into a [PduError]
+pub fn ffi_guard<F: FnOnce() -> Result<(), PduError>>(f: F) -> PduError {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => PduError::StatusNoError,
+        Ok(Err(e)) => e,
+        Err(_) => PduError::FctFailed
+    }
+}
+
+/// Returns an error if a pointer is null
+fn not_null<T>(p: *const T) -> Result<(), PduError> {
+    if p.is_null() {
+        Err(PduError::InvalidParameters)
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts a required module handle
+fn req_mod(h_mod: u32) -> Result<ModuleHandle, PduError> {
+    ModuleHandle::new(h_mod).ok_or(PduError::InvalidHandle)
+}
+
+/// Converts a required ComLogicalLink handle
+fn req_cll(h_cll: u32) -> Result<CllHandle, PduError> {
+    CllHandle::new(h_cll).ok_or(PduError::InvalidHandle)
+}
+
+#[derive(Debug)]
+/// Global state of an exported D-PDU API library
+///
+/// This is created by [export_pdu_api](crate::export_pdu_api), each function of this structure
+/// matches an API function, validating the arguments before calling the [PduBackend].
+///
+/// # Safety
+/// All pointers passed to the functions must either be null, or valid for the access
+/// described by the ISO 22900-2 standard for the matching API function
+pub struct Provider<B: PduBackend> {
+    backend: RwLock<Option<Arc<B>>>
+}
+
+impl<B: PduBackend> Default for Provider<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: PduBackend> Provider<B> {
+    /// Creates a new provider, in the not constructed state
+    pub const fn new() -> Self {
+        Self { backend: RwLock::new(None) }
+    }
+
+    /// Returns the backend, or [None] if the API is not constructed
+    pub fn backend(&self) -> Option<Arc<B>> {
+        self.backend.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn with<F: FnOnce(&B) -> Result<(), PduError>>(&self, f: F) -> PduError {
+        match self.backend() {
+            Some(b) => ffi_guard(|| f(&b)),
+            None => PduError::PduApiNotConstructed
+        }
+    }
+
+    /// `PDUConstruct`
+    ///
+    /// # Safety
+    /// `option_str` must be null or a valid null terminated string
+    pub unsafe fn construct(&self, option_str: *mut u8, p_api_tag: *mut c_void) -> PduError {
+        ffi_guard(|| {
+            let mut lock = self.backend.write().unwrap_or_else(|e| e.into_inner());
+            if lock.is_some() {
+                return Err(PduError::SharingViolation);
+            }
+            let options = if option_str.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(option_str.cast()).to_string_lossy().into_owned()
+            };
+            *lock = Some(Arc::new(B::construct(&options, PduTag(p_api_tag))?));
+            Ok(())
+        })
+    }
+
+    /// `PDUDestruct`
+    pub fn destruct(&self) -> PduError {
+        ffi_guard(|| {
+            let backend = self
+                .backend
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+                .ok_or(PduError::PduApiNotConstructed)?;
+            backend.destruct()
+        })
+    }
+
+    /// `PDUIoCtl`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn ioctl(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        ioctl_command_id: u32,
+        p_input_data: *mut PduDataItem,
+        p_output_data: *mut *mut PduDataItem
+    ) -> PduError {
+        self.with(|b| {
+            let id = ObjectId::new(ioctl_command_id).ok_or(PduError::IdNotSupported)?;
+            let out = b.ioctl(ModuleHandle::new(h_mod), CllHandle::new(h_cll), id, p_input_data.as_ref())?;
+            if p_output_data.is_null() {
+                if !out.is_null() {
+                    b.destroy_item(out.cast())?;
+                }
+            } else {
+                *p_output_data = out;
+            }
+            Ok(())
+        })
+    }
+
+    /// `PDUGetVersion`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_version(&self, h_mod: u32, p_version_data: *mut VersionData) -> PduError {
+        self.with(|b| {
+            not_null(p_version_data)?;
+            *p_version_data = b.get_version(req_mod(h_mod)?)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetStatus`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_status(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        h_cop: u32,
+        p_status_code: *mut PduStatus,
+        p_timestamp: *mut u32,
+        p_extra_info: *mut u32
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_status_code)?;
+            not_null(p_timestamp)?;
+            not_null(p_extra_info)?;
+            let h_cll = CllHandle::new(h_cll);
+            let h_cop = CopHandle::new(h_cop);
+            if h_cop.is_some() && h_cll.is_none() {
+                return Err(PduError::InvalidHandle);
+            }
+            let status = b.get_status(req_mod(h_mod)?, h_cll, h_cop)?;
+            *p_status_code = status.status;
+            *p_timestamp = status.timestamp;
+            *p_extra_info = status.extra_info;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetLastError`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_last_error(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        p_error_code: *mut PduErrorEvt,
+        ph_cop: *mut u32,
+        p_timestamp: *mut u32,
+        p_extra_error_info: *mut u32
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_error_code)?;
+            not_null(ph_cop)?;
+            not_null(p_timestamp)?;
+            not_null(p_extra_error_info)?;
+            let err = b.get_last_error(req_mod(h_mod)?, CllHandle::new(h_cll))?;
+            *p_error_code = err.error_code;
+            *ph_cop = CopHandle::option_to_raw(err.h_cop);
+            *p_timestamp = err.timestamp;
+            *p_extra_error_info = err.extra_error_info;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetResourceStatus`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_resource_status(&self, p_resource_status: *mut RscStatusData) -> PduError {
+        self.with(|b| {
+            not_null(p_resource_status)?;
+            let data = &mut *p_resource_status;
+            if data.num_entries == 0 {
+                return Ok(());
+            }
+            not_null(data.p_resource_status_data)?;
+            let items = std::slice::from_raw_parts_mut(data.p_resource_status_data, data.num_entries as usize);
+            b.get_resource_status(items)
+        })
+    }
+
+    /// `PDUCreateComLogicalLink`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn create_com_logical_link(
+        &self,
+        h_mod: u32,
+        p_rsc_data: *mut RscData,
+        resource_id: u32,
+        p_cll_tag: *mut c_void,
+        ph_cll: *mut u32,
+        p_cll_create_flag: *mut FlagData
+    ) -> PduError {
+        self.with(|b| {
+            not_null(ph_cll)?;
+            let resource_id = ResourceId::new(resource_id);
+            if p_rsc_data.is_null() && resource_id.is_none() {
+                return Err(PduError::InvalidParameters);
+            }
+            let h_cll = b.create_com_logical_link(
+                req_mod(h_mod)?,
+                p_rsc_data.as_ref(),
+                resource_id,
+                PduTag(p_cll_tag),
+                p_cll_create_flag.as_ref()
+            )?;
+            *ph_cll = h_cll.raw();
+            Ok(())
+        })
+    }
+
+    /// `PDUDestroyComLogicalLink`
+    pub fn destroy_com_logical_link(&self, h_mod: u32, h_cll: u32) -> PduError {
+        self.with(|b| b.destroy_com_logical_link(req_mod(h_mod)?, req_cll(h_cll)?))
+    }
+
+    /// `PDUConnect`
+    pub fn connect(&self, h_mod: u32, h_cll: u32) -> PduError {
+        self.with(|b| b.connect(req_mod(h_mod)?, req_cll(h_cll)?))
+    }
+
+    /// `PDUDisconnect`
+    pub fn disconnect(&self, h_mod: u32, h_cll: u32) -> PduError {
+        self.with(|b| b.disconnect(req_mod(h_mod)?, req_cll(h_cll)?))
+    }
+
+    /// `PDULockResource`
+    pub fn lock_resource(&self, h_mod: u32, h_cll: u32, lock_mask: u32) -> PduError {
+        self.with(|b| b.lock_resource(req_mod(h_mod)?, req_cll(h_cll)?, lock_mask))
+    }
+
+    /// `PDUUnlockResource`
+    pub fn unlock_resource(&self, h_mod: u32, h_cll: u32, lock_mask: u32) -> PduError {
+        self.with(|b| b.unlock_resource(req_mod(h_mod)?, req_cll(h_cll)?, lock_mask))
+    }
+
+    /// `PDUGetComParam`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_com_param(&self, h_mod: u32, h_cll: u32, param_id: u32, p_param_item: *mut *mut ParamItem) -> PduError {
+        self.with(|b| {
+            not_null(p_param_item)?;
+            let id = ObjectId::new(param_id).ok_or(PduError::ComParamNotSupported)?;
+            *p_param_item = b.get_com_param(req_mod(h_mod)?, req_cll(h_cll)?, id)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUSetComParam`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn set_com_param(&self, h_mod: u32, h_cll: u32, p_param_item: *mut ParamItem) -> PduError {
+        self.with(|b| {
+            not_null(p_param_item)?;
+            let item = &*p_param_item;
+            if item.item_type != PduIt::Param || item.p_com_param_data.is_null() {
+                return Err(PduError::InvalidParameters);
+            }
+            b.set_com_param(req_mod(h_mod)?, req_cll(h_cll)?, item)
+        })
+    }
+
+    /// `PDUStartComPrimitive`
+    ///
+    /// # Safety
+    /// See [Provider]
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn start_com_primitive(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        cop_type: PduCopt,
+        cop_data_size: u32,
+        p_cop_data: *mut u8,
+        p_cop_ctrl_data: *mut CopCtrlData,
+        p_cop_tag: *mut c_void,
+        ph_cop: *mut u32
+    ) -> PduError {
+        self.with(|b| {
+            not_null(ph_cop)?;
+            let data = if cop_data_size == 0 {
+                &[]
+            } else {
+                not_null(p_cop_data)?;
+                std::slice::from_raw_parts(p_cop_data, cop_data_size as usize)
+            };
+            if cop_type == PduCopt::SendRecv && p_cop_ctrl_data.is_null() {
+                return Err(PduError::InvalidParameters);
+            }
+            let h_cop = b.start_com_primitive(
+                req_mod(h_mod)?,
+                req_cll(h_cll)?,
+                cop_type,
+                data,
+                p_cop_ctrl_data.as_ref(),
+                PduTag(p_cop_tag)
+            )?;
+            *ph_cop = h_cop.raw();
+            Ok(())
+        })
+    }
+
+    /// `PDUCancelComPrimitive`
+    pub fn cancel_com_primitive(&self, h_mod: u32, h_cll: u32, h_cop: u32) -> PduError {
+        self.with(|b| {
+            let h_cop = CopHandle::new(h_cop).ok_or(PduError::InvalidHandle)?;
+            b.cancel_com_primitive(req_mod(h_mod)?, req_cll(h_cll)?, h_cop)
+        })
+    }
+
+    /// `PDUGetEventItem`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_event_item(&self, h_mod: u32, h_cll: u32, p_event_item: *mut *mut EventItem) -> PduError {
+        self.with(|b| {
+            not_null(p_event_item)?;
+            let h_mod = ModuleHandle::new(h_mod);
+            let h_cll = CllHandle::new(h_cll);
+            if h_cll.is_some() && h_mod.is_none() {
+                return Err(PduError::InvalidHandle);
+            }
+            *p_event_item = b.get_event_item(h_mod, h_cll)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUDestroyItem`
+    pub fn destroy_item(&self, p_item: *mut PduItem) -> PduError {
+        self.with(|b| {
+            not_null(p_item)?;
+            b.destroy_item(p_item)
+        })
+    }
+
+    /// `PDURegisterEventCallback`
+    pub fn register_event_callback(&self, h_mod: u32, h_cll: u32, callback: Option<EventCallbackFn>) -> PduError {
+        self.with(|b| {
+            let h_mod = ModuleHandle::new(h_mod);
+            let h_cll = CllHandle::new(h_cll);
+            if h_cll.is_some() && h_mod.is_none() {
+                return Err(PduError::InvalidHandle);
+            }
+            b.register_event_callback(h_mod, h_cll, callback)
+        })
+    }
+
+    /// `PDUGetObjectId`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_object_id(&self, object_type: PduObjt, p_short_name: *mut u8, p_object_id: *mut u32) -> PduError {
+        self.with(|b| {
+            not_null(p_short_name)?;
+            not_null(p_object_id)?;
+            let name = CStr::from_ptr(p_short_name.cast()).to_str().map_err(|_| PduError::InvalidParameters)?;
+            *p_object_id = ObjectId::option_to_raw(b.get_object_id(object_type, name)?);
+            Ok(())
+        })
+    }
+
+    /// `PDUGetModuleIds`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_module_ids(&self, p_module_id_list: *mut *mut ModuleItem) -> PduError {
+        self.with(|b| {
+            not_null(p_module_id_list)?;
+            *p_module_id_list = b.get_module_ids()?;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetResourceIds`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_resource_ids(
+        &self,
+        h_mod: u32,
+        p_resource_id_data: *mut RscData,
+        p_resource_id_list: *mut *mut RscIdItem
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_resource_id_data)?;
+            not_null(p_resource_id_list)?;
+            let data = &*p_resource_id_data;
+            if data.num_pin_data != 0 {
+                not_null(data.p_dlc_pin_data)?;
+            }
+            *p_resource_id_list = b.get_resource_ids(ModuleHandle::new(h_mod), data)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetConflictingResources`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_conflicting_resources(
+        &self,
+        resource_id: u32,
+        p_input_module_list: *mut ModuleItem,
+        p_output_conflict_list: *mut *mut RscConflictItem
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_output_conflict_list)?;
+            let resource_id = ResourceId::new(resource_id).ok_or(PduError::InvalidParameters)?;
+            let mut modules = Vec::new();
+            if let Some(list) = p_input_module_list.as_ref() {
+                if list.num_entries != 0 {
+                    not_null(list.p_module_data)?;
+                    for m in std::slice::from_raw_parts(list.p_module_data, list.num_entries as usize) {
+                        modules.push(req_mod(m.h_mod)?);
+                    }
+                }
+            }
+            *p_output_conflict_list = b.get_conflicting_resources(resource_id, &modules)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUGetUniqueRespIdTable`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_unique_resp_id_table(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        p_unique_resp_id_table: *mut *mut UniqueRespIdTableItem
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_unique_resp_id_table)?;
+            *p_unique_resp_id_table = b.get_unique_resp_id_table(req_mod(h_mod)?, req_cll(h_cll)?)?;
+            Ok(())
+        })
+    }
+
+    /// `PDUSetUniqueRespIdTable`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn set_unique_resp_id_table(
+        &self,
+        h_mod: u32,
+        h_cll: u32,
+        p_unique_resp_id_table: *mut UniqueRespIdTableItem
+    ) -> PduError {
+        self.with(|b| {
+            not_null(p_unique_resp_id_table)?;
+            let table = &*p_unique_resp_id_table;
+            if table.num_entries != 0 {
+                not_null(table.p_unique_data)?;
+            }
+            b.set_unique_resp_id_table(req_mod(h_mod)?, req_cll(h_cll)?, table)
+        })
+    }
+
+    /// `PDUModuleConnect`
+    pub fn module_connect(&self, h_mod: u32) -> PduError {
+        self.with(|b| b.module_connect(ModuleHandle::new(h_mod)))
+    }
+
+    /// `PDUModuleDisconnect`
+    pub fn module_disconnect(&self, h_mod: u32) -> PduError {
+        self.with(|b| b.module_disconnect(ModuleHandle::new(h_mod)))
+    }
+
+    /// `PDUGetTimestamp`
+    ///
+    /// # Safety
+    /// See [Provider]
+    pub unsafe fn get_timestamp(&self, h_mod: u32, p_timestamp: *mut u32) -> PduError {
+        self.with(|b| {
+            not_null(p_timestamp)?;
+            *p_timestamp = b.get_timestamp(req_mod(h_mod)?)?;
+            Ok(())
+        })
+    }
+}
+
+/// Exports a [PduBackend](crate::provider::PduBackend) as a D-PDU API library.
+///
+/// This creates all the `PDU*` functions of the API as `extern "C"` symbols, with signatures
+/// matching the function types of this crate. It should be invoked once, in the root of
+/// a `cdylib` crate.
+///
+/// ```ignore
+/// dpdu_rust::export_pdu_api!(MyBackend);
+/// ```
+#[macro_export]
+macro_rules! export_pdu_api {
+    ($backend:ty) => {
+        static __PDU_PROVIDER: $crate::provider::Provider<$backend> = $crate::provider::Provider::new();
+
+        /// Constructs and initializes the PDU API library
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUConstruct(option_str: *mut u8, p_api_tag: *mut ::std::ffi::c_void) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.construct(option_str, p_api_tag) }
+        }
+
+        /// Closes all open communication channels and destructs the PDU API library
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUDestruct() -> $crate::PduError {
+            __PDU_PROVIDER.destruct()
+        }
+
+        /// Performs generic IOCTL calls on a MVCI or ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUIoCtl(
+            h_mod: u32,
+            h_cll: u32,
+            ioctl_command_id: u32,
+            p_input_data: *mut $crate::PduDataItem,
+            p_output_data: *mut *mut $crate::PduDataItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.ioctl(h_mod, h_cll, ioctl_command_id, p_input_data, p_output_data) }
+        }
+
+        /// Gets version information from MVCI module
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetVersion(h_mod: u32, p_version_data: *mut $crate::VersionData) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_version(h_mod, p_version_data) }
+        }
+
+        /// Gets runtime information from either a MVCI module, ComLogicalLink or ComPrimitive
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetStatus(
+            h_mod: u32,
+            h_cll: u32,
+            h_cop: u32,
+            p_status_code: *mut $crate::PduStatus,
+            p_timestamp: *mut u32,
+            p_extra_info: *mut u32
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_status(h_mod, h_cll, h_cop, p_status_code, p_timestamp, p_extra_info) }
+        }
+
+        /// Gets the last runtime error from the MVCI module or ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetLastError(
+            h_mod: u32,
+            h_cll: u32,
+            p_error_code: *mut $crate::PduErrorEvt,
+            ph_cop: *mut u32,
+            p_timestamp: *mut u32,
+            p_extra_error_info: *mut u32
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_last_error(h_mod, h_cll, p_error_code, ph_cop, p_timestamp, p_extra_error_info) }
+        }
+
+        /// Obtains resource status information from the PDU API
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetResourceStatus(p_resource_status: *mut $crate::RscStatusData) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_resource_status(p_resource_status) }
+        }
+
+        /// Creates a ComLogicalLink for a given resource ID
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUCreateComLogicalLink(
+            h_mod: u32,
+            p_rsc_data: *mut $crate::RscData,
+            resource_id: u32,
+            p_cll_tag: *mut ::std::ffi::c_void,
+            ph_cll: *mut u32,
+            p_cll_create_flag: *mut $crate::FlagData
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.create_com_logical_link(h_mod, p_rsc_data, resource_id, p_cll_tag, ph_cll, p_cll_create_flag) }
+        }
+
+        /// Destroys a given ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUDestroyComLogicalLink(h_mod: u32, h_cll: u32) -> $crate::PduError {
+            __PDU_PROVIDER.destroy_com_logical_link(h_mod, h_cll)
+        }
+
+        /// Connects a ComLogicalLink to a vehicle interface
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUConnect(h_mod: u32, h_cll: u32) -> $crate::PduError {
+            __PDU_PROVIDER.connect(h_mod, h_cll)
+        }
+
+        /// Disconnects a ComLogicalLink from a vehicle interface
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUDisconnect(h_mod: u32, h_cll: u32) -> $crate::PduError {
+            __PDU_PROVIDER.disconnect(h_mod, h_cll)
+        }
+
+        /// Locks a physical resource so that a ComLogicalLink has exclusive access to it
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDULockResource(h_mod: u32, h_cll: u32, lock_mask: u32) -> $crate::PduError {
+            __PDU_PROVIDER.lock_resource(h_mod, h_cll, lock_mask)
+        }
+
+        /// Unlocks a physical resource from a ComLogicalLink that has exclusive access to it
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUUnlockResource(h_mod: u32, h_cll: u32, lock_mask: u32) -> $crate::PduError {
+            __PDU_PROVIDER.unlock_resource(h_mod, h_cll, lock_mask)
+        }
+
+        /// Obtains a ComParam out of the working buffer of a ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetComParam(
+            h_mod: u32,
+            h_cll: u32,
+            param_id: u32,
+            p_param_item: *mut *mut $crate::ParamItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_com_param(h_mod, h_cll, param_id, p_param_item) }
+        }
+
+        /// Sets a ComParam on a ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUSetComParam(h_mod: u32, h_cll: u32, p_param_item: *mut $crate::ParamItem) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.set_com_param(h_mod, h_cll, p_param_item) }
+        }
+
+        /// Creates and starts a ComPrimitive
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[allow(clippy::too_many_arguments)]
+        pub extern "C" fn PDUStartComPrimitive(
+            h_mod: u32,
+            h_cll: u32,
+            cop_type: $crate::PduCopt,
+            cop_data_size: u32,
+            p_cop_data: *mut u8,
+            p_cop_ctrl_data: *mut $crate::CopCtrlData,
+            p_cop_tag: *mut ::std::ffi::c_void,
+            ph_cop: *mut u32
+        ) -> $crate::PduError {
+            unsafe {
+                __PDU_PROVIDER.start_com_primitive(
+                    h_mod,
+                    h_cll,
+                    cop_type,
+                    cop_data_size,
+                    p_cop_data,
+                    p_cop_ctrl_data,
+                    p_cop_tag,
+                    ph_cop
+                )
+            }
+        }
+
+        /// Cancels and stops a ComPrimitive
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUCancelComPrimitive(h_mod: u32, h_cll: u32, h_cop: u32) -> $crate::PduError {
+            __PDU_PROVIDER.cancel_com_primitive(h_mod, h_cll, h_cop)
+        }
+
+        /// Retrieves event data for a given event source
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetEventItem(h_mod: u32, h_cll: u32, p_event_item: *mut *mut $crate::EventItem) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_event_item(h_mod, h_cll, p_event_item) }
+        }
+
+        /// Destroys a given item
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUDestroyItem(p_item: *mut $crate::PduItem) -> $crate::PduError {
+            __PDU_PROVIDER.destroy_item(p_item)
+        }
+
+        /// Registers a callback function
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDURegisterEventCallback(
+            h_mod: u32,
+            h_cll: u32,
+            callback_fn: Option<$crate::EventCallbackFn>
+        ) -> $crate::PduError {
+            __PDU_PROVIDER.register_event_callback(h_mod, h_cll, callback_fn)
+        }
+
+        /// Gets the ID of a given object
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetObjectId(
+            pdu_object_type: $crate::PduObjt,
+            p_short_name: *mut u8,
+            p_pdu_object_id: *mut u32
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_object_id(pdu_object_type, p_short_name, p_pdu_object_id) }
+        }
+
+        /// Obtains module information
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetModuleIds(p_module_id_list: *mut *mut $crate::ModuleItem) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_module_ids(p_module_id_list) }
+        }
+
+        /// Get a list of resource IDs
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetResourceIds(
+            h_mod: u32,
+            p_resource_id_data: *mut $crate::RscData,
+            p_resource_id_list: *mut *mut $crate::RscIdItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_resource_ids(h_mod, p_resource_id_data, p_resource_id_list) }
+        }
+
+        /// Gets a list of conflicting resources
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetConflictingResources(
+            resource_id: u32,
+            p_input_module_list: *mut $crate::ModuleItem,
+            p_output_conflict_list: *mut *mut $crate::RscConflictItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_conflicting_resources(resource_id, p_input_module_list, p_output_conflict_list) }
+        }
+
+        /// Gets a list of unique response IDs from a ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetUniqueRespIdTable(
+            h_mod: u32,
+            h_cll: u32,
+            p_unique_resp_id_table: *mut *mut $crate::UniqueRespIdTableItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_unique_resp_id_table(h_mod, h_cll, p_unique_resp_id_table) }
+        }
+
+        /// Sets a unique response ID table for the ComLogicalLink
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUSetUniqueRespIdTable(
+            h_mod: u32,
+            h_cll: u32,
+            p_unique_resp_id_table: *mut $crate::UniqueRespIdTableItem
+        ) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.set_unique_resp_id_table(h_mod, h_cll, p_unique_resp_id_table) }
+        }
+
+        /// Connects to a MVCI module
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUModuleConnect(h_mod: u32) -> $crate::PduError {
+            __PDU_PROVIDER.module_connect(h_mod)
+        }
+
+        /// Closes all communication channels of a MVCI module
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUModuleDisconnect(h_mod: u32) -> $crate::PduError {
+            __PDU_PROVIDER.module_disconnect(h_mod)
+        }
+
+        /// Obtains the current hardware clock of the MVCI module
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn PDUGetTimestamp(h_mod: u32, p_timestamp: *mut u32) -> $crate::PduError {
+            unsafe { __PDU_PROVIDER.get_timestamp(h_mod, p_timestamp) }
+        }
+    };
+}
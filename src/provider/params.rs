@@ -0,0 +1,366 @@
+//! ComParam buffers of ComLogicalLinks
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, MutexGuard, Weak}
+};
+
+use crate::*;
+
+use super::{Event, EventData, EventQueue, ItemAllocator, Param, PduTag};
+
+#[derive(Debug)]
+struct PhysicalLink {
+    events: Arc<EventQueue>,
+    buffers: Weak<Mutex<StoreState>>
+}
+
+#[derive(Debug, Default)]
+struct PhysicalState {
+    values: BTreeMap<ObjectId, ComParamValue>,
+    lock_owner: Option<CllHandle>,
+    links: BTreeMap<CllHandle, PhysicalLink>
+}
+
+#[derive(Debug, Default)]
+/// Active physical (Bus type) ComParams of one resource, which are shared by every
+/// ComLogicalLink on the resource
+pub struct PhysicalParams {
+    state: Mutex<PhysicalState>
+}
+
+impl PhysicalParams {
+    /// Creates an empty set of physical ComParams
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PhysicalState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets the ComLogicalLink which has locked the resource. Only this link may then change
+    /// physical ComParams
+    pub fn set_lock_owner(&self, owner: Option<CllHandle>) {
+        self.lock().lock_owner = owner;
+    }
+
+    /// Returns the ComLogicalLink which has locked the resource
+    pub fn lock_owner(&self) -> Option<CllHandle> {
+        self.lock().lock_owner
+    }
+
+    /// Returns the active value of a physical ComParam, if any link has applied one
+    pub fn value(&self, id: ObjectId) -> Option<ComParamValue> {
+        self.lock().values.get(&id).cloned()
+    }
+
+    /// Returns the ComLogicalLinks which share the resource
+    pub fn links(&self) -> Vec<CllHandle> {
+        self.lock().links.keys().copied().collect()
+    }
+
+    fn check_writable(&self, h_cll: CllHandle) -> Result<(), PduError> {
+        match self.lock().lock_owner {
+            Some(owner) if owner != h_cll => Err(PduError::ComParamLocked),
+            _ => Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// The ComParams in force for one ComPrimitive
+pub struct ComParamSnapshot {
+    values: BTreeMap<ObjectId, ComParamValue>
+}
+
+impl ComParamSnapshot {
+    /// Returns the value of a ComParam
+    pub fn get(&self, id: ObjectId) -> Option<&ComParamValue> {
+        self.values.get(&id)
+    }
+
+    /// Returns the value of a numeric ComParam
+    pub fn get_u32(&self, id: ObjectId) -> Option<u32> {
+        self.get(id).and_then(|v| v.as_u32())
+    }
+}
+
+#[derive(Debug)]
+struct StoreState {
+    working: BTreeMap<ObjectId, ComParamValue>,
+    active: BTreeMap<ObjectId, ComParamValue>,
+    /// Physical ComParams set by the link since its last update
+    dirty: BTreeSet<ObjectId>
+}
+
+impl StoreState {
+    /// Takes physical ComParams another link has applied to the resource, keeping the ones
+    /// this link has set but not yet applied
+    fn refresh(&mut self, changed: &[(ObjectId, ComParamValue)]) {
+        for (id, value) in changed {
+            self.active.insert(*id, value.clone());
+            if !self.dirty.contains(id) {
+                self.working.insert(*id, value.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+/// ComParam buffers of one ComLogicalLink
+///
+/// `PDUSetComParam` writes to the working buffer, which has no effect on communication until
+/// an [PduCopt::UpdateParam] ComPrimitive copies it to the active buffer ([ComParamStore::update]).
+/// [PduCopt::RestoreParam] copies the active buffer back to the working buffer
+/// ([ComParamStore::restore]). A ComPrimitive started with `temp_param_update` runs with the
+/// working buffer without making it active ([ComParamStore::snapshot]).
+///
+/// Physical ComParams are shared by every link on the resource. They cannot be changed while
+/// another link has locked the resource ([PduError::ComParamLocked]), or temporarily
+/// ([PduError::TempParamNotAllowed]). Only the physical ComParams a link has set since its last
+/// update are applied. Every other link on the resource then takes the new values into both of
+/// its buffers, and is sent a [PduInfo::ComParamChange] event.
+pub struct ComParamStore {
+    h_cll: CllHandle,
+    physical: Arc<PhysicalParams>,
+    defs: BTreeMap<ObjectId, &'static ComParamDef>,
+    state: Arc<Mutex<StoreState>>
+}
+
+impl ComParamStore {
+    /// Creates the ComParam buffers of a ComLogicalLink, with both buffers holding the defaults
+    ///
+    /// ## Parameters
+    /// * h_cll - The ComLogicalLink
+    /// * events - Event queue of the ComLogicalLink
+    /// * physical - Physical ComParams of the resource the link is on
+    /// * params - Every ComParam the link supports (ID, definition, default value)
+    pub fn new(
+        h_cll: CllHandle,
+        events: Arc<EventQueue>,
+        physical: Arc<PhysicalParams>,
+        params: impl IntoIterator<Item = (ObjectId, &'static ComParamDef, ComParamValue)>
+    ) -> Self {
+        let mut defs = BTreeMap::new();
+        let mut working = BTreeMap::new();
+        for (id, def, value) in params {
+            defs.insert(id, def);
+            working.insert(id, value);
+        }
+        let state = Arc::new(Mutex::new(StoreState { active: working.clone(), working, dirty: BTreeSet::new() }));
+        physical.lock().links.insert(h_cll, PhysicalLink { events, buffers: Arc::downgrade(&state) });
+        Self { h_cll, physical, defs, state }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StoreState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn def(&self, id: ObjectId) -> Result<&'static ComParamDef, PduError> {
+        self.defs.get(&id).copied().ok_or(PduError::ComParamNotSupported)
+    }
+
+    fn is_physical(&self, id: &ObjectId) -> bool {
+        self.defs.get(id).is_some_and(|d| d.is_physical())
+    }
+
+    /// Returns the physical ComParams the link has set which differ from the resource
+    fn changed(&self, state: &StoreState, phys: &PhysicalState) -> Result<Vec<(ObjectId, ComParamValue)>, PduError> {
+        let mut changed = Vec::new();
+        for id in state.dirty.iter().filter(|id| self.is_physical(id)) {
+            let Some(value) = state.working.get(id) else {
+                continue;
+            };
+            if phys.values.get(id) == Some(value) {
+                continue;
+            }
+            if phys.lock_owner.is_some_and(|o| o != self.h_cll) {
+                return Err(PduError::ComParamLocked);
+            }
+            changed.push((*id, value.clone()));
+        }
+        Ok(changed)
+    }
+
+    /// Returns the definition of a ComParam
+    pub fn definition(&self, id: ObjectId) -> Option<&'static ComParamDef> {
+        self.defs.get(&id).copied()
+    }
+
+    /// Returns the physical ComParams of the resource
+    pub fn physical(&self) -> &Arc<PhysicalParams> {
+        &self.physical
+    }
+
+    /// Returns a ComParam from the working buffer (`PDUGetComParam`)
+    pub fn get(&self, id: ObjectId) -> Result<ComParamValue, PduError> {
+        self.def(id)?;
+        self.lock().working.get(&id).cloned().ok_or(PduError::ComParamNotSupported)
+    }
+
+    /// Allocates a ComParam from the working buffer as a [ParamItem] (`PDUGetComParam`)
+    pub fn get_item(&self, id: ObjectId, items: &ItemAllocator) -> Result<*mut ParamItem, PduError> {
+        let def = self.def(id)?;
+        let value = self.get(id)?;
+        Ok(items.alloc_param(&Param { id, class: def.class, value }))
+    }
+
+    /// Writes a ComParam to the working buffer (`PDUSetComParam`), checking its data type
+    pub fn set(&self, id: ObjectId, value: ComParamValue) -> Result<(), PduError> {
+        let def = self.def(id)?;
+        def.validate(&value)?;
+        let mut state = self.lock();
+        if def.is_physical() {
+            self.physical.check_writable(self.h_cll)?;
+            state.dirty.insert(id);
+        }
+        state.working.insert(id, value);
+        Ok(())
+    }
+
+    /// Writes a [ParamItem] passed to `PDUSetComParam` to the working buffer
+    ///
+    /// # Safety
+    /// The ComParam data pointer of the item must be valid for its data type
+    pub unsafe fn set_item(&self, item: &ParamItem) -> Result<(), PduError> {
+        let id = ObjectId::new(item.com_param_id).ok_or(PduError::ComParamNotSupported)?;
+        let def = self.def(id)?;
+        if item.com_param_data_type != def.data_type || item.com_param_class != def.class {
+            return Err(PduError::InvalidParameters);
+        }
+        self.set(id, ComParamValue::from_param_item(item)?)
+    }
+
+    /// Returns the ComParams which [ComParamStore::update] would make active, so they can be
+    /// applied to the channel first. Returns [PduError::ComParamLocked] if the update would change
+    /// a physical ComParam of a resource another link has locked
+    pub fn pending(&self) -> Result<ComParamSnapshot, PduError> {
+        let state = self.lock();
+        let phys = self.physical.lock();
+        self.changed(&state, &phys)?;
+        let mut values = state.working.clone();
+        for (id, value) in values.iter_mut() {
+            if self.is_physical(id) && !state.dirty.contains(id) {
+                if let Some(v) = phys.values.get(id) {
+                    *value = v.clone();
+                }
+            }
+        }
+        Ok(ComParamSnapshot { values })
+    }
+
+    /// Copies the working buffer to the active buffer ([PduCopt::UpdateParam])
+    ///
+    /// Physical ComParams set since the last update are applied to the resource, and every other
+    /// link on the resource is refreshed and notified with [PduInfo::ComParamChange]
+    pub fn update(&self, timestamp: u32) -> Result<(), PduError> {
+        let mut state = self.lock();
+        let (changed, others) = {
+            let mut phys = self.physical.lock();
+            let changed = self.changed(&state, &phys)?;
+            for (id, value) in changed.iter() {
+                phys.values.insert(*id, value.clone());
+            }
+            // Physical ComParams this link has not set follow the resource
+            let dirty = std::mem::take(&mut state.dirty);
+            let state = &mut *state;
+            for (id, value) in state.working.iter_mut() {
+                if self.is_physical(id) && !dirty.contains(id) {
+                    if let Some(v) = phys.values.get(id) {
+                        *value = v.clone();
+                    }
+                }
+            }
+            let others: Vec<(Arc<EventQueue>, Weak<Mutex<StoreState>>)> = if changed.is_empty() {
+                Vec::new()
+            } else {
+                phys.links.iter().filter(|(h, _)| **h != self.h_cll).map(|(_, l)| (l.events.clone(), l.buffers.clone())).collect()
+            };
+            (changed, others)
+        };
+        state.active = state.working.clone();
+        drop(state);
+        // Refresh and notify the other links outside of the locks, as event callbacks may call
+        // back into the API
+        for (events, buffers) in others {
+            if let Some(buffers) = buffers.upgrade() {
+                buffers.lock().unwrap_or_else(|e| e.into_inner()).refresh(&changed);
+            }
+            for (id, _) in changed.iter() {
+                events.push(Event {
+                    h_cop: None,
+                    cop_tag: PduTag::NULL,
+                    timestamp,
+                    data: EventData::Info(InfoData { info_code: PduInfo::ComParamChange, extra_info_data: id.raw() })
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the active buffer to the working buffer ([PduCopt::RestoreParam])
+    ///
+    /// Physical ComParams are restored to the values active on the resource
+    pub fn restore(&self) {
+        let mut state = self.lock();
+        let phys = self.physical.lock();
+        let mut working = state.active.clone();
+        for (id, value) in working.iter_mut() {
+            if let Some(v) = phys.values.get(id) {
+                *value = v.clone();
+            }
+        }
+        state.working = working;
+        state.dirty.clear();
+    }
+
+    /// Returns the value of a ComParam in force for communication
+    pub fn active(&self, id: ObjectId) -> Option<ComParamValue> {
+        if self.is_physical(&id) {
+            if let Some(v) = self.physical.value(id) {
+                return Some(v);
+            }
+        }
+        self.lock().active.get(&id).cloned()
+    }
+
+    /// Returns the ComParams in force for a ComPrimitive.
+    ///
+    /// With `temp_param_update`, the working buffer is used for this ComPrimitive only. This
+    /// is rejected with [PduError::TempParamNotAllowed] if a physical ComParam would change
+    pub fn snapshot(&self, temp_param_update: bool) -> Result<ComParamSnapshot, PduError> {
+        let state = self.lock();
+        let phys = self.physical.lock();
+        let source = if temp_param_update { &state.working } else { &state.active };
+        let mut values = BTreeMap::new();
+        for (id, value) in source.iter() {
+            let physical = self.is_physical(id);
+            let set = temp_param_update && state.dirty.contains(id);
+            let value = match (physical, phys.values.get(id)) {
+                (true, Some(active)) => {
+                    if set && active != value {
+                        return Err(PduError::TempParamNotAllowed);
+                    }
+                    active.clone()
+                },
+                (true, None) if set && state.active.get(id) != Some(value) => {
+                    return Err(PduError::TempParamNotAllowed);
+                },
+                _ => value.clone()
+            };
+            values.insert(*id, value);
+        }
+        Ok(ComParamSnapshot { values })
+    }
+}
+
+impl Drop for ComParamStore {
+    fn drop(&mut self) {
+        let mut phys = self.physical.lock();
+        phys.links.remove(&self.h_cll);
+        if phys.lock_owner == Some(self.h_cll) {
+            phys.lock_owner = None;
+        }
+    }
+}
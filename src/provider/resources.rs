@@ -0,0 +1,409 @@
+//! Resource model of providers
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard}
+};
+
+use crate::*;
+
+use super::{Event, EventData, EventQueue, PduTag, PhysicalParams};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A physical resource of a module (A bus on a set of pins), and the protocols it can run
+pub struct ResourceInfo {
+    /// Resource ID
+    pub id: ResourceId,
+    /// Bus type ID
+    pub bus_type_id: ObjectId,
+    /// Protocol IDs which can run on the resource
+    pub protocol_ids: Vec<ObjectId>,
+    /// Pins used on the data link connector
+    pub pins: Vec<PinData>
+}
+
+impl ResourceInfo {
+    /// Returns true if the resource satisfies a request from the application. If the request
+    /// has no pins, any pins are accepted
+    pub fn matches(&self, bus_type_id: u32, protocol_id: u32, pins: &[PinData]) -> bool {
+        self.bus_type_id.raw() == bus_type_id
+            && self.protocol_ids.iter().any(|p| p.raw() == protocol_id)
+            && (pins.is_empty() || (pins.len() == self.pins.len() && pins.iter().all(|p| self.pins.contains(p))))
+    }
+
+    /// Returns true if the resources cannot be used at the same time, as they share a pin
+    pub fn conflicts_with(&self, other: &ResourceInfo) -> bool {
+        self.id != other.id
+            && self.pins.iter().any(|a| other.pins.iter().any(|b| a.dlc_pin_number == b.dlc_pin_number))
+    }
+}
+
+#[derive(Debug)]
+struct ResourceEntry {
+    info: ResourceInfo,
+    physical: Arc<PhysicalParams>,
+    links: BTreeMap<CllHandle, Arc<EventQueue>>,
+    param_lock: Option<CllHandle>,
+    tx_lock: Option<CllHandle>
+}
+
+impl ResourceEntry {
+    fn is_locked(&self) -> bool {
+        self.param_lock.is_some() || self.tx_lock.is_some()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ManagerState {
+    modules: BTreeMap<ModuleHandle, Vec<ResourceEntry>>,
+    link_index: BTreeMap<CllHandle, (ModuleHandle, ResourceId)>
+}
+
+impl ManagerState {
+    fn resource(&mut self, h_mod: ModuleHandle, id: ResourceId) -> Option<&mut ResourceEntry> {
+        self.modules.get_mut(&h_mod)?.iter_mut().find(|r| r.info.id == id)
+    }
+
+    fn status(&self, h_mod: ModuleHandle, id: ResourceId) -> ResourceStatus {
+        let Some(resources) = self.modules.get(&h_mod) else {
+            return ResourceStatus::NotAvailable;
+        };
+        let Some(entry) = resources.iter().find(|r| r.info.id == id) else {
+            return ResourceStatus::NotAvailable;
+        };
+        let blocked = resources.iter().any(|r| !r.links.is_empty() && r.info.conflicts_with(&entry.info));
+        let links = entry.links.len().min(u16::MAX as usize) as u16;
+        if blocked {
+            ResourceStatus::NotAvailable
+        } else if entry.is_locked() {
+            ResourceStatus::Locked { links }
+        } else if links == 0 {
+            ResourceStatus::Available
+        } else {
+            ResourceStatus::InUse { links }
+        }
+    }
+
+    fn link_resource(&mut self, h_mod: ModuleHandle, h_cll: CllHandle) -> Result<&mut ResourceEntry, PduError> {
+        match self.link_index.get(&h_cll) {
+            Some((m, id)) if *m == h_mod => {
+                let id = *id;
+                self.resource(h_mod, id).ok_or(PduError::InvalidHandle)
+            },
+            _ => Err(PduError::InvalidHandle)
+        }
+    }
+}
+
+fn lock_change_events(entry: &ResourceEntry) -> Vec<Arc<EventQueue>> {
+    entry.links.values().cloned().collect()
+}
+
+fn notify_lock_change(queues: Vec<Arc<EventQueue>>, resource_id: ResourceId, timestamp: u32) {
+    for events in queues {
+        events.push(Event {
+            h_cop: None,
+            cop_tag: PduTag::NULL,
+            timestamp,
+            data: EventData::Info(InfoData { info_code: PduInfo::ResourceLockChange, extra_info_data: resource_id.raw() })
+        });
+    }
+}
+
+#[derive(Debug, Default)]
+/// Model of the physical resources of every module, and the ComLogicalLinks using them
+///
+/// Providers register the resources of each module, then attach each ComLogicalLink to the
+/// resource it is created on. The manager answers the resource queries of the API, and
+/// arbitrates `PDULockResource` and `PDUUnlockResource`. Resources conflict if they share a pin
+/// on the same module, and a resource is not available while a conflicting resource is in use.
+///
+/// Every lock change is sent to the links on the resource as a [PduInfo::ResourceLockChange]
+/// event, with the resource ID as the extra information.
+pub struct ResourceManager {
+    state: Mutex<ManagerState>
+}
+
+impl ResourceManager {
+    /// Creates an empty resource manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ManagerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers the resources of a module, replacing any previous resources
+    pub fn add_module(&self, h_mod: ModuleHandle, resources: Vec<ResourceInfo>) {
+        let entries = resources
+            .into_iter()
+            .map(|info| ResourceEntry {
+                info,
+                physical: Arc::new(PhysicalParams::new()),
+                links: BTreeMap::new(),
+                param_lock: None,
+                tx_lock: None
+            })
+            .collect();
+        self.lock().modules.insert(h_mod, entries);
+    }
+
+    /// Removes a module and all of its links
+    pub fn remove_module(&self, h_mod: ModuleHandle) {
+        let mut state = self.lock();
+        state.modules.remove(&h_mod);
+        state.link_index.retain(|_, (m, _)| *m != h_mod);
+    }
+
+    /// Returns the resources of a module
+    pub fn resources(&self, h_mod: ModuleHandle) -> Result<Vec<ResourceInfo>, PduError> {
+        self.lock()
+            .modules
+            .get(&h_mod)
+            .map(|r| r.iter().map(|e| e.info.clone()).collect())
+            .ok_or(PduError::InvalidHandle)
+    }
+
+    /// Returns the resources of each module which satisfy a request (`PDUGetResourceIds`).
+    /// If no module is given, every module is searched
+    ///
+    /// # Safety
+    /// The pin data pointer of the request must be valid
+    pub unsafe fn resource_ids(
+        &self,
+        h_mod: Option<ModuleHandle>,
+        rsc_data: &RscData
+    ) -> Result<Vec<(ModuleHandle, Vec<ResourceId>)>, PduError> {
+        let pins = pins_of(rsc_data)?;
+        let state = self.lock();
+        if let Some(h) = h_mod {
+            if !state.modules.contains_key(&h) {
+                return Err(PduError::InvalidHandle);
+            }
+        }
+        Ok(state
+            .modules
+            .iter()
+            .filter(|(h, _)| h_mod.is_none_or(|m| m == **h))
+            .map(|(h, resources)| {
+                let ids = resources
+                    .iter()
+                    .filter(|r| r.info.matches(rsc_data.bus_type_id, rsc_data.protocol_id, pins))
+                    .map(|r| r.info.id)
+                    .collect();
+                (*h, ids)
+            })
+            .collect())
+    }
+
+    /// Picks the resource for a new ComLogicalLink created from [RscData], preferring resources
+    /// which are not in use. Returns [PduError::ResourceBusy] if every matching resource is
+    /// unavailable or locked
+    ///
+    /// # Safety
+    /// The pin data pointer of the request must be valid
+    pub unsafe fn select_resource(&self, h_mod: ModuleHandle, rsc_data: &RscData) -> Result<ResourceId, PduError> {
+        let pins = pins_of(rsc_data)?;
+        let state = self.lock();
+        let resources = state.modules.get(&h_mod).ok_or(PduError::InvalidHandle)?;
+        let matching: Vec<ResourceId> = resources
+            .iter()
+            .filter(|r| r.info.matches(rsc_data.bus_type_id, rsc_data.protocol_id, pins))
+            .map(|r| r.info.id)
+            .collect();
+        if matching.is_empty() {
+            return Err(PduError::InvalidParameters);
+        }
+        matching
+            .iter()
+            .find(|id| state.status(h_mod, **id).is_free())
+            .or_else(|| matching.iter().find(|id| state.status(h_mod, **id).is_usable()))
+            .copied()
+            .ok_or(PduError::ResourceBusy)
+    }
+
+    /// Returns the status of a resource on a module. Unknown resources are not available
+    pub fn status(&self, h_mod: ModuleHandle, id: ResourceId) -> ResourceStatus {
+        self.lock().status(h_mod, id)
+    }
+
+    /// Fills in the status of each entry (`PDUGetResourceStatus`)
+    pub fn fill_status(&self, items: &mut [RscStatusItem]) {
+        let state = self.lock();
+        for item in items.iter_mut() {
+            let status = match (ModuleHandle::new(item.h_mod), ResourceId::new(item.resource_id)) {
+                (Some(h_mod), Some(id)) => state.status(h_mod, id),
+                _ => ResourceStatus::NotAvailable
+            };
+            item.resource_status = status.to_raw();
+        }
+    }
+
+    /// Returns the resources which conflict with a resource on the given modules
+    /// (`PDUGetConflictingResources`). If no modules are given, every module is searched
+    pub fn conflicts(&self, id: ResourceId, modules: &[ModuleHandle]) -> Result<Vec<(ModuleHandle, ResourceId)>, PduError> {
+        let state = self.lock();
+        if modules.iter().any(|h| !state.modules.contains_key(h)) {
+            return Err(PduError::InvalidHandle);
+        }
+        let mut conflicts = Vec::new();
+        for (h_mod, resources) in state.modules.iter().filter(|(h, _)| modules.is_empty() || modules.contains(h)) {
+            if let Some(entry) = resources.iter().find(|r| r.info.id == id) {
+                conflicts.extend(resources.iter().filter(|r| r.info.conflicts_with(&entry.info)).map(|r| (*h_mod, r.info.id)));
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Attaches a new ComLogicalLink to a resource, returning the physical ComParams of the
+    /// resource
+    ///
+    /// Returns [PduError::ResourceBusy] if a conflicting resource is in use, and
+    /// [PduError::RscLocked] if the resource is locked by another link
+    pub fn attach(
+        &self,
+        h_mod: ModuleHandle,
+        id: ResourceId,
+        h_cll: CllHandle,
+        events: Arc<EventQueue>
+    ) -> Result<Arc<PhysicalParams>, PduError> {
+        let mut state = self.lock();
+        match state.status(h_mod, id) {
+            ResourceStatus::NotAvailable => {
+                let known = state.resource(h_mod, id).is_some();
+                return Err(if known { PduError::ResourceBusy } else { PduError::InvalidParameters });
+            },
+            ResourceStatus::Locked { .. } => return Err(PduError::RscLocked),
+            _ => {}
+        }
+        let entry = state.resource(h_mod, id).ok_or(PduError::InvalidParameters)?;
+        entry.links.insert(h_cll, events);
+        let physical = entry.physical.clone();
+        state.link_index.insert(h_cll, (h_mod, id));
+        Ok(physical)
+    }
+
+    /// Detaches a destroyed ComLogicalLink from its resource, releasing its locks
+    pub fn detach(&self, h_cll: CllHandle, timestamp: u32) {
+        let notify = {
+            let mut state = self.lock();
+            let Some((h_mod, id)) = state.link_index.remove(&h_cll) else {
+                return;
+            };
+            let Some(entry) = state.resource(h_mod, id) else {
+                return;
+            };
+            entry.links.remove(&h_cll);
+            let mut released = false;
+            if entry.param_lock == Some(h_cll) {
+                entry.param_lock = None;
+                entry.physical.set_lock_owner(None);
+                released = true;
+            }
+            if entry.tx_lock == Some(h_cll) {
+                entry.tx_lock = None;
+                released = true;
+            }
+            released.then(|| (lock_change_events(entry), id))
+        };
+        if let Some((queues, id)) = notify {
+            notify_lock_change(queues, id, timestamp);
+        }
+    }
+
+    /// Returns the resource a ComLogicalLink is attached to
+    pub fn link_resource(&self, h_cll: CllHandle) -> Option<(ModuleHandle, ResourceId)> {
+        self.lock().link_index.get(&h_cll).copied()
+    }
+
+    /// Locks parts of the link's resource (`PDULockResource`)
+    ///
+    /// The lock mask is a combination of [LOCK_MASK_PHYSICAL_COMPARAMS] and [LOCK_MASK_TX_QUEUE].
+    /// Returns [PduError::RscLocked] if the link already holds a requested lock, and
+    /// [PduError::RscLockedByAnotherCll] if another link does
+    pub fn lock_resource(&self, h_mod: ModuleHandle, h_cll: CllHandle, lock_mask: u32, timestamp: u32) -> Result<(), PduError> {
+        check_mask(lock_mask)?;
+        let (queues, id) = {
+            let mut state = self.lock();
+            let entry = state.link_resource(h_mod, h_cll)?;
+            let wanted = [
+                (LOCK_MASK_PHYSICAL_COMPARAMS, entry.param_lock),
+                (LOCK_MASK_TX_QUEUE, entry.tx_lock)
+            ];
+            for (bit, owner) in wanted {
+                match owner {
+                    Some(o) if lock_mask & bit != 0 && o == h_cll => return Err(PduError::RscLocked),
+                    Some(_) if lock_mask & bit != 0 => return Err(PduError::RscLockedByAnotherCll),
+                    _ => {}
+                }
+            }
+            if lock_mask & LOCK_MASK_PHYSICAL_COMPARAMS != 0 {
+                entry.param_lock = Some(h_cll);
+                entry.physical.set_lock_owner(Some(h_cll));
+            }
+            if lock_mask & LOCK_MASK_TX_QUEUE != 0 {
+                entry.tx_lock = Some(h_cll);
+            }
+            (lock_change_events(entry), entry.info.id)
+        };
+        notify_lock_change(queues, id, timestamp);
+        Ok(())
+    }
+
+    /// Unlocks parts of the link's resource (`PDUUnlockResource`). Returns [PduError::RscNotLocked]
+    /// if the link does not hold a requested lock
+    pub fn unlock_resource(&self, h_mod: ModuleHandle, h_cll: CllHandle, lock_mask: u32, timestamp: u32) -> Result<(), PduError> {
+        check_mask(lock_mask)?;
+        let (queues, id) = {
+            let mut state = self.lock();
+            let entry = state.link_resource(h_mod, h_cll)?;
+            if (lock_mask & LOCK_MASK_PHYSICAL_COMPARAMS != 0 && entry.param_lock != Some(h_cll))
+                || (lock_mask & LOCK_MASK_TX_QUEUE != 0 && entry.tx_lock != Some(h_cll))
+            {
+                return Err(PduError::RscNotLocked);
+            }
+            if lock_mask & LOCK_MASK_PHYSICAL_COMPARAMS != 0 {
+                entry.param_lock = None;
+                entry.physical.set_lock_owner(None);
+            }
+            if lock_mask & LOCK_MASK_TX_QUEUE != 0 {
+                entry.tx_lock = None;
+            }
+            (lock_change_events(entry), entry.info.id)
+        };
+        notify_lock_change(queues, id, timestamp);
+        Ok(())
+    }
+
+    /// Returns [PduError::RscLockedByAnotherCll] if another link has locked the transmit queue
+    /// of the link's resource
+    pub fn check_tx(&self, h_cll: CllHandle) -> Result<(), PduError> {
+        let mut state = self.lock();
+        let Some((h_mod, id)) = state.link_index.get(&h_cll).copied() else {
+            return Err(PduError::InvalidHandle);
+        };
+        match state.resource(h_mod, id).and_then(|r| r.tx_lock) {
+            Some(owner) if owner != h_cll => Err(PduError::RscLockedByAnotherCll),
+            _ => Ok(())
+        }
+    }
+}
+
+fn check_mask(lock_mask: u32) -> Result<(), PduError> {
+    let all = LOCK_MASK_PHYSICAL_COMPARAMS | LOCK_MASK_TX_QUEUE;
+    if lock_mask == 0 || lock_mask & !all != 0 {
+        return Err(PduError::InvalidParameters);
+    }
+    Ok(())
+}
+
+unsafe fn pins_of(rsc_data: &RscData) -> Result<&[PinData], PduError> {
+    if rsc_data.num_pin_data == 0 {
+        Ok(&[])
+    } else if rsc_data.p_dlc_pin_data.is_null() {
+        Err(PduError::InvalidParameters)
+    } else {
+        Ok(std::slice::from_raw_parts(rsc_data.p_dlc_pin_data, rsc_data.num_pin_data as usize))
+    }
+}
@@ -0,0 +1,263 @@
+//! Module and ComLogicalLink state machines
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::*;
+
+use super::{Event, EventData, EventQueue, PduTag};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// State of a module
+pub enum ModuleState {
+    /// Available for connection ([PduStatus::ModstAvail])
+    Avail,
+    /// Connected and ready for communication ([PduStatus::ModstReady])
+    Ready,
+    /// Connected but not ready for communication ([PduStatus::ModstNotReady])
+    NotReady,
+    /// Unavailable for connection ([PduStatus::ModstNotAvail])
+    NotAvail
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Action which changes the state of a module
+pub enum ModuleAction {
+    /// `PDUModuleConnect`
+    Connect,
+    /// `PDUModuleDisconnect`
+    Disconnect,
+    /// The connected module became ready for communication
+    Ready,
+    /// The connected module is no longer ready for communication
+    NotReady,
+    /// The module was detected
+    Detected,
+    /// The module was lost (Unplugged or unreachable)
+    Lost
+}
+
+impl ModuleState {
+    /// Returns the status code of the state
+    pub fn status(&self) -> PduStatus {
+        match self {
+            Self::Avail => PduStatus::ModstAvail,
+            Self::Ready => PduStatus::ModstReady,
+            Self::NotReady => PduStatus::ModstNotReady,
+            Self::NotAvail => PduStatus::ModstNotAvail
+        }
+    }
+
+    /// Converts a module status code
+    pub fn from_status(status: PduStatus) -> Option<Self> {
+        match status {
+            PduStatus::ModstAvail => Some(Self::Avail),
+            PduStatus::ModstReady => Some(Self::Ready),
+            PduStatus::ModstNotReady => Some(Self::NotReady),
+            PduStatus::ModstNotAvail => Some(Self::NotAvail),
+            _ => None
+        }
+    }
+
+    /// Returns true if the module is connected
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Self::Ready | Self::NotReady)
+    }
+
+    /// Returns the state after an action, or the error the API returns if the action is illegal
+    ///
+    /// | State    | Connect       | Disconnect         | Ready / NotReady   | Detected | Lost     |
+    /// |----------|---------------|--------------------|--------------------|----------|----------|
+    /// | Avail    | Ready         | ModuleNotConnected | ModuleNotConnected | Avail    | NotAvail |
+    /// | Ready    | FctFailed     | Avail              | Ready / NotReady   | Ready    | NotAvail |
+    /// | NotReady | FctFailed     | Avail              | Ready / NotReady   | NotReady | NotAvail |
+    /// | NotAvail | FctFailed     | ModuleNotConnected | ModuleNotConnected | Avail    | NotAvail |
+    pub fn next(&self, action: ModuleAction) -> Result<Self, PduError> {
+        match (self, action) {
+            (Self::Avail, ModuleAction::Connect) => Ok(Self::Ready),
+            (_, ModuleAction::Connect) => Err(PduError::FctFailed),
+            (s, ModuleAction::Disconnect) if s.is_connected() => Ok(Self::Avail),
+            (s, ModuleAction::Ready) if s.is_connected() => Ok(Self::Ready),
+            (s, ModuleAction::NotReady) if s.is_connected() => Ok(Self::NotReady),
+            (_, ModuleAction::Disconnect | ModuleAction::Ready | ModuleAction::NotReady) => {
+                Err(PduError::ModuleNotConnected)
+            },
+            (Self::NotAvail, ModuleAction::Detected) => Ok(Self::Avail),
+            (s, ModuleAction::Detected) => Ok(*s),
+            (_, ModuleAction::Lost) => Ok(Self::NotAvail)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// State of a ComLogicalLink
+pub enum LinkState {
+    /// Not connected ([PduStatus::CllstOffline])
+    Offline,
+    /// Connected, communication not started ([PduStatus::CllstOnline])
+    Online,
+    /// Connected with communication started ([PduStatus::CllstCommStarted])
+    CommStarted
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Action which changes the state of a ComLogicalLink
+pub enum LinkAction {
+    /// `PDUConnect`
+    Connect,
+    /// `PDUDisconnect`
+    Disconnect,
+    /// A [PduCopt::StartComm] ComPrimitive finished
+    StartComm,
+    /// A [PduCopt::StopComm] ComPrimitive finished
+    StopComm,
+    /// The module of the link was disconnected or lost
+    ModuleLost
+}
+
+impl LinkState {
+    /// Returns the status code of the state
+    pub fn status(&self) -> PduStatus {
+        match self {
+            Self::Offline => PduStatus::CllstOffline,
+            Self::Online => PduStatus::CllstOnline,
+            Self::CommStarted => PduStatus::CllstCommStarted
+        }
+    }
+
+    /// Converts a ComLogicalLink status code
+    pub fn from_status(status: PduStatus) -> Option<Self> {
+        match status {
+            PduStatus::CllstOffline => Some(Self::Offline),
+            PduStatus::CllstOnline => Some(Self::Online),
+            PduStatus::CllstCommStarted => Some(Self::CommStarted),
+            _ => None
+        }
+    }
+
+    /// Returns the state after an action, or the error the API returns if the action is illegal
+    ///
+    /// | State       | Connect      | Disconnect      | StartComm       | StopComm        | ModuleLost |
+    /// |-------------|--------------|-----------------|-----------------|-----------------|------------|
+    /// | Offline     | Online       | CllNotConnected | CllNotConnected | CllNotConnected | Offline    |
+    /// | Online      | CllConnected | Offline         | CommStarted     | CllNotStarted   | Offline    |
+    /// | CommStarted | CllConnected | Offline         | CommStarted     | Online          | Offline    |
+    pub fn next(&self, action: LinkAction) -> Result<Self, PduError> {
+        match (self, action) {
+            (_, LinkAction::ModuleLost) => Ok(Self::Offline),
+            (Self::Offline, LinkAction::Connect) => Ok(Self::Online),
+            (Self::Offline, _) => Err(PduError::CllNotConnected),
+            (_, LinkAction::Connect) => Err(PduError::CllConnected),
+            (_, LinkAction::Disconnect) => Ok(Self::Offline),
+            (_, LinkAction::StartComm) => Ok(Self::CommStarted),
+            (Self::Online, LinkAction::StopComm) => Err(PduError::CllNotStarted),
+            (Self::CommStarted, LinkAction::StopComm) => Ok(Self::Online)
+        }
+    }
+
+    /// Checks that a ComPrimitive can be started in this state
+    ///
+    /// Every ComPrimitive requires the link to be connected ([PduError::CllNotConnected]).
+    /// [PduCopt::SendRecv] and [PduCopt::StopComm] also require communication to be started
+    /// ([PduError::CllNotStarted]), unless `start_comm_required` is false for the protocol
+    pub fn check_cop(&self, cop_type: PduCopt, start_comm_required: bool) -> Result<(), PduError> {
+        match (self, cop_type) {
+            (Self::Offline, _) => Err(PduError::CllNotConnected),
+            (Self::Online, PduCopt::StopComm) => Err(PduError::CllNotStarted),
+            (Self::Online, PduCopt::SendRecv) if start_comm_required => Err(PduError::CllNotStarted),
+            _ => Ok(())
+        }
+    }
+}
+
+fn status_event(status: PduStatus, timestamp: u32) -> Event {
+    Event { h_cop: None, cop_tag: PduTag::NULL, timestamp, data: EventData::Status(status) }
+}
+
+#[derive(Debug)]
+/// State machine of a module, which sends a [PduIt::Status] event to the module's event queue
+/// on every state change
+pub struct ModuleStateMachine {
+    state: Mutex<ModuleState>,
+    events: Arc<EventQueue>
+}
+
+impl ModuleStateMachine {
+    /// Creates a new state machine
+    pub fn new(initial: ModuleState, events: Arc<EventQueue>) -> Self {
+        Self { state: Mutex::new(initial), events }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ModuleState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> ModuleState {
+        *self.lock()
+    }
+
+    /// Performs an action, returning the new state
+    pub fn apply(&self, action: ModuleAction, timestamp: u32) -> Result<ModuleState, PduError> {
+        let (old, new) = {
+            let mut state = self.lock();
+            let old = *state;
+            *state = old.next(action)?;
+            (old, *state)
+        };
+        if old != new {
+            self.events.push(status_event(new.status(), timestamp));
+        }
+        Ok(new)
+    }
+
+    /// Returns [PduError::ModuleNotConnected] unless the module is connected
+    pub fn require_connected(&self) -> Result<(), PduError> {
+        match self.state().is_connected() {
+            true => Ok(()),
+            false => Err(PduError::ModuleNotConnected)
+        }
+    }
+}
+
+#[derive(Debug)]
+/// State machine of a ComLogicalLink, which sends a [PduIt::Status] event to the link's event
+/// queue on every state change
+pub struct LinkStateMachine {
+    state: Mutex<LinkState>,
+    events: Arc<EventQueue>
+}
+
+impl LinkStateMachine {
+    /// Creates a new offline state machine
+    pub fn new(events: Arc<EventQueue>) -> Self {
+        Self { state: Mutex::new(LinkState::Offline), events }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LinkState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> LinkState {
+        *self.lock()
+    }
+
+    /// Performs an action, returning the new state
+    pub fn apply(&self, action: LinkAction, timestamp: u32) -> Result<LinkState, PduError> {
+        let (old, new) = {
+            let mut state = self.lock();
+            let old = *state;
+            *state = old.next(action)?;
+            (old, *state)
+        };
+        if old != new {
+            self.events.push(status_event(new.status(), timestamp));
+        }
+        Ok(new)
+    }
+
+    /// Checks that a ComPrimitive can be started in the current state
+    pub fn check_cop(&self, cop_type: PduCopt, start_comm_required: bool) -> Result<(), PduError> {
+        self.state().check_cop(cop_type, start_comm_required)
+    }
+}
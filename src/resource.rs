@@ -0,0 +1,197 @@
+use crate::{ModuleHandle, PduError, PduGetResourceStatusFn, PduIt, ResourceId, RscStatusData, RscStatusItem};
+
+/// Bit set in [RscStatusItem::resource_status] when the resource is not available
+const RSC_STATUS_NOT_AVAIL: u32 = 0x8000_0000;
+
+/// Bit set in [RscStatusItem::resource_status] when the resource is locked by a ComLogicalLink
+const RSC_STATUS_LOCKED: u32 = 0x4000_0000;
+
+/// Mask of [RscStatusItem::resource_status] containing the number of ComLogicalLinks
+/// which are currently using the resource
+const RSC_STATUS_LINK_COUNT_MASK: u32 = 0x0000_FFFF;
+
+/// Lock mask bit of `PDULockResource` which locks the physical ComParams of the resource
+pub const LOCK_MASK_PHYSICAL_COMPARAMS: u32 = 0x0000_0001;
+
+/// Lock mask bit of `PDULockResource` which locks the transmit queue of the resource
+pub const LOCK_MASK_TX_QUEUE: u32 = 0x0000_0002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Decoded resource status
+///
+/// The standard only defines 0 as an available resource. Providers built with this crate
+/// encode the status with their own bits ([ResourceStatusEncoding::Provider]):
+/// * `0x8000_0000` - Resource cannot be used (Not supported by the cable, or module not ready)
+/// * `0x4000_0000` - Resource has been locked by a ComLogicalLink
+/// * `0x0000_FFFF` - Number of ComLogicalLinks using the resource
+pub enum ResourceStatus {
+    /// Resource is available and not used by any ComLogicalLink
+    Available,
+    /// Resource is available, but is being shared by a number of ComLogicalLinks
+    InUse {
+        /// Number of ComLogicalLinks using the resource
+        links: u16
+    },
+    /// Resource is locked by a ComLogicalLink, and cannot be used by any other ComLogicalLink
+    Locked {
+        /// Number of ComLogicalLinks using the resource
+        links: u16
+    },
+    /// Resource is not available
+    NotAvailable
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// Encoding of [RscStatusItem::resource_status]
+pub enum ResourceStatusEncoding {
+    /// Encoding of any D-PDU API. 0 is available, every other value is not available
+    #[default]
+    Standard,
+    /// Encoding of providers built with this crate, which also report the resources in use and
+    /// locked ([ResourceStatus])
+    Provider
+}
+
+impl ResourceStatus {
+    /// Decodes a raw resource status value of any D-PDU API ([ResourceStatusEncoding::Standard])
+    pub fn from_raw(raw: u32) -> Self {
+        match raw {
+            0 => Self::Available,
+            _ => Self::NotAvailable
+        }
+    }
+
+    /// Decodes a raw resource status value of a provider built with this crate
+    /// ([ResourceStatusEncoding::Provider])
+    pub fn from_provider_raw(raw: u32) -> Self {
+        let links = (raw & RSC_STATUS_LINK_COUNT_MASK) as u16;
+        if raw & RSC_STATUS_NOT_AVAIL != 0 {
+            Self::NotAvailable
+        } else if raw & RSC_STATUS_LOCKED != 0 {
+            Self::Locked { links }
+        } else if links == 0 {
+            Self::Available
+        } else {
+            Self::InUse { links }
+        }
+    }
+
+    /// Decodes a raw resource status value in the given encoding
+    pub fn decode(raw: u32, encoding: ResourceStatusEncoding) -> Self {
+        match encoding {
+            ResourceStatusEncoding::Standard => Self::from_raw(raw),
+            ResourceStatusEncoding::Provider => Self::from_provider_raw(raw)
+        }
+    }
+
+    /// Encodes the resource status into the raw value of this crate's providers
+    pub fn to_raw(self) -> u32 {
+        match self {
+            Self::Available => 0,
+            Self::InUse { links } => links as u32,
+            Self::Locked { links } => RSC_STATUS_LOCKED | links as u32,
+            Self::NotAvailable => RSC_STATUS_NOT_AVAIL
+        }
+    }
+
+    /// Returns true if the resource is unused and can be taken by a new ComLogicalLink
+    pub fn is_free(&self) -> bool {
+        matches!(self, Self::Available)
+    }
+
+    /// Returns true if a new ComLogicalLink can share the resource
+    pub fn is_usable(&self) -> bool {
+        matches!(self, Self::Available | Self::InUse { .. })
+    }
+
+    /// Returns the number of ComLogicalLinks using the resource
+    pub fn link_count(&self) -> u16 {
+        match self {
+            Self::InUse { links } | Self::Locked { links } => *links,
+            _ => 0
+        }
+    }
+}
+
+impl RscStatusItem {
+    /// Creates a new status request entry for a resource on a module
+    pub fn new(h_mod: ModuleHandle, resource_id: ResourceId) -> Self {
+        Self {
+            h_mod: h_mod.raw(),
+            resource_id: resource_id.raw(),
+            resource_status: 0
+        }
+    }
+
+    /// Returns the decoded resource status
+    pub fn status(&self, encoding: ResourceStatusEncoding) -> ResourceStatus {
+        ResourceStatus::decode(self.resource_status, encoding)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Status of a resource on a module
+pub struct ResourceStatusEntry {
+    /// MVCI module the resource belongs to
+    pub module: ModuleHandle,
+    /// Resource ID
+    pub resource_id: ResourceId,
+    /// Decoded status of the resource
+    pub status: ResourceStatus
+}
+
+/// Queries the status of a list of resources, which may be spread across multiple modules.
+///
+/// The returned list is in the same order as `resources`
+///
+/// ## Parameters
+/// * get_resource_status - The API's `PDUGetResourceStatus` function
+/// * resources - List of modules and the resource ID on each module to query
+/// * encoding - Encoding of the statuses. Only use [ResourceStatusEncoding::Provider] for APIs
+///   which are built with this crate
+pub fn get_resource_status(
+    get_resource_status: PduGetResourceStatusFn,
+    resources: &[(ModuleHandle, ResourceId)],
+    encoding: ResourceStatusEncoding
+) -> Result<Vec<ResourceStatusEntry>, PduError> {
+    if resources.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut items: Vec<RscStatusItem> = resources
+        .iter()
+        .map(|(h_mod, rsc_id)| RscStatusItem::new(*h_mod, *rsc_id))
+        .collect();
+    let mut data = RscStatusData {
+        item_type: PduIt::RscStatus,
+        num_entries: items.len() as u32,
+        p_resource_status_data: items.as_mut_ptr()
+    };
+    match get_resource_status(&mut data) {
+        PduError::StatusNoError => {}
+        e => return Err(e)
+    }
+    Ok(resources
+        .iter()
+        .zip(items.iter())
+        .map(|((h_mod, rsc_id), item)| ResourceStatusEntry {
+            module: *h_mod,
+            resource_id: *rsc_id,
+            status: item.status(encoding)
+        })
+        .collect())
+}
+
+/// Returns the first resource in the list which is not used by any ComLogicalLink.
+/// If no resource is free, then the first resource which can still be shared is returned
+pub fn find_available_resource(entries: &[ResourceStatusEntry]) -> Option<ResourceStatusEntry> {
+    entries
+        .iter()
+        .find(|e| e.status.is_free())
+        .or_else(|| {
+            entries
+                .iter()
+                .filter(|e| e.status.is_usable())
+                .min_by_key(|e| e.status.link_count())
+        })
+        .copied()
+}
@@ -133,7 +133,7 @@ pub struct RscStatusItem {
     pub h_mod: u32,
     /// Resource ID
     pub resource_id: u32,
-    /// Resource information status
+    /// Resource information status (See [ResourceStatus](crate::ResourceStatus) for decoding)
     pub resource_status: u32
 }
 
@@ -507,7 +507,7 @@ pub struct ParamStructAccessTiming {
     pub p2_min: u8,
     /// 0.5ms resolution - Maximum time between tester request and ECU response
     pub p2_max: u8,
-    /// 250ms resolution - Minimum time between ECU response and start of new tester request
+    /// 0.5ms resolution - Minimum time between ECU response and start of new tester request
     pub p3_min: u8,
     /// 250ms resolution - Maximum time between ECU response and start of new tester request
     pub p3_max: u8,
@@ -0,0 +1,116 @@
+//! Tests of the catalogue of standard short names
+
+use std::{
+    cell::Cell,
+    ffi::CStr,
+    fmt::{Debug, Display},
+    str::FromStr
+};
+
+use dpdu_rust::{
+    provider::{std_object, std_object_id, std_object_id_by_name},
+    BusType, IoctlCommand, ObjectId, ObjectIdCache, PduError, PduObjt, PinData, PinType, Protocol, ResourceDescriptor,
+    StandardObject
+};
+
+thread_local! {
+    /// Number of calls to [get_object_id] on this thread
+    static CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// `PDUGetObjectId` which resolves standard short names
+extern "C" fn get_object_id(object_type: PduObjt, short_name: *mut u8, id: *mut u32) -> PduError {
+    CALLS.set(CALLS.get() + 1);
+    // Safety: The cache passes a null terminated short name and a valid ID pointer
+    unsafe {
+        let name = CStr::from_ptr(short_name.cast()).to_str().unwrap();
+        match std_object_id_by_name(object_type, name) {
+            Some(object) => *id = object.raw(),
+            None => return PduError::IdNotSupported
+        }
+    }
+    PduError::StatusNoError
+}
+
+/// Resolves every object of the catalogue from its short name, and back again
+fn round_trip<T>(cache: &mut ObjectIdCache) -> Vec<ObjectId>
+where
+    T: StandardObject + FromStr<Err = PduError> + Display + Debug
+{
+    let mut ids = Vec::new();
+    for object in T::ALL {
+        let short_name = object.short_name();
+        assert_eq!(object.to_string(), short_name);
+        assert_eq!(short_name.parse::<T>(), Ok(*object));
+        let id = cache.resolve(T::OBJECT_TYPE, short_name).unwrap();
+        assert_eq!(id, std_object_id(*object), "{short_name}");
+        assert_eq!(cache.resolve_object(*object), Ok(id));
+        assert_eq!(std_object::<T>(id).map(|o| o.short_name()), Some(short_name));
+        ids.push(id);
+    }
+    ids
+}
+
+#[test]
+fn short_name_round_trips() {
+    let mut cache = ObjectIdCache::new(get_object_id);
+    let mut ids = round_trip::<BusType>(&mut cache);
+    ids.extend(round_trip::<Protocol>(&mut cache));
+    ids.extend(round_trip::<PinType>(&mut cache));
+    ids.extend(round_trip::<IoctlCommand>(&mut cache));
+    // Every object has its own ID
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), count);
+
+    // IDs of another object type, or past the end of the catalogue, are not objects of the type
+    assert_eq!(std_object::<Protocol>(std_object_id(BusType::Iso11898_2Dwcan)), None);
+    let past_end = ObjectId::new(std_object_id(PinType::ProgV).raw() + 1).unwrap();
+    assert_eq!(std_object::<PinType>(past_end), None);
+}
+
+#[test]
+fn unknown_short_names() {
+    let mut cache = ObjectIdCache::new(get_object_id);
+    // Short names are case sensitive
+    for unknown in ["ISO_11898_RAW_", "iso_11898_raw", "ISO_15765_3_ON_ISO_15765_2", ""] {
+        assert_eq!(unknown.parse::<Protocol>(), Err(PduError::InvalidParameters));
+        assert_eq!(std_object_id_by_name(PduObjt::Protocol, unknown), None);
+        assert_eq!(cache.resolve(PduObjt::Protocol, unknown), Err(PduError::IdNotSupported));
+    }
+    // Short names of other object types are rejected
+    assert_eq!("ISO_11898_2_DWCAN".parse::<Protocol>(), Err(PduError::InvalidParameters));
+    assert_eq!("ISO_11898_RAW".parse::<BusType>(), Err(PduError::InvalidParameters));
+    assert_eq!("HI".parse::<IoctlCommand>(), Err(PduError::InvalidParameters));
+    assert_eq!("PDU_IOCTL_RESET".parse::<PinType>(), Err(PduError::InvalidParameters));
+    assert_eq!(cache.resolve(PduObjt::BusType, "ISO_11898_RAW"), Err(PduError::IdNotSupported));
+    // Resources have no short names
+    assert_eq!(std_object_id_by_name(PduObjt::Resource, "ISO_11898_RAW"), None);
+    // Short names which cannot be passed to the API
+    assert_eq!(cache.resolve(PduObjt::Protocol, "ISO_11898\0RAW"), Err(PduError::InvalidParameters));
+}
+
+#[test]
+fn cached_resolution() {
+    let mut cache = ObjectIdCache::new(get_object_id);
+    let descriptor = ResourceDescriptor::obd(Protocol::Iso15765_3OnIso15765_2);
+    assert_eq!(descriptor.bus_type, BusType::Iso11898_2Dwcan);
+    let calls = CALLS.get();
+    let request = descriptor.resolve(&mut cache).unwrap();
+    assert_eq!(request.bus_type_id, std_object_id(BusType::Iso11898_2Dwcan));
+    assert_eq!(request.protocol_id, std_object_id(Protocol::Iso15765_3OnIso15765_2));
+    assert_eq!(request.pins, [
+        PinData { dlc_pin_number: 6, dlc_pin_type_id: std_object_id(PinType::Hi).raw() },
+        PinData { dlc_pin_number: 14, dlc_pin_type_id: std_object_id(PinType::Lo).raw() }
+    ]);
+    assert_eq!(CALLS.get(), calls + 4);
+
+    // Resolved short names are not resolved by the API again, until the cache is cleared
+    let calls = CALLS.get();
+    assert_eq!(descriptor.resolve(&mut cache), Ok(request.clone()));
+    assert_eq!(CALLS.get(), calls);
+    cache.clear();
+    assert_eq!(descriptor.resolve(&mut cache), Ok(request));
+    assert_eq!(CALLS.get(), calls + 4);
+}
@@ -0,0 +1,114 @@
+//! Helpers shared by the tests of the backends against their simulators
+//!
+//! Every test crate uses a different part of the helpers
+#![allow(dead_code)]
+
+use std::io;
+
+use dpdu_rust::{
+    provider::{Clock, Driver, LinkParams},
+    PduError, Protocol, StdComParam
+};
+
+/// Configuration of the simulator of a backend
+pub trait SimSetup: Sized {
+    /// Simulator started with the configuration
+    type Simulator;
+    /// Driver of the backend
+    type Driver: Driver;
+
+    /// Starts the simulator
+    fn start(self) -> io::Result<Self::Simulator>;
+    /// Returns the options which open the driver on the simulator
+    fn options(sim: &Self::Simulator) -> String;
+}
+
+#[cfg(feature = "doip")]
+impl SimSetup for dpdu_rust::backends::doip::sim::SimConfig {
+    type Simulator = dpdu_rust::backends::doip::sim::DoIpSimulator;
+    type Driver = dpdu_rust::backends::doip::DoIp;
+
+    fn start(self) -> io::Result<Self::Simulator> {
+        Self::Simulator::start("127.0.0.1:0".parse().unwrap(), self)
+    }
+
+    fn options(sim: &Self::Simulator) -> String {
+        sim.address().to_string()
+    }
+}
+
+#[cfg(feature = "elm327")]
+impl SimSetup for dpdu_rust::backends::elm327::sim::SimConfig {
+    type Simulator = dpdu_rust::backends::elm327::sim::Elm327Simulator;
+    type Driver = dpdu_rust::backends::elm327::Elm327;
+
+    fn start(self) -> io::Result<Self::Simulator> {
+        Self::Simulator::start(self)
+    }
+
+    fn options(sim: &Self::Simulator) -> String {
+        sim.path().display().to_string()
+    }
+}
+
+#[cfg(feature = "kline")]
+impl SimSetup for dpdu_rust::backends::kline::sim::SimConfig {
+    type Simulator = dpdu_rust::backends::kline::sim::KLineSimulator;
+    type Driver = dpdu_rust::backends::kline::KLine;
+
+    fn start(self) -> io::Result<Self::Simulator> {
+        Self::Simulator::start(self)
+    }
+
+    fn options(sim: &Self::Simulator) -> String {
+        // The pseudo terminal can not change its baud rate for the 5 baud address
+        format!("{};5baud=uart", sim.path().display())
+    }
+}
+
+#[cfg(feature = "slcan")]
+impl SimSetup for dpdu_rust::backends::slcan::sim::SimConfig {
+    type Simulator = dpdu_rust::backends::slcan::sim::SlcanSimulator;
+    type Driver = dpdu_rust::backends::slcan::Slcan;
+
+    fn start(self) -> io::Result<Self::Simulator> {
+        Self::Simulator::start(self)
+    }
+
+    fn options(sim: &Self::Simulator) -> String {
+        sim.path().display().to_string()
+    }
+}
+
+/// Starts a simulator, and opens the driver of its backend on it
+pub fn start_sim<S: SimSetup>(config: S) -> (S::Simulator, S::Driver) {
+    let sim = config.start().unwrap();
+    let driver = S::Driver::open(&S::options(&sim)).unwrap();
+    (sim, driver)
+}
+
+/// Returns the default ComParams of the protocol, with some of them changed
+pub fn params<D: Driver>(driver: &D, protocol: Protocol, changes: &[(StdComParam, u32)]) -> LinkParams {
+    let mut params = LinkParams::new(driver.com_params(protocol));
+    for (param, value) in changes {
+        params.set(*param, param.value(*value).unwrap());
+    }
+    params
+}
+
+/// Connects the first module, and opens a channel on its first resource which supports the protocol
+pub fn open_channel<D: Driver>(driver: &D, protocol: Protocol, params: &LinkParams) -> Result<D::Channel, PduError> {
+    driver.connect_module(0)?;
+    let resource = driver.modules().remove(0).resources.into_iter().find(|r| r.protocols.contains(&protocol)).unwrap();
+    driver.open_channel(0, &resource, protocol, params, Clock::new())
+}
+
+/// Virtual ECU which answers every request positively, repeating the request. Services without a
+/// positive response SID (0xC0 and above) are not supported
+pub fn echo_ecu(request: &[u8]) -> Vec<Vec<u8>> {
+    match request {
+        [sid @ 0x00..=0xBF, data @ ..] => vec![[&[sid + 0x40][..], data].concat()],
+        [sid, ..] => vec![vec![0x7F, *sid, 0x11]],
+        [] => Vec::new()
+    }
+}
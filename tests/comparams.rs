@@ -0,0 +1,200 @@
+//! Tests of the registry of standard ComParams
+
+use std::{ffi::CStr, sync::Mutex, time::Duration};
+
+use dpdu_rust::{
+    provider::{std_object, std_object_id, std_object_id_by_name},
+    CllHandle, ComParamUnit, ComParamValue, ModuleHandle, ObjectIdCache, ParamItem, ParamStructAccessTiming,
+    ParamStructSessionTiming, PduCpst, PduError, PduObjt, PduPc, PduPt, Protocol, StdComParam, TimingSet
+};
+
+/// ComParams which identify the ECU a response comes from
+const UNIQUE_ID_PARAMS: [StdComParam; 8] = [
+    StdComParam::CanRespUsdtId,
+    StdComParam::CanRespUudtId,
+    StdComParam::CanRespUsdtFormat,
+    StdComParam::CanRespUudtFormat,
+    StdComParam::CanRespUsdtExtAddr,
+    StdComParam::CanRespUudtExtAddr,
+    StdComParam::EcuRespSourceAddress,
+    StdComParam::DoIpLogicalEcuAddress
+];
+
+#[test]
+fn response_ids_are_unique_id_params() {
+    for param in UNIQUE_ID_PARAMS {
+        assert_eq!(param.class(), PduPc::UniqueId, "{param}");
+    }
+    // The addresses of requests are ordinary ComParams
+    for param in [
+        StdComParam::CanPhysReqId,
+        StdComParam::CanFuncReqId,
+        StdComParam::CanPhysReqExtAddr,
+        StdComParam::TesterSourceAddress,
+        StdComParam::PhysReqTargetAddr,
+        StdComParam::DoIpLogicalTesterAddress,
+        StdComParam::DoIpLogicalGatewayAddress
+    ] {
+        assert_eq!(param.class(), PduPc::Com, "{param}");
+    }
+    let unique = StdComParam::ALL.iter().filter(|p| p.class() == PduPc::UniqueId).count();
+    assert_eq!(unique, UNIQUE_ID_PARAMS.len());
+}
+
+#[test]
+fn registry_lookup() {
+    for param in StdComParam::ALL {
+        let short_name = param.short_name();
+        assert!(short_name.starts_with("CP_"), "{short_name}");
+        assert_eq!(param.to_string(), short_name);
+        assert_eq!(short_name.parse::<StdComParam>(), Ok(*param));
+        let id = std_object_id(*param);
+        assert_eq!(std_object::<StdComParam>(id), Some(*param));
+        assert_eq!(std_object_id_by_name(PduObjt::ComParam, short_name), Some(id));
+        // Only struct fields have a structure type
+        assert_eq!(param.def().struct_type.is_some(), param.data_type() == PduPt::StructField, "{short_name}");
+    }
+    let mut names: Vec<_> = StdComParam::ALL.iter().map(|p| p.short_name()).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), StdComParam::ALL.len());
+
+    // Short names are case sensitive
+    for unknown in ["CP_Unknown", "cp_p2max", "P2Max", ""] {
+        assert_eq!(unknown.parse::<StdComParam>(), Err(PduError::ComParamNotSupported));
+        assert_eq!(std_object_id_by_name(PduObjt::ComParam, unknown), None);
+    }
+    // Short names of other object types are not ComParams
+    assert_eq!(std_object_id_by_name(PduObjt::ComParam, "ISO_11898_RAW"), None);
+}
+
+#[test]
+fn registry_definitions() {
+    let p2_max = StdComParam::P2Max.def();
+    assert_eq!(p2_max.class, PduPc::Timing);
+    assert_eq!(p2_max.data_type, PduPt::Unum32);
+    assert_eq!(p2_max.unit, ComParamUnit::Microseconds);
+    assert!(!p2_max.is_physical());
+    assert!(StdComParam::Baudrate.def().is_physical());
+
+    let session = StdComParam::SessionTimingOverride.def();
+    assert_eq!(session.data_type, PduPt::StructField);
+    assert_eq!(session.struct_type, Some(PduCpst::SessionTiming));
+
+    // Protocols of CAN, K-Line and every protocol
+    let ext_addr = StdComParam::CanRespUsdtExtAddr;
+    assert!(ext_addr.def().supports_protocol(Protocol::Iso15765_3OnIso15765_2));
+    assert!(!ext_addr.def().supports_protocol(Protocol::Iso11898Raw));
+    assert!(!StdComParam::P4Min.def().supports_protocol(Protocol::Iso15765_3OnIso15765_2));
+    assert!(StdComParam::P4Min.def().supports_protocol(Protocol::Iso14230_3OnIso14230_2));
+    assert!(StdComParam::Baudrate.protocols().is_empty());
+    assert!(StdComParam::Baudrate.def().supports_protocol(Protocol::Iso14229_5OnIso13400_2));
+}
+
+#[test]
+fn numeric_values_are_range_checked() {
+    assert_eq!(StdComParam::BlockSize.value(0xFF), Ok(ComParamValue::Unum32(0xFF)));
+    assert_eq!(StdComParam::BlockSize.value(0x100), Err(PduError::ValueNotSupported));
+    assert_eq!(StdComParam::CanFillerByteHandling.value(1), Ok(ComParamValue::Unum32(1)));
+    assert_eq!(StdComParam::CanFillerByteHandling.value(2), Err(PduError::ValueNotSupported));
+    assert_eq!(StdComParam::DoIpLogicalEcuAddress.value(0xFFFF), Ok(ComParamValue::Unum32(0xFFFF)));
+    assert_eq!(StdComParam::DoIpLogicalEcuAddress.value(0x1_0000), Err(PduError::ValueNotSupported));
+    assert_eq!(StdComParam::CanPhysReqId.value(u32::MAX), Ok(ComParamValue::Unum32(u32::MAX)));
+    // Numbers are not byte fields
+    assert_eq!(StdComParam::TesterPresentMessage.value(0x3E), Err(PduError::InvalidParameters));
+
+    // Values read back from an API are checked the same way
+    let def = StdComParam::InitializationSettings.def();
+    assert_eq!(def.validate(&ComParamValue::Unum32(2)), Ok(()));
+    assert_eq!(def.validate(&ComParamValue::Unum32(3)), Err(PduError::ValueNotSupported));
+    assert_eq!(def.validate(&ComParamValue::Unum8(1)), Err(PduError::InvalidParameters));
+    assert_eq!(def.validate(&ComParamValue::Snum32(1)), Err(PduError::InvalidParameters));
+}
+
+#[test]
+fn durations() {
+    let p2_max = StdComParam::P2Max;
+    assert_eq!(p2_max.duration(Duration::from_millis(50)), Ok(ComParamValue::Unum32(50_000)));
+    assert_eq!(p2_max.duration(Duration::from_secs(4295)), Err(PduError::ValueNotSupported));
+    assert_eq!(p2_max.to_duration(&ComParamValue::Unum32(50_000)), Some(Duration::from_millis(50)));
+    // Only ComParams measured in microseconds take a duration
+    assert_eq!(StdComParam::BlockSize.duration(Duration::from_millis(1)), Err(PduError::InvalidParameters));
+    assert_eq!(StdComParam::BlockSize.to_duration(&ComParamValue::Unum32(1)), None);
+}
+
+#[test]
+fn field_values_are_type_checked() {
+    let present = StdComParam::TesterPresentMessage;
+    assert_eq!(present.bytes(&[0x3E, 0x00]), Ok(ComParamValue::ByteField(vec![0x3E, 0x00])));
+    assert_eq!(StdComParam::P2Max.bytes(&[0x3E]), Err(PduError::InvalidParameters));
+    assert_eq!(present.long_field(&[0x3E]), Err(PduError::InvalidParameters));
+
+    let session = ParamStructSessionTiming {
+        session: 1,
+        p2_max_high: 0,
+        p2_max_low: 50,
+        p2_star_high: 1,
+        p2_star_low: 0xF4
+    };
+    let access = ParamStructAccessTiming {
+        p2_min: 0,
+        p2_max: 100,
+        p3_min: 110,
+        p3_max: 20,
+        p4_min: 10,
+        timing_set: TimingSet::Default
+    };
+    let (session_override, access_override) = (StdComParam::SessionTimingOverride, StdComParam::AccessTimingOverride);
+    assert_eq!(session_override.session_timing(&[session]), Ok(ComParamValue::SessionTiming(vec![session])));
+    assert_eq!(access_override.access_timing(&[access]), Ok(ComParamValue::AccessTiming(vec![access])));
+    // The structure type must match the ComParam
+    assert_eq!(session_override.access_timing(&[access]), Err(PduError::InvalidParameters));
+    assert_eq!(access_override.session_timing(&[session]), Err(PduError::InvalidParameters));
+}
+
+/// ComParams received by [set_com_param]: Class, ID and value
+static SET: Mutex<Vec<(PduPc, u32, ComParamValue)>> = Mutex::new(Vec::new());
+
+/// `PDUGetObjectId` which resolves standard short names
+extern "C" fn get_object_id(object_type: PduObjt, short_name: *mut u8, id: *mut u32) -> PduError {
+    // Safety: The cache passes a null terminated short name and a valid ID pointer
+    unsafe {
+        let name = CStr::from_ptr(short_name.cast()).to_str().unwrap();
+        match std_object_id_by_name(object_type, name) {
+            Some(object) => *id = object.raw(),
+            None => return PduError::IdNotSupported
+        }
+    }
+    PduError::StatusNoError
+}
+
+/// `PDUSetComParam` which records the ComParam
+extern "C" fn set_com_param(_h_mod: u32, _h_cll: u32, item: *mut ParamItem) -> PduError {
+    // Safety: set passes a valid item
+    let item = unsafe { &*item };
+    // Safety: The item holds a value of its data type
+    let value = unsafe { ComParamValue::from_param_item(item) }.unwrap();
+    SET.lock().unwrap().push((item.com_param_class, item.com_param_id, value));
+    PduError::StatusNoError
+}
+
+#[test]
+fn set_validates_before_calling_the_api() {
+    let mut cache = ObjectIdCache::new(get_object_id);
+    let (h_mod, h_cll) = (ModuleHandle::new(1).unwrap(), CllHandle::new(2).unwrap());
+    let set = |cache: &mut ObjectIdCache, param: StdComParam, value: ComParamValue| {
+        param.set(set_com_param, cache, h_mod, h_cll, &value)
+    };
+    let usdt = StdComParam::CanRespUsdtId;
+    assert_eq!(set(&mut cache, usdt, ComParamValue::Unum32(0x7E8)), Ok(()));
+    let p2_max = StdComParam::P2Max.duration(Duration::from_millis(50)).unwrap();
+    assert_eq!(set(&mut cache, StdComParam::P2Max, p2_max), Ok(()));
+    // Values of the wrong type, or out of range, never reach the API
+    assert_eq!(set(&mut cache, StdComParam::BlockSize, ComParamValue::Unum32(0x100)), Err(PduError::ValueNotSupported));
+    assert_eq!(set(&mut cache, StdComParam::P2Max, ComParamValue::Unum8(50)), Err(PduError::InvalidParameters));
+
+    assert_eq!(*SET.lock().unwrap(), [
+        (PduPc::UniqueId, std_object_id(usdt).raw(), ComParamValue::Unum32(0x7E8)),
+        (PduPc::Timing, std_object_id(StdComParam::P2Max).raw(), ComParamValue::Unum32(50_000))
+    ]);
+}
@@ -0,0 +1,397 @@
+//! Tests of the ComPrimitive engine
+
+use std::{
+    ffi::c_void,
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    provider::{
+        send_error_event, CopControl, CopEngine, CopIo, Cycles, Event, EventData, EventQueue, EventSource, PduTag,
+        PendingHandling, ResponseTiming, ResultEvent, DEFAULT_TX_QUEUE_SIZE
+    },
+    CopHandle, ExpectedResponse, PduCopt, PduError, PduErrorEvt, PduEvtData, PduStatus, Protocol, ResponseMatcher,
+    ResponseType
+};
+
+/// Protocol of the links in these tests
+const UDS: Protocol = Protocol::Iso15765_3OnIso15765_2;
+
+/// Link which accepts every send
+struct Io;
+
+impl CopIo for Io {
+    fn send(&mut self, _h_cop: CopHandle, _data: &[u8], _ctrl: &CopControl) -> Result<(), PduError> {
+        Ok(())
+    }
+
+    fn execute(&mut self, _h_cop: CopHandle, _cop_type: PduCopt, _data: &[u8]) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    fn timestamp(&self) -> u32 {
+        0
+    }
+}
+
+const H_COP: CopHandle = match CopHandle::new(1) {
+    Some(h) => h,
+    None => unreachable!()
+};
+
+/// Expects positive responses to service 0x22 and negative responses to it
+fn uds_ctrl() -> CopControl {
+    CopControl {
+        expected_responses: ResponseMatcher::new(vec![
+            ExpectedResponse::new(ResponseType::Positive, 1, vec![0xFF], vec![0x62]).unwrap(),
+            ExpectedResponse::new(ResponseType::Negative, 2, vec![0xFF, 0xFF], vec![0x7F, 0x22]).unwrap(),
+        ]),
+        ..Default::default()
+    }
+}
+
+fn response(data: &[u8]) -> ResultEvent {
+    ResultEvent { data: data.to_vec(), ..Default::default() }
+}
+
+fn drain(events: &EventQueue) -> Vec<EventData> {
+    std::iter::from_fn(|| events.pop().ok()).map(|e: Event| e.data).collect()
+}
+
+fn start(engine: &CopEngine, events: &EventQueue, now: Instant) {
+    engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x22, 0xF1, 0x90], uds_ctrl(), PduTag::NULL).unwrap();
+    engine.poll(now, &mut Io, events);
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstWaiting));
+}
+
+#[test]
+fn negative_response_finishes_without_timeout() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let now = Instant::now();
+    start(&engine, &events, now);
+
+    assert!(engine.on_response(now, response(&[0x7F, 0x22, 0x31]), &events));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+    assert_eq!(engine.poll(now + Duration::from_secs(5), &mut Io, &events), None);
+    let data = drain(&events);
+    assert!(!data.iter().any(|d| matches!(d, EventData::Error(e) if e.error_code_id == PduErrorEvt::RxTimeout)));
+    assert!(matches!(&data[data.len() - 2], EventData::Result(r) if r.acceptance_id == 2));
+}
+
+#[test]
+fn response_pending_waits_for_p2_star() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let timing = engine.response_timing();
+    assert!(timing.p2_star > timing.p2_max * 2);
+    let now = Instant::now();
+    start(&engine, &events, now);
+
+    let pending = now + timing.p2_max / 2;
+    assert!(engine.on_response(pending, response(&[0x7F, 0x22, 0x78]), &events));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstWaiting));
+    // The final response arrives well after P2Max
+    assert_eq!(engine.poll(pending + timing.p2_max, &mut Io, &events), Some(pending + timing.p2_star));
+    assert!(engine.on_response(pending + timing.p2_max * 2, response(&[0x62, 0xF1, 0x90]), &events));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+    let results = drain(&events).into_iter().filter(|d| matches!(d, EventData::Result(_))).count();
+    assert_eq!(results, 2);
+}
+
+#[test]
+fn response_pending_handling() {
+    let timing = ResponseTiming { p2_max: Duration::from_millis(50), p2_star: Duration::from_millis(500), ..Default::default() };
+    let now = Instant::now();
+
+    // Disabled, the response pending is the final response
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    engine.set_response_timing(ResponseTiming { pending: PendingHandling::Disabled, ..timing });
+    start(&engine, &events, now);
+    assert!(engine.on_response(now, response(&[0x7F, 0x22, 0x78]), &events));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+
+    // Raw CAN frames are never a response pending, even if their ID starts like one
+    let engine = CopEngine::new(Protocol::Iso11898Raw);
+    start(&engine, &events, now);
+    assert!(engine.on_response(now, response(&[0x7F, 0x22, 0x78]), &events));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+
+    // Each response pending waits P2Star, up to the completion timeout after the first one
+    let engine = CopEngine::new(UDS);
+    let completion = Duration::from_millis(800);
+    engine.set_response_timing(ResponseTiming { pending: PendingHandling::UntilTimeout(completion), ..timing });
+    start(&engine, &events, now);
+    assert!(engine.on_response(now, response(&[0x7F, 0x22, 0x78]), &events));
+    assert_eq!(engine.poll(now, &mut Io, &events), Some(now + timing.p2_star));
+    let later = now + Duration::from_millis(400);
+    assert!(engine.on_response(later, response(&[0x7F, 0x22, 0x78]), &events));
+    assert_eq!(engine.poll(later, &mut Io, &events), Some(now + completion));
+    events.clear();
+    assert_eq!(engine.poll(now + completion, &mut Io, &events), None);
+    let data = drain(&events);
+    assert!(data.iter().any(|d| matches!(d, EventData::Error(e) if e.error_code_id == PduErrorEvt::RxTimeout)));
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+}
+
+/// Link which records the time of every send
+#[derive(Default)]
+struct Recorder {
+    sends: Vec<Instant>,
+    now: Option<Instant>
+}
+
+impl CopIo for Recorder {
+    fn send(&mut self, _h_cop: CopHandle, _data: &[u8], _ctrl: &CopControl) -> Result<(), PduError> {
+        self.sends.push(self.now.unwrap());
+        Ok(())
+    }
+
+    fn execute(&mut self, _h_cop: CopHandle, _cop_type: PduCopt, _data: &[u8]) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    fn timestamp(&self) -> u32 {
+        0
+    }
+}
+
+#[test]
+fn cyclic_send_timing() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let cycle = Duration::from_millis(100);
+    let ctrl = CopControl { time: cycle, send_cycles: Cycles::Count(3), receive_cycles: Cycles::Count(0), ..Default::default() };
+    engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x3E, 0x80], ctrl, PduTag::NULL).unwrap();
+    let mut io = Recorder::default();
+    let start = Instant::now();
+    let mut now = start;
+    // Polls whenever the engine asks, and a few times in between
+    loop {
+        io.now = Some(now);
+        let Some(wake) = engine.poll(now, &mut io, &events) else {
+            break;
+        };
+        now = (now + cycle / 3).min(wake);
+    }
+    assert_eq!(io.sends, [start, start + cycle, start + cycle * 2]);
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+
+    // A cyclic ComPrimitive runs in the background, so the next one starts straight away
+    let (cyclic, single) = (CopHandle::new(2).unwrap(), CopHandle::new(3).unwrap());
+    let ctrl = CopControl { time: cycle, send_cycles: Cycles::Infinite, receive_cycles: Cycles::Count(0), ..Default::default() };
+    engine.enqueue(cyclic, PduCopt::SendRecv, vec![0x3E, 0x80], ctrl, PduTag::NULL).unwrap();
+    let ctrl = CopControl { receive_cycles: Cycles::Count(0), ..Default::default() };
+    engine.enqueue(single, PduCopt::SendRecv, vec![0x3E, 0x80], ctrl, PduTag::NULL).unwrap();
+    io = Recorder { now: Some(start), ..Default::default() };
+    assert_eq!(engine.poll(start, &mut io, &events), Some(start + cycle));
+    assert_eq!(io.sends.len(), 2);
+    assert_eq!(engine.status(single), Some(PduStatus::CopstFinished));
+    assert_eq!(engine.status(cyclic), Some(PduStatus::CopstWaiting));
+}
+
+#[test]
+fn cancel_while_waiting() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let now = Instant::now();
+    start(&engine, &events, now);
+    events.clear();
+
+    engine.cancel(H_COP, 0, &events).unwrap();
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstCancelled));
+    assert_eq!(drain(&events), [EventData::Status(PduStatus::CopstCancelled)]);
+    // The response and the timeout no longer belong to the ComPrimitive
+    assert!(!engine.on_response(now, response(&[0x62, 0xF1, 0x90]), &events));
+    assert_eq!(engine.poll(now + Duration::from_secs(5), &mut Io, &events), None);
+    assert!(drain(&events).is_empty());
+    assert_eq!(engine.cancel(H_COP, 0, &events), Err(PduError::InvalidHandle));
+    assert!(engine.is_idle());
+}
+
+/// Link which cancels the ComPrimitive while sending it, as the application may from another thread
+struct CancellingIo<'a>(&'a CopEngine);
+
+impl CopIo for CancellingIo<'_> {
+    fn send(&mut self, h_cop: CopHandle, _data: &[u8], _ctrl: &CopControl) -> Result<(), PduError> {
+        // Deadlocks if the engine is locked during the send
+        self.0.cancel(h_cop, 0, &EventQueue::new(EventSource::default()))
+    }
+
+    fn execute(&mut self, _h_cop: CopHandle, _cop_type: PduCopt, _data: &[u8]) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    fn timestamp(&self) -> u32 {
+        0
+    }
+}
+
+#[test]
+fn sends_run_with_the_engine_unlocked() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x22, 0xF1, 0x90], uds_ctrl(), PduTag::NULL).unwrap();
+    assert_eq!(engine.poll(Instant::now(), &mut CancellingIo(&engine), &events), None);
+    // The ComPrimitive is cancelled once the send returns
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstCancelled));
+    assert_eq!(drain(&events).last(), Some(&EventData::Status(PduStatus::CopstCancelled)));
+    assert!(engine.is_idle());
+}
+
+/// Link whose sends fail, with the error event of the transport layer if it reports one
+struct FailingIo {
+    error: PduError,
+    code: Option<PduErrorEvt>
+}
+
+impl CopIo for FailingIo {
+    fn send(&mut self, _h_cop: CopHandle, _data: &[u8], _ctrl: &CopControl) -> Result<(), PduError> {
+        Err(self.error)
+    }
+
+    fn send_error(&mut self, error: PduError) -> PduErrorEvt {
+        self.code.unwrap_or(send_error_event(error))
+    }
+
+    fn execute(&mut self, _h_cop: CopHandle, _cop_type: PduCopt, _data: &[u8]) -> Result<Option<Vec<u8>>, PduError> {
+        Ok(None)
+    }
+
+    fn timestamp(&self) -> u32 {
+        0
+    }
+}
+
+#[test]
+fn send_errors_finish_the_com_primitive() {
+    let cases = [
+        (PduError::FctFailed, None, PduErrorEvt::TxError),
+        (PduError::CommPcToVciFailed, None, PduErrorEvt::LostCommToVCI),
+        (PduError::FctFailed, Some(PduErrorEvt::RxTimeout), PduErrorEvt::RxTimeout)
+    ];
+    for (error, code, expected) in cases {
+        let engine = CopEngine::new(UDS);
+        let events = EventQueue::new(EventSource::default());
+        engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x22, 0xF1, 0x90], uds_ctrl(), PduTag::NULL).unwrap();
+        assert_eq!(engine.poll(Instant::now(), &mut FailingIo { error, code }, &events), None);
+        assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+        let errors: Vec<PduErrorEvt> = drain(&events)
+            .iter()
+            .filter_map(|d| match d {
+                EventData::Error(e) => Some(e.error_code_id),
+                _ => None
+            })
+            .collect();
+        assert_eq!(errors, [expected]);
+    }
+}
+
+/// Calls back into the engine whose address is the ComLogicalLink tag
+unsafe extern "C" fn reentrant_callback(_event: PduEvtData, _h_mod: u32, _h_cll: u32, cll_tag: *mut c_void, _api_tag: *mut c_void) {
+    let engine = &*(cll_tag as *const CopEngine);
+    let _ = engine.status(H_COP);
+    let _ = engine.cancel(H_COP, 0, &EventQueue::new(EventSource::default()));
+}
+
+#[test]
+fn callbacks_may_call_into_the_engine() {
+    let engine = Box::new(CopEngine::new(UDS));
+    let source = EventSource { cll_tag: PduTag(std::ptr::from_ref::<CopEngine>(&engine) as *mut c_void), ..Default::default() };
+    let events = EventQueue::new(source);
+    events.set_callback(Some(reentrant_callback));
+    // Each of these pushes events, and deadlocks if the engine is still locked
+    engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x22, 0xF1, 0x90], uds_ctrl(), PduTag::NULL).unwrap();
+    engine.poll(Instant::now(), &mut Io, &events);
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstCancelled));
+    engine.forget(H_COP);
+
+    engine.enqueue(H_COP, PduCopt::SendRecv, vec![0x22, 0xF1, 0x90], uds_ctrl(), PduTag::NULL).unwrap();
+    engine.suspend_tx();
+    events.clear();
+    assert_eq!(engine.clear_tx_queue(0, &events), [H_COP]);
+}
+
+/// ComPrimitive which sends without expecting a response, so it finishes once it is sent
+fn send_only(engine: &CopEngine, h_cop: u32) -> Result<(), PduError> {
+    let ctrl = CopControl { receive_cycles: Cycles::Count(0), ..Default::default() };
+    engine.enqueue(CopHandle::new(h_cop).unwrap(), PduCopt::SendRecv, vec![0x3E, 0x80], ctrl, PduTag::NULL)
+}
+
+#[test]
+fn tx_queue_full() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    assert_eq!(engine.tx_capacity(), DEFAULT_TX_QUEUE_SIZE);
+    assert_eq!(engine.set_tx_capacity(0), Err(PduError::InvalidParameters));
+    engine.set_tx_capacity(2).unwrap();
+
+    engine.suspend_tx();
+    send_only(&engine, 1).unwrap();
+    send_only(&engine, 2).unwrap();
+    assert_eq!(send_only(&engine, 3), Err(PduError::TxQueueFull));
+    assert_eq!(engine.tx_pending(), 2);
+
+    // Shrinking keeps the queued ComPrimitives, but no more are accepted until they have started
+    engine.set_tx_capacity(1).unwrap();
+    assert_eq!(engine.tx_pending(), 2);
+    assert_eq!(send_only(&engine, 3), Err(PduError::TxQueueFull));
+    engine.resume_tx();
+    engine.poll(Instant::now(), &mut Io, &events);
+    assert_eq!(engine.tx_pending(), 0);
+    send_only(&engine, 3).unwrap();
+}
+
+#[test]
+fn suspended_sends_are_held() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let now = Instant::now();
+    let mut io = Recorder { now: Some(now), ..Default::default() };
+    engine.suspend_tx();
+    assert!(engine.is_tx_suspended());
+    send_only(&engine, 1).unwrap();
+    send_only(&engine, 2).unwrap();
+    assert_eq!(engine.poll(now, &mut io, &events), None);
+    assert!(io.sends.is_empty());
+    assert_eq!(engine.status(CopHandle::new(1).unwrap()), Some(PduStatus::CopstIdle));
+    assert!(drain(&events).is_empty());
+
+    // Resuming releases the held sends in order
+    engine.resume_tx();
+    assert!(!engine.is_tx_suspended());
+    assert_eq!(engine.poll(now, &mut io, &events), None);
+    assert_eq!(io.sends.len(), 2);
+    for h_cop in [1, 2] {
+        assert_eq!(engine.status(CopHandle::new(h_cop).unwrap()), Some(PduStatus::CopstFinished));
+    }
+    assert!(engine.is_idle());
+}
+
+#[test]
+fn clear_tx_queue() {
+    let engine = CopEngine::new(UDS);
+    let events = EventQueue::new(EventSource::default());
+    let now = Instant::now();
+    start(&engine, &events, now);
+    send_only(&engine, 2).unwrap();
+    send_only(&engine, 3).unwrap();
+    events.clear();
+
+    // Only the ComPrimitives which have not started are cancelled
+    let cancelled = engine.clear_tx_queue(0, &events);
+    assert_eq!(cancelled, [CopHandle::new(2).unwrap(), CopHandle::new(3).unwrap()]);
+    assert_eq!(drain(&events), vec![EventData::Status(PduStatus::CopstCancelled); 2]);
+    assert_eq!(engine.tx_pending(), 0);
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstWaiting));
+    assert_eq!(engine.status(CopHandle::new(2).unwrap()), Some(PduStatus::CopstCancelled));
+
+    // The started ComPrimitive still gets its response, and nothing is sent after it
+    assert!(engine.on_response(now, response(&[0x62, 0xF1, 0x90]), &events));
+    let mut io = Recorder { now: Some(now), ..Default::default() };
+    assert_eq!(engine.poll(now, &mut io, &events), None);
+    assert!(io.sends.is_empty());
+    assert_eq!(engine.status(H_COP), Some(PduStatus::CopstFinished));
+    assert!(engine.clear_tx_queue(0, &events).is_empty());
+}
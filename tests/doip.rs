@@ -0,0 +1,419 @@
+//! Tests of the DoIP backend against the simulator and a scripted entity on loopback
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    backends::doip::{
+        decode_message, encode_message,
+        sim::{DoIpSimulator, SimConfig},
+        DoIp, DoIpChannel, VehicleIdentification, ALIVE_CHECK_REQUEST, ALIVE_CHECK_RESPONSE, DIAGNOSTIC_MESSAGE,
+        ENTITY_STATUS_REQUEST, ENTITY_STATUS_RESPONSE, GENERIC_NACK, HEADER_LEN, NACK_INCORRECT_PATTERN,
+        POWER_MODE_REQUEST, POWER_MODE_RESPONSE, ROUTING_ACTIVATION_REQUEST, ROUTING_ACTIVATION_RESPONSE,
+        ROUTING_SUCCESS, VEHICLE_ANNOUNCEMENT, VEHICLE_ID_REQUEST, VEHICLE_ID_REQUEST_EID, VEHICLE_ID_REQUEST_VIN
+    },
+    provider::{Channel, Clock, Driver, IoctlData, IoctlInput, VehicleIdRequestData},
+    CombinationMode, IoEntityAddressData, IoEntityStatusData, IoctlCommand, PduError, PduErrorEvt, Protocol,
+    StdComParam, VidPreselectMode
+};
+
+mod common;
+
+use common::{echo_ecu, open_channel, params, start_sim};
+
+const PROTOCOL: Protocol = Protocol::Iso14229_5OnIso13400_2;
+
+/// Opens a channel to the default gateway, with some ComParams changed
+fn connect(driver: &DoIp, changes: &[(StdComParam, u32)]) -> Result<DoIpChannel, PduError> {
+    open_channel(driver, PROTOCOL, &params(driver, PROTOCOL, changes))
+}
+
+/// Answers requests to service 0x22 positively
+fn read_data_ecu(request: &[u8]) -> Vec<Vec<u8>> {
+    match request {
+        [0x22, did @ ..] => vec![[&[0x62][..], did, &[0x01]].concat()],
+        [sid, ..] => vec![vec![0x7F, *sid, 0x11]],
+        [] => Vec::new()
+    }
+}
+
+#[test]
+fn tester_present_round_trip() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    sim.add_ecu(0x1000, echo_ecu);
+    let mut channel = connect(&driver, &[]).unwrap();
+    assert_eq!(sim.tcp_clients(), 1);
+    channel.send(&[0x3E, 0x00], &[], &params(&driver, PROTOCOL, &[])).unwrap();
+    assert_eq!(channel.recv(Duration::from_secs(1)).unwrap().unwrap().data, [0x7E, 0x00]);
+
+    sim.set_routing_response(Some(0x04));
+    assert_eq!(connect(&driver, &[]).err(), Some(PduError::DoIPRoutingActivationAuthFailed));
+}
+
+#[test]
+fn routing_activation_response_codes() {
+    // Closed connections are only noticed by the simulator after a poll interval
+    let (sim, driver) = start_sim(SimConfig { max_sockets: 32, ..SimConfig::default() });
+    let codes = [
+        (0x00, Err(PduError::DoIPRoutineActivationInvalidSrcAddress)),
+        (0x01, Err(PduError::DoIPRoutingActivationNoDataSocketAvailable)),
+        (0x02, Err(PduError::DoIPRoutineActivationSourceAddressChanged)),
+        (0x03, Err(PduError::DoIPRoutingActivationSourceAddressInUse)),
+        (0x04, Err(PduError::DoIPRoutingActivationAuthFailed)),
+        (0x05, Err(PduError::DoIPRoutineActivationConfirmationRejected)),
+        (0x06, Err(PduError::DoIPRoutineActivationTypeUnsupported)),
+        (0x07, Err(PduError::DoIPRoutingActivationFailed)),
+        (0x0F, Err(PduError::DoIPRoutingActivationFailed)),
+        (ROUTING_SUCCESS, Ok(())),
+        (0x12, Err(PduError::DoIPRoutineActivationResponseCodeUnknown)),
+        (0xFF, Err(PduError::DoIPRoutineActivationResponseCodeUnknown))
+    ];
+    for (code, expected) in codes {
+        sim.set_routing_response(Some(code));
+        assert_eq!(connect(&driver, &[]).map(|_| ()), expected, "code {code:#04x}");
+    }
+    // A response asking for confirmation is followed by no final response
+    sim.set_routing_response(Some(0x11));
+    assert_eq!(connect(&driver, &[]).err(), Some(PduError::DoIPRoutingActivationResponseTimeout));
+}
+
+#[test]
+fn routing_activation_checks() {
+    let (sim, driver) = start_sim(SimConfig { tester_addresses: vec![0x0E80], ..SimConfig::default() });
+    let tester = (StdComParam::DoIpLogicalTesterAddress, 0x0E80);
+    assert_eq!(connect(&driver, &[]).err(), Some(PduError::DoIPRoutineActivationInvalidSrcAddress));
+    let _first = connect(&driver, &[tester]).unwrap();
+    assert_eq!(connect(&driver, &[tester]).err(), Some(PduError::DoIPRoutingActivationSourceAddressInUse));
+    assert_eq!(
+        connect(&driver, &[(StdComParam::DoIpRoutingActivationType, 0xE0)]).err(),
+        Some(PduError::DoIPRoutineActivationInvalidSrcAddress)
+    );
+    sim.set_config(SimConfig::default());
+    assert_eq!(
+        connect(&driver, &[(StdComParam::DoIpRoutingActivationType, 0xE0)]).err(),
+        Some(PduError::DoIPRoutineActivationTypeUnsupported)
+    );
+}
+
+#[test]
+fn connection_failures() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    // No entity has the gateway address
+    assert_eq!(
+        connect(&driver, &[(StdComParam::DoIpLogicalGatewayAddress, 0x2000)]).err(),
+        Some(PduError::DoIPResponseTimeout)
+    );
+    drop(connect(&driver, &[]).unwrap());
+    // The entity is known, but no longer accepts connections
+    drop(sim);
+    assert_eq!(connect(&driver, &[]).err(), Some(PduError::CommPcToVciFailed));
+}
+
+#[test]
+fn diagnostic_messages() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    sim.add_ecu(0x1000, read_data_ecu);
+    sim.add_ecu(0x1001, read_data_ecu);
+    let mut channel = connect(&driver, &[]).unwrap();
+
+    channel.send(&[0x22, 0xF1, 0x90], &[], &params(&driver, PROTOCOL, &[])).unwrap();
+    let result = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(result.data, [0x62, 0xF1, 0x90, 0x01]);
+    assert_eq!(result.extra_info.unwrap().header, [0x10, 0x00, 0x0E, 0x00]);
+
+    // Functional requests reach every ECU
+    channel.send(&[0x22, 0xF1, 0x90], &[], &params(&driver, PROTOCOL, &[(StdComParam::RequestAddrMode, 2)])).unwrap();
+    let mut sources: Vec<Vec<u8>> = (0..2)
+        .map(|_| channel.recv(Duration::from_secs(1)).unwrap().unwrap().extra_info.unwrap().header[..2].to_vec())
+        .collect();
+    sources.sort();
+    assert_eq!(sources, [[0x10, 0x00], [0x10, 0x01]]);
+
+    // The entity answers a target without an ECU with a diagnostic message NACK
+    let unknown = params(&driver, PROTOCOL, &[(StdComParam::DoIpLogicalEcuAddress, 0x1234)]);
+    assert_eq!(channel.send(&[0x22, 0xF1, 0x90], &[], &unknown), Err(PduError::FctFailed));
+    assert_eq!(channel.send(&[], &[], &unknown), Err(PduError::InvalidParameters));
+}
+
+#[test]
+fn virtual_ecus_run_without_blocking_the_simulator() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    let (entered_tx, entered_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    // Holds the request until the test has used the simulator
+    sim.add_ecu(0x1000, move |request: &[u8]| {
+        entered_tx.send(()).unwrap();
+        match release_rx.recv_timeout(Duration::from_secs(2)) {
+            Ok(()) => echo_ecu(request),
+            Err(_) => vec![vec![0x7F, request[0], 0x21]]
+        }
+    });
+    let mut channel = connect(&driver, &[]).unwrap();
+    let tester = thread::spawn(move || {
+        channel.send(&[0x3E, 0x00], &[], &params(&driver, PROTOCOL, &[])).unwrap();
+        channel.recv(Duration::from_secs(1)).unwrap().unwrap().data
+    });
+    entered_rx.recv().unwrap();
+    assert_eq!(sim.tcp_clients(), 1);
+    release_tx.send(()).unwrap();
+    assert_eq!(tester.join().unwrap(), [0x7E, 0x00]);
+}
+
+#[test]
+fn simulator_alive_checks() {
+    let (sim, driver) = start_sim(SimConfig { alive_check: Some(Duration::from_millis(100)), ..SimConfig::default() });
+    let mut channel = connect(&driver, &[]).unwrap();
+    // The channel answers the requests while it waits for results
+    let end = Instant::now() + Duration::from_millis(500);
+    while Instant::now() < end {
+        assert_eq!(channel.recv(Duration::from_millis(50)), Ok(None));
+    }
+    assert_eq!(sim.tcp_clients(), 1);
+    // An unanswered request closes the connection
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(sim.tcp_clients(), 0);
+    assert_eq!(channel.recv(Duration::from_millis(100)), Err(PduError::CommPcToVciFailed));
+}
+
+#[test]
+fn gateway_changes_reconnect() {
+    let (first, _) = start_sim(SimConfig::default());
+    let mut config = SimConfig::default();
+    config.identification.logical_address = 0x2000;
+    let (second, _) = start_sim(config);
+    let driver = DoIp::open(&format!("{},{}", first.address(), second.address())).unwrap();
+    let mut channel = connect(&driver, &[]).unwrap();
+    assert_eq!((first.tcp_clients(), second.tcp_clients()), (1, 0));
+
+    channel.apply_params(&params(&driver, PROTOCOL, &[(StdComParam::DoIpLogicalGatewayAddress, 0x2000)])).unwrap();
+    assert_eq!(second.tcp_clients(), 1);
+    // Closed connections are only noticed by the simulator after a poll interval
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(first.tcp_clients(), 0);
+    // A gateway which can not be found keeps the connection
+    let unknown = params(&driver, PROTOCOL, &[(StdComParam::DoIpLogicalGatewayAddress, 0x3000)]);
+    assert_eq!(channel.apply_params(&unknown), Err(PduError::DoIPResponseTimeout));
+    assert_eq!(second.tcp_clients(), 1);
+
+    // Channels connected to an address directly can not change their gateway
+    let mut direct = DoIpChannel::connect(first.address(), &params(&driver, PROTOCOL, &[]), Clock::new()).unwrap();
+    let gateway = params(&driver, PROTOCOL, &[(StdComParam::DoIpLogicalGatewayAddress, 0x2000)]);
+    assert_eq!(direct.apply_params(&gateway), Err(PduError::InvalidParameters));
+    assert!(direct.apply_params(&params(&driver, PROTOCOL, &[])).is_ok());
+}
+
+/// Vehicle identification request of the module
+fn identify(driver: &DoIp, preselection_mode: VidPreselectMode, value: &[u8]) -> Result<(), PduError> {
+    let request = VehicleIdRequestData {
+        preselection_mode,
+        preselection_value: value.to_vec(),
+        combination_mode: CombinationMode::None,
+        discovery_time: Duration::from_millis(200),
+        destination_addresses: Vec::new()
+    };
+    driver.module_ioctl(0, IoctlCommand::VehicleIdRequest, &IoctlInput::VehicleIdRequest(request)).map(|_| ())
+}
+
+fn identification(logical_address: u16, vin: &[u8; 17], eid: [u8; 6]) -> VehicleIdentification {
+    VehicleIdentification { vin: *vin, logical_address, eid, ..SimConfig::default().identification }
+}
+
+#[test]
+fn preselected_vehicle_identification() {
+    let (first, _) = start_sim(SimConfig::default());
+    let second_id = identification(0x2000, b"DPDUSIM0000000002", [0x02, 0, 0, 0, 0, 0x02]);
+    let (second, _) = start_sim(SimConfig { identification: second_id, ..SimConfig::default() });
+    let driver = DoIp::open(&format!("{},{}", first.address(), second.address())).unwrap();
+    let found = |driver: &DoIp| driver.entities().iter().map(|e| e.identification.logical_address).collect::<Vec<_>>();
+
+    // Only the entity with the VIN or EID answers
+    identify(&driver, VidPreselectMode::VIN, b"DPDUSIM0000000002").unwrap();
+    assert_eq!(found(&driver), [0x2000]);
+    assert_eq!(driver.entities()[0].address, second.address());
+    let driver = DoIp::open(&format!("{},{}", first.address(), second.address())).unwrap();
+    identify(&driver, VidPreselectMode::EID, b"02:00:00:00:00:01").unwrap();
+    assert_eq!(found(&driver), [0x1000]);
+    identify(&driver, VidPreselectMode::EID, b"020000000002").unwrap();
+    assert_eq!(found(&driver), [0x1000, 0x2000]);
+    identify(&driver, VidPreselectMode::VIN, b"DPDUSIM0000000003").unwrap();
+    assert_eq!(found(&driver).len(), 2);
+
+    assert_eq!(identify(&driver, VidPreselectMode::VIN, b"DPDUSIM"), Err(PduError::InvalidParameters));
+    assert_eq!(identify(&driver, VidPreselectMode::EID, b"02:00:00:00:00"), Err(PduError::InvalidParameters));
+    assert_eq!(identify(&driver, VidPreselectMode::EID, b"02:00:00:00:00:0G"), Err(PduError::InvalidParameters));
+    assert_eq!(
+        driver.module_ioctl(0, IoctlCommand::VehicleIdRequest, &IoctlInput::None),
+        Err(PduError::InvalidParameters)
+    );
+}
+
+/// IOCTL input naming an entity
+fn entity_address(logical_address: u32) -> IoctlInput {
+    IoctlInput::EntityAddress(IoEntityAddressData { logical_address, doip_ctrl_timeout: 500 })
+}
+
+#[test]
+fn entity_status() {
+    let (sim, driver) = start_sim(SimConfig { node_type: 1, max_sockets: 3, ..SimConfig::default() });
+    let status = |driver: &DoIp| driver.module_ioctl(0, IoctlCommand::GetEntityStatus, &entity_address(0x1000));
+    let expected = IoEntityStatusData { entity_type: 1, tcp_clients_max: 3, tcp_clients: 0, max_data_size: 4096 };
+    assert_eq!(status(&driver), Ok(Some(IoctlData::EntityStatus(expected))));
+
+    // Links query their gateway, and are counted as open sockets
+    let mut channel = connect(&driver, &[]).unwrap();
+    assert_eq!(
+        channel.ioctl(IoctlCommand::GetEntityStatus, &IoctlInput::None),
+        Ok(Some(IoctlData::EntityStatus(IoEntityStatusData { tcp_clients: 1, ..expected })))
+    );
+
+    // The maximum data size is optional
+    sim.set_config(SimConfig { node_type: 1, max_sockets: 3, max_data_size: None, ..SimConfig::default() });
+    assert_eq!(
+        status(&driver),
+        Ok(Some(IoctlData::EntityStatus(IoEntityStatusData { tcp_clients: 1, max_data_size: 0, ..expected })))
+    );
+    assert_eq!(
+        driver.module_ioctl(0, IoctlCommand::GetEntityStatus, &IoctlInput::None),
+        Err(PduError::InvalidParameters)
+    );
+}
+
+#[test]
+fn diagnostic_power_mode() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    let power_mode =
+        |driver: &DoIp| driver.module_ioctl(0, IoctlCommand::GetDiagnosticPowerMode, &entity_address(0x1000));
+    assert_eq!(power_mode(&driver), Ok(Some(IoctlData::Unum32(1))));
+    sim.set_config(SimConfig { power_mode: 0, ..SimConfig::default() });
+    assert_eq!(power_mode(&driver), Ok(Some(IoctlData::Unum32(0))));
+    let mut channel = connect(&driver, &[]).unwrap();
+    assert_eq!(
+        channel.ioctl(IoctlCommand::GetDiagnosticPowerMode, &IoctlInput::None),
+        Ok(Some(IoctlData::Unum32(0)))
+    );
+    // An entity which does not answer
+    assert_eq!(
+        driver.module_ioctl(0, IoctlCommand::GetDiagnosticPowerMode, &entity_address(0x2000)),
+        Err(PduError::DoIPResponseTimeout)
+    );
+}
+
+/// Sends a UDP request to the simulator, returning its response if it answers within 200 ms
+fn udp_request(sim: &DoIpSimulator, message: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    socket.send_to(message, sim.address()).unwrap();
+    let mut buf = [0; 1500];
+    let n = socket.recv(&mut buf).ok()?;
+    decode_message(&buf[..n]).unwrap().map(|(payload_type, payload)| (payload_type, payload.to_vec()))
+}
+
+#[test]
+fn simulator_vehicle_identification() {
+    let (sim, _) = start_sim(SimConfig::default());
+    let id = SimConfig::default().identification;
+    let announcement = Some((VEHICLE_ANNOUNCEMENT, id.to_payload()));
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST, &[])), announcement);
+    // Preselected requests are only answered by the entity with the VIN or EID
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_VIN, &id.vin)), announcement);
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_VIN, b"DPDUSIM0000000002")), None);
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_EID, &id.eid)), announcement);
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_EID, &[0x02, 0, 0, 0, 0, 0x02])), None);
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST, &[0x00])), None);
+
+    let other = identification(0x2000, b"DPDUSIM0000000002", [0x02, 0, 0, 0, 0, 0x02]);
+    sim.set_config(SimConfig { identification: other, ..SimConfig::default() });
+    let response = udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_VIN, b"DPDUSIM0000000002")).unwrap();
+    assert_eq!(VehicleIdentification::from_payload(&response.1), Some(other));
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_EID, &id.eid)), None);
+}
+
+#[test]
+fn simulator_entity_status_and_power_mode() {
+    let (sim, driver) = start_sim(SimConfig { node_type: 1, max_sockets: 2, ..SimConfig::default() });
+    let status = encode_message(ENTITY_STATUS_REQUEST, &[]);
+    assert_eq!(udp_request(&sim, &status), Some((ENTITY_STATUS_RESPONSE, vec![1, 2, 0, 0, 0, 0x10, 0x00])));
+    // Open connections are counted, and the maximum data size is left out if it is not set
+    let _channel = connect(&driver, &[]).unwrap();
+    sim.set_config(SimConfig { node_type: 1, max_sockets: 2, max_data_size: None, ..SimConfig::default() });
+    assert_eq!(udp_request(&sim, &status), Some((ENTITY_STATUS_RESPONSE, vec![1, 2, 1])));
+
+    let power_mode = encode_message(POWER_MODE_REQUEST, &[]);
+    assert_eq!(udp_request(&sim, &power_mode), Some((POWER_MODE_RESPONSE, vec![1])));
+    sim.set_config(SimConfig { power_mode: 2, ..SimConfig::default() });
+    assert_eq!(udp_request(&sim, &power_mode), Some((POWER_MODE_RESPONSE, vec![2])));
+
+    // Invalid headers, unknown payload types (0x01) and short datagrams (0x04) are answered
+    // with a generic header NACK
+    let nack = |code| Some((GENERIC_NACK, vec![code]));
+    assert_eq!(udp_request(&sim, &[0x02, 0x02, 0x40, 0x01, 0, 0, 0, 0]), nack(NACK_INCORRECT_PATTERN));
+    assert_eq!(udp_request(&sim, &encode_message(0x1234, &[])), nack(0x01));
+    assert_eq!(udp_request(&sim, &encode_message(VEHICLE_ID_REQUEST_VIN, b"DPDUSIM")[..12]), nack(0x04));
+}
+
+/// Reads one DoIP message from a stream
+fn read_message(stream: &mut TcpStream) -> (u16, Vec<u8>) {
+    let mut buf = vec![0; HEADER_LEN];
+    stream.read_exact(&mut buf).unwrap();
+    let len = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as usize;
+    buf.resize(HEADER_LEN + len, 0);
+    stream.read_exact(&mut buf[HEADER_LEN..]).unwrap();
+    let (payload_type, payload) = decode_message(&buf).unwrap().unwrap();
+    (payload_type, payload.to_vec())
+}
+
+/// Entity on loopback which activates routing for the tester, then runs `script` on the
+/// connection
+fn scripted_entity<T: Send + 'static>(
+    script: impl FnOnce(TcpStream) -> T + Send + 'static
+) -> (SocketAddr, JoinHandle<T>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let entity = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (payload_type, request) = read_message(&mut stream);
+        assert_eq!(payload_type, ROUTING_ACTIVATION_REQUEST);
+        let response = [&request[..2], &[0x10, 0x00, ROUTING_SUCCESS, 0, 0, 0, 0]].concat();
+        stream.write_all(&encode_message(ROUTING_ACTIVATION_RESPONSE, &response)).unwrap();
+        script(stream)
+    });
+    (address, entity)
+}
+
+#[test]
+fn alive_check_requests_are_answered() {
+    // Checks that the tester is alive and then sends a diagnostic message
+    let (address, entity) = scripted_entity(|mut stream| {
+        stream.write_all(&encode_message(ALIVE_CHECK_REQUEST, &[])).unwrap();
+        let alive = read_message(&mut stream);
+        stream.write_all(&encode_message(DIAGNOSTIC_MESSAGE, &[0x10, 0x00, 0x0E, 0x00, 0x7E, 0x00])).unwrap();
+        alive
+    });
+
+    let driver = DoIp::open("").unwrap();
+    let mut channel = DoIpChannel::connect(address, &params(&driver, PROTOCOL, &[]), Clock::new()).unwrap();
+    assert_eq!(channel.recv(Duration::from_secs(1)).unwrap().unwrap().data, [0x7E, 0x00]);
+    assert_eq!(entity.join().unwrap(), (ALIVE_CHECK_RESPONSE, vec![0x0E, 0x00]));
+}
+
+#[test]
+fn invalid_headers_close_the_connection() {
+    let (address, entity) = scripted_entity(|mut stream| {
+        // The inverse protocol version does not match
+        stream.write_all(&[0x02, 0x02, 0x80, 0x01, 0, 0, 0, 0]).unwrap();
+        assert_eq!(read_message(&mut stream), (GENERIC_NACK, vec![NACK_INCORRECT_PATTERN]));
+        assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+    });
+    let driver = DoIp::open("").unwrap();
+    let mut channel = DoIpChannel::connect(address, &params(&driver, PROTOCOL, &[]), Clock::new()).unwrap();
+    assert_eq!(channel.recv(Duration::from_secs(1)), Err(PduError::CommPcToVciFailed));
+    assert_eq!(channel.poll_error(), Some(PduErrorEvt::LostCommToVCI));
+    entity.join().unwrap();
+    assert_eq!(channel.recv(Duration::from_millis(100)), Err(PduError::CommPcToVciFailed));
+    assert_eq!(channel.send(&[0x3E, 0x00], &[], &params(&driver, PROTOCOL, &[])), Err(PduError::CommPcToVciFailed));
+}
@@ -0,0 +1,138 @@
+//! Tests of the ELM327 backend against the emulated adapter on a pseudo terminal
+
+use std::time::Duration;
+
+use dpdu_rust::{
+    backends::elm327::{sim::SimConfig, Elm327Channel, ElmProtocol},
+    provider::{Channel, Driver},
+    PduError, PduErrorEvt, Protocol, StdComParam
+};
+
+mod common;
+
+use common::{open_channel, params, start_sim};
+
+const OBD: Protocol = Protocol::IsoObdOnIso15765_4;
+
+/// Answers every request positively, repeating the request to fill `len` bytes
+fn ecu(len: usize) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static {
+    move |request: &[u8]| {
+        let mut response = vec![request[0].wrapping_add(0x40)];
+        response.extend(request[1..].iter().cycle().take(len - 1));
+        vec![response]
+    }
+}
+
+fn recv(channel: &mut Elm327Channel) -> Option<(Vec<u8>, Vec<u8>)> {
+    let result = channel.recv(Duration::from_millis(500)).unwrap()?;
+    Some((result.extra_info.unwrap().header, result.data))
+}
+
+#[test]
+fn identification() {
+    let cases = [
+        ("ELM327 v1.5", None, Ok((1, 5))),
+        ("ELM327 v2.1", Some("STN1110 v4.2.0"), Ok((2, 1))),
+        ("ELM327 v1.2", None, Err(PduError::ModuleFwOutOfDate)),
+        ("OBDII to RS232", None, Err(PduError::CableUnknown))
+    ];
+    for (identification, stn, expected) in cases {
+        let (_sim, driver) = start_sim(SimConfig { identification, stn, ..SimConfig::default() });
+        let connected = driver.connect_module(0).map(|_| driver.identification(0).unwrap());
+        assert_eq!(connected.as_ref().map(|i| i.version).map_err(|e| *e), expected, "{identification}");
+        if let Ok(connected) = connected {
+            assert_eq!(connected.stn.as_deref(), stn);
+        }
+    }
+}
+
+#[test]
+fn unknown_command_replies() {
+    // A command the adapter does not know fails the setup of the channel
+    let (_sim, driver) = start_sim(SimConfig { unsupported: &["ATAT"], ..SimConfig::default() });
+    let params = params(&driver, OBD, &[]);
+    assert_eq!(open_channel(&driver, OBD, &params).err(), Some(PduError::ValueNotSupported));
+
+    // A request it does not accept is a transmit error
+    let (sim, driver) = start_sim(SimConfig { unsupported: &["3E"], ..SimConfig::default() });
+    sim.add_ecu(0x7E0, ecu(2));
+    let mut channel = open_channel(&driver, OBD, &params).unwrap();
+    channel.send(&[0x3E, 0x00], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), None);
+    assert_eq!(channel.poll_error(), Some(PduErrorEvt::TxError));
+    channel.send(&[0x01, 0x00], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), Some((vec![0x00, 0x00, 0x07, 0xE8], vec![0x41, 0x00])));
+    assert_eq!(channel.poll_error(), None);
+}
+
+#[test]
+fn no_data_and_can_error_replies() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    let params = params(&driver, OBD, &[]);
+    let mut channel = open_channel(&driver, OBD, &params).unwrap();
+    // No ECU answers, which is not an error
+    channel.send(&[0x01, 0x00], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), None);
+    assert_eq!(channel.poll_error(), None);
+
+    // The vehicle uses 29 bit IDs, so the adapter sees errors on the bus
+    sim.add_ecu(0x7E0, ecu(2));
+    sim.set_config(SimConfig { protocol: ElmProtocol::Can29Bit500k, ..SimConfig::default() });
+    channel.send(&[0x01, 0x00], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), None);
+    assert_eq!(channel.poll_error(), Some(PduErrorEvt::TxError));
+}
+
+#[test]
+fn multi_frame_responses() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    sim.add_ecu(0x7E0, ecu(20));
+    sim.add_ecu(0x7E1, ecu(12));
+    let physical = params(&driver, OBD, &[]);
+    let mut channel = open_channel(&driver, OBD, &physical).unwrap();
+    channel.send(&[0x09, 0x02], &[], &physical).unwrap();
+    assert_eq!(recv(&mut channel), Some((vec![0x00, 0x00, 0x07, 0xE8], ecu(20)(&[0x09, 0x02]).remove(0))));
+
+    // Responses of several ECUs are reassembled by their ID
+    let functional = params(&driver, OBD, &[(StdComParam::RequestAddrMode, 2)]);
+    channel.send(&[0x09, 0x02], &[], &functional).unwrap();
+    let mut responses = vec![recv(&mut channel).unwrap(), recv(&mut channel).unwrap()];
+    responses.sort();
+    assert_eq!(
+        responses,
+        [
+            (vec![0x00, 0x00, 0x07, 0xE8], ecu(20)(&[0x09, 0x02]).remove(0)),
+            (vec![0x00, 0x00, 0x07, 0xE9], ecu(12)(&[0x09, 0x02]).remove(0))
+        ]
+    );
+    assert_eq!(channel.poll_error(), None);
+}
+
+#[test]
+fn multi_frame_responses_29bit() {
+    let (sim, driver) = start_sim(SimConfig { protocol: ElmProtocol::Can29Bit500k, ..SimConfig::default() });
+    sim.add_ecu(0x10, ecu(30));
+    let params = params(
+        &driver,
+        OBD,
+        &[(StdComParam::CanPhysReqId, 0x18DA10F1), (StdComParam::CanFuncReqId, 0x18DB33F1), (StdComParam::CanRespUsdtId, 0x18DAF110)]
+    );
+    let mut channel = open_channel(&driver, OBD, &params).unwrap();
+    assert_eq!(channel.protocol(), ElmProtocol::Can29Bit500k);
+    channel.send(&[0x22, 0xF1, 0x90], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), Some((vec![0x98, 0xDA, 0xF1, 0x10], ecu(30)(&[0x22, 0xF1, 0x90]).remove(0))));
+}
+
+#[test]
+fn start_comm_data() {
+    let (sim, driver) = start_sim(SimConfig { protocol: ElmProtocol::Iso14230Fast, ..SimConfig::default() });
+    sim.add_ecu(0x10, ecu(2));
+    let protocol = Protocol::Iso14230_3OnIso14230_2;
+    let params = params(&driver, protocol, &[]);
+    let mut channel = open_channel(&driver, protocol, &params).unwrap();
+    // The adapter sends StartCommunication itself
+    assert_eq!(channel.start_comm(&[0x81], &params), Err(PduError::InvalidParameters));
+    assert!(!sim.is_initialized());
+    assert_eq!(channel.start_comm(&[], &params), Ok(Some(vec![0xE9, 0x8F])));
+    assert!(sim.is_initialized());
+}
@@ -0,0 +1,140 @@
+//! Tests of the event queues of modules and ComLogicalLinks
+
+use std::{cell::RefCell, ffi::c_void};
+
+use dpdu_rust::{
+    provider::{Event, EventData, EventQueue, EventSource, PduTag, DEFAULT_EVENT_QUEUE_SIZE},
+    IoEventQueuePropertyData, PduError, PduEvtData, PduQueueMode, PduStatus
+};
+
+thread_local! {
+    /// Notifications of the callback, which is called on the thread pushing the event
+    static NOTIFIED: RefCell<Vec<PduEvtData>> = const { RefCell::new(Vec::new()) };
+}
+
+extern "C" fn callback(event_type: PduEvtData, _h_mod: u32, _h_cll: u32, _cll_tag: *mut c_void, _api_tag: *mut c_void) {
+    NOTIFIED.with(|n| n.borrow_mut().push(event_type));
+}
+
+/// Returns and forgets the notifications of the callback
+fn notified() -> Vec<PduEvtData> {
+    NOTIFIED.with(|n| n.take())
+}
+
+/// Creates a queue with the callback registered, in the given mode
+fn queue(queue_mode: PduQueueMode, queue_size: u32) -> EventQueue {
+    let events = EventQueue::new(EventSource::default());
+    events.set_callback(Some(callback));
+    events.set_property(IoEventQueuePropertyData { queue_size, queue_mode }).unwrap();
+    notified();
+    events
+}
+
+/// Event which is told apart by its timestamp
+fn event(timestamp: u32) -> Event {
+    Event { h_cop: None, cop_tag: PduTag::NULL, timestamp, data: EventData::Status(PduStatus::CopstIdle) }
+}
+
+fn push(events: &EventQueue, timestamps: std::ops::Range<u32>) {
+    timestamps.for_each(|t| events.push(event(t)));
+}
+
+fn drain(events: &EventQueue) -> Vec<u32> {
+    std::iter::from_fn(|| events.pop().ok()).map(|e| e.timestamp).collect()
+}
+
+#[test]
+fn unlimited_mode() {
+    let events = EventQueue::new(EventSource::default());
+    events.set_callback(Some(callback));
+    assert_eq!(events.property().queue_mode, PduQueueMode::Unlimited);
+    assert_eq!(events.pop(), Err(PduError::EventQueueEmpty));
+
+    // The queue size does not limit an unlimited queue
+    push(&events, 0..DEFAULT_EVENT_QUEUE_SIZE * 2);
+    assert_eq!(events.len(), DEFAULT_EVENT_QUEUE_SIZE as usize * 2);
+    assert!(!events.has_lost_events());
+    assert_eq!(notified(), [PduEvtData::Available]);
+    assert_eq!(drain(&events), (0..DEFAULT_EVENT_QUEUE_SIZE * 2).collect::<Vec<_>>());
+
+    // An unlimited queue can be switched to without a size
+    let events = queue(PduQueueMode::Unlimited, 0);
+    push(&events, 0..3);
+    assert_eq!(events.len(), 3);
+}
+
+#[test]
+fn limited_mode() {
+    let events = queue(PduQueueMode::Limited, 3);
+    push(&events, 0..5);
+    // New events are discarded once the queue is full, and the overrun is only notified once
+    assert_eq!(events.len(), 3);
+    assert!(events.has_lost_events());
+    assert_eq!(notified(), [PduEvtData::Available, PduEvtData::Lost]);
+    assert_eq!(drain(&events), [0, 1, 2]);
+    assert!(!events.has_lost_events());
+
+    // Draining the queue resets the overrun
+    push(&events, 5..9);
+    assert_eq!(notified(), [PduEvtData::Available, PduEvtData::Lost]);
+    assert_eq!(drain(&events), [5, 6, 7]);
+
+    // Shrinking discards the newest events
+    push(&events, 0..3);
+    notified();
+    events.set_property(IoEventQueuePropertyData { queue_size: 1, queue_mode: PduQueueMode::Limited }).unwrap();
+    assert_eq!(notified(), [PduEvtData::Lost]);
+    assert_eq!(drain(&events), [0]);
+}
+
+#[test]
+fn circular_mode() {
+    let events = queue(PduQueueMode::Circular, 3);
+    push(&events, 0..5);
+    // The oldest events are overwritten once the queue is full
+    assert_eq!(events.len(), 3);
+    assert!(events.has_lost_events());
+    assert_eq!(notified(), [PduEvtData::Available, PduEvtData::Lost]);
+    assert_eq!(drain(&events), [2, 3, 4]);
+    assert!(!events.has_lost_events());
+
+    // Clearing the queue also resets the overrun
+    push(&events, 0..4);
+    events.clear();
+    assert!(events.is_empty());
+    assert!(!events.has_lost_events());
+    push(&events, 0..4);
+    assert_eq!(notified(), [PduEvtData::Available, PduEvtData::Lost, PduEvtData::Available, PduEvtData::Lost]);
+
+    // Shrinking discards the oldest events
+    events.set_property(IoEventQueuePropertyData { queue_size: 2, queue_mode: PduQueueMode::Circular }).unwrap();
+    // The overrun was not reset, so it is not notified again
+    assert!(notified().is_empty());
+    assert_eq!(drain(&events), [2, 3]);
+}
+
+#[test]
+fn queue_properties() {
+    let events = queue(PduQueueMode::Limited, 3);
+    for queue_mode in [PduQueueMode::Limited, PduQueueMode::Circular] {
+        let property = IoEventQueuePropertyData { queue_size: 0, queue_mode };
+        assert_eq!(events.set_property(property), Err(PduError::InvalidParameters));
+    }
+    assert_eq!(events.property(), IoEventQueuePropertyData { queue_size: 3, queue_mode: PduQueueMode::Limited });
+
+    // Growing the queue keeps every event
+    push(&events, 0..3);
+    events.set_property(IoEventQueuePropertyData { queue_size: 5, queue_mode: PduQueueMode::Circular }).unwrap();
+    assert!(!events.has_lost_events());
+    push(&events, 3..5);
+    assert_eq!(drain(&events), [0, 1, 2, 3, 4]);
+
+    // Without a callback, events are still queued and lost
+    events.set_callback(None);
+    assert!(!events.has_callback());
+    notified();
+    push(&events, 0..6);
+    assert!(notified().is_empty());
+    assert!(events.has_lost_events());
+    assert_eq!(drain(&events), [1, 2, 3, 4, 5]);
+}
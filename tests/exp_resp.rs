@@ -0,0 +1,102 @@
+//! Tests of matching responses against expected responses
+
+use dpdu_rust::{ExpectedResponse, PduError, Protocol, ResponseMatch, ResponseMatcher, ResponseType};
+
+const UDS: Protocol = Protocol::Iso15765_3OnIso15765_2;
+
+/// Expects positive and negative responses to service 0x22
+fn read_data_matcher() -> ResponseMatcher {
+    ResponseMatcher::new(vec![
+        ExpectedResponse::new(ResponseType::Positive, 1, vec![0xFF], vec![0x62]).unwrap(),
+        ExpectedResponse::new(ResponseType::Negative, 2, vec![0xFF, 0xFF], vec![0x7F, 0x22]).unwrap(),
+    ])
+}
+
+fn matched(acceptance_id: u32, response_type: ResponseType, counts_as_receive: bool) -> Option<ResponseMatch> {
+    Some(ResponseMatch { acceptance_id, response_type, counts_as_receive })
+}
+
+#[test]
+fn mask_and_pattern() {
+    assert_eq!(
+        ExpectedResponse::new(ResponseType::Positive, 1, vec![0xFF], vec![0x62, 0xF1]),
+        Err(PduError::InvalidParameters)
+    );
+    let response = ExpectedResponse::new(ResponseType::Positive, 1, vec![0xFF, 0xF0], vec![0x62, 0xF1]).unwrap();
+    assert!(response.matches_data(&[0x62, 0xF1, 0x90]));
+    // Only the masked bits are compared
+    assert!(response.matches_data(&[0x62, 0xFF]));
+    assert!(!response.matches_data(&[0x62, 0x01]));
+    assert!(!response.matches_data(&[0x63, 0xF1]));
+    // Responses shorter than the mask never match
+    assert!(!response.matches_data(&[0x62]));
+    assert!(ExpectedResponse::any(1).matches_data(&[]));
+}
+
+#[test]
+fn negative_responses() {
+    let matcher = read_data_matcher();
+    assert_eq!(matcher.match_response(&[0x62, 0xF1, 0x90, 0x01], 0, UDS), matched(1, ResponseType::Positive, true));
+    // A negative response answers the request, so it counts as a receive
+    assert_eq!(matcher.match_response(&[0x7F, 0x22, 0x31], 0, UDS), matched(2, ResponseType::Negative, true));
+    // Responses to other services belong to another ComPrimitive
+    assert_eq!(matcher.match_response(&[0x7F, 0x2E, 0x31], 0, UDS), None);
+    assert_eq!(matcher.match_response(&[0x6E, 0xF1, 0x90], 0, UDS), None);
+    assert_eq!(matcher.match_response(&[], 0, UDS), None);
+}
+
+#[test]
+fn response_pending() {
+    let matcher = read_data_matcher();
+    for protocol in [
+        UDS,
+        Protocol::Iso14230_3OnIso15765_2,
+        Protocol::Iso14230_3OnIso14230_2,
+        Protocol::Iso14229_5OnIso13400_2,
+        Protocol::IsoObdOnIso15765_4
+    ] {
+        assert!(protocol.has_response_pending(), "{protocol}");
+        let m = matcher.match_response(&[0x7F, 0x22, 0x78], 0, protocol);
+        assert_eq!(m, matched(2, ResponseType::Negative, false), "{protocol}");
+    }
+    // Messages of raw CAN and J1939 start with an ID, so they are never a response pending
+    for protocol in [Protocol::Iso11898Raw, Protocol::SaeJ1939_73OnSaeJ1939_21] {
+        assert!(!protocol.has_response_pending(), "{protocol}");
+        let m = matcher.match_response(&[0x7F, 0x22, 0x78], 0, protocol);
+        assert_eq!(m, matched(2, ResponseType::Negative, true), "{protocol}");
+    }
+
+    // A positive expected response which matches a response pending counts as a receive
+    let any = ResponseMatcher::new(vec![ExpectedResponse::any(3)]);
+    assert_eq!(any.match_response(&[0x7F, 0x22, 0x78], 0, UDS), matched(3, ResponseType::Positive, true));
+}
+
+#[test]
+fn acceptance_id_routing() {
+    // Without expected responses, every response is accepted as positive
+    let empty = ResponseMatcher::default();
+    assert!(empty.is_empty());
+    assert_eq!(empty.match_response(&[0x7F, 0x22, 0x78], 5, UDS), matched(0, ResponseType::Positive, true));
+
+    // Positive responses from some ECUs
+    let from_ecus = |acceptance_id, ids| {
+        let response = ExpectedResponse::new(ResponseType::Positive, acceptance_id, vec![0xFF], vec![0x62]).unwrap();
+        response.with_unique_resp_ids(ids)
+    };
+    let matcher = ResponseMatcher::new(vec![
+        from_ecus(10, vec![1]),
+        from_ecus(20, vec![2, 3]),
+        ExpectedResponse::any(30)
+    ]);
+    assert_eq!(matcher.expected().len(), 3);
+    assert_eq!(matcher.match_response(&[0x62], 1, UDS), matched(10, ResponseType::Positive, true));
+    assert_eq!(matcher.match_response(&[0x62], 2, UDS), matched(20, ResponseType::Positive, true));
+    assert_eq!(matcher.match_response(&[0x62], 3, UDS), matched(20, ResponseType::Positive, true));
+    // Responses of other ECUs, and other responses, fall through to the catch all
+    assert_eq!(matcher.match_response(&[0x62], 4, UDS), matched(30, ResponseType::Positive, true));
+    assert_eq!(matcher.match_response(&[0x7F, 0x22, 0x31], 1, UDS), matched(30, ResponseType::Positive, true));
+
+    // The first expected response which matches wins
+    let overlapping = ResponseMatcher::new(vec![ExpectedResponse::any(1), ExpectedResponse::any(2)]);
+    assert_eq!(overlapping.match_response(&[0x62], 0, UDS), matched(1, ResponseType::Positive, true));
+}
@@ -0,0 +1,224 @@
+//! Tests of the functions exported by `export_pdu_api!`, over a stub backend
+
+use std::{
+    ffi::CString,
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard
+    }
+};
+
+use dpdu_rust::{
+    provider::{PduBackend, PduTag, StatusInfo},
+    CllHandle, CopCtrlData, CopHandle, EventCallbackFn, EventItem, FlagData, ModuleHandle, ModuleItem, ObjectId,
+    ParamItem, PduCopt, PduDataItem, PduError, PduItem, PduObjt, PduStatus, ResourceId, RscConflictItem, RscData,
+    RscIdItem, RscStatusItem, VersionData
+};
+
+/// Number of calls which reached the backend
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Backend which accepts every call, and panics when a module is connected
+struct StubBackend;
+
+impl StubBackend {
+    fn call<T>(&self, value: T) -> Result<T, PduError> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(value)
+    }
+}
+
+impl PduBackend for StubBackend {
+    fn construct(options: &str, _api_tag: PduTag) -> Result<Self, PduError> {
+        match options {
+            "fail" => Err(PduError::ValueNotSupported),
+            _ => Ok(Self)
+        }
+    }
+
+    fn get_module_ids(&self) -> Result<*mut ModuleItem, PduError> {
+        self.call(ptr::null_mut())
+    }
+
+    fn module_connect(&self, _h_mod: Option<ModuleHandle>) -> Result<(), PduError> {
+        panic!("module can not be connected")
+    }
+
+    fn module_disconnect(&self, _h_mod: Option<ModuleHandle>) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn get_version(&self, _h_mod: ModuleHandle) -> Result<VersionData, PduError> {
+        Err(PduError::FctFailed)
+    }
+
+    fn get_timestamp(&self, _h_mod: ModuleHandle) -> Result<u32, PduError> {
+        self.call(1234)
+    }
+
+    fn get_status(&self, _h_mod: ModuleHandle, _h_cll: Option<CllHandle>, _h_cop: Option<CopHandle>) -> Result<StatusInfo, PduError> {
+        self.call(StatusInfo { status: PduStatus::ModstReady, timestamp: 0, extra_info: 0 })
+    }
+
+    fn ioctl(
+        &self,
+        _h_mod: Option<ModuleHandle>,
+        _h_cll: Option<CllHandle>,
+        _ioctl_id: ObjectId,
+        _input: Option<&PduDataItem>
+    ) -> Result<*mut PduDataItem, PduError> {
+        self.call(ptr::null_mut())
+    }
+
+    fn get_object_id(&self, _object_type: PduObjt, _short_name: &str) -> Result<Option<ObjectId>, PduError> {
+        self.call(None)
+    }
+
+    fn get_resource_ids(&self, _h_mod: Option<ModuleHandle>, _rsc_data: &RscData) -> Result<*mut RscIdItem, PduError> {
+        self.call(ptr::null_mut())
+    }
+
+    fn get_resource_status(&self, _items: &mut [RscStatusItem]) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn get_conflicting_resources(&self, _resource_id: ResourceId, _modules: &[ModuleHandle]) -> Result<*mut RscConflictItem, PduError> {
+        self.call(ptr::null_mut())
+    }
+
+    fn create_com_logical_link(
+        &self,
+        _h_mod: ModuleHandle,
+        _rsc_data: Option<&RscData>,
+        _resource_id: Option<ResourceId>,
+        _cll_tag: PduTag,
+        _create_flags: Option<&FlagData>
+    ) -> Result<CllHandle, PduError> {
+        self.call(CllHandle::new(2).unwrap())
+    }
+
+    fn destroy_com_logical_link(&self, _h_mod: ModuleHandle, _h_cll: CllHandle) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn connect(&self, _h_mod: ModuleHandle, _h_cll: CllHandle) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn disconnect(&self, _h_mod: ModuleHandle, _h_cll: CllHandle) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn get_com_param(&self, _h_mod: ModuleHandle, _h_cll: CllHandle, _param_id: ObjectId) -> Result<*mut ParamItem, PduError> {
+        self.call(ptr::null_mut())
+    }
+
+    fn set_com_param(&self, _h_mod: ModuleHandle, _h_cll: CllHandle, _param: &ParamItem) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn start_com_primitive(
+        &self,
+        _h_mod: ModuleHandle,
+        _h_cll: CllHandle,
+        _cop_type: PduCopt,
+        _data: &[u8],
+        _ctrl: Option<&CopCtrlData>,
+        _cop_tag: PduTag
+    ) -> Result<CopHandle, PduError> {
+        self.call(CopHandle::new(3).unwrap())
+    }
+
+    fn cancel_com_primitive(&self, _h_mod: ModuleHandle, _h_cll: CllHandle, _h_cop: CopHandle) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn get_event_item(&self, _h_mod: Option<ModuleHandle>, _h_cll: Option<CllHandle>) -> Result<*mut EventItem, PduError> {
+        Err(PduError::EventQueueEmpty)
+    }
+
+    fn register_event_callback(
+        &self,
+        _h_mod: Option<ModuleHandle>,
+        _h_cll: Option<CllHandle>,
+        _callback: Option<EventCallbackFn>
+    ) -> Result<(), PduError> {
+        self.call(())
+    }
+
+    fn destroy_item(&self, _item: *mut PduItem) -> Result<(), PduError> {
+        self.call(())
+    }
+}
+
+dpdu_rust::export_pdu_api!(StubBackend);
+
+/// Constructs the API. The exported functions share one provider, so the tests run one at a time
+fn constructed() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = PDUDestruct();
+    assert_eq!(PDUConstruct(ptr::null_mut(), ptr::null_mut()), PduError::StatusNoError);
+    guard
+}
+
+#[test]
+fn construct_and_destruct() {
+    let _guard = constructed();
+    assert_eq!(PDUConstruct(ptr::null_mut(), ptr::null_mut()), PduError::SharingViolation);
+    assert_eq!(PDUDestruct(), PduError::StatusNoError);
+    assert_eq!(PDUDestruct(), PduError::PduApiNotConstructed);
+    assert_eq!(PDUModuleDisconnect(1), PduError::PduApiNotConstructed);
+
+    let fail = CString::new("fail").unwrap();
+    assert_eq!(PDUConstruct(fail.as_ptr().cast_mut().cast(), ptr::null_mut()), PduError::ValueNotSupported);
+    assert_eq!(PDUModuleDisconnect(1), PduError::PduApiNotConstructed);
+}
+
+#[test]
+fn null_pointers_are_invalid_parameters() {
+    let _guard = constructed();
+    let calls = CALLS.load(Ordering::SeqCst);
+    let results = [
+        PDUGetModuleIds(ptr::null_mut()),
+        PDUGetTimestamp(1, ptr::null_mut()),
+        PDUGetVersion(1, ptr::null_mut()),
+        PDUGetStatus(1, 0, 0, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()),
+        PDUGetLastError(1, 0, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut()),
+        PDUGetResourceStatus(ptr::null_mut()),
+        PDUCreateComLogicalLink(1, ptr::null_mut(), 1, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()),
+        PDUGetComParam(1, 2, 1, ptr::null_mut()),
+        PDUSetComParam(1, 2, ptr::null_mut()),
+        PDUStartComPrimitive(1, 2, PduCopt::SendRecv, 0, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut()),
+        PDUStartComPrimitive(1, 2, PduCopt::SendRecv, 2, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut 0),
+        PDUGetEventItem(1, 2, ptr::null_mut()),
+        PDUDestroyItem(ptr::null_mut()),
+        PDUGetObjectId(PduObjt::Protocol, ptr::null_mut(), &mut 0),
+        PDUGetObjectId(PduObjt::Protocol, c"ISO_11898_RAW".as_ptr().cast_mut().cast(), ptr::null_mut()),
+        PDUGetResourceIds(1, ptr::null_mut(), ptr::null_mut()),
+        PDUGetConflictingResources(1, ptr::null_mut(), ptr::null_mut()),
+        PDUGetUniqueRespIdTable(1, 2, ptr::null_mut()),
+        PDUSetUniqueRespIdTable(1, 2, ptr::null_mut())
+    ];
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result, PduError::InvalidParameters, "call {i}");
+    }
+    // The backend never sees the null pointers
+    assert_eq!(CALLS.load(Ordering::SeqCst), calls);
+
+    let mut timestamp = 0;
+    assert_eq!(PDUGetTimestamp(1, &mut timestamp), PduError::StatusNoError);
+    assert_eq!(timestamp, 1234);
+    assert_eq!(CALLS.load(Ordering::SeqCst), calls + 1);
+}
+
+#[test]
+fn panics_are_caught() {
+    let _guard = constructed();
+    assert_eq!(PDUModuleConnect(1), PduError::FctFailed);
+    // The API keeps working after the panic
+    assert_eq!(PDUModuleDisconnect(1), PduError::StatusNoError);
+    assert_eq!(PDUModuleConnect(1), PduError::FctFailed);
+    assert_eq!(PDUDestruct(), PduError::StatusNoError);
+}
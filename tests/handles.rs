@@ -0,0 +1,46 @@
+//! Tests of the handle registry of providers
+
+use dpdu_rust::{provider::HandleRegistry, PduError};
+
+type Registry = HandleRegistry<&'static str, &'static str, &'static str>;
+
+#[test]
+fn objects_are_created_without_the_registry_locked() {
+    let registry = Registry::new();
+    // The closures look up the registry, which would deadlock if it was locked
+    let (h_mod, _) = registry.add_module_with(|_| {
+        assert!(registry.modules().is_empty());
+        "module"
+    });
+    let (h_cll, link) = registry
+        .add_link_with(h_mod, |_| {
+            registry.module(h_mod)?;
+            Ok("link")
+        })
+        .unwrap();
+    assert_eq!(*link, "link");
+    assert_eq!(registry.link(h_mod, h_cll).map(|l| *l), Ok("link"));
+
+    // Nothing is registered if creating the link fails, or the module is removed meanwhile
+    assert_eq!(registry.add_link_with(h_mod, |_| Err(PduError::FctFailed)).err(), Some(PduError::FctFailed));
+    let removed = registry.add_link_with(h_mod, |_| {
+        registry.remove_module(h_mod)?;
+        Ok("link")
+    });
+    assert_eq!(removed.err(), Some(PduError::InvalidHandle));
+    assert!(registry.modules().is_empty());
+    assert_eq!(registry.link(h_mod, h_cll).err(), Some(PduError::InvalidHandle));
+}
+
+#[test]
+fn remove_module_cascades() {
+    let registry = Registry::new();
+    let h_mod = registry.add_module("module");
+    let h_cll = registry.add_link(h_mod, "link").unwrap();
+    let h_cop = registry.add_cop(h_mod, h_cll, "cop").unwrap();
+    let removed = registry.remove_module(h_mod).unwrap();
+    assert_eq!(removed.modules.iter().map(|(h, _)| *h).collect::<Vec<_>>(), [h_mod]);
+    assert_eq!(removed.links.iter().map(|(_, h, _)| *h).collect::<Vec<_>>(), [h_cll]);
+    assert_eq!(removed.cops.iter().map(|(.., h, _)| *h).collect::<Vec<_>>(), [h_cop]);
+    assert_eq!(registry.remove_module(h_mod).err(), Some(PduError::InvalidHandle));
+}
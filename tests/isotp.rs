@@ -0,0 +1,329 @@
+//! Tests of the ISO 15765-2 engine, driven frame by frame
+
+use std::time::{Duration, Instant};
+
+use dpdu_rust::{
+    provider::{CanFrame, IsoTp, IsoTpConfig, IsoTpEvent, LinkParams},
+    PduErrorEvt, StdComParam
+};
+
+const TESTER: IsoTpConfig = IsoTpConfig {
+    tx_id: 0x7E0,
+    rx_id: 0x7E8,
+    func_id: 0x7DF,
+    tx_ext_addr: None,
+    rx_ext_addr: None,
+    func_ext_addr: None,
+    padding: None,
+    tx_dl: 8,
+    brs: false,
+    block_size: 0,
+    st_min: Duration::ZERO,
+    block_size_override: None,
+    st_min_override: None,
+    n_as: Duration::from_millis(50),
+    n_bs: Duration::from_millis(100),
+    n_cr: Duration::from_millis(150),
+    max_wait_frames: 2,
+    max_rx_len: 0x1000
+};
+
+fn ecu_config() -> IsoTpConfig {
+    IsoTpConfig { tx_id: TESTER.rx_id, rx_id: TESTER.tx_id, ..TESTER }
+}
+
+fn frame(id: u32, data: &[u8]) -> CanFrame {
+    CanFrame { id, data: data.to_vec(), ..Default::default() }
+}
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// Returns the next frame to transmit, confirming it straight away
+fn transmit(engine: &mut IsoTp, now: Instant) -> Option<CanFrame> {
+    let frame = engine.poll_transmit(now)?;
+    engine.confirm_transmit(now);
+    Some(frame)
+}
+
+/// Passes frames between both engines until neither has a frame due
+fn exchange(a: &mut IsoTp, b: &mut IsoTp, now: Instant) -> usize {
+    let mut frames = 0;
+    loop {
+        let mut idle = true;
+        while let Some(frame) = transmit(a, now) {
+            b.on_frame(&frame, now);
+            frames += 1;
+            idle = false;
+        }
+        while let Some(frame) = transmit(b, now) {
+            a.on_frame(&frame, now);
+            frames += 1;
+            idle = false;
+        }
+        if idle {
+            return frames;
+        }
+    }
+}
+
+/// Starts sending a 20 byte message, returning the engine once its first frame was sent
+fn sending_first_frame(config: IsoTpConfig, now: Instant) -> IsoTp {
+    let mut tester = IsoTp::new(config);
+    tester.send(&[0x11; 20], false, now).unwrap();
+    let first = transmit(&mut tester, now).unwrap();
+    assert_eq!(first.data[..2], [0x10, 20]);
+    tester
+}
+
+#[test]
+fn loopback_message() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(TESTER);
+    let mut ecu = IsoTp::new(ecu_config());
+    let request: Vec<u8> = (0..=255).collect();
+    tester.send(&request, false, now).unwrap();
+    // First frame, flow control and 36 consecutive frames
+    assert_eq!(exchange(&mut tester, &mut ecu, now), 38);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::Sent));
+    assert!(matches!(ecu.poll_event(now), Some(IsoTpEvent::Received { data, start, .. }) if data == request && start == now));
+    assert!(tester.is_idle() && ecu.is_idle());
+}
+
+#[test]
+fn flow_control_timeout() {
+    let now = Instant::now();
+    let mut tester = sending_first_frame(TESTER, now);
+    assert_eq!(tester.next_wake(), Some(now + TESTER.n_bs));
+    assert_eq!(tester.poll_event(now + TESTER.n_bs - ms(1)), None);
+    assert_eq!(tester.poll_event(now + TESTER.n_bs), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::RxTimeout)));
+    assert!(!tester.is_sending());
+}
+
+#[test]
+fn consecutive_frame_timeout() {
+    let now = Instant::now();
+    let mut ecu = IsoTp::new(ecu_config());
+    ecu.on_frame(&frame(0x7E0, &[0x10, 20, 0, 1, 2, 3, 4, 5]), now);
+    assert_eq!(transmit(&mut ecu, now).unwrap().data, [0x30, 0, 0]);
+    ecu.on_frame(&frame(0x7E0, &[0x21, 6, 7, 8, 9, 10, 11, 12]), now + ms(100));
+    // Every consecutive frame restarts N_Cr
+    assert_eq!(ecu.poll_event(now + ms(200)), None);
+    assert_eq!(ecu.next_wake(), Some(now + ms(100) + TESTER.n_cr));
+    assert_eq!(ecu.poll_event(now + ms(250)), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::RxTimeout)));
+    assert!(ecu.is_idle());
+}
+
+#[test]
+fn wrong_sequence_number() {
+    let now = Instant::now();
+    let mut ecu = IsoTp::new(ecu_config());
+    ecu.on_frame(&frame(0x7E0, &[0x10, 20, 0, 1, 2, 3, 4, 5]), now);
+    ecu.on_frame(&frame(0x7E0, &[0x22, 6, 7, 8, 9, 10, 11, 12]), now);
+    assert_eq!(ecu.poll_event(now), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct)));
+    // Later consecutive frames of the aborted message are ignored
+    ecu.on_frame(&frame(0x7E0, &[0x23, 13, 14, 15, 16, 17, 18, 19]), now);
+    assert_eq!(ecu.poll_event(now), None);
+}
+
+#[test]
+fn flow_control_overflow_and_invalid_status() {
+    let now = Instant::now();
+    let mut tester = sending_first_frame(TESTER, now);
+    tester.on_frame(&frame(0x7E8, &[0x32, 0, 0]), now);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::TxError)));
+
+    let mut tester = sending_first_frame(TESTER, now);
+    tester.on_frame(&frame(0x7E8, &[0x35, 0, 0]), now);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::FrameStruct)));
+}
+
+#[test]
+fn oversized_messages_are_refused() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(TESTER);
+    let mut ecu = IsoTp::new(ecu_config());
+    tester.send(&[0x22; 0x1001], false, now).unwrap();
+    let first = transmit(&mut tester, now).unwrap();
+    assert_eq!(first.data[..6], [0x10, 0, 0, 0, 0x10, 0x01]);
+    ecu.on_frame(&first, now);
+    assert_eq!(ecu.poll_event(now), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::RxError)));
+    let overflow = transmit(&mut ecu, now).unwrap();
+    assert_eq!(overflow.data, [0x32, 0, 0]);
+    tester.on_frame(&overflow, now);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::TxError)));
+
+    // Later consecutive frames are ignored, and messages of the largest length are received
+    ecu.on_frame(&frame(0x7E0, &[0x21, 1, 2, 3, 4, 5, 6, 7]), now);
+    assert_eq!(ecu.poll_event(now), None);
+    tester.send(&[0x22; 0x1000], false, now).unwrap();
+    exchange(&mut tester, &mut ecu, now);
+    assert!(matches!(ecu.poll_event(now), Some(IsoTpEvent::Received { data, .. }) if data.len() == 0x1000));
+}
+
+#[test]
+fn wait_frames_are_limited() {
+    let now = Instant::now();
+    let mut tester = sending_first_frame(TESTER, now);
+    // Each wait restarts N_Bs
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now + ms(90));
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now + ms(180));
+    assert_eq!(tester.poll_event(now + ms(270)), None);
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now + ms(270));
+    assert_eq!(tester.poll_event(now + ms(270)), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::RxTimeout)));
+
+    // A clear to send resets the count
+    let mut tester = sending_first_frame(TESTER, now);
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now);
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now);
+    tester.on_frame(&frame(0x7E8, &[0x30, 1, 0]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x21);
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now);
+    tester.on_frame(&frame(0x7E8, &[0x31, 0, 0]), now);
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 0]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x22);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::Sent));
+}
+
+#[test]
+fn transmit_confirmation_timeout() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(TESTER);
+    tester.send(&[0x11; 20], false, now).unwrap();
+    assert!(tester.poll_transmit(now).is_some());
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 0]), now);
+    // The next frame waits for the confirmation of the first frame
+    assert_eq!(tester.poll_transmit(now), None);
+    assert_eq!(tester.next_wake(), Some(now + TESTER.n_as));
+    assert_eq!(tester.poll_event(now + TESTER.n_as), Some(IsoTpEvent::TransmitFailed(PduErrorEvt::TxError)));
+
+    // The message is only sent once its last frame is confirmed
+    let mut tester = IsoTp::new(TESTER);
+    tester.send(&[0x3E, 0x00], false, now).unwrap();
+    assert_eq!(tester.poll_transmit(now).unwrap().data, [0x02, 0x3E, 0x00]);
+    assert_eq!(tester.poll_event(now), None);
+    tester.confirm_transmit(now + ms(10));
+    assert_eq!(tester.poll_event(now + ms(10)), Some(IsoTpEvent::Sent));
+}
+
+#[test]
+fn block_size() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(TESTER);
+    tester.send(&[0x11; 30], false, now).unwrap();
+    transmit(&mut tester, now).unwrap();
+    tester.on_frame(&frame(0x7E8, &[0x30, 2, 0]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x21);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x22);
+    // The block is complete, the sender waits for the next flow control frame
+    assert_eq!(transmit(&mut tester, now), None);
+    assert_eq!(tester.next_wake(), Some(now + TESTER.n_bs));
+    tester.on_frame(&frame(0x7E8, &[0x30, 2, 0]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x23);
+    assert_eq!(transmit(&mut tester, now).unwrap().data, [0x24, 0x11, 0x11, 0x11]);
+    assert_eq!(tester.poll_event(now), Some(IsoTpEvent::Sent));
+
+    // The receiver sends a flow control frame after every block
+    let mut ecu = IsoTp::new(IsoTpConfig { block_size: 2, ..ecu_config() });
+    ecu.on_frame(&frame(0x7E0, &[0x10, 30, 0, 1, 2, 3, 4, 5]), now);
+    assert_eq!(transmit(&mut ecu, now).unwrap().data, [0x30, 2, 0]);
+    ecu.on_frame(&frame(0x7E0, &[0x21, 6, 7, 8, 9, 10, 11, 12]), now);
+    assert_eq!(transmit(&mut ecu, now), None);
+    ecu.on_frame(&frame(0x7E0, &[0x22, 13, 14, 15, 16, 17, 18, 19]), now);
+    assert_eq!(transmit(&mut ecu, now).unwrap().data, [0x30, 2, 0]);
+
+    // The override replaces the block size of the receiver
+    let mut tester = sending_first_frame(IsoTpConfig { block_size_override: Some(0), ..TESTER }, now);
+    tester.on_frame(&frame(0x7E8, &[0x30, 1, 0]), now);
+    assert_eq!(std::iter::from_fn(|| transmit(&mut tester, now)).count(), 2);
+}
+
+#[test]
+fn separation_time() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(TESTER);
+    tester.send(&[0x11; 30], false, now).unwrap();
+    transmit(&mut tester, now).unwrap();
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 10]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data[0], 0x21);
+    assert_eq!(transmit(&mut tester, now + ms(9)), None);
+    assert_eq!(tester.next_wake(), Some(now + ms(10)));
+    assert_eq!(transmit(&mut tester, now + ms(10)).unwrap().data[0], 0x22);
+
+    // 0xF5 is 500us
+    let mut tester = sending_first_frame(TESTER, now);
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 0xF5]), now);
+    transmit(&mut tester, now).unwrap();
+    assert_eq!(tester.next_wake(), Some(now + Duration::from_micros(500)));
+
+    // The override replaces the separation time of the receiver
+    let mut tester = sending_first_frame(IsoTpConfig { st_min_override: Some(ms(2)), ..TESTER }, now);
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 50]), now);
+    transmit(&mut tester, now).unwrap();
+    assert_eq!(tester.next_wake(), Some(now + ms(2)));
+
+    // The receiver reports its separation time
+    let mut ecu = IsoTp::new(IsoTpConfig { st_min: Duration::from_micros(300), ..ecu_config() });
+    ecu.on_frame(&frame(0x7E0, &[0x10, 20, 0, 1, 2, 3, 4, 5]), now);
+    assert_eq!(transmit(&mut ecu, now).unwrap().data, [0x30, 0, 0xF3]);
+}
+
+#[test]
+fn padding() {
+    let now = Instant::now();
+    let mut tester = IsoTp::new(IsoTpConfig { padding: Some(0xAA), ..TESTER });
+    tester.send(&[0x10, 0x03], false, now).unwrap();
+    assert_eq!(transmit(&mut tester, now).unwrap().data, [0x02, 0x10, 0x03, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+    tester.send(&[0x11; 10], false, now).unwrap();
+    transmit(&mut tester, now).unwrap();
+    tester.on_frame(&frame(0x7E8, &[0x30, 0, 0]), now);
+    assert_eq!(transmit(&mut tester, now).unwrap().data, [0x21, 0x11, 0x11, 0x11, 0x11, 0xAA, 0xAA, 0xAA]);
+
+    // Flow control frames are padded as well
+    let mut ecu = IsoTp::new(IsoTpConfig { padding: Some(0x55), ..ecu_config() });
+    ecu.on_frame(&frame(0x7E0, &[0x10, 20, 0, 1, 2, 3, 4, 5]), now);
+    assert_eq!(transmit(&mut ecu, now).unwrap().data, [0x30, 0, 0, 0x55, 0x55, 0x55, 0x55, 0x55]);
+
+    // Received padding is stripped
+    let mut ecu = IsoTp::new(ecu_config());
+    ecu.on_frame(&frame(0x7E0, &[0x02, 0x10, 0x03, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]), now);
+    assert!(matches!(ecu.poll_event(now), Some(IsoTpEvent::Received { data, .. }) if data == [0x10, 0x03]));
+}
+
+#[test]
+fn malformed_frames() {
+    let now = Instant::now();
+    let mut ecu = IsoTp::new(ecu_config());
+    // Single frames with no data, or less data than their length
+    for data in [&[0x00][..], &[0x05, 1, 2, 3]] {
+        ecu.on_frame(&frame(0x7E0, data), now);
+        assert_eq!(ecu.poll_event(now), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct)));
+    }
+    // First frames shorter than 8 bytes, or with a length which fits a single frame
+    for data in [&[0x10, 20, 0, 1, 2][..], &[0x10, 7, 0, 1, 2, 3, 4, 5]] {
+        ecu.on_frame(&frame(0x7E0, data), now);
+        assert_eq!(ecu.poll_event(now), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct)));
+    }
+    // A new message interrupts the message being received
+    ecu.on_frame(&frame(0x7E0, &[0x10, 20, 0, 1, 2, 3, 4, 5]), now);
+    ecu.on_frame(&frame(0x7E0, &[0x01, 0x3E]), now);
+    assert_eq!(ecu.poll_event(now), Some(IsoTpEvent::ReceiveFailed(PduErrorEvt::FrameStruct)));
+    assert!(matches!(ecu.poll_event(now), Some(IsoTpEvent::Received { data, .. }) if data == [0x3E]));
+    // Frames of other IDs are ignored
+    ecu.on_frame(&frame(0x7E1, &[0x01, 0x3E]), now);
+    assert_eq!(ecu.poll_event(now), None);
+}
+
+#[test]
+fn config_from_params() {
+    let params = LinkParams::new(
+        [(StdComParam::Bs, 25_000), (StdComParam::CanMaxNumWaitFrames, 3), (StdComParam::CanFillerByteHandling, 1)]
+            .map(|(p, v)| (p, p.value(v).unwrap()))
+    );
+    let config = IsoTpConfig::from_params(&params);
+    assert_eq!(config.n_bs, ms(25));
+    assert_eq!(config.max_wait_frames, 3);
+    assert_eq!(config.padding, Some(0));
+    assert_eq!(IsoTpConfig::from_params(&LinkParams::default()), IsoTpConfig::default());
+}
@@ -0,0 +1,414 @@
+//! Tests of the allocator of items which are freed by `PDUDestroyItem`
+
+use std::{ffi::CStr, slice};
+
+use dpdu_rust::{
+    provider::{
+        Event, EventData, ExtraInfoData, IoctlData, ItemAllocator, ItemError, ModuleInfo, Param, PduTag, ResultEvent
+    },
+    ComParamValue, CopHandle, EcuUniqueRespData, ErrorData, EventItem, InfoData, IoByteArrayData,
+    IoEntityStatusData, IoEventQueuePropertyData, IoProgVoltageData, ModuleData, ModuleHandle, ObjectId,
+    ParamByteFieldData, ParamItem, ParamLongFieldData, ParamStructAccessTiming, ParamStructFieldData,
+    ParamStructSessionTiming, PduCpst, PduError, PduErrorEvt, PduInfo, PduIt, PduItem, PduPc, PduPt, PduQueueMode,
+    PduStatus, ResourceId, ResultData, RscConflictData, RscIdItemData, TimingSet
+};
+
+fn module(raw: u32) -> ModuleHandle {
+    ModuleHandle::new(raw).unwrap()
+}
+
+fn resource(raw: u32) -> ResourceId {
+    ResourceId::new(raw).unwrap()
+}
+
+fn param(id: u32, value: ComParamValue) -> Param {
+    Param { id: ObjectId::new(id).unwrap(), class: PduPc::Com, value }
+}
+
+/// Session timing of 50 ms P2Max and 5 s P2*Max
+fn session_timing(session: u16) -> ParamStructSessionTiming {
+    ParamStructSessionTiming { session, p2_max_high: 0, p2_max_low: 50, p2_star_high: 1, p2_star_low: 0xF4 }
+}
+
+/// Destroys the item, checking it was the only live item of the allocator
+fn destroy<T>(items: &ItemAllocator, item: *mut T, item_type: PduIt) {
+    let item = item.cast::<PduItem>();
+    assert!(items.is_live(item));
+    assert_eq!(items.live_count(), 1);
+    assert_eq!(items.destroy(item), Ok(item_type));
+    assert!(!items.is_live(item));
+    assert_eq!(items.live_count(), 0);
+}
+
+/// Reads an array of the item, which is null when empty
+///
+/// # Safety
+/// `p` must point to `len` values, or be null when `len` is 0
+unsafe fn array<'a, T>(p: *const T, len: u32) -> &'a [T] {
+    if len == 0 {
+        assert!(p.is_null());
+        &[]
+    } else {
+        slice::from_raw_parts(p, len as usize)
+    }
+}
+
+/// Reads a null terminated string of the item
+///
+/// # Safety
+/// `p` must point to a null terminated string
+unsafe fn string<'a>(p: *const u8) -> &'a str {
+    CStr::from_ptr(p.cast()).to_str().unwrap()
+}
+
+#[test]
+fn double_free_is_rejected() {
+    let items = ItemAllocator::new();
+    let item = items.alloc_ioctl_data(&IoctlData::Unum32(1)).cast::<PduItem>();
+    assert_eq!(items.destroy(item), Ok(PduIt::IoUnum32));
+    assert_eq!(items.destroy(item), Err(ItemError::DoubleFree));
+    assert_eq!(PduError::from(ItemError::DoubleFree), PduError::InvalidParameters);
+
+    // Other items are still tracked after the double free
+    let other = items.alloc_ioctl_data(&IoctlData::Unum32(2)).cast::<PduItem>();
+    assert_eq!(items.live_count(), 1);
+    assert_eq!(items.destroy(other), Ok(PduIt::IoUnum32));
+}
+
+#[test]
+fn foreign_pointers_are_rejected() {
+    let items = ItemAllocator::new();
+    assert_eq!(items.destroy(std::ptr::null_mut()), Err(ItemError::Null));
+
+    let mut foreign = PduItem { item_type: PduIt::IoUnum32 };
+    assert!(!items.is_live(&foreign));
+    assert_eq!(items.destroy(&mut foreign), Err(ItemError::ForeignPointer));
+
+    // Pointers into the middle of an item, or items of another allocator, are foreign too
+    let item = items.alloc_ioctl_data(&IoctlData::Unum32(1));
+    // Safety: The item is live
+    let inner = unsafe { (*item).p_data.cast::<PduItem>() };
+    assert_eq!(items.destroy(inner), Err(ItemError::ForeignPointer));
+    assert_eq!(ItemAllocator::new().destroy(item.cast()), Err(ItemError::ForeignPointer));
+    assert_eq!(PduError::from(ItemError::ForeignPointer), PduError::InvalidParameters);
+
+    // The failed destroys left the item allocated
+    destroy(&items, item, PduIt::IoUnum32);
+}
+
+#[test]
+fn clear_frees_every_item() {
+    let items = ItemAllocator::new();
+    let first = items.alloc_ioctl_data(&IoctlData::Unum32(1)).cast::<PduItem>();
+    let second = items.alloc_ioctl_data(&IoctlData::Unum32(2)).cast::<PduItem>();
+    assert_eq!(items.live_count(), 2);
+    items.clear();
+    assert_eq!(items.live_count(), 0);
+    assert!(!items.is_live(first));
+    assert!(!items.is_live(second));
+}
+
+#[test]
+fn module_items() {
+    let items = ItemAllocator::new();
+    let modules = [
+        ModuleInfo {
+            module_type_id: 1,
+            h_mod: module(2),
+            vendor_module_name: "first".into(),
+            vendor_additional_info: String::new(),
+            status: PduStatus::ModstReady
+        },
+        ModuleInfo {
+            module_type_id: 3,
+            h_mod: module(4),
+            vendor_module_name: "sec\0ond".into(),
+            vendor_additional_info: "info".into(),
+            status: PduStatus::ModstNotReady
+        }
+    ];
+    let item = items.alloc_modules(&modules);
+    // Safety: The item is live, and built from the modules
+    unsafe {
+        assert_eq!((*item).item_type, PduIt::ModuleId);
+        let data: &[ModuleData] = array((*item).p_module_data, (*item).num_entries);
+        assert_eq!(data.len(), 2);
+        for (data, module) in data.iter().zip(&modules) {
+            assert_eq!(data.module_type_id, module.module_type_id);
+            assert_eq!(data.h_mod, module.h_mod.raw());
+            assert_eq!(data.status, module.status);
+            assert_eq!(string(data.vendor_additional_info), module.vendor_additional_info);
+        }
+        // Interior nulls are removed, so the string is not truncated
+        assert_eq!(string(data[0].vendor_module_name), "first");
+        assert_eq!(string(data[1].vendor_module_name), "second");
+    }
+    destroy(&items, item, PduIt::ModuleId);
+
+    let empty = items.alloc_modules(&[]);
+    // Safety: The item is live
+    unsafe {
+        assert_eq!((*empty).num_entries, 0);
+        assert!((*empty).p_module_data.is_null());
+    }
+    destroy(&items, empty, PduIt::ModuleId);
+}
+
+#[test]
+fn resource_id_items() {
+    let items = ItemAllocator::new();
+    let ids = [(module(1), vec![resource(10), resource(11)]), (module(2), vec![])];
+    let item = items.alloc_resource_ids(&ids);
+    // Safety: The item is live, and built from the IDs
+    unsafe {
+        assert_eq!((*item).item_type, PduIt::RscId);
+        let data: &[RscIdItemData] = array((*item).p_id_item_data, (*item).num_modules);
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].h_mod, 1);
+        assert_eq!(array(data[0].p_resource_id_array, data[0].num_ids), &[10, 11]);
+        assert_eq!(data[1].h_mod, 2);
+        assert_eq!(array(data[1].p_resource_id_array, data[1].num_ids), &[]);
+    }
+    destroy(&items, item, PduIt::RscId);
+}
+
+#[test]
+fn resource_conflict_items() {
+    let items = ItemAllocator::new();
+    let item = items.alloc_conflicts(&[(module(1), resource(10)), (module(2), resource(20))]);
+    // Safety: The item is live, and built from the conflicts
+    unsafe {
+        assert_eq!((*item).item_type, PduIt::RscConflict);
+        let data: &[RscConflictData] = array((*item).p_rsc_conflict_data, (*item).num_entries);
+        assert_eq!(data, &[
+            RscConflictData { h_mod: 1, resource_id: 10 },
+            RscConflictData { h_mod: 2, resource_id: 20 }
+        ]);
+    }
+    destroy(&items, item, PduIt::RscConflict);
+}
+
+#[test]
+fn param_items() {
+    let session = session_timing(1);
+    let access = ParamStructAccessTiming {
+        p2_min: 50,
+        p2_max: 100,
+        p3_min: 110,
+        p3_max: 20,
+        p4_min: 10,
+        timing_set: TimingSet::Default
+    };
+    let values = [
+        ComParamValue::Unum8(0xAB),
+        ComParamValue::Snum8(-2),
+        ComParamValue::Unum16(0xABCD),
+        ComParamValue::Snum16(-300),
+        ComParamValue::Unum32(0x1234_5678),
+        ComParamValue::Snum32(-70000),
+        ComParamValue::ByteField(vec![1, 2, 3]),
+        ComParamValue::ByteField(vec![]),
+        ComParamValue::LongField(vec![0x7E0, 0x7E8]),
+        ComParamValue::SessionTiming(vec![session]),
+        ComParamValue::AccessTiming(vec![access, access])
+    ];
+    let items = ItemAllocator::new();
+    for (id, value) in values.into_iter().enumerate() {
+        let param = param(id as u32 + 1, value);
+        let item = items.alloc_param(&param);
+        // Safety: The item is live, and built from the ComParam
+        unsafe {
+            let raw: &ParamItem = &*item;
+            assert_eq!(raw.item_type, PduIt::Param);
+            assert_eq!(raw.com_param_id, param.id.raw());
+            assert_eq!(raw.com_param_class, PduPc::Com);
+            assert_eq!(raw.com_param_data_type, param.value.data_type());
+            assert_eq!(ComParamValue::from_param_item(raw).as_ref(), Ok(&param.value));
+        }
+        destroy(&items, item, PduIt::Param);
+    }
+}
+
+#[test]
+fn param_field_layouts() {
+    let items = ItemAllocator::new();
+    let bytes = items.alloc_param(&param(1, ComParamValue::ByteField(vec![1, 2, 3])));
+    // Safety: The item is live, and holds a byte field
+    unsafe {
+        assert_eq!((*bytes).com_param_data_type, PduPt::ByteField);
+        let field = &*(*bytes).p_com_param_data.cast::<ParamByteFieldData>();
+        assert_eq!((field.param_max_len, field.param_act_len), (3, 3));
+        assert_eq!(array(field.p_data_array, field.param_act_len), &[1, 2, 3]);
+    }
+    destroy(&items, bytes, PduIt::Param);
+
+    let longs = items.alloc_param(&param(2, ComParamValue::LongField(vec![0x7E0, 0x7E8])));
+    // Safety: The item is live, and holds a long field
+    unsafe {
+        assert_eq!((*longs).com_param_data_type, PduPt::LongField);
+        let field = &*(*longs).p_com_param_data.cast::<ParamLongFieldData>();
+        assert_eq!((field.param_max_len, field.param_act_len), (2, 2));
+        assert_eq!(array(field.p_data_array, field.param_act_len), &[0x7E0, 0x7E8]);
+    }
+    destroy(&items, longs, PduIt::Param);
+
+    let session = session_timing(3);
+    let structs = items.alloc_param(&param(3, ComParamValue::SessionTiming(vec![session])));
+    // Safety: The item is live, and holds a struct field of session timings
+    unsafe {
+        assert_eq!((*structs).com_param_data_type, PduPt::StructField);
+        let field = &*(*structs).p_com_param_data.cast::<ParamStructFieldData>();
+        assert_eq!(field.com_param_struct_type, PduCpst::SessionTiming);
+        assert_eq!((field.param_max_entries, field.param_act_entries), (1, 1));
+        let timings = array(field.p_struct_array.cast::<ParamStructSessionTiming>(), field.param_act_entries);
+        assert_eq!(timings, &[session]);
+    }
+    destroy(&items, structs, PduIt::Param);
+}
+
+#[test]
+fn unique_response_id_table_items() {
+    let items = ItemAllocator::new();
+    let table = [
+        (1, vec![
+            param(10, ComParamValue::Unum32(0x7E0)),
+            param(11, ComParamValue::ByteField(vec![0x10, 0x01]))
+        ]),
+        (2, vec![])
+    ];
+    let item = items.alloc_unique_resp_id_table(&table);
+    // Safety: The item is live, and built from the table
+    unsafe {
+        assert_eq!((*item).item_type, PduIt::UniqueRespIdTable);
+        let data: &[EcuUniqueRespData] = array((*item).p_unique_data, (*item).num_entries);
+        assert_eq!(data.len(), 2);
+        for (data, (id, params)) in data.iter().zip(&table) {
+            assert_eq!(data.unique_resp_identifier, *id);
+            let raw = array(data.p_params, data.num_param_items);
+            assert_eq!(raw.len(), params.len());
+            for (raw, param) in raw.iter().zip(params) {
+                assert_eq!(raw.item_type, PduIt::Param);
+                assert_eq!(raw.com_param_id, param.id.raw());
+                assert_eq!(ComParamValue::from_param_item(raw).as_ref(), Ok(&param.value));
+            }
+        }
+    }
+    destroy(&items, item, PduIt::UniqueRespIdTable);
+}
+
+#[test]
+fn result_event_items() {
+    let items = ItemAllocator::new();
+    let mut tag_data = 0u8;
+    let tag = PduTag((&mut tag_data as *mut u8).cast());
+    let result = ResultEvent {
+        rx_flag: vec![0x01],
+        unique_resp_identifier: 7,
+        acceptance_id: 3,
+        timestamp_flags: vec![],
+        tx_msg_done_timestamp: 100,
+        start_msg_timestamp: 200,
+        extra_info: Some(ExtraInfoData { header: vec![0x7E, 0x8], footer: vec![] }),
+        data: vec![0x50, 0x03]
+    };
+    let event = Event {
+        h_cop: CopHandle::new(5),
+        cop_tag: tag,
+        timestamp: 300,
+        data: EventData::Result(result.clone())
+    };
+    let item = items.alloc_event(&event);
+    // Safety: The item is live, and built from the result event
+    unsafe {
+        let raw: &EventItem = &*item;
+        assert_eq!(raw.item_type, PduIt::Result);
+        assert_eq!(raw.h_cop, 5);
+        assert_eq!(raw.p_cop_tag, tag.0);
+        assert_eq!(raw.timestamp, 300);
+        let data = &*raw.p_data.cast::<ResultData>();
+        assert_eq!(array(data.rx_flag.p_flag_data, data.rx_flag.num_flag_bytes), &result.rx_flag[..]);
+        assert_eq!(array(data.timestamp_flags.p_flag_data, data.timestamp_flags.num_flag_bytes), &[]);
+        assert_eq!(data.unique_resp_identifier, 7);
+        assert_eq!(data.acceptance_id, 3);
+        assert_eq!(data.tx_msg_done_timestamp, 100);
+        assert_eq!(data.start_msg_timestamp, 200);
+        assert_eq!(array(data.p_data_bytes, data.num_data_bytes), &result.data[..]);
+        let extra = &*data.p_extra_info;
+        assert_eq!(array(extra.p_header_bytes, extra.num_header_bytes), &[0x7E, 0x8]);
+        assert_eq!(array(extra.p_footer_bytes, extra.num_footer_bytes), &[]);
+    }
+    destroy(&items, item, PduIt::Result);
+
+    // Results without extra information, of events not from a ComPrimitive
+    let event = Event {
+        h_cop: None,
+        data: EventData::Result(ResultEvent { extra_info: None, ..result }),
+        ..event
+    };
+    let item = items.alloc_event(&event);
+    // Safety: The item is live, and built from the result event
+    unsafe {
+        assert_eq!((*item).h_cop, CopHandle::UNDEF.raw());
+        assert!((*(*item).p_data.cast::<ResultData>()).p_extra_info.is_null());
+    }
+    destroy(&items, item, PduIt::Result);
+}
+
+#[test]
+fn status_error_and_info_event_items() {
+    let items = ItemAllocator::new();
+    let event = |data| Event { h_cop: None, cop_tag: PduTag(std::ptr::null_mut()), timestamp: 1, data };
+
+    let item = items.alloc_event(&event(EventData::Status(PduStatus::CllstOnline)));
+    // Safety: The item is live, and holds a status
+    unsafe { assert_eq!(*(*item).p_data.cast::<PduStatus>(), PduStatus::CllstOnline) }
+    destroy(&items, item, PduIt::Status);
+
+    let error = ErrorData { error_code_id: PduErrorEvt::LostCommToVCI, extra_error_info_id: 2 };
+    let item = items.alloc_event(&event(EventData::Error(error)));
+    // Safety: The item is live, and holds an error
+    unsafe { assert_eq!(*(*item).p_data.cast::<ErrorData>(), error) }
+    destroy(&items, item, PduIt::Error);
+
+    let info = InfoData { info_code: PduInfo::ModuleListChange, extra_info_data: 3 };
+    let item = items.alloc_event(&event(EventData::Info(info)));
+    // Safety: The item is live, and holds information
+    unsafe { assert_eq!(*(*item).p_data.cast::<InfoData>(), info) }
+    destroy(&items, item, PduIt::Info);
+}
+
+#[test]
+fn ioctl_data_items() {
+    let items = ItemAllocator::new();
+
+    let item = items.alloc_ioctl_data(&IoctlData::Unum32(0xDEAD_BEEF));
+    // Safety: The item is live, and holds a u32
+    unsafe { assert_eq!(*(*item).p_data.cast::<u32>(), 0xDEAD_BEEF) }
+    destroy(&items, item, PduIt::IoUnum32);
+
+    let voltage = IoProgVoltageData { prog_voltage_mv: 12000, pin_on_dlc: 15 };
+    let item = items.alloc_ioctl_data(&IoctlData::ProgVoltage(voltage));
+    // Safety: The item is live, and holds a programming voltage
+    unsafe { assert_eq!(*(*item).p_data.cast::<IoProgVoltageData>(), voltage) }
+    destroy(&items, item, PduIt::IoProgVoltage);
+
+    let item = items.alloc_ioctl_data(&IoctlData::ByteArray(vec![1, 2, 3, 4, 5]));
+    // Safety: The item is live, and holds a byte array
+    unsafe {
+        let data = &*(*item).p_data.cast::<IoByteArrayData>();
+        assert_eq!(array(data.p_data, data.data_size), &[1, 2, 3, 4, 5]);
+    }
+    destroy(&items, item, PduIt::IoByteArray);
+
+    let queue = IoEventQueuePropertyData { queue_size: 10, queue_mode: PduQueueMode::Circular };
+    let item = items.alloc_ioctl_data(&IoctlData::EventQueueProperty(queue));
+    // Safety: The item is live, and holds event queue properties
+    unsafe { assert_eq!(*(*item).p_data.cast::<IoEventQueuePropertyData>(), queue) }
+    destroy(&items, item, PduIt::IoEventQueueProperty);
+
+    let status = IoEntityStatusData { entity_type: 0, tcp_clients_max: 4, tcp_clients: 1, max_data_size: 4096 };
+    let item = items.alloc_ioctl_data(&IoctlData::EntityStatus(status));
+    // Safety: The item is live, and holds an entity status
+    unsafe { assert_eq!(*(*item).p_data.cast::<IoEntityStatusData>(), status) }
+    destroy(&items, item, PduIt::EntityStatus);
+}
@@ -0,0 +1,334 @@
+//! Tests of the SAE J1939-21 engine, driven frame by frame
+
+use std::time::{Duration, Instant};
+
+use dpdu_rust::{
+    provider::{
+        AddressClaim, CanFrame, J1939Config, J1939Event, J1939Message, J1939, GLOBAL_ADDRESS, NULL_ADDRESS,
+        PGN_ADDRESS_CLAIMED
+    },
+    PduError, PduErrorEvt
+};
+
+const TESTER: u8 = 0xF9;
+const ECU: u8 = 0x00;
+
+/// Proprietary A, a PDU1 PGN
+const PROPA: u32 = 0xEF00;
+/// DM1, a PDU2 PGN
+const DM1: u32 = 0xFECA;
+
+/// NAME which may move to an arbitrary address
+const ARBITRARY_NAME: u64 = 1 << 63;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// Creates a node which uses its address without claiming it
+fn node(address: u8, now: Instant) -> J1939 {
+    let mut node = J1939::new(J1939Config { address, claim: AddressClaim::None, ..Default::default() });
+    node.claim(now);
+    assert_eq!(node.poll_event(now), Some(J1939Event::AddressClaimed(address)));
+    node
+}
+
+/// Creates a node which claims its address with the default claim timeout
+fn claiming(address: u8, name: u64, claim: AddressClaim, now: Instant) -> J1939 {
+    let mut node = J1939::new(J1939Config { address, name, claim, ..Default::default() });
+    node.claim(now);
+    node
+}
+
+/// Returns the ID of a transport protocol frame (priority 7) to `destination`
+fn tp_id(pgn: u32, destination: u8, source: u8) -> u32 {
+    0x9C00_0000 | (pgn << 8) | ((destination as u32) << 8) | source as u32
+}
+
+/// Builds a TP.CM frame from the ECU to `destination`
+fn cm_to(destination: u8, data: [u8; 5], pgn: u32) -> CanFrame {
+    let [p0, p1, p2, _] = pgn.to_le_bytes();
+    CanFrame { id: tp_id(0xEC00, destination, ECU), data: [&data[..], &[p0, p1, p2]].concat(), ..Default::default() }
+}
+
+/// Builds a TP.CM frame from the ECU to the tester
+fn cm(data: [u8; 5], pgn: u32) -> CanFrame {
+    cm_to(TESTER, data, pgn)
+}
+
+/// Builds a TP.DT packet from the ECU to `destination`
+fn dt(destination: u8, seq: u8, data: &[u8]) -> CanFrame {
+    let mut packet = [&[seq][..], &data[(seq as usize - 1) * 7..(seq as usize * 7).min(data.len())]].concat();
+    packet.resize(8, 0xFF);
+    CanFrame { id: tp_id(0xEB00, destination, ECU), data: packet, ..Default::default() }
+}
+
+fn drain(node: &mut J1939, now: Instant) -> Vec<CanFrame> {
+    std::iter::from_fn(|| node.poll_transmit(now)).collect()
+}
+
+/// Returns the payloads of the frames a node has to send
+fn payloads(node: &mut J1939, now: Instant) -> Vec<Vec<u8>> {
+    drain(node, now).into_iter().map(|f| f.data).collect()
+}
+
+/// Returns the events of a node, other than received address claims
+fn events(node: &mut J1939, now: Instant) -> Vec<J1939Event> {
+    std::iter::from_fn(|| node.poll_event(now))
+        .filter(|e| !matches!(e, J1939Event::Received { message, .. } if message.pgn == PGN_ADDRESS_CLAIMED))
+        .collect()
+}
+
+/// Passes frames between both nodes until neither has a frame due
+fn exchange(a: &mut J1939, b: &mut J1939, now: Instant) {
+    loop {
+        let mut idle = true;
+        while let Some(frame) = a.poll_transmit(now) {
+            b.on_frame(&frame, now);
+            idle = false;
+        }
+        while let Some(frame) = b.poll_transmit(now) {
+            a.on_frame(&frame, now);
+            idle = false;
+        }
+        if idle {
+            return;
+        }
+    }
+}
+
+/// Returns the messages received by a node, other than address claims
+fn received(node: &mut J1939, now: Instant) -> Vec<J1939Message> {
+    events(node, now)
+        .into_iter()
+        .filter_map(|e| match e {
+            J1939Event::Received { message, .. } => Some(message),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn bam() {
+    let start = Instant::now();
+    let (mut tester, mut ecu) = (node(TESTER, start), node(ECU, start));
+    let data: Vec<u8> = (0..20).collect();
+    tester.send(DM1, GLOBAL_ADDRESS, &data, start).unwrap();
+
+    let announce = tester.poll_transmit(start).unwrap();
+    assert_eq!(announce.id, 0x9CEC_FFF9);
+    assert_eq!(announce.data, [32, 20, 0, 3, 0xFF, 0xCA, 0xFE, 0x00]);
+    ecu.on_frame(&announce, start);
+    // The packets follow 50 ms apart
+    let mut now = start;
+    for seq in 1..=3 {
+        assert_eq!(tester.poll_transmit(now), None);
+        assert_eq!(tester.next_wake(), Some(now + ms(50)));
+        now += ms(50);
+        let packet = tester.poll_transmit(now).unwrap();
+        assert_eq!((packet.id, packet.data[0]), (0x9CEB_FFF9, seq));
+        ecu.on_frame(&packet, now);
+    }
+    assert_eq!(events(&mut tester, now), [J1939Event::Sent]);
+    assert!(!tester.is_sending());
+    // A BAM is not acknowledged
+    assert!(drain(&mut ecu, now).is_empty());
+    let message = J1939Message { pgn: DM1, priority: 7, source: TESTER, destination: GLOBAL_ADDRESS, data };
+    assert_eq!(events(&mut ecu, now), [J1939Event::Received { message, start }]);
+}
+
+#[test]
+fn rts_cts_loopback() {
+    let now = Instant::now();
+    let (mut tester, mut ecu) = (node(TESTER, now), node(ECU, now));
+    let data: Vec<u8> = (0..=255).cycle().take(1785).collect();
+    tester.send(PROPA, ECU, &data, now).unwrap();
+    exchange(&mut tester, &mut ecu, now);
+    assert_eq!(events(&mut tester, now), [J1939Event::Sent]);
+    let message = J1939Message { pgn: PROPA, priority: 7, source: TESTER, destination: ECU, data };
+    assert_eq!(received(&mut ecu, now), [message]);
+
+    // RTS/CTS sessions to other nodes are ignored
+    let mut other = node(0x01, now);
+    tester.send(PROPA, ECU, &[0; 20], now).unwrap();
+    for frame in drain(&mut tester, now) {
+        other.on_frame(&frame, now);
+    }
+    assert!(drain(&mut other, now).is_empty());
+    assert!(received(&mut other, now).is_empty());
+}
+
+#[test]
+fn cts_window_of_the_receiver() {
+    let now = Instant::now();
+    let mut tester = node(TESTER, now);
+    let data: Vec<u8> = (0..20).collect();
+    tester.send(PROPA, ECU, &data, now).unwrap();
+    let rts = tester.poll_transmit(now).unwrap();
+    assert_eq!(rts.id, 0x9CEC_00F9);
+    assert_eq!(rts.data, [16, 20, 0, 3, 0xFF, 0x00, 0xEF, 0x00]);
+    assert_eq!(tester.poll_transmit(now), None);
+
+    // The ECU takes one packet, then the other two
+    tester.on_frame(&cm([17, 1, 1, 0xFF, 0xFF], PROPA), now);
+    let packets: Vec<u8> = drain(&mut tester, now).iter().map(|f| f.data[0]).collect();
+    assert_eq!(packets, [1]);
+    tester.on_frame(&cm([17, 2, 2, 0xFF, 0xFF], PROPA), now);
+    let packets: Vec<u8> = drain(&mut tester, now).iter().map(|f| f.data[0]).collect();
+    assert_eq!(packets, [2, 3]);
+    assert!(tester.is_sending());
+    tester.on_frame(&cm([19, 20, 0, 3, 0xFF], PROPA), now);
+    assert_eq!(events(&mut tester, now), [J1939Event::Sent]);
+}
+
+#[test]
+fn cts_window_of_the_sender() {
+    let now = Instant::now();
+    let mut tester = node(TESTER, now);
+    let data: Vec<u8> = (0..20).collect();
+    // The ECU sends at most 2 packets per CTS
+    let [p0, p1, p2, _] = PROPA.to_le_bytes();
+    let rts = cm([16, 20, 0, 3, 2], PROPA);
+    tester.on_frame(&rts, now);
+    assert_eq!(payloads(&mut tester, now), [[17, 2, 1, 0xFF, 0xFF, p0, p1, p2]]);
+    tester.on_frame(&dt(TESTER, 1, &data), now);
+    assert!(drain(&mut tester, now).is_empty());
+    tester.on_frame(&dt(TESTER, 2, &data), now);
+    assert_eq!(payloads(&mut tester, now), [[17, 1, 3, 0xFF, 0xFF, p0, p1, p2]]);
+    tester.on_frame(&dt(TESTER, 3, &data), now);
+    let eoma = drain(&mut tester, now);
+    assert_eq!((eoma[0].id, eoma[0].data.clone()), (0x9CEC_00F9, vec![19, 20, 0, 3, 0xFF, p0, p1, p2]));
+    assert_eq!(received(&mut tester, now)[0].data, data);
+}
+
+#[test]
+fn connection_abort() {
+    let now = Instant::now();
+    let mut tester = node(TESTER, now);
+    let data: Vec<u8> = (0..20).collect();
+
+    // The receiver aborts the session
+    tester.send(PROPA, ECU, &data, now).unwrap();
+    drain(&mut tester, now);
+    tester.on_frame(&cm([255, 1, 0xFF, 0xFF, 0xFF], PROPA), now);
+    assert_eq!(events(&mut tester, now), [J1939Event::TransmitFailed(PduErrorEvt::TxError)]);
+    assert!(!tester.is_sending());
+    // Aborts of other PGNs are ignored
+    tester.send(PROPA, ECU, &data, now).unwrap();
+    drain(&mut tester, now);
+    tester.on_frame(&cm([255, 1, 0xFF, 0xFF, 0xFF], 0xEA00), now);
+    assert!(tester.is_sending());
+
+    // The sender aborts the session
+    let mut tester = node(TESTER, now);
+    let [p0, p1, p2, _] = PROPA.to_le_bytes();
+    let rts = cm([16, 20, 0, 3, 0xFF], PROPA);
+    tester.on_frame(&rts, now);
+    tester.on_frame(&dt(TESTER, 1, &data), now);
+    tester.on_frame(&cm([255, 2, 0xFF, 0xFF, 0xFF], PROPA), now);
+    assert_eq!(events(&mut tester, now), [J1939Event::ReceiveFailed(PduErrorEvt::RxError)]);
+
+    // A packet out of sequence is answered with an abort
+    drain(&mut tester, now);
+    tester.on_frame(&rts, now);
+    tester.on_frame(&dt(TESTER, 2, &data), now);
+    let abort = drain(&mut tester, now);
+    assert_eq!(abort.last().map(|f| f.data.clone()), Some(vec![255, 7, 0xFF, 0xFF, 0xFF, p0, p1, p2]));
+    assert_eq!(events(&mut tester, now), [J1939Event::ReceiveFailed(PduErrorEvt::FrameStruct)]);
+}
+
+#[test]
+fn transmit_timeouts() {
+    let start = Instant::now();
+    let mut tester = node(TESTER, start);
+    let data: Vec<u8> = (0..20).collect();
+    let [p0, p1, p2, _] = PROPA.to_le_bytes();
+    let timeout = vec![255, 3, 0xFF, 0xFF, 0xFF, p0, p1, p2];
+
+    // T3, no CTS after the RTS
+    tester.send(PROPA, ECU, &data, start).unwrap();
+    drain(&mut tester, start);
+    assert_eq!(tester.next_wake(), Some(start + ms(1250)));
+    assert!(events(&mut tester, start + ms(1249)).is_empty());
+    assert_eq!(events(&mut tester, start + ms(1250)), [J1939Event::TransmitFailed(PduErrorEvt::RxTimeout)]);
+    assert_eq!(payloads(&mut tester, start + ms(1250)), std::slice::from_ref(&timeout));
+
+    // T4, a CTS holding the connection open is not followed by another CTS
+    tester.send(PROPA, ECU, &data, start).unwrap();
+    drain(&mut tester, start);
+    tester.on_frame(&cm([17, 0, 0xFF, 0xFF, 0xFF], PROPA), start);
+    assert_eq!(tester.next_wake(), Some(start + ms(1050)));
+    assert!(events(&mut tester, start + ms(1049)).is_empty());
+    assert_eq!(events(&mut tester, start + ms(1050)), [J1939Event::TransmitFailed(PduErrorEvt::RxTimeout)]);
+    assert_eq!(payloads(&mut tester, start + ms(1050)), [timeout]);
+}
+
+#[test]
+fn receive_timeouts() {
+    let start = Instant::now();
+    let mut tester = node(TESTER, start);
+    let data: Vec<u8> = (0..20).collect();
+    let [p0, p1, p2, _] = PROPA.to_le_bytes();
+
+    // T1, a BAM stops after its first packet. BAMs are not aborted
+    tester.on_frame(&cm_to(GLOBAL_ADDRESS, [32, 20, 0, 3, 0xFF], PROPA), start);
+    tester.on_frame(&dt(GLOBAL_ADDRESS, 1, &data), start + ms(100));
+    assert_eq!(tester.next_wake(), Some(start + ms(850)));
+    assert!(events(&mut tester, start + ms(849)).is_empty());
+    assert_eq!(events(&mut tester, start + ms(850)), [J1939Event::ReceiveFailed(PduErrorEvt::RxTimeout)]);
+    assert!(drain(&mut tester, start + ms(850)).is_empty());
+
+    // T2, no packet after the CTS
+    let rts = cm([16, 20, 0, 3, 0xFF], PROPA);
+    tester.on_frame(&rts, start);
+    assert_eq!(drain(&mut tester, start).len(), 1);
+    assert_eq!(tester.next_wake(), Some(start + ms(1250)));
+    assert!(events(&mut tester, start + ms(1249)).is_empty());
+    assert_eq!(events(&mut tester, start + ms(1250)), [J1939Event::ReceiveFailed(PduErrorEvt::RxTimeout)]);
+    assert_eq!(payloads(&mut tester, start + ms(1250)), [[255, 3, 0xFF, 0xFF, 0xFF, p0, p1, p2]]);
+}
+
+#[test]
+fn losing_an_address_claim() {
+    let now = Instant::now();
+    let mut winner = claiming(0x25, 0x10, AddressClaim::Preferred, now);
+    let mut loser = claiming(0x25, 0x20, AddressClaim::Preferred, now);
+    exchange(&mut winner, &mut loser, now);
+    assert_eq!(events(&mut loser, now), [J1939Event::AddressLost]);
+    assert_eq!(loser.address(), None);
+    assert_eq!(loser.send(DM1, GLOBAL_ADDRESS, &[0; 8], now), Err(PduError::FctFailed));
+
+    // The winner uses the address once the claim timeout has passed
+    let later = now + ms(250);
+    assert_eq!(events(&mut winner, later), [J1939Event::AddressClaimed(0x25)]);
+    assert_eq!(winner.address(), Some(0x25));
+
+    // The loser answers requests for address claims with Cannot Claim Address
+    let request = CanFrame { id: 0x98EA_FF25, data: vec![0x00, 0xEE, 0x00], ..Default::default() };
+    loser.on_frame(&request, later);
+    let cannot_claim = drain(&mut loser, later);
+    assert_eq!(cannot_claim.iter().map(|f| f.id as u8).collect::<Vec<_>>(), [NULL_ADDRESS]);
+}
+
+#[test]
+fn arbitrary_address_fallback() {
+    let now = Instant::now();
+    let mut holder = claiming(128, 0x01, AddressClaim::Preferred, now);
+    let mut winner = claiming(0x25, 0x10, AddressClaim::Preferred, now);
+    let mut loser = claiming(0x25, ARBITRARY_NAME | 0x20, AddressClaim::Arbitrary, now);
+    // The loser has seen the claim of the first arbitrary address
+    for frame in drain(&mut holder, now) {
+        loser.on_frame(&frame, now);
+    }
+    exchange(&mut winner, &mut loser, now);
+    assert!(events(&mut loser, now).is_empty());
+    let later = now + ms(250);
+    assert_eq!(events(&mut loser, later), [J1939Event::AddressClaimed(129)]);
+    assert_eq!(events(&mut winner, later), [J1939Event::AddressClaimed(0x25)]);
+
+    // A NAME which is not arbitrary address capable loses the address instead
+    let mut winner = claiming(0x25, 0x10, AddressClaim::Preferred, now);
+    let mut loser = claiming(0x25, 0x20, AddressClaim::Arbitrary, now);
+    exchange(&mut winner, &mut loser, now);
+    assert_eq!(events(&mut loser, later), [J1939Event::AddressLost]);
+}
@@ -0,0 +1,408 @@
+//! Tests of the J2534 bridge against the mock library in `j2534_mock`
+
+use std::{
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    ffi::{c_long, c_ulong},
+    path::PathBuf,
+    process::Command,
+    ptr,
+    sync::{Mutex, MutexGuard, OnceLock},
+    thread,
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    backends::j2534::{pdu_error, J2534},
+    provider::{
+        std_object_id, Channel, Clock, Driver, DriverBackend, IoctlData, IoctlInput, LinkParams, MessageFilter,
+        MessageFilters, PduBackend, PduTag, ResultEvent
+    },
+    BusType, CllHandle, CopCtrlData, FlagData, IoFilterData, IoProgVoltageData, IoctlCommand, ModuleHandle, PduCopt,
+    PduDataItem, PduError, PduFilter, PduIt, Protocol, ResultData, RscData, StdComParam
+};
+
+/// Builds the mock library once
+fn mock_library() -> &'static PathBuf {
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{DLL_PREFIX}mock_j2534{DLL_SUFFIX}"));
+        let status = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+            .args(["--edition", "2021", "--crate-type", "cdylib", "--crate-name", "mock_j2534", "-o"])
+            .arg(&path)
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/j2534_mock/lib.rs"))
+            .status()
+            .expect("rustc runs");
+        assert!(status.success(), "mock J2534 library builds");
+        path
+    })
+}
+
+/// Returns the option string of the mock library. The mock has one device, so the tests hold
+/// the returned guard to run one at a time
+fn mock_options() -> (MutexGuard<'static, ()>, String) {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    (guard, format!("{};name=Mock", mock_library().display()))
+}
+
+/// Opens the driver with the mock library and connects its module
+fn connected_driver() -> (MutexGuard<'static, ()>, J2534) {
+    let (guard, options) = mock_options();
+    let driver = J2534::open(&options).unwrap();
+    driver.connect_module(0).unwrap();
+    (guard, driver)
+}
+
+fn open_channel(driver: &J2534, protocol: Protocol, changes: &[(StdComParam, u32)]) -> Result<impl Channel, PduError> {
+    let mut params = LinkParams::new(driver.com_params(protocol));
+    for (param, value) in changes {
+        params.set(*param, param.value(*value).unwrap());
+    }
+    let resource = &driver.modules()[0].resources[0];
+    driver.open_channel(0, resource, protocol, &params, Clock::new())
+}
+
+/// Unplugs the mock device, or plugs it back in. The library is already loaded by the driver, so
+/// the mock state is shared with it
+fn set_unplugged(unplugged: bool) {
+    // Safety: The mock library exports the function with this signature
+    unsafe {
+        let library = libloading::Library::new(mock_library()).unwrap();
+        let set = library.get::<unsafe extern "system" fn(c_ulong)>(b"MockSetUnplugged").unwrap();
+        set(unplugged as c_ulong);
+    }
+}
+
+/// Waits up to 100ms for a result
+fn recv(channel: &mut impl Channel) -> Option<ResultEvent> {
+    let deadline = Instant::now() + Duration::from_millis(100);
+    while Instant::now() < deadline {
+        if let Some(result) = channel.recv(Duration::from_millis(5)).unwrap() {
+            return Some(result);
+        }
+    }
+    None
+}
+
+#[test]
+fn module_and_version() {
+    let (_guard, driver) = connected_driver();
+    let modules = driver.modules();
+    assert_eq!(modules.len(), 1);
+    assert_eq!(modules[0].name, "Mock");
+    assert!(modules[0].resources[0].protocols.contains(&Protocol::IsoObdOnIso15765_4));
+    let version = driver.version(0).unwrap();
+    assert_eq!((version.firmware.as_str(), version.api.as_str()), ("1.2.3", "04.04"));
+    driver.disconnect_module(0).unwrap();
+    assert_eq!(driver.version(0), None);
+    assert_eq!(open_channel(&driver, Protocol::Iso11898Raw, &[]).err(), Some(PduError::ModuleNotConnected));
+}
+
+#[test]
+fn iso15765_requests() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso15765_3OnIso15765_2, &[]).unwrap();
+    let params = LinkParams::new(driver.com_params(Protocol::Iso15765_3OnIso15765_2));
+
+    channel.send(&[0x22, 0xF1, 0x90], &[], &params).unwrap();
+    let result = recv(&mut channel).unwrap();
+    assert_eq!(result.extra_info.unwrap().header, [0x00, 0x00, 0x07, 0xE8]);
+    assert_eq!(result.data[..3], [0x62, 0xF1, 0x90]);
+    assert_eq!(&result.data[3..], b"WDD2220011A000001");
+
+    let mut functional = params.clone();
+    functional.set(StdComParam::RequestAddrMode, StdComParam::RequestAddrMode.value(2).unwrap());
+    channel.send(&[0x01, 0x00], &[], &functional).unwrap();
+    assert_eq!(recv(&mut channel).unwrap().data, [0x41, 0x00]);
+    // The mock only sends multi frame messages to IDs of a flow control filter
+    assert_eq!(channel.send(&[0x01; 12], &[], &functional), Err(PduError::FctFailed));
+    assert_eq!(driver.last_error(0).as_deref(), Some("No flow control filter for the CAN ID"));
+    assert_eq!(channel.send(&[], &[], &params), Err(PduError::InvalidParameters));
+}
+
+#[test]
+fn raw_can_frames() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let params = LinkParams::new(driver.com_params(Protocol::Iso11898Raw));
+
+    channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x10, 0x03], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel).unwrap().data, [0x00, 0x00, 0x07, 0xE8, 0x50, 0x03]);
+    channel.send(&[0x18, 0xDA, 0x10, 0xF1, 0x3E, 0x00], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel).unwrap().data, [0x98, 0xDA, 0xF1, 0x10, 0x7E, 0x00]);
+    assert_eq!(channel.send(&[0x00, 0x07], &[], &params), Err(PduError::InvalidParameters));
+    assert_eq!(channel.send(&[0x00, 0x00, 0x07, 0xE0, 0, 1, 2, 3, 4, 5, 6, 7, 8], &[], &params), Err(PduError::InvalidParameters));
+}
+
+#[test]
+fn params_reconnect_the_channel() {
+    let (_guard, driver) = connected_driver();
+    assert_eq!(open_channel(&driver, Protocol::Iso11898Raw, &[(StdComParam::Baudrate, 33_333)]).err(), Some(PduError::ValueNotSupported));
+
+    let mut channel = open_channel(&driver, Protocol::IsoObdOnIso15765_4, &[]).unwrap();
+    let mut params = LinkParams::new(driver.com_params(Protocol::IsoObdOnIso15765_4));
+    params.set(StdComParam::CanRespUsdtId, StdComParam::CanRespUsdtId.value(0x7E9).unwrap());
+    channel.apply_params(&params).unwrap();
+    // The flow control filter now passes 0x7E9, which the ECU does not send from
+    channel.send(&[0x09, 0x02], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel), None);
+
+    params.set(StdComParam::Baudrate, StdComParam::Baudrate.value(1).unwrap());
+    assert_eq!(channel.apply_params(&params), Err(PduError::ValueNotSupported));
+    params.set(StdComParam::Baudrate, StdComParam::Baudrate.value(250_000).unwrap());
+    params.set(StdComParam::CanRespUsdtId, StdComParam::CanRespUsdtId.value(0x7E8).unwrap());
+    channel.apply_params(&params).unwrap();
+    channel.send(&[0x09, 0x02], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel).unwrap().data[..2], [0x49, 0x02]);
+}
+
+#[test]
+fn failed_restores_lose_the_channel() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let mut params = LinkParams::new(driver.com_params(Protocol::Iso11898Raw));
+    params.set(StdComParam::Baudrate, StdComParam::Baudrate.value(250_000).unwrap());
+    // Neither the new channel nor the previous one can be connected
+    set_unplugged(true);
+    assert_eq!(channel.apply_params(&params), Err(PduError::CommPcToVciFailed));
+    assert_eq!(driver.last_error(0).as_deref(), Some("Device is unplugged"));
+    set_unplugged(false);
+    assert_eq!(channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x3E], &[], &params), Err(PduError::CommPcToVciFailed));
+    assert_eq!(channel.recv(Duration::ZERO), Err(PduError::CommPcToVciFailed));
+    assert_eq!(channel.apply_params(&params), Err(PduError::CommPcToVciFailed));
+    // The disconnected channel is not disconnected a second time
+    drop(channel);
+    assert_eq!(driver.last_error(0).as_deref(), Some("Device is unplugged"));
+}
+
+#[test]
+fn buffer_ioctls() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let params = LinkParams::new(driver.com_params(Protocol::Iso11898Raw));
+    channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x3E], &[], &params).unwrap();
+    assert_eq!(channel.ioctl(IoctlCommand::ClearRxQueue, &IoctlInput::None), Ok(None));
+    assert_eq!(recv(&mut channel), None);
+    assert_eq!(channel.ioctl(IoctlCommand::ClearTxQueue, &IoctlInput::None), Ok(None));
+    channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x3E], &[], &params).unwrap();
+    assert_eq!(recv(&mut channel).unwrap().data, [0x00, 0x00, 0x07, 0xE8, 0x7E]);
+    assert_eq!(channel.ioctl(IoctlCommand::GetCableId, &IoctlInput::None), Err(PduError::IdNotSupported));
+}
+
+#[test]
+fn error_codes() {
+    let codes: [(c_long, PduError); 25] = [
+        (0x00, PduError::FctFailed),
+        (0x01, PduError::IdNotSupported),
+        (0x02, PduError::InvalidHandle),
+        (0x03, PduError::ValueNotSupported),
+        (0x04, PduError::InvalidParameters),
+        (0x05, PduError::ValueNotSupported),
+        (0x06, PduError::ValueNotSupported),
+        (0x07, PduError::FctFailed),
+        (0x08, PduError::CommPcToVciFailed),
+        (0x09, PduError::FctFailed),
+        (0x0A, PduError::InvalidParameters),
+        (0x0B, PduError::InvalidParameters),
+        (0x0C, PduError::ResourceError),
+        (0x0D, PduError::InvalidParameters),
+        (0x0E, PduError::ResourceBusy),
+        (0x0F, PduError::IdNotSupported),
+        (0x10, PduError::FctFailed),
+        (0x11, PduError::TxQueueFull),
+        (0x12, PduError::FctFailed),
+        (0x13, PduError::MuxRscNotSupported),
+        (0x14, PduError::ResourceBusy),
+        (0x15, PduError::InvalidParameters),
+        (0x16, PduError::InvalidHandle),
+        (0x18, PduError::ResourceBusy),
+        (0x19, PduError::ValueNotSupported)
+    ];
+    for (code, expected) in codes {
+        assert_eq!(pdu_error(code), expected, "{code:#04x}");
+    }
+    assert_eq!(pdu_error(0x1A), PduError::InvalidHandle);
+    // Codes J2534 does not define
+    assert_eq!(pdu_error(0x1B), PduError::FctFailed);
+    assert_eq!(pdu_error(-1), PduError::FctFailed);
+}
+
+#[test]
+fn voltage_ioctls() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    assert_eq!(channel.ioctl(IoctlCommand::ReadVbatt, &IoctlInput::None), Ok(Some(IoctlData::Unum32(12_800))));
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::ReadVbatt, &IoctlInput::None), Ok(Some(IoctlData::Unum32(12_800))));
+
+    let voltage = |pin_on_dlc, prog_voltage_mv| IoctlInput::ProgVoltage(IoProgVoltageData { prog_voltage_mv, pin_on_dlc });
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::SetProgVoltage, &voltage(3, 18_000)), Err(PduError::MuxRscNotSupported));
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::SetProgVoltage, &voltage(12, 30_000)), Err(PduError::ResourceError));
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::SetProgVoltage, &voltage(12, 18_000)), Ok(None));
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::ReadProgVoltage, &IoctlInput::None), Ok(Some(IoctlData::Unum32(18_000))));
+    assert_eq!(driver.module_ioctl(0, IoctlCommand::GetCableId, &IoctlInput::None), Err(PduError::IdNotSupported));
+}
+
+/// Filter comparing the CAN ID of raw CAN messages
+fn id_filter(filter_type: PduFilter, id: u32) -> MessageFilter {
+    MessageFilter { filter_type, mask: vec![0xFF; 4], pattern: id.to_be_bytes().to_vec() }
+}
+
+/// Sends a request to each ID, returning the IDs of the responses which pass the filters
+fn response_ids(channel: &mut impl Channel, params: &LinkParams, ids: &[u32]) -> Vec<u32> {
+    for id in ids {
+        channel.send(&[&id.to_be_bytes()[..], &[0x3E, 0x00]].concat(), &[], params).unwrap();
+    }
+    std::iter::from_fn(|| recv(channel)).map(|r| u32::from_be_bytes(r.data[..4].try_into().unwrap())).collect()
+}
+
+#[test]
+fn can_message_filters() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let params = LinkParams::new(driver.com_params(Protocol::Iso11898Raw));
+    let requests = [0x7E0, 0x7E1, 0x98DA_10F1];
+    assert_eq!(response_ids(&mut channel, &params, &requests), [0x7E8, 0x7E9, 0x98DA_F110]);
+
+    // The filters are started on the J2534 channel, which keeps them when it is reconnected
+    let mut filters = MessageFilters::new();
+    filters.start_filter(1, id_filter(PduFilter::Pass, 0x7E8)).unwrap();
+    filters.start_filter(2, id_filter(PduFilter::Pass, 0x98DA_F110)).unwrap();
+    channel.apply_filters(&filters).unwrap();
+    assert_eq!(response_ids(&mut channel, &params, &requests), [0x7E8, 0x98DA_F110]);
+    let mut reconnected = params.clone();
+    reconnected.set(StdComParam::Baudrate, StdComParam::Baudrate.value(250_000).unwrap());
+    channel.apply_params(&reconnected).unwrap();
+    assert_eq!(response_ids(&mut channel, &reconnected, &requests), [0x7E8, 0x98DA_F110]);
+
+    // Without pass filters every frame which is not blocked passes. Block filters are only
+    // started on the J2534 channel if they ignore bit 31 of the CAN ID
+    filters.clear();
+    filters.start_filter(1, MessageFilter { mask: vec![0x7F, 0xFF, 0xFF, 0xFF], ..id_filter(PduFilter::Block, 0x7E8) }).unwrap();
+    channel.apply_filters(&filters).unwrap();
+    assert_eq!(response_ids(&mut channel, &reconnected, &requests), [0x7E9, 0x98DA_F110]);
+    channel.apply_filters(&MessageFilters::new()).unwrap();
+    assert_eq!(response_ids(&mut channel, &reconnected, &requests), [0x7E8, 0x7E9, 0x98DA_F110]);
+}
+
+/// Returns the handle of the mock module
+fn mock_module(backend: &DriverBackend<J2534>) -> ModuleHandle {
+    let item = backend.get_module_ids().unwrap();
+    // Safety: The item is a module item of the backend with one module
+    let h_mod = ModuleHandle::new(unsafe { (*(*item).p_module_data).h_mod }).unwrap();
+    backend.destroy_item(item.cast()).unwrap();
+    h_mod
+}
+
+/// Waits for the results of a link, returning their CAN IDs
+fn result_ids(backend: &DriverBackend<J2534>, h_mod: ModuleHandle, h_cll: CllHandle) -> Vec<u32> {
+    thread::sleep(Duration::from_millis(100));
+    let mut ids = Vec::new();
+    loop {
+        let item = match backend.get_event_item(Some(h_mod), Some(h_cll)) {
+            Ok(item) => item,
+            Err(PduError::EventQueueEmpty) => return ids,
+            Err(e) => panic!("{e:?}")
+        };
+        // Safety: The item is an event item of the backend
+        unsafe {
+            if (*item).item_type == PduIt::Result {
+                let result = &*(*item).p_data.cast::<ResultData>();
+                let data = std::slice::from_raw_parts(result.p_data_bytes, result.num_data_bytes as usize);
+                ids.push(u32::from_be_bytes(data[..4].try_into().unwrap()));
+            }
+        }
+        backend.destroy_item(item.cast()).unwrap();
+    }
+}
+
+#[test]
+fn message_filter_ioctls() {
+    let (_guard, options) = mock_options();
+    let backend = DriverBackend::<J2534>::construct(&options, PduTag::NULL).unwrap();
+    let h_mod = mock_module(&backend);
+    backend.module_connect(Some(h_mod)).unwrap();
+    let rsc = RscData {
+        bus_type_id: std_object_id(BusType::Iso11898_2Dwcan).raw(),
+        protocol_id: std_object_id(Protocol::Iso11898Raw).raw(),
+        num_pin_data: 0,
+        p_dlc_pin_data: ptr::null_mut()
+    };
+    let h_cll = backend.create_com_logical_link(h_mod, Some(&rsc), None, PduTag::NULL, None).unwrap();
+    let ioctl = |command, item: Option<PduDataItem>| {
+        backend.ioctl(Some(h_mod), Some(h_cll), std_object_id(command), item.as_ref()).unwrap();
+    };
+    let mut pass = IoFilterData {
+        filter_type: PduFilter::Pass,
+        filter_number: 1,
+        filter_compare_size: 4,
+        filter_mask_msg: [0; 12],
+        filter_pattern_msg: [0; 12]
+    };
+    pass.filter_mask_msg[..4].fill(0xFF);
+    pass.filter_pattern_msg[..4].copy_from_slice(&0x7E9_u32.to_be_bytes());
+    let pass_item = PduDataItem { item_type: PduIt::IoFilter, p_data: ptr::from_mut(&mut pass).cast() };
+    // Filters started before the link is connected are started when the channel opens
+    ioctl(IoctlCommand::StartMsgFilter, Some(pass_item));
+    backend.connect(h_mod, h_cll).unwrap();
+
+    // Receives every message until it is cancelled
+    let mut ctrl = CopCtrlData {
+        time: 0,
+        num_send_cycles: 1,
+        num_receive_cycles: -1,
+        temp_param_update: 0,
+        tx_flag: FlagData { num_flag_bytes: 0, p_flag_data: ptr::null_mut() },
+        num_possible_expected_responses: 0,
+        expected_response_array: ptr::null_mut()
+    };
+    backend.start_com_primitive(h_mod, h_cll, PduCopt::SendRecv, &[0x00, 0x00, 0x07, 0xE1, 0x3E], Some(&ctrl), PduTag::NULL).unwrap();
+    ctrl.num_receive_cycles = 0;
+    let send = |id: u32| {
+        let request = [&id.to_be_bytes()[..], &[0x3E]].concat();
+        backend.start_com_primitive(h_mod, h_cll, PduCopt::SendRecv, &request, Some(&ctrl), PduTag::NULL).unwrap();
+    };
+    send(0x7E0);
+    assert_eq!(result_ids(&backend, h_mod, h_cll), [0x7E9]);
+
+    let mut filter_number = 1_u32;
+    ioctl(IoctlCommand::StopMsgFilter, Some(PduDataItem { item_type: PduIt::IoUnum32, p_data: ptr::from_mut(&mut filter_number).cast() }));
+    send(0x7E0);
+    send(0x7E1);
+    assert_eq!(result_ids(&backend, h_mod, h_cll), [0x7E8, 0x7E9]);
+
+    ioctl(IoctlCommand::StartMsgFilter, Some(pass_item));
+    send(0x7E0);
+    send(0x7E1);
+    assert_eq!(result_ids(&backend, h_mod, h_cll), [0x7E9]);
+    ioctl(IoctlCommand::ClearMsgFilter, None);
+    send(0x7E0);
+    assert_eq!(result_ids(&backend, h_mod, h_cll), [0x7E8]);
+    // The queues of the backend and the buffers of the device are cleared
+    ioctl(IoctlCommand::ClearTxQueue, None);
+    ioctl(IoctlCommand::ClearRxQueue, None);
+    backend.destruct().unwrap();
+}
+
+#[test]
+fn result_timestamps() {
+    let (_guard, driver) = connected_driver();
+    let mut channel = open_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let params = LinkParams::new(driver.com_params(Protocol::Iso11898Raw));
+    let mut exchange = |wait| {
+        channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x3E], &[], &params).unwrap();
+        thread::sleep(wait);
+        recv(&mut channel).unwrap().start_msg_timestamp
+    };
+    // The results keep the time the device received them rather than the time they were read,
+    // across a wrap of the device timestamps. The requests are sent 50, 150 and 150 ms apart
+    let mut timestamps = vec![exchange(Duration::ZERO)];
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(50));
+        timestamps.push(exchange(Duration::from_millis(100)));
+    }
+    for (pair, expected) in timestamps.windows(2).zip([50_000, 150_000, 150_000]) {
+        // The timestamps are in microseconds
+        let gap = pair[1].wrapping_sub(pair[0]);
+        assert!((expected..expected + 30_000).contains(&gap), "{timestamps:?}");
+    }
+}
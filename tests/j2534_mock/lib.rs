@@ -0,0 +1,435 @@
+//! Mock J2534 (04.04) library for the tests of `backends::j2534`, built as a cdylib by the tests
+//!
+//! One device, which connects `CAN` and `ISO15765` channels at 125, 250 or 500 kbaud. An
+//! emulated ECU answers every request to a CAN ID: 11 bit IDs get a response from the ID + 8
+//! (0x7E8 for 0x7DF), 29 bit IDs from the ID with the two address bytes swapped. The response
+//! is the request with 0x40 added to the first byte, and ReadDataByIdentifier 0xF190 is answered
+//! with a VIN. Responses are only received if a filter of the channel passes them.
+//!
+//! Messages are timestamped in microseconds since the device was opened, starting at
+//! [TIMESTAMP_START] so that the timestamps wrap around 65 ms later.
+
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{c_char, c_long, c_ulong, c_void},
+    slice,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant}
+};
+
+const CAN: c_ulong = 0x05;
+const ISO15765: c_ulong = 0x06;
+
+const ISO15765_ADDR_TYPE: c_ulong = 0x0080;
+const CAN_29BIT_ID: c_ulong = 0x0100;
+
+const START_OF_MESSAGE: c_ulong = 0x0002;
+const TX_INDICATION: c_ulong = 0x0008;
+
+const PASS_FILTER: c_ulong = 0x01;
+const BLOCK_FILTER: c_ulong = 0x02;
+const FLOW_CONTROL_FILTER: c_ulong = 0x03;
+
+const SET_CONFIG: c_ulong = 0x02;
+const READ_VBATT: c_ulong = 0x03;
+const CLEAR_TX_BUFFER: c_ulong = 0x07;
+const CLEAR_RX_BUFFER: c_ulong = 0x08;
+const CLEAR_MSG_FILTERS: c_ulong = 0x0A;
+const READ_PROG_VOLTAGE: c_ulong = 0x0E;
+
+const STATUS_NOERROR: c_long = 0x00;
+const ERR_INVALID_CHANNEL_ID: c_long = 0x02;
+const ERR_INVALID_PROTOCOL_ID: c_long = 0x03;
+const ERR_NULL_PARAMETER: c_long = 0x04;
+const ERR_TIMEOUT: c_long = 0x09;
+const ERR_DEVICE_NOT_CONNECTED: c_long = 0x08;
+const ERR_INVALID_MSG: c_long = 0x0A;
+const ERR_EXCEEDED_LIMIT: c_long = 0x0C;
+const ERR_DEVICE_IN_USE: c_long = 0x0E;
+const ERR_INVALID_IOCTL_ID: c_long = 0x0F;
+const ERR_BUFFER_EMPTY: c_long = 0x10;
+const ERR_PIN_INVALID: c_long = 0x13;
+const ERR_MSG_PROTOCOL_ID: c_long = 0x15;
+const ERR_NO_FLOW_CONTROL: c_long = 0x17;
+const ERR_INVALID_BAUDRATE: c_long = 0x19;
+const ERR_INVALID_FILTER_ID: c_long = 0x16;
+const ERR_INVALID_DEVICE_ID: c_long = 0x1A;
+
+const DEVICE_ID: c_ulong = 1;
+const VBATT_MV: c_ulong = 12_800;
+const VIN: &[u8] = b"WDD2220011A000001";
+/// Timestamp of the device when it is opened
+const TIMESTAMP_START: u32 = 0xFFFF_0000;
+
+#[repr(C)]
+pub struct PassThruMsg {
+    protocol_id: c_ulong,
+    rx_status: c_ulong,
+    tx_flags: c_ulong,
+    timestamp: c_ulong,
+    data_size: c_ulong,
+    extra_data_index: c_ulong,
+    data: [u8; 4128]
+}
+
+#[repr(C)]
+pub struct SConfig {
+    parameter: c_ulong,
+    value: c_ulong
+}
+
+#[repr(C)]
+pub struct SConfigList {
+    num_of_params: c_ulong,
+    config_ptr: *mut SConfig
+}
+
+struct Filter {
+    filter_type: c_ulong,
+    mask: Vec<u8>,
+    pattern: Vec<u8>,
+    flow_control: Vec<u8>
+}
+
+impl Filter {
+    fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.mask.len() && self.mask.iter().zip(&self.pattern).zip(data).all(|((m, p), d)| m & d == m & p)
+    }
+}
+
+#[derive(Clone)]
+struct Message {
+    rx_status: c_ulong,
+    timestamp: c_ulong,
+    data: Vec<u8>
+}
+
+struct Channel {
+    protocol_id: c_ulong,
+    filters: HashMap<c_ulong, Filter>,
+    next_filter_id: c_ulong,
+    config: HashMap<c_ulong, c_ulong>,
+    rx: VecDeque<Message>
+}
+
+#[derive(Default)]
+struct State {
+    open: bool,
+    opened: Option<Instant>,
+    /// The device keeps its channels, but can not connect new ones
+    unplugged: bool,
+    next_id: c_ulong,
+    channels: HashMap<c_ulong, Channel>,
+    prog_voltage: c_ulong,
+    last_error: String
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Runs a function on the state, recording the description of a failure
+fn with_state(f: impl FnOnce(&mut State) -> Result<(), (c_long, &'static str)>) -> c_long {
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = guard.get_or_insert_with(State::default);
+    match f(state) {
+        Ok(()) => STATUS_NOERROR,
+        Err((code, description)) => {
+            state.last_error = description.to_string();
+            code
+        }
+    }
+}
+
+fn check_device(state: &State, device_id: c_ulong) -> Result<(), (c_long, &'static str)> {
+    match state.open && device_id == DEVICE_ID {
+        true => Ok(()),
+        false => Err((ERR_INVALID_DEVICE_ID, "Device is not open"))
+    }
+}
+
+/// Returns the timestamp of the device
+fn timestamp(state: &State) -> c_ulong {
+    let elapsed = state.opened.map_or(0, |opened| opened.elapsed().as_micros() as u32);
+    TIMESTAMP_START.wrapping_add(elapsed) as c_ulong
+}
+
+/// Returns the response of the emulated ECU to a request
+fn respond(id: u32, data: &[u8]) -> Option<(u32, Vec<u8>)> {
+    let response_id = match id > 0x7FF {
+        true => (id & 0xFFFF_0000) | ((id & 0xFF) << 8) | ((id >> 8) & 0xFF),
+        false if id == 0x7DF => 0x7E8,
+        false => id + 8
+    };
+    let mut response = data.to_vec();
+    *response.first_mut()? = response[0].wrapping_add(0x40);
+    if data == [0x22, 0xF1, 0x90] {
+        response.extend(VIN);
+    }
+    Some((response_id, response))
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruOpen(_name: *const c_void, device_id: *mut c_ulong) -> c_long {
+    with_state(|state| {
+        if device_id.is_null() {
+            return Err((ERR_NULL_PARAMETER, "No device ID pointer"));
+        }
+        if state.open {
+            return Err((ERR_DEVICE_IN_USE, "Device is already open"));
+        }
+        state.open = true;
+        state.opened = Some(Instant::now());
+        state.unplugged = false;
+        *device_id = DEVICE_ID;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruClose(device_id: c_ulong) -> c_long {
+    with_state(|state| {
+        check_device(state, device_id)?;
+        state.open = false;
+        state.channels.clear();
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruConnect(
+    device_id: c_ulong,
+    protocol_id: c_ulong,
+    _flags: c_ulong,
+    baudrate: c_ulong,
+    channel_id: *mut c_ulong
+) -> c_long {
+    with_state(|state| {
+        check_device(state, device_id)?;
+        if state.unplugged {
+            return Err((ERR_DEVICE_NOT_CONNECTED, "Device is unplugged"));
+        }
+        if protocol_id != CAN && protocol_id != ISO15765 {
+            return Err((ERR_INVALID_PROTOCOL_ID, "Protocol is not supported"));
+        }
+        if ![125_000, 250_000, 500_000].contains(&baudrate) {
+            return Err((ERR_INVALID_BAUDRATE, "Baud rate is not supported"));
+        }
+        state.next_id += 1;
+        let channel =
+            Channel { protocol_id, filters: HashMap::new(), next_filter_id: 0, config: HashMap::new(), rx: VecDeque::new() };
+        state.channels.insert(state.next_id, channel);
+        *channel_id = state.next_id;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruDisconnect(channel_id: c_ulong) -> c_long {
+    with_state(|state| match state.channels.remove(&channel_id) {
+        Some(_) => Ok(()),
+        None => Err((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruReadMsgs(channel_id: c_ulong, msgs: *mut PassThruMsg, count: *mut c_ulong, timeout: c_ulong) -> c_long {
+    let mut read = 0;
+    let code = with_state(|state| {
+        let channel = state.channels.get_mut(&channel_id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+        let msgs = slice::from_raw_parts_mut(msgs, *count as usize);
+        for msg in msgs.iter_mut() {
+            let Some(rx) = channel.rx.pop_front() else {
+                break;
+            };
+            msg.protocol_id = channel.protocol_id;
+            msg.rx_status = rx.rx_status;
+            msg.timestamp = rx.timestamp;
+            msg.data_size = rx.data.len() as c_ulong;
+            msg.extra_data_index = rx.data.len() as c_ulong;
+            msg.data[..rx.data.len()].copy_from_slice(&rx.data);
+            read += 1;
+        }
+        Ok(())
+    });
+    *count = read;
+    match (code, read, timeout) {
+        (STATUS_NOERROR, 0, 0) => ERR_BUFFER_EMPTY,
+        (STATUS_NOERROR, 0, timeout) => {
+            thread::sleep(Duration::from_millis(timeout as u64));
+            ERR_TIMEOUT
+        },
+        (code, ..) => code
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruWriteMsgs(channel_id: c_ulong, msgs: *const PassThruMsg, count: *mut c_ulong, _timeout: c_ulong) -> c_long {
+    with_state(|state| {
+        let timestamp = timestamp(state);
+        let channel = state.channels.get_mut(&channel_id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+        for msg in slice::from_raw_parts(msgs, *count as usize) {
+            if msg.protocol_id != channel.protocol_id {
+                return Err((ERR_MSG_PROTOCOL_ID, "Message protocol does not match the channel"));
+            }
+            let data = &msg.data[..msg.data_size as usize];
+            let header_len = if msg.tx_flags & ISO15765_ADDR_TYPE != 0 { 5 } else { 4 };
+            let max_len = if channel.protocol_id == CAN { 4 + 8 } else { 4095 + header_len };
+            if data.len() < header_len || data.len() > max_len {
+                return Err((ERR_INVALID_MSG, "Message length is invalid"));
+            }
+            let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let extended = msg.tx_flags & CAN_29BIT_ID != 0;
+            if channel.protocol_id == ISO15765 {
+                let single_frame = data.len() - header_len <= 7 - (header_len - 4);
+                let flow_control = channel.filters.values().any(|f| f.filter_type == FLOW_CONTROL_FILTER && f.flow_control == data[..header_len]);
+                if !single_frame && !flow_control {
+                    return Err((ERR_NO_FLOW_CONTROL, "No flow control filter for the CAN ID"));
+                }
+                channel.rx.push_back(Message { rx_status: TX_INDICATION, timestamp, data: data[..header_len].to_vec() });
+            }
+            let Some((response_id, response)) = respond(id, &data[header_len..]) else {
+                continue;
+            };
+            let mut rx = response_id.to_be_bytes().to_vec();
+            rx.extend(&data[4..header_len]);
+            rx.extend(&response);
+            let passed = match channel.protocol_id {
+                ISO15765 => channel.filters.values().any(|f| f.filter_type == FLOW_CONTROL_FILTER && f.matches(&rx)),
+                _ => {
+                    channel.filters.values().any(|f| f.filter_type == PASS_FILTER && f.matches(&rx))
+                        && !channel.filters.values().any(|f| f.filter_type == BLOCK_FILTER && f.matches(&rx))
+                }
+            };
+            if passed {
+                let rx_status = (if extended { CAN_29BIT_ID } else { 0 }) | (msg.tx_flags & ISO15765_ADDR_TYPE);
+                if channel.protocol_id == ISO15765 && response.len() > 7 {
+                    let start = Message { rx_status: rx_status | START_OF_MESSAGE, timestamp, data: rx[..header_len].to_vec() };
+                    channel.rx.push_back(start);
+                }
+                channel.rx.push_back(Message { rx_status, timestamp, data: rx });
+            }
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruStartMsgFilter(
+    channel_id: c_ulong,
+    filter_type: c_ulong,
+    mask: *const PassThruMsg,
+    pattern: *const PassThruMsg,
+    flow_control: *const PassThruMsg,
+    filter_id: *mut c_ulong
+) -> c_long {
+    with_state(|state| {
+        let channel = state.channels.get_mut(&channel_id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+        if mask.is_null() || pattern.is_null() || (filter_type == FLOW_CONTROL_FILTER && flow_control.is_null()) {
+            return Err((ERR_NULL_PARAMETER, "Filter message is missing"));
+        }
+        let bytes = |msg: *const PassThruMsg| (&(*msg).data)[..(*msg).data_size as usize].to_vec();
+        let filter = Filter {
+            filter_type,
+            mask: bytes(mask),
+            pattern: bytes(pattern),
+            flow_control: if flow_control.is_null() { Vec::new() } else { bytes(flow_control) }
+        };
+        let id = channel.next_filter_id;
+        channel.next_filter_id += 1;
+        channel.filters.insert(id, filter);
+        *filter_id = id;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruStopMsgFilter(channel_id: c_ulong, filter_id: c_ulong) -> c_long {
+    with_state(|state| {
+        let channel = state.channels.get_mut(&channel_id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+        match channel.filters.remove(&filter_id) {
+            Some(_) => Ok(()),
+            None => Err((ERR_INVALID_FILTER_ID, "Filter is not started"))
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruSetProgrammingVoltage(device_id: c_ulong, pin: c_ulong, voltage: c_ulong) -> c_long {
+    with_state(|state| {
+        check_device(state, device_id)?;
+        if ![0, 6, 9, 11, 12, 13, 14, 15].contains(&pin) {
+            return Err((ERR_PIN_INVALID, "Pin can not be switched"));
+        }
+        if !(5_000..=20_000).contains(&voltage) && voltage < 0xFFFF_FFFE {
+            return Err((ERR_EXCEEDED_LIMIT, "Voltage is out of range"));
+        }
+        state.prog_voltage = voltage;
+        Ok(())
+    })
+}
+
+unsafe fn write_string(dest: *mut c_char, s: &str) {
+    for (i, b) in s.bytes().chain([0]).enumerate() {
+        *dest.add(i) = b as c_char;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruReadVersion(device_id: c_ulong, firmware: *mut c_char, dll: *mut c_char, api: *mut c_char) -> c_long {
+    with_state(|state| {
+        check_device(state, device_id)?;
+        write_string(firmware, "1.2.3");
+        write_string(dll, "0.1.0");
+        write_string(api, "04.04");
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruGetLastError(description: *mut c_char) -> c_long {
+    let guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    write_string(description, guard.as_ref().map_or("", |s| s.last_error.as_str()));
+    STATUS_NOERROR
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn PassThruIoctl(id: c_ulong, ioctl_id: c_ulong, input: *const c_void, output: *mut c_void) -> c_long {
+    with_state(|state| match ioctl_id {
+        READ_VBATT | READ_PROG_VOLTAGE => {
+            check_device(state, id)?;
+            *output.cast::<c_ulong>() = if ioctl_id == READ_VBATT { VBATT_MV } else { state.prog_voltage };
+            Ok(())
+        },
+        SET_CONFIG => {
+            let channel = state.channels.get_mut(&id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+            let list = &*input.cast::<SConfigList>();
+            for config in slice::from_raw_parts(list.config_ptr, list.num_of_params as usize) {
+                channel.config.insert(config.parameter, config.value);
+            }
+            Ok(())
+        },
+        CLEAR_TX_BUFFER | CLEAR_RX_BUFFER | CLEAR_MSG_FILTERS => {
+            let channel = state.channels.get_mut(&id).ok_or((ERR_INVALID_CHANNEL_ID, "Channel is not connected"))?;
+            match ioctl_id {
+                // Messages are sent as soon as they are written
+                CLEAR_TX_BUFFER => (),
+                CLEAR_RX_BUFFER => channel.rx.clear(),
+                _ => channel.filters.clear()
+            }
+            Ok(())
+        },
+        _ => Err((ERR_INVALID_IOCTL_ID, "IOCTL is not supported"))
+    })
+}
+
+/// Unplugs the device (1) or plugs it back in (0). Not part of J2534, the tests load it from the
+/// library themselves
+#[no_mangle]
+pub extern "system" fn MockSetUnplugged(unplugged: c_ulong) {
+    with_state(|state| {
+        state.unplugged = unplugged != 0;
+        Ok(())
+    });
+}
@@ -0,0 +1,277 @@
+//! Tests of the K-Line backend against the simulator on a pseudo terminal
+
+use std::{
+    thread,
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    backends::kline::{
+        checksum,
+        sim::{KLineSimulator, SimConfig},
+        KLine, KLineTiming
+    },
+    provider::{Channel, IoctlInput},
+    IoctlCommand, ParamStructAccessTiming, PduError, PduErrorEvt, Protocol, StdComParam, TimingSet
+};
+
+mod common;
+
+use common::{echo_ecu, open_channel, params, start_sim};
+
+const KWP: Protocol = Protocol::Iso14230_3OnIso14230_2;
+
+const ISO9141: Protocol = Protocol::IsoObdOnIso9141_2;
+
+/// ComParams of 5 baud init with the physical address of an ECU
+const FIVE_BAUD: [(StdComParam, u32); 2] = [(StdComParam::InitializationSettings, 1), (StdComParam::FiveBaudAddressPhys, 0x10)];
+
+/// Starts the simulator with an ECU at address 0x10
+fn start_ecu(config: SimConfig) -> (KLineSimulator, KLine) {
+    let (sim, driver) = start_sim(config);
+    sim.add_ecu(0x10, echo_ecu);
+    (sim, driver)
+}
+
+#[test]
+fn five_baud_init() {
+    let (sim, driver) = start_ecu(SimConfig::default());
+    let params = params(&driver, KWP, &FIVE_BAUD);
+    let mut channel = open_channel(&driver, KWP, &params).unwrap();
+    assert_eq!(channel.start_comm(&[], &params), Ok(Some(vec![0xEF, 0x8F])));
+    assert_eq!(channel.key_bytes(), Some([0xEF, 0x8F]));
+    assert!(sim.is_initialized());
+
+    channel.send(&[0x3E, 0x01], &[], &params).unwrap();
+    let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(response.data, [0x7E, 0x01]);
+    assert_eq!(response.extra_info.unwrap().header, [0x82, 0xF1, 0x10]);
+}
+
+#[test]
+fn five_baud_init_failures() {
+    let (sim, driver) = start_ecu(SimConfig::default());
+    // No ECU answers the address
+    let unknown = params(&driver, KWP, &[FIVE_BAUD[0], (StdComParam::FiveBaudAddressPhys, 0x20)]);
+    let mut channel = open_channel(&driver, KWP, &unknown).unwrap();
+    assert_eq!(channel.start_comm(&[], &unknown), Err(PduError::FctFailed));
+    assert_eq!(channel.key_bytes(), None);
+
+    // Key bytes of another protocol are not confirmed
+    let cases = [
+        (KWP, [0x08, 0x08], false),
+        (KWP, [0xEE, 0x8F], false),
+        (KWP, [0xE9, 0x8F], true),
+        (ISO9141, [0xEF, 0x8F], false),
+        (ISO9141, [0x94, 0x94], true)
+    ];
+    for (protocol, key_bytes, valid) in cases {
+        sim.set_config(SimConfig { key_bytes, ..SimConfig::default() });
+        let params = params(&driver, protocol, &FIVE_BAUD);
+        let mut channel = open_channel(&driver, protocol, &params).unwrap();
+        let expected = if valid { Ok(Some(key_bytes.to_vec())) } else { Err(PduError::FctFailed) };
+        assert_eq!(channel.start_comm(&[], &params), expected, "{protocol:?} with {key_bytes:02X?}");
+    }
+}
+
+#[test]
+fn checksum_errors() {
+    let (sim, driver) = start_ecu(SimConfig::default());
+    let params = params(&driver, KWP, &[]);
+    let mut channel = open_channel(&driver, KWP, &params).unwrap();
+    assert_eq!(channel.start_comm(&[], &params), Ok(Some(vec![0xC1, 0xEF, 0x8F])));
+
+    let mut frame = vec![0x82, 0xF1, 0x10, 0x7E, 0x01];
+    frame.push(checksum(&frame).wrapping_add(1));
+    sim.send_bytes(&frame);
+    assert_eq!(channel.recv(Duration::from_secs(1)), Ok(None));
+    assert_eq!(channel.poll_error(), Some(PduErrorEvt::FrameStruct));
+
+    // Frames after the broken one are received again
+    *frame.last_mut().unwrap() = checksum(&frame[..5]);
+    sim.send_bytes(&frame);
+    assert_eq!(channel.recv(Duration::from_secs(1)).unwrap().unwrap().data, [0x7E, 0x01]);
+    assert_eq!(channel.poll_error(), None);
+}
+
+#[test]
+fn p2_timing() {
+    let (sim, driver) = start_ecu(SimConfig { response_delay: Duration::from_millis(100), ..SimConfig::default() });
+    // The StartCommunication response arrives after P2Max
+    let short = params(&driver, KWP, &[(StdComParam::P2Max, 50_000)]);
+    let mut channel = open_channel(&driver, KWP, &short).unwrap();
+    assert_eq!(channel.start_comm(&[], &short), Err(PduError::FctFailed));
+    // The late response is dropped before the next request
+    thread::sleep(Duration::from_millis(100));
+
+    let long = params(&driver, KWP, &[(StdComParam::P2Max, 300_000)]);
+    channel.apply_params(&long).unwrap();
+    assert_eq!(channel.start_comm(&[], &long), Ok(Some(vec![0xC1, 0xEF, 0x8F])));
+    assert!(sim.is_initialized());
+}
+
+#[test]
+fn p3_timing() {
+    let (_sim, driver) = start_ecu(SimConfig::default());
+    for p3_min in [Duration::from_millis(20), Duration::from_millis(250)] {
+        let params = params(&driver, KWP, &[(StdComParam::P3Min, p3_min.as_micros() as u32)]);
+        let mut channel = open_channel(&driver, KWP, &params).unwrap();
+        channel.start_comm(&[], &params).unwrap();
+        channel.send(&[0x3E, 0x01], &[], &params).unwrap();
+        channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+        // The next request waits for P3Min after the response
+        let start = Instant::now();
+        channel.send(&[0x3E, 0x01], &[], &params).unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= p3_min, "request sent {elapsed:?} after the response, P3Min is {p3_min:?}");
+        assert!(elapsed < p3_min + Duration::from_millis(100), "request sent {elapsed:?} after the response");
+        channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    }
+}
+
+#[test]
+fn fast_init() {
+    let (sim, driver) = start_ecu(SimConfig::default());
+    let params = params(&driver, KWP, &[]);
+    let mut channel = open_channel(&driver, KWP, &params).unwrap();
+    // Requests other than StartCommunication are not answered before communication starts
+    assert_eq!(channel.start_comm(&[0x3E, 0x01], &params), Err(PduError::FctFailed));
+    assert!(!sim.is_initialized());
+    assert_eq!(channel.start_comm(&[], &params), Ok(Some(vec![0xC1, 0xEF, 0x8F])));
+    assert_eq!(channel.key_bytes(), Some([0xEF, 0x8F]));
+    assert!(sim.is_initialized());
+
+    assert_eq!(channel.stop_comm(&[], &params), Ok(Some(vec![0xC2])));
+    assert!(!sim.is_initialized());
+    assert_eq!(channel.key_bytes(), None);
+    // The data of the ComPrimitive is sent as the request
+    assert_eq!(channel.start_comm(&[0x81], &params), Ok(Some(vec![0xC1, 0xEF, 0x8F])));
+    assert!(sim.is_initialized());
+
+    // ISO 9141-2 only has 5 baud init
+    let iso9141 = self::params(&driver, ISO9141, &[(StdComParam::InitializationSettings, 2)]);
+    let mut channel = open_channel(&driver, ISO9141, &iso9141).unwrap();
+    assert_eq!(channel.start_comm(&[], &iso9141), Err(PduError::ValueNotSupported));
+}
+
+#[test]
+fn send_break() {
+    let (_sim, driver) = start_ecu(SimConfig::default());
+    let params = params(&driver, KWP, &[(StdComParam::TInil, 40_000)]);
+    let mut channel = open_channel(&driver, KWP, &params).unwrap();
+    // Without input, the break lasts TInil
+    let start = Instant::now();
+    assert_eq!(channel.ioctl(IoctlCommand::SendBreak, &IoctlInput::None), Ok(None));
+    assert!(start.elapsed() >= Duration::from_millis(40));
+    let start = Instant::now();
+    assert_eq!(channel.ioctl(IoctlCommand::SendBreak, &IoctlInput::Unum32(100_000)), Ok(None));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    assert_eq!(
+        channel.ioctl(IoctlCommand::SendBreak, &IoctlInput::ByteArray(vec![1])),
+        Err(PduError::InvalidParameters)
+    );
+    assert_eq!(channel.ioctl(IoctlCommand::ClearTxQueue, &IoctlInput::None), Err(PduError::IdNotSupported));
+}
+
+#[test]
+fn iso9141_exchange() {
+    let (sim, driver) = start_ecu(SimConfig { key_bytes: [0x08, 0x08], ..SimConfig::default() });
+    let params = params(&driver, ISO9141, &[]);
+    let mut channel = open_channel(&driver, ISO9141, &params).unwrap();
+    assert_eq!(channel.start_comm(&[], &params), Ok(Some(vec![0x08, 0x08])));
+    assert!(sim.is_initialized());
+
+    channel.send(&[0x01, 0x00], &[], &params).unwrap();
+    let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(response.data, [0x41, 0x00]);
+    let extra_info = response.extra_info.unwrap();
+    assert_eq!(extra_info.header, [0x48, 0x6B, 0x10]);
+    assert_eq!(extra_info.footer, [checksum(&[0x48, 0x6B, 0x10, 0x41, 0x00])]);
+    // ISO 9141-2 has no StopCommunication request
+    assert_eq!(channel.stop_comm(&[], &params), Ok(None));
+    assert!(sim.is_initialized());
+}
+
+#[test]
+fn header_formats() {
+    let (_sim, driver) = start_ecu(SimConfig::default());
+    let init = params(&driver, KWP, &[(StdComParam::P4Min, 0)]);
+    let mut channel = open_channel(&driver, KWP, &init).unwrap();
+    channel.start_comm(&[], &init).unwrap();
+
+    // The simulator answers with the header format of the request, so the response header
+    // shows the format sent. Long requests have 70 bytes, more than the format byte holds
+    let short = [0x3E, 0x01];
+    let long: Vec<u8> = [0x31].into_iter().chain(0..69).collect();
+    let cases = [
+        (0, vec![0x02], None),
+        (1, vec![0x00, 0x02], Some(vec![0x00, 70])),
+        (2, vec![0x82, 0xF1, 0x10], None),
+        (3, vec![0x80, 0xF1, 0x10, 0x02], Some(vec![0x80, 0xF1, 0x10, 70])),
+        (4, vec![0x02], Some(vec![0x00, 70])),
+        (5, vec![0x82, 0xF1, 0x10], Some(vec![0x80, 0xF1, 0x10, 70]))
+    ];
+    for (format, short_header, long_header) in cases {
+        let params = params(&driver, KWP, &[(StdComParam::P4Min, 0), (StdComParam::HeaderFormatKw, format)]);
+        for (request, header) in [(&short[..], Some(short_header)), (&long[..], long_header)] {
+            let Some(header) = header else {
+                assert_eq!(channel.send(request, &[], &params), Err(PduError::InvalidParameters), "format {format}");
+                continue;
+            };
+            channel.send(request, &[], &params).unwrap();
+            let response = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+            assert_eq!(response.extra_info.unwrap().header, header, "format {format}");
+            assert_eq!(response.data.len(), request.len());
+        }
+    }
+}
+
+/// Access timing entry, in the units of the structure (0.5 ms, P3Max 250 ms)
+fn access_timing(timing_set: TimingSet, p2_max: u8, p3_min: u8) -> ParamStructAccessTiming {
+    ParamStructAccessTiming { p2_min: 10, p2_max, p3_min, p3_max: 8, p4_min: 0, timing_set }
+}
+
+#[test]
+fn access_timing_override() {
+    let sim_config = SimConfig { response_delay: Duration::from_millis(60), ..SimConfig::default() };
+    let (sim, driver) = start_ecu(sim_config);
+    let entries = [access_timing(TimingSet::Normal, 40, 20), access_timing(TimingSet::Extended, 250, 40)];
+    let mut params = params(&driver, KWP, &[(StdComParam::P2Max, 300_000)]);
+    let value = StdComParam::AccessTimingOverride.access_timing(&entries).unwrap();
+    params.set(StdComParam::AccessTimingOverride, value);
+    let mut channel = open_channel(&driver, KWP, &params).unwrap();
+    let normal = KLineTiming {
+        p2_min: Duration::from_millis(5),
+        p2_max: Duration::from_millis(20),
+        p3_min: Duration::from_millis(10),
+        p3_max: Duration::from_secs(2),
+        p4_min: Duration::ZERO
+    };
+    assert_eq!(channel.timing(&params), normal);
+    // The normal set replaces CP_P2Max, so the response arrives too late
+    assert_eq!(channel.response_timing(&params).p2_max, Duration::from_millis(20));
+    assert_eq!(channel.start_comm(&[], &params), Err(PduError::FctFailed));
+    thread::sleep(Duration::from_millis(100));
+
+    // Key byte 1 of the simulator selects the extended set
+    sim.set_config(SimConfig { key_bytes: [0x91, 0x8F], ..sim_config });
+    let five_baud = {
+        let mut five_baud = params.clone();
+        for (param, value) in FIVE_BAUD {
+            five_baud.set(param, param.value(value).unwrap());
+        }
+        five_baud
+    };
+    assert_eq!(channel.start_comm(&[], &five_baud), Ok(Some(vec![0x91, 0x8F])));
+    let extended = KLineTiming { p2_max: Duration::from_millis(125), p3_min: Duration::from_millis(20), ..normal };
+    assert_eq!(channel.timing(&params), extended);
+    assert_eq!(channel.response_timing(&params).p2_max, Duration::from_millis(125));
+    assert_eq!(channel.stop_comm(&[], &params), Ok(Some(vec![0xC2])));
+    assert_eq!(channel.timing(&params), normal);
+
+    // The tester's set takes precedence
+    let entries = [entries[0], entries[1], access_timing(TimingSet::OverrideTester, 100, 0)];
+    params.set(StdComParam::AccessTimingOverride, StdComParam::AccessTimingOverride.access_timing(&entries).unwrap());
+    assert_eq!(channel.timing(&params).p2_max, Duration::from_millis(50));
+}
@@ -0,0 +1,114 @@
+//! Tests of the ComParam buffers of ComLogicalLinks
+
+use std::sync::Arc;
+
+use dpdu_rust::{
+    provider::{std_object_id, ComParamStore, Event, EventData, EventQueue, EventSource, PhysicalParams},
+    CllHandle, InfoData, PduError, PduInfo, StdComParam
+};
+
+/// ComParams of the links, the bit rate being physical
+const DEFAULTS: [(StdComParam, u32); 2] = [(StdComParam::Baudrate, 500_000), (StdComParam::P2Max, 50_000)];
+
+/// A link on the shared resource, with its event queue
+struct Link {
+    params: ComParamStore,
+    events: Arc<EventQueue>
+}
+
+impl Link {
+    fn new(raw: u32, physical: &Arc<PhysicalParams>) -> Self {
+        let h_cll = CllHandle::new(raw).unwrap();
+        let events = Arc::new(EventQueue::new(EventSource { h_cll: Some(h_cll), ..Default::default() }));
+        let params = ComParamStore::new(
+            h_cll,
+            events.clone(),
+            physical.clone(),
+            DEFAULTS.map(|(p, value)| (std_object_id(p), p.def(), p.value(value).unwrap()))
+        );
+        Self { params, events }
+    }
+
+    fn set(&self, param: StdComParam, value: u32) {
+        self.params.set(std_object_id(param), param.value(value).unwrap()).unwrap();
+    }
+
+    fn get(&self, param: StdComParam) -> Option<u32> {
+        self.params.get(std_object_id(param)).unwrap().as_u32()
+    }
+
+    fn active(&self, param: StdComParam) -> Option<u32> {
+        self.params.active(std_object_id(param)).and_then(|v| v.as_u32())
+    }
+
+    /// Returns the ComParam IDs of the change events of the link
+    fn changes(&self) -> Vec<u32> {
+        std::iter::from_fn(|| self.events.pop().ok())
+            .map(|e: Event| match e.data {
+                EventData::Info(InfoData { info_code: PduInfo::ComParamChange, extra_info_data }) => extra_info_data,
+                data => panic!("unexpected event {data:?}")
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn physical_changes_of_other_links_are_kept() {
+    let physical = Arc::new(PhysicalParams::new());
+    let (a, b) = (Link::new(1, &physical), Link::new(2, &physical));
+    a.set(StdComParam::Baudrate, 250_000);
+    a.params.update(0).unwrap();
+    assert_eq!(b.changes(), [std_object_id(StdComParam::Baudrate).raw()]);
+    // The other link takes the new value into both of its buffers
+    assert_eq!(b.get(StdComParam::Baudrate), Some(250_000));
+    assert_eq!(b.active(StdComParam::Baudrate), Some(250_000));
+
+    // Updating an unrelated ComParam does not write back the old bit rate
+    b.set(StdComParam::P2Max, 100_000);
+    b.params.update(0).unwrap();
+    assert_eq!(physical.value(std_object_id(StdComParam::Baudrate)).and_then(|v| v.as_u32()), Some(250_000));
+    assert_eq!(a.active(StdComParam::Baudrate), Some(250_000));
+    assert_eq!(b.active(StdComParam::P2Max), Some(100_000));
+    assert_eq!(a.active(StdComParam::P2Max), Some(50_000));
+    assert!(a.changes().is_empty());
+    assert!(b.params.snapshot(true).is_ok());
+}
+
+#[test]
+fn unapplied_physical_changes_are_kept() {
+    let physical = Arc::new(PhysicalParams::new());
+    let (a, b) = (Link::new(1, &physical), Link::new(2, &physical));
+    b.set(StdComParam::Baudrate, 125_000);
+    a.set(StdComParam::Baudrate, 250_000);
+    a.params.update(0).unwrap();
+    // The other link still holds its own value in the working buffer, and applies it later
+    assert_eq!(b.get(StdComParam::Baudrate), Some(125_000));
+    assert_eq!(b.active(StdComParam::Baudrate), Some(250_000));
+    assert_eq!(b.params.snapshot(true).err(), Some(PduError::TempParamNotAllowed));
+    assert_eq!(b.changes().len(), 1);
+    b.params.update(0).unwrap();
+    assert_eq!(a.get(StdComParam::Baudrate), Some(125_000));
+    assert_eq!(a.changes(), [std_object_id(StdComParam::Baudrate).raw()]);
+
+    // Restoring drops the change which was not applied
+    a.set(StdComParam::Baudrate, 1_000_000);
+    a.params.restore();
+    a.params.update(0).unwrap();
+    assert_eq!(a.get(StdComParam::Baudrate), Some(125_000));
+    assert!(b.changes().is_empty());
+}
+
+#[test]
+fn pending_params_of_a_locked_resource() {
+    let physical = Arc::new(PhysicalParams::new());
+    let (a, b) = (Link::new(1, &physical), Link::new(2, &physical));
+    b.set(StdComParam::Baudrate, 125_000);
+    physical.set_lock_owner(Some(CllHandle::new(1).unwrap()));
+    assert_eq!(b.params.pending().err(), Some(PduError::ComParamLocked));
+    assert_eq!(b.params.update(0), Err(PduError::ComParamLocked));
+    a.set(StdComParam::Baudrate, 250_000);
+    let pending = a.params.pending().unwrap();
+    assert_eq!(pending.get_u32(std_object_id(StdComParam::Baudrate)), Some(250_000));
+    // Nothing changes until the update
+    assert_eq!(a.active(StdComParam::Baudrate), Some(500_000));
+}
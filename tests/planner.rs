@@ -0,0 +1,229 @@
+//! Tests of planning concurrent ComLogicalLinks against the resources of a provider
+
+use std::{cell::RefCell, sync::Arc};
+
+use dpdu_rust::{
+    provider::{std_object_id, EventQueue, EventSource, ItemAllocator, ResourceInfo, ResourceManager},
+    BusType, CllHandle, LinkCandidate, ModuleHandle, ModuleItem, PduError, PduItem, PinData, Protocol, ResourceId,
+    ResourcePlanner, ResourceRequest, ResourceStatus, RscConflictItem, RscData, RscIdItem, UnplacedReason,
+    LOCK_MASK_PHYSICAL_COMPARAMS, LOCK_MASK_TX_QUEUE
+};
+
+/// Resources of the provider, and the items it has allocated
+struct Api {
+    resources: ResourceManager,
+    items: ItemAllocator
+}
+
+thread_local! {
+    /// API of the test running on this thread
+    static API: RefCell<Api> = RefCell::new(Api { resources: ResourceManager::new(), items: ItemAllocator::new() });
+}
+
+extern "C" fn get_resource_ids(h_mod: u32, rsc_data: *mut RscData, list: *mut *mut RscIdItem) -> PduError {
+    API.with_borrow(|api| {
+        // Safety: The planner passes a valid request
+        match unsafe { api.resources.resource_ids(ModuleHandle::new(h_mod), &*rsc_data) } {
+            Ok(ids) => {
+                // Safety: The planner passes a valid output pointer
+                unsafe { *list = api.items.alloc_resource_ids(&ids) };
+                PduError::StatusNoError
+            },
+            Err(e) => e
+        }
+    })
+}
+
+extern "C" fn get_conflicting_resources(
+    resource_id: u32,
+    modules: *mut ModuleItem,
+    list: *mut *mut RscConflictItem
+) -> PduError {
+    // Safety: The planner passes a valid module list
+    let modules: Vec<ModuleHandle> = unsafe {
+        let modules = &*modules;
+        std::slice::from_raw_parts(modules.p_module_data, modules.num_entries as usize)
+            .iter()
+            .filter_map(|m| ModuleHandle::new(m.h_mod))
+            .collect()
+    };
+    API.with_borrow(|api| match api.resources.conflicts(ResourceId::new(resource_id).unwrap(), &modules) {
+        Ok(conflicts) => {
+            // Safety: The planner passes a valid output pointer
+            unsafe { *list = api.items.alloc_conflicts(&conflicts) };
+            PduError::StatusNoError
+        },
+        Err(e) => e
+    })
+}
+
+extern "C" fn destroy_item(item: *mut PduItem) -> PduError {
+    API.with_borrow(|api| match api.items.destroy(item) {
+        Ok(_) => PduError::StatusNoError,
+        Err(_) => PduError::InvalidParameters
+    })
+}
+
+fn planner() -> ResourcePlanner {
+    ResourcePlanner::new(get_resource_ids, get_conflicting_resources, destroy_item)
+}
+
+/// Returns true if the planner destroyed every item of the API
+fn items_destroyed() -> bool {
+    API.with_borrow(|api| api.items.live_count() == 0)
+}
+
+const MOD_A: ModuleHandle = match ModuleHandle::new(1) {
+    Some(h) => h,
+    None => unreachable!()
+};
+
+const MOD_B: ModuleHandle = match ModuleHandle::new(2) {
+    Some(h) => h,
+    None => unreachable!()
+};
+
+const CAN: ResourceId = match ResourceId::new(1) {
+    Some(id) => id,
+    None => unreachable!()
+};
+
+const KWP: ResourceId = match ResourceId::new(2) {
+    Some(id) => id,
+    None => unreachable!()
+};
+
+const ISO9141: ResourceId = match ResourceId::new(3) {
+    Some(id) => id,
+    None => unreachable!()
+};
+
+fn pins(numbers: &[u32]) -> Vec<PinData> {
+    numbers.iter().map(|n| PinData { dlc_pin_number: *n, dlc_pin_type_id: 0 }).collect()
+}
+
+/// Adds a module with a CAN resource, and two K-Line resources which share pin 7
+fn add_module(h_mod: ModuleHandle) {
+    let resource = |id, bus_type, protocols: &[Protocol], pin_numbers: &[u32]| ResourceInfo {
+        id,
+        bus_type_id: std_object_id(bus_type),
+        protocol_ids: protocols.iter().map(|p| std_object_id(*p)).collect(),
+        pins: pins(pin_numbers)
+    };
+    let resources = vec![
+        resource(CAN, BusType::Iso11898_2Dwcan, &[Protocol::Iso11898Raw, Protocol::Iso15765_3OnIso15765_2], &[6, 14]),
+        resource(KWP, BusType::Iso14230_1Uart, &[Protocol::Iso14230_3OnIso14230_2], &[7]),
+        resource(ISO9141, BusType::Iso9141_2Uart, &[Protocol::IsoObdOnIso9141_2], &[7, 15])
+    ];
+    API.with_borrow(|api| api.resources.add_module(h_mod, resources));
+}
+
+fn request(bus_type: BusType, protocol: Protocol) -> ResourceRequest {
+    ResourceRequest::new(std_object_id(bus_type), std_object_id(protocol), Vec::new())
+}
+
+fn kwp() -> ResourceRequest {
+    request(BusType::Iso14230_1Uart, Protocol::Iso14230_3OnIso14230_2)
+}
+
+fn obd_9141() -> ResourceRequest {
+    request(BusType::Iso9141_2Uart, Protocol::IsoObdOnIso9141_2)
+}
+
+fn placed(link: usize, module: ModuleHandle, resource_id: ResourceId) -> Option<LinkCandidate> {
+    Some(LinkCandidate { link, module, resource_id })
+}
+
+#[test]
+fn protocols_sharing_pins() {
+    add_module(MOD_A);
+    // Protocols on the same resource share its pins, so they can run at the same time
+    let links = [
+        request(BusType::Iso11898_2Dwcan, Protocol::Iso15765_3OnIso15765_2),
+        request(BusType::Iso11898_2Dwcan, Protocol::Iso11898Raw)
+    ];
+    let plan = planner().plan(&links, &[MOD_A]).unwrap();
+    assert!(plan.is_complete());
+    assert_eq!(plan.assignment, [placed(0, MOD_A, CAN), placed(1, MOD_A, CAN)]);
+    assert!(plan.graph.conflicts.is_empty());
+
+    // Protocols on different resources which share the K-Line pin cannot
+    let links = [kwp(), obd_9141()];
+    let plan = planner().plan(&links, &[MOD_A]).unwrap();
+    assert!(!plan.is_complete());
+    assert_eq!(plan.assignment, [placed(0, MOD_A, KWP), None]);
+    assert_eq!(plan.graph.conflicts.len(), 1);
+    assert_eq!(plan.link_conflicts.len(), 1);
+    assert_eq!((plan.link_conflicts[0].link_a, plan.link_conflicts[0].link_b), (0, 1));
+    assert_eq!(plan.unplaced.len(), 1);
+    assert_eq!(plan.unplaced[0].link, 1);
+    assert_eq!(plan.unplaced[0].reason, UnplacedReason::Conflicts {
+        with_links: vec![0],
+        resources: vec![(placed(1, MOD_A, ISO9141).unwrap(), placed(0, MOD_A, KWP).unwrap())]
+    });
+
+    // Requests with pins only match resources on exactly those pins
+    let mut on_pins = obd_9141();
+    on_pins.pins = pins(&[7]);
+    let plan = planner().plan(&[on_pins], &[MOD_A]).unwrap();
+    assert_eq!(plan.unplaced[0].reason, UnplacedReason::NoResource);
+    assert!(items_destroyed());
+}
+
+#[test]
+fn conflicts_across_modules() {
+    add_module(MOD_A);
+    add_module(MOD_B);
+    // Resources only conflict with the resources of their own module
+    let links = [kwp(), obd_9141()];
+    let plan = planner().plan(&links, &[MOD_A, MOD_B]).unwrap();
+    assert!(plan.is_complete());
+    assert_eq!(plan.assignment, [placed(0, MOD_A, KWP), placed(1, MOD_B, ISO9141)]);
+    assert!(plan.link_conflicts.is_empty());
+    assert_eq!(plan.graph.candidates.len(), 4);
+    assert_eq!(plan.graph.conflicts.len(), 2);
+    for (a, b) in &plan.graph.conflicts {
+        assert_eq!(plan.graph.candidates[*a].module, plan.graph.candidates[*b].module);
+    }
+
+    // A third link on the K-Line only fits if it shares a resource
+    let links = [kwp(), obd_9141(), kwp()];
+    let plan = planner().plan(&links, &[MOD_A, MOD_B]).unwrap();
+    assert!(plan.is_complete());
+    assert_eq!(plan.assignment, [placed(0, MOD_A, KWP), placed(1, MOD_B, ISO9141), placed(2, MOD_A, KWP)]);
+
+    let links = [kwp(), obd_9141(), obd_9141(), kwp()];
+    let plan = planner().plan(&links, &[MOD_A, MOD_B]).unwrap();
+    assert_eq!(plan.assignment[..3], [placed(0, MOD_A, KWP), placed(1, MOD_B, ISO9141), placed(2, MOD_B, ISO9141)]);
+    assert_eq!(plan.assignment[3], placed(3, MOD_A, KWP));
+
+    // Unknown modules are errors of the API
+    let unknown = ModuleHandle::new(3).unwrap();
+    assert_eq!(planner().plan(&links, &[MOD_A, unknown]), Err(PduError::InvalidHandle));
+    assert!(items_destroyed());
+}
+
+#[test]
+fn locked_resource() {
+    add_module(MOD_A);
+    let h_cll = CllHandle::new(1).unwrap();
+    API.with_borrow(|api| {
+        api.resources.attach(MOD_A, KWP, h_cll, Arc::new(EventQueue::new(EventSource::default()))).unwrap();
+        api.resources.lock_resource(MOD_A, h_cll, LOCK_MASK_PHYSICAL_COMPARAMS | LOCK_MASK_TX_QUEUE, 0).unwrap();
+        assert_eq!(api.resources.status(MOD_A, KWP), ResourceStatus::Locked { links: 1 });
+        // The resource which shares the pin with the locked resource is blocked by its link
+        assert_eq!(api.resources.status(MOD_A, ISO9141), ResourceStatus::NotAvailable);
+    });
+
+    // Conflicts come from the pins of the resources, so the lock does not change the plan. The
+    // status of the planned resources is checked with get_resource_status before creating links
+    let links = [kwp(), obd_9141()];
+    let plan = planner().plan(&links, &[MOD_A]).unwrap();
+    assert_eq!(plan.assignment, [placed(0, MOD_A, KWP), None]);
+    assert_eq!(plan.link_conflicts.len(), 1);
+
+    // The plan is the same once the resource is unlocked and free again
+    API.with_borrow(|api| api.resources.detach(h_cll, 0));
+    assert_eq!(planner().plan(&links, &[MOD_A]).unwrap(), plan);
+    assert!(items_destroyed());
+}
@@ -0,0 +1,205 @@
+//! Tests of the resource locks of providers
+
+use std::sync::Arc;
+
+use dpdu_rust::{
+    provider::{std_object_id, Event, EventData, EventQueue, EventSource, ResourceInfo, ResourceManager},
+    find_available_resource, get_resource_status, BusType, CllHandle, InfoData, ModuleHandle, PduError, PduInfo,
+    PinData, Protocol, ResourceId, ResourceStatus, ResourceStatusEncoding, RscStatusData, LOCK_MASK_PHYSICAL_COMPARAMS,
+    LOCK_MASK_TX_QUEUE
+};
+
+const H_MOD: ModuleHandle = match ModuleHandle::new(1) {
+    Some(h) => h,
+    None => unreachable!()
+};
+
+const RESOURCE: ResourceId = match ResourceId::new(1) {
+    Some(id) => id,
+    None => unreachable!()
+};
+
+/// Both locks of a resource
+const LOCK_ALL: u32 = LOCK_MASK_PHYSICAL_COMPARAMS | LOCK_MASK_TX_QUEUE;
+
+/// A link attached to the resource, with its event queue
+struct Link {
+    h_cll: CllHandle,
+    events: Arc<EventQueue>
+}
+
+impl Link {
+    /// Returns the resource IDs of the lock change events of the link
+    fn lock_changes(&self) -> Vec<u32> {
+        std::iter::from_fn(|| self.events.pop().ok())
+            .map(|e: Event| match e.data {
+                EventData::Info(InfoData { info_code: PduInfo::ResourceLockChange, extra_info_data }) => extra_info_data,
+                data => panic!("unexpected event {data:?}")
+            })
+            .collect()
+    }
+}
+
+/// Creates a manager with one CAN resource on the module, and attaches links to it
+fn manager(links: u32) -> (ResourceManager, Vec<Link>) {
+    let manager = ResourceManager::new();
+    manager.add_module(
+        H_MOD,
+        vec![ResourceInfo {
+            id: RESOURCE,
+            bus_type_id: std_object_id(BusType::Iso11898_2Dwcan),
+            protocol_ids: vec![std_object_id(Protocol::Iso11898Raw)],
+            pins: vec![PinData { dlc_pin_number: 6, dlc_pin_type_id: 0 }, PinData { dlc_pin_number: 14, dlc_pin_type_id: 0 }]
+        }]
+    );
+    let links = (1..=links)
+        .map(|raw| {
+            let h_cll = CllHandle::new(raw).unwrap();
+            let events = Arc::new(EventQueue::new(EventSource { h_mod: Some(H_MOD), h_cll: Some(h_cll), ..Default::default() }));
+            manager.attach(H_MOD, RESOURCE, h_cll, events.clone()).unwrap();
+            Link { h_cll, events }
+        })
+        .collect();
+    (manager, links)
+}
+
+#[test]
+fn lock_errors() {
+    let (manager, links) = manager(2);
+    let (a, b) = (links[0].h_cll, links[1].h_cll);
+    assert_eq!(manager.lock_resource(H_MOD, a, 0, 0), Err(PduError::InvalidParameters));
+    assert_eq!(manager.lock_resource(H_MOD, a, 0x04, 0), Err(PduError::InvalidParameters));
+    assert_eq!(manager.unlock_resource(H_MOD, a, LOCK_MASK_TX_QUEUE, 0), Err(PduError::RscNotLocked));
+
+    manager.lock_resource(H_MOD, a, LOCK_MASK_PHYSICAL_COMPARAMS, 0).unwrap();
+    assert_eq!(manager.status(H_MOD, RESOURCE), ResourceStatus::Locked { links: 2 });
+    assert_eq!(manager.lock_resource(H_MOD, a, LOCK_MASK_PHYSICAL_COMPARAMS, 0), Err(PduError::RscLocked));
+    assert_eq!(manager.lock_resource(H_MOD, b, LOCK_ALL, 0), Err(PduError::RscLockedByAnotherCll));
+    assert_eq!(manager.unlock_resource(H_MOD, b, LOCK_MASK_PHYSICAL_COMPARAMS, 0), Err(PduError::RscNotLocked));
+    // The other link only locks the transmit queue, which stops the first link sending
+    manager.lock_resource(H_MOD, b, LOCK_MASK_TX_QUEUE, 0).unwrap();
+    assert_eq!(manager.check_tx(a), Err(PduError::RscLockedByAnotherCll));
+    assert_eq!(manager.check_tx(b), Ok(()));
+    assert_eq!(manager.unlock_resource(H_MOD, a, LOCK_ALL, 0), Err(PduError::RscNotLocked));
+
+    // New links cannot attach to a locked resource
+    let h_new = CllHandle::new(3).unwrap();
+    let events = Arc::new(EventQueue::new(EventSource::default()));
+    assert_eq!(manager.attach(H_MOD, RESOURCE, h_new, events.clone()).err(), Some(PduError::RscLocked));
+
+    manager.unlock_resource(H_MOD, a, LOCK_MASK_PHYSICAL_COMPARAMS, 0).unwrap();
+    manager.unlock_resource(H_MOD, b, LOCK_MASK_TX_QUEUE, 0).unwrap();
+    assert_eq!(manager.status(H_MOD, RESOURCE), ResourceStatus::InUse { links: 2 });
+    assert_eq!(manager.check_tx(a), Ok(()));
+    assert!(manager.attach(H_MOD, RESOURCE, h_new, events).is_ok());
+}
+
+#[test]
+fn locks_are_released_when_the_link_is_destroyed() {
+    let (manager, links) = manager(2);
+    let (a, b) = (links[0].h_cll, links[1].h_cll);
+    manager.lock_resource(H_MOD, a, LOCK_ALL, 0).unwrap();
+    assert_eq!(manager.check_tx(b), Err(PduError::RscLockedByAnotherCll));
+    links[1].lock_changes();
+
+    manager.detach(a, 0);
+    assert_eq!(manager.status(H_MOD, RESOURCE), ResourceStatus::InUse { links: 1 });
+    assert_eq!(manager.link_resource(a), None);
+    assert_eq!(manager.check_tx(b), Ok(()));
+    assert_eq!(links[1].lock_changes(), [RESOURCE.raw()]);
+    manager.lock_resource(H_MOD, b, LOCK_ALL, 0).unwrap();
+
+    // Destroying a link without locks is not a lock change
+    manager.unlock_resource(H_MOD, b, LOCK_ALL, 0).unwrap();
+    links[1].lock_changes();
+    manager.detach(b, 0);
+    assert_eq!(manager.status(H_MOD, RESOURCE), ResourceStatus::Available);
+    assert_eq!(links[1].lock_changes(), []);
+}
+
+#[test]
+fn lock_change_events() {
+    let (manager, links) = manager(2);
+    let (a, b) = (links[0].h_cll, links[1].h_cll);
+    manager.lock_resource(H_MOD, a, LOCK_MASK_PHYSICAL_COMPARAMS, 10).unwrap();
+    manager.lock_resource(H_MOD, a, LOCK_MASK_TX_QUEUE, 20).unwrap();
+    manager.unlock_resource(H_MOD, a, LOCK_ALL, 30).unwrap();
+    // Every link on the resource is told of each change
+    for link in &links {
+        let timestamps: Vec<u32> = std::iter::from_fn(|| link.events.pop().ok()).map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, [10, 20, 30]);
+    }
+
+    // Failed requests do not change the locks
+    manager.lock_resource(H_MOD, b, LOCK_MASK_TX_QUEUE, 0).unwrap();
+    assert_eq!(links[0].lock_changes(), [RESOURCE.raw()]);
+    assert_eq!(manager.lock_resource(H_MOD, a, LOCK_MASK_TX_QUEUE, 0), Err(PduError::RscLockedByAnotherCll));
+    assert_eq!(manager.unlock_resource(H_MOD, a, LOCK_MASK_TX_QUEUE, 0), Err(PduError::RscNotLocked));
+    assert_eq!(links[0].lock_changes(), []);
+    assert_eq!(links[1].lock_changes(), [RESOURCE.raw()]);
+}
+
+#[test]
+fn standard_status_decoding() {
+    assert_eq!(ResourceStatus::from_raw(0), ResourceStatus::Available);
+    // Vendor values are not decoded with the bits of this crate
+    for raw in [1, 2, 0x0000_0003, 0x4000_0001, 0x8000_0000, 0xFFFF_FFFF] {
+        assert_eq!(ResourceStatus::from_raw(raw), ResourceStatus::NotAvailable);
+        assert_eq!(ResourceStatus::decode(raw, ResourceStatusEncoding::Standard), ResourceStatus::NotAvailable);
+    }
+}
+
+#[test]
+fn provider_status_decoding() {
+    let cases = [
+        (0, ResourceStatus::Available),
+        (3, ResourceStatus::InUse { links: 3 }),
+        (0x4000_0000, ResourceStatus::Locked { links: 0 }),
+        (0x4000_0002, ResourceStatus::Locked { links: 2 }),
+        (0x8000_0000, ResourceStatus::NotAvailable)
+    ];
+    for (raw, status) in cases {
+        assert_eq!(ResourceStatus::from_provider_raw(raw), status);
+        assert_eq!(ResourceStatus::decode(raw, ResourceStatusEncoding::Provider), status);
+        assert_eq!(status.to_raw(), raw);
+    }
+    // Resources which cannot be used are not available, whatever the other bits
+    assert_eq!(ResourceStatus::from_provider_raw(0xC000_0001), ResourceStatus::NotAvailable);
+}
+
+/// `PDUGetResourceStatus` which reports the resource ID as its status
+extern "C" fn status_is_resource_id(data: *mut RscStatusData) -> PduError {
+    // Safety: get_resource_status passes a valid list
+    let data = unsafe { &mut *data };
+    // Safety: The list has num_entries items
+    let items = unsafe { std::slice::from_raw_parts_mut(data.p_resource_status_data, data.num_entries as usize) };
+    for item in items {
+        item.resource_status = item.resource_id;
+    }
+    PduError::StatusNoError
+}
+
+#[test]
+fn statuses_of_several_modules() {
+    let other = ModuleHandle::new(2).unwrap();
+    let resources = [(H_MOD, ResourceId::new(2).unwrap()), (other, RESOURCE), (other, ResourceId::new(0).unwrap())];
+
+    let standard = get_resource_status(status_is_resource_id, &resources, ResourceStatusEncoding::Standard).unwrap();
+    let statuses: Vec<_> = standard.iter().map(|e| (e.module, e.resource_id, e.status)).collect();
+    assert_eq!(statuses, [
+        (H_MOD, resources[0].1, ResourceStatus::NotAvailable),
+        (other, RESOURCE, ResourceStatus::NotAvailable),
+        (other, resources[2].1, ResourceStatus::Available)
+    ]);
+    assert_eq!(find_available_resource(&standard).map(|e| e.module), Some(other));
+
+    let provider = get_resource_status(status_is_resource_id, &resources, ResourceStatusEncoding::Provider).unwrap();
+    let statuses: Vec<_> = provider.iter().map(|e| e.status).collect();
+    assert_eq!(statuses, [
+        ResourceStatus::InUse { links: 2 },
+        ResourceStatus::InUse { links: 1 },
+        ResourceStatus::Available
+    ]);
+    // Without the free resource, the least used shared resource is picked
+    assert_eq!(find_available_resource(&provider[..2]).map(|e| e.module), Some(other));
+}
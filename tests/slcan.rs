@@ -0,0 +1,172 @@
+//! Tests of the SLCAN backend against the emulated adapter on a pseudo terminal
+
+use std::{
+    ptr, thread,
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    backends::slcan::{sim::SimConfig, Slcan},
+    provider::{std_object_id, CanFrame, Channel, DriverBackend, PduBackend, PduTag},
+    BusType, CopCtrlData, ErrorData, FlagData, ModuleHandle, PduCopt, PduError, PduErrorEvt, PduIt, Protocol, RscData,
+    StdComParam
+};
+
+mod common;
+
+use common::{echo_ecu, open_channel, params, start_sim};
+
+const RAW: Protocol = Protocol::Iso11898Raw;
+
+const ISOTP: Protocol = Protocol::Iso15765_3OnIso15765_2;
+
+#[test]
+fn refused_frames() {
+    let (sim, driver) = start_sim(SimConfig { refuse_frames: true, ..SimConfig::default() });
+    sim.add_ecu(0x7E0, 0x7E8, echo_ecu);
+    let raw = params(&driver, RAW, &[]);
+    let mut channel = open_channel(&driver, RAW, &raw).unwrap();
+    assert_eq!(channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x3E], &[], &raw), Err(PduError::FctFailed));
+    drop(channel);
+
+    let isotp = params(&driver, ISOTP, &[]);
+    let mut channel = open_channel(&driver, ISOTP, &isotp).unwrap();
+    assert_eq!(channel.send(&[0x3E, 0x00], &[], &isotp), Err(PduError::FctFailed));
+    assert!(sim.take_frames().is_empty());
+
+    // The adapter sends again once it accepts frames
+    sim.set_config(SimConfig::default());
+    channel.send(&[0x3E, 0x00], &[], &isotp).unwrap();
+    assert_eq!(channel.recv(Duration::from_secs(1)).unwrap().unwrap().data, [0x7E, 0x00]);
+}
+
+/// Runs a ComPrimitive sending `data` to an ISO-TP link of the simulator, returning the error
+/// events of the ComPrimitive
+fn com_primitive_errors(config: SimConfig, data: &[u8]) -> Vec<PduErrorEvt> {
+    let (sim, _driver) = start_sim(config);
+    let backend = DriverBackend::<Slcan>::construct(&sim.path().display().to_string(), PduTag::NULL).unwrap();
+    let item = backend.get_module_ids().unwrap();
+    // Safety: The item is a module item of the backend with one module
+    let h_mod = ModuleHandle::new(unsafe { (*(*item).p_module_data).h_mod }).unwrap();
+    backend.destroy_item(item.cast()).unwrap();
+    backend.module_connect(Some(h_mod)).unwrap();
+    let rsc = RscData {
+        bus_type_id: std_object_id(BusType::Iso11898_2Dwcan).raw(),
+        protocol_id: std_object_id(ISOTP).raw(),
+        num_pin_data: 0,
+        p_dlc_pin_data: ptr::null_mut()
+    };
+    let h_cll = backend.create_com_logical_link(h_mod, Some(&rsc), None, PduTag::NULL, None).unwrap();
+    backend.connect(h_mod, h_cll).unwrap();
+    let send = CopCtrlData {
+        time: 0,
+        num_send_cycles: 1,
+        num_receive_cycles: 0,
+        temp_param_update: 0,
+        tx_flag: FlagData { num_flag_bytes: 0, p_flag_data: ptr::null_mut() },
+        num_possible_expected_responses: 0,
+        expected_response_array: ptr::null_mut()
+    };
+    let h_cop = backend.start_com_primitive(h_mod, h_cll, PduCopt::SendRecv, data, Some(&send), PduTag::NULL).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut errors = Vec::new();
+    while errors.is_empty() && Instant::now() < deadline {
+        let item = match backend.get_event_item(Some(h_mod), Some(h_cll)) {
+            Ok(item) => item,
+            Err(PduError::EventQueueEmpty) => {
+                thread::sleep(Duration::from_millis(20));
+                continue;
+            },
+            Err(e) => panic!("{e:?}")
+        };
+        // Safety: The item is an event item of the backend
+        unsafe {
+            if (*item).item_type == PduIt::Error {
+                assert_eq!((*item).h_cop, h_cop.raw());
+                errors.push((*(*item).p_data.cast::<ErrorData>()).error_code_id);
+            }
+        }
+        backend.destroy_item(item.cast()).unwrap();
+    }
+    backend.destruct().unwrap();
+    errors
+}
+
+#[test]
+fn refused_frames_fail_the_com_primitive() {
+    let errors = com_primitive_errors(SimConfig { refuse_frames: true, ..SimConfig::default() }, &[0x3E, 0x00]);
+    assert_eq!(errors, [PduErrorEvt::TxError]);
+}
+
+#[test]
+fn transport_errors_fail_the_com_primitive() {
+    // No ECU answers the first frame with a flow control frame, so the send times out (N_Bs)
+    let errors = com_primitive_errors(SimConfig::default(), &[0x2E; 20]);
+    assert_eq!(errors, [PduErrorEvt::RxTimeout]);
+}
+
+#[test]
+fn unsupported_bitrates() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    let unsupported = params(&driver, RAW, &[(StdComParam::Baudrate, 33_333)]);
+    assert_eq!(open_channel(&driver, RAW, &unsupported).err(), Some(PduError::ValueNotSupported));
+
+    // The CAN channel stays open at the previous bit rate
+    let supported = params(&driver, RAW, &[]);
+    let mut channel = open_channel(&driver, RAW, &supported).unwrap();
+    assert_eq!(channel.apply_params(&unsupported), Err(PduError::ValueNotSupported));
+    assert_eq!(sim.bitrate(), Some(500_000));
+    channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x01], &[], &supported).unwrap();
+    assert_eq!(sim.take_frames(), [CanFrame { id: 0x7E0, data: vec![0x01], ..Default::default() }]);
+}
+
+#[test]
+fn frames_with_29bit_ids() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    let params = params(&driver, RAW, &[]);
+    let mut channel = open_channel(&driver, RAW, &params).unwrap();
+    // Bit 31 marks a 29 bit ID, which is implied for IDs above 0x7FF
+    channel.send(&[0x98, 0xDA, 0x10, 0xF1, 0x02, 0x3E, 0x00], &[], &params).unwrap();
+    channel.send(&[0x18, 0xDB, 0x33, 0xF1, 0x01], &[], &params).unwrap();
+    channel.send(&[0x80, 0x00, 0x01, 0x23, 0x02], &[], &params).unwrap();
+    let ids: Vec<u32> = sim.take_frames().iter().map(|f| f.id).collect();
+    assert_eq!(ids, [0x98DA_10F1, 0x98DB_33F1, 0x8000_0123]);
+
+    sim.send_frame(CanFrame { id: 0x98DA_F110, data: vec![0x02, 0x7E, 0x00], ..Default::default() });
+    let result = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(result.data, [0x98, 0xDA, 0xF1, 0x10, 0x02, 0x7E, 0x00]);
+}
+
+#[test]
+fn isotp_with_29bit_ids() {
+    let (sim, driver) = start_sim(SimConfig::default());
+    // A positive response which needs several frames
+    let long_ecu = |request: &[u8]| echo_ecu(request).into_iter().map(|r| [&r[..], &[0xAA; 20]].concat()).collect();
+    sim.add_ecu(0x98DA_10F1, 0x98DA_F110, long_ecu);
+    let params = params(&driver, ISOTP, &[(StdComParam::CanPhysReqId, 0x18DA10F1), (StdComParam::CanRespUsdtId, 0x18DAF110)]);
+    let mut channel = open_channel(&driver, ISOTP, &params).unwrap();
+    channel.send(&[0x22, 0xF1, 0x90], &[], &params).unwrap();
+    let result = channel.recv(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(result.data, [&[0x62, 0xF1, 0x90][..], &[0xAA; 20]].concat());
+}
+
+#[test]
+fn timestamps_wrap_around() {
+    // The timestamps of the adapter wrap around from 59999 to 0 ms after 200 ms
+    let (sim, driver) = start_sim(SimConfig { timestamp_start: 59_800, ..SimConfig::default() });
+    let params = params(&driver, RAW, &[]);
+    let mut channel = open_channel(&driver, RAW, &params).unwrap();
+    let frame = CanFrame { id: 0x7E8, data: vec![0x01], ..Default::default() };
+    let mut timestamps = Vec::new();
+    for _ in 0..4 {
+        sim.send_frame(frame.clone());
+        timestamps.push(channel.recv(Duration::from_secs(1)).unwrap().unwrap().start_msg_timestamp);
+        thread::sleep(Duration::from_millis(100));
+    }
+    for pair in timestamps.windows(2) {
+        // The timestamps are in microseconds
+        let gap = pair[1].wrapping_sub(pair[0]);
+        assert!((90_000..150_000).contains(&gap), "{timestamps:?}");
+    }
+}
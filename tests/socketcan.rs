@@ -0,0 +1,617 @@
+//! Tests of the SocketCAN backend on the `vcan0` interface
+//!
+//! The tests are ignored by default, and fail if `vcan0` does not exist. It is created with:
+//! ```text
+//! ip link add dev vcan0 type vcan
+//! ip link set vcan0 up
+//! ```
+//! and the tests are run with `cargo test --features socketcan --test socketcan -- --ignored`.
+//! The CAN FD tests need the MTU set to 72 before the interface is brought up.
+
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    path::Path,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard
+    },
+    thread,
+    time::{Duration, Instant}
+};
+
+use dpdu_rust::{
+    backends::socketcan::{SocketCan, SocketCanChannel},
+    provider::{
+        std_object_id, CanFrame, Channel, Clock, Driver, DriverBackend, IsoTp, IsoTpConfig, IsoTpEvent, J1939Config,
+        J1939Event, J1939Message, LinkParams, PduBackend, PduTag, ResultEvent, DEFAULT_ADDR_CLAIM_TIMEOUT,
+        DEFAULT_TESTER_ADDRESS, DEFAULT_TESTER_NAME, J1939, PGN_ADDRESS_CLAIMED
+    },
+    BusType, CanFlags, CllHandle, CopCtrlData, FlagData, IoFilterData, IoctlCommand, ModuleHandle, PduCopt,
+    PduDataItem, PduError, PduErrorEvt, PduFilter, PduIt, Protocol, ResultData, RscData, StdComParam
+};
+
+mod common;
+
+use common::params;
+
+const VCAN: &str = "vcan0";
+
+const ISOTP: Protocol = Protocol::Iso15765_3OnIso15765_2;
+
+const J1939_PROTOCOL: Protocol = Protocol::SaeJ1939_73OnSaeJ1939_21;
+
+/// ECU answering the default addressing of the ISO-TP links
+const ECU: IsoTpConfig = IsoTpConfig {
+    tx_id: 0x7E8,
+    rx_id: 0x7E0,
+    func_id: 0x7DF,
+    tx_ext_addr: None,
+    rx_ext_addr: None,
+    func_ext_addr: None,
+    padding: Some(0x55),
+    tx_dl: 8,
+    brs: false,
+    block_size: 0,
+    st_min: Duration::ZERO,
+    block_size_override: None,
+    st_min_override: None,
+    n_as: Duration::from_secs(1),
+    n_bs: Duration::from_secs(1),
+    n_cr: Duration::from_secs(1),
+    max_wait_frames: 10,
+    max_rx_len: 0x1000
+};
+
+/// Opens the driver, failing if `vcan0` does not exist. Every test uses the same interface, so
+/// they run one at a time
+fn vcan() -> (MutexGuard<'static, ()>, SocketCan) {
+    static LOCK: Mutex<()> = Mutex::new(());
+    assert!(Path::new("/sys/class/net").join(VCAN).exists(), "{VCAN} does not exist");
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let driver = SocketCan::open("").unwrap();
+    assert!(driver.interfaces().iter().any(|i| i == VCAN));
+    (guard, driver)
+}
+
+/// Opens the driver, failing if `vcan0` is not a CAN FD interface
+fn fd_vcan() -> (MutexGuard<'static, ()>, SocketCan) {
+    let vcan = vcan();
+    assert!(is_fd(), "{VCAN} is not a CAN FD interface");
+    vcan
+}
+
+fn is_fd() -> bool {
+    std::fs::read_to_string(Path::new("/sys/class/net").join(VCAN).join("mtu")).is_ok_and(|mtu| mtu.trim() == "72")
+}
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// Opens a channel on `vcan0`, with some of the default ComParams of the protocol changed
+fn vcan_channel(
+    driver: &SocketCan,
+    protocol: Protocol,
+    changes: &[(StdComParam, u32)]
+) -> Result<SocketCanChannel, PduError> {
+    let module = driver.interfaces().iter().position(|i| i == VCAN).unwrap();
+    let resource = &driver.modules()[module].resources[0];
+    driver.open_channel(module, resource, protocol, &params(driver, protocol, changes), Clock::new())
+}
+
+/// Waits up to `timeout` for a result
+fn recv(channel: &mut impl Channel, timeout: Duration) -> Option<ResultEvent> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(result) = channel.recv(ms(10)).unwrap() {
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// Waits up to `timeout` for an error event, receiving while waiting
+fn recv_error(channel: &mut impl Channel, timeout: Duration) -> Option<PduErrorEvt> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        channel.recv(ms(10)).unwrap();
+        if let Some(code) = channel.poll_error() {
+            return Some(code);
+        }
+    }
+    None
+}
+
+/// Raw channel playing the ECU
+struct Peer(SocketCanChannel);
+
+impl Peer {
+    fn open(driver: &SocketCan) -> Self {
+        Self(vcan_channel(driver, Protocol::Iso11898Raw, &[]).unwrap())
+    }
+
+    fn send(&mut self, frame: &CanFrame) {
+        let data = [frame.id.to_be_bytes().as_slice(), &frame.data].concat();
+        self.0.send(&data, &frame.flags.to_flag_bytes(), &LinkParams::default()).unwrap();
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Option<CanFrame> {
+        let result = self.0.recv(timeout).unwrap()?;
+        Some(CanFrame {
+            id: u32::from_be_bytes(result.data[..4].try_into().unwrap()),
+            flags: CanFlags::from_flag_bytes(&result.rx_flag),
+            data: result.data[4..].to_vec()
+        })
+    }
+
+    /// Waits up to one second for a frame with a CAN ID
+    fn recv_id(&mut self, id: u32) -> Option<CanFrame> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            match self.recv(ms(10)) {
+                Some(frame) if frame.id == id => return Some(frame),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Receives one ISO-TP request and answers it with `response`, returning the request
+    fn answer(&mut self, config: IsoTpConfig, response: &[u8]) -> Option<Vec<u8>> {
+        let mut engine = IsoTp::new(config);
+        let mut request = None;
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while Instant::now() < deadline {
+            while let Some(frame) = engine.poll_transmit(Instant::now()) {
+                self.send(&frame);
+                engine.confirm_transmit(Instant::now());
+            }
+            if let Some(frame) = self.recv(ms(5)) {
+                engine.on_frame(&frame, Instant::now());
+            }
+            while let Some(event) = engine.poll_event(Instant::now()) {
+                match event {
+                    IsoTpEvent::Received { data, .. } => {
+                        engine.send(response, false, Instant::now()).unwrap();
+                        request = Some(data);
+                    },
+                    IsoTpEvent::Sent => return request,
+                    other => panic!("ECU failed: {other:?}")
+                }
+            }
+        }
+        None
+    }
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn raw_frames() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, Protocol::Iso11898Raw, &[]).unwrap();
+    let params = params(&driver, Protocol::Iso11898Raw, &[]);
+
+    channel.send(&[0x00, 0x00, 0x07, 0xE0, 0x02, 0x10, 0x03], &[], &params).unwrap();
+    assert_eq!(peer.recv_id(0x7E0).unwrap().data, [0x02, 0x10, 0x03]);
+    // IDs above 0x7FF are sent as 29 bit IDs, and received with bit 31 set
+    channel.send(&[0x18, 0xDA, 0x10, 0xF1, 0x02, 0x3E, 0x00], &[], &params).unwrap();
+    assert_eq!(peer.recv_id(0x98DA_10F1).unwrap().data, [0x02, 0x3E, 0x00]);
+
+    peer.send(&CanFrame { id: 0x98DA_F110, data: vec![0x02, 0x7E, 0x00], ..Default::default() });
+    let result = recv(&mut channel, Duration::from_secs(1)).unwrap();
+    assert_eq!(result.data, [0x98, 0xDA, 0xF1, 0x10, 0x02, 0x7E, 0x00]);
+    assert_eq!(CanFlags::from_flag_bytes(&result.rx_flag), CanFlags::default());
+
+    assert_eq!(channel.send(&[0x00, 0x07], &[], &params), Err(PduError::InvalidParameters));
+    if !is_fd() {
+        let long = [&[0x00, 0x00, 0x07, 0xE0][..], &[0xAA; 12]].concat();
+        assert_eq!(channel.send(&long, &[], &params), Err(PduError::ValueNotSupported));
+        assert_eq!(channel.send(&long[..6], &CanFlags::fd(true).to_flag_bytes(), &params), Err(PduError::ValueNotSupported));
+    }
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn raw_timestamps() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let module = driver.interfaces().iter().position(|i| i == VCAN).unwrap();
+    let resource = &driver.modules()[module].resources[0];
+    let clock = Clock::new();
+    let params = params(&driver, Protocol::Iso11898Raw, &[]);
+    let mut channel = driver.open_channel(module, resource, Protocol::Iso11898Raw, &params, clock).unwrap();
+
+    // Frames are timestamped when the kernel received them, not when they are read
+    peer.send(&CanFrame { id: 0x7E8, data: vec![0x02, 0x50, 0x03], ..Default::default() });
+    thread::sleep(ms(100));
+    let read = clock.now();
+    let result = recv(&mut channel, Duration::from_secs(1)).unwrap();
+    assert!(read - result.start_msg_timestamp >= 90_000, "{} read at {read}", result.start_msg_timestamp);
+}
+
+#[test]
+#[ignore = "needs vcan0 with a CAN FD MTU"]
+fn canfd_frames() {
+    let (_guard, driver) = fd_vcan();
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, Protocol::Iso11898Raw, &[(StdComParam::CanFdBaudrate, 2_000_000)]).unwrap();
+    let params = params(&driver, Protocol::Iso11898Raw, &[]);
+
+    // Frames longer than 8 bytes are CAN FD frames, with bit rate switching if requested
+    let data: Vec<u8> = (0..64).collect();
+    channel.send(&[&[0x00, 0x00, 0x01, 0x23][..], &data].concat(), &[], &params).unwrap();
+    let frame = peer.recv_id(0x123).unwrap();
+    assert_eq!((frame.flags, frame.data.as_slice()), (CanFlags::fd(false), data.as_slice()));
+    channel.send(&[0x00, 0x00, 0x01, 0x23, 0x01], &CanFlags::fd(true).to_flag_bytes(), &params).unwrap();
+    let frame = peer.recv_id(0x123).unwrap();
+    assert_eq!((frame.flags, frame.data.as_slice()), (CanFlags::fd(true), [0x01].as_slice()));
+
+    peer.send(&CanFrame { id: 0x456, flags: CanFlags::fd(true), data: vec![0xCC; 12] });
+    let result = recv(&mut channel, Duration::from_secs(1)).unwrap();
+    assert_eq!(CanFlags::from_flag_bytes(&result.rx_flag), CanFlags::fd(true));
+    assert_eq!(result.data, [&[0x00, 0x00, 0x04, 0x56][..], &[0xCC; 12]].concat());
+    // 13 bytes is not a CAN FD length
+    assert_eq!(channel.send(&[&[0x00, 0x00, 0x01, 0x23][..], &[0; 13]].concat(), &[], &params), Err(PduError::InvalidParameters));
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn bitrates_and_frame_formats() {
+    let (_guard, driver) = vcan();
+    // Virtual interfaces accept any bit rate
+    let mut channel = vcan_channel(&driver, Protocol::Iso11898Raw, &[(StdComParam::Baudrate, 33_333)]).unwrap();
+    channel.apply_params(&params(&driver, Protocol::Iso11898Raw, &[(StdComParam::Baudrate, 125_000)])).unwrap();
+
+    let params = params(&driver, ISOTP, &[]);
+    assert_eq!(SocketCanChannel::open("nocan0", ISOTP, &params, Clock::new()).err(), Some(PduError::CommPcToVciFailed));
+    if !is_fd() {
+        let fd = [(StdComParam::CanFdTxMaxDataLength, 64)];
+        assert_eq!(vcan_channel(&driver, ISOTP, &fd).err(), Some(PduError::ValueNotSupported));
+    }
+}
+
+/// Sends a request on an ISO-TP link and receives the response of the ECU, in both directions
+/// as multi frame messages
+fn isotp_exchange(driver: &SocketCan, changes: &[(StdComParam, u32)], ecu: IsoTpConfig, len: usize) {
+    let mut peer = Peer::open(driver);
+    let mut channel = vcan_channel(driver, ISOTP, changes).unwrap();
+    let params = params(driver, ISOTP, changes);
+    let request: Vec<u8> = [0x2E, 0xF1, 0x90].into_iter().chain((0..len as u32).map(|b| b as u8)).collect();
+    let response: Vec<u8> = [0x62, 0xF1, 0x90].into_iter().chain((0..len as u32 * 2).map(|b| b as u8)).collect();
+    thread::scope(|s| {
+        let ecu = s.spawn(|| peer.answer(ecu, &response));
+        channel.send(&request, &[], &params).unwrap();
+        assert_eq!(recv(&mut channel, Duration::from_secs(2)).unwrap().data, response);
+        assert_eq!(ecu.join().unwrap(), Some(request));
+    });
+}
+
+/// Timeouts which select the userspace engine
+const USERSPACE: [(StdComParam, u32); 2] = [(StdComParam::Bs, 200_000), (StdComParam::Cr, 200_000)];
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn kernel_isotp() {
+    let (_guard, driver) = vcan();
+    // Uses the kernel's can-isotp sockets where available
+    isotp_exchange(&driver, &[], ECU, 20);
+
+    // Functional requests are single frames to CP_CanFuncReqId
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, ISOTP, &[]).unwrap();
+    let functional = params(&driver, ISOTP, &[(StdComParam::RequestAddrMode, 2)]);
+    channel.send(&[0x3E, 0x00], &[], &functional).unwrap();
+    assert_eq!(peer.recv_id(0x7DF).unwrap().data, [0x02, 0x3E, 0x00, 0x55, 0x55, 0x55, 0x55, 0x55]);
+    assert_eq!(channel.send(&[0x3E; 8], &[], &functional), Err(PduError::InvalidParameters));
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn userspace_isotp() {
+    let (_guard, driver) = vcan();
+    isotp_exchange(&driver, &USERSPACE, ECU, 20);
+    // A block size override also needs the userspace engine
+    isotp_exchange(&driver, &[(StdComParam::BlockSizeOverride, 0)], IsoTpConfig { block_size: 2, ..ECU }, 40);
+}
+
+#[test]
+#[ignore = "needs vcan0 with a CAN FD MTU"]
+fn canfd_isotp() {
+    let (_guard, driver) = fd_vcan();
+    let fd = [(StdComParam::CanFdTxMaxDataLength, 64)];
+    let ecu = IsoTpConfig { tx_dl: 64, ..ECU };
+    isotp_exchange(&driver, &fd, ecu, 200);
+    isotp_exchange(&driver, &[fd[0], USERSPACE[0], USERSPACE[1]], ecu, 200);
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn isotp_timeouts() {
+    let (_guard, driver) = vcan();
+    // The userspace engine fails the send if no flow control frame arrives within N_Bs
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, ISOTP, &USERSPACE).unwrap();
+    let start = Instant::now();
+    assert_eq!(channel.send(&[0x2E; 20], &[], &params(&driver, ISOTP, &USERSPACE)), Err(PduError::FctFailed));
+    assert!(start.elapsed() >= ms(200) && start.elapsed() < ms(600));
+    assert_eq!(peer.recv_id(0x7E0).unwrap().data[..2], [0x10, 20]);
+
+    // A first frame without consecutive frames times out after N_Cr, which is fixed to one
+    // second on kernel sockets
+    let first_frame = CanFrame { id: 0x7E8, data: vec![0x10, 20, 0x62, 0xF1, 0x90, 0, 1, 2], ..Default::default() };
+    for (changes, timeout) in [(&[][..], Duration::from_secs(1)), (&USERSPACE[..], ms(200))] {
+        let mut peer = Peer::open(&driver);
+        let mut channel = vcan_channel(&driver, ISOTP, changes).unwrap();
+        peer.send(&first_frame);
+        let start = Instant::now();
+        assert_eq!(recv_error(&mut channel, Duration::from_secs(2)), Some(PduErrorEvt::RxTimeout), "{changes:?}");
+        assert!(start.elapsed() >= timeout && start.elapsed() < timeout * 3, "{changes:?}");
+        assert_eq!(peer.recv_id(0x7E0).unwrap().data[0], 0x30);
+    }
+}
+
+/// Node on the peer with a lower NAME than the tester
+fn ecu_node(address: u8) -> J1939Config {
+    J1939Config { address, name: 0x10, claim_timeout: Duration::ZERO, ..Default::default() }
+}
+
+/// Runs a J1939 node on the peer until `stop` is set, returning the messages it received. The
+/// messages of `outgoing` (PGN, destination address and data) are sent one after another once
+/// the node has claimed its address
+fn run_node(peer: &mut Peer, config: J1939Config, mut outgoing: VecDeque<(u32, u8, Vec<u8>)>, stop: &AtomicBool) -> Vec<J1939Message> {
+    let mut node = J1939::new(config);
+    node.claim(Instant::now());
+    let mut received = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        while let Some(frame) = node.poll_transmit(Instant::now()) {
+            peer.send(&frame);
+        }
+        if let Some(frame) = peer.recv(ms(5)) {
+            node.on_frame(&frame, Instant::now());
+        }
+        while let Some(event) = node.poll_event(Instant::now()) {
+            match event {
+                J1939Event::AddressClaimed(_) | J1939Event::Sent if !node.is_sending() => {
+                    if let Some((pgn, destination, data)) = outgoing.pop_front() {
+                        node.send(pgn, destination, &data, Instant::now()).unwrap();
+                    }
+                },
+                J1939Event::Received { message, .. } if message.pgn != PGN_ADDRESS_CLAIMED => received.push(message),
+                J1939Event::TransmitFailed(e) | J1939Event::ReceiveFailed(e) => panic!("node failed: {e:?}"),
+                _ => {}
+            }
+        }
+    }
+    received
+}
+
+/// Waits up to `timeout` for a J1939 result other than an address claim, returning the ExtraInfo
+/// header and the data
+fn recv_j1939(channel: &mut impl Channel, timeout: Duration) -> Option<(Vec<u8>, Vec<u8>)> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let Some(result) = channel.recv(ms(10)).unwrap() else { continue };
+        let header = result.extra_info.unwrap().header;
+        if header[..3] != PGN_ADDRESS_CLAIMED.to_be_bytes()[1..] {
+            return Some((header, result.data));
+        }
+    }
+    None
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn j1939_address_claim() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let start = Instant::now();
+    let mut channel = vcan_channel(&driver, J1939_PROTOCOL, &[]).unwrap();
+    // Connecting waits for contending claims
+    assert!(start.elapsed() >= DEFAULT_ADDR_CLAIM_TIMEOUT);
+    assert_eq!(peer.recv_id(0x98EE_FFF9).unwrap().data, DEFAULT_TESTER_NAME.to_le_bytes());
+
+    let params = params(&driver, J1939_PROTOCOL, &[]);
+    channel.send(&[0x00, 0xFE, 0xCA, 0xFF, 0x01, 0x02, 0x03], &[], &params).unwrap();
+    assert_eq!(peer.recv_id(0x98FE_CAF9).unwrap().data, [0x01, 0x02, 0x03]);
+    assert_eq!(channel.send(&[0x00, 0xFE, 0xCA], &[], &params), Err(PduError::InvalidParameters));
+
+    // A request for the Address Claimed message is answered with the claim
+    peer.send(&CanFrame { id: 0x98EA_FF00, data: vec![0x00, 0xEE, 0x00], ..Default::default() });
+    let (header, data) = recv_j1939(&mut channel, Duration::from_secs(1)).unwrap();
+    assert_eq!((header.as_slice(), data.as_slice()), ([0x00, 0xEA, 0x00, 0x00, 0xFF].as_slice(), [0x00, 0xEE, 0x00].as_slice()));
+    channel.recv(ms(50)).unwrap();
+    assert_eq!(peer.recv_id(0x98EE_FFF9).unwrap().data, DEFAULT_TESTER_NAME.to_le_bytes());
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn j1939_lost_arbitration() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        // The node holds the preferred address of the tester, and has a lower NAME
+        let node = s.spawn(|| run_node(&mut peer, ecu_node(DEFAULT_TESTER_ADDRESS), VecDeque::new(), &stop));
+        thread::sleep(ms(50));
+        let preferred_only = [(StdComParam::J1939AddrClaim, 1)];
+        assert_eq!(vcan_channel(&driver, J1939_PROTOCOL, &preferred_only).err(), Some(PduError::ResourceBusy));
+
+        // The tester moves to the arbitrary address range
+        let mut channel = vcan_channel(&driver, J1939_PROTOCOL, &[]).unwrap();
+        channel.send(&[0x00, 0xFE, 0xCA, 0xFF, 0x01], &[], &params(&driver, J1939_PROTOCOL, &[])).unwrap();
+        thread::sleep(ms(50));
+        stop.store(true, Ordering::Relaxed);
+        let received = node.join().unwrap();
+        let message = received.iter().find(|m| m.pgn == 0xFECA).unwrap();
+        assert!((128..=247).contains(&message.source), "{message:?}");
+    });
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn j1939_transport_protocol() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, J1939_PROTOCOL, &[]).unwrap();
+    let params = params(&driver, J1939_PROTOCOL, &[]);
+    let bam: Vec<u8> = (0..20).collect();
+    let rts: Vec<u8> = (100..130).collect();
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        let outgoing = VecDeque::from([(0xFECA, 0xFF, bam.clone()), (0xEF00, DEFAULT_TESTER_ADDRESS, rts.clone())]);
+        let node = s.spawn(|| run_node(&mut peer, ecu_node(0x00), outgoing, &stop));
+
+        // Received as a BAM, and as an RTS/CTS session which the tester answers while receiving
+        assert_eq!(recv_j1939(&mut channel, Duration::from_secs(3)), Some((vec![0x00, 0xFE, 0xCA, 0x00, 0xFF], bam.clone())));
+        assert_eq!(recv_j1939(&mut channel, Duration::from_secs(3)), Some((vec![0x00, 0xEF, 0x00, 0x00, 0xF9], rts.clone())));
+
+        // Sent as an RTS/CTS session to the node, and as a BAM
+        channel.send(&[&[0x00, 0xEF, 0x00, 0x00][..], &rts].concat(), &[], &params).unwrap();
+        channel.send(&[&[0x00, 0xFE, 0xCA, 0xFF][..], &bam].concat(), &[], &params).unwrap();
+        thread::sleep(ms(100));
+        stop.store(true, Ordering::Relaxed);
+        let received = node.join().unwrap();
+        let find = |pgn: u32| received.iter().find(|m| m.pgn == pgn).map(|m| (m.source, m.destination, m.data.clone()));
+        assert_eq!(find(0xEF00), Some((DEFAULT_TESTER_ADDRESS, 0x00, rts.clone())));
+        assert_eq!(find(0xFECA), Some((DEFAULT_TESTER_ADDRESS, 0xFF, bam.clone())));
+    });
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn j1939_transport_timeouts() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let mut channel = vcan_channel(&driver, J1939_PROTOCOL, &[]).unwrap();
+    let params = params(&driver, J1939_PROTOCOL, &[]);
+
+    // Nobody answers the RTS with a CTS within T3
+    let start = Instant::now();
+    assert_eq!(channel.send(&[&[0x00, 0xEF, 0x00, 0x33][..], &[0xAA; 20]].concat(), &[], &params), Err(PduError::FctFailed));
+    assert!(start.elapsed() >= ms(1250));
+    assert_eq!(peer.recv_id(0x9CEC_33F9).unwrap().data[0], 16);
+    assert_eq!(peer.recv_id(0x9CEC_33F9).unwrap().data[..2], [255, 3]);
+
+    // A BAM stops after its first packet, which times out after T1
+    peer.send(&CanFrame { id: 0x9CEC_FF33, data: vec![32, 20, 0, 3, 0xFF, 0xCA, 0xFE, 0x00], ..Default::default() });
+    peer.send(&CanFrame { id: 0x9CEB_FF33, data: vec![1, 0, 1, 2, 3, 4, 5, 6], ..Default::default() });
+    let start = Instant::now();
+    assert_eq!(recv_error(&mut channel, Duration::from_secs(2)), Some(PduErrorEvt::RxTimeout));
+    assert!(start.elapsed() >= ms(700));
+
+    // No packet follows the CTS within T2, and the tester aborts the session
+    peer.send(&CanFrame { id: 0x9CEC_F933, data: vec![16, 20, 0, 3, 0xFF, 0x00, 0xEF, 0x00], ..Default::default() });
+    let start = Instant::now();
+    assert_eq!(recv_error(&mut channel, Duration::from_secs(2)), Some(PduErrorEvt::RxTimeout));
+    assert!(start.elapsed() >= ms(1200));
+    assert_eq!(peer.recv_id(0x9CEC_33F9).unwrap().data[..3], [17, 3, 1]);
+    assert_eq!(peer.recv_id(0x9CEC_33F9).unwrap().data[..2], [255, 3]);
+}
+
+/// Returns the handle of the `vcan0` module
+fn vcan_module(backend: &DriverBackend<SocketCan>) -> ModuleHandle {
+    let item = backend.get_module_ids().unwrap();
+    // Safety: The item is a module item of the backend
+    let h_mod = unsafe {
+        std::slice::from_raw_parts((*item).p_module_data, (*item).num_entries as usize)
+            .iter()
+            .find(|m| CStr::from_ptr(m.vendor_module_name.cast()).to_bytes() == VCAN.as_bytes())
+            .map(|m| m.h_mod)
+    };
+    backend.destroy_item(item.cast()).unwrap();
+    ModuleHandle::new(h_mod.unwrap()).unwrap()
+}
+
+fn link_ioctl(backend: &DriverBackend<SocketCan>, h_mod: ModuleHandle, h_cll: CllHandle, command: IoctlCommand, filter: Option<IoFilterData>) {
+    let mut filter = filter;
+    let item = filter.as_mut().map(|f| PduDataItem { item_type: PduIt::IoFilter, p_data: ptr::from_mut(f).cast() });
+    backend.ioctl(Some(h_mod), Some(h_cll), std_object_id(command), item.as_ref()).unwrap();
+}
+
+/// Filter comparing the PGN of J1939 results
+fn pgn_filter(filter_type: PduFilter, pgn: u32) -> IoFilterData {
+    let mut filter = IoFilterData {
+        filter_type,
+        filter_number: 1,
+        filter_compare_size: 3,
+        filter_mask_msg: [0; 12],
+        filter_pattern_msg: [0; 12]
+    };
+    filter.filter_mask_msg[..3].fill(0xFF);
+    filter.filter_pattern_msg[..3].copy_from_slice(&pgn.to_be_bytes()[1..]);
+    filter
+}
+
+/// Waits for the results of a link, returning their ExtraInfo headers
+fn result_headers(backend: &DriverBackend<SocketCan>, h_mod: ModuleHandle, h_cll: CllHandle) -> Vec<Vec<u8>> {
+    thread::sleep(ms(300));
+    let mut headers = Vec::new();
+    loop {
+        let item = match backend.get_event_item(Some(h_mod), Some(h_cll)) {
+            Ok(item) => item,
+            Err(PduError::EventQueueEmpty) => return headers,
+            Err(e) => panic!("{e:?}")
+        };
+        // Safety: The item is an event item of the backend
+        unsafe {
+            if (*item).item_type == PduIt::Result {
+                let extra = *(*(*item).p_data.cast::<ResultData>()).p_extra_info;
+                headers.push(std::slice::from_raw_parts(extra.p_header_bytes, extra.num_header_bytes as usize).to_vec());
+            }
+        }
+        backend.destroy_item(item.cast()).unwrap();
+    }
+}
+
+#[test]
+#[ignore = "needs the vcan0 interface"]
+fn j1939_pgn_filters() {
+    let (_guard, driver) = vcan();
+    let mut peer = Peer::open(&driver);
+    let backend = DriverBackend::<SocketCan>::construct("", PduTag::NULL).unwrap();
+    let h_mod = vcan_module(&backend);
+    backend.module_connect(Some(h_mod)).unwrap();
+    let rsc = RscData {
+        bus_type_id: std_object_id(BusType::Iso11898_2Dwcan).raw(),
+        protocol_id: std_object_id(J1939_PROTOCOL).raw(),
+        num_pin_data: 0,
+        p_dlc_pin_data: ptr::null_mut()
+    };
+    let h_cll = backend.create_com_logical_link(h_mod, Some(&rsc), None, PduTag::NULL, None).unwrap();
+    backend.connect(h_mod, h_cll).unwrap();
+    // Receives every message until it is cancelled
+    let receive = CopCtrlData {
+        time: 0,
+        num_send_cycles: 0,
+        num_receive_cycles: -1,
+        temp_param_update: 0,
+        tx_flag: FlagData { num_flag_bytes: 0, p_flag_data: ptr::null_mut() },
+        num_possible_expected_responses: 0,
+        expected_response_array: ptr::null_mut()
+    };
+    backend.start_com_primitive(h_mod, h_cll, PduCopt::SendRecv, &[], Some(&receive), PduTag::NULL).unwrap();
+
+    let dm1 = CanFrame { id: 0x98FE_CA00, data: vec![0x00, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF], ..Default::default() };
+    let eec1 = CanFrame { id: 0x8CF0_0400, data: vec![0xFF; 8], ..Default::default() };
+    let dm1_header = [0x00, 0xFE, 0xCA, 0x00, 0xFF];
+    let eec1_header = [0x00, 0xF0, 0x04, 0x00, 0xFF];
+
+    link_ioctl(&backend, h_mod, h_cll, IoctlCommand::StartMsgFilter, Some(pgn_filter(PduFilter::Pass, 0xFECA)));
+    peer.send(&dm1);
+    peer.send(&eec1);
+    assert_eq!(result_headers(&backend, h_mod, h_cll), [dm1_header]);
+
+    link_ioctl(&backend, h_mod, h_cll, IoctlCommand::StartMsgFilter, Some(pgn_filter(PduFilter::Block, 0xFECA)));
+    peer.send(&dm1);
+    peer.send(&eec1);
+    assert_eq!(result_headers(&backend, h_mod, h_cll), [eec1_header]);
+
+    link_ioctl(&backend, h_mod, h_cll, IoctlCommand::ClearMsgFilter, None);
+    peer.send(&dm1);
+    peer.send(&eec1);
+    assert_eq!(result_headers(&backend, h_mod, h_cll), [dm1_header, eec1_header]);
+    backend.destruct().unwrap();
+}
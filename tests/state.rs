@@ -0,0 +1,133 @@
+//! Tests of the module and ComLogicalLink state machines
+
+use std::sync::Arc;
+
+use dpdu_rust::{
+    provider::{EventData, EventQueue, EventSource, LinkAction, LinkState, LinkStateMachine, ModuleAction, ModuleState, ModuleStateMachine},
+    PduCopt, PduError, PduStatus
+};
+
+const MODULE_STATES: [ModuleState; 4] = [ModuleState::Avail, ModuleState::Ready, ModuleState::NotReady, ModuleState::NotAvail];
+
+const MODULE_ACTIONS: [ModuleAction; 6] = [
+    ModuleAction::Connect,
+    ModuleAction::Disconnect,
+    ModuleAction::Ready,
+    ModuleAction::NotReady,
+    ModuleAction::Detected,
+    ModuleAction::Lost
+];
+
+const LINK_STATES: [LinkState; 3] = [LinkState::Offline, LinkState::Online, LinkState::CommStarted];
+
+const LINK_ACTIONS: [LinkAction; 5] =
+    [LinkAction::Connect, LinkAction::Disconnect, LinkAction::StartComm, LinkAction::StopComm, LinkAction::ModuleLost];
+
+#[test]
+fn module_transitions() {
+    use ModuleState::*;
+    let not_connected = Err(PduError::ModuleNotConnected);
+    let connected = Err(PduError::FctFailed);
+    // Rows follow MODULE_STATES, columns follow MODULE_ACTIONS
+    let table: [[Result<ModuleState, PduError>; 6]; 4] = [
+        [Ok(Ready), not_connected, not_connected, not_connected, Ok(Avail), Ok(NotAvail)],
+        [connected, Ok(Avail), Ok(Ready), Ok(NotReady), Ok(Ready), Ok(NotAvail)],
+        [connected, Ok(Avail), Ok(Ready), Ok(NotReady), Ok(NotReady), Ok(NotAvail)],
+        [connected, not_connected, not_connected, not_connected, Ok(Avail), Ok(NotAvail)]
+    ];
+    for (state, row) in MODULE_STATES.iter().zip(table) {
+        for (action, expected) in MODULE_ACTIONS.iter().zip(row) {
+            assert_eq!(state.next(*action), expected, "{state:?} on {action:?}");
+        }
+    }
+}
+
+#[test]
+fn link_transitions() {
+    use LinkState::*;
+    let not_connected = Err(PduError::CllNotConnected);
+    // Rows follow LINK_STATES, columns follow LINK_ACTIONS
+    let table: [[Result<LinkState, PduError>; 5]; 3] = [
+        [Ok(Online), not_connected, not_connected, not_connected, Ok(Offline)],
+        [Err(PduError::CllConnected), Ok(Offline), Ok(CommStarted), Err(PduError::CllNotStarted), Ok(Offline)],
+        [Err(PduError::CllConnected), Ok(Offline), Ok(CommStarted), Ok(Online), Ok(Offline)]
+    ];
+    for (state, row) in LINK_STATES.iter().zip(table) {
+        for (action, expected) in LINK_ACTIONS.iter().zip(row) {
+            assert_eq!(state.next(*action), expected, "{state:?} on {action:?}");
+        }
+    }
+}
+
+#[test]
+fn link_cop_checks() {
+    const COP_TYPES: [PduCopt; 6] =
+        [PduCopt::StartComm, PduCopt::StopComm, PduCopt::UpdateParam, PduCopt::SendRecv, PduCopt::Delay, PduCopt::RestoreParam];
+    let ok = Ok(());
+    let offline = Err(PduError::CllNotConnected);
+    let not_started = Err(PduError::CllNotStarted);
+    // Rows follow LINK_STATES, columns follow COP_TYPES, for protocols with and without StartComm
+    let required: [[Result<(), PduError>; 6]; 3] = [
+        [offline; 6],
+        [ok, not_started, ok, not_started, ok, ok],
+        [ok; 6]
+    ];
+    let not_required: [[Result<(), PduError>; 6]; 3] = [
+        [offline; 6],
+        [ok, not_started, ok, ok, ok, ok],
+        [ok; 6]
+    ];
+    for (start_comm_required, table) in [(true, required), (false, not_required)] {
+        for (state, row) in LINK_STATES.iter().zip(table) {
+            for (cop_type, expected) in COP_TYPES.iter().zip(row) {
+                assert_eq!(state.check_cop(*cop_type, start_comm_required), expected, "{cop_type:?} while {state:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn status_codes_round_trip() {
+    for state in MODULE_STATES {
+        assert_eq!(ModuleState::from_status(state.status()), Some(state));
+        assert_eq!(LinkState::from_status(state.status()), None);
+    }
+    for state in LINK_STATES {
+        assert_eq!(LinkState::from_status(state.status()), Some(state));
+        assert_eq!(ModuleState::from_status(state.status()), None);
+    }
+}
+
+fn statuses(events: &EventQueue) -> Vec<PduStatus> {
+    std::iter::from_fn(|| events.pop().ok())
+        .map(|e| match e.data {
+            EventData::Status(s) => s,
+            other => panic!("unexpected event {other:?}")
+        })
+        .collect()
+}
+
+#[test]
+fn state_machines_send_status_events_on_change() {
+    let events = Arc::new(EventQueue::new(EventSource::default()));
+    let module = ModuleStateMachine::new(ModuleState::Avail, events.clone());
+    assert_eq!(module.require_connected(), Err(PduError::ModuleNotConnected));
+    assert_eq!(module.apply(ModuleAction::Disconnect, 0), Err(PduError::ModuleNotConnected));
+    assert_eq!(module.apply(ModuleAction::Connect, 0), Ok(ModuleState::Ready));
+    assert_eq!(module.apply(ModuleAction::Ready, 0), Ok(ModuleState::Ready));
+    assert_eq!(module.apply(ModuleAction::NotReady, 0), Ok(ModuleState::NotReady));
+    assert_eq!(module.require_connected(), Ok(()));
+    assert_eq!(module.apply(ModuleAction::Lost, 0), Ok(ModuleState::NotAvail));
+    assert_eq!(module.state(), ModuleState::NotAvail);
+    assert_eq!(statuses(&events), [PduStatus::ModstReady, PduStatus::ModstNotReady, PduStatus::ModstNotAvail]);
+
+    let link = LinkStateMachine::new(events.clone());
+    assert_eq!(link.check_cop(PduCopt::StartComm, true), Err(PduError::CllNotConnected));
+    assert_eq!(link.apply(LinkAction::StartComm, 0), Err(PduError::CllNotConnected));
+    assert_eq!(link.apply(LinkAction::Connect, 0), Ok(LinkState::Online));
+    assert_eq!(link.apply(LinkAction::StartComm, 0), Ok(LinkState::CommStarted));
+    assert_eq!(link.apply(LinkAction::StartComm, 0), Ok(LinkState::CommStarted));
+    assert_eq!(link.check_cop(PduCopt::SendRecv, true), Ok(()));
+    assert_eq!(link.apply(LinkAction::ModuleLost, 0), Ok(LinkState::Offline));
+    assert_eq!(statuses(&events), [PduStatus::CllstOnline, PduStatus::CllstCommStarted, PduStatus::CllstOffline]);
+}